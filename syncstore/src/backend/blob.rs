@@ -0,0 +1,152 @@
+//! Offload of oversized body fields to a blob store.
+//!
+//! A top-level body field whose JSON encoding exceeds the threshold is stored as a blob and
+//! replaced in the row by a reference carrying its hash:
+//! ```json
+//! "content": { "$blob": { "sha256": "9f86d08…", "size": 1048576 } }
+//! ```
+//! Schema validation always runs against the inlined body, references sent back by clients are
+//! resolved before the body is validated again.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Digest;
+
+use crate::error::{StoreError, StoreResult};
+
+const BLOB_KEY: &str = "$blob";
+
+/// Content addressed storage of the offloaded values, keyed by their hex sha256.
+pub trait BlobStore: Send + Sync {
+    /// Store the bytes, storing the same content again is a no-op.
+    fn put(&self, sha256: &str, bytes: &[u8]) -> StoreResult<()>;
+
+    fn get(&self, sha256: &str) -> StoreResult<Vec<u8>>;
+}
+
+/// Blobs as files under a local directory, sharded by the first two hex chars of the hash.
+pub struct FsBlobStore {
+    dir: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(dir: impl AsRef<Path>) -> StoreResult<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, sha256: &str) -> StoreResult<PathBuf> {
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(StoreError::Validation(format!("invalid blob hash `{}`", sha256)));
+        }
+        Ok(self.dir.join(&sha256[..2]).join(sha256))
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put(&self, sha256: &str, bytes: &[u8]) -> StoreResult<()> {
+        let path = self.path(sha256)?;
+        if path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // write then rename, a crash never leaves a truncated blob behind
+        let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn get(&self, sha256: &str) -> StoreResult<Vec<u8>> {
+        match std::fs::read(self.path(sha256)?) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(StoreError::NotFound(format!("Blob {}", sha256))),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Where and from which size body fields are offloaded.
+#[derive(Clone)]
+pub struct BlobOffload {
+    pub store: Arc<dyn BlobStore>,
+    /// fields whose JSON encoding is larger than this many bytes are offloaded
+    pub threshold: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BlobRef {
+    sha256: String,
+    size: usize,
+}
+
+impl BlobOffload {
+    /// Replace the oversized top-level fields by blob references.
+    pub fn offload(&self, body: &Value) -> StoreResult<Value> {
+        let mut body = body.clone();
+        let Some(map) = body.as_object_mut() else {
+            return Ok(body);
+        };
+        for value in map.values_mut() {
+            if as_blob_ref(value).is_some() {
+                continue;
+            }
+            let bytes = serde_json::to_vec(value)?;
+            if bytes.len() <= self.threshold {
+                continue;
+            }
+            let sha256 = hex_sha256(&bytes);
+            self.store.put(&sha256, &bytes)?;
+            *value = serde_json::json!({ BLOB_KEY: BlobRef { sha256, size: bytes.len() } });
+        }
+        Ok(body)
+    }
+}
+
+/// Whether the body holds any blob reference.
+pub fn has_blob_refs(body: &Value) -> bool {
+    body.as_object()
+        .is_some_and(|map| map.values().any(|v| as_blob_ref(v).is_some()))
+}
+
+/// Replace the blob references of the body by their values, checking the stored hash.
+pub fn inline(body: &mut Value, store: &dyn BlobStore) -> StoreResult<()> {
+    let Some(map) = body.as_object_mut() else {
+        return Ok(());
+    };
+    for value in map.values_mut() {
+        let Some(blob) = as_blob_ref(value) else {
+            continue;
+        };
+        let bytes = store.get(&blob.sha256)?;
+        if hex_sha256(&bytes) != blob.sha256 {
+            return Err(StoreError::Backend(format!("blob {} is corrupted", blob.sha256)));
+        }
+        *value = serde_json::from_slice(&bytes)?;
+    }
+    Ok(())
+}
+
+fn as_blob_ref(value: &Value) -> Option<BlobRef> {
+    let map = value.as_object()?;
+    if map.len() != 1 {
+        return None;
+    }
+    serde_json::from_value(map.get(BLOB_KEY)?.clone()).ok()
+}
+
+pub(crate) fn hex_sha256(bytes: &[u8]) -> String {
+    sha2::Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
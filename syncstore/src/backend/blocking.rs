@@ -0,0 +1,130 @@
+//! Async access to the blocking backends.
+//!
+//! The backends run their statements on the calling thread. Called from a handler, that thread is an
+//! HTTP worker of the runtime, so the work is moved to the blocking pool instead. The request
+//! transaction and deadline entered by the caller are entered again on the blocking thread.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::backend::{Backend, deadline, tx::TxScope};
+use crate::error::{StoreError, StoreResult};
+use crate::types::{DataItem, Id};
+
+/// Run `f` on the blocking pool, inside the caller's request transaction and deadline.
+///
+/// Dropping the returned future doesn't stop `f`, a passed deadline interrupts its statements.
+pub async fn spawn<R, F>(f: F) -> StoreResult<R>
+where
+    R: Send + 'static,
+    F: FnOnce() -> StoreResult<R> + Send + 'static,
+{
+    let scope = TxScope::current();
+    let until = deadline::current();
+    let run = move || {
+        let f = move || match until {
+            Some(until) => deadline::enter_sync(until, f),
+            None => f(),
+        };
+        match scope {
+            Some(scope) => scope.enter_sync(f),
+            None => f(),
+        }
+    };
+    tokio::task::spawn_blocking(run)
+        .await
+        .map_err(|e| StoreError::Backend(format!("blocking task failed: {}", e)))?
+}
+
+/// The [`Backend`] operations as futures, each one run on the blocking pool.
+pub struct AsyncBackend<B: ?Sized> {
+    inner: Arc<B>,
+}
+
+impl<B: ?Sized> Clone for AsyncBackend<B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<B: Backend + ?Sized + 'static> AsyncBackend<B> {
+    pub fn new(inner: Arc<B>) -> Self {
+        Self { inner }
+    }
+
+    /// The wrapped backend, for the blocking callers.
+    pub fn inner(&self) -> &Arc<B> {
+        &self.inner
+    }
+
+    async fn call<R, F>(&self, f: F) -> StoreResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&B) -> StoreResult<R> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        spawn(move || f(&inner)).await
+    }
+
+    pub async fn insert(&self, collection: &str, body: &Value, owner: String) -> StoreResult<String> {
+        let (collection, body) = (collection.to_string(), body.clone());
+        self.call(move |b| b.insert(&collection, &body, owner)).await
+    }
+
+    pub async fn list_by_owner(
+        &self,
+        collection: &str,
+        owner: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        let (collection, owner) = (collection.to_string(), owner.to_string());
+        self.call(move |b| b.list_by_owner(&collection, &owner, marker, limit))
+            .await
+    }
+
+    pub async fn list_children(
+        &self,
+        collection: &str,
+        parent_id: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        let (collection, parent_id) = (collection.to_string(), parent_id.to_string());
+        self.call(move |b| b.list_children(&collection, &parent_id, marker, limit))
+            .await
+    }
+
+    pub async fn get(&self, collection: &str, id: &Id) -> StoreResult<DataItem> {
+        let (collection, id) = (collection.to_string(), id.clone());
+        self.call(move |b| b.get(&collection, &id)).await
+    }
+
+    pub async fn get_by_unique(&self, collection: &str, unique: &str) -> StoreResult<DataItem> {
+        let (collection, unique) = (collection.to_string(), unique.to_string());
+        self.call(move |b| b.get_by_unique(&collection, &unique)).await
+    }
+
+    pub async fn update(&self, collection: &str, id: &Id, body: &Value) -> StoreResult<DataItem> {
+        let (collection, id, body) = (collection.to_string(), id.clone(), body.clone());
+        self.call(move |b| b.update(&collection, &id, &body)).await
+    }
+
+    pub async fn move_to_parent(&self, collection: &str, id: &Id, parent_id: &str) -> StoreResult<DataItem> {
+        let (collection, id, parent_id) = (collection.to_string(), id.clone(), parent_id.to_string());
+        self.call(move |b| b.move_to_parent(&collection, &id, &parent_id)).await
+    }
+
+    pub async fn delete(&self, collection: &str, id: &Id) -> StoreResult<()> {
+        let (collection, id) = (collection.to_string(), id.clone());
+        self.call(move |b| b.delete(&collection, &id)).await
+    }
+
+    pub async fn batch_delete(&self, collection: &str, ids: &[Id]) -> StoreResult<()> {
+        let (collection, ids) = (collection.to_string(), ids.to_vec());
+        self.call(move |b| b.batch_delete(&collection, &ids)).await
+    }
+}
@@ -0,0 +1,172 @@
+//! Body fields maintained by the server on every insert/update.
+//!
+//! `x-computed` derives fields from other fields of the body:
+//! ```json
+//! "x-computed": {
+//!     "slug": { "op": "slug", "from": "title" },
+//!     "words": { "op": "word_count", "from": "content" }
+//! }
+//! ```
+//!
+//! `x-inject-timestamps` mirrors the meta timestamps into the body, either `true` for
+//! `created_at`/`updated_at` or an object naming the body fields:
+//! ```json
+//! "x-inject-timestamps": { "created_at": "createdAt", "updated_at": "updatedAt" }
+//! ```
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::{StoreError, StoreResult};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ComputedExpr {
+    /// lowercase alphanumeric words joined by `-`
+    Slug {
+        from: String,
+    },
+    /// number of whitespace separated words
+    WordCount {
+        from: String,
+    },
+    /// number of unicode chars
+    CharCount {
+        from: String,
+    },
+    Lowercase {
+        from: String,
+    },
+    Uppercase {
+        from: String,
+    },
+    Trim {
+        from: String,
+    },
+}
+
+impl ComputedExpr {
+    fn source(&self) -> &str {
+        match self {
+            ComputedExpr::Slug { from }
+            | ComputedExpr::WordCount { from }
+            | ComputedExpr::CharCount { from }
+            | ComputedExpr::Lowercase { from }
+            | ComputedExpr::Uppercase { from }
+            | ComputedExpr::Trim { from } => from,
+        }
+    }
+
+    fn eval(&self, input: &str) -> Value {
+        match self {
+            ComputedExpr::Slug { .. } => Value::String(slugify(input)),
+            ComputedExpr::WordCount { .. } => Value::from(input.split_whitespace().count()),
+            ComputedExpr::CharCount { .. } => Value::from(input.chars().count()),
+            ComputedExpr::Lowercase { .. } => Value::String(input.to_lowercase()),
+            ComputedExpr::Uppercase { .. } => Value::String(input.to_uppercase()),
+            ComputedExpr::Trim { .. } => Value::String(input.trim().to_string()),
+        }
+    }
+}
+
+/// Parse the `x-computed` keyword of a collection schema, if any.
+pub fn parse(schema: &Value) -> StoreResult<Vec<(String, ComputedExpr)>> {
+    let Some(spec) = schema.get("x-computed") else {
+        return Ok(Vec::new());
+    };
+    let spec = spec
+        .as_object()
+        .ok_or_else(|| StoreError::Validation("x-computed: expected an object of field => expression".into()))?;
+    let mut fields = Vec::with_capacity(spec.len());
+    for (field, expr) in spec {
+        let expr: ComputedExpr = serde_json::from_value(expr.clone())
+            .map_err(|e| StoreError::Validation(format!("x-computed: invalid expression for '{}': {}", field, e)))?;
+        if expr.source() == field {
+            return Err(StoreError::Validation(format!(
+                "x-computed: field '{}' can not be computed from itself",
+                field
+            )));
+        }
+        fields.push((field.clone(), expr));
+    }
+    Ok(fields)
+}
+
+/// Overwrite the computed fields in body, client supplied values are discarded.
+/// A computed field is removed when its source field is missing or not a string.
+pub fn apply(fields: &[(String, ComputedExpr)], body: &mut Value) {
+    let Some(map) = body.as_object_mut() else {
+        return;
+    };
+    for (field, expr) in fields {
+        match map.get(expr.source()).and_then(|v| v.as_str()).map(|s| expr.eval(s)) {
+            Some(value) => {
+                map.insert(field.clone(), value);
+            }
+            None => {
+                map.remove(field);
+            }
+        }
+    }
+}
+
+fn slugify(input: &str) -> String {
+    input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Body field names receiving the meta timestamps.
+#[derive(Debug, Clone, Default)]
+pub struct TimestampFields {
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// Parse the `x-inject-timestamps` keyword of a collection schema, if any.
+pub fn parse_timestamps(schema: &Value) -> StoreResult<Option<TimestampFields>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Flag(bool),
+        Fields {
+            created_at: Option<String>,
+            updated_at: Option<String>,
+        },
+    }
+
+    let Some(spec) = schema.get("x-inject-timestamps") else {
+        return Ok(None);
+    };
+    let repr: Repr = serde_json::from_value(spec.clone())
+        .map_err(|e| StoreError::Validation(format!("x-inject-timestamps: invalid format: {}", e)))?;
+    Ok(match repr {
+        Repr::Flag(false) => None,
+        Repr::Flag(true) => Some(TimestampFields {
+            created_at: Some("created_at".to_string()),
+            updated_at: Some("updated_at".to_string()),
+        }),
+        Repr::Fields { created_at, updated_at } => Some(TimestampFields { created_at, updated_at }),
+    })
+}
+
+pub fn apply_timestamps(
+    fields: &TimestampFields,
+    body: &mut Value,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+) {
+    let Some(map) = body.as_object_mut() else {
+        return;
+    };
+    if let Some(field) = &fields.created_at {
+        map.insert(field.clone(), Value::String(created_at.to_rfc3339()));
+    }
+    if let Some(field) = &fields.updated_at {
+        map.insert(field.clone(), Value::String(updated_at.to_rfc3339()));
+    }
+}
@@ -0,0 +1,50 @@
+//! Request deadlines reaching the database.
+//!
+//! While a deadline is entered, the connections handed out by the backends abort their running
+//! statement with `SQLITE_INTERRUPT` once it passes, so a handler cut off by the request timeout
+//! doesn't keep the database busy.
+
+use std::time::Instant;
+
+use r2d2_sqlite::rusqlite;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+// virtual machine instructions between two deadline checks
+const CHECK_INTERVAL: std::ffi::c_int = 1000;
+
+/// Run `fut` with the deadline entered.
+pub async fn enter<F: Future>(deadline: Instant, fut: F) -> F::Output {
+    DEADLINE.scope(deadline, fut).await
+}
+
+/// Like `enter`, for synchronous callers.
+pub fn enter_sync<R>(deadline: Instant, f: impl FnOnce() -> R) -> R {
+    DEADLINE.sync_scope(deadline, f)
+}
+
+/// The entered deadline, if any.
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Whether the entered deadline, if any, has passed.
+pub fn passed() -> bool {
+    DEADLINE
+        .try_with(|deadline| Instant::now() >= *deadline)
+        .unwrap_or(false)
+}
+
+/// Make the statements of `conn` abort past the entered deadline, if any.
+pub(crate) fn arm(conn: &rusqlite::Connection) {
+    if let Some(deadline) = current() {
+        conn.progress_handler(CHECK_INTERVAL, Some(move || Instant::now() >= deadline));
+    }
+}
+
+/// Remove the deadline check before the connection is used by another request.
+pub(crate) fn disarm(conn: &rusqlite::Connection) {
+    conn.progress_handler(0, None::<fn() -> bool>);
+}
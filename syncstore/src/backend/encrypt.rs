@@ -0,0 +1,220 @@
+//! Encryption of selected body fields with a server-held key.
+//!
+//! The top-level fields listed by a collection schema are encrypted before the row is written:
+//! ```json
+//! "x-encrypt-fields": ["email", "token"]
+//! ```
+//! and replaced in the row by their XChaCha20-Poly1305 ciphertext, nonce first, in base64:
+//! ```json
+//! "email": { "$enc": "q8Hc0m…" }
+//! ```
+//! Reads decrypt them again, the store has checked the reader's permissions by then. Schema
+//! validation runs against the plain body.
+//!
+//! The nonce is derived from the field and its value, so equal values encrypt alike: the content
+//! hash of a document stays the same when it is written again, at the cost of showing a reader of
+//! the database file which documents share a value. Encrypted fields can not be filtered, sorted,
+//! indexed or searched on, the database only sees the ciphertext.
+
+use base64::Engine;
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, Payload},
+};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::error::{StoreError, StoreResult};
+
+const ENC_KEY: &str = "$enc";
+const NONCE_SIZE: usize = 24;
+
+/// The key encrypting the `x-encrypt-fields` of every collection.
+#[derive(Clone)]
+pub struct FieldCipher {
+    cipher: XChaCha20Poly1305,
+    // derives the nonce of a value, kept apart from the encryption key
+    nonce_key: [u8; 32],
+}
+
+impl FieldCipher {
+    /// Cipher of a 32 byte key.
+    pub fn new(key: &[u8]) -> StoreResult<Self> {
+        if key.len() != 32 {
+            return Err(StoreError::Validation(format!(
+                "field encryption key must be 32 bytes, got {}",
+                key.len()
+            )));
+        }
+        Ok(Self {
+            cipher: XChaCha20Poly1305::new_from_slice(&derive_key(key, b"syncstore field encryption"))
+                .expect("derived keys are 32 bytes"),
+            nonce_key: derive_key(key, b"syncstore field nonce"),
+        })
+    }
+
+    /// Cipher of a base64 encoded 32 byte key, as the config holds it.
+    pub fn from_base64(key: &str) -> StoreResult<Self> {
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(key.trim())
+            .map_err(|e| StoreError::Validation(format!("invalid field encryption key: {}", e)))?;
+        Self::new(&key)
+    }
+
+    fn encrypt(&self, field: &str, value: &Value) -> StoreResult<Value> {
+        let plain = serde_json::to_vec(value)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.nonce_key).expect("hmac accepts keys of any size");
+        mac.update(field.as_bytes());
+        mac.update(&[0]);
+        mac.update(&plain);
+        let nonce = mac.finalize().into_bytes();
+        let nonce = XNonce::from_slice(&nonce[..NONCE_SIZE]);
+        // the field name is authenticated, a ciphertext copied into another field fails to decrypt
+        let sealed = self
+            .cipher
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: &plain,
+                    aad: field.as_bytes(),
+                },
+            )
+            .map_err(|_| StoreError::Backend(format!("failed to encrypt field '{}'", field)))?;
+        let mut bytes = nonce.to_vec();
+        bytes.extend(sealed);
+        Ok(serde_json::json!({ ENC_KEY: base64::engine::general_purpose::STANDARD.encode(bytes) }))
+    }
+
+    fn decrypt(&self, field: &str, encoded: &str) -> StoreResult<Value> {
+        let corrupted = || StoreError::Backend(format!("encrypted field '{}' can not be decrypted", field));
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| corrupted())?;
+        if bytes.len() < NONCE_SIZE {
+            return Err(corrupted());
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_SIZE);
+        let plain = self
+            .cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: field.as_bytes(),
+                },
+            )
+            .map_err(|_| corrupted())?;
+        Ok(serde_json::from_slice(&plain)?)
+    }
+}
+
+fn derive_key(key: &[u8], label: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(label);
+    mac.finalize().into_bytes().into()
+}
+
+/// The `x-encrypt-fields` of a schema.
+pub(crate) fn parse(schema: &Value) -> StoreResult<Vec<String>> {
+    let Some(spec) = schema.get("x-encrypt-fields") else {
+        return Ok(Vec::new());
+    };
+    let invalid = || StoreError::Validation(format!("x-encrypt-fields: expected an array of field names: {}", spec));
+    let mut fields: Vec<String> = Vec::new();
+    for field in spec.as_array().ok_or_else(invalid)? {
+        let field = field.as_str().ok_or_else(invalid)?;
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(StoreError::Validation(format!(
+                "x-encrypt-fields: only top-level fields can be encrypted, got '{}'",
+                field
+            )));
+        }
+        if !fields.iter().any(|f| f == field) {
+            fields.push(field.to_string());
+        }
+    }
+    Ok(fields)
+}
+
+/// Encrypt the listed fields present in the body.
+pub(crate) fn encrypt_fields(body: &mut Value, fields: &[String], cipher: &FieldCipher) -> StoreResult<()> {
+    let Some(map) = body.as_object_mut() else {
+        return Ok(());
+    };
+    for field in fields {
+        if let Some(value) = map.get_mut(field) {
+            *value = cipher.encrypt(field, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the body holds any encrypted field.
+pub(crate) fn has_encrypted_fields(body: &Value) -> bool {
+    body.as_object()
+        .is_some_and(|map| map.values().any(|v| as_encrypted(v).is_some()))
+}
+
+/// Decrypt every encrypted field of the body, also those no longer listed by the schema.
+pub(crate) fn decrypt_fields(body: &mut Value, cipher: &FieldCipher) -> StoreResult<()> {
+    let Some(map) = body.as_object_mut() else {
+        return Ok(());
+    };
+    for (field, value) in map.iter_mut() {
+        if let Some(encoded) = as_encrypted(value) {
+            *value = cipher.decrypt(field, &encoded)?;
+        }
+    }
+    Ok(())
+}
+
+fn as_encrypted(value: &Value) -> Option<String> {
+    let map = value.as_object()?;
+    if map.len() != 1 {
+        return None;
+    }
+    map.get(ENC_KEY)?.as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields_round_trip() {
+        let cipher = FieldCipher::new(&[7; 32]).unwrap();
+        let plain = serde_json::json!({ "name": "n", "email": "a@b.c", "token": { "v": 1 } });
+        let fields = vec!["email".to_string(), "token".to_string(), "absent".to_string()];
+
+        let mut body = plain.clone();
+        encrypt_fields(&mut body, &fields, &cipher).unwrap();
+        assert_eq!(body["name"], "n");
+        assert!(body["email"]["$enc"].is_string());
+        assert!(body.get("absent").is_none());
+        assert!(has_encrypted_fields(&body));
+
+        // equal values encrypt alike
+        let mut again = plain.clone();
+        encrypt_fields(&mut again, &fields, &cipher).unwrap();
+        assert_eq!(body, again);
+
+        decrypt_fields(&mut body, &cipher).unwrap();
+        assert_eq!(body, plain);
+    }
+
+    #[test]
+    fn test_wrong_key_or_field_fails() {
+        let cipher = FieldCipher::new(&[7; 32]).unwrap();
+        let mut body = serde_json::json!({ "email": "a@b.c" });
+        encrypt_fields(&mut body, &["email".to_string()], &cipher).unwrap();
+
+        let other = FieldCipher::new(&[8; 32]).unwrap();
+        assert!(decrypt_fields(&mut body.clone(), &other).is_err());
+        let mut moved = serde_json::json!({ "token": body["email"].clone() });
+        assert!(decrypt_fields(&mut moved, &cipher).is_err());
+
+        assert!(FieldCipher::new(&[7; 16]).is_err());
+        assert!(parse(&serde_json::json!({ "x-encrypt-fields": ["a.b"] })).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Composable filter expressions over documents, compiled to parameterized SQL.
+//!
+//! ```json
+//! { "and": [ { "eq": ["category", "news"] }, { "gt": ["created_at", "2024-01-01T00:00:00Z"] } ] }
+//! ```
+//! `id`, `owner`, `parent_id`, `created_at` and `updated_at` name the meta columns, any other
+//! field is a (dotted) path into the body. Field paths and values are always bound as parameters,
+//! only the column names of the fixed meta list end up in the SQL text.
+//!
+//! Fields held in a generated column, the `x-index` ones of a collection, compare against that
+//! column instead, see [`FilterExpr::compile_with_columns`].
+//!
+//! The listings also take the compact `category=news,status=normal` form, see [`FilterExpr::from_query`].
+
+use std::collections::HashMap;
+
+use r2d2_sqlite::rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{StoreError, StoreResult};
+
+const MAX_DEPTH: usize = 8;
+const MAX_NODES: usize = 64;
+const MAX_IN_VALUES: usize = 100;
+
+const META_COLUMNS: [&str; 5] = ["id", "owner", "parent_id", "created_at", "updated_at"];
+const TIMESTAMP_COLUMNS: [&str; 2] = ["created_at", "updated_at"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterExpr {
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Eq(String, Value),
+    Ne(String, Value),
+    Gt(String, Value),
+    Gte(String, Value),
+    Lt(String, Value),
+    Lte(String, Value),
+    In(String, Vec<Value>),
+    /// the field is present, a body field set to null counts as present
+    Exists(String),
+}
+
+impl FilterExpr {
+    /// SQL condition and its positional `?` parameters.
+    pub fn compile(&self) -> StoreResult<(String, Vec<SqlValue>)> {
+        self.compile_with_columns(&HashMap::new())
+    }
+
+    /// `compile` reading the body fields of `columns`, dict<field, column>, from their generated column.
+    pub fn compile_with_columns(&self, columns: &HashMap<String, String>) -> StoreResult<(String, Vec<SqlValue>)> {
+        let mut compiler = Compiler {
+            params: Vec::new(),
+            nodes: 0,
+            columns,
+        };
+        let sql = compiler.expr(self, 0)?;
+        Ok((sql, compiler.params))
+    }
+
+    /// Filter given as a query parameter, either a JSON expression or `field=value` pairs joined by
+    /// `,` that all have to match.
+    ///
+    /// A pair's value is taken as JSON when it is a JSON scalar (`rank=1`, `done=true`, `title="1"`),
+    /// as plain text otherwise. Values holding a `,` need the JSON form.
+    pub fn from_query(input: &str) -> StoreResult<Self> {
+        let input = input.trim();
+        if input.starts_with('{') {
+            return serde_json::from_str(input).map_err(|e| StoreError::Validation(format!("invalid filter: {}", e)));
+        }
+        let mut exprs = Vec::new();
+        for pair in input.split(',') {
+            let Some((field, value)) = pair.split_once('=') else {
+                return Err(StoreError::Validation(format!(
+                    "invalid filter `{}`, expected field=value",
+                    pair
+                )));
+            };
+            let value = match serde_json::from_str::<Value>(value) {
+                Ok(json) if !json.is_array() && !json.is_object() => json,
+                _ => Value::String(value.to_string()),
+            };
+            exprs.push(FilterExpr::Eq(field.trim().to_string(), value));
+        }
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => FilterExpr::And(exprs),
+        })
+    }
+}
+
+/// Order of a listing by a meta column or a (dotted) body field, ties are broken by id.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct SortSpec {
+    pub field: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl SortSpec {
+    /// SQL ordering term and its positional `?` parameters.
+    pub fn compile(&self) -> StoreResult<(String, Vec<SqlValue>)> {
+        self.compile_with_columns(&HashMap::new())
+    }
+
+    /// `compile` ordering by the generated column of the field when `columns` has one.
+    pub fn compile_with_columns(&self, columns: &HashMap<String, String>) -> StoreResult<(String, Vec<SqlValue>)> {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        if META_COLUMNS.contains(&self.field.as_str()) {
+            return Ok((format!("{} {}", self.field, direction), Vec::new()));
+        }
+        if let Some(column) = columns.get(&self.field) {
+            return Ok((format!("{} {}", column, direction), Vec::new()));
+        }
+        let path = body_path(&self.field)?;
+        Ok((
+            format!("json_extract(body, ?) {}", direction),
+            vec![SqlValue::Text(path)],
+        ))
+    }
+}
+
+struct Compiler<'a> {
+    params: Vec<SqlValue>,
+    nodes: usize,
+    // dict<body field, generated column>
+    columns: &'a HashMap<String, String>,
+}
+
+impl Compiler<'_> {
+    fn expr(&mut self, expr: &FilterExpr, depth: usize) -> StoreResult<String> {
+        self.nodes += 1;
+        if depth > MAX_DEPTH || self.nodes > MAX_NODES {
+            return Err(StoreError::Validation(format!(
+                "filter exceeds {} levels or {} expressions",
+                MAX_DEPTH, MAX_NODES
+            )));
+        }
+        match expr {
+            FilterExpr::And(exprs) => self.group(exprs, " AND ", depth),
+            FilterExpr::Or(exprs) => self.group(exprs, " OR ", depth),
+            FilterExpr::Not(expr) => Ok(format!("NOT ({})", self.expr(expr, depth + 1)?)),
+            FilterExpr::Eq(field, Value::Null) => Ok(format!("{} IS NULL", self.field(field)?)),
+            FilterExpr::Ne(field, Value::Null) => Ok(format!("{} IS NOT NULL", self.field(field)?)),
+            FilterExpr::Eq(field, value) => self.compare(field, "=", value),
+            FilterExpr::Ne(field, value) => self.compare(field, "IS NOT", value),
+            FilterExpr::Gt(field, value) => self.compare(field, ">", value),
+            FilterExpr::Gte(field, value) => self.compare(field, ">=", value),
+            FilterExpr::Lt(field, value) => self.compare(field, "<", value),
+            FilterExpr::Lte(field, value) => self.compare(field, "<=", value),
+            FilterExpr::In(field, values) => {
+                if values.is_empty() || values.len() > MAX_IN_VALUES {
+                    return Err(StoreError::Validation(format!(
+                        "`in` takes between 1 and {} values",
+                        MAX_IN_VALUES
+                    )));
+                }
+                let column = self.field(field)?;
+                let mut placeholders = Vec::with_capacity(values.len());
+                for value in values {
+                    self.value(field, value)?;
+                    placeholders.push("?");
+                }
+                Ok(format!("{} IN ({})", column, placeholders.join(", ")))
+            }
+            FilterExpr::Exists(field) if META_COLUMNS.contains(&field.as_str()) => Ok(format!("{} IS NOT NULL", field)),
+            FilterExpr::Exists(field) => {
+                self.params.push(SqlValue::Text(body_path(field)?));
+                Ok("json_type(body, ?) IS NOT NULL".to_string())
+            }
+        }
+    }
+
+    fn group(&mut self, exprs: &[FilterExpr], op: &str, depth: usize) -> StoreResult<String> {
+        if exprs.is_empty() {
+            return Err(StoreError::Validation("empty `and`/`or` filter".to_string()));
+        }
+        let parts = exprs
+            .iter()
+            .map(|expr| Ok(format!("({})", self.expr(expr, depth + 1)?)))
+            .collect::<StoreResult<Vec<_>>>()?;
+        Ok(parts.join(op))
+    }
+
+    fn compare(&mut self, field: &str, op: &str, value: &Value) -> StoreResult<String> {
+        let column = self.field(field)?;
+        self.value(field, value)?;
+        Ok(format!("{} {} ?", column, op))
+    }
+
+    // the column or the body extraction of the field, binding the body path
+    fn field(&mut self, field: &str) -> StoreResult<String> {
+        if META_COLUMNS.contains(&field) {
+            return Ok(field.to_string());
+        }
+        if let Some(column) = self.columns.get(field) {
+            return Ok(column.clone());
+        }
+        self.params.push(SqlValue::Text(body_path(field)?));
+        Ok("json_extract(body, ?)".to_string())
+    }
+
+    fn value(&mut self, field: &str, value: &Value) -> StoreResult<()> {
+        let value = match value {
+            // timestamps are stored as rfc3339 text, normalize before comparing
+            Value::String(s) if TIMESTAMP_COLUMNS.contains(&field) => {
+                let ts = chrono::DateTime::parse_from_rfc3339(s)
+                    .map_err(|e| StoreError::Validation(format!("`{}` is not a rfc3339 timestamp: {}", s, e)))?;
+                SqlValue::Text(ts.with_timezone(&chrono::Utc).to_rfc3339())
+            }
+            Value::String(s) => SqlValue::Text(s.clone()),
+            // json_extract yields 1 and 0 for booleans
+            Value::Bool(b) => SqlValue::Integer(*b as i64),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => SqlValue::Integer(i),
+                None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+            },
+            Value::Null | Value::Array(_) | Value::Object(_) => {
+                return Err(StoreError::Validation(format!(
+                    "filter on `{}` only compares strings, numbers and booleans",
+                    field
+                )));
+            }
+        };
+        self.params.push(value);
+        Ok(())
+    }
+}
+
+// `$.a.b` json path of a dotted body field
+fn body_path(field: &str) -> StoreResult<String> {
+    let valid = !field.is_empty()
+        && field
+            .split('.')
+            .all(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    if !valid {
+        return Err(StoreError::Validation(format!("invalid filter field `{}`", field)));
+    }
+    Ok(format!("$.{}", field))
+}
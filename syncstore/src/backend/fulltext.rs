@@ -0,0 +1,117 @@
+//! Full text search over body fields, kept in an FTS5 shadow table per collection.
+//!
+//! ```json
+//! "x-fulltext": ["title", "content"]
+//! ```
+//! The shadow table `{table}_fts` shares the rowid of the collection table and is maintained by
+//! triggers, so every write path (imports, moves, restores) keeps it in sync without extra code.
+
+use r2d2_sqlite::rusqlite::{self, OptionalExtension, params};
+
+use crate::error::{StoreError, StoreResult};
+
+pub fn fts_table(table: &str) -> String {
+    format!("{table}_fts")
+}
+
+// fts5 column holding an `x-fulltext` field
+fn fts_column(field: &str) -> String {
+    format!("f_{}", field.replace('.', "__"))
+}
+
+// kept without `IF NOT EXISTS`, sqlite_master then holds this exact text and a changed field list shows
+fn table_ddl(table: &str, fields: &[String]) -> String {
+    let columns: Vec<String> = fields.iter().map(|f| fts_column(f)).collect();
+    format!(
+        "CREATE VIRTUAL TABLE {} USING fts5({})",
+        fts_table(table),
+        columns.join(", ")
+    )
+}
+
+// `INSERT INTO {fts} (rowid, columns...)` and the matching `rowid, values...` of the `row` alias
+fn insert_parts(table: &str, fields: &[String], row: &str) -> (String, String) {
+    let columns: Vec<String> = fields.iter().map(|f| fts_column(f)).collect();
+    let values: Vec<String> = fields
+        .iter()
+        .map(|f| format!("json_extract({row}.body, '$.{f}')"))
+        .collect();
+    (
+        format!("INSERT INTO {} (rowid, {})", fts_table(table), columns.join(", ")),
+        format!("{row}.rowid, {}", values.join(", ")),
+    )
+}
+
+fn drop_ddl(table: &str) -> String {
+    format!(
+        "DROP TRIGGER IF EXISTS {table}_fts_ai;
+        DROP TRIGGER IF EXISTS {table}_fts_ad;
+        DROP TRIGGER IF EXISTS {table}_fts_au;
+        DROP TABLE IF EXISTS {fts};",
+        fts = fts_table(table)
+    )
+}
+
+/// Create, rebuild or drop the shadow table of `table` so it indexes exactly `fields`.
+///
+/// The table is refilled from the collection when it was (re)created, or when its triggers went
+/// missing because the collection table itself was rebuilt.
+pub fn sync(conn: &rusqlite::Connection, table: &str, fields: &[String]) -> rusqlite::Result<()> {
+    let fts = fts_table(table);
+    let current: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![fts],
+            |r| r.get(0),
+        )
+        .optional()?;
+    if fields.is_empty() {
+        if current.is_some() {
+            conn.execute_batch(&drop_ddl(table))?;
+        }
+        return Ok(());
+    }
+    let ddl = table_ddl(table, fields);
+    let triggers_present: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'trigger' AND name = ?1)",
+        params![format!("{table}_fts_ai")],
+        |r| r.get(0),
+    )?;
+    if current.as_deref() == Some(ddl.as_str()) && triggers_present {
+        return Ok(());
+    }
+    tracing::info!("rebuild full text index {}", fts);
+    let (insert, new_values) = insert_parts(table, fields, "new");
+    let (_, existing_values) = insert_parts(table, fields, "t");
+    conn.execute_batch(&format!(
+        "{drop}
+        {ddl};
+        CREATE TRIGGER {table}_fts_ai AFTER INSERT ON {table} BEGIN
+            {insert} VALUES ({new_values});
+        END;
+        CREATE TRIGGER {table}_fts_ad AFTER DELETE ON {table} BEGIN
+            DELETE FROM {fts} WHERE rowid = old.rowid;
+        END;
+        CREATE TRIGGER {table}_fts_au AFTER UPDATE OF body ON {table} BEGIN
+            DELETE FROM {fts} WHERE rowid = old.rowid;
+            {insert} VALUES ({new_values});
+        END;
+        {insert} SELECT {existing_values} FROM {table} t;",
+        drop = drop_ddl(table),
+    ))
+}
+
+/// Turn user input into an FTS5 query matching documents holding every term.
+///
+/// Each term is quoted, the FTS5 operators and column filters of the input are searched for as
+/// plain text instead of being interpreted.
+pub fn match_query(input: &str) -> StoreResult<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        return Err(StoreError::Validation("empty search query".to_string()));
+    }
+    Ok(terms.join(" "))
+}
@@ -0,0 +1,73 @@
+//! Custom JSON Schema keywords registered by embedders.
+//!
+//! A keyword factory is called once per occurrence of the keyword while compiling a collection schema,
+//! with the keyword value and a handle on the namespace database, so the returned validator can look
+//! up other documents:
+//! ```rust,ignore
+//! let schemas = DataSchemasBuilder::new()
+//!     .add_keyword("db_exists", Arc::new(|value, db| Ok(Box::new(DbExists::new(value, db)?))))
+//!     .add_schema("post", json!({ "properties": { "author": { "db_exists": "user" } } }))
+//!     .build();
+//! ```
+
+use std::sync::Arc;
+
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use r2d2_sqlite::rusqlite::{OptionalExtension, params};
+use serde_json::Value;
+
+pub use jsonschema::{Keyword, ValidationError};
+
+use crate::backend::sqlite::sanitize_table_name;
+use crate::backend::tx;
+use crate::error::{StoreError, StoreResult};
+
+/// Keywords implemented by the crate itself, they can not be registered again.
+pub(crate) const BUILTIN_KEYWORDS: [&str; 1] = ["x-parent-id"];
+
+/// Build the validator of one keyword occurrence from its value in the schema.
+pub type KeywordFactory = Arc<dyn Fn(&Value, KeywordContext) -> StoreResult<Box<dyn Keyword>> + Send + Sync>;
+
+/// Read access to the namespace database, given to the custom keywords.
+#[derive(Clone)]
+pub struct KeywordContext {
+    pool: Arc<Pool<SqliteConnectionManager>>,
+    readers: Option<Arc<Pool<SqliteConnectionManager>>>,
+}
+
+impl KeywordContext {
+    pub(crate) fn new(
+        pool: Arc<Pool<SqliteConnectionManager>>,
+        readers: Option<Arc<Pool<SqliteConnectionManager>>>,
+    ) -> Self {
+        Self { pool, readers }
+    }
+
+    /// Body of the document, none if it does not exist.
+    pub fn get(&self, collection: &str, id: &str) -> StoreResult<Option<Value>> {
+        let conn = tx::read_conn(&self.pool, self.readers.as_ref())?;
+        let sql = format!("SELECT body FROM {} WHERE id = ?1", sanitize_table_name(collection));
+        let body: Option<String> = conn.query_row(&sql, params![id], |r| r.get(0)).optional()?;
+        Ok(body.map(|b| serde_json::from_str(&b)).transpose()?)
+    }
+
+    pub fn exists(&self, collection: &str, id: &str) -> StoreResult<bool> {
+        let conn = tx::read_conn(&self.pool, self.readers.as_ref())?;
+        let sql = format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE id = ?1)",
+            sanitize_table_name(collection)
+        );
+        Ok(conn.query_row(&sql, params![id], |r| r.get(0))?)
+    }
+}
+
+pub(crate) fn check_keyword_name(name: &str) -> StoreResult<()> {
+    if name.is_empty() || BUILTIN_KEYWORDS.contains(&name) {
+        return Err(StoreError::Validation(format!(
+            "keyword '{}' can not be registered",
+            name
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,356 @@
+//! Documents kept in process maps, no database behind.
+//!
+//! Meant for tests and examples exercising the [`Backend`] behavior: the collection schemas are
+//! validated, `x-unique` (with `x-unique-scope`, or as `x-unique-per-parent`) and `x-parent-id` are
+//! enforced as the sqlite backend does, but nothing survives the process and there is no change
+//! log, acl or attachment storage.
+//!
+//! It is used on its own, through the [`Backend`] trait. A [`Store`](crate::store::Store) keeps its
+//! namespaces in sqlite, the acls, change log, locks and the rest of the store live in the database,
+//! use `DataManagerBuilder::add_memory_db` for a store without files.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use serde_json::Value;
+
+use crate::backend::schema::{
+    SCHEMA_REF_PREFIX, XParentIdMeta, parse_field_paths, parse_schema_version, parse_unique, schema_draft,
+};
+use crate::backend::{Backend, body_field, check_field_path, check_immutable, set_body_field};
+use crate::error::{StoreError, StoreResult};
+use crate::types::{DataItem, Id};
+
+/// Builder to create a MemoryBackend, register each collection schema then call `build()`.
+#[derive(Default)]
+pub struct MemoryBackendBuilder {
+    collection_schemas: Vec<(String, Value)>, // (collection name, json schema)
+}
+
+impl MemoryBackendBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_collection_schema(mut self, collection: &str, schema: Value) -> Self {
+        self.collection_schemas.push((collection.to_string(), schema));
+        self
+    }
+
+    pub fn build(self) -> StoreResult<MemoryBackend> {
+        let mut resources = HashMap::new();
+        for (name, schema) in self.collection_schemas.iter() {
+            if resources.insert(name.clone(), schema.clone()).is_some() {
+                return Err(StoreError::Validation(format!(
+                    "schema name '{}' registered more than once",
+                    name
+                )));
+            }
+        }
+        let mut collections = HashMap::new();
+        for (collection, schema) in self.collection_schemas {
+            let draft = schema_draft(&schema)?;
+            let validator = jsonschema::options()
+                .with_draft(draft)
+                .with_resources(resources.iter().map(|(name, schema)| {
+                    (
+                        format!("{}{}", SCHEMA_REF_PREFIX, name),
+                        draft.detect(schema).create_resource(schema.clone()),
+                    )
+                }))
+                .build(&schema)
+                .map_err(|e| StoreError::Validation(format!("invalid schema: {}", e)))?;
+            let parent = schema
+                .get("x-parent-id")
+                .and_then(|v| serde_json::from_value::<XParentIdMeta>(v.clone()).ok());
+            if let Some(xpm) = &parent {
+                check_field_path("x-parent-id", &xpm.field)?;
+            }
+            let (unique_field, parent_scoped_unique) = parse_unique(&schema, &collection, parent.is_some())?;
+            collections.insert(
+                collection,
+                MemoryCollection {
+                    validator,
+                    unique_field,
+                    parent_scoped_unique,
+                    parent,
+                    immutable_fields: parse_field_paths(&schema, "x-immutable")?,
+                    schema_version: parse_schema_version(&schema)?,
+                },
+            );
+        }
+        let data = collections.keys().map(|c| (c.clone(), BTreeMap::new())).collect();
+        Ok(MemoryBackend {
+            collections,
+            data: RwLock::new(data),
+        })
+    }
+}
+
+struct MemoryCollection {
+    validator: jsonschema::Validator,
+    unique_field: Option<String>,
+    // the unique field only needs to be unique among siblings
+    parent_scoped_unique: bool,
+    parent: Option<XParentIdMeta>,
+    immutable_fields: Vec<String>,
+    // the writes are stamped with it, no earlier versions are kept
+    schema_version: i64,
+}
+
+/// Backend holding every collection in a map ordered by id, the listings page like the sqlite ones.
+pub struct MemoryBackend {
+    collections: HashMap<String, MemoryCollection>,
+    // dict<collection, dict<id, item>>
+    data: RwLock<HashMap<String, BTreeMap<Id, DataItem>>>,
+}
+
+type Data = HashMap<String, BTreeMap<Id, DataItem>>;
+
+impl MemoryBackend {
+    fn collection(&self, collection: &str) -> StoreResult<&MemoryCollection> {
+        self.collections
+            .get(collection)
+            .ok_or_else(|| StoreError::Validation(format!("collection '{}' not registered", collection)))
+    }
+
+    fn read(&self) -> StoreResult<std::sync::RwLockReadGuard<'_, Data>> {
+        self.data.read().map_err(|e| StoreError::Backend(e.to_string()))
+    }
+
+    fn write(&self) -> StoreResult<std::sync::RwLockWriteGuard<'_, Data>> {
+        self.data.write().map_err(|e| StoreError::Backend(e.to_string()))
+    }
+
+    /// Validate the body and derive its unique and parent id values, the way the sqlite backend stores them.
+    fn check_body(
+        &self,
+        data: &Data,
+        collection: &str,
+        id: &str,
+        body: &Value,
+    ) -> StoreResult<(Option<String>, Option<String>)> {
+        let meta = self.collection(collection)?;
+        meta.validator
+            .validate(body)
+            .map_err(|errors| StoreError::Validation(errors.to_string()))?;
+        let parent_id = match &meta.parent {
+            Some(xpm) => {
+                let parent_id = body_field(body, &xpm.field).and_then(|v| v.as_str());
+                // a parent of another namespace is not held here
+                let exists = parent_id.is_some_and(|p| {
+                    xpm.namespace.is_some() || data.get(&xpm.parent).is_some_and(|items| items.contains_key(p))
+                });
+                if !exists {
+                    return Err(StoreError::Validation(format!(
+                        "parent `{}` of collection `{}` not found",
+                        xpm.parent, collection
+                    )));
+                }
+                parent_id.map(|p| p.to_string())
+            }
+            None => None,
+        };
+        let unique = match meta.unique_field.as_ref().and_then(|field| body_field(body, field)) {
+            Some(v) => Some(match v.as_str() {
+                Some(s) => s.to_string(),
+                None => serde_json::to_string(v)?,
+            }),
+            None => None,
+        };
+        if let Some(unique) = &unique {
+            let taken = data
+                .get(collection)
+                .into_iter()
+                .flat_map(|items| items.values())
+                .any(|other| {
+                    other.id != id
+                        && other.unique.as_ref() == Some(unique)
+                        && (!meta.parent_scoped_unique || other.parent_id == parent_id)
+                });
+            if taken {
+                return Err(StoreError::Validation(format!(
+                    "unique constraint violation: {}.{} = {}",
+                    collection,
+                    meta.unique_field.as_deref().unwrap_or_default(),
+                    unique
+                )));
+            }
+        }
+        Ok((unique, parent_id))
+    }
+
+    fn list_where(
+        &self,
+        collection: &str,
+        marker: Option<String>,
+        limit: usize,
+        matches: impl Fn(&DataItem) -> bool,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.collection(collection)?;
+        let data = self.read()?;
+        let mut items = data[collection]
+            .range(marker.unwrap_or_default()..)
+            .map(|(_, item)| item)
+            .filter(|item| matches(item));
+        let page = items.by_ref().take(limit).cloned().collect();
+        Ok((page, items.next().map(|item| item.id.clone())))
+    }
+
+    fn replace(&self, collection: &str, id: &Id, body: Value) -> StoreResult<DataItem> {
+        let mut data = self.write()?;
+        let Some(current) = data.get(collection).and_then(|items| items.get(id)) else {
+            return Err(StoreError::NotFound(format!("Update Data {} / {}", collection, id)));
+        };
+        let (unique, parent_id) = self.check_body(&data, collection, id, &body)?;
+        let item = DataItem {
+            updated_at: chrono::Utc::now(),
+            unique,
+            parent_id,
+            body,
+            rev: current.rev + 1,
+            schema_version: self.collection(collection)?.schema_version,
+            ..current.clone()
+        };
+        data.entry(collection.to_string())
+            .or_default()
+            .insert(id.clone(), item.clone());
+        Ok(item)
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn import(
+        &self,
+        collection: &str,
+        body: &Value,
+        owner: String,
+        id: String,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+    ) -> StoreResult<String> {
+        let mut data = self.write()?;
+        if data.get(collection).is_some_and(|items| items.contains_key(&id)) {
+            return Err(StoreError::Validation(format!(
+                "unique constraint violation: {}.id = {}",
+                collection, id
+            )));
+        }
+        let (unique, parent_id) = self.check_body(&data, collection, &id, body)?;
+        let item = DataItem {
+            id: id.clone(),
+            created_at,
+            updated_at,
+            owner,
+            unique,
+            parent_id,
+            body: body.clone(),
+            rev: 1,
+            schema_version: self.collection(collection)?.schema_version,
+            lock: None,
+            attachments: Vec::new(),
+            deleted_at: None,
+        };
+        data.entry(collection.to_string()).or_default().insert(id.clone(), item);
+        Ok(id)
+    }
+
+    fn insert(&self, collection: &str, body: &Value, owner: String) -> StoreResult<String> {
+        let now = chrono::Utc::now();
+        self.import(collection, body, owner, uuid::Uuid::new_v4().to_string(), now, now)
+    }
+
+    fn list_by_owner(
+        &self,
+        collection: &str,
+        owner: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.list_where(collection, marker, limit, |item| item.owner == owner)
+    }
+
+    fn list_children(
+        &self,
+        collection: &str,
+        parent_id: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.list_where(collection, marker, limit, |item| {
+            item.parent_id.as_deref() == Some(parent_id)
+        })
+    }
+
+    fn get(&self, collection: &str, id: &Id) -> StoreResult<DataItem> {
+        self.read()?
+            .get(collection)
+            .and_then(|items| items.get(id))
+            .cloned()
+            .ok_or(StoreError::NotFound(format!("Get Data {} / {}", collection, id)))
+    }
+
+    fn get_by_unique(&self, collection: &str, unique: &str) -> StoreResult<DataItem> {
+        let meta = self.collection(collection)?;
+        if meta.unique_field.is_none() {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' does not have unique field defined",
+                collection
+            )));
+        }
+        if meta.parent_scoped_unique {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' unique field is scoped by parent, use get_by_parent_unique",
+                collection
+            )));
+        }
+        self.read()?
+            .get(collection)
+            .and_then(|items| items.values().find(|item| item.unique.as_deref() == Some(unique)))
+            .cloned()
+            .ok_or(StoreError::NotFound("Get Data by Unique".to_string()))
+    }
+
+    fn update(&self, collection: &str, id: &Id, body: &Value) -> StoreResult<DataItem> {
+        let fields = &self.collection(collection)?.immutable_fields;
+        if !fields.is_empty() {
+            check_immutable(collection, fields, &self.get(collection, id)?.body, body)?;
+        }
+        self.replace(collection, id, body.clone())
+    }
+
+    fn move_to_parent(&self, collection: &str, id: &Id, parent_id: &str) -> StoreResult<DataItem> {
+        let Some(xpm) = &self.collection(collection)?.parent else {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' has no parent collection",
+                collection
+            )));
+        };
+        let mut body = self.get(collection, id)?.body;
+        set_body_field(&mut body, &xpm.field, Value::String(parent_id.to_string()))?;
+        self.replace(collection, id, body)
+    }
+
+    fn delete(&self, collection: &str, id: &Id) -> StoreResult<()> {
+        self.write()?
+            .get_mut(collection)
+            .and_then(|items| items.remove(id))
+            .map(|_| ())
+            .ok_or(StoreError::NotFound("Delete Data".to_string()))
+    }
+
+    fn batch_delete(&self, collection: &str, ids: &[Id]) -> StoreResult<()> {
+        let mut data = self.write()?;
+        let Some(items) = data.get_mut(collection) else {
+            return Err(StoreError::NotFound(format!("Delete Data collection={}", collection)));
+        };
+        // nothing is deleted unless every id exists
+        if let Some(missing) = ids.iter().find(|id| !items.contains_key(*id)) {
+            return Err(StoreError::NotFound(format!("Delete Data id={}", missing)));
+        }
+        for id in ids {
+            items.remove(id);
+        }
+        Ok(())
+    }
+}
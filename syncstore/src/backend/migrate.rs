@@ -0,0 +1,103 @@
+//! Stored documents brought along when the schema of their collection changes.
+//!
+//! `x-migrate` runs once, when a database opens with a collection schema differing from the one it
+//! stored, before the new schema is accepted:
+//! ```json
+//! "x-migrate": {
+//!     "rename": { "title": "name" },
+//!     "default": { "status": "normal" },
+//!     "check": "reject"
+//! }
+//! ```
+//! `rename` moves top-level fields the documents hold and the new name is still free, `default`
+//! fills top-level fields the documents lack. With `check` the documents are then validated against
+//! the new schema, `reject` refuses the schema when one of them does not match and `warn` only reports
+//! them. A document left untouched at an earlier `x-version` registered with
+//! `DataSchemasBuilder::add_schema_version` is validated against that version instead.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::{StoreError, StoreResult};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Migration {
+    /// dict<old field, new field>
+    #[serde(default)]
+    pub rename: serde_json::Map<String, Value>,
+    /// dict<field, value>
+    #[serde(default)]
+    pub default: serde_json::Map<String, Value>,
+    #[serde(default)]
+    pub check: Option<MigrationCheck>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationCheck {
+    /// the schema is refused while a stored document does not match it
+    Reject,
+    /// the documents not matching are reported, the schema is accepted
+    Warn,
+}
+
+pub fn parse(schema: &Value) -> StoreResult<Option<Migration>> {
+    let Some(spec) = schema.get("x-migrate") else {
+        return Ok(None);
+    };
+    let migration: Migration = serde_json::from_value(spec.clone())
+        .map_err(|e| StoreError::Validation(format!("invalid x-migrate: {}", e)))?;
+    for (from, to) in &migration.rename {
+        match to.as_str() {
+            Some(to) if !from.is_empty() && !to.is_empty() && from != to => {}
+            _ => {
+                return Err(StoreError::Validation(format!(
+                    "invalid x-migrate rename: '{}' to {}",
+                    from, to
+                )));
+            }
+        }
+    }
+    if migration.default.keys().any(|field| field.is_empty()) {
+        return Err(StoreError::Validation(
+            "invalid x-migrate default: empty field".to_string(),
+        ));
+    }
+    Ok(Some(migration))
+}
+
+impl Migration {
+    /// The top-level fields the transforms write, renamed ones included.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.rename
+            .iter()
+            .flat_map(|(from, to)| [from.as_str(), to.as_str().unwrap_or_default()])
+            .chain(self.default.keys().map(|field| field.as_str()))
+    }
+
+    /// Apply the transforms to a stored body, returns whether it changed.
+    pub fn apply(&self, body: &mut Value) -> bool {
+        let Some(map) = body.as_object_mut() else {
+            return false;
+        };
+        let mut changed = false;
+        for (from, to) in &self.rename {
+            let to = to.as_str().unwrap_or_default();
+            if map.contains_key(to) {
+                continue;
+            }
+            if let Some(value) = map.remove(from) {
+                map.insert(to.to_string(), value);
+                changed = true;
+            }
+        }
+        for (field, value) in &self.default {
+            if !map.contains_key(field) {
+                map.insert(field.clone(), value.clone());
+                changed = true;
+            }
+        }
+        changed
+    }
+}
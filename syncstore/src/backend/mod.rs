@@ -1,4 +1,4 @@
-use crate::error::StoreResult;
+use crate::error::{StoreError, StoreResult};
 use crate::types::{DataItem, Id};
 use serde_json::Value;
 
@@ -46,6 +46,9 @@ pub trait Backend: Send + Sync {
     /// Update an existing document by id
     fn update(&self, collection: &str, id: &Id, body: &Value) -> StoreResult<DataItem>;
 
+    /// Move a document under another parent, rewriting the x-parent-id body field and parent_id together.
+    fn move_to_parent(&self, collection: &str, id: &Id, parent_id: &str) -> StoreResult<DataItem>;
+
     /// Delete a document by id.
     fn delete(&self, collection: &str, id: &Id) -> StoreResult<()>;
 
@@ -53,6 +56,65 @@ pub trait Backend: Send + Sync {
     fn batch_delete(&self, collection: &str, ids: &[Id]) -> StoreResult<()>;
 }
 
+pub mod blob;
+pub mod blocking;
+mod computed;
+pub mod deadline;
+pub mod encrypt;
+pub mod filter;
+mod fulltext;
+pub mod keyword;
+pub mod memory;
+pub mod migrate;
+pub mod patch;
+pub mod schema;
 pub mod sqlite;
+pub mod tx;
+pub mod write_queue;
 
+pub use memory::MemoryBackend;
 pub use sqlite::SqliteBackend;
+
+/// The value at a dotted body path, the `x-unique` and `x-parent-id` fields can be nested like `profile.email`.
+pub fn body_field<'a>(body: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(body, |value, key| value.get(key))
+}
+
+// set the value at a dotted body path, the objects missing on the way are created
+pub(crate) fn set_body_field(body: &mut Value, path: &str, value: Value) -> StoreResult<()> {
+    let mut current = body;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        let Some(map) = current.as_object_mut() else {
+            return Err(StoreError::Validation(format!("body field '{}': not an object", path)));
+        };
+        if keys.peek().is_none() {
+            map.insert(key.to_string(), value);
+            return Ok(());
+        }
+        current = map.entry(key).or_insert_with(|| Value::Object(serde_json::Map::new()));
+    }
+    Ok(())
+}
+
+// reject an update changing one of the `x-immutable` fields, a field the update drops is changed as well
+pub(crate) fn check_immutable(collection: &str, fields: &[String], current: &Value, body: &Value) -> StoreResult<()> {
+    match fields
+        .iter()
+        .find(|field| body_field(current, field) != body_field(body, field))
+    {
+        Some(field) => Err(StoreError::Validation(format!(
+            "x-immutable: field '{}' of collection '{}' can not be changed",
+            field, collection
+        ))),
+        None => Ok(()),
+    }
+}
+
+// a dotted path without empty segments
+pub(crate) fn check_field_path(keyword: &str, path: &str) -> StoreResult<()> {
+    if path.split('.').any(str::is_empty) {
+        return Err(StoreError::Validation(format!("invalid {} field: '{}'", keyword, path)));
+    }
+    Ok(())
+}
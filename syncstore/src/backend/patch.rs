@@ -0,0 +1,187 @@
+//! JSON Patch (RFC 6902) operations on a document body.
+//!
+//! The operations address the body with JSON Pointers (RFC 6901) and are applied in order:
+//! ```json
+//! [
+//!   { "op": "test", "path": "/title", "value": "draft" },
+//!   { "op": "add", "path": "/tags/-", "value": "urgent" },
+//!   { "op": "remove", "path": "/tags/0" }
+//! ]
+//! ```
+//! A failing operation, a `test` included, leaves the body untouched.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{StoreError, StoreResult};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, salvo::oapi::ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PatchOp {
+    Add {
+        path: String,
+        value: Value,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        value: Value,
+    },
+    Move {
+        from: String,
+        path: String,
+    },
+    Copy {
+        from: String,
+        path: String,
+    },
+    /// fails the patch unless the value at `path` equals `value`
+    Test {
+        path: String,
+        value: Value,
+    },
+}
+
+/// Apply the operations to the body, all of them or none.
+pub fn apply(body: &mut Value, ops: &[PatchOp]) -> StoreResult<()> {
+    let mut patched = body.clone();
+    for (i, op) in ops.iter().enumerate() {
+        apply_op(&mut patched, op).map_err(|e| StoreError::Validation(format!("json patch op {}: {}", i, e)))?;
+    }
+    *body = patched;
+    Ok(())
+}
+
+fn apply_op(doc: &mut Value, op: &PatchOp) -> Result<(), String> {
+    match op {
+        PatchOp::Add { path, value } => add(doc, path, value.clone()),
+        PatchOp::Remove { path } => remove(doc, path).map(|_| ()),
+        PatchOp::Replace { path, value } => {
+            *doc.pointer_mut(path).ok_or_else(|| missing(path))? = value.clone();
+            Ok(())
+        }
+        PatchOp::Move { from, path } => {
+            if path == from {
+                return Ok(());
+            }
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(format!("can not move '{}' into itself", from));
+            }
+            let value = remove(doc, from)?;
+            add(doc, path, value)
+        }
+        PatchOp::Copy { from, path } => {
+            let value = doc.pointer(from).ok_or_else(|| missing(from))?.clone();
+            add(doc, path, value)
+        }
+        PatchOp::Test { path, value } => match doc.pointer(path) {
+            Some(current) if current == value => Ok(()),
+            Some(_) => Err(format!("test of '{}' failed", path)),
+            None => Err(missing(path)),
+        },
+    }
+}
+
+fn add(doc: &mut Value, path: &str, value: Value) -> Result<(), String> {
+    if path.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let (parent, key) = split(path)?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(map)) => {
+            map.insert(key, value);
+            Ok(())
+        }
+        Some(Value::Array(items)) => {
+            let index = match key.as_str() {
+                "-" => items.len(),
+                _ => array_index(&key, items.len() + 1)?,
+            };
+            items.insert(index, value);
+            Ok(())
+        }
+        Some(_) => Err(format!("'{}' is neither an object nor an array", parent)),
+        None => Err(missing(parent)),
+    }
+}
+
+fn remove(doc: &mut Value, path: &str) -> Result<Value, String> {
+    let (parent, key) = split(path)?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(map)) => map.remove(&key).ok_or_else(|| missing(path)),
+        Some(Value::Array(items)) => {
+            let index = array_index(&key, items.len())?;
+            Ok(items.remove(index))
+        }
+        _ => Err(missing(path)),
+    }
+}
+
+// the parent pointer and the unescaped last token
+fn split(path: &str) -> Result<(&str, String), String> {
+    match path.rsplit_once('/') {
+        Some((parent, key)) if path.starts_with('/') => Ok((parent, key.replace("~1", "/").replace("~0", "~"))),
+        _ => Err(format!("invalid path '{}'", path)),
+    }
+}
+
+// an index below `len`, digits without a leading zero
+fn array_index(token: &str, len: usize) -> Result<usize, String> {
+    let digits = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit());
+    match token.parse::<usize>() {
+        Ok(index) if digits && (token == "0" || !token.starts_with('0')) && index < len => Ok(index),
+        _ => Err(format!("invalid array index '{}'", token)),
+    }
+}
+
+fn missing(path: &str) -> String {
+    format!("path '{}' does not exist", path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ops(ops: Value) -> Vec<PatchOp> {
+        serde_json::from_value(ops).unwrap()
+    }
+
+    #[test]
+    fn test_apply_ops() {
+        let mut body = json!({ "title": "draft", "tags": ["a", "b"], "meta": { "a/b": 1 } });
+        let patch = ops(json!([
+            { "op": "test", "path": "/title", "value": "draft" },
+            { "op": "add", "path": "/tags/-", "value": "c" },
+            { "op": "add", "path": "/tags/0", "value": "z" },
+            { "op": "remove", "path": "/tags/2" },
+            { "op": "replace", "path": "/title", "value": "done" },
+            { "op": "move", "from": "/meta/a~1b", "path": "/count" },
+            { "op": "copy", "from": "/tags", "path": "/meta/tags" }
+        ]));
+        apply(&mut body, &patch).unwrap();
+        assert_eq!(
+            body,
+            json!({ "title": "done", "tags": ["z", "a", "c"], "count": 1, "meta": { "tags": ["z", "a", "c"] } })
+        );
+    }
+
+    #[test]
+    fn test_failed_op_leaves_body() {
+        let body = json!({ "title": "draft", "tags": ["a"] });
+        for patch in [
+            json!([{ "op": "add", "path": "/title", "value": "x" }, { "op": "test", "path": "/title", "value": "y" }]),
+            json!([{ "op": "remove", "path": "/missing" }]),
+            json!([{ "op": "add", "path": "/tags/2", "value": "x" }]),
+            json!([{ "op": "remove", "path": "/tags/01" }]),
+            json!([{ "op": "move", "from": "/tags", "path": "/tags/0" }]),
+        ] {
+            let mut patched = body.clone();
+            assert!(apply(&mut patched, &ops(patch)).is_err());
+            assert_eq!(patched, body);
+        }
+    }
+}
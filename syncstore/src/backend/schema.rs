@@ -0,0 +1,121 @@
+//! The `x-` keywords of the collection schemas, read the same way by every backend.
+//!
+//! Only the parsing lives here, the keywords are enforced by [`SqliteBackend`](super::SqliteBackend)
+//! and [`MemoryBackend`](super::MemoryBackend) each on their own storage.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::backend::check_field_path;
+use crate::error::{StoreError, StoreResult};
+
+/// Every collection schema and shared definition of a database can be referenced
+/// from other schemas of the same database with `{"$ref": "urn:syncstore:<name>"}`.
+pub const SCHEMA_REF_PREFIX: &str = "urn:syncstore:";
+
+/// `x-parent-id: {"parent": "repo", "field": "repo_id"}`, the collection and body field of the parent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct XParentIdMeta {
+    /// namespace of the parent collection when not the one of the child, its database is not
+    /// reachable from here and the store checks the parent instead
+    #[serde(default)]
+    pub namespace: Option<String>,
+    pub parent: String,
+    pub field: String,
+}
+
+/// Pick the JSON Schema draft from the `$schema` keyword, draft 7 if absent.
+pub(crate) fn schema_draft(schema: &Value) -> StoreResult<jsonschema::Draft> {
+    match jsonschema::Draft::Draft7.detect(schema) {
+        draft @ (jsonschema::Draft::Draft4
+        | jsonschema::Draft::Draft6
+        | jsonschema::Draft::Draft7
+        | jsonschema::Draft::Draft201909
+        | jsonschema::Draft::Draft202012) => Ok(draft),
+        _ => Err(StoreError::Validation(format!(
+            "unsupported $schema: {}",
+            schema.get("$schema").unwrap_or(&Value::Null)
+        ))),
+    }
+}
+
+// `x-index` / `x-fulltext: ["field", "nested.field"]`, the names end up in the DDL so only plain paths are accepted
+pub(crate) fn parse_field_paths(schema: &Value, keyword: &str) -> StoreResult<Vec<String>> {
+    let Some(spec) = schema.get(keyword) else {
+        return Ok(Vec::new());
+    };
+    let invalid = || StoreError::Validation(format!("invalid {}: {}", keyword, spec));
+    let mut fields: Vec<String> = Vec::new();
+    for field in spec.as_array().ok_or_else(invalid)? {
+        let field = field.as_str().ok_or_else(invalid)?;
+        let plain = field
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        if !plain {
+            return Err(StoreError::Validation(format!(
+                "invalid {} field: '{}'",
+                keyword, field
+            )));
+        }
+        if !fields.iter().any(|f| f == field) {
+            fields.push(field.to_string());
+        }
+    }
+    Ok(fields)
+}
+
+// `x-unique: "slug"` with `x-unique-scope: "parent"`, or the `x-unique-per-parent: "slug"` shorthand:
+// the unique field if any and whether it only needs to be unique among the siblings of one parent
+pub(crate) fn parse_unique(schema: &Value, collection: &str, has_parent: bool) -> StoreResult<(Option<String>, bool)> {
+    fn field<'a>(schema: &'a Value, keyword: &str) -> StoreResult<Option<&'a str>> {
+        match schema.get(keyword).and_then(|v| v.as_str()) {
+            Some(field) if !field.is_empty() => check_field_path(keyword, field).map(|_| Some(field)),
+            _ => Ok(None),
+        }
+    }
+    let (unique, per_parent) = (field(schema, "x-unique")?, field(schema, "x-unique-per-parent")?);
+    let scope = schema.get("x-unique-scope").and_then(|v| v.as_str());
+    let (field, parent_scoped, origin) = match (unique, per_parent) {
+        (Some(_), Some(_)) => {
+            return Err(StoreError::Validation(format!(
+                "x-unique and x-unique-per-parent both set in collection '{}'",
+                collection
+            )));
+        }
+        (None, Some(_)) if scope.is_some() => {
+            return Err(StoreError::Validation(format!(
+                "x-unique-per-parent takes no x-unique-scope in collection '{}'",
+                collection
+            )));
+        }
+        (None, Some(field)) => (Some(field), true, "x-unique-per-parent"),
+        (field, None) => match scope {
+            None | Some("global") => (field, false, "x-unique"),
+            Some("parent") => (field, true, "x-unique-scope 'parent'"),
+            Some(other) => {
+                return Err(StoreError::Validation(format!("invalid x-unique-scope: {}", other)));
+            }
+        },
+    };
+    if parent_scoped && !has_parent {
+        return Err(StoreError::Validation(format!(
+            "{} requires x-parent-id in collection '{}'",
+            origin, collection
+        )));
+    }
+    Ok((field.map(|field| field.to_string()), parent_scoped))
+}
+
+// `x-version: 3`, the version of the schema the writes are stamped with, 1 when absent
+pub(crate) fn parse_schema_version(schema: &Value) -> StoreResult<i64> {
+    match schema.get("x-version") {
+        None => Ok(1),
+        Some(v) => match v.as_i64() {
+            Some(version) if version > 0 => Ok(version),
+            _ => Err(StoreError::Validation(format!(
+                "x-version: expected a positive integer: {}",
+                v
+            ))),
+        },
+    }
+}
@@ -1,19 +1,34 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 
-use r2d2::{Pool, PooledConnection};
+use r2d2::Pool;
+use r2d2_sqlite::rusqlite::types::Value as SqlValue;
 use r2d2_sqlite::rusqlite::{OptionalExtension, params};
 use r2d2_sqlite::{SqliteConnectionManager, rusqlite};
 use serde_json::Value;
 
-use crate::backend::Backend;
+use crate::backend::blob::{self, BlobOffload, hex_sha256};
+use crate::backend::encrypt::{self, FieldCipher};
+use crate::backend::filter::{FilterExpr, SortSpec};
+use crate::backend::keyword::{self, KeywordContext, KeywordFactory};
+use crate::backend::migrate::{self, Migration, MigrationCheck};
+use crate::backend::schema::{
+    SCHEMA_REF_PREFIX, XParentIdMeta, parse_field_paths, parse_schema_version, parse_unique, schema_draft,
+};
+use crate::backend::{Backend, body_field, check_field_path, check_immutable, computed, fulltext, set_body_field, tx};
 use crate::error::{StoreError, StoreResult};
-use crate::types::{AccessLevel, DataItem, DataItemDocument, Id, PermissionSchema};
+use crate::types::{
+    AccessLevel, AccessStats, AclDirection, AclEntry, Attachment, AttachmentContent, Change, ChangeMeta, ChangeOp,
+    CollectionMode, CollectionStats, CollectionUsage, DataItem, DataItemDocument, Id, ImportMode, Imported, ItemLock,
+    ListOrder, NamespaceHealth, Orphan, Page, ParentCount, Permission, PermissionSchema, SavedView, ServiceAccess,
+    ServiceKey, SortKey, SortMarker, ValidationIssue, Webhook, WebhookDelivery,
+};
 
 // ?let's write some user define schema checker here for now, late move to separate file module.
-mod checker {
+pub(crate) mod checker {
     use std::sync::Arc;
 
     use jsonschema::Keyword;
@@ -22,18 +37,14 @@ mod checker {
         SqliteConnectionManager,
         rusqlite::{OptionalExtension, params},
     };
-    use serde::Deserialize;
 
+    use crate::backend::schema::XParentIdMeta;
     use crate::backend::sqlite::sanitize_table_name;
-
-    #[derive(Debug, Clone, Deserialize)]
-    pub struct XParentIdMeta {
-        pub parent: String,
-        pub field: String,
-    }
+    use crate::backend::{body_field, tx};
 
     pub struct XParentId {
         pub pool: Arc<Pool<SqliteConnectionManager>>,
+        pub readers: Option<Arc<Pool<SqliteConnectionManager>>>,
         pub meta: XParentIdMeta,
     }
 
@@ -48,10 +59,13 @@ mod checker {
             // );
             let m = &self.meta;
             tracing::info!("x_parent[validate] check meta: {:?}", m);
-            let Some(value) = instance.get(&m.field).and_then(|f| f.as_str()) else {
+            let Some(value) = body_field(instance, &m.field).and_then(|f| f.as_str()) else {
                 return Err(msg_err("x_parent: field value missing or not string".into()));
             };
-            let Ok(conn) = self.pool.get() else {
+            if m.namespace.is_some() {
+                return Ok(());
+            }
+            let Ok(conn) = tx::read_conn(&self.pool, self.readers.as_ref()) else {
                 return Err(msg_err("x_parent: failed to get db connection".into()));
             };
             let sql = format!(
@@ -88,8 +102,11 @@ mod checker {
                 "SELECT body, owner FROM {} WHERE id = ?1 LIMIT 1",
                 sanitize_table_name(&m.parent)
             );
-            if let Some(value) = instance.get(&m.field).and_then(|f| f.as_str())
-                && let Ok(conn) = self.pool.get()
+            if m.namespace.is_some() {
+                return body_field(instance, &m.field).is_some_and(|f| f.is_string());
+            }
+            if let Some(value) = body_field(instance, &m.field).and_then(|f| f.as_str())
+                && let Ok(conn) = tx::read_conn(&self.pool, self.readers.as_ref())
                 && let Ok(Some((_body_text, _parent_owner))) = conn
                     .query_row(&sql, params![value], |r| {
                         let body_text: String = r.get(0)?;
@@ -105,14 +122,38 @@ mod checker {
     }
 }
 
+/// Body field of a document of an `x-ttl` collection setting when it expires, an RFC 3339 date-time
+/// taking over from the lifetime of the collection.
+pub const EXPIRES_AT_FIELD: &str = "expires_at";
+
+// documents listed by a refused schema change, the others are only counted
+const MAX_REPORTED_DOCUMENTS: usize = 20;
+
+// problems reported by the integrity check of `SqliteBackend::vacuum`, it stops after them
+const MAX_INTEGRITY_ERRORS: usize = 100;
+
+/// Connections of the read-only pool of a database file, see [`SqliteBackendBuilder::read_connections`].
+pub const DEFAULT_READ_CONNECTIONS: u32 = 8;
+
+/// How long a write waits for the writer connection of a database file before it fails.
+pub const WRITER_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Builder to create a SqliteBackend with options.
 ///
 /// 1. first use `SqliteBackendBuilder::memory()` or `SqliteBackendBuilder::file(path)`
 /// 2. then optionally call `with_collection_schema` to register each collection schemas,
+///    `with_schema_version` for the earlier versions of them stored documents may still be at,
+///    and `with_definition` to register shared sub-schemas referenced by `$ref`,
 /// 3. finally call `build()` to get the backend instance.
 pub struct SqliteBackendBuilder {
-    path: Option<PathBuf>,                    // if None, use in-memory database
-    collection_schemas: Vec<(String, Value)>, // (collection name, json schema)
+    path: Option<PathBuf>,                      // if None, use in-memory database
+    collection_schemas: Vec<(String, Value)>,   // (collection name, json schema)
+    schema_versions: Vec<(String, i64, Value)>, // (collection name, version, json schema), earlier versions
+    definitions: Vec<(String, Value)>,          // (definition name, json schema), no table behind
+    keywords: Vec<(String, KeywordFactory)>,    // (keyword name, factory), custom validation keywords
+    read_only: bool,
+    read_connections: u32,
+    encryption_key: Option<String>,
 }
 
 impl SqliteBackendBuilder {
@@ -120,33 +161,158 @@ impl SqliteBackendBuilder {
         Self {
             path: None,
             collection_schemas: Vec::new(),
+            schema_versions: Vec::new(),
+            definitions: Vec::new(),
+            keywords: Vec::new(),
+            read_only: false,
+            read_connections: DEFAULT_READ_CONNECTIONS,
+            encryption_key: None,
         }
     }
     pub fn file<P: AsRef<Path>>(path: P) -> Self {
         Self {
             path: Some(path.as_ref().to_path_buf()),
             collection_schemas: Vec::new(),
+            schema_versions: Vec::new(),
+            definitions: Vec::new(),
+            keywords: Vec::new(),
+            read_only: false,
+            read_connections: DEFAULT_READ_CONNECTIONS,
+            encryption_key: None,
         }
     }
 
+    /// Open an existing database file with read-only flags, nothing is ever written to it.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
     pub fn with_collection_schema(mut self, collection: &str, schema: Value) -> Self {
         self.collection_schemas.push((collection.to_string(), schema));
         self
     }
+
+    /// Size of the pool of read-only connections serving the reads of a database file outside a
+    /// transaction, [`DEFAULT_READ_CONNECTIONS`] by default. The writes go through the one writer
+    /// connection of the file, a long listing never holds it and a long write never holds the reads.
+    pub fn read_connections(mut self, connections: u32) -> Self {
+        self.read_connections = connections;
+        self
+    }
+
+    /// Encrypt the database file with SQLCipher under this passphrase, needs the `sqlcipher` feature.
+    ///
+    /// The key is set on every connection before anything else runs, a file created without it or
+    /// under another key fails to open.
+    pub fn encryption_key(mut self, key: &str) -> Self {
+        self.encryption_key = Some(key.to_string());
+        self
+    }
+
+    /// Register an earlier version of a collection schema, below the `x-version` of the current one.
+    pub fn with_schema_version(mut self, collection: &str, version: i64, schema: Value) -> Self {
+        self.schema_versions.push((collection.to_string(), version, schema));
+        self
+    }
+
+    pub fn with_definition(mut self, name: &str, schema: Value) -> Self {
+        self.definitions.push((name.to_string(), schema));
+        self
+    }
+
+    /// Register a custom schema keyword, see [`keyword`].
+    pub fn with_keyword(mut self, name: &str, factory: KeywordFactory) -> Self {
+        self.keywords.push((name.to_string(), factory));
+        self
+    }
+
     pub fn build(self) -> StoreResult<SqliteBackend> {
-        let mut backend = if let Some(p) = self.path {
-            SqliteBackend::open(p)?
-        } else {
-            SqliteBackend::memory()?
+        let mut names = HashSet::new();
+        for (name, _) in &self.keywords {
+            keyword::check_keyword_name(name)?;
+            if !names.insert(name.as_str()) {
+                return Err(StoreError::Validation(format!(
+                    "keyword '{}' registered more than once",
+                    name
+                )));
+            }
+        }
+        for (collection, _) in &self.collection_schemas {
+            check_collection_name(collection)?;
+        }
+        if self.read_connections == 0 {
+            return Err(StoreError::Validation("read connections must be positive".to_string()));
+        }
+        match &self.encryption_key {
+            Some(_) if !cfg!(feature = "sqlcipher") => {
+                return Err(StoreError::Validation(
+                    "database encryption needs syncstore built with the `sqlcipher` feature".to_string(),
+                ));
+            }
+            Some(_) if self.path.is_none() => {
+                return Err(StoreError::Validation(
+                    "a memory database can not be encrypted".to_string(),
+                ));
+            }
+            Some(key) if key.is_empty() => {
+                return Err(StoreError::Validation("empty database encryption key".to_string()));
+            }
+            _ => {}
+        }
+        let mut backend = match self.path {
+            Some(p) if self.read_only => {
+                let tables = self.collection_schemas.iter().map(|(c, _)| c.as_str()).collect();
+                SqliteBackend::open_read_only(p, tables, self.encryption_key)?
+            }
+            Some(p) => SqliteBackend::open(p, self.read_connections, self.encryption_key)?,
+            None if self.read_only => {
+                return Err(StoreError::Validation(
+                    "a memory database can not be read-only".to_string(),
+                ));
+            }
+            None => SqliteBackend::memory()?,
         };
+        // all schemas are known before compiling, so `$ref` does not depend on registration order
+        let mut resources = HashMap::new();
+        for (name, schema) in self.definitions.iter().chain(self.collection_schemas.iter()) {
+            if resources.insert(name.clone(), schema.clone()).is_some() {
+                return Err(StoreError::Validation(format!(
+                    "schema name '{}' registered more than once",
+                    name
+                )));
+            }
+        }
         // set collection schemas
+        let mut migrations = Vec::new();
         for (collection, schema) in self.collection_schemas {
-            backend.init_collection_schema(&collection, &schema)?;
+            if let Some(migration) = backend.init_collection_schema(&collection, &schema, &resources, &self.keywords)? {
+                migrations.push((collection, migration));
+            }
+        }
+        for (collection, version, schema) in &self.schema_versions {
+            backend.init_earlier_version(collection, *version, schema, &resources, &self.keywords)?;
         }
+        // once every table exists, the x-parent-id checks of the migrated documents read their parents
+        for (collection, migration) in migrations {
+            backend.migrate_documents(&collection, &migration)?;
+        }
+        backend.schema_resources = resources;
         Ok(backend)
     }
 }
 
+/// What deleting a parent does to the children of a collection, `x-on-parent-delete` of its schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnParentDelete {
+    /// the children stay behind, `Store::find_orphans` lists them
+    Orphan,
+    /// the children are deleted along, their own children as their collection says
+    Cascade,
+    /// the parent can not be deleted while it has children
+    Restrict,
+}
+
 /// One sqlite backend handle one certain database (file or memory)
 /// Each database may contain multiple collections (tables).
 /// Each collection do have its own JSON schema (stored in __schemas table).
@@ -154,12 +320,64 @@ impl SqliteBackendBuilder {
 /// User `SqliteBackendBuilder` to create an instance.
 pub struct SqliteBackend {
     pool: Arc<Pool<SqliteConnectionManager>>,
+    // `PRAGMA query_only` connections of a database file, the reads outside a transaction go there
+    readers: Option<Arc<Pool<SqliteConnectionManager>>>,
+    // SQLCipher key of the database file, the backups are encrypted under it as well
+    encryption_key: Option<String>,
     // every collection's compiled schema validator
     schema_validator: HashMap<String, jsonschema::Validator>,
+    // every collection's schema as registered in __schemas
+    schema_json: HashMap<String, String>,
+    // every collection's schema version (`x-version`), 1 when absent
+    schema_versions: HashMap<String, i64>,
+    // validators of the earlier schema versions stored documents may still be at, dict<(collection, version), validator>
+    earlier_validators: HashMap<(String, i64), jsonschema::Validator>,
+    // dict<schema name, schema>, every collection schema and shared definition `$ref` can reach
+    schema_resources: HashMap<String, Value>,
 
     // every collection's parent collection info
-    parent_ref: HashMap<String, checker::XParentIdMeta>,
+    parent_ref: HashMap<String, XParentIdMeta>,
     unique_fields: HashMap<String, String>, // collection -> unique field
+    // collections whose unique field only needs to be unique among siblings (`x-unique-scope: "parent"`
+    // or `x-unique-per-parent`)
+    parent_scoped_unique: HashSet<String>,
+    // what deleting a parent does to the children of a collection, orphaned when absent (`x-on-parent-delete`)
+    on_parent_delete: HashMap<String, OnParentDelete>,
+    // every collection's server computed fields
+    computed_fields: HashMap<String, Vec<(String, computed::ComputedExpr)>>,
+    // every collection's body fields mirroring meta timestamps
+    timestamp_fields: HashMap<String, computed::TimestampFields>,
+    // oversized body fields go to the blob store when set
+    blob_offload: RwLock<Option<BlobOffload>>,
+    // every collection's body fields encrypted at rest (`x-encrypt-fields`)
+    encrypted_fields: HashMap<String, Vec<String>>,
+    // key of the encrypted fields, writing or reading them fails without it
+    field_cipher: RwLock<Option<FieldCipher>>,
+    // every collection's body fields an update can not change (`x-immutable`)
+    immutable_fields: HashMap<String, Vec<String>>,
+    // every collection's largest body in bytes of its JSON text (`x-max-bytes`)
+    max_bytes: HashMap<String, usize>,
+    // every collection's body fields with an indexed generated column (`x-index`)
+    indexed_fields: HashMap<String, Vec<String>>,
+    // every collection's body fields in its FTS5 shadow table (`x-fulltext`)
+    fulltext_fields: HashMap<String, Vec<String>>,
+    // collections counting the reads of their items (`x-access-stats: true`)
+    access_stats: HashSet<String>,
+    // collections marking their deleted rows instead of removing them (`x-soft-delete: true`)
+    soft_delete: HashSet<String>,
+    // collections whose documents expire (`x-ttl`), with the lifetime after the last write, none when
+    // only the `expires_at` body field sets it
+    ttl: HashMap<String, Option<u64>>,
+    // read-only collections whose table has no `rev` column yet
+    unrevisioned: HashSet<String>,
+    // read-only collections whose table has no `schema_version` column yet
+    unversioned: HashSet<String>,
+    // reads not written yet, dict<(collection, id), (reads, last accessed)>
+    pending_reads: Mutex<HashMap<(String, Id), (u64, chrono::DateTime<chrono::Utc>)>>,
+    // opened with read-only flags, the store rejects every mutation
+    read_only: bool,
+    // what the `x-migrate` runs of this open did to the stored documents
+    schema_changes: Vec<String>,
 }
 
 impl SqliteBackend {
@@ -170,12 +388,117 @@ impl SqliteBackend {
             .map(|m| (m.parent.as_str(), m.field.as_str()))
     }
 
+    // namespace of the parent collection when its `x-parent-id` names another one
+    pub(crate) fn parent_namespace(&self, collection: &str) -> Option<&str> {
+        self.parent_ref.get(collection).and_then(|m| m.namespace.as_deref())
+    }
+
+    /// Collections whose documents expire (`x-ttl`).
+    pub fn ttl_collections(&self) -> Vec<&str> {
+        let mut collections: Vec<&str> = self.ttl.keys().map(|c| c.as_str()).collect();
+        collections.sort_unstable();
+        collections
+    }
+
+    /// Ids of the documents of the collection expired by `now`, by their `expires_at` field or else
+    /// the lifetime of the collection after their last write.
+    pub fn list_expired(&self, collection: &str, now: chrono::DateTime<chrono::Utc>) -> StoreResult<Vec<Id>> {
+        let Some(ttl) = self.ttl.get(collection) else {
+            return Ok(Vec::new());
+        };
+        let lifetime = match ttl {
+            Some(secs) => format!("datetime(updated_at, '+{} seconds')", secs),
+            None => "NULL".to_string(),
+        };
+        // only a text field is a date-time, julianday would take a number as a day count
+        let sql = format!(
+            "SELECT id FROM {} WHERE {} AND julianday(COALESCE(\
+             CASE WHEN json_type(body, '$.{field}') = 'text' THEN json_extract(body, '$.{field}') END, {})) \
+             <= julianday(?1)",
+            sanitize_table_name(collection),
+            self.live_rows(collection, false),
+            lifetime,
+            field = EXPIRES_AT_FIELD,
+        );
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![now.to_rfc3339()], |r| r.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Whether deleting an item of the collection only marks it deleted (`x-soft-delete: true`).
+    pub fn soft_deletes(&self, collection: &str) -> bool {
+        self.soft_delete.contains(collection)
+    }
+
+    // the rows of the collection a read sees, the soft deleted ones only when asked for
+    fn live_rows(&self, collection: &str, include_deleted: bool) -> &'static str {
+        if include_deleted || !self.soft_deletes(collection) {
+            "1"
+        } else {
+            "deleted_at IS NULL"
+        }
+    }
+
+    // the deletion time of the rows, always NULL in the collections removing their rows
+    fn deleted_column(&self, collection: &str) -> &'static str {
+        if self.soft_deletes(collection) {
+            "deleted_at"
+        } else {
+            "NULL"
+        }
+    }
+
+    // the revision and schema version of the rows, the tables of a read-only file written before
+    // either are all at 1
+    fn rev_columns(&self, collection: &str) -> &'static str {
+        match (
+            self.unrevisioned.contains(collection),
+            self.unversioned.contains(collection),
+        ) {
+            (false, false) => "rev, schema_version",
+            (false, true) => "rev, 1",
+            (true, false) => "1, schema_version",
+            (true, true) => "1, 1",
+        }
+    }
+
+    /// The schema version the writes of the collection are stamped with, its `x-version`.
+    pub fn schema_version(&self, collection: &str) -> i64 {
+        self.schema_versions.get(collection).copied().unwrap_or(1)
+    }
+
     fn new(pool: Arc<Pool<SqliteConnectionManager>>) -> Self {
         Self {
             pool,
+            readers: None,
+            encryption_key: None,
             schema_validator: HashMap::new(),
+            schema_json: HashMap::new(),
+            schema_versions: HashMap::new(),
+            earlier_validators: HashMap::new(),
+            schema_resources: HashMap::new(),
             parent_ref: HashMap::new(),
             unique_fields: HashMap::new(),
+            parent_scoped_unique: HashSet::new(),
+            on_parent_delete: HashMap::new(),
+            computed_fields: HashMap::new(),
+            timestamp_fields: HashMap::new(),
+            blob_offload: RwLock::new(None),
+            encrypted_fields: HashMap::new(),
+            field_cipher: RwLock::new(None),
+            immutable_fields: HashMap::new(),
+            max_bytes: HashMap::new(),
+            indexed_fields: HashMap::new(),
+            fulltext_fields: HashMap::new(),
+            access_stats: HashSet::new(),
+            soft_delete: HashSet::new(),
+            ttl: HashMap::new(),
+            unrevisioned: HashSet::new(),
+            unversioned: HashSet::new(),
+            pending_reads: Mutex::new(HashMap::new()),
+            read_only: false,
+            schema_changes: Vec::new(),
         }
     }
 
@@ -188,66 +511,520 @@ impl SqliteBackend {
     }
 
     // file-based sqlite
-    fn open<P: AsRef<Path>>(path: P) -> StoreResult<Self> {
-        let manager = SqliteConnectionManager::file(path.as_ref());
-        let pool = Pool::new(manager)?;
-        let backend = Self::new(Arc::new(pool));
+    fn open<P: AsRef<Path>>(path: P, read_connections: u32, encryption_key: Option<String>) -> StoreResult<Self> {
+        let key = encryption_key.clone();
+        let manager = SqliteConnectionManager::file(path.as_ref()).with_init(move |conn| apply_key(conn, &key));
+        // a single writer, the writes queue for it rather than for the file lock
+        let pool = Pool::builder()
+            .max_size(1)
+            .connection_timeout(WRITER_WAIT)
+            .build(manager)?;
+        // readers keep their snapshot while writers commit, see `TxScope::read_only`
+        pool.get()?
+            .pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get::<_, String>(0))?;
+        let key = encryption_key.clone();
+        let readers = SqliteConnectionManager::file(path.as_ref()).with_init(move |conn| {
+            apply_key(conn, &key)?;
+            conn.execute_batch("PRAGMA query_only = ON")
+        });
+        let readers = Pool::builder().max_size(read_connections).build(readers)?;
+        let mut backend = Self::new(Arc::new(pool));
+        backend.readers = Some(Arc::new(readers));
+        backend.encryption_key = encryption_key;
         backend.init().map(|_| backend)
     }
 
-    fn get_conn(&self) -> StoreResult<PooledConnection<SqliteConnectionManager>> {
-        Ok(self.pool.get()?)
+    // existing sqlite file, never written
+    fn open_read_only<P: AsRef<Path>>(
+        path: P,
+        collections: Vec<&str>,
+        encryption_key: Option<String>,
+    ) -> StoreResult<Self> {
+        let tables: Vec<(String, String)> = collections
+            .into_iter()
+            .map(|c| {
+                let table = sanitize_table_name(c);
+                let ddl = collection_table_ddl(&table, false, &[]);
+                (table, ddl)
+            })
+            .collect();
+        let key = encryption_key.clone();
+        let manager = SqliteConnectionManager::file(path.as_ref())
+            .with_flags(
+                rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | rusqlite::OpenFlags::SQLITE_OPEN_URI
+                    | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+            .with_init(move |conn| {
+                apply_key(conn, &key)?;
+                stand_in_missing_tables(conn, &tables)
+            });
+        let pool = Pool::new(manager)?;
+        let mut backend = Self::new(Arc::new(pool));
+        backend.read_only = true;
+        backend.encryption_key = encryption_key;
+        Ok(backend)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Collection schemas and shared definitions by name, the collection ones under their collection.
+    pub fn schema_resources(&self) -> &HashMap<String, Value> {
+        &self.schema_resources
+    }
+
+    /// Health of the database file, a failing probe is reported as unreachable with its error.
+    pub fn health(&self, namespace: &str) -> NamespaceHealth {
+        let mut health = NamespaceHealth {
+            namespace: namespace.to_string(),
+            reachable: false,
+            read_only: self.read_only,
+            file_size: None,
+            last_write: None,
+            pending_migrations: Vec::new(),
+            error: None,
+        };
+        match self.probe(&mut health) {
+            Ok(()) => health.reachable = true,
+            Err(e) => health.error = Some(e.to_string()),
+        }
+        health
+    }
+
+    fn probe(&self, health: &mut NamespaceHealth) -> StoreResult<()> {
+        let conn = self.read_conn()?;
+        let file: String = conn.query_row("SELECT file FROM pragma_database_list WHERE name = 'main'", [], |r| {
+            r.get(0)
+        })?;
+        // memory databases have no file
+        if !file.is_empty() {
+            // pooled connections keep reading a file removed under them, check the path itself
+            health.file_size = Some(std::fs::metadata(&file)?.len());
+        }
+        // reads the header and the schema, fails on a file that is not (or no longer) a database
+        conn.query_row("SELECT COUNT(*) FROM main.sqlite_master", [], |r| r.get::<_, i64>(0))?;
+
+        for (name, _) in internal_tables() {
+            if !table_exists(&conn, name)? {
+                health.pending_migrations.push(name.to_string());
+            }
+        }
+        for collection in self.collections() {
+            let stored: Option<String> = if table_exists(&conn, "__schemas")? {
+                conn.query_row(
+                    "SELECT schema FROM __schemas WHERE collection = ?1",
+                    params![collection],
+                    |r| r.get(0),
+                )
+                .optional()?
+            } else {
+                None
+            };
+            if !table_exists(&conn, &sanitize_table_name(collection))?
+                || stored.as_ref() != self.schema_json.get(collection)
+            {
+                health.pending_migrations.push(collection.to_string());
+            }
+        }
+        health.last_write = conn.query_row("SELECT MAX(changed_at) FROM __changes", [], |r| r.get(0))?;
+        Ok(())
+    }
+
+    // joins the request transaction when one is entered, see [`tx`]
+    fn get_conn(&self) -> StoreResult<tx::Conn> {
+        match tx::TxScope::current() {
+            // a read snapshot never writes, it leaves the writer to the others
+            Some(scope) if scope.is_read_only() => self.read_conn(),
+            _ => tx::get_conn(&self.pool),
+        }
+    }
+
+    // a read-only connection, inside a transaction the one of the transaction so its writes are seen
+    fn read_conn(&self) -> StoreResult<tx::Conn> {
+        tx::read_conn(&self.pool, self.readers.as_ref())
     }
 
     /// common initialization, create internal tables
     ///
     /// __schemas: store collection schemas
     /// __acls: store access control list entries
+    /// __changes: change log of the data and acls, in commit order, written in the mutation's transaction
+    ///            so it doubles as the outbox of the change events; `parent_id` is the one of the document,
+    ///            `prev_parent_id` the one an upsert moved it away from
+    /// __replication: how far the change log has been pushed to each replication target
+    /// __locks: advisory locks on documents
+    /// __collection_modes: collections switched to read-only
+    /// __webhooks: endpoints receiving the change log
+    /// __webhook_deliveries: pending and dead webhook requests
+    /// __attachments: files bound to documents, removed in the transaction deleting the document
+    /// __access_stats: read counts of the documents of the `x-access-stats` collections
+    /// __views: named filters and sorts saved by the users for a collection
+    /// __service_keys: credentials of the service identities, by the hash of the key
     ///
     fn init(&self) -> StoreResult<()> {
         // table to store collection schemas and a small meta for collections
         let conn = self.get_conn()?;
+        conn.execute_batch(INTERNAL_TABLES_DDL)?;
+        // webhooks registered before they could be filtered
+        if !column_exists(&conn, "__webhooks", "events")? {
+            conn.execute_batch(
+                "ALTER TABLE __webhooks ADD COLUMN events TEXT NOT NULL DEFAULT '[]';
+                ALTER TABLE __webhooks ADD COLUMN filter_expr TEXT;",
+            )?;
+        }
+        // changes logged before the feed could be followed under one parent, the deletes and acl
+        // changes are under the parent of the last upsert before them
+        if !column_exists(&conn, "__changes", "parent_id")? {
+            conn.execute_batch(
+                "ALTER TABLE __changes ADD COLUMN parent_id TEXT;
+                UPDATE __changes SET parent_id = json_extract(payload, '$.item.parent_id') WHERE op = 'upsert';
+                UPDATE __changes SET parent_id = (SELECT u.parent_id FROM __changes u \
+                    WHERE u.collection = __changes.collection AND u.data_id = __changes.data_id \
+                    AND u.op = 'upsert' AND u.seq < __changes.seq ORDER BY u.seq DESC LIMIT 1) \
+                    WHERE op != 'upsert';",
+            )?;
+        }
+        // upserts logged before the moves were recorded under the parent left as well
+        if !column_exists(&conn, "__changes", "prev_parent_id")? {
+            conn.execute_batch(
+                "ALTER TABLE __changes ADD COLUMN prev_parent_id TEXT;
+                UPDATE __changes SET prev_parent_id = (SELECT u.parent_id FROM __changes u \
+                    WHERE u.collection = __changes.collection AND u.data_id = __changes.data_id \
+                    AND u.op = 'upsert' AND u.seq < __changes.seq ORDER BY u.seq DESC LIMIT 1) \
+                    WHERE op = 'upsert';
+                UPDATE __changes SET prev_parent_id = NULL WHERE prev_parent_id IS parent_id;",
+            )?;
+        }
         conn.execute_batch(
-            r#"
-                CREATE TABLE IF NOT EXISTS __schemas (
-                    collection TEXT PRIMARY KEY,
-                    schema TEXT NOT NULL
-                );
-                CREATE TABLE IF NOT EXISTS __acls (
-                    id TEXT PRIMARY KEY,
-                    data_collection TEXT NOT NULL,
-                    data_id TEXT NOT NULL,
-                    user_id TEXT NOT NULL,
-                    permission TEXT NOT NULL,
-                    created_at TEXT NOT NULL,
-                    updated_at TEXT NOT NULL,
-                    owner TEXT NOT NULL
-                );
-            "#,
+            "CREATE INDEX IF NOT EXISTS idx_changes_parent ON __changes (parent_id, seq);
+            CREATE INDEX IF NOT EXISTS idx_changes_prev_parent ON __changes (prev_parent_id, seq);",
         )?;
         Ok(())
     }
 
     /// Save or update a collection schema.
-    fn init_collection_schema(&mut self, collection: &str, schema: &Value) -> StoreResult<()> {
+    // a changed schema with `x-migrate` comes back, it is only stored once `migrate_documents` accepted it
+    fn init_collection_schema(
+        &mut self,
+        collection: &str,
+        schema: &Value,
+        resources: &HashMap<String, Value>,
+        keywords: &[(String, KeywordFactory)],
+    ) -> StoreResult<Option<Migration>> {
         let s = serde_json::to_string(schema)?;
         let mut conn = self.get_conn()?;
 
         let tx = conn.transaction()?;
 
-        tx.execute(
-            "INSERT INTO __schemas(collection, schema) VALUES (?1, ?2) ON CONFLICT(collection) DO UPDATE SET schema = excluded.schema",
-            params![collection, s],
-        )
-        ?;
+        let migration = migrate::parse(schema)?;
+        let stored: Option<String> = if self.read_only {
+            None
+        } else {
+            tx.query_row(
+                "SELECT schema FROM __schemas WHERE collection = ?1",
+                params![collection],
+                |r| r.get(0),
+            )
+            .optional()?
+        };
+        let migration = migration.filter(|_| stored.as_ref().is_some_and(|stored| *stored != s));
+        if !self.read_only && migration.is_none() {
+            tx.execute(
+                "INSERT INTO __schemas(collection, schema) VALUES (?1, ?2) ON CONFLICT(collection) DO UPDATE SET schema = excluded.schema",
+                params![collection, s],
+            )?;
+        }
         // compile and cache the schema validator
+        let compiled = self.compile_schema(schema, resources, keywords)?;
+        self.schema_validator.insert(collection.to_string(), compiled);
+        self.schema_versions
+            .insert(collection.to_string(), parse_schema_version(schema)?);
+        self.schema_json.insert(collection.to_string(), s);
+        if let Some(xpi) = schema
+            .get("x-parent-id")
+            .and_then(|v| serde_json::from_value::<XParentIdMeta>(v.clone()).ok())
+        {
+            check_field_path("x-parent-id", &xpi.field)?;
+            tracing::info!("init_collection_schema x-parent-id: {:?}", xpi);
+            self.parent_ref.insert(collection.to_string(), xpi);
+        }
+        let computed = computed::parse(schema)?;
+        if !computed.is_empty() {
+            self.computed_fields.insert(collection.to_string(), computed);
+        }
+        if let Some(timestamps) = computed::parse_timestamps(schema)? {
+            self.timestamp_fields.insert(collection.to_string(), timestamps);
+        }
+
+        // record the unique field if any
+        let (unique, parent_scoped) = parse_unique(schema, collection, self.parent_ref.contains_key(collection))?;
+        if let Some(xu) = unique {
+            self.unique_fields.insert(collection.to_string(), xu);
+        }
+        if parent_scoped {
+            self.parent_scoped_unique.insert(collection.to_string());
+        }
+        let on_parent_delete = match schema.get("x-on-parent-delete") {
+            None => OnParentDelete::Orphan,
+            Some(_) if !self.parent_ref.contains_key(collection) => {
+                return Err(StoreError::Validation(format!(
+                    "x-on-parent-delete requires x-parent-id in collection '{}'",
+                    collection
+                )));
+            }
+            // the deletes of the other database never reach the children here
+            Some(_) if self.parent_namespace(collection).is_some() => {
+                return Err(StoreError::Validation(format!(
+                    "x-on-parent-delete requires a parent in the same namespace in collection '{}'",
+                    collection
+                )));
+            }
+            Some(v) => match v.as_str() {
+                Some("orphan") => OnParentDelete::Orphan,
+                Some("cascade") => OnParentDelete::Cascade,
+                Some("restrict") => OnParentDelete::Restrict,
+                _ => return Err(StoreError::Validation(format!("invalid x-on-parent-delete: {}", v))),
+            },
+        };
+        if on_parent_delete != OnParentDelete::Orphan {
+            self.on_parent_delete.insert(collection.to_string(), on_parent_delete);
+        }
+        if schema.get("x-access-stats").and_then(|v| v.as_bool()) == Some(true) {
+            self.access_stats.insert(collection.to_string());
+        }
+        let soft_delete = schema.get("x-soft-delete").and_then(|v| v.as_bool()) == Some(true);
+        let ttl = match schema.get("x-ttl") {
+            None => None,
+            Some(Value::Bool(true)) => Some(None),
+            Some(v) => match v.as_u64() {
+                Some(secs) if secs > 0 => Some(Some(secs)),
+                _ => {
+                    return Err(StoreError::Validation(format!(
+                        "x-ttl: expected a positive number of seconds or true: {}",
+                        v
+                    )));
+                }
+            },
+        };
+        let immutable = parse_field_paths(schema, "x-immutable")?;
+        if !immutable.is_empty() {
+            self.immutable_fields.insert(collection.to_string(), immutable);
+        }
+        if let Some(v) = schema.get("x-max-bytes") {
+            match v.as_u64() {
+                Some(bytes) if bytes > 0 => {
+                    self.max_bytes.insert(collection.to_string(), bytes as usize);
+                }
+                _ => {
+                    return Err(StoreError::Validation(format!(
+                        "x-max-bytes: expected a positive number of bytes: {}",
+                        v
+                    )));
+                }
+            }
+        }
+        let indexed = parse_field_paths(schema, "x-index")?;
+        let searchable = parse_field_paths(schema, "x-fulltext")?;
+        let encrypted = encrypt::parse(schema)?;
+        // the database only sees the ciphertext of an encrypted field
+        let expires_at = EXPIRES_AT_FIELD.to_string();
+        let looked_up = self
+            .unique_fields
+            .get(collection)
+            .into_iter()
+            .chain(self.parent_ref.get(collection).map(|m| &m.field))
+            .chain(&indexed)
+            .chain(&searchable)
+            .chain(ttl.iter().map(|_| &expires_at));
+        for field in looked_up {
+            if encrypted
+                .iter()
+                .any(|e| field == e || field.starts_with(&format!("{}.", e)))
+            {
+                return Err(StoreError::Validation(format!(
+                    "x-encrypt-fields: field '{}' of collection '{}' is also unique, a parent id, indexed, searched or an expiry",
+                    field, collection
+                )));
+            }
+        }
+        // the stored ciphertext is bound to its field name
+        if let Some(field) = migration
+            .iter()
+            .flat_map(|m| m.fields())
+            .find(|field| encrypted.iter().any(|e| e == field))
+        {
+            return Err(StoreError::Validation(format!(
+                "x-migrate: encrypted field '{}' of collection '{}' can not be renamed or filled",
+                field, collection
+            )));
+        }
+        if !encrypted.is_empty() {
+            self.encrypted_fields.insert(collection.to_string(), encrypted);
+        }
+        if let Some(ttl) = ttl {
+            self.ttl.insert(collection.to_string(), ttl);
+        }
+
+        let table = sanitize_table_name(collection);
+        if self.read_only {
+            // only the columns an earlier writable open created can be looked up
+            let mut present = Vec::new();
+            for field in indexed {
+                if column_exists(&tx, &table, &index_column(&field))? {
+                    present.push(field);
+                }
+            }
+            if !present.is_empty() {
+                self.indexed_fields.insert(collection.to_string(), present);
+            }
+            if !searchable.is_empty() && table_exists(&tx, &fulltext::fts_table(&table))? {
+                self.fulltext_fields.insert(collection.to_string(), searchable);
+            }
+            if soft_delete && column_exists(&tx, &table, "deleted_at")? {
+                self.soft_delete.insert(collection.to_string());
+            }
+            if !column_exists(&tx, &table, "rev")? {
+                self.unrevisioned.insert(collection.to_string());
+            }
+            if !column_exists(&tx, &table, "schema_version")? {
+                self.unversioned.insert(collection.to_string());
+            }
+            return Ok(None);
+        }
+        let stored: Vec<String> = indexed.iter().map(|field| stored_column(field)).collect();
+        // ensure collection table exists
+        tx.execute_batch(&collection_table_ddl(&table, parent_scoped, &stored))?;
+        if parent_scoped {
+            // tables created before the scope was set carry a global UNIQUE on uniq, rebuild them without it
+            let table_sql: String = tx.query_row(
+                "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |r| r.get(0),
+            )?;
+            if table_sql.contains("uniq TEXT UNIQUE") {
+                tracing::info!("rebuild table {} for parent scoped unique", table);
+                // the revisions carry over, a client holding one must not see it match again
+                let mut rev = String::new();
+                for column in ["rev", "schema_version"] {
+                    if column_exists(&tx, &table, column)? {
+                        rev.push_str(", ");
+                        rev.push_str(column);
+                    }
+                }
+                tx.execute_batch(&format!(
+                    "ALTER TABLE {table} RENAME TO {table}__old;
+                    {ddl}
+                    INSERT INTO {table} (id, body, created_at, updated_at, owner, uniq, parent_id{rev})
+                        SELECT id, body, created_at, updated_at, owner, uniq, parent_id{rev} FROM {table}__old;
+                    DROP TABLE {table}__old;",
+                    ddl = collection_table_ddl(&table, true, &stored),
+                ))?;
+            }
+        }
+        // tables created before the content hash, or just rebuilt above
+        if !column_exists(&tx, &table, "content_hash")? {
+            tx.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN content_hash TEXT;"))?;
+        }
+        if !column_exists(&tx, &table, "rev")? {
+            tx.execute_batch(&format!(
+                "ALTER TABLE {table} ADD COLUMN rev INTEGER NOT NULL DEFAULT 1;"
+            ))?;
+        }
+        // the documents written before schema versions are at the first one
+        if !column_exists(&tx, &table, "schema_version")? {
+            tx.execute_batch(&format!(
+                "ALTER TABLE {table} ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1;"
+            ))?;
+        }
+        // dropping the flag later keeps the column, the rows deleted meanwhile are listed again
+        if soft_delete {
+            if !column_exists(&tx, &table, "deleted_at")? {
+                tx.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN deleted_at TEXT;"))?;
+            }
+            self.soft_delete.insert(collection.to_string());
+        }
+        // `x-index` fields are kept in stored generated columns, which SQLite only adds by rebuilding the
+        // table; the columns of fields dropped from `x-index` stay until the next rebuild
+        let mut rebuild = false;
+        for field in &indexed {
+            rebuild |= !stored_column_exists(&tx, &table, &index_column(field))?;
+        }
+        if rebuild {
+            tracing::info!("rebuild table {} for stored x-index columns", table);
+            let columns: Vec<String> = tx
+                .prepare("SELECT name FROM pragma_table_xinfo(?1) WHERE hidden = 0")?
+                .query_map(params![table], |r| r.get(0))?
+                .collect::<Result<_, _>>()?;
+            let mut extra = stored.clone();
+            if columns.iter().any(|c| c == "deleted_at") {
+                extra.push("deleted_at TEXT".to_string());
+            }
+            // the rowids carry over, the full text index refers to them
+            tx.execute_batch(&format!(
+                "ALTER TABLE {table} RENAME TO {table}__old;
+                {ddl}
+                INSERT INTO {table} (rowid, {columns}) SELECT rowid, {columns} FROM {table}__old;
+                DROP TABLE {table}__old;",
+                ddl = collection_table_ddl(&table, parent_scoped, &extra),
+                columns = columns.join(", "),
+            ))?;
+        }
+        if parent_scoped {
+            tx.execute_batch(&format!(
+                "CREATE UNIQUE INDEX IF NOT EXISTS {table}_parent_uniq ON {table} (parent_id, uniq);"
+            ))?;
+        }
+        tx.execute_batch(&format!(
+            "CREATE INDEX IF NOT EXISTS {table}_owner_hash ON {table} (owner, content_hash);
+            CREATE INDEX IF NOT EXISTS {table}_owner_created ON {table} (owner, created_at);
+            CREATE INDEX IF NOT EXISTS {table}_owner_parent ON {table} (owner, parent_id);"
+        ))?;
+        let missing: Vec<(String, String)> = tx
+            .prepare(&format!("SELECT id, body FROM {table} WHERE content_hash IS NULL"))?
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        for (id, body_text) in missing {
+            tx.execute(
+                &format!("UPDATE {table} SET content_hash = ?1 WHERE id = ?2"),
+                params![self.content_hash(collection, &body_text)?, id],
+            )?;
+        }
+        for field in &indexed {
+            let column = index_column(field);
+            tx.execute_batch(&format!(
+                "CREATE INDEX IF NOT EXISTS {table}_{column} ON {table} ({column}, id);"
+            ))?;
+        }
+        if !indexed.is_empty() {
+            self.indexed_fields.insert(collection.to_string(), indexed);
+        }
+        // after any rebuild of the collection table, which takes the triggers along
+        fulltext::sync(&tx, &table, &searchable)?;
+        if !searchable.is_empty() {
+            self.fulltext_fields.insert(collection.to_string(), searchable);
+        }
+        tx.commit()?;
+        Ok(migration)
+    }
+
+    // compile a collection schema, its `x-parent-id` and custom keywords checked against this database
+    fn compile_schema(
+        &self,
+        schema: &Value,
+        resources: &HashMap<String, Value>,
+        keywords: &[(String, KeywordFactory)],
+    ) -> StoreResult<jsonschema::Validator> {
         let pool = self.pool.clone();
+        let readers = self.readers.clone();
 
         fn x_parent_id_check<'a>(
             _parent: &'a serde_json::Map<String, Value>,
             value: &'a Value,
             _path: jsonschema::paths::Location,
             pool: Arc<Pool<SqliteConnectionManager>>,
+            readers: Option<Arc<Pool<SqliteConnectionManager>>>,
         ) -> Result<Box<dyn jsonschema::Keyword>, Box<jsonschema::ValidationError<'a>>> {
             tracing::info!("more: value: {value:?}");
             tracing::info!("more: _parent: {:?}", _parent);
@@ -256,57 +1033,183 @@ impl SqliteBackend {
             tracing::info!("create parent check meta: {:?}", meta);
             Ok(Box::new(checker::XParentId {
                 pool: pool.clone(),
+                readers,
                 meta,
             }))
         }
 
-        let compiled = jsonschema::draft7::options().with_keyword("x-parent-id", move |parent, value, path| {
-            x_parent_id_check(parent, value, path, pool.clone()).map_err(|e| *e)
-        });
-        let compiled = compiled
+        let draft = schema_draft(schema)?;
+        let mut options = jsonschema::options().with_draft(draft);
+        for (name, factory) in keywords {
+            let factory = factory.clone();
+            let context = KeywordContext::new(self.pool.clone(), self.readers.clone());
+            options = options.with_keyword(name.as_str(), move |_parent, value, _path| {
+                factory(value, context.clone()).map_err(|e| jsonschema::ValidationError::custom(e.to_string()))
+            });
+        }
+        let compiled = options
+            .with_keyword("x-parent-id", move |parent, value, path| {
+                x_parent_id_check(parent, value, path, pool.clone(), readers.clone()).map_err(|e| *e)
+            })
+            .with_resources(resources.iter().map(|(name, schema)| {
+                (
+                    format!("{}{}", SCHEMA_REF_PREFIX, name),
+                    // referenced schemas without their own `$schema` follow the referencing one
+                    draft.detect(schema).create_resource(schema.clone()),
+                )
+            }));
+        compiled
             .build(schema)
-            .map_err(|e| StoreError::Validation(format!("invalid schema: {}", e)))?;
+            .map_err(|e| StoreError::Validation(format!("invalid schema: {}", e)))
+    }
 
-        self.schema_validator.insert(collection.to_string(), compiled);
-        // record the unique field if any
-        if let Some(xu) = schema.get("x-unique").and_then(|v| v.as_str())
-            && !xu.is_empty()
-        {
-            self.unique_fields.insert(collection.to_string(), xu.to_string());
+    // compile an earlier version of a collection schema, the documents still at it validate against it
+    fn init_earlier_version(
+        &mut self,
+        collection: &str,
+        version: i64,
+        schema: &Value,
+        resources: &HashMap<String, Value>,
+        keywords: &[(String, KeywordFactory)],
+    ) -> StoreResult<()> {
+        if !self.schema_validator.contains_key(collection) {
+            return Err(StoreError::Validation(format!(
+                "schema version {} of collection '{}': collection not registered",
+                version, collection
+            )));
         }
-        if let Some(xpi) = schema
-            .get("x-parent-id")
-            .and_then(|v| serde_json::from_value::<checker::XParentIdMeta>(v.clone()).ok())
-        {
-            tracing::info!("init_collection_schema x-parent-id: {:?}", xpi);
-            self.parent_ref.insert(collection.to_string(), xpi);
+        let current = self.schema_version(collection);
+        if version < 1 || version >= current {
+            return Err(StoreError::Validation(format!(
+                "schema version {} of collection '{}' must be below its x-version {}",
+                version, collection, current
+            )));
         }
+        if self.earlier_validators.contains_key(&(collection.to_string(), version)) {
+            return Err(StoreError::Validation(format!(
+                "schema version {} of collection '{}' registered more than once",
+                version, collection
+            )));
+        }
+        let compiled = self.compile_schema(schema, resources, keywords)?;
+        self.earlier_validators
+            .insert((collection.to_string(), version), compiled);
+        Ok(())
+    }
 
-        // ensure collection table exists
+    // the validator of a schema version of the collection, the current one for a version not registered
+    fn validator_for(&self, collection: &str, version: i64) -> Option<&jsonschema::Validator> {
+        self.earlier_validators
+            .get(&(collection.to_string(), version))
+            .or_else(|| self.schema_validator.get(collection))
+    }
+
+    // run the `x-migrate` transforms over the stored documents and check them, then store the new schema
+    fn migrate_documents(&mut self, collection: &str, migration: &Migration) -> StoreResult<()> {
         let table = sanitize_table_name(collection);
+        let meta = ChangeMeta {
+            actor: None,
+            reason: Some("schema migration".to_string()),
+        };
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let rows: Vec<(String, String, i64)> = tx
+            .prepare(&format!("SELECT id, body, schema_version FROM {table} ORDER BY id"))?
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        let (mut migrated, mut unchecked, mut incompatible) = (0, 0, Vec::new());
+        for (id, body_text, mut version) in rows {
+            let mut body: Value = serde_json::from_str(&body_text)?;
+            if migration.apply(&mut body) {
+                version = self.schema_version(collection);
+                let body_text = serde_json::to_string(&body)?;
+                tx.execute(
+                    &format!(
+                        "UPDATE {table} SET body = ?1, uniq = ?2, parent_id = ?3, content_hash = ?4, rev = rev + 1, \
+                         schema_version = ?5 WHERE id = ?6"
+                    ),
+                    params![
+                        body_text,
+                        self.fetch_unique_field(collection, &body)?,
+                        self.fetch_parent_id(collection, &body)?,
+                        self.content_hash(collection, &body_text)?,
+                        self.schema_version(collection),
+                        id
+                    ],
+                )
+                .map_err(map_write_error)?;
+                record_upsert(&tx, collection, &id, &meta)?;
+                migrated += 1;
+            }
+            if migration.check.is_none() {
+                continue;
+            }
+            // encrypted and offloaded values only validate decoded, the key and blob store are set later
+            if encrypt::has_encrypted_fields(&body) || blob::has_blob_refs(&body) {
+                unchecked += 1;
+                continue;
+            }
+            // the documents left at an earlier schema version only need to match that one
+            let Some(validator) = self.validator_for(collection, version) else {
+                continue;
+            };
+            let errors: Vec<String> = validator
+                .iter_errors(&body)
+                .map(|e| format!("{} {}", e.instance_path(), e))
+                .collect();
+            if !errors.is_empty() {
+                incompatible.push(format!("{}/{}: {}", collection, id, errors.join("; ")));
+            }
+        }
+        if !incompatible.is_empty() && migration.check == Some(MigrationCheck::Reject) {
+            let shown = incompatible
+                .iter()
+                .take(MAX_REPORTED_DOCUMENTS)
+                .cloned()
+                .collect::<Vec<_>>();
+            return Err(StoreError::Validation(format!(
+                "schema change of collection '{}' rejected, {} stored documents do not match it: {}",
+                collection,
+                incompatible.len(),
+                shown.join(", ")
+            )));
+        }
+        tx.execute(
+            "INSERT INTO __schemas(collection, schema) VALUES (?1, ?2) ON CONFLICT(collection) DO UPDATE SET schema = excluded.schema",
+            params![collection, self.schema_json[collection]],
+        )?;
+        tx.commit()?;
 
-        let sql = format!(
-            "CREATE TABLE IF NOT EXISTS {} (
-                id TEXT PRIMARY KEY,
-                body TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                owner TEXT NOT NULL,
-                uniq TEXT UNIQUE,
-                parent_id TEXT
-            );",
-            table
+        tracing::info!("schema of {} changed, {} documents migrated", collection, migrated);
+        if migrated > 0 {
+            self.schema_changes.push(format!(
+                "collection `{}`: schema changed, {} documents migrated",
+                collection, migrated
+            ));
+        }
+        if unchecked > 0 {
+            self.schema_changes.push(format!(
+                "collection `{}`: {} documents with encrypted or offloaded fields not checked against the new schema",
+                collection, unchecked
+            ));
+        }
+        self.schema_changes.extend(
+            incompatible
+                .into_iter()
+                .map(|e| format!("does not match the new schema: {}", e)),
         );
-        tx.execute_batch(&sql)?;
-        tx.commit()?;
         Ok(())
     }
 
+    /// What the `x-migrate` runs did when the database was opened, see `backend::migrate`.
+    pub fn schema_changes(&self) -> &[String] {
+        &self.schema_changes
+    }
+
     // fetch the unique field value from body if was defined in schema
     fn fetch_unique_field(&self, collection: &str, body: &Value) -> StoreResult<Option<String>> {
-        // todo future support nested field like "a.b.c"
         if let Some(field) = self.unique_fields.get(collection)
-            && let Some(v) = body.get(field)
+            && let Some(v) = body_field(body, field)
         {
             return match v.as_str() {
                 Some(s) => Ok(Some(s.to_string())),
@@ -318,7 +1221,7 @@ impl SqliteBackend {
 
     fn fetch_parent_id(&self, collection: &str, body: &Value) -> StoreResult<Option<String>> {
         if let Some(xpm) = self.parent_ref.get(collection)
-            && let Some(v) = body.get(&xpm.field)
+            && let Some(v) = body_field(body, &xpm.field)
         {
             return match v.as_str() {
                 Some(s) => Ok(Some(s.to_string())),
@@ -328,17 +1231,503 @@ impl SqliteBackend {
         Ok(None)
     }
 
-    fn validate_against_schema(&self, collection: &str, body: &Value) -> StoreResult<()> {
-        self.schema_validator
-            .get(collection)
-            .ok_or_else(|| StoreError::Validation(format!("collection '{}' not registered", collection)))?
-            .validate(body)
-            .map_err(|errors| StoreError::Validation(errors.to_string()))?;
-        Ok(())
-    }
-}
+    /// Apply the server side body rewrites (`x-computed`, `x-inject-timestamps`) before validation and storage.
+    fn prepare_body(
+        &self,
+        collection: &str,
+        body: &Value,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+    ) -> Value {
+        let mut body = body.clone();
+        if let Some(fields) = self.computed_fields.get(collection) {
+            computed::apply(fields, &mut body);
+        }
+        if let Some(fields) = self.timestamp_fields.get(collection) {
+            computed::apply_timestamps(fields, &mut body, created_at, updated_at);
+        }
+        body
+    }
+
+    /// Offload the oversized fields of every written body from now on, `None` keeps them inline.
+    pub fn set_blob_offload(&self, offload: Option<BlobOffload>) -> StoreResult<()> {
+        *self
+            .blob_offload
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))? = offload;
+        Ok(())
+    }
+
+    fn blob_offload(&self) -> StoreResult<Option<BlobOffload>> {
+        Ok(self
+            .blob_offload
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .clone())
+    }
+
+    /// Replace the blob references of the item body by their values.
+    pub fn inline_blobs(&self, item: &mut DataItem) -> StoreResult<()> {
+        if !blob::has_blob_refs(&item.body) {
+            return Ok(());
+        }
+        let Some(offload) = self.blob_offload()? else {
+            return Err(StoreError::Unavailable("blob storage is not configured".to_string()));
+        };
+        blob::inline(&mut item.body, offload.store.as_ref())
+    }
+
+    // bodies are validated inlined, resolve the references a client sent back
+    fn resolve_blobs<'a>(&self, body: &'a Value) -> StoreResult<Cow<'a, Value>> {
+        if !blob::has_blob_refs(body) {
+            return Ok(Cow::Borrowed(body));
+        }
+        let Some(offload) = self.blob_offload()? else {
+            return Err(StoreError::Validation(
+                "body holds blob references but blob storage is not configured".to_string(),
+            ));
+        };
+        let mut body = body.clone();
+        blob::inline(&mut body, offload.store.as_ref())?;
+        Ok(Cow::Owned(body))
+    }
+
+    /// Encrypt the `x-encrypt-fields` of every written body and decrypt them on read.
+    pub fn set_field_cipher(&self, cipher: Option<FieldCipher>) -> StoreResult<()> {
+        *self
+            .field_cipher
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))? = cipher;
+        Ok(())
+    }
+
+    fn field_cipher(&self) -> StoreResult<Option<FieldCipher>> {
+        Ok(self
+            .field_cipher
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .clone())
+    }
+
+    // the item with its encrypted fields decrypted
+    fn decrypted(&self, mut item: DataItem) -> StoreResult<DataItem> {
+        self.decrypt_body(&mut item.body)?;
+        Ok(item)
+    }
+
+    fn decrypt_body(&self, body: &mut Value) -> StoreResult<()> {
+        if !encrypt::has_encrypted_fields(body) {
+            return Ok(());
+        }
+        let Some(cipher) = self.field_cipher()? else {
+            return Err(StoreError::Unavailable(
+                "field encryption key is not configured".to_string(),
+            ));
+        };
+        encrypt::decrypt_fields(body, &cipher)
+    }
+
+    // the stored row text, with the encrypted fields encrypted and the oversized fields offloaded
+    fn encode_body(&self, collection: &str, body: &Value) -> StoreResult<String> {
+        let mut body = Cow::Borrowed(body);
+        if let Some(fields) = self.encrypted_fields.get(collection) {
+            let Some(cipher) = self.field_cipher()? else {
+                return Err(StoreError::Unavailable(format!(
+                    "collection '{}' encrypts fields but the field encryption key is not configured",
+                    collection
+                )));
+            };
+            encrypt::encrypt_fields(body.to_mut(), fields, &cipher)?;
+        }
+        match self.blob_offload()? {
+            Some(offload) => Ok(serde_json::to_string(&offload.offload(&body)?)?),
+            None => Ok(serde_json::to_string(&body)?),
+        }
+    }
+
+    // hash of the stored row text without the server side rewrites, so the same content imported
+    // again with other timestamps still matches
+    fn content_hash(&self, collection: &str, body_text: &str) -> StoreResult<String> {
+        let mut body: Value = serde_json::from_str(body_text)?;
+        if let Some(map) = body.as_object_mut() {
+            for (field, _) in self.computed_fields.get(collection).into_iter().flatten() {
+                map.remove(field);
+            }
+            if let Some(fields) = self.timestamp_fields.get(collection) {
+                for field in fields.created_at.iter().chain(fields.updated_at.iter()) {
+                    map.remove(field);
+                }
+            }
+        }
+        // object keys are sorted, equal bodies encode the same
+        Ok(hex_sha256(serde_json::to_string(&body)?.as_bytes()))
+    }
+
+    /// Every reason the body would be refused for, without writing it: the schema errors, the
+    /// `x-parent-id` one included, and a taken `x-unique` value. `id` is the document the body would
+    /// replace, its own unique value is not taken.
+    pub fn check_body(&self, collection: &str, body: &Value, id: Option<&str>) -> StoreResult<Vec<ValidationIssue>> {
+        let validator = self
+            .schema_validator
+            .get(collection)
+            .ok_or_else(|| StoreError::Validation(format!("collection '{}' not registered", collection)))?;
+        let body = self.resolve_blobs(body)?;
+        let now = chrono::Utc::now();
+        let body = self.prepare_body(collection, &body, now, now);
+        let mut issues: Vec<ValidationIssue> = validator
+            .iter_errors(&body)
+            .map(|e| ValidationIssue {
+                path: e.instance_path().to_string(),
+                message: e.to_string(),
+            })
+            .collect();
+        if let Some(message) = self.oversized(collection, &body)? {
+            issues.push(ValidationIssue {
+                path: String::new(),
+                message,
+            });
+        }
+        if let (Some(field), Some(unique)) = (
+            self.unique_fields.get(collection),
+            self.fetch_unique_field(collection, &body)?,
+        ) {
+            // deleted rows keep their value, the unique constraint counts them as well
+            let parent_id = match self.parent_scoped_unique.contains(collection) {
+                true => self.fetch_parent_id(collection, &body)?,
+                false => None,
+            };
+            let taken: bool = self.read_conn()?.query_row(
+                &format!(
+                    "SELECT EXISTS (SELECT 1 FROM {} WHERE uniq = ?1 AND (?2 IS NULL OR parent_id = ?2) \
+                     AND (?3 IS NULL OR id != ?3))",
+                    sanitize_table_name(collection)
+                ),
+                params![unique, parent_id, id],
+                |r| r.get(0),
+            )?;
+            if taken {
+                issues.push(ValidationIssue {
+                    path: format!("/{}", field),
+                    message: format!("x-unique: '{}' is already taken", unique),
+                });
+            }
+        }
+        Ok(issues)
+    }
+
+    fn validate_against_schema(&self, collection: &str, body: &Value) -> StoreResult<()> {
+        self.schema_validator
+            .get(collection)
+            .ok_or_else(|| StoreError::Validation(format!("collection '{}' not registered", collection)))?
+            .validate(body)
+            .map_err(|errors| StoreError::Validation(errors.to_string()))?;
+        if let Some(message) = self.oversized(collection, body)? {
+            return Err(StoreError::Validation(message));
+        }
+        Ok(())
+    }
+
+    // why the body is over the `x-max-bytes` of the collection, counted with the offloaded fields resolved
+    fn oversized(&self, collection: &str, body: &Value) -> StoreResult<Option<String>> {
+        let Some(max) = self.max_bytes.get(collection) else {
+            return Ok(None);
+        };
+        let size = serde_json::to_vec(body)?.len();
+        Ok((size > *max).then(|| {
+            format!(
+                "x-max-bytes: body of {} bytes exceeds the {} bytes allowed in collection '{}'",
+                size, max, collection
+            )
+        }))
+    }
+}
+
+// internal tables of every database, see `SqliteBackend::init`
+const INTERNAL_TABLES_DDL: &str = r#"
+    CREATE TABLE IF NOT EXISTS __schemas (
+        collection TEXT PRIMARY KEY,
+        schema TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS __acls (
+        id TEXT PRIMARY KEY,
+        data_collection TEXT NOT NULL,
+        data_id TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        permission TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        owner TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS __changes (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        collection TEXT NOT NULL,
+        data_id TEXT NOT NULL,
+        op TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        changed_at TEXT NOT NULL,
+        actor TEXT,
+        reason TEXT,
+        parent_id TEXT,
+        prev_parent_id TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_changes_data ON __changes (collection, data_id, seq);
+    CREATE TABLE IF NOT EXISTS __tombstones (
+        collection TEXT NOT NULL,
+        data_id TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        deleted_at TEXT NOT NULL,
+        deleted_by TEXT,
+        PRIMARY KEY (collection, data_id)
+    );
+    CREATE INDEX IF NOT EXISTS idx_tombstones_deleted_at ON __tombstones (deleted_at);
+    CREATE TABLE IF NOT EXISTS __tombstone_prunes (
+        generation INTEGER PRIMARY KEY AUTOINCREMENT,
+        pruned_seq INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS __replication (
+        target TEXT PRIMARY KEY,
+        last_seq INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS __locks (
+        collection TEXT NOT NULL,
+        data_id TEXT NOT NULL,
+        holder TEXT NOT NULL,
+        acquired_at TEXT NOT NULL,
+        expires_at TEXT NOT NULL,
+        PRIMARY KEY (collection, data_id)
+    );
+    CREATE TABLE IF NOT EXISTS __collection_modes (
+        collection TEXT PRIMARY KEY,
+        read_only INTEGER NOT NULL,
+        reason TEXT,
+        since TEXT
+    );
+    CREATE TABLE IF NOT EXISTS __webhooks (
+        id TEXT PRIMARY KEY,
+        url TEXT NOT NULL,
+        secret TEXT NOT NULL,
+        collections TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        events TEXT NOT NULL DEFAULT '[]',
+        filter_expr TEXT
+    );
+    CREATE TABLE IF NOT EXISTS __webhook_deliveries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        webhook_id TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        payload TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        next_attempt_at TEXT NOT NULL,
+        last_error TEXT,
+        dead INTEGER NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON __webhook_deliveries (dead, next_attempt_at);
+    CREATE TABLE IF NOT EXISTS __attachments (
+        id TEXT PRIMARY KEY,
+        collection TEXT NOT NULL,
+        data_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        content_type TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        sha256 TEXT NOT NULL,
+        content BLOB NOT NULL,
+        owner TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_attachments_data ON __attachments (collection, data_id);
+    CREATE TABLE IF NOT EXISTS __access_stats (
+        collection TEXT NOT NULL,
+        data_id TEXT NOT NULL,
+        reads INTEGER NOT NULL,
+        last_accessed TEXT NOT NULL,
+        PRIMARY KEY (collection, data_id)
+    );
+    CREATE TABLE IF NOT EXISTS __views (
+        collection TEXT NOT NULL,
+        owner TEXT NOT NULL,
+        name TEXT NOT NULL,
+        filter TEXT,
+        sort TEXT,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (collection, owner, name)
+    );
+    CREATE TABLE IF NOT EXISTS __service_keys (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        key_hash TEXT NOT NULL UNIQUE,
+        user_id TEXT NOT NULL,
+        grants TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+"#;
+
+// a read-only database may predate some tables, stand in empty temp tables for them on each connection
+fn stand_in_missing_tables(conn: &mut rusqlite::Connection, tables: &[(String, String)]) -> rusqlite::Result<()> {
+    let internal = internal_tables().map(|(name, ddl)| (name.to_string(), ddl.to_string()));
+    for (name, ddl) in internal.chain(tables.iter().cloned()) {
+        if !table_exists(conn, &name)? {
+            conn.execute_batch(&ddl.replacen("CREATE TABLE", "CREATE TEMP TABLE", 1))?;
+        }
+    }
+    Ok(())
+}
+
+// name and DDL of every internal table
+fn internal_tables() -> impl Iterator<Item = (&'static str, &'static str)> {
+    INTERNAL_TABLES_DDL.split(';').map(str::trim).filter_map(|ddl| {
+        let name = ddl
+            .strip_prefix("CREATE TABLE IF NOT EXISTS ")?
+            .split_whitespace()
+            .next()?;
+        Some((name, ddl))
+    })
+}
+
+// the generated columns are only listed by `table_xinfo`
+fn column_exists(conn: &rusqlite::Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_xinfo(?1) WHERE name = ?2)",
+        params![table, column],
+        |r| r.get(0),
+    )
+}
+
+// `PRAGMA key` of an encrypted database, the first statement a connection runs
+fn apply_key(conn: &rusqlite::Connection, key: &Option<String>) -> rusqlite::Result<()> {
+    match key {
+        Some(key) => conn.pragma_update(None, "key", key),
+        None => Ok(()),
+    }
+}
+
+// whether the table exists in the database file itself, temp stand-ins do not count
+fn table_exists(conn: &rusqlite::Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM main.sqlite_master WHERE type = 'table' AND name = ?1)",
+        params![name],
+        |r| r.get(0),
+    )
+}
+
+// whether the column is a stored generated one
+fn stored_column_exists(conn: &rusqlite::Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_xinfo(?1) WHERE name = ?2 AND hidden = 3)",
+        params![table, column],
+        |r| r.get(0),
+    )
+}
+
+// `extra` column definitions go after the fixed columns
+fn collection_table_ddl(table: &str, parent_scoped_unique: bool, extra: &[String]) -> String {
+    // parent scoped uniqueness is enforced by the (parent_id, uniq) index instead
+    let uniq = if parent_scoped_unique {
+        "uniq TEXT"
+    } else {
+        "uniq TEXT UNIQUE"
+    };
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+            id TEXT PRIMARY KEY,
+            body TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            owner TEXT NOT NULL,
+            {},
+            parent_id TEXT,
+            content_hash TEXT,
+            rev INTEGER NOT NULL DEFAULT 1,
+            schema_version INTEGER NOT NULL DEFAULT 1{}
+        );",
+        table,
+        uniq,
+        extra
+            .iter()
+            .map(|column| format!(",\n            {}", column))
+            .collect::<String>()
+    )
+}
+
+// map the constraint violations of a write into validation errors
+fn map_write_error(e: rusqlite::Error) -> StoreError {
+    match &e {
+        rusqlite::Error::SqliteFailure(err, msg)
+            if err.code == rusqlite::ErrorCode::ConstraintViolation
+                && msg.as_ref().is_some_and(|m| m.contains("UNIQUE")) =>
+        {
+            StoreError::Validation(format!("unique constraint violation: {}, {:?}", err, msg))
+        }
+        rusqlite::Error::SqliteFailure(err, msg) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
+            StoreError::Validation(format!("id already exists: {}, {:?}", err, msg))
+        }
+        _ => StoreError::Backend(e.to_string()),
+    }
+}
+
+/// Longest collection name accepted.
+const MAX_COLLECTION_NAME_LEN: usize = 64;
 
-fn sanitize_table_name(name: &str) -> String {
+/// Name segments and endings the tables use for the sqlite objects derived from a collection, a
+/// collection named with them would take the name of another collection's index or search table.
+const RESERVED_NAME_SEGMENTS: [&str; 2] = ["fts", "xi"];
+const RESERVED_NAME_ENDINGS: [&str; 4] = ["_owner_hash", "_owner_created", "_owner_parent", "_parent_uniq"];
+
+/// Check a collection name before its table is created.
+///
+/// Names are lowercase ASCII letters, digits and `_`, start with a letter, do not hold `__` and are
+/// at most 64 characters long. So two collections never map to the same table, and none maps to an
+/// internal table or to an index, trigger or search table of another collection.
+pub(crate) fn check_collection_name(name: &str) -> StoreResult<()> {
+    let invalid = |reason: &str| {
+        Err(StoreError::Validation(format!(
+            "invalid collection name '{}': {}",
+            name, reason
+        )))
+    };
+    if !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return invalid("it must start with a lowercase ASCII letter");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return invalid("only lowercase ASCII letters, digits and '_' are allowed");
+    }
+    if name.len() > MAX_COLLECTION_NAME_LEN {
+        return invalid(&format!("it is longer than {} characters", MAX_COLLECTION_NAME_LEN));
+    }
+    if name.contains("__") {
+        return invalid("'__' is reserved");
+    }
+    if let Some(segment) = name.split('_').skip(1).find(|s| RESERVED_NAME_SEGMENTS.contains(s)) {
+        return invalid(&format!("'_{}' is reserved", segment));
+    }
+    if let Some(ending) = RESERVED_NAME_ENDINGS.iter().find(|e| name.ends_with(*e)) {
+        return invalid(&format!("'{}' is reserved", ending));
+    }
+    Ok(())
+}
+
+/// Longest document id a client can choose.
+const MAX_DOCUMENT_ID_LEN: usize = 64;
+
+/// Check a document id generated by a client, ASCII letters, digits, `-` and `_`, at most 64 characters
+/// long. The server generated ones are UUIDs.
+pub(crate) fn check_document_id(id: &str) -> StoreResult<()> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_DOCUMENT_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(StoreError::Validation(format!(
+            "invalid document id '{}': 1 to {} ASCII letters, digits, '-' or '_'",
+            id, MAX_DOCUMENT_ID_LEN
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn sanitize_table_name(name: &str) -> String {
     let mut s = String::with_capacity(name.len());
     for c in name.chars() {
         if c.is_ascii_alphanumeric() || c == '_' {
@@ -361,43 +1750,9 @@ impl Backend for SqliteBackend {
         created_at: chrono::DateTime<chrono::Utc>,
         updated_at: chrono::DateTime<chrono::Utc>,
     ) -> StoreResult<String> {
-        self.validate_against_schema(collection, body)?;
-        let body_text = serde_json::to_string(body)?;
-        let table = sanitize_table_name(collection);
-        let conn = self.get_conn()?;
-
-        let unique = self.fetch_unique_field(collection, body)?;
-        let parent_id = self.fetch_parent_id(collection, body)?;
-
-        let sql = format!(
-            "INSERT INTO {} (id, body, created_at, updated_at, owner, uniq, parent_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            table
-        );
-        conn.execute(
-            &sql,
-            params![
-                id,
-                body_text,
-                created_at.to_rfc3339(),
-                updated_at.to_rfc3339(),
-                owner,
-                unique,
-                parent_id
-            ],
-        )
-        .map_err(|e| match &e {
-            rusqlite::Error::SqliteFailure(err, msg)
-                if err.code == rusqlite::ErrorCode::ConstraintViolation
-                    && msg.as_ref().is_some_and(|m| m.contains("UNIQUE")) =>
-            {
-                StoreError::Validation(format!("unique constraint violation: {}, {:?}", err, msg))
-            }
-            rusqlite::Error::SqliteFailure(err, msg) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
-                StoreError::Validation(format!("id already exists: {}, {:?}", err, msg))
-            }
-            _ => StoreError::Backend(e.to_string()),
-        })?;
-        Ok(id)
+        match self.import_with_mode(collection, body, owner, id, created_at, updated_at, ImportMode::Always)? {
+            Imported::Inserted(id) | Imported::Duplicate(id) => Ok(id),
+        }
     }
 
     fn insert(&self, collection: &str, body: &Value, owner: String) -> StoreResult<String> {
@@ -415,42 +1770,7 @@ impl Backend for SqliteBackend {
         marker: Option<String>,
         limit: usize,
     ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
-        let conn = self.get_conn()?;
-        let table = sanitize_table_name(collection);
-        // use a single query: if marker is NULL the WHERE clause is ignored
-        let sql = format!(
-            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id \
-             FROM {} \
-             WHERE (owner = ?1) AND (?2 IS NULL OR id >= ?2) \
-             ORDER BY id ASC \
-             LIMIT ?3",
-            table
-        );
-        let mut stmt = conn.prepare(&sql)?;
-        let mut rows = stmt.query(params![owner, marker, limit as i64 + 1])?;
-        let mut items = Vec::new();
-        let mut next_marker: Option<String> = None;
-        while let Some(row) = rows.next()? {
-            let id = row.get::<_, String>(0)?;
-            if items.len() == limit {
-                // we have one more item, set next_marker
-                next_marker = Some(id);
-                break;
-            }
-            items.push(
-                DataItemDocument {
-                    id: id.clone(),
-                    body: row.get(1)?,
-                    created_at: row.get(2)?,
-                    updated_at: row.get(3)?,
-                    owner: row.get(4)?,
-                    unique: row.get(5)?,
-                    parent_id: row.get(6)?,
-                }
-                .try_into()?,
-            );
-        }
-        Ok((items, next_marker))
+        self.list_by_owner_at(collection, owner, None, marker, None, false, limit)
     }
 
     fn list_children(
@@ -460,68 +1780,15 @@ impl Backend for SqliteBackend {
         marker: Option<String>,
         limit: usize,
     ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
-        let conn = self.get_conn()?;
-        let table = sanitize_table_name(collection);
-        // use a single query: if marker is NULL the WHERE clause is ignored
-        let sql = format!(
-            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id \
-             FROM {} \
-             WHERE (parent_id = ?1) AND (?2 IS NULL OR id >= ?2) \
-             ORDER BY id ASC \
-             LIMIT ?3",
-            table
-        );
-        // tracing::info!("list sql: {}, {}", sql, limit);
-        let mut stmt = conn.prepare(&sql)?;
-        let mut rows = stmt.query(params![parent_id, marker, limit as i64 + 1])?;
-        let mut items = Vec::new();
-        let mut next_marker: Option<String> = None;
-        while let Some(row) = rows.next()? {
-            let id = row.get::<_, String>(0)?;
-            if items.len() == limit {
-                // we have one more item, set next_marker
-                next_marker = Some(id);
-                break;
-            }
-            items.push(
-                DataItemDocument {
-                    id: id.clone(),
-                    body: row.get(1)?,
-                    created_at: row.get(2)?,
-                    updated_at: row.get(3)?,
-                    owner: row.get(4)?,
-                    unique: row.get(5)?,
-                    parent_id: row.get(6)?,
-                }
-                .try_into()?,
-            );
-        }
-        Ok((items, next_marker))
+        self.list_children_at(collection, parent_id, marker, None, false, limit)
     }
 
     fn get(&self, collection: &str, id: &Id) -> StoreResult<DataItem> {
-        let table = sanitize_table_name(collection);
-        let conn = self.get_conn()?;
-        let sql = format!(
-            "SELECT body, created_at, updated_at, owner, uniq, parent_id FROM {} WHERE id = ?1",
-            table
-        );
-        let mut stmt = conn.prepare(&sql)?;
-        let data = stmt
-            .query_row(params![id], |r| {
-                Ok(DataItemDocument {
-                    id: id.to_string(),
-                    body: r.get(0)?,
-                    created_at: r.get(1)?,
-                    updated_at: r.get(2)?,
-                    owner: r.get(3)?,
-                    unique: r.get(4)?,
-                    parent_id: r.get(5)?,
-                })
-            })
-            .optional()?
-            .ok_or(StoreError::NotFound(format!("Get Data {} / {}", collection, id)))?;
-        data.try_into()
+        let item = self.get_with_deleted(collection, id)?;
+        if item.deleted_at.is_some() {
+            return Err(StoreError::NotFound(format!("Get Data {} / {}", collection, id)));
+        }
+        Ok(item)
     }
 
     fn get_by_unique(&self, collection: &str, unique: &str) -> StoreResult<DataItem> {
@@ -531,11 +1798,19 @@ impl Backend for SqliteBackend {
                 collection
             )));
         }
+        if self.parent_scoped_unique.contains(collection) {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' unique field is scoped by parent, use get_by_parent_unique",
+                collection
+            )));
+        }
         let table = sanitize_table_name(collection);
-        let conn = self.get_conn()?;
+        let conn = self.read_conn()?;
         let sql = format!(
-            "SELECT id, body, created_at, updated_at, owner, parent_id FROM {} WHERE uniq = ?1",
-            table
+            "SELECT id, body, created_at, updated_at, owner, parent_id, {} FROM {} WHERE uniq = ?1 AND {}",
+            self.rev_columns(collection),
+            table,
+            self.live_rows(collection, false)
         );
         let mut stmt = conn.prepare(&sql)?;
         let data = stmt
@@ -548,90 +1823,1522 @@ impl Backend for SqliteBackend {
                     owner: r.get(4)?,
                     unique: Some(unique.to_string()),
                     parent_id: r.get(5)?,
+                    rev: r.get(6)?,
+                    schema_version: r.get(7)?,
                 })
             })
             .optional()?
             .ok_or(StoreError::NotFound("Get Data by Unique".to_string()))?;
-        data.try_into()
+        self.decrypted(data.try_into()?)
     }
 
     fn update(&self, collection: &str, id: &Id, body: &Value) -> StoreResult<DataItem> {
-        // validate data, ensure collection table exists and schema validated
-        self.validate_against_schema(collection, body)?;
-        let body_text = serde_json::to_string(body)?;
-        let updated_at = chrono::Utc::now();
-        let table = sanitize_table_name(collection);
-        let conn = self.get_conn()?;
-        let unique = self.fetch_unique_field(collection, body)?;
-        let parent_id = self.fetch_parent_id(collection, body)?;
-        let sql = format!(
-            "UPDATE {} SET body = ?1, updated_at = ?2, uniq = ?3, parent_id = ?4 WHERE id = ?5",
-            table
-        );
-        let n = conn.execute(&sql, params![body_text, updated_at, unique, parent_id, id])?;
-        if n == 0 {
-            return Err(StoreError::NotFound("Update Data".to_string()));
-        }
+        self.update_with_meta(collection, id, body, None, &ChangeMeta::default())
+    }
 
-        // read back
-        let item = self.get(collection, id)?;
-        Ok(item)
+    fn move_to_parent(&self, collection: &str, id: &Id, parent_id: &str) -> StoreResult<DataItem> {
+        self.move_to_parent_with_meta(collection, id, parent_id, &ChangeMeta::default())
     }
 
     fn delete(&self, collection: &str, id: &Id) -> StoreResult<()> {
-        let table = sanitize_table_name(collection);
-        let conn = self.get_conn()?;
-        let sql = format!("DELETE FROM {} WHERE id = ?1", table);
-        let n = conn.execute(&sql, params![id])?;
-        if n == 0 {
-            return Err(StoreError::NotFound("Delete Data".to_string()));
-        }
-        Ok(())
+        self.delete_with_meta(collection, id, None, &ChangeMeta::default())
     }
 
     fn batch_delete(&self, collection: &str, ids: &[Id]) -> StoreResult<()> {
-        let table = sanitize_table_name(collection);
         let mut conn = self.get_conn()?;
         let tx = conn.transaction()?;
-        let sql = format!("DELETE FROM {} WHERE id = ?1", table);
-        {
-            let mut stmt = tx.prepare(&sql)?;
-            for id in ids {
-                let n = stmt.execute(params![id])?;
-                if n == 0 {
-                    return Err(StoreError::NotFound(format!("Delete Data id={}", id)));
-                }
-            }
-            // drop stmt before commit
+        let mut visited = HashSet::new();
+        for id in ids {
+            self.delete_tree(&tx, collection, id, &ChangeMeta::default(), &mut visited)?;
         }
         tx.commit()?;
         Ok(())
     }
 }
 
-// impl acls related methods
+// impl writes recording who made the change and why into the change log
 impl SqliteBackend {
-    pub fn get_data_permissions(&self, data_collection: &str, data_id: &str) -> StoreResult<Vec<PermissionSchema>> {
-        let conn = self.get_conn()?;
-        let sql = "SELECT user_id, permission FROM __acls WHERE data_collection = ?1 AND data_id = ?2".to_string();
-        let mut stmt = conn.prepare(&sql)?;
-        let mut rows = stmt.query(params![data_collection, data_id])?;
-        let mut permissions = Vec::new();
-        while let Some(row) = rows.next()? {
-            let user_id: String = row.get(0)?;
-            let permission_str: String = row.get(1)?;
-            let access_level = AccessLevel::from_str(&permission_str)?;
-            permissions.push(PermissionSchema {
-                data_id: data_id.to_string(),
-                user_id,
-                access_level,
-            });
-        }
-        Ok(permissions)
-    }
+    /// Import a document with specific id and timestamps, with `ImportMode::SkipDuplicates` a body already
+    /// stored for the owner in this collection is not inserted again.
+    #[allow(clippy::too_many_arguments)]
+    pub fn import_with_mode(
+        &self,
+        collection: &str,
+        body: &Value,
+        owner: String,
+        id: String,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+        mode: ImportMode,
+    ) -> StoreResult<Imported> {
+        let body = self.resolve_blobs(body)?;
+        let body = &self.prepare_body(collection, &body, created_at, updated_at);
+        self.validate_against_schema(collection, body)?;
+        let body_text = self.encode_body(collection, body)?;
+        let content_hash = self.content_hash(collection, &body_text)?;
+        let table = sanitize_table_name(collection);
+        let mut conn = self.get_conn()?;
 
-    pub fn get_user_permissions(&self, data_collection: &str, user_id: &str) -> StoreResult<Vec<PermissionSchema>> {
+        let unique = self.fetch_unique_field(collection, body)?;
+        let parent_id = self.fetch_parent_id(collection, body)?;
+
+        let tx = conn.transaction()?;
+        if mode == ImportMode::SkipDuplicates
+            && let Some(existing) = tx
+                .query_row(
+                    &format!(
+                        "SELECT id FROM {} WHERE owner = ?1 AND content_hash = ?2 LIMIT 1",
+                        table
+                    ),
+                    params![owner, content_hash],
+                    |r| r.get(0),
+                )
+                .optional()?
+        {
+            return Ok(Imported::Duplicate(existing));
+        }
+        let sql = format!(
+            "INSERT INTO {} (id, body, created_at, updated_at, owner, uniq, parent_id, content_hash, schema_version) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            table
+        );
+        tx.execute(
+            &sql,
+            params![
+                id,
+                body_text,
+                created_at.to_rfc3339(),
+                updated_at.to_rfc3339(),
+                owner,
+                unique,
+                parent_id,
+                content_hash,
+                self.schema_version(collection)
+            ],
+        )
+        .map_err(map_write_error)?;
+        record_upsert(&tx, collection, &id, &ChangeMeta::actor(&owner))?;
+        tx.commit()?;
+        Ok(Imported::Inserted(id))
+    }
+
+    /// Replace the body, with `expected_rev` only while the document is still at that revision.
+    pub fn update_with_meta(
+        &self,
+        collection: &str,
+        id: &Id,
+        body: &Value,
+        expected_rev: Option<i64>,
+        meta: &ChangeMeta,
+    ) -> StoreResult<DataItem> {
+        let updated_at = chrono::Utc::now();
+        let immutable = self.immutable_fields.get(collection);
+        // the current document is only needed for its immutable fields or a created_at mirrored into the body
+        let mirrors_created_at = self
+            .timestamp_fields
+            .get(collection)
+            .is_some_and(|fields| fields.created_at.is_some());
+        let current = if immutable.is_some() || mirrors_created_at {
+            Some(self.get(collection, id)?)
+        } else {
+            None
+        };
+        let created_at = match &current {
+            Some(current) if mirrors_created_at => current.created_at,
+            _ => updated_at,
+        };
+        // validate data, ensure collection table exists and schema validated
+        let body = self.resolve_blobs(body)?;
+        if let (Some(fields), Some(current)) = (immutable, &current) {
+            check_immutable(collection, fields, &self.resolve_blobs(&current.body)?, &body)?;
+        }
+        let body = &self.prepare_body(collection, &body, created_at, updated_at);
+        self.validate_against_schema(collection, body)?;
+        let body_text = self.encode_body(collection, body)?;
+        let table = sanitize_table_name(collection);
+        let mut conn = self.get_conn()?;
+        let unique = self.fetch_unique_field(collection, body)?;
+        let parent_id = self.fetch_parent_id(collection, body)?;
+        let content_hash = self.content_hash(collection, &body_text)?;
+        let sql = format!(
+            "UPDATE {} SET body = ?1, updated_at = ?2, uniq = ?3, parent_id = ?4, content_hash = ?5, rev = rev + 1, \
+             schema_version = ?8 WHERE id = ?6 AND (?7 IS NULL OR rev = ?7) AND {}",
+            table,
+            self.live_rows(collection, false)
+        );
+        let tx = conn.transaction()?;
+        let n = tx
+            .execute(
+                &sql,
+                params![
+                    body_text,
+                    updated_at,
+                    unique,
+                    parent_id,
+                    content_hash,
+                    id,
+                    expected_rev,
+                    self.schema_version(collection)
+                ],
+            )
+            .map_err(map_write_error)?;
+        if n == 0 {
+            // a stale revision is a conflict, not a missing document
+            self.check_rev(&tx, collection, id, expected_rev)?;
+            return Err(StoreError::NotFound("Update Data".to_string()));
+        }
+
+        // read back
+        let item = record_upsert(&tx, collection, id, meta)?;
+        tx.commit()?;
+        self.decrypted(item)
+    }
+
+    pub fn move_to_parent_with_meta(
+        &self,
+        collection: &str,
+        id: &Id,
+        parent_id: &str,
+        meta: &ChangeMeta,
+    ) -> StoreResult<DataItem> {
+        let Some(xpm) = self.parent_ref.get(collection) else {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' has no parent collection",
+                collection
+            )));
+        };
+        let table = sanitize_table_name(collection);
+        let mut conn = self.get_conn()?;
+        // read and rewrite inside one transaction so a concurrent update can't be lost
+        let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        let current = tx
+            .query_row(
+                &format!(
+                    "SELECT body, created_at FROM {} WHERE id = ?1 AND {}",
+                    table,
+                    self.live_rows(collection, false)
+                ),
+                params![id],
+                |r| Ok((r.get::<_, String>(0)?, r.get::<_, chrono::DateTime<chrono::Utc>>(1)?)),
+            )
+            .optional()?;
+        let Some((body_text, created_at)) = current else {
+            return Err(StoreError::NotFound(format!("Move Data {} / {}", collection, id)));
+        };
+        let mut body: Value = serde_json::from_str(&body_text)?;
+        self.decrypt_body(&mut body)?;
+        let mut body = self.resolve_blobs(&body)?.into_owned();
+        set_body_field(&mut body, &xpm.field, Value::String(parent_id.to_string()))?;
+
+        let updated_at = chrono::Utc::now();
+        let body = &self.prepare_body(collection, &body, created_at, updated_at);
+        // the x-parent-id keyword checks the new parent exists
+        self.validate_against_schema(collection, body)?;
+        let unique = self.fetch_unique_field(collection, body)?;
+        let body_text = self.encode_body(collection, body)?;
+        tx.execute(
+            &format!(
+                "UPDATE {} SET body = ?1, updated_at = ?2, uniq = ?3, parent_id = ?4, content_hash = ?5, rev = rev + 1, \
+                 schema_version = ?7 WHERE id = ?6",
+                table
+            ),
+            params![
+                body_text,
+                updated_at,
+                unique,
+                parent_id,
+                self.content_hash(collection, &body_text)?,
+                id,
+                self.schema_version(collection)
+            ],
+        )
+        .map_err(map_write_error)?;
+        let item = record_upsert(&tx, collection, id, meta)?;
+        tx.commit()?;
+        self.decrypted(item)
+    }
+
+    /// Delete the document, the soft deleting collections only mark it deleted.
+    ///
+    /// Its children go along or keep it from being deleted as their `x-on-parent-delete` says, all
+    /// in one transaction.
+    ///
+    /// With `expected_rev` only while the document is still at that revision.
+    pub fn delete_with_meta(
+        &self,
+        collection: &str,
+        id: &Id,
+        expected_rev: Option<i64>,
+        meta: &ChangeMeta,
+    ) -> StoreResult<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        self.check_rev(&tx, collection, id, expected_rev)?;
+        self.delete_tree(&tx, collection, id, meta, &mut HashSet::new())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Bring back a soft deleted document, recorded as an upsert in the change log.
+    pub fn restore_with_meta(&self, collection: &str, id: &Id, meta: &ChangeMeta) -> StoreResult<DataItem> {
+        if !self.soft_deletes(collection) {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' does not keep deleted items",
+                collection
+            )));
+        }
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let n = tx.execute(
+            &format!(
+                "UPDATE {} SET deleted_at = NULL, updated_at = ?1, rev = rev + 1 WHERE id = ?2 AND deleted_at IS NOT NULL",
+                sanitize_table_name(collection)
+            ),
+            params![chrono::Utc::now(), id],
+        )?;
+        if n == 0 {
+            return Err(StoreError::NotFound(format!("Deleted Data {} / {}", collection, id)));
+        }
+        let item = record_upsert(&tx, collection, id, meta)?;
+        tx.commit()?;
+        self.decrypted(item)
+    }
+
+    // delete the document after its children, following the `x-on-parent-delete` of their collections
+    fn delete_tree(
+        &self,
+        conn: &rusqlite::Connection,
+        collection: &str,
+        id: &str,
+        meta: &ChangeMeta,
+        visited: &mut HashSet<(String, String)>,
+    ) -> StoreResult<()> {
+        // a document moved under its own descendant is met again, it is deleted once
+        if !visited.insert((collection.to_string(), id.to_string())) {
+            return Ok(());
+        }
+        for (child, policy) in &self.on_parent_delete {
+            if self.parent_collection(child).map(|(parent, _)| parent) != Some(collection)
+                || self.parent_namespace(child).is_some()
+            {
+                continue;
+            }
+            let children: Vec<String> = conn
+                .prepare(&format!(
+                    "SELECT id FROM {} WHERE parent_id = ?1 AND {}",
+                    sanitize_table_name(child),
+                    self.live_rows(child, false)
+                ))?
+                .query_map(params![id], |r| r.get(0))?
+                .collect::<Result<_, _>>()?;
+            match policy {
+                OnParentDelete::Restrict if !children.is_empty() => {
+                    return Err(StoreError::Conflict(format!(
+                        "{} / {} still has {} children in '{}'",
+                        collection,
+                        id,
+                        children.len(),
+                        child
+                    )));
+                }
+                OnParentDelete::Cascade => {
+                    for child_id in children {
+                        self.delete_tree(conn, child, &child_id, meta, visited)?;
+                    }
+                }
+                OnParentDelete::Restrict | OnParentDelete::Orphan => {}
+            }
+        }
+        self.delete_row(conn, collection, id)?;
+        record_change(conn, collection, id, &ChangeOp::Delete, meta)
+    }
+
+    /// Whether a document of the collection holds the id, soft deleted ones included.
+    pub fn id_taken(&self, collection: &str, id: &str) -> StoreResult<bool> {
+        Ok(self.read_conn()?.query_row(
+            &format!(
+                "SELECT EXISTS (SELECT 1 FROM {} WHERE id = ?1)",
+                sanitize_table_name(collection)
+            ),
+            params![id],
+            |r| r.get(0),
+        )?)
+    }
+
+    // a conflict when the document is no longer at the revision the writer expects
+    fn check_rev(
+        &self,
+        conn: &rusqlite::Connection,
+        collection: &str,
+        id: &str,
+        expected_rev: Option<i64>,
+    ) -> StoreResult<()> {
+        let Some(expected_rev) = expected_rev else {
+            return Ok(());
+        };
+        let rev: Option<i64> = conn
+            .query_row(
+                &format!(
+                    "SELECT rev FROM {} WHERE id = ?1 AND {}",
+                    sanitize_table_name(collection),
+                    self.live_rows(collection, false)
+                ),
+                params![id],
+                |r| r.get(0),
+            )
+            .optional()?;
+        match rev {
+            None => Err(StoreError::NotFound(format!("Data {} / {}", collection, id))),
+            Some(rev) if rev != expected_rev => Err(StoreError::Conflict(format!(
+                "{} / {} is at revision {}, not {}",
+                collection, id, rev, expected_rev
+            ))),
+            Some(_) => Ok(()),
+        }
+    }
+
+    // remove the row with its attachments and read counts, or mark it deleted and keep them for a restore
+    fn delete_row(&self, conn: &rusqlite::Connection, collection: &str, id: &str) -> StoreResult<()> {
+        let table = sanitize_table_name(collection);
+        if self.soft_deletes(collection) {
+            let n = conn.execute(
+                &format!(
+                    "UPDATE {} SET deleted_at = ?1, updated_at = ?1, rev = rev + 1 WHERE id = ?2 AND deleted_at IS NULL",
+                    table
+                ),
+                params![chrono::Utc::now(), id],
+            )?;
+            if n == 0 {
+                return Err(StoreError::NotFound(format!("Delete Data id={}", id)));
+            }
+            return Ok(());
+        }
+        let n = conn.execute(&format!("DELETE FROM {} WHERE id = ?1", table), params![id])?;
+        if n == 0 {
+            return Err(StoreError::NotFound(format!("Delete Data id={}", id)));
+        }
+        delete_attachments(conn, collection, id)?;
+        delete_access_stats(conn, collection, id)
+    }
+
+    /// The document, also when it is soft deleted, see `DataItem::deleted_at`.
+    pub fn get_with_deleted(&self, collection: &str, id: &Id) -> StoreResult<DataItem> {
+        let conn = self.read_conn()?;
+        let mut item = read_item(&conn, collection, id, self.rev_columns(collection))?
+            .ok_or(StoreError::NotFound(format!("Get Data {} / {}", collection, id)))?;
+        if self.soft_deletes(collection) {
+            item.deleted_at = conn.query_row(
+                &format!(
+                    "SELECT deleted_at FROM {} WHERE id = ?1",
+                    sanitize_table_name(collection)
+                ),
+                params![id],
+                |r| r.get(0),
+            )?;
+        }
+        self.decrypted(item)
+    }
+}
+
+impl SqliteBackend {
+    /// Get a document by its unique field among the children of one parent.
+    pub fn get_by_parent_unique(&self, collection: &str, parent_id: &str, unique: &str) -> StoreResult<DataItem> {
+        if !self.unique_fields.contains_key(collection) {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' does not have unique field defined",
+                collection
+            )));
+        }
+        let table = sanitize_table_name(collection);
+        let conn = self.read_conn()?;
+        let sql = format!(
+            "SELECT id, body, created_at, updated_at, owner, {} FROM {} WHERE parent_id = ?1 AND uniq = ?2 AND {}",
+            self.rev_columns(collection),
+            table,
+            self.live_rows(collection, false)
+        );
+        let data = conn
+            .query_row(&sql, params![parent_id, unique], |r| {
+                Ok(DataItemDocument {
+                    id: r.get(0)?,
+                    body: r.get(1)?,
+                    created_at: r.get(2)?,
+                    updated_at: r.get(3)?,
+                    owner: r.get(4)?,
+                    unique: Some(unique.to_string()),
+                    parent_id: Some(parent_id.to_string()),
+                    rev: r.get(5)?,
+                    schema_version: r.get(6)?,
+                })
+            })
+            .optional()?
+            .ok_or(StoreError::NotFound("Get Data by Parent Unique".to_string()))?;
+        self.decrypted(data.try_into()?)
+    }
+}
+
+// impl listings pinned to a snapshot of the table
+impl SqliteBackend {
+    /// Boundary of the rows present now, rowids only grow with inserts, an update keeps the rowid.
+    ///
+    /// A rowid is only reused after deleting the newest row of the table.
+    pub fn snapshot_boundary(&self, collection: &str) -> StoreResult<i64> {
+        let conn = self.read_conn()?;
+        let sql = format!(
+            "SELECT COALESCE(MAX(rowid), 0) FROM {}",
+            sanitize_table_name(collection)
+        );
+        Ok(conn.query_row(&sql, [], |r| r.get(0))?)
+    }
+
+    /// `list_by_owner` only seeing the rows up to the `snapshot` rowid, when given, and with
+    /// `include_deleted` the soft deleted ones too. With `parent_id` only the owner's children of that
+    /// parent are listed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_by_owner_at(
+        &self,
+        collection: &str,
+        owner: &str,
+        parent_id: Option<&str>,
+        marker: Option<String>,
+        snapshot: Option<i64>,
+        include_deleted: bool,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        let conn = self.read_conn()?;
+        let table = sanitize_table_name(collection);
+        // use a single query: if marker is NULL the WHERE clause is ignored
+        let sql = format!(
+            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id, {}, {} \
+             FROM {} \
+             WHERE (owner = ?1) AND (?5 IS NULL OR parent_id = ?5) AND (?2 IS NULL OR id >= ?2) \
+             AND (?3 IS NULL OR rowid <= ?3) AND {} \
+             ORDER BY id ASC \
+             LIMIT ?4",
+            self.deleted_column(collection),
+            self.rev_columns(collection),
+            table,
+            self.live_rows(collection, include_deleted)
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![owner, marker, snapshot, limit as i64 + 1, parent_id])?;
+        let mut items = Vec::new();
+        let mut next_marker: Option<String> = None;
+        while let Some(row) = rows.next()? {
+            let id = row.get::<_, String>(0)?;
+            if items.len() == limit {
+                // we have one more item, set next_marker
+                next_marker = Some(id);
+                break;
+            }
+            let mut item: DataItem = DataItemDocument {
+                id: id.clone(),
+                body: row.get(1)?,
+                created_at: row.get(2)?,
+                updated_at: row.get(3)?,
+                owner: row.get(4)?,
+                unique: row.get(5)?,
+                parent_id: row.get(6)?,
+                rev: row.get(8)?,
+                schema_version: row.get(9)?,
+            }
+            .try_into()?;
+            item.deleted_at = row.get(7)?;
+            items.push(self.decrypted(item)?);
+        }
+        Ok((items, next_marker))
+    }
+
+    /// `list_children` only seeing the rows up to the `snapshot` rowid, when given, and with
+    /// `include_deleted` the soft deleted ones too.
+    pub fn list_children_at(
+        &self,
+        collection: &str,
+        parent_id: &str,
+        marker: Option<String>,
+        snapshot: Option<i64>,
+        include_deleted: bool,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        let conn = self.read_conn()?;
+        let table = sanitize_table_name(collection);
+        // use a single query: if marker is NULL the WHERE clause is ignored
+        let sql = format!(
+            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id, {}, {} \
+             FROM {} \
+             WHERE (parent_id = ?1) AND (?2 IS NULL OR id >= ?2) AND (?3 IS NULL OR rowid <= ?3) AND {} \
+             ORDER BY id ASC \
+             LIMIT ?4",
+            self.deleted_column(collection),
+            self.rev_columns(collection),
+            table,
+            self.live_rows(collection, include_deleted)
+        );
+        // tracing::info!("list sql: {}, {}", sql, limit);
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![parent_id, marker, snapshot, limit as i64 + 1])?;
+        let mut items = Vec::new();
+        let mut next_marker: Option<String> = None;
+        while let Some(row) = rows.next()? {
+            let id = row.get::<_, String>(0)?;
+            if items.len() == limit {
+                // we have one more item, set next_marker
+                next_marker = Some(id);
+                break;
+            }
+            let mut item: DataItem = DataItemDocument {
+                id: id.clone(),
+                body: row.get(1)?,
+                created_at: row.get(2)?,
+                updated_at: row.get(3)?,
+                owner: row.get(4)?,
+                unique: row.get(5)?,
+                parent_id: row.get(6)?,
+                rev: row.get(8)?,
+                schema_version: row.get(9)?,
+            }
+            .try_into()?;
+            item.deleted_at = row.get(7)?;
+            items.push(self.decrypted(item)?);
+        }
+        Ok((items, next_marker))
+    }
+}
+
+// impl pages walked both ways
+impl SqliteBackend {
+    /// A page of the owner's documents or of a parent's children, with the marker of the page before.
+    pub fn list_page(
+        &self,
+        collection: &str,
+        scope: QueryScope,
+        marker: Option<String>,
+        include_deleted: bool,
+        limit: usize,
+    ) -> StoreResult<Page> {
+        let (items, next_marker) = match scope {
+            QueryScope::Owner(owner) => {
+                self.list_by_owner_at(collection, owner, None, marker.clone(), None, include_deleted, limit)?
+            }
+            QueryScope::Parent(parent_id) => {
+                self.list_children_at(collection, parent_id, marker.clone(), None, include_deleted, limit)?
+            }
+        };
+        let (has_prev, prev_marker) = match marker {
+            Some(marker) => self.prev_marker(collection, &scope, &marker, include_deleted, limit)?,
+            None => (false, None),
+        };
+        Ok(Page {
+            items,
+            next_marker,
+            prev_marker,
+            has_prev,
+        })
+    }
+
+    /// A page of the owner's documents or of a parent's children in `order`, ties broken by id.
+    ///
+    /// With `since` only the documents updated at or after it are listed, with `include_deleted` the
+    /// soft deleted ones too.
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_sorted(
+        &self,
+        collection: &str,
+        scope: QueryScope,
+        order: ListOrder,
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        include_deleted: bool,
+        marker: Option<&SortMarker>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<SortMarker>)> {
+        let (scope_column, scope_value) = scope.column();
+        let key = match order.key {
+            SortKey::CreatedAt => "created_at",
+            SortKey::UpdatedAt => "updated_at",
+            // documents without a unique value come first
+            SortKey::Unique => "IFNULL(uniq, '')",
+        };
+        let (direction, cmp) = if order.descending {
+            ("DESC", "<=")
+        } else {
+            ("ASC", ">=")
+        };
+        let sql = format!(
+            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id, {key}, {}, {} \
+             FROM {} \
+             WHERE ({scope_column} = ?1) AND (?2 IS NULL OR ({key}, id) {cmp} (?2, ?3)) \
+             AND (?5 IS NULL OR updated_at >= ?5) AND {} \
+             ORDER BY {key} {direction}, id {direction} \
+             LIMIT ?4",
+            self.deleted_column(collection),
+            self.rev_columns(collection),
+            sanitize_table_name(collection),
+            self.live_rows(collection, include_deleted)
+        );
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![
+            scope_value,
+            marker.map(|m| &m.value),
+            marker.map(|m| &m.id),
+            limit as i64 + 1,
+            since
+        ])?;
+        let mut items = Vec::new();
+        let mut next_marker = None;
+        while let Some(row) = rows.next()? {
+            let id = row.get::<_, String>(0)?;
+            if items.len() == limit {
+                next_marker = Some(SortMarker { value: row.get(7)?, id });
+                break;
+            }
+            let mut item: DataItem = DataItemDocument {
+                id,
+                body: row.get(1)?,
+                created_at: row.get(2)?,
+                updated_at: row.get(3)?,
+                owner: row.get(4)?,
+                unique: row.get(5)?,
+                parent_id: row.get(6)?,
+                rev: row.get(9)?,
+                schema_version: row.get(10)?,
+            }
+            .try_into()?;
+            item.deleted_at = row.get(8)?;
+            items.push(self.decrypted(item)?);
+        }
+        Ok((items, next_marker))
+    }
+
+    // walk `limit` ids back from the marker, no marker when the page before is the first one
+    fn prev_marker(
+        &self,
+        collection: &str,
+        scope: &QueryScope,
+        marker: &str,
+        include_deleted: bool,
+        limit: usize,
+    ) -> StoreResult<(bool, Option<String>)> {
+        let (scope_column, scope_value) = scope.column();
+        let sql = format!(
+            "SELECT id FROM {} WHERE {} = ?1 AND id < ?2 AND {} ORDER BY id DESC LIMIT ?3",
+            sanitize_table_name(collection),
+            scope_column,
+            self.live_rows(collection, include_deleted)
+        );
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let ids = stmt
+            .query_map(params![scope_value, marker, limit as i64 + 1], |r| {
+                r.get::<_, String>(0)
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let prev_marker = if ids.len() > limit {
+            ids.get(limit - 1).cloned()
+        } else {
+            None
+        };
+        Ok((!ids.is_empty(), prev_marker))
+    }
+}
+
+// impl inbox style queries, used by inner components
+impl SqliteBackend {
+    /// List documents of an owner newest first, optionally only those whose body `field` equals `value`.
+    pub fn list_recent_by_owner(
+        &self,
+        collection: &str,
+        owner: &str,
+        filter: Option<(&str, &Value)>,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        let conn = self.read_conn()?;
+        let table = sanitize_table_name(collection);
+        let (path, value) = json_filter(filter)?;
+        let (live, rev) = (self.live_rows(collection, false), self.rev_columns(collection));
+        let sql = format!(
+            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id, {rev} \
+             FROM {table} \
+             WHERE (owner = ?1) AND (?2 IS NULL OR json_extract(body, ?2) = json_extract(?3, '$')) \
+             AND (?4 IS NULL OR (created_at, id) <= (SELECT created_at, id FROM {table} WHERE id = ?4)) AND {live} \
+             ORDER BY created_at DESC, id DESC \
+             LIMIT ?5"
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![owner, path, value, marker, limit as i64 + 1])?;
+        let mut items = Vec::new();
+        let mut next_marker: Option<String> = None;
+        while let Some(row) = rows.next()? {
+            let id = row.get::<_, String>(0)?;
+            if items.len() == limit {
+                next_marker = Some(id);
+                break;
+            }
+            items.push(
+                self.decrypted(
+                    DataItemDocument {
+                        id: id.clone(),
+                        body: row.get(1)?,
+                        created_at: row.get(2)?,
+                        updated_at: row.get(3)?,
+                        owner: row.get(4)?,
+                        unique: row.get(5)?,
+                        parent_id: row.get(6)?,
+                        rev: row.get(7)?,
+                        schema_version: row.get(8)?,
+                    }
+                    .try_into()?,
+                )?,
+            );
+        }
+        Ok((items, next_marker))
+    }
+
+    /// Count documents of an owner, optionally only those whose body `field` equals `value`.
+    pub fn count_by_owner(&self, collection: &str, owner: &str, filter: Option<(&str, &Value)>) -> StoreResult<u64> {
+        let conn = self.read_conn()?;
+        let (path, value) = json_filter(filter)?;
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} \
+             WHERE (owner = ?1) AND (?2 IS NULL OR json_extract(body, ?2) = json_extract(?3, '$')) AND {}",
+            sanitize_table_name(collection),
+            self.live_rows(collection, false)
+        );
+        let count: i64 = conn.query_row(&sql, params![owner, path, value], |r| r.get(0))?;
+        Ok(count as u64)
+    }
+
+    /// Items and stored bytes of an owner in every collection, attachments count for the item owner.
+    pub fn usage_by_owner(&self, owner: &str) -> StoreResult<Vec<CollectionUsage>> {
+        let conn = self.read_conn()?;
+        self.collections()
+            .into_iter()
+            .map(|collection| -> StoreResult<CollectionUsage> {
+                let table = sanitize_table_name(collection);
+                let (items, body_bytes): (i64, i64) = conn.query_row(
+                    &format!(
+                        "SELECT COUNT(*), COALESCE(SUM(LENGTH(CAST(body AS BLOB))), 0) FROM {} WHERE owner = ?1",
+                        table
+                    ),
+                    params![owner],
+                    |r| Ok((r.get(0)?, r.get(1)?)),
+                )?;
+                let attachment_bytes: i64 = conn.query_row(
+                    &format!(
+                        "SELECT COALESCE(SUM(a.size), 0) FROM __attachments a JOIN {} t ON t.id = a.data_id \
+                         WHERE a.collection = ?1 AND t.owner = ?2",
+                        table
+                    ),
+                    params![collection, owner],
+                    |r| r.get(0),
+                )?;
+                Ok(CollectionUsage {
+                    collection: collection.to_string(),
+                    items: items as u64,
+                    body_bytes: body_bytes as u64,
+                    attachment_bytes: attachment_bytes as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// Count and time range of an owner's items in a collection, and their count per parent.
+    pub fn stats_by_owner(&self, collection: &str, owner: &str) -> StoreResult<CollectionStats> {
+        let table = sanitize_table_name(collection);
+        let live = self.live_rows(collection, false);
+        let conn = self.read_conn()?;
+        // each aggregate is answered from the (owner, ...) indexes
+        let (count, oldest, newest, last_updated) = conn.query_row(
+            &format!(
+                "SELECT COUNT(*), MIN(created_at), MAX(created_at), MAX(updated_at) FROM {} WHERE owner = ?1 AND {}",
+                table, live
+            ),
+            params![owner],
+            |r| Ok((r.get::<_, i64>(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+        )?;
+        let parents = if self.parent_ref.contains_key(collection) {
+            conn.prepare(&format!(
+                "SELECT parent_id, COUNT(*) FROM {} WHERE owner = ?1 AND parent_id IS NOT NULL AND {} \
+                 GROUP BY parent_id ORDER BY parent_id",
+                table, live
+            ))?
+            .query_map(params![owner], |r| {
+                Ok(ParentCount {
+                    parent_id: r.get(0)?,
+                    count: r.get::<_, i64>(1)? as u64,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        } else {
+            Vec::new()
+        };
+        Ok(CollectionStats {
+            collection: collection.to_string(),
+            count: count as u64,
+            oldest,
+            newest,
+            last_updated,
+            parents,
+        })
+    }
+}
+
+// impl filter expression queries
+impl SqliteBackend {
+    /// List the documents of an owner, or the children of a parent, matching the filter, ordered by id.
+    pub fn query(
+        &self,
+        collection: &str,
+        scope: QueryScope,
+        filter: &FilterExpr,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.query_sorted(collection, scope, Some(filter), None, marker, limit)
+    }
+
+    /// `query` with an optional filter and sort.
+    ///
+    /// Sorted pages are followed by position, their marker is the offset of the next page instead of an id.
+    pub fn query_sorted(
+        &self,
+        collection: &str,
+        scope: QueryScope,
+        filter: Option<&FilterExpr>,
+        sort: Option<&SortSpec>,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        let (condition, filter_params) = match filter {
+            Some(filter) => filter.compile_with_columns(&self.index_columns(collection))?,
+            None => ("1".to_string(), Vec::new()),
+        };
+        let (scope_column, scope_value) = scope.column();
+        let mut query_params = vec![SqlValue::Text(scope_value.to_string())];
+        query_params.extend(filter_params);
+        let (position, order, offset) = match sort {
+            None => {
+                let marker = marker.map_or(SqlValue::Null, SqlValue::Text);
+                query_params.extend([marker.clone(), marker]);
+                ("? IS NULL OR id >= ?", "id ASC".to_string(), 0)
+            }
+            Some(sort) => {
+                let offset = match marker {
+                    Some(marker) => marker
+                        .parse::<usize>()
+                        .map_err(|_| StoreError::Validation(format!("invalid sorted page marker `{}`", marker)))?,
+                    None => 0,
+                };
+                let (term, sort_params) = sort.compile_with_columns(&self.index_columns(collection))?;
+                query_params.extend(sort_params);
+                ("1", format!("{}, id ASC", term), offset)
+            }
+        };
+        let sql = format!(
+            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id, {} \
+             FROM {} \
+             WHERE ({} = ?) AND ({}) AND ({}) AND {} \
+             ORDER BY {} \
+             LIMIT ? OFFSET ?",
+            self.rev_columns(collection),
+            sanitize_table_name(collection),
+            scope_column,
+            condition,
+            position,
+            self.live_rows(collection, false),
+            order
+        );
+        query_params.extend([SqlValue::Integer(limit as i64 + 1), SqlValue::Integer(offset as i64)]);
+
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(query_params))?;
+        let mut items = Vec::new();
+        let mut next_marker: Option<String> = None;
+        while let Some(row) = rows.next()? {
+            let id = row.get::<_, String>(0)?;
+            if items.len() == limit {
+                next_marker = Some(match sort {
+                    Some(_) => (offset + limit).to_string(),
+                    None => id,
+                });
+                break;
+            }
+            items.push(
+                self.decrypted(
+                    DataItemDocument {
+                        id: id.clone(),
+                        body: row.get(1)?,
+                        created_at: row.get(2)?,
+                        updated_at: row.get(3)?,
+                        owner: row.get(4)?,
+                        unique: row.get(5)?,
+                        parent_id: row.get(6)?,
+                        rev: row.get(7)?,
+                        schema_version: row.get(8)?,
+                    }
+                    .try_into()?,
+                )?,
+            );
+        }
+        Ok((items, next_marker))
+    }
+}
+
+// impl saved views, each user sees only their own
+impl SqliteBackend {
+    /// Create or replace the view of the same name.
+    pub fn save_view(&self, collection: &str, owner: &str, view: &SavedView) -> StoreResult<()> {
+        let filter = view.filter.as_ref().map(serde_json::to_string).transpose()?;
+        let sort = view.sort.as_ref().map(serde_json::to_string).transpose()?;
+        self.get_conn()?.execute(
+            "INSERT INTO __views (collection, owner, name, filter, sort, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+             ON CONFLICT(collection, owner, name) DO UPDATE SET filter = excluded.filter, sort = excluded.sort, \
+             updated_at = excluded.updated_at",
+            params![collection, owner, view.name, filter, sort, view.updated_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_view(&self, collection: &str, owner: &str, name: &str) -> StoreResult<SavedView> {
+        let conn = self.read_conn()?;
+        let view = conn
+            .query_row(
+                "SELECT name, filter, sort, updated_at FROM __views WHERE collection = ?1 AND owner = ?2 AND name = ?3",
+                params![collection, owner, name],
+                read_view,
+            )
+            .optional()?;
+        parse_view(view.ok_or_else(|| StoreError::NotFound(format!("View {}/{}", collection, name)))?)
+    }
+
+    /// Views of the owner in the collection, by name.
+    pub fn list_views(&self, collection: &str, owner: &str) -> StoreResult<Vec<SavedView>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT name, filter, sort, updated_at FROM __views WHERE collection = ?1 AND owner = ?2 ORDER BY name",
+        )?;
+        let rows = stmt.query_map(params![collection, owner], read_view)?;
+        rows.map(|row| parse_view(row?)).collect()
+    }
+
+    pub fn count_views(&self, collection: &str, owner: &str) -> StoreResult<usize> {
+        let count: i64 = self.read_conn()?.query_row(
+            "SELECT COUNT(*) FROM __views WHERE collection = ?1 AND owner = ?2",
+            params![collection, owner],
+            |r| r.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    pub fn delete_view(&self, collection: &str, owner: &str, name: &str) -> StoreResult<()> {
+        let deleted = self.get_conn()?.execute(
+            "DELETE FROM __views WHERE collection = ?1 AND owner = ?2 AND name = ?3",
+            params![collection, owner, name],
+        )?;
+        if deleted == 0 {
+            return Err(StoreError::NotFound(format!("View {}/{}", collection, name)));
+        }
+        Ok(())
+    }
+}
+
+// (name, filter json, sort json, updated_at)
+type ViewRow = (String, Option<String>, Option<String>, chrono::DateTime<chrono::Utc>);
+
+fn read_view(r: &rusqlite::Row) -> rusqlite::Result<ViewRow> {
+    Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+}
+
+fn parse_view((name, filter, sort, updated_at): ViewRow) -> StoreResult<SavedView> {
+    Ok(SavedView {
+        name,
+        filter: filter.as_deref().map(serde_json::from_str).transpose()?,
+        sort: sort.as_deref().map(serde_json::from_str).transpose()?,
+        updated_at,
+    })
+}
+
+// impl service keys, only the hash of a key is kept
+impl SqliteBackend {
+    pub fn add_service_key(&self, key: &ServiceKey, key_hash: &str) -> StoreResult<()> {
+        self.get_conn()?.execute(
+            "INSERT INTO __service_keys (id, name, key_hash, user_id, grants, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                key.id,
+                key.name,
+                key_hash,
+                key.user_id,
+                serde_json::to_string(&key.grants)?,
+                key.created_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_service_keys(&self) -> StoreResult<Vec<ServiceKey>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM __service_keys ORDER BY created_at, id",
+            SERVICE_KEY_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], read_service_key)?;
+        rows.map(|row| parse_service_key(row?)).collect()
+    }
+
+    pub fn get_service_key(&self, id: &str) -> StoreResult<ServiceKey> {
+        let conn = self.read_conn()?;
+        let row = conn
+            .query_row(
+                &format!("SELECT {} FROM __service_keys WHERE id = ?1", SERVICE_KEY_COLUMNS),
+                params![id],
+                read_service_key,
+            )
+            .optional()?;
+        parse_service_key(row.ok_or_else(|| StoreError::NotFound(format!("Service key {}", id)))?)
+    }
+
+    /// The key of the hash, none when it is unknown or revoked.
+    pub fn find_service_key(&self, key_hash: &str) -> StoreResult<Option<ServiceKey>> {
+        let conn = self.read_conn()?;
+        let row = conn
+            .query_row(
+                &format!("SELECT {} FROM __service_keys WHERE key_hash = ?1", SERVICE_KEY_COLUMNS),
+                params![key_hash],
+                read_service_key,
+            )
+            .optional()?;
+        row.map(parse_service_key).transpose()
+    }
+
+    pub fn set_service_key_grants(&self, id: &str, grants: &BTreeMap<String, ServiceAccess>) -> StoreResult<()> {
+        let updated = self.get_conn()?.execute(
+            "UPDATE __service_keys SET grants = ?2 WHERE id = ?1",
+            params![id, serde_json::to_string(grants)?],
+        )?;
+        if updated == 0 {
+            return Err(StoreError::NotFound(format!("Service key {}", id)));
+        }
+        Ok(())
+    }
+
+    pub fn delete_service_key(&self, id: &str) -> StoreResult<()> {
+        let deleted = self
+            .get_conn()?
+            .execute("DELETE FROM __service_keys WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(StoreError::NotFound(format!("Service key {}", id)));
+        }
+        Ok(())
+    }
+}
+
+const SERVICE_KEY_COLUMNS: &str = "id, name, user_id, grants, created_at";
+
+// (id, name, user_id, grants json, created_at)
+type ServiceKeyRow = (String, String, String, String, chrono::DateTime<chrono::Utc>);
+
+fn read_service_key(r: &rusqlite::Row) -> rusqlite::Result<ServiceKeyRow> {
+    Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+}
+
+fn parse_service_key((id, name, user_id, grants, created_at): ServiceKeyRow) -> StoreResult<ServiceKey> {
+    Ok(ServiceKey {
+        id,
+        name,
+        user_id,
+        grants: serde_json::from_str(&grants)?,
+        created_at,
+        key: None,
+    })
+}
+
+// impl lookups on the `x-index` fields
+impl SqliteBackend {
+    // dict<field, generated column> of the `x-index` fields, the filters and sorts read those directly
+    fn index_columns(&self, collection: &str) -> HashMap<String, String> {
+        self.indexed_fields
+            .get(collection)
+            .into_iter()
+            .flatten()
+            .map(|field| (field.clone(), index_column(field)))
+            .collect()
+    }
+
+    /// List the documents whose indexed body field equals `value`, ordered by id.
+    ///
+    /// Only the fields listed in the `x-index` of the collection schema can be looked up.
+    pub fn list_by_field(
+        &self,
+        collection: &str,
+        field: &str,
+        value: &Value,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        if !self
+            .indexed_fields
+            .get(collection)
+            .is_some_and(|fields| fields.iter().any(|f| f == field))
+        {
+            return Err(StoreError::Validation(format!(
+                "field '{}' of collection '{}' is not in x-index",
+                field, collection
+            )));
+        }
+        // the sql values json_extract gives back
+        let value = match value {
+            Value::Null => SqlValue::Null,
+            Value::Bool(b) => SqlValue::Integer(*b as i64),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => SqlValue::Integer(i),
+                None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => SqlValue::Text(s.clone()),
+            Value::Array(_) | Value::Object(_) => {
+                return Err(StoreError::Validation(
+                    "x-index lookups only take a scalar value".to_string(),
+                ));
+            }
+        };
+        let sql = format!(
+            "SELECT id, body, created_at, updated_at, owner, uniq, parent_id, {} \
+             FROM {} \
+             WHERE ({} IS ?1) AND (?2 IS NULL OR id >= ?2) AND {} \
+             ORDER BY id ASC \
+             LIMIT ?3",
+            self.rev_columns(collection),
+            sanitize_table_name(collection),
+            index_column(field),
+            self.live_rows(collection, false)
+        );
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![value, marker, limit as i64 + 1])?;
+        let mut items = Vec::new();
+        let mut next_marker: Option<String> = None;
+        while let Some(row) = rows.next()? {
+            let id = row.get::<_, String>(0)?;
+            if items.len() == limit {
+                next_marker = Some(id);
+                break;
+            }
+            items.push(
+                self.decrypted(
+                    DataItemDocument {
+                        id: id.clone(),
+                        body: row.get(1)?,
+                        created_at: row.get(2)?,
+                        updated_at: row.get(3)?,
+                        owner: row.get(4)?,
+                        unique: row.get(5)?,
+                        parent_id: row.get(6)?,
+                        rev: row.get(7)?,
+                        schema_version: row.get(8)?,
+                    }
+                    .try_into()?,
+                )?,
+            );
+        }
+        Ok((items, next_marker))
+    }
+}
+
+// impl full text search on the `x-fulltext` fields
+impl SqliteBackend {
+    /// Documents holding every term of `query` in their `x-fulltext` fields, best match first.
+    pub fn search(&self, collection: &str, query: &str, offset: usize, limit: usize) -> StoreResult<Vec<DataItem>> {
+        if !self.fulltext_fields.contains_key(collection) {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' has no x-fulltext fields",
+                collection
+            )));
+        }
+        let table = sanitize_table_name(collection);
+        let fts = fulltext::fts_table(&table);
+        // the fts columns are all `f_` prefixed, the unqualified ones are the table's
+        let (live, rev) = (self.live_rows(collection, false), self.rev_columns(collection));
+        let sql = format!(
+            "SELECT t.id, t.body, t.created_at, t.updated_at, t.owner, t.uniq, t.parent_id, {rev} \
+             FROM {fts} JOIN {table} t ON t.rowid = {fts}.rowid \
+             WHERE {fts} MATCH ?1 AND {live} \
+             ORDER BY {fts}.rank, t.id \
+             LIMIT ?2 OFFSET ?3"
+        );
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![fulltext::match_query(query)?, limit as i64, offset as i64])?;
+        let mut items = Vec::new();
+        while let Some(row) = rows.next()? {
+            items.push(
+                self.decrypted(
+                    DataItemDocument {
+                        id: row.get(0)?,
+                        body: row.get(1)?,
+                        created_at: row.get(2)?,
+                        updated_at: row.get(3)?,
+                        owner: row.get(4)?,
+                        unique: row.get(5)?,
+                        parent_id: row.get(6)?,
+                        rev: row.get(7)?,
+                        schema_version: row.get(8)?,
+                    }
+                    .try_into()?,
+                )?,
+            );
+        }
+        Ok(items)
+    }
+}
+
+// generated column holding an `x-index` field
+fn index_column(field: &str) -> String {
+    format!("xi_{}", field.replace('.', "__"))
+}
+
+// definition of the stored column of an `x-index` field, the fields are checked to be plain paths
+fn stored_column(field: &str) -> String {
+    format!(
+        "{} GENERATED ALWAYS AS (json_extract(body, '$.{}')) STORED",
+        index_column(field),
+        field
+    )
+}
+
+/// Which documents a filter query or a page runs over.
+pub enum QueryScope<'a> {
+    Owner(&'a str),
+    Parent(&'a str),
+}
+
+impl<'a> QueryScope<'a> {
+    fn column(&self) -> (&'static str, &'a str) {
+        match self {
+            QueryScope::Owner(owner) => ("owner", owner),
+            QueryScope::Parent(parent_id) => ("parent_id", parent_id),
+        }
+    }
+}
+
+// (json path, json text) parameters of a body field equality filter
+fn json_filter(filter: Option<(&str, &Value)>) -> StoreResult<(Option<String>, Option<String>)> {
+    match filter {
+        Some((field, value)) => Ok((Some(format!("$.{}", field)), Some(serde_json::to_string(value)?))),
+        None => Ok((None, None)),
+    }
+}
+
+// impl maintenance related methods
+impl SqliteBackend {
+    /// Collections registered in this backend.
+    pub fn collections(&self) -> Vec<&str> {
+        let mut collections: Vec<&str> = self.schema_validator.keys().map(|c| c.as_str()).collect();
+        collections.sort_unstable();
+        collections
+    }
+
+    /// Write a transactionally consistent copy of the database into `path`, which must not exist yet.
+    pub fn snapshot_to(&self, path: &Path) -> StoreResult<()> {
+        let conn = self.get_conn()?;
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Copy the live database into the new file `path` with the SQLite online backup API.
+    ///
+    /// The pages are copied in one step, a read transaction in WAL mode, so the copy is consistent
+    /// and the writers carry on meanwhile; copied in several steps a write in between restarts it.
+    pub fn backup_to(&self, path: &Path) -> StoreResult<()> {
+        if path.exists() {
+            return Err(StoreError::Validation(format!(
+                "backup destination {} already exists",
+                path.display()
+            )));
+        }
+        let conn = self.read_conn()?;
+        let result = rusqlite::Connection::open(path).and_then(|mut dest| {
+            apply_key(&dest, &self.encryption_key)?;
+            let backup = rusqlite::backup::Backup::new(&conn, &mut dest)?;
+            backup.run_to_completion(-1, std::time::Duration::ZERO, None)
+        });
+        if result.is_err() {
+            // never leave a partial copy behind
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(result?)
+    }
+
+    /// Check the integrity of the database, then VACUUM and ANALYZE it. Returns the size before and
+    /// after in bytes, and the problems the check found.
+    ///
+    /// The check runs first so a damaged database is reported before VACUUM rewrites it.
+    pub fn vacuum(&self) -> StoreResult<(u64, u64, Vec<String>)> {
         let conn = self.get_conn()?;
+        let size = |conn: &rusqlite::Connection| -> StoreResult<u64> {
+            Ok(conn.query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                [],
+                |r| r.get::<_, i64>(0),
+            )? as u64)
+        };
+        let before = size(&conn)?;
+        let errors: Vec<String> = conn
+            .prepare(&format!("PRAGMA integrity_check({})", MAX_INTEGRITY_ERRORS))?
+            .query_map([], |r| r.get(0))?
+            .collect::<Result<_, _>>()?;
+        let errors = errors.into_iter().filter(|e| e != "ok").collect();
+        conn.execute_batch("VACUUM; ANALYZE;")?;
+        Ok((before, size(&conn)?, errors))
+    }
+
+    /// Collections having a parent collection in this database, the only ones that can hold orphans
+    /// found here.
+    pub fn child_collections(&self) -> Vec<&str> {
+        let mut collections: Vec<&str> = self
+            .parent_ref
+            .iter()
+            .filter(|(_, m)| m.namespace.is_none())
+            .map(|(c, _)| c.as_str())
+            .collect();
+        collections.sort_unstable();
+        collections
+    }
+
+    /// List documents of `collection` whose parent no longer exists.
+    pub fn list_orphans(&self, collection: &str) -> StoreResult<Vec<Orphan>> {
+        let Some(xpm) = self.parent_ref.get(collection).filter(|m| m.namespace.is_none()) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.read_conn()?;
+        // a soft deleted parent can still be restored, its children are not orphans, and soft deleted
+        // orphans are already resolved
+        let sql = format!(
+            "SELECT c.id, c.owner, c.parent_id FROM {} c \
+             WHERE c.parent_id IS NOT NULL AND {} \
+             AND NOT EXISTS (SELECT 1 FROM {} p WHERE p.id = c.parent_id) \
+             ORDER BY c.id ASC",
+            sanitize_table_name(collection),
+            self.live_rows(collection, false),
+            sanitize_table_name(&xpm.parent)
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let orphans = stmt
+            .query_map([], |r| {
+                Ok(Orphan {
+                    collection: collection.to_string(),
+                    id: r.get(0)?,
+                    owner: r.get(1)?,
+                    parent_id: r.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(orphans)
+    }
+
+    /// Schema problems the compiled validators only run into on the first write, e.g. an
+    /// `x-parent-id` naming a collection this database does not have.
+    pub fn check_schemas(&self) -> Vec<String> {
+        self.child_collections()
+            .into_iter()
+            .filter_map(|collection| {
+                let parent = &self.parent_ref[collection].parent;
+                (!self.schema_validator.contains_key(parent)).then(|| {
+                    format!(
+                        "collection `{}`: x-parent-id names unknown collection `{}`",
+                        collection, parent
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Fill the `uniq` and `parent_id` columns of the documents stored before their collection
+    /// declared `x-unique` or `x-parent-id`.
+    ///
+    /// Returns the number of documents filled, and the ones whose unique value another document
+    /// already holds, those keep an empty column.
+    pub fn backfill_derived_columns(&self) -> StoreResult<(usize, Vec<String>)> {
+        let mut collections: Vec<&str> = self
+            .unique_fields
+            .keys()
+            .chain(self.parent_ref.keys())
+            .map(|c| c.as_str())
+            .collect();
+        collections.sort_unstable();
+        collections.dedup();
+
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let mut filled = 0;
+        let mut conflicts = Vec::new();
+        for collection in collections {
+            let table = sanitize_table_name(collection);
+            let rows: Vec<(String, String, Option<String>, Option<String>)> = tx
+                .prepare(&format!(
+                    "SELECT id, body, uniq, parent_id FROM {table} WHERE uniq IS NULL OR parent_id IS NULL ORDER BY created_at, id"
+                ))?
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?
+                .collect::<Result<_, _>>()?;
+            for (id, body_text, uniq, parent_id) in rows {
+                let body: Value = serde_json::from_str(&body_text)?;
+                // the parent first, a parent scoped unique value is only taken among its siblings
+                let columns = [
+                    (
+                        "parent_id",
+                        parent_id.is_none(),
+                        self.fetch_parent_id(collection, &body)?,
+                    ),
+                    ("uniq", uniq.is_none(), self.fetch_unique_field(collection, &body)?),
+                ];
+                let mut changed = false;
+                for (column, empty, value) in columns {
+                    let Some(value) = value.filter(|_| empty) else {
+                        continue;
+                    };
+                    if fill_column(&tx, &table, column, &id, &value)? {
+                        changed = true;
+                    } else {
+                        conflicts.push(format!(
+                            "{}/{}: {} `{}` clashes with another document",
+                            collection, id, column, value
+                        ));
+                    }
+                }
+                if changed {
+                    filled += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok((filled, conflicts))
+    }
+}
+
+// set an empty derived column, false when a unique constraint refuses the value
+fn fill_column(conn: &rusqlite::Connection, table: &str, column: &str, id: &str, value: &str) -> StoreResult<bool> {
+    match conn.execute(
+        &format!("UPDATE {table} SET {column} = ?1 WHERE id = ?2"),
+        params![value, id],
+    ) {
+        Ok(_) => Ok(true),
+        Err(rusqlite::Error::SqliteFailure(err, _)) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// impl acls related methods
+impl SqliteBackend {
+    pub fn get_data_permissions(&self, data_collection: &str, data_id: &str) -> StoreResult<Vec<PermissionSchema>> {
+        let conn = self.read_conn()?;
+        read_data_permissions(&conn, data_collection, data_id)
+    }
+
+    pub fn get_user_permissions(&self, data_collection: &str, user_id: &str) -> StoreResult<Vec<PermissionSchema>> {
+        let conn = self.read_conn()?;
         let sql = "SELECT data_id, permission FROM __acls WHERE data_collection = ?1 AND user_id = ?2".to_string();
         let mut stmt = conn.prepare(&sql)?;
         let mut rows = stmt.query(params![data_collection, user_id])?;
@@ -649,79 +3356,1103 @@ impl SqliteBackend {
         Ok(permissions)
     }
 
-    pub fn delete_acls_by_data_id(&self, data_collection: &str, data_id: &str) -> StoreResult<()> {
+    /// Grant and revoke the same permissions on many documents in one transaction, the entries
+    /// of the users not named are kept.
+    pub fn update_acls_bulk(
+        &self,
+        data_collection: &str,
+        data_ids: &[String],
+        grant: &[Permission],
+        revoke: &[String],
+        owner: &str,
+    ) -> StoreResult<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        for data_id in data_ids {
+            for user_id in revoke {
+                tx.execute(
+                    "DELETE FROM __acls WHERE data_collection = ?1 AND data_id = ?2 AND user_id = ?3",
+                    params![data_collection, data_id, user_id],
+                )?;
+            }
+            for p in grant {
+                let permission_str = p.access_level.to_string();
+                let updated = tx.execute(
+                    "UPDATE __acls SET permission = ?1, updated_at = ?2 WHERE data_collection = ?3 AND data_id = ?4 AND user_id = ?5",
+                    params![permission_str, now, data_collection, data_id, p.user],
+                )?;
+                if updated == 0 {
+                    tx.execute(
+                        "INSERT INTO __acls (id, data_collection, data_id, user_id, permission, created_at, updated_at, owner) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7)",
+                        params![
+                            uuid::Uuid::new_v4().to_string(),
+                            data_collection,
+                            data_id,
+                            p.user,
+                            permission_str,
+                            now,
+                            owner
+                        ],
+                    )?;
+                }
+            }
+            let op = ChangeOp::Acl {
+                owner: owner.to_string(),
+                permissions: read_data_permissions(&tx, data_collection, data_id)?,
+            };
+            record_change(&tx, data_collection, data_id, &op, &ChangeMeta::actor(owner))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// ACL entries granted by or to the user, optionally of one collection, ordered by entry id.
+    pub fn list_acls(
+        &self,
+        direction: AclDirection,
+        user: &str,
+        collection: Option<&str>,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<AclEntry>, Option<String>)> {
+        let user_column = match direction {
+            AclDirection::Granted => "owner",
+            AclDirection::Received => "user_id",
+        };
+        let sql = format!(
+            "SELECT id, data_collection, data_id, user_id, permission, owner, updated_at FROM __acls \
+             WHERE {} = ?1 AND (?2 IS NULL OR data_collection = ?2) AND (?3 IS NULL OR id >= ?3) \
+             ORDER BY id ASC LIMIT ?4",
+            user_column
+        );
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![user, collection, marker, limit as i64 + 1])?;
+        let mut entries = Vec::new();
+        let mut next_marker = None;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            if entries.len() == limit {
+                next_marker = Some(id);
+                break;
+            }
+            let permission_str: String = row.get(4)?;
+            entries.push(AclEntry {
+                collection: row.get(1)?,
+                data_id: row.get(2)?,
+                user: row.get(3)?,
+                access_level: AccessLevel::from_str(&permission_str)?,
+                granted_by: row.get(5)?,
+                updated_at: row.get(6)?,
+            });
+        }
+        Ok((entries, next_marker))
+    }
+
+    pub fn delete_acls_by_data_id(&self, data_collection: &str, data_id: &str) -> StoreResult<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let sql = "DELETE FROM __acls WHERE data_collection = ?1 AND data_id = ?2".to_string();
+        if tx.execute(&sql, params![data_collection, data_id])? > 0 {
+            let op = ChangeOp::Acl {
+                owner: String::new(),
+                permissions: Vec::new(),
+            };
+            record_change(&tx, data_collection, data_id, &op, &ChangeMeta::default())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn update_acls(
+        &self,
+        data_collection: &str,
+        data_id: &str,
+        new_permissions: &[PermissionSchema],
+        owner: &str,
+    ) -> StoreResult<()> {
+        let old_permissions = self.get_data_permissions(data_collection, data_id)?;
+
+        let mut deleted_ids = Vec::new();
+        let mut to_update_permissions = Vec::new();
+        let mut new_permissions: HashMap<String, PermissionSchema> =
+            HashMap::from_iter(new_permissions.iter().map(|p| (p.user_id.clone(), p.clone())));
+        for old in &old_permissions {
+            match new_permissions.remove(&old.user_id) {
+                // exists in both old and new, update if different
+                Some(new_p) if new_p.access_level != old.access_level => to_update_permissions.push(new_p),
+                // same permission, do nothing
+                Some(_) => {}
+                // only in old, delete
+                None => deleted_ids.push(old.user_id.clone()),
+            }
+        }
+        let updated_at = chrono::Utc::now();
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        for user_id in deleted_ids {
+            let sql = "DELETE FROM __acls WHERE data_collection = ?1 AND data_id = ?2 AND user_id = ?3".to_string();
+            tx.execute(&sql, params![data_collection, data_id, user_id])?;
+        }
+        for p in to_update_permissions {
+            let permission_str = p.access_level.to_string();
+            let sql = "UPDATE __acls SET permission = ?1, updated_at = ?2 WHERE data_collection = ?3 AND data_id = ?4 AND user_id = ?5".to_string();
+            tx.execute(
+                &sql,
+                params![
+                    permission_str,
+                    updated_at.to_rfc3339(),
+                    data_collection,
+                    data_id,
+                    p.user_id
+                ],
+            )?;
+        }
+        for (_user_id, p) in new_permissions {
+            let permission_str = p.access_level.to_string();
+            let now = chrono::Utc::now();
+            let sql = "INSERT INTO __acls (id, data_collection, data_id, user_id, permission, created_at, updated_at, owner) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)".to_string();
+            let acl_id = uuid::Uuid::new_v4().to_string();
+            tx.execute(
+                &sql,
+                params![
+                    acl_id,
+                    data_collection,
+                    data_id,
+                    p.user_id,
+                    permission_str,
+                    now.to_rfc3339(),
+                    now.to_rfc3339(),
+                    owner
+                ],
+            )?;
+        }
+
+        let op = ChangeOp::Acl {
+            owner: owner.to_string(),
+            permissions: read_data_permissions(&tx, data_collection, data_id)?,
+        };
+        record_change(&tx, data_collection, data_id, &op, &ChangeMeta::actor(owner))?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// impl collection mode related methods
+impl SqliteBackend {
+    pub fn collection_mode(&self, collection: &str) -> StoreResult<CollectionMode> {
+        let conn = self.read_conn()?;
+        let mode = conn
+            .query_row(
+                "SELECT read_only, reason, since FROM __collection_modes WHERE collection = ?1",
+                params![collection],
+                |r| {
+                    Ok(CollectionMode {
+                        read_only: r.get(0)?,
+                        reason: r.get(1)?,
+                        since: r.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(mode.unwrap_or_default())
+    }
+
+    /// Persist the mode of a collection, writable collections drop their row.
+    pub fn set_collection_mode(&self, collection: &str, mode: &CollectionMode) -> StoreResult<()> {
+        let conn = self.get_conn()?;
+        if mode.read_only {
+            conn.execute(
+                "INSERT INTO __collection_modes (collection, read_only, reason, since) VALUES (?1, 1, ?2, ?3) \
+                 ON CONFLICT(collection) DO UPDATE SET read_only = 1, reason = excluded.reason, since = excluded.since",
+                params![collection, mode.reason, mode.since.map(|t| t.to_rfc3339())],
+            )?;
+        } else {
+            conn.execute(
+                "DELETE FROM __collection_modes WHERE collection = ?1",
+                params![collection],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// impl advisory lock related methods, expired locks are treated as released
+impl SqliteBackend {
+    pub fn get_lock(&self, collection: &str, id: &str) -> StoreResult<Option<ItemLock>> {
+        let conn = self.read_conn()?;
+        read_lock(&conn, collection, id)
+    }
+
+    /// Take or refresh the lock of `holder`, fails while another holder's lock is active.
+    pub fn acquire_lock(
+        &self,
+        collection: &str,
+        id: &str,
+        holder: &str,
+        ttl: chrono::Duration,
+    ) -> StoreResult<ItemLock> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        let now = chrono::Utc::now();
+        let acquired_at = match read_lock(&tx, collection, id)? {
+            Some(lock) if lock.holder != holder => {
+                return Err(StoreError::Conflict(format!(
+                    "{}/{} is locked by {} until {}",
+                    collection,
+                    id,
+                    lock.holder,
+                    lock.expires_at.to_rfc3339()
+                )));
+            }
+            Some(lock) => lock.acquired_at,
+            None => now,
+        };
+        let lock = ItemLock {
+            holder: holder.to_string(),
+            acquired_at,
+            expires_at: now + ttl,
+        };
+        tx.execute(
+            "INSERT INTO __locks (collection, data_id, holder, acquired_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5) \
+             ON CONFLICT(collection, data_id) DO UPDATE SET holder = excluded.holder, \
+             acquired_at = excluded.acquired_at, expires_at = excluded.expires_at",
+            params![
+                collection,
+                id,
+                lock.holder,
+                lock.acquired_at.to_rfc3339(),
+                lock.expires_at.to_rfc3339()
+            ],
+        )?;
+        tx.commit()?;
+        Ok(lock)
+    }
+
+    pub fn release_lock(&self, collection: &str, id: &str) -> StoreResult<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "DELETE FROM __locks WHERE collection = ?1 AND data_id = ?2",
+            params![collection, id],
+        )?;
+        Ok(())
+    }
+}
+
+fn read_lock(conn: &rusqlite::Connection, collection: &str, id: &str) -> StoreResult<Option<ItemLock>> {
+    let lock = conn
+        .query_row(
+            "SELECT holder, acquired_at, expires_at FROM __locks WHERE collection = ?1 AND data_id = ?2",
+            params![collection, id],
+            |r| {
+                Ok(ItemLock {
+                    holder: r.get(0)?,
+                    acquired_at: r.get(1)?,
+                    expires_at: r.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(lock.filter(|lock| lock.expires_at > chrono::Utc::now()))
+}
+
+// impl attachment related methods, the contents are only read one at a time
+impl SqliteBackend {
+    pub fn add_attachment(
+        &self,
+        collection: &str,
+        data_id: &str,
+        attachment: &Attachment,
+        content: &[u8],
+    ) -> StoreResult<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO __attachments (id, collection, data_id, name, content_type, size, sha256, content, owner, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                attachment.id,
+                collection,
+                data_id,
+                attachment.name,
+                attachment.content_type,
+                attachment.size as i64,
+                attachment.sha256,
+                content,
+                attachment.owner,
+                attachment.created_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_attachments(&self, collection: &str, data_id: &str) -> StoreResult<Vec<Attachment>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, content_type, size, sha256, owner, created_at FROM __attachments \
+             WHERE collection = ?1 AND data_id = ?2 ORDER BY created_at, id",
+        )?;
+        let rows = stmt.query_map(params![collection, data_id], read_attachment)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn get_attachment(&self, collection: &str, data_id: &str, id: &str) -> StoreResult<AttachmentContent> {
+        let conn = self.read_conn()?;
+        conn.query_row(
+            "SELECT id, name, content_type, size, sha256, owner, created_at, content FROM __attachments \
+             WHERE collection = ?1 AND data_id = ?2 AND id = ?3",
+            params![collection, data_id, id],
+            |r| {
+                Ok(AttachmentContent {
+                    attachment: read_attachment(r)?,
+                    content: r.get(7)?,
+                })
+            },
+        )
+        .optional()?
+        .ok_or_else(|| StoreError::NotFound(format!("attachment {}", id)))
+    }
+
+    pub fn delete_attachment(&self, collection: &str, data_id: &str, id: &str) -> StoreResult<()> {
         let conn = self.get_conn()?;
-        let sql = "DELETE FROM __acls WHERE data_collection = ?1 AND data_id = ?2".to_string();
-        conn.execute(&sql, params![data_collection, data_id])?;
+        let n = conn.execute(
+            "DELETE FROM __attachments WHERE collection = ?1 AND data_id = ?2 AND id = ?3",
+            params![collection, data_id, id],
+        )?;
+        if n == 0 {
+            return Err(StoreError::NotFound(format!("attachment {}", id)));
+        }
         Ok(())
     }
+}
 
-    pub fn update_acls(
-        &self,
-        data_collection: &str,
-        data_id: &str,
-        new_permissions: &[PermissionSchema],
-        owner: &str,
-    ) -> StoreResult<()> {
-        let old_permissions = self.get_data_permissions(data_collection, data_id)?;
+fn read_attachment(r: &rusqlite::Row) -> rusqlite::Result<Attachment> {
+    Ok(Attachment {
+        id: r.get(0)?,
+        name: r.get(1)?,
+        content_type: r.get(2)?,
+        size: r.get::<_, i64>(3)? as u64,
+        sha256: r.get(4)?,
+        owner: r.get(5)?,
+        created_at: r.get(6)?,
+    })
+}
 
-        let mut deleted_ids = Vec::new();
-        let mut to_update_permissions = Vec::new();
-        let mut new_permissions: HashMap<String, PermissionSchema> =
-            HashMap::from_iter(new_permissions.iter().map(|p| (p.user_id.clone(), p.clone())));
-        for old in &old_permissions {
-            match new_permissions.remove(&old.user_id) {
-                // exists in both old and new, update if different
-                Some(new_p) if new_p.access_level != old.access_level => to_update_permissions.push(new_p),
-                // same permission, do nothing
-                Some(_) => {}
-                // only in old, delete
-                None => deleted_ids.push(old.user_id.clone()),
-            }
+// attachments live and die with their document
+fn delete_attachments(conn: &rusqlite::Connection, collection: &str, data_id: &str) -> StoreResult<()> {
+    conn.execute(
+        "DELETE FROM __attachments WHERE collection = ?1 AND data_id = ?2",
+        params![collection, data_id],
+    )?;
+    Ok(())
+}
+
+/// Reads buffered before being written, a full buffer is written by the read adding to it.
+const ACCESS_STATS_BATCH: usize = 256;
+
+// impl access statistics related methods, the reads are counted in memory and written in batches
+impl SqliteBackend {
+    pub fn records_access_stats(&self, collection: &str) -> bool {
+        self.access_stats.contains(collection)
+    }
+
+    /// Count one read of the item, nothing for the collections without `x-access-stats`.
+    pub fn record_read(&self, collection: &str, id: &Id) -> StoreResult<()> {
+        if self.read_only || !self.records_access_stats(collection) {
+            return Ok(());
+        }
+        let full = {
+            let mut pending = self
+                .pending_reads
+                .lock()
+                .map_err(|e| StoreError::Backend(e.to_string()))?;
+            let now = chrono::Utc::now();
+            let entry = pending.entry((collection.to_string(), id.clone())).or_insert((0, now));
+            entry.0 += 1;
+            entry.1 = now;
+            pending.len() >= ACCESS_STATS_BATCH
+        };
+        if full {
+            self.flush_access_stats()?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffered reads in one transaction, returns the number of items they were buffered for.
+    pub fn flush_access_stats(&self) -> StoreResult<usize> {
+        let pending = std::mem::take(
+            &mut *self
+                .pending_reads
+                .lock()
+                .map_err(|e| StoreError::Backend(e.to_string()))?,
+        );
+        if pending.is_empty() {
+            return Ok(0);
         }
-        let updated_at = chrono::Utc::now();
         let mut conn = self.get_conn()?;
         let tx = conn.transaction()?;
-        for user_id in deleted_ids {
-            let sql = "DELETE FROM __acls WHERE data_collection = ?1 AND data_id = ?2 AND user_id = ?3".to_string();
-            tx.execute(&sql, params![data_collection, data_id, user_id])?;
-        }
-        for p in to_update_permissions {
-            let permission_str = p.access_level.to_string();
-            let sql = "UPDATE __acls SET permission = ?1, updated_at = ?2 WHERE data_collection = ?3 AND data_id = ?4 AND user_id = ?5".to_string();
+        for ((collection, id), (reads, last_accessed)) in &pending {
+            // items deleted since their read are skipped
             tx.execute(
-                &sql,
-                params![
-                    permission_str,
-                    updated_at.to_rfc3339(),
-                    data_collection,
-                    data_id,
-                    p.user_id
-                ],
+                &format!(
+                    "INSERT INTO __access_stats (collection, data_id, reads, last_accessed) \
+                     SELECT ?1, ?2, ?3, ?4 WHERE EXISTS (SELECT 1 FROM {} WHERE id = ?2) \
+                     ON CONFLICT(collection, data_id) DO UPDATE SET reads = reads + excluded.reads, \
+                     last_accessed = MAX(last_accessed, excluded.last_accessed)",
+                    sanitize_table_name(collection)
+                ),
+                params![collection, id, *reads as i64, last_accessed.to_rfc3339()],
             )?;
         }
-        for (_user_id, p) in new_permissions {
-            let permission_str = p.access_level.to_string();
-            let now = chrono::Utc::now();
-            let sql = "INSERT INTO __acls (id, data_collection, data_id, user_id, permission, created_at, updated_at, owner) \
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)".to_string();
-            let acl_id = uuid::Uuid::new_v4().to_string();
+        tx.commit()?;
+        Ok(pending.len())
+    }
+
+    /// Reads of the item so far, the buffered ones included.
+    pub fn access_stats(&self, collection: &str, id: &Id) -> StoreResult<AccessStats> {
+        let conn = self.read_conn()?;
+        let stored: Option<(i64, chrono::DateTime<chrono::Utc>)> = conn
+            .query_row(
+                "SELECT reads, last_accessed FROM __access_stats WHERE collection = ?1 AND data_id = ?2",
+                params![collection, id],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?;
+        let mut stats = AccessStats {
+            reads: stored.map(|(reads, _)| reads as u64).unwrap_or_default(),
+            last_accessed: stored.map(|(_, at)| at),
+        };
+        let pending = self
+            .pending_reads
+            .lock()
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        if let Some((reads, at)) = pending.get(&(collection.to_string(), id.clone())) {
+            stats.reads += reads;
+            stats.last_accessed = stats.last_accessed.max(Some(*at));
+        }
+        Ok(stats)
+    }
+}
+
+fn delete_access_stats(conn: &rusqlite::Connection, collection: &str, data_id: &str) -> StoreResult<()> {
+    conn.execute(
+        "DELETE FROM __access_stats WHERE collection = ?1 AND data_id = ?2",
+        params![collection, data_id],
+    )?;
+    Ok(())
+}
+
+// impl change log and replication related methods
+impl SqliteBackend {
+    /// Seq of the latest change, 0 when the change log is empty.
+    pub fn head_seq(&self) -> StoreResult<i64> {
+        let conn = self.read_conn()?;
+        Ok(conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM __changes", [], |r| r.get(0))?)
+    }
+
+    /// How many runs of `prune_tombstones` dropped tombstones, 0 before the first.
+    pub fn tombstone_generation(&self) -> StoreResult<i64> {
+        let conn = self.read_conn()?;
+        Ok(
+            conn.query_row("SELECT COALESCE(MAX(generation), 0) FROM __tombstone_prunes", [], |r| {
+                r.get(0)
+            })?,
+        )
+    }
+
+    /// Whether a prune after `generation` dropped a tombstone past `seq`, a feed resumed at `seq`
+    /// would miss that delete.
+    pub fn tombstones_pruned_after(&self, generation: i64, seq: i64) -> StoreResult<bool> {
+        let conn = self.read_conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM __tombstone_prunes WHERE generation > ?1 AND pruned_seq > ?2)",
+            params![generation, seq],
+            |r| r.get(0),
+        )?)
+    }
+
+    /// Drop the tombstones of the documents deleted before `before` along with the change log of
+    /// those documents, returns how many were dropped.
+    ///
+    /// A delete not yet pushed to every replication target or webhook is kept.
+    pub fn prune_tombstones(&self, before: chrono::DateTime<chrono::Utc>) -> StoreResult<usize> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let delivered: i64 = tx.query_row(
+            "SELECT COALESCE(MIN(last_seq), ?1) FROM __replication",
+            [i64::MAX],
+            |r| r.get(0),
+        )?;
+        let expired = {
+            let mut stmt = tx.prepare(
+                "SELECT collection, data_id, seq FROM __tombstones \
+                 WHERE julianday(deleted_at) < julianday(?1) AND seq <= ?2",
+            )?;
+            stmt.query_map(params![before.to_rfc3339(), delivered], |r| {
+                Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, i64>(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+        for (collection, data_id, seq) in &expired {
             tx.execute(
-                &sql,
-                params![
-                    acl_id,
-                    data_collection,
-                    data_id,
-                    p.user_id,
-                    permission_str,
-                    now.to_rfc3339(),
-                    now.to_rfc3339(),
-                    owner
-                ],
+                "DELETE FROM __changes WHERE collection = ?1 AND data_id = ?2 AND seq <= ?3",
+                params![collection, data_id, seq],
             )?;
+            tx.execute(
+                "DELETE FROM __tombstones WHERE collection = ?1 AND data_id = ?2",
+                params![collection, data_id],
+            )?;
+        }
+        // each prune is told apart, a cursor past all the tombstones one dropped missed none of them
+        if let Some(pruned) = expired.iter().map(|(_, _, seq)| *seq).max() {
+            tx.execute("INSERT INTO __tombstone_prunes (pruned_seq) VALUES (?1)", [pruned])?;
+        }
+        tx.commit()?;
+        Ok(expired.len())
+    }
+
+    /// Changes committed after `after_seq`, oldest first.
+    pub fn changes_since(&self, after_seq: i64, limit: usize) -> StoreResult<Vec<Change>> {
+        let conn = self.read_conn()?;
+        let sql = format!(
+            "SELECT {} FROM __changes WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+            CHANGE_COLUMNS
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query(params![after_seq, limit as i64])?;
+        read_changes(rows)
+    }
+
+    /// Like `changes_since`, only the changes of the documents directly under `parent_id`, and the
+    /// upserts moving a document away from it.
+    pub fn changes_under(&self, parent_id: &str, after_seq: i64, limit: usize) -> StoreResult<Vec<Change>> {
+        let conn = self.read_conn()?;
+        let sql = format!(
+            "SELECT {} FROM __changes WHERE (parent_id = ?1 OR prev_parent_id = ?1) AND seq > ?2 \
+             ORDER BY seq LIMIT ?3",
+            CHANGE_COLUMNS
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query(params![parent_id, after_seq, limit as i64])?;
+        read_changes(rows)
+    }
+
+    /// Changes of one document, newest first, `marker` is the seq of the last change of the previous page.
+    pub fn history(
+        &self,
+        collection: &str,
+        data_id: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<Change>, Option<String>)> {
+        let before_seq = match marker {
+            Some(m) => m
+                .parse::<i64>()
+                .map_err(|_| StoreError::Validation(format!("invalid marker: {}", m)))?,
+            None => i64::MAX,
+        };
+        let conn = self.read_conn()?;
+        let sql = format!(
+            "SELECT {} FROM __changes WHERE collection = ?1 AND data_id = ?2 AND seq < ?3 ORDER BY seq DESC LIMIT ?4",
+            CHANGE_COLUMNS
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query(params![collection, data_id, before_seq, limit as i64 + 1])?;
+        let mut changes = read_changes(rows)?;
+        // the log keeps the rows as stored, the document history is read like the document
+        for change in &mut changes {
+            if let ChangeOp::Upsert { item } = &mut change.op {
+                self.decrypt_body(&mut item.body)?;
+            }
+        }
+        let next_marker = if changes.len() > limit {
+            changes.truncate(limit);
+            changes.last().map(|c| c.seq.to_string())
+        } else {
+            None
+        };
+        Ok((changes, next_marker))
+    }
+
+    /// Read the document of an upsert change as `get_inlined` would, the log keeps the rows as stored.
+    pub fn decode_change(&self, change: &mut Change) -> StoreResult<()> {
+        if let ChangeOp::Upsert { item } = &mut change.op {
+            self.decrypt_body(&mut item.body)?;
+            self.inline_blobs(item)?;
+        }
+        Ok(())
+    }
+
+    /// Apply a change of another instance, applying the same change twice leaves the same state.
+    ///
+    /// No schema validation here, the change has been validated by the instance producing it.
+    pub fn apply_change(&self, change: &Change) -> StoreResult<()> {
+        if !self.schema_validator.contains_key(&change.collection) {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' does not exist",
+                change.collection
+            )));
+        }
+        let table = sanitize_table_name(&change.collection);
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        match &change.op {
+            ChangeOp::Upsert { item } => {
+                let sql = format!(
+                    "INSERT INTO {} (id, body, created_at, updated_at, owner, uniq, parent_id, content_hash, rev, schema_version) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10) \
+                     ON CONFLICT(id) DO UPDATE SET body = excluded.body, created_at = excluded.created_at, \
+                     updated_at = excluded.updated_at, owner = excluded.owner, uniq = excluded.uniq, parent_id = excluded.parent_id, \
+                     content_hash = excluded.content_hash, rev = excluded.rev, schema_version = excluded.schema_version",
+                    table
+                );
+                let body_text = serde_json::to_string(&item.body)?;
+                tx.execute(
+                    &sql,
+                    params![
+                        item.id,
+                        body_text,
+                        item.created_at.to_rfc3339(),
+                        item.updated_at.to_rfc3339(),
+                        item.owner,
+                        item.unique,
+                        item.parent_id,
+                        self.content_hash(&change.collection, &body_text)?,
+                        // the revision of the producing instance, changes logged before revisions have none
+                        item.rev.max(1),
+                        item.schema_version.max(1)
+                    ],
+                )
+                .map_err(map_write_error)?;
+                if self.soft_deletes(&change.collection) {
+                    // an upsert of a deleted document is its restore
+                    tx.execute(
+                        &format!("UPDATE {} SET deleted_at = NULL WHERE id = ?1", table),
+                        params![item.id],
+                    )?;
+                }
+            }
+            ChangeOp::Delete if self.soft_deletes(&change.collection) => {
+                tx.execute(
+                    &format!(
+                        "UPDATE {} SET deleted_at = ?1, rev = rev + 1 WHERE id = ?2 AND deleted_at IS NULL",
+                        table
+                    ),
+                    params![change.changed_at, change.data_id],
+                )?;
+            }
+            ChangeOp::Delete => {
+                tx.execute(&format!("DELETE FROM {} WHERE id = ?1", table), params![change.data_id])?;
+                delete_attachments(&tx, &change.collection, &change.data_id)?;
+                delete_access_stats(&tx, &change.collection, &change.data_id)?;
+            }
+            ChangeOp::Acl { owner, permissions } => {
+                tx.execute(
+                    "DELETE FROM __acls WHERE data_collection = ?1 AND data_id = ?2",
+                    params![change.collection, change.data_id],
+                )?;
+                let now = chrono::Utc::now().to_rfc3339();
+                for p in permissions {
+                    tx.execute(
+                        "INSERT INTO __acls (id, data_collection, data_id, user_id, permission, created_at, updated_at, owner) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                        params![
+                            uuid::Uuid::new_v4().to_string(),
+                            change.collection,
+                            change.data_id,
+                            p.user_id,
+                            p.access_level.to_string(),
+                            now,
+                            now,
+                            owner
+                        ],
+                    )?;
+                }
+            }
+        }
+        // keep a change log of our own, so the replica can be replicated further
+        record_change(&tx, &change.collection, &change.data_id, &change.op, &change.meta)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Seq of the latest change acknowledged by `target`, 0 when nothing was pushed yet.
+    pub fn replication_cursor(&self, target: &str) -> StoreResult<i64> {
+        let conn = self.read_conn()?;
+        let seq = conn
+            .query_row(
+                "SELECT last_seq FROM __replication WHERE target = ?1",
+                params![target],
+                |r| r.get(0),
+            )
+            .optional()?;
+        Ok(seq.unwrap_or(0))
+    }
+
+    pub fn set_replication_cursor(&self, target: &str, last_seq: i64) -> StoreResult<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO __replication (target, last_seq) VALUES (?1, ?2) \
+             ON CONFLICT(target) DO UPDATE SET last_seq = excluded.last_seq",
+            params![target, last_seq],
+        )?;
+        Ok(())
+    }
+}
+
+// impl webhook related methods, the deliveries are a queue fed from the change log
+impl SqliteBackend {
+    /// Register a webhook, it receives the changes made after this call.
+    pub fn add_webhook(&self, webhook: &Webhook) -> StoreResult<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        tx.execute(
+            "INSERT INTO __webhooks (id, url, secret, collections, created_at, events, filter_expr) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                webhook.id,
+                webhook.url,
+                webhook.secret.as_deref().unwrap_or_default(),
+                serde_json::to_string(&webhook.collections)?,
+                webhook.created_at.to_rfc3339(),
+                serde_json::to_string(&webhook.events)?,
+                webhook.filter.as_ref().map(serde_json::to_string).transpose()?
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO __replication (target, last_seq) VALUES (?1, (SELECT IFNULL(MAX(seq), 0) FROM __changes)) \
+             ON CONFLICT(target) DO UPDATE SET last_seq = excluded.last_seq",
+            params![webhook_cursor(&webhook.id)],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every webhook including its secret.
+    pub fn list_webhooks(&self) -> StoreResult<Vec<Webhook>> {
+        let conn = self.read_conn()?;
+        let mut stmt =
+            conn.prepare(
+            "SELECT id, url, secret, collections, created_at, events, filter_expr FROM __webhooks ORDER BY created_at, id",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, chrono::DateTime<chrono::Utc>>(4)?,
+                r.get::<_, String>(5)?,
+                r.get::<_, Option<String>>(6)?,
+            ))
+        })?;
+        let mut webhooks = Vec::new();
+        for row in rows {
+            let (id, url, secret, collections, created_at, events, filter) = row?;
+            webhooks.push(Webhook {
+                id,
+                url,
+                collections: serde_json::from_str(&collections)?,
+                events: serde_json::from_str(&events)?,
+                filter: filter.map(|f| serde_json::from_str(&f)).transpose()?,
+                created_at,
+                secret: Some(secret),
+            });
+        }
+        Ok(webhooks)
+    }
+
+    /// Whether the item passes the filter, evaluated by SQLite over the one row so that it compares
+    /// exactly as the filtered listings do.
+    pub fn item_matches(&self, item: &DataItem, filter: &FilterExpr) -> StoreResult<bool> {
+        let (condition, filter_params) = filter.compile()?;
+        let mut query_params = vec![
+            SqlValue::Text(item.id.clone()),
+            SqlValue::Text(serde_json::to_string(&item.body)?),
+            SqlValue::Text(item.owner.clone()),
+            item.parent_id.clone().map_or(SqlValue::Null, SqlValue::Text),
+            SqlValue::Text(item.created_at.to_rfc3339()),
+            SqlValue::Text(item.updated_at.to_rfc3339()),
+        ];
+        query_params.extend(filter_params);
+        let sql = format!(
+            "SELECT EXISTS (SELECT 1 FROM \
+             (SELECT ? AS id, ? AS body, ? AS owner, ? AS parent_id, ? AS created_at, ? AS updated_at) \
+             WHERE {})",
+            condition
+        );
+        let conn = self.read_conn()?;
+        Ok(conn.query_row(&sql, rusqlite::params_from_iter(query_params), |r| r.get(0))?)
+    }
+
+    /// Remove the webhook together with its queued and dead deliveries.
+    pub fn delete_webhook(&self, id: &str) -> StoreResult<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let deleted = tx.execute("DELETE FROM __webhooks WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(StoreError::NotFound(format!("Webhook {}", id)));
         }
+        tx.execute("DELETE FROM __webhook_deliveries WHERE webhook_id = ?1", params![id])?;
+        tx.execute(
+            "DELETE FROM __replication WHERE target = ?1",
+            params![webhook_cursor(id)],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Seq of the last change queued for the webhook.
+    pub fn webhook_cursor(&self, id: &str) -> StoreResult<i64> {
+        self.replication_cursor(&webhook_cursor(id))
+    }
 
+    /// Queue `(seq, payload)` deliveries and move the webhook cursor to `cursor` at once.
+    pub fn enqueue_webhook_deliveries(&self, id: &str, deliveries: &[(i64, String)], cursor: i64) -> StoreResult<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        for (seq, payload) in deliveries {
+            tx.execute(
+                "INSERT INTO __webhook_deliveries (webhook_id, seq, payload, attempts, next_attempt_at, dead, created_at) \
+                 VALUES (?1, ?2, ?3, 0, ?4, 0, ?4)",
+                params![id, seq, payload, now],
+            )?;
+        }
+        tx.execute(
+            "INSERT INTO __replication (target, last_seq) VALUES (?1, ?2) \
+             ON CONFLICT(target) DO UPDATE SET last_seq = excluded.last_seq",
+            params![webhook_cursor(id), cursor],
+        )?;
         tx.commit()?;
         Ok(())
     }
+
+    /// Live deliveries whose next attempt is due, oldest first.
+    pub fn due_webhook_deliveries(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> StoreResult<Vec<WebhookDelivery>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM __webhook_deliveries WHERE dead = 0 AND next_attempt_at <= ?1 ORDER BY id LIMIT ?2",
+            DELIVERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![now.to_rfc3339(), limit as i64], read_delivery)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    pub fn dead_webhook_deliveries(&self) -> StoreResult<Vec<WebhookDelivery>> {
+        let conn = self.read_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM __webhook_deliveries WHERE dead = 1 ORDER BY id",
+            DELIVERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], read_delivery)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Drop a delivered request from the queue.
+    pub fn complete_webhook_delivery(&self, id: i64) -> StoreResult<()> {
+        let conn = self.get_conn()?;
+        conn.execute("DELETE FROM __webhook_deliveries WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Record a failed attempt, retried at `next_attempt_at` or dead-lettered when none.
+    pub fn fail_webhook_delivery(
+        &self,
+        id: i64,
+        error: &str,
+        next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> StoreResult<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE __webhook_deliveries SET attempts = attempts + 1, last_error = ?2, \
+             next_attempt_at = IFNULL(?3, next_attempt_at), dead = ?4 WHERE id = ?1",
+            params![
+                id,
+                error,
+                next_attempt_at.map(|t| t.to_rfc3339()),
+                next_attempt_at.is_none()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Put a dead delivery back in the queue with a fresh attempt budget.
+    pub fn replay_webhook_delivery(&self, id: i64) -> StoreResult<WebhookDelivery> {
+        let conn = self.get_conn()?;
+        let updated = conn.execute(
+            "UPDATE __webhook_deliveries SET attempts = 0, dead = 0, next_attempt_at = ?2 WHERE id = ?1 AND dead = 1",
+            params![id, chrono::Utc::now().to_rfc3339()],
+        )?;
+        if updated == 0 {
+            return Err(StoreError::NotFound(format!("Dead webhook delivery {}", id)));
+        }
+        Ok(conn.query_row(
+            &format!("SELECT {} FROM __webhook_deliveries WHERE id = ?1", DELIVERY_COLUMNS),
+            params![id],
+            read_delivery,
+        )?)
+    }
+}
+
+// webhook cursors share the replication cursor table
+fn webhook_cursor(id: &str) -> String {
+    format!("webhook:{}", id)
+}
+
+const DELIVERY_COLUMNS: &str = "id, webhook_id, seq, payload, attempts, next_attempt_at, last_error, dead, created_at";
+
+fn read_delivery(r: &rusqlite::Row<'_>) -> rusqlite::Result<WebhookDelivery> {
+    Ok(WebhookDelivery {
+        id: r.get(0)?,
+        webhook_id: r.get(1)?,
+        seq: r.get(2)?,
+        payload: r.get(3)?,
+        attempts: r.get(4)?,
+        next_attempt_at: r.get(5)?,
+        last_error: r.get(6)?,
+        dead: r.get(7)?,
+        created_at: r.get(8)?,
+    })
+}
+
+// `rev` are the revision and schema version columns, see `SqliteBackend::rev_columns`
+fn read_item(conn: &rusqlite::Connection, collection: &str, id: &str, rev: &str) -> StoreResult<Option<DataItem>> {
+    let table = sanitize_table_name(collection);
+    let sql = format!(
+        "SELECT body, created_at, updated_at, owner, uniq, parent_id, {} FROM {} WHERE id = ?1",
+        rev, table
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let data = stmt
+        .query_row(params![id], |r| {
+            Ok(DataItemDocument {
+                id: id.to_string(),
+                body: r.get(0)?,
+                created_at: r.get(1)?,
+                updated_at: r.get(2)?,
+                owner: r.get(3)?,
+                unique: r.get(4)?,
+                parent_id: r.get(5)?,
+                rev: r.get(6)?,
+                schema_version: r.get(7)?,
+            })
+        })
+        .optional()?;
+    data.map(DataItem::try_from).transpose()
+}
+
+fn read_data_permissions(
+    conn: &rusqlite::Connection,
+    data_collection: &str,
+    data_id: &str,
+) -> StoreResult<Vec<PermissionSchema>> {
+    let sql = "SELECT user_id, permission FROM __acls WHERE data_collection = ?1 AND data_id = ?2".to_string();
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(params![data_collection, data_id])?;
+    let mut permissions = Vec::new();
+    while let Some(row) = rows.next()? {
+        let user_id: String = row.get(0)?;
+        let permission_str: String = row.get(1)?;
+        let access_level = AccessLevel::from_str(&permission_str)?;
+        permissions.push(PermissionSchema {
+            data_id: data_id.to_string(),
+            user_id,
+            access_level,
+        });
+    }
+    Ok(permissions)
+}
+
+const CHANGE_COLUMNS: &str = "seq, collection, data_id, payload, changed_at, actor, reason";
+
+fn read_changes(mut rows: rusqlite::Rows<'_>) -> StoreResult<Vec<Change>> {
+    let mut changes = Vec::new();
+    while let Some(row) = rows.next()? {
+        let payload: String = row.get(3)?;
+        changes.push(Change {
+            seq: row.get(0)?,
+            collection: row.get(1)?,
+            data_id: row.get(2)?,
+            op: serde_json::from_str(&payload)?,
+            changed_at: row.get(4)?,
+            meta: ChangeMeta {
+                actor: row.get(5)?,
+                reason: row.get(6)?,
+            },
+        });
+    }
+    Ok(changes)
+}
+
+fn record_change(
+    conn: &rusqlite::Connection,
+    collection: &str,
+    data_id: &str,
+    op: &ChangeOp,
+    meta: &ChangeMeta,
+) -> StoreResult<()> {
+    let now = chrono::Utc::now().to_rfc3339();
+    // the row may be gone already, the document is under the parent of its last upsert
+    let last_parent_id: Option<String> = conn
+        .query_row(
+            "SELECT parent_id FROM __changes WHERE collection = ?1 AND data_id = ?2 AND op = 'upsert' \
+             ORDER BY seq DESC LIMIT 1",
+            params![collection, data_id],
+            |r| r.get(0),
+        )
+        .optional()?
+        .flatten();
+    let (parent_id, prev_parent_id) = match op {
+        // a move is logged under the parent left as well, its followers see the document go
+        ChangeOp::Upsert { item } if item.parent_id != last_parent_id => (item.parent_id.clone(), last_parent_id),
+        ChangeOp::Upsert { item } => (item.parent_id.clone(), None),
+        ChangeOp::Delete | ChangeOp::Acl { .. } => (last_parent_id, None),
+    };
+    conn.execute(
+        "INSERT INTO __changes (collection, data_id, op, payload, changed_at, actor, reason, parent_id, \
+         prev_parent_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            collection,
+            data_id,
+            op.name(),
+            serde_json::to_string(op)?,
+            now,
+            meta.actor,
+            meta.reason,
+            parent_id,
+            prev_parent_id
+        ],
+    )?;
+    // a delete leaves a tombstone until `prune_tombstones`, an id stored again clears it
+    match op {
+        ChangeOp::Delete => conn.execute(
+            "INSERT INTO __tombstones (collection, data_id, seq, deleted_at, deleted_by) \
+             VALUES (?1, ?2, last_insert_rowid(), ?3, ?4) \
+             ON CONFLICT(collection, data_id) DO UPDATE SET \
+             seq = excluded.seq, deleted_at = excluded.deleted_at, deleted_by = excluded.deleted_by",
+            params![collection, data_id, now, meta.actor],
+        )?,
+        ChangeOp::Upsert { .. } => conn.execute(
+            "DELETE FROM __tombstones WHERE collection = ?1 AND data_id = ?2",
+            params![collection, data_id],
+        )?,
+        ChangeOp::Acl { .. } => 0,
+    };
+    Ok(())
+}
+
+// read back the written row and log it, returns the row
+fn record_upsert(conn: &rusqlite::Connection, collection: &str, id: &str, meta: &ChangeMeta) -> StoreResult<DataItem> {
+    // only writable tables are written, they all have the column
+    let item = read_item(conn, collection, id, "rev, schema_version")?
+        .ok_or_else(|| StoreError::NotFound(format!("Get Data {} / {}", collection, id)))?;
+    record_change(conn, collection, id, &ChangeOp::Upsert { item: item.clone() }, meta)?;
+    Ok(item)
 }
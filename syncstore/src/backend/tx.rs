@@ -0,0 +1,261 @@
+//! Request scoped transactions.
+//!
+//! While a [`TxScope`] is entered, the backends run every statement of the current task on one
+//! connection per database, opened with `BEGIN` on first use and kept until the scope is committed
+//! or rolled back. Their own transactions become savepoints inside it, so several `Store` calls
+//! succeed or fail as a whole. Each database commits on its own, a scope spanning two namespaces
+//! is not atomic across them.
+//!
+//! A [`TxScope::read_only`] scope is a read snapshot: its connections refuse writes, and the read
+//! transaction pins what they see at their first statement until the scope ends. In WAL mode the
+//! writers carry on meanwhile, see [`read_conn`] for the databases with read-only connections.
+
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RawMutex, lock_api::ArcMutexGuard};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::{SqliteConnectionManager, rusqlite};
+
+use crate::backend::deadline;
+use crate::error::StoreResult;
+
+type Pooled = PooledConnection<SqliteConnectionManager>;
+
+tokio::task_local! {
+    static CURRENT: Arc<TxScope>;
+}
+
+/// Transaction shared by the backend operations run while the scope is entered.
+#[derive(Default)]
+pub struct TxScope {
+    // dict<pool address, (connection inside the open transaction, made query-only by the scope)>
+    conns: Mutex<HashMap<usize, (Arc<Mutex<Pooled>>, bool)>>,
+    read_only: bool,
+}
+
+impl TxScope {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// A scope whose connections refuse writes, see the module doc.
+    pub fn read_only() -> Arc<Self> {
+        Arc::new(Self {
+            read_only: true,
+            ..Self::default()
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Run `fut` with the scope entered, the operations awaited in it join the transaction.
+    pub async fn enter<F: Future>(self: &Arc<Self>, fut: F) -> F::Output {
+        CURRENT.scope(self.clone(), fut).await
+    }
+
+    /// Like `enter`, for synchronous callers.
+    pub fn enter_sync<R>(self: &Arc<Self>, f: impl FnOnce() -> R) -> R {
+        CURRENT.sync_scope(self.clone(), f)
+    }
+
+    /// The scope entered by the current task, if any.
+    pub fn current() -> Option<Arc<Self>> {
+        CURRENT.try_with(|scope| scope.clone()).ok()
+    }
+
+    /// Commit every joined database, the ones left after a failed commit are rolled back.
+    pub fn commit(&self) -> StoreResult<()> {
+        let mut result = Ok(());
+        for (conn, made_query_only) in self.take() {
+            let conn = conn.lock();
+            if result.is_ok() {
+                result = conn.execute_batch("COMMIT");
+            }
+            if result.is_err() {
+                // a failed COMMIT may leave the transaction open, never hand it back to the pool
+                let _ = conn.execute_batch("ROLLBACK");
+            }
+            release(&conn, made_query_only);
+        }
+        Ok(result?)
+    }
+
+    /// Roll back every joined database.
+    pub fn rollback(&self) {
+        for (conn, made_query_only) in self.take() {
+            let conn = conn.lock();
+            if let Err(e) = conn.execute_batch("ROLLBACK") {
+                tracing::warn!("failed to roll back request transaction: {}", e);
+            }
+            release(&conn, made_query_only);
+        }
+    }
+
+    fn take(&self) -> Vec<(Arc<Mutex<Pooled>>, bool)> {
+        self.conns.lock().drain().map(|(_, joined)| joined).collect()
+    }
+
+    fn join(&self, pool: &Arc<Pool<SqliteConnectionManager>>) -> StoreResult<Option<ArcMutexGuard<RawMutex, Pooled>>> {
+        let key = Arc::as_ptr(pool) as usize;
+        let mut conns = self.conns.lock();
+        let conn = match conns.get(&key) {
+            Some((conn, _)) => conn.clone(),
+            None => {
+                let conn = pool.get()?;
+                // the connections of a read-only pool are query-only already, and stay so
+                let made_query_only =
+                    self.read_only && !conn.query_row("PRAGMA query_only", [], |r| r.get::<_, bool>(0))?;
+                if made_query_only {
+                    conn.execute_batch("PRAGMA query_only = ON")?;
+                }
+                if let Err(e) = conn.execute_batch("BEGIN") {
+                    release(&conn, made_query_only);
+                    return Err(e.into());
+                }
+                let conn = Arc::new(Mutex::new(conn));
+                conns.insert(key, (conn.clone(), made_query_only));
+                conn
+            }
+        };
+        // held by an outer call of the same operation, the nested call reads outside the transaction
+        Ok(conn.try_lock_arc())
+    }
+
+    // the connection of the transaction begun on this pool, none before the scope used it
+    fn joined(&self, pool: &Arc<Pool<SqliteConnectionManager>>) -> Option<ArcMutexGuard<RawMutex, Pooled>> {
+        let key = Arc::as_ptr(pool) as usize;
+        self.conns.lock().get(&key).and_then(|(conn, _)| conn.try_lock_arc())
+    }
+}
+
+impl Drop for TxScope {
+    // neither committed nor rolled back, e.g. the handler panicked
+    fn drop(&mut self) {
+        self.rollback();
+    }
+}
+
+// the connection goes back to the pool, writable again
+fn release(conn: &Pooled, made_query_only: bool) {
+    if made_query_only && let Err(e) = conn.execute_batch("PRAGMA query_only = OFF") {
+        tracing::warn!("failed to make a snapshot connection writable again: {}", e);
+    }
+}
+
+/// Connection of the entered scope for this pool, or a plain pooled one outside a scope.
+pub(crate) fn get_conn(pool: &Arc<Pool<SqliteConnectionManager>>) -> StoreResult<Conn> {
+    let conn = match CURRENT.try_with(|scope| scope.join(pool)) {
+        Ok(joined) => match joined? {
+            Some(conn) => Conn::Scoped(conn),
+            None => Conn::Pooled(pool.get()?),
+        },
+        Err(_) => Conn::Pooled(pool.get()?),
+    };
+    deadline::arm(&conn);
+    Ok(conn)
+}
+
+/// Connection for reads of the database writing through `pool`.
+///
+/// With `readers` the reads outside a transaction, and the nested ones beside it, take a read-only
+/// connection rather than waiting for the writer. In a transaction they only join it once it wrote
+/// to the database, so a request that merely reads never holds the writer. A read snapshot joins
+/// one of `readers`, it never holds the writer for as long as it is open.
+pub(crate) fn read_conn(
+    pool: &Arc<Pool<SqliteConnectionManager>>,
+    readers: Option<&Arc<Pool<SqliteConnectionManager>>>,
+) -> StoreResult<Conn> {
+    let Some(readers) = readers else {
+        return get_conn(pool);
+    };
+    let conn = match CURRENT.try_with(|scope| scope.clone()) {
+        Ok(scope) => {
+            let joined = match scope.read_only {
+                true => scope.join(readers)?,
+                false => scope.joined(pool),
+            };
+            match joined {
+                Some(conn) => Conn::Scoped(conn),
+                None => Conn::Pooled(readers.get()?),
+            }
+        }
+        Err(_) => Conn::Pooled(readers.get()?),
+    };
+    deadline::arm(&conn);
+    Ok(conn)
+}
+
+pub(crate) enum Conn {
+    Pooled(Pooled),
+    Scoped(ArcMutexGuard<RawMutex, Pooled>),
+}
+
+impl Conn {
+    pub fn transaction(&mut self) -> rusqlite::Result<Tx<'_>> {
+        self.transaction_with_behavior(rusqlite::TransactionBehavior::Deferred)
+    }
+
+    pub fn transaction_with_behavior(&mut self, behavior: rusqlite::TransactionBehavior) -> rusqlite::Result<Tx<'_>> {
+        match self {
+            Conn::Pooled(conn) => conn.transaction_with_behavior(behavior).map(Tx::Top),
+            // the scope already holds the transaction
+            Conn::Scoped(conn) => conn.savepoint().map(Tx::Nested),
+        }
+    }
+}
+
+impl Drop for Conn {
+    fn drop(&mut self) {
+        deadline::disarm(self);
+    }
+}
+
+impl Deref for Conn {
+    type Target = rusqlite::Connection;
+
+    fn deref(&self) -> &rusqlite::Connection {
+        match self {
+            Conn::Pooled(conn) => conn,
+            Conn::Scoped(conn) => conn,
+        }
+    }
+}
+
+impl DerefMut for Conn {
+    fn deref_mut(&mut self) -> &mut rusqlite::Connection {
+        match self {
+            Conn::Pooled(conn) => conn,
+            Conn::Scoped(conn) => conn,
+        }
+    }
+}
+
+/// A transaction of its own, or a savepoint inside the scope's transaction.
+pub(crate) enum Tx<'a> {
+    Top(rusqlite::Transaction<'a>),
+    Nested(rusqlite::Savepoint<'a>),
+}
+
+impl Tx<'_> {
+    pub fn commit(self) -> rusqlite::Result<()> {
+        match self {
+            Tx::Top(tx) => tx.commit(),
+            Tx::Nested(sp) => sp.commit(),
+        }
+    }
+}
+
+impl Deref for Tx<'_> {
+    type Target = rusqlite::Connection;
+
+    fn deref(&self) -> &rusqlite::Connection {
+        match self {
+            Tx::Top(tx) => tx,
+            Tx::Nested(sp) => sp,
+        }
+    }
+}
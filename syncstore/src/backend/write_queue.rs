@@ -0,0 +1,261 @@
+//! Admission of the writes to the database writer, interactive ones ahead of the batch traffic.
+//!
+//! SQLite has a single writer, a sync push or bulk import holding it makes every interactive write
+//! behind it wait. Writes take a [`WritePermit`] first: at most `concurrency` of them run at a time
+//! and a freed slot goes to a waiting interactive write, unless `interactive_burst` of those were
+//! let in a row while a batch write waited. Batch traffic is slowed down, never starved.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::error::{StoreError, StoreResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteTier {
+    /// writes of a user waiting for them
+    Interactive,
+    /// sync pushes, bulk imports and other writes of many items
+    Batch,
+}
+
+pub struct WriteQueue {
+    state: Arc<Mutex<QueueState>>,
+}
+
+struct QueueState {
+    concurrency: usize,
+    interactive_burst: usize,
+    running: usize,
+    // interactive writes let in since the last batch one, counted while batch writes wait
+    streak: usize,
+    interactive: VecDeque<oneshot::Sender<WritePermit>>,
+    batch: VecDeque<oneshot::Sender<WritePermit>>,
+    granted: [u64; 2],
+}
+
+/// Queue depth and admissions of the write queue.
+#[derive(Debug, Clone, Serialize)]
+pub struct WriteQueueStats {
+    pub running: usize,
+    pub interactive_waiting: usize,
+    pub batch_waiting: usize,
+    pub interactive_granted: u64,
+    pub batch_granted: u64,
+}
+
+/// A slot of the writer, given back when dropped.
+pub struct WritePermit {
+    // none once given back
+    state: Option<Arc<Mutex<QueueState>>>,
+}
+
+impl WriteQueue {
+    pub fn new(concurrency: usize, interactive_burst: usize) -> StoreResult<Self> {
+        check_limits(concurrency, interactive_burst)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(QueueState {
+                concurrency,
+                interactive_burst,
+                running: 0,
+                streak: 0,
+                interactive: VecDeque::new(),
+                batch: VecDeque::new(),
+                granted: [0; 2],
+            })),
+        })
+    }
+
+    /// Change the limits, the writes already running keep their slot.
+    pub fn set_limits(&self, concurrency: usize, interactive_burst: usize) -> StoreResult<()> {
+        check_limits(concurrency, interactive_burst)?;
+        let grants = {
+            let mut state = self.state.lock();
+            state.concurrency = concurrency;
+            state.interactive_burst = interactive_burst;
+            state.take_grants()
+        };
+        self.send(grants);
+        Ok(())
+    }
+
+    /// Wait for a slot of the writer, behind the writes of the same tier queued before.
+    pub async fn acquire(&self, tier: WriteTier) -> StoreResult<WritePermit> {
+        let waiting = {
+            let mut state = self.state.lock();
+            if state.running < state.concurrency && state.interactive.is_empty() && state.batch.is_empty() {
+                state.grant(tier);
+                return Ok(self.permit());
+            }
+            let (tx, rx) = oneshot::channel();
+            match tier {
+                WriteTier::Interactive => state.interactive.push_back(tx),
+                WriteTier::Batch => state.batch.push_back(tx),
+            }
+            rx
+        };
+        waiting
+            .await
+            .map_err(|_| StoreError::Unavailable("write queue closed".to_string()))
+    }
+
+    pub fn stats(&self) -> WriteQueueStats {
+        let state = self.state.lock();
+        // waiters who gave up stay queued until their turn comes
+        let waiting =
+            |queue: &VecDeque<oneshot::Sender<WritePermit>>| queue.iter().filter(|tx| !tx.is_closed()).count();
+        WriteQueueStats {
+            running: state.running,
+            interactive_waiting: waiting(&state.interactive),
+            batch_waiting: waiting(&state.batch),
+            interactive_granted: state.granted[0],
+            batch_granted: state.granted[1],
+        }
+    }
+
+    fn permit(&self) -> WritePermit {
+        WritePermit {
+            state: Some(self.state.clone()),
+        }
+    }
+
+    // outside the lock, a waiter who gave up drops its permit right away and gives the slot back
+    fn send(&self, grants: Vec<oneshot::Sender<WritePermit>>) {
+        for tx in grants {
+            let _ = tx.send(self.permit());
+        }
+    }
+}
+
+impl QueueState {
+    fn grant(&mut self, tier: WriteTier) {
+        self.running += 1;
+        match tier {
+            WriteTier::Interactive => {
+                self.granted[0] += 1;
+                self.streak = if self.batch.is_empty() { 0 } else { self.streak + 1 };
+            }
+            WriteTier::Batch => {
+                self.granted[1] += 1;
+                self.streak = 0;
+            }
+        }
+    }
+
+    // the waiters let in on the free slots, in the order of the fairness rule
+    fn take_grants(&mut self) -> Vec<oneshot::Sender<WritePermit>> {
+        let mut grants = Vec::new();
+        while self.running < self.concurrency {
+            let batch_turn = self.interactive.is_empty() || self.streak >= self.interactive_burst;
+            let (tier, tx) = match (batch_turn, self.batch.pop_front()) {
+                (true, Some(tx)) => (WriteTier::Batch, tx),
+                (_, batch) => {
+                    if let Some(tx) = batch {
+                        self.batch.push_front(tx);
+                    }
+                    match self.interactive.pop_front() {
+                        Some(tx) => (WriteTier::Interactive, tx),
+                        None => break,
+                    }
+                }
+            };
+            self.grant(tier);
+            grants.push(tx);
+        }
+        grants
+    }
+}
+
+impl Drop for WritePermit {
+    fn drop(&mut self) {
+        let Some(state) = self.state.take() else {
+            return;
+        };
+        let queue = WriteQueue { state };
+        let grants = {
+            let mut state = queue.state.lock();
+            state.running -= 1;
+            state.take_grants()
+        };
+        queue.send(grants);
+    }
+}
+
+fn check_limits(concurrency: usize, interactive_burst: usize) -> StoreResult<()> {
+    if concurrency == 0 || interactive_burst == 0 {
+        return Err(StoreError::Validation(
+            "write queue concurrency and interactive burst must be positive".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_interactive_ahead_of_batch() {
+        let queue = WriteQueue::new(1, 2).unwrap();
+        let running = queue.acquire(WriteTier::Batch).await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let queue = Arc::new(queue);
+        let mut waiters = Vec::new();
+        for (i, tier) in [
+            WriteTier::Batch,
+            WriteTier::Interactive,
+            WriteTier::Interactive,
+            WriteTier::Interactive,
+            WriteTier::Batch,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let (queue, order) = (queue.clone(), order.clone());
+            waiters.push(tokio::spawn(async move {
+                let _permit = queue.acquire(tier).await.unwrap();
+                order.lock().push(i);
+            }));
+            // queued in this order
+            tokio::task::yield_now().await;
+        }
+        let stats = queue.stats();
+        assert_eq!(
+            (stats.running, stats.interactive_waiting, stats.batch_waiting),
+            (1, 3, 2)
+        );
+
+        drop(running);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        // two interactive writes in a row, then the oldest batch one gets its turn
+        assert_eq!(*order.lock(), vec![1, 2, 0, 3, 4]);
+        let stats = queue.stats();
+        assert_eq!(
+            (stats.running, stats.interactive_granted, stats.batch_granted),
+            (0, 3, 3)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_waiter_gives_slot_back() {
+        let queue = WriteQueue::new(1, 1).unwrap();
+        let running = queue.acquire(WriteTier::Interactive).await.unwrap();
+        let abandoned = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            queue.acquire(WriteTier::Interactive),
+        )
+        .await;
+        assert!(abandoned.is_err());
+        assert_eq!(queue.stats().interactive_waiting, 0);
+
+        drop(running);
+        let _permit = queue.acquire(WriteTier::Batch).await.unwrap();
+        assert_eq!(queue.stats().running, 1);
+    }
+}
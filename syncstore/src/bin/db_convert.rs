@@ -1,20 +1,44 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+};
 
 use base64::Engine;
+use chrono::{DateTime, TimeZone, Utc};
 use rusqlite::{Connection, OpenFlags};
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{Value, json};
 use syncstore::{
     backend::Backend,
     components::DataSchemasBuilder,
     error::StoreError,
+    store::Store,
+    types::{ImportMode, Imported},
     utils::constant::{ROOT_OWNER, USER_TABLE},
 };
 
 fn main() -> anyhow::Result<()> {
     let args = std::env::args().collect::<Vec<String>>();
-    if args.len() < 2 {
+    if args.get(1).is_some_and(|a| a == "mongo_import") {
+        if args.len() < 4 {
+            eprintln!("Usage: db_convert mongo_import <convert.toml> <mongoexport dir>");
+            std::process::exit(1);
+        }
+        return mongo_import(&args[2], &args[3]);
+    }
+    if args.get(1).is_some_and(|a| a == "firestore_import") {
+        if args.len() < 4 {
+            eprintln!("Usage: db_convert firestore_import <convert.toml> <export.json>");
+            std::process::exit(1);
+        }
+        return firestore_import(&args[2], &args[3]);
+    }
+    if args.len() < 3 {
         eprintln!("Usage: db_convert <convert.toml> <source.db>");
+        eprintln!("       db_convert mongo_import <convert.toml> <mongoexport dir>");
+        eprintln!("       db_convert firestore_import <convert.toml> <export.json>");
         std::process::exit(1);
     }
 
@@ -49,22 +73,7 @@ fn main() -> anyhow::Result<()> {
         println!("Found columns: {:?}", col_names);
     }
 
-    // init target store
-    let mut schemas = DataSchemasBuilder::new();
-    for (collection, schema_str) in config
-        .data_mappings
-        .iter()
-        .map(|m| (&m.target_collection, &m.target_schema))
-    {
-        let schema_json: serde_json::Value = serde_json::from_str(schema_str)?;
-        schemas = schemas.add_schema(collection, schema_json);
-    }
-    let schemas = schemas.build();
-
-    let store = syncstore::store::Store::build(
-        &config.general.target_db_path,
-        vec![(&config.general.namespace, schemas)],
-    )?;
+    let store = build_target_store(&config)?;
 
     // user import
     if let Some(user_table) = config.user_mapping.map(|u| u.source_table) {
@@ -87,20 +96,7 @@ fn main() -> anyhow::Result<()> {
 
             println!("Imported user: {}", &id);
 
-            let user_backend = store.get_user_backend();
-            match user_backend.import(USER_TABLE, &body, ROOT_OWNER.to_string(), id, created_at, updated_at) {
-                Ok(_id) => (),
-                Err(ref e @ StoreError::Validation(ref err)) => {
-                    if err.clone().to_ascii_lowercase().contains("unique constraint failed") {
-                        println!(" [SKIP] User {} already exists, skipping.", username);
-                    } else {
-                        return Err(anyhow::anyhow!("Failed to insert user {}: {}", username, e));
-                    }
-                }
-                Err(e) => {
-                    return Err(anyhow::anyhow!("Failed to insert user {}: {}", username, e));
-                }
-            }
+            import_user(&store, &username, body, id, created_at, updated_at)?;
         }
     };
 
@@ -163,46 +159,407 @@ fn main() -> anyhow::Result<()> {
                 &id, &mapping.target_collection
             );
 
-            let data_backend = store.get_data_backend(&config.general.namespace)?;
-            match data_backend.import(
-                &mapping.target_collection,
-                &body,
-                owner.clone(),
-                id.clone(),
+            import_data(
+                &store,
+                &config.general.namespace,
+                mapping,
+                body,
+                owner,
+                id,
                 created_at,
                 updated_at,
-            ) {
-                Ok(_) => (),
-                Err(ref e @ StoreError::Validation(ref err)) => {
-                    if err.clone().to_ascii_lowercase().contains("unique constraint failed") {
-                        println!(
-                            " [SKIP] Data item {} in collection {} already exists, skipping.",
-                            id, &mapping.target_collection
-                        );
-                    } else {
-                        return Err(anyhow::anyhow!(
-                            "Failed to insert data item {} into collection {}: {}",
-                            id,
-                            &mapping.target_collection,
-                            e
-                        ));
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn build_target_store(config: &MappingConfig) -> anyhow::Result<Arc<Store>> {
+    let mut schemas = DataSchemasBuilder::new();
+    for (collection, schema_str) in config
+        .data_mappings
+        .iter()
+        .map(|m| (&m.target_collection, &m.target_schema))
+    {
+        let schema_json: serde_json::Value = serde_json::from_str(schema_str)?;
+        schemas = schemas.add_schema(collection, schema_json);
+    }
+    let schemas = schemas.build();
+
+    Ok(Store::build(
+        &config.general.target_db_path,
+        vec![(&config.general.namespace, schemas)],
+    )?)
+}
+
+// existing users are skipped, so the conversion can be re-run
+fn import_user(
+    store: &Store,
+    username: &str,
+    body: Value,
+    id: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let user_backend = store.get_user_backend();
+    match user_backend.import(USER_TABLE, &body, ROOT_OWNER.to_string(), id, created_at, updated_at) {
+        Ok(_id) => Ok(()),
+        Err(StoreError::Validation(ref err)) if err.to_ascii_lowercase().contains("unique constraint failed") => {
+            println!(" [SKIP] User {} already exists, skipping.", username);
+            Ok(())
+        }
+        Err(e) => Err(anyhow::anyhow!("Failed to insert user {}: {}", username, e)),
+    }
+}
+
+// existing data items are skipped, so the conversion can be re-run
+#[allow(clippy::too_many_arguments)]
+fn import_data(
+    store: &Store,
+    namespace: &str,
+    mapping: &DataMapping,
+    body: Value,
+    owner: String,
+    id: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let data_backend = store.get_data_backend(namespace)?;
+    // re-running a conversion, or a source holding the same record twice, imports the content once
+    match data_backend.import_with_mode(
+        &mapping.target_collection,
+        &body,
+        owner,
+        id.clone(),
+        created_at,
+        updated_at,
+        ImportMode::SkipDuplicates,
+    ) {
+        Ok(Imported::Inserted(_)) => Ok(()),
+        Ok(Imported::Duplicate(existing)) => {
+            println!(
+                " [SKIP] Data item {} in collection {} duplicates {}, skipping.",
+                id, &mapping.target_collection, existing
+            );
+            Ok(())
+        }
+        Err(StoreError::Validation(ref err)) if err.to_ascii_lowercase().contains("unique constraint failed") => {
+            println!(
+                " [SKIP] Data item {} in collection {} already exists, skipping.",
+                id, &mapping.target_collection
+            );
+            Ok(())
+        }
+        Err(e) => Err(anyhow::anyhow!(
+            "Failed to insert data item {} into collection {}: {}",
+            id,
+            &mapping.target_collection,
+            e
+        )),
+    }
+}
+
+/// Import mongoexport JSONL dumps, one `<source_table>.json` file per mapping in `dump_dir`.
+///
+/// Extended JSON values (`$oid`, `$date`, `$numberLong`, ...) are converted to plain JSON,
+/// `_id` becomes the item id and `createdAt`/`updatedAt` (or the configured fields) the meta timestamps.
+fn mongo_import(config_path: &str, dump_dir: &str) -> anyhow::Result<()> {
+    let config: MappingConfig = toml::from_str(&std::fs::read_to_string(config_path)?)?;
+    println!("Loaded mapping config: {:#?}", config);
+    let dump_dir = Path::new(dump_dir);
+    let store = build_target_store(&config)?;
+
+    if let Some(user_mapping) = &config.user_mapping {
+        let docs = read_mongo_export(&dump_dir.join(format!("{}.json", user_mapping.source_table)))?;
+        let docs = docs
+            .into_iter()
+            .map(|doc| Ok((mongo_id(&doc)?, doc)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        import_user_documents(&store, docs)?;
+    }
+
+    for mapping in config.data_mappings.iter() {
+        println!(
+            "--------------\nImporting mongo collection: {} to collection: {}",
+            &mapping.source_table, &mapping.target_collection
+        );
+        let docs = read_mongo_export(&dump_dir.join(format!("{}.json", mapping.source_table)))?;
+        let docs = docs
+            .into_iter()
+            .map(|doc| Ok((mongo_id(&doc)?, doc, None)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        import_documents(&store, &config.general.namespace, mapping, docs)?;
+    }
+
+    Ok(())
+}
+
+/// Import a Firestore JSON export, as written by the common `firestore-export` tools:
+/// ```json
+/// { "repos": { "<doc id>": { "name": "...", "__collections__": { "posts": { "<doc id>": { ... } } } } } }
+/// ```
+/// Mappings address (sub)collections by their path without document ids, e.g. `repos/posts`,
+/// and `parent_field` receives the id of the parent document so x-parent-id relations are kept.
+fn firestore_import(config_path: &str, export_path: &str) -> anyhow::Result<()> {
+    let config: MappingConfig = toml::from_str(&std::fs::read_to_string(config_path)?)?;
+    println!("Loaded mapping config: {:#?}", config);
+    let export: Value = serde_json::from_str(&std::fs::read_to_string(export_path)?)?;
+    let Value::Object(root) = from_firestore_json(export) else {
+        return Err(anyhow::anyhow!(
+            "firestore export root must be an object of collections"
+        ));
+    };
+    let store = build_target_store(&config)?;
+
+    // dict<collection path, [(doc id, doc, parent doc id)]>
+    let mut collections = HashMap::new();
+    collect_firestore_documents(&root, "", None, &mut collections);
+    println!(
+        "Found firestore collections: {:?}",
+        collections.keys().collect::<Vec<_>>()
+    );
+
+    if let Some(user_mapping) = &config.user_mapping {
+        let docs = collections.remove(&user_mapping.source_table).unwrap_or_default();
+        import_user_documents(&store, docs.into_iter().map(|(id, doc, _)| (id, doc)).collect())?;
+    }
+
+    for mapping in config.data_mappings.iter() {
+        println!(
+            "--------------\nImporting firestore collection: {} to collection: {}",
+            &mapping.source_table, &mapping.target_collection
+        );
+        let docs = collections.remove(&mapping.source_table).unwrap_or_default();
+        import_documents(&store, &config.general.namespace, mapping, docs)?;
+    }
+
+    Ok(())
+}
+
+type ExportDocument = (String, Value, Option<String>);
+
+fn collect_firestore_documents(
+    collections: &serde_json::Map<String, Value>,
+    prefix: &str,
+    parent_id: Option<&str>,
+    out: &mut HashMap<String, Vec<ExportDocument>>,
+) {
+    for (name, docs) in collections {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let Value::Object(docs) = docs else {
+            continue;
+        };
+        for (id, doc) in docs {
+            let mut doc = doc.clone();
+            if let Some(Value::Object(sub)) = doc.as_object_mut().and_then(|d| d.remove("__collections__")) {
+                collect_firestore_documents(&sub, &path, Some(id), out);
+            }
+            out.entry(path.clone())
+                .or_default()
+                .push((id.clone(), doc, parent_id.map(str::to_string)));
+        }
+    }
+}
+
+/// Convert the typed values of the Firestore export into plain JSON values.
+fn from_firestore_json(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            if let Some(datatype) = map.get("__datatype__").and_then(Value::as_str)
+                && let Some(inner) = map.get("value")
+            {
+                match datatype {
+                    "timestamp" => {
+                        let seconds = inner.get("_seconds").or(inner.get("seconds")).and_then(Value::as_i64);
+                        let nanos = inner
+                            .get("_nanoseconds")
+                            .or(inner.get("nanoseconds"))
+                            .and_then(Value::as_u64)
+                            .unwrap_or(0);
+                        if let Some(date) = seconds.and_then(|s| Utc.timestamp_opt(s, nanos as u32).single()) {
+                            return Value::String(date.to_rfc3339());
+                        }
                     }
-                }
-                Err(e) => {
-                    return Err(anyhow::anyhow!(
-                        "Failed to insert data item {} into collection {}: {}",
-                        id,
-                        &mapping.target_collection,
-                        e
-                    ));
+                    // document references become the referenced document id
+                    "documentReferenceType" => {
+                        if let Some(path) = inner.as_str() {
+                            return Value::String(path.rsplit('/').next().unwrap_or(path).to_string());
+                        }
+                    }
+                    _ => return from_firestore_json(inner.clone()),
                 }
             }
+            Value::Object(map.into_iter().map(|(k, v)| (k, from_firestore_json(v))).collect())
         }
+        Value::Array(items) => Value::Array(items.into_iter().map(from_firestore_json).collect()),
+        other => other,
     }
+}
 
+fn import_user_documents(store: &Store, docs: Vec<(String, Value)>) -> anyhow::Result<()> {
+    let now = Utc::now();
+    for (id, doc) in docs {
+        let username = doc_string(&doc, "username")
+            .or_else(|| doc_string(&doc, "name"))
+            .ok_or_else(|| anyhow::anyhow!("user {} has no username", id))?;
+        let password = doc_string(&doc, "password").ok_or_else(|| anyhow::anyhow!("user {} has no password", id))?;
+        let created_at = doc_timestamp(&doc, None, &["created_at", "createdAt"]).unwrap_or(now);
+        let updated_at = doc_timestamp(&doc, None, &["updated_at", "updatedAt"]).unwrap_or(created_at);
+
+        let (pk, sk) = syncstore::utils::hpke::generate_keypair();
+        let body = json!({
+            "username": username,
+            "password": password,
+            "public_key": base64::engine::general_purpose::STANDARD.encode(&pk),
+            "secret_key": base64::engine::general_purpose::STANDARD.encode(&sk),
+        });
+        println!("Imported user: {}", &id);
+        import_user(store, &username, body, id, created_at, updated_at)?;
+    }
     Ok(())
 }
 
+/// Import exported JSON documents `(id, doc, parent id)` with the field mapping of `mapping`.
+fn import_documents(
+    store: &Store,
+    namespace: &str,
+    mapping: &DataMapping,
+    docs: Vec<ExportDocument>,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    for (doc_id, doc, parent_id) in docs {
+        let id = match &mapping.id_field {
+            Some(field) => doc_string(&doc, field).ok_or_else(|| anyhow::anyhow!("missing id field {}", field))?,
+            None => doc_id,
+        };
+        let created_at =
+            doc_timestamp(&doc, mapping.created_at_field.as_deref(), &["created_at", "createdAt"]).unwrap_or(now);
+        let updated_at = doc_timestamp(&doc, mapping.updated_at_field.as_deref(), &["updated_at", "updatedAt"])
+            .unwrap_or(created_at);
+        let owner = doc_string(&doc, &mapping.owner_field)
+            .ok_or_else(|| anyhow::anyhow!("item {} has no owner field {}", id, mapping.owner_field))?;
+
+        let mut body = serde_json::Map::new();
+        let fields = mapping
+            .data_fields
+            .iter()
+            .map(|f| (f.as_str(), f.as_str()))
+            .chain(mapping.field_map.iter().map(|(t, s)| (t.as_str(), s.as_str())));
+        for (target, source) in fields {
+            if let Some(value) = lookup_path(&doc, source)
+                && !value.is_null()
+            {
+                body.insert(target.to_string(), value.clone());
+            }
+        }
+        if let Some(parent_field) = &mapping.parent_field
+            && let Some(parent_id) = parent_id
+        {
+            body.insert(parent_field.clone(), Value::String(parent_id));
+        }
+
+        println!(
+            "Imported data item: {} into collection: {}",
+            &id, &mapping.target_collection
+        );
+        import_data(
+            store,
+            namespace,
+            mapping,
+            Value::Object(body),
+            owner,
+            id,
+            created_at,
+            updated_at,
+        )?;
+    }
+    Ok(())
+}
+
+// mongoexport writes one extended JSON document per line
+fn read_mongo_export(path: &Path) -> anyhow::Result<Vec<Value>> {
+    let file = std::fs::File::open(path).map_err(|e| anyhow::anyhow!("failed to open {}: {}", path.display(), e))?;
+    let mut docs = Vec::new();
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let doc: Value = serde_json::from_str(&line)
+            .map_err(|e| anyhow::anyhow!("{}:{}: invalid json: {}", path.display(), n + 1, e))?;
+        docs.push(from_extended_json(doc));
+    }
+    Ok(docs)
+}
+
+/// Convert MongoDB extended JSON wrappers into plain JSON values.
+fn from_extended_json(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            if map.len() == 1 {
+                let (key, inner) = map.iter().next().expect("one entry");
+                match (key.as_str(), inner) {
+                    ("$oid", Value::String(oid)) => return Value::String(oid.clone()),
+                    ("$numberLong" | "$numberInt", Value::String(n)) => {
+                        return n.parse::<i64>().map(Value::from).unwrap_or(Value::String(n.clone()));
+                    }
+                    ("$numberDouble" | "$numberDecimal", Value::String(n)) => {
+                        return n.parse::<f64>().map(Value::from).unwrap_or(Value::String(n.clone()));
+                    }
+                    ("$date", date) => {
+                        if let Some(date) = parse_export_date(&from_extended_json(date.clone())) {
+                            return Value::String(date.to_rfc3339());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Value::Object(map.into_iter().map(|(k, v)| (k, from_extended_json(v))).collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(from_extended_json).collect()),
+        other => other,
+    }
+}
+
+// relaxed mode dates are RFC3339 strings, canonical mode dates are epoch millis
+fn parse_export_date(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|d| d.with_timezone(&Utc)),
+        Value::Number(n) => n.as_i64().and_then(|ms| Utc.timestamp_millis_opt(ms).single()),
+        _ => None,
+    }
+}
+
+fn lookup_path<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(doc, |v, key| v.get(key))
+}
+
+fn mongo_id(doc: &Value) -> anyhow::Result<String> {
+    doc_string(doc, "_id").ok_or_else(|| anyhow::anyhow!("document without _id: {}", doc))
+}
+
+fn doc_string(doc: &Value, path: &str) -> Option<String> {
+    match lookup_path(doc, path)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn doc_timestamp(doc: &Value, field: Option<&str>, defaults: &[&str]) -> Option<DateTime<Utc>> {
+    match field {
+        Some(field) => lookup_path(doc, field).and_then(parse_export_date),
+        None => defaults
+            .iter()
+            .find_map(|f| lookup_path(doc, f).and_then(parse_export_date)),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct MappingConfig {
     general: GeneralConfig,
@@ -236,4 +593,9 @@ struct DataMapping {
 
     owner_field: String,
     data_fields: Vec<String>,
+    // mongo_import/firestore_import only, dict<target field, source dotted path>
+    #[serde(default)]
+    field_map: HashMap<String, String>,
+    // firestore_import only, body field receiving the parent document id of a subcollection
+    parent_field: Option<String>,
 }
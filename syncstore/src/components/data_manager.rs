@@ -5,7 +5,7 @@ use std::{
 };
 
 use crate::{
-    backend::{SqliteBackend, sqlite::SqliteBackendBuilder},
+    backend::{SqliteBackend, keyword::KeywordFactory, sqlite::SqliteBackendBuilder},
     error::{StoreError, StoreResult},
 };
 
@@ -27,11 +27,24 @@ impl DataManager {
             None => Err(StoreError::NotFound(namespace.to_string())),
         }
     }
+
+    /// Databases written to, each one has a single writer connection.
+    pub(crate) fn writers(&self) -> usize {
+        self.map.values().filter(|backend| !backend.is_read_only()).count()
+    }
+
+    pub(crate) fn namespaces(&self) -> Vec<String> {
+        let mut namespaces: Vec<String> = self.map.keys().cloned().collect();
+        namespaces.sort_unstable();
+        namespaces
+    }
 }
 
 pub struct DataManagerBuilder {
     base_dir: PathBuf,
     map: HashMap<String, Arc<SqliteBackend>>,
+    // dict<namespace, SQLCipher key>
+    encryption_keys: HashMap<String, String>,
 }
 
 impl DataManagerBuilder {
@@ -39,33 +52,60 @@ impl DataManagerBuilder {
         Self {
             base_dir: base_dir.as_ref().to_path_buf(),
             map: HashMap::new(),
+            encryption_keys: HashMap::new(),
         }
     }
 
+    /// Encrypt the database of the namespace added afterwards under `key`, see
+    /// `SqliteBackendBuilder::encryption_key`.
+    pub fn with_encryption_key(mut self, namespace: &str, key: &str) -> Self {
+        self.encryption_keys.insert(namespace.to_string(), key.to_string());
+        self
+    }
+
     pub fn add_memory_db(mut self, schemas: DataSchemas) -> StoreResult<Self> {
-        let mut backend = SqliteBackendBuilder::memory();
-        for (collection, schema) in schemas.map.into_iter() {
-            backend = backend.with_collection_schema(&collection, schema);
-        }
-        let backend = backend.build()?;
+        let backend = schemas.apply(SqliteBackendBuilder::memory()).build()?;
         self.map.insert(MEMORY_NAMESPACE.into(), Arc::new(backend));
 
         Ok(self)
     }
 
-    pub fn add_db(mut self, namespace: &str, schemas: DataSchemas) -> StoreResult<Self> {
-        let mut path = self.base_dir.clone();
-        std::fs::create_dir_all(&path)?;
-        path.push(format!("{}.db", namespace));
-        let mut backend = SqliteBackendBuilder::file(path);
-        for (collection, schema) in schemas.map.into_iter() {
-            backend = backend.with_collection_schema(&collection, schema);
+    /// Add the namespace as `<base_dir>/<namespace>.db`.
+    pub fn add_db(self, namespace: &str, schemas: DataSchemas) -> StoreResult<Self> {
+        let path = self.base_dir.join(format!("{}.db", namespace));
+        self.add_db_at(namespace, path, schemas)
+    }
+
+    /// Add the namespace with its database file at an explicit path, e.g. on another volume.
+    pub fn add_db_at(mut self, namespace: &str, path: impl AsRef<Path>, schemas: DataSchemas) -> StoreResult<Self> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
         }
-        let backend = backend.build()?;
+        let backend = schemas.apply(self.file_backend(namespace, path)).build()?;
+        self.map.insert(namespace.to_string(), Arc::new(backend));
+        Ok(self)
+    }
+
+    /// Serve an existing database file as a read-only namespace, every mutation is rejected by the store.
+    pub fn add_readonly_db(
+        mut self,
+        namespace: &str,
+        path: impl AsRef<Path>,
+        schemas: DataSchemas,
+    ) -> StoreResult<Self> {
+        let backend = schemas.apply(self.file_backend(namespace, path).read_only()).build()?;
         self.map.insert(namespace.to_string(), Arc::new(backend));
         Ok(self)
     }
 
+    fn file_backend(&self, namespace: &str, path: impl AsRef<Path>) -> SqliteBackendBuilder {
+        let backend = SqliteBackendBuilder::file(path);
+        match self.encryption_keys.get(namespace) {
+            Some(key) => backend.encryption_key(key),
+            None => backend,
+        }
+    }
+
     pub fn build(self) -> DataManager {
         DataManager {
             _base_dir: self.base_dir,
@@ -77,10 +117,37 @@ impl DataManagerBuilder {
 pub struct DataSchemas {
     // dict<collection, schema>
     map: HashMap<String, serde_json::Value>,
+    // (collection, version, schema), earlier versions of the collection schemas
+    versions: Vec<(String, i64, serde_json::Value)>,
+    // dict<definition name, schema>, shared sub-schemas without a collection table
+    definitions: HashMap<String, serde_json::Value>,
+    // custom validation keywords of the namespace
+    keywords: Vec<(String, KeywordFactory)>,
+}
+
+impl DataSchemas {
+    fn apply(self, mut backend: SqliteBackendBuilder) -> SqliteBackendBuilder {
+        for (collection, schema) in self.map.into_iter() {
+            backend = backend.with_collection_schema(&collection, schema);
+        }
+        for (collection, version, schema) in self.versions.into_iter() {
+            backend = backend.with_schema_version(&collection, version, schema);
+        }
+        for (name, schema) in self.definitions.into_iter() {
+            backend = backend.with_definition(&name, schema);
+        }
+        for (name, factory) in self.keywords.into_iter() {
+            backend = backend.with_keyword(&name, factory);
+        }
+        backend
+    }
 }
 
 pub struct DataSchemasBuilder {
     map: HashMap<String, serde_json::Value>,
+    versions: Vec<(String, i64, serde_json::Value)>,
+    definitions: HashMap<String, serde_json::Value>,
+    keywords: Vec<(String, KeywordFactory)>,
 }
 
 impl Default for DataSchemasBuilder {
@@ -91,16 +158,54 @@ impl Default for DataSchemasBuilder {
 
 impl DataSchemasBuilder {
     pub fn new() -> Self {
-        Self { map: HashMap::new() }
+        Self {
+            map: HashMap::new(),
+            versions: Vec::new(),
+            definitions: HashMap::new(),
+            keywords: Vec::new(),
+        }
     }
 
+    /// Register the schema of a collection.
+    ///
+    /// Collection names are lowercase ASCII letters, digits and `_`, start with a letter and are at
+    /// most 64 characters long. `__`, the `_fts` and `_xi` segments and a few index name endings are
+    /// reserved, building the store fails on a name it can not take.
     pub fn add_schema(mut self, collection: &str, schema: serde_json::Value) -> Self {
         self.map.insert(collection.to_string(), schema);
         self
     }
 
+    /// Register an earlier version of a collection schema.
+    ///
+    /// The current schema sets its version with `x-version` (1 when absent) and every write is stamped
+    /// with it. A stored document still at an earlier version keeps validating against the schema
+    /// registered here, e.g. when an `x-migrate` check runs; a version not registered falls back to
+    /// the current schema.
+    pub fn add_schema_version(mut self, collection: &str, version: i64, schema: serde_json::Value) -> Self {
+        self.versions.push((collection.to_string(), version, schema));
+        self
+    }
+
+    /// Register a shared sub-schema, referenced from collection schemas by `{"$ref": "urn:syncstore:<name>"}`.
+    pub fn add_definition(mut self, name: &str, schema: serde_json::Value) -> Self {
+        self.definitions.insert(name.to_string(), schema);
+        self
+    }
+
+    /// Register a custom schema keyword, its validators can read the namespace database.
+    pub fn add_keyword(mut self, name: &str, factory: KeywordFactory) -> Self {
+        self.keywords.push((name.to_string(), factory));
+        self
+    }
+
     pub fn build(self) -> DataSchemas {
-        DataSchemas { map: self.map }
+        DataSchemas {
+            map: self.map,
+            versions: self.versions,
+            definitions: self.definitions,
+            keywords: self.keywords,
+        }
     }
 }
 
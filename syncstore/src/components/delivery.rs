@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+
+use crate::{
+    config::{Delivery, Smtp, SmtpTls},
+    error::{StoreError, StoreResult},
+    types::NotificationMessage,
+};
+
+/// A message sent to a user outside of the service.
+#[derive(Debug, Clone)]
+pub struct DeliveryMessage {
+    pub subject: String,
+    pub body: String,
+}
+
+impl From<&NotificationMessage> for DeliveryMessage {
+    fn from(value: &NotificationMessage) -> Self {
+        Self {
+            subject: value.title.clone(),
+            body: value.content.clone().unwrap_or_else(|| value.title.clone()),
+        }
+    }
+}
+
+/// Outbound delivery channel, shared by every feature that needs to reach users outside the API.
+#[async_trait]
+pub trait DeliveryChannel: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Deliver the message to `address`, the address format depends on the channel.
+    async fn deliver(&self, address: &str, message: &DeliveryMessage) -> StoreResult<()>;
+}
+
+pub fn build_channel(config: &Delivery) -> StoreResult<Arc<dyn DeliveryChannel>> {
+    Ok(match config {
+        Delivery::Smtp(smtp) => Arc::new(SmtpChannel::new(smtp)?),
+        Delivery::Log => Arc::new(LogChannel),
+    })
+}
+
+/// Email delivery through an SMTP relay.
+pub struct SmtpChannel {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpChannel {
+    pub fn new(config: &Smtp) -> StoreResult<Self> {
+        let smtp_error = |e: lettre::transport::smtp::Error| StoreError::Validation(format!("smtp: {}", e));
+        let mut builder = match config.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host).map_err(smtp_error)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host).map_err(smtp_error)?,
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        let from = config
+            .from
+            .parse()
+            .map_err(|e| StoreError::Validation(format!("smtp: invalid from address: {}", e)))?;
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl DeliveryChannel for SmtpChannel {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn deliver(&self, address: &str, message: &DeliveryMessage) -> StoreResult<()> {
+        let to: Mailbox = address
+            .parse()
+            .map_err(|e| StoreError::Validation(format!("invalid email address {}: {}", address, e)))?;
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(message.subject.clone())
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body.clone())
+            .map_err(|e| StoreError::Validation(format!("failed to build email: {}", e)))?;
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| StoreError::Backend(format!("smtp send failed: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Only log the messages, for development setups without a mail server.
+pub struct LogChannel;
+
+#[async_trait]
+impl DeliveryChannel for LogChannel {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn deliver(&self, address: &str, message: &DeliveryMessage) -> StoreResult<()> {
+        tracing::info!("deliver to {}: [{}] {}", address, message.subject, message.body);
+        Ok(())
+    }
+}
@@ -1,5 +1,20 @@
 mod data_manager;
+mod delivery;
+mod notification_manager;
+mod outbox;
+mod replication;
+mod scheduler;
 mod user_manager;
+mod webhook;
 
 pub use data_manager::{DataManager, DataManagerBuilder, DataSchemas, DataSchemasBuilder};
+pub use delivery::{DeliveryChannel, DeliveryMessage, LogChannel, SmtpChannel, build_channel};
+pub use notification_manager::NotificationManager;
+pub use outbox::OutboxConsumer;
+pub(crate) use outbox::ReplicationConsumer;
+pub use replication::{HttpReplicationTarget, ReplicationTarget};
+pub use scheduler::{JobStatus, Scheduler};
 pub use user_manager::UserManager;
+pub use webhook::{
+    DELIVERY_HEADER, HttpWebhookSender, SIGNATURE_HEADER, TIMESTAMP_HEADER, WebhookRequest, WebhookSender, sign_webhook,
+};
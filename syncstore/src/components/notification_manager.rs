@@ -0,0 +1,152 @@
+use std::{path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{Backend, SqliteBackend, sqlite::SqliteBackendBuilder},
+    error::{StoreError, StoreResult},
+    types::{DataItem, Notification, NotificationMessage},
+    utils::constant::NOTIFICATION_TABLE,
+};
+
+/// Per user notification inbox, stored in the inner `notifications.db`.
+///
+/// The recipient is the owner of the notification document.
+pub struct NotificationManager {
+    backend: Arc<SqliteBackend>,
+}
+
+// stored notification body
+#[derive(Serialize, Deserialize)]
+struct NotificationDocument {
+    #[serde(flatten)]
+    message: NotificationMessage,
+    read: bool,
+}
+
+impl NotificationManager {
+    pub fn new(base_dir: impl AsRef<Path>) -> StoreResult<Self> {
+        let mut path = base_dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&path)?;
+        path.push("notifications.db");
+
+        let notification_schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "kind": { "type": "string" },
+                "title": { "type": "string" },
+                "content": { "type": "string" },
+                "payload": {},
+                "read": { "type": "boolean" }
+            },
+            "required": ["kind", "title", "read"]
+        });
+        let backend = Arc::new(
+            SqliteBackendBuilder::file(path)
+                .with_collection_schema(NOTIFICATION_TABLE, notification_schema)
+                .build()?,
+        );
+
+        Ok(NotificationManager { backend })
+    }
+
+    pub(crate) fn backend(&self) -> Arc<SqliteBackend> {
+        self.backend.clone()
+    }
+
+    pub fn publish(&self, recipient: &str, message: NotificationMessage) -> StoreResult<String> {
+        let body = serde_json::to_value(NotificationDocument { message, read: false })?;
+        self.backend.insert(NOTIFICATION_TABLE, &body, recipient.to_string())
+    }
+
+    /// List the notifications of a user, newest first.
+    pub fn list(
+        &self,
+        user_id: &str,
+        unread_only: bool,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<Notification>, Option<String>)> {
+        let unread = serde_json::json!(false);
+        let filter = unread_only.then_some(("read", &unread));
+        let (items, next_marker) =
+            self.backend
+                .list_recent_by_owner(NOTIFICATION_TABLE, user_id, filter, marker, limit)?;
+        let items = items.into_iter().map(to_notification).collect::<StoreResult<_>>()?;
+        Ok((items, next_marker))
+    }
+
+    pub fn unread_count(&self, user_id: &str) -> StoreResult<u64> {
+        self.backend
+            .count_by_owner(NOTIFICATION_TABLE, user_id, Some(("read", &serde_json::json!(false))))
+    }
+
+    pub fn mark_read(&self, user_id: &str, id: &str) -> StoreResult<Notification> {
+        let item = self.get_owned(user_id, id)?;
+        let mut doc: NotificationDocument = serde_json::from_value(item.body.clone())?;
+        if doc.read {
+            return to_notification(item);
+        }
+        doc.read = true;
+        let item = self
+            .backend
+            .update(NOTIFICATION_TABLE, &id.to_string(), &serde_json::to_value(doc)?)?;
+        to_notification(item)
+    }
+
+    /// Mark every unread notification of the user as read, return how many were updated.
+    pub fn mark_all_read(&self, user_id: &str) -> StoreResult<usize> {
+        let mut count = 0;
+        loop {
+            let (items, _) = self.list(user_id, true, None, 100)?;
+            if items.is_empty() {
+                break;
+            }
+            for item in items {
+                self.mark_read(user_id, &item.id)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn delete(&self, user_id: &str, id: &str) -> StoreResult<()> {
+        self.get_owned(user_id, id)?;
+        self.backend.delete(NOTIFICATION_TABLE, &id.to_string())
+    }
+
+    /// Empty the inbox of a user, returns how many notifications were deleted.
+    pub fn delete_all(&self, user_id: &str) -> StoreResult<usize> {
+        let mut count = 0;
+        loop {
+            let (items, _) = self.backend.list_by_owner(NOTIFICATION_TABLE, user_id, None, 100)?;
+            if items.is_empty() {
+                break;
+            }
+            for item in items {
+                self.backend.delete(NOTIFICATION_TABLE, &item.id)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    // other users' notifications are reported as not found
+    fn get_owned(&self, user_id: &str, id: &str) -> StoreResult<DataItem> {
+        let item = self.backend.get(NOTIFICATION_TABLE, &id.to_string())?;
+        if item.owner != user_id {
+            return Err(StoreError::NotFound(format!("Notification {}", id)));
+        }
+        Ok(item)
+    }
+}
+
+fn to_notification(item: DataItem) -> StoreResult<Notification> {
+    let doc: NotificationDocument = serde_json::from_value(item.body)?;
+    Ok(Notification {
+        id: item.id,
+        created_at: item.created_at,
+        message: doc.message,
+        read: doc.read,
+    })
+}
@@ -0,0 +1,38 @@
+use crate::{
+    error::StoreResult,
+    types::{Change, ReplicationBatch},
+};
+
+use super::ReplicationTarget;
+
+/// Consumer of the change events drained by `Store::dispatch_outbox`.
+///
+/// The change log of every namespace is its outbox: each change is written in the same transaction
+/// as the mutation it describes, and a consumer's cursor only moves once it accepted a batch. An
+/// event is never lost between commit and publish, but a batch might be handed over again after a
+/// failure or a crash, so consumers must be idempotent.
+pub trait OutboxConsumer: Send + Sync {
+    /// Stable name of the consumer, its cursors are stored under it.
+    fn name(&self) -> &str;
+
+    fn consume(&self, namespace: &str, changes: &[Change]) -> StoreResult<()>;
+}
+
+// replication targets are outbox consumers keyed by their own name
+pub(crate) struct ReplicationConsumer<'a>(pub &'a dyn ReplicationTarget);
+
+impl OutboxConsumer for ReplicationConsumer<'_> {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn consume(&self, namespace: &str, changes: &[Change]) -> StoreResult<()> {
+        self.0.push(
+            namespace,
+            &ReplicationBatch {
+                changes: changes.to_vec(),
+            },
+        )?;
+        Ok(())
+    }
+}
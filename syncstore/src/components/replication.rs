@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use crate::{
+    config::Replication,
+    error::{StoreError, StoreResult},
+    types::{ReplicationAck, ReplicationBatch},
+};
+
+/// Receiver of the change batches pushed by `Store::replicate_namespace`.
+///
+/// Pushes run inside the blocking scheduler jobs, so the trait is synchronous.
+pub trait ReplicationTarget: Send + Sync {
+    /// Stable name of the target, the replication cursors are stored under it.
+    fn name(&self) -> &str;
+
+    /// Apply the batch on the target, the same batch might be pushed again after a failure.
+    fn push(&self, namespace: &str, batch: &ReplicationBatch) -> StoreResult<ReplicationAck>;
+}
+
+/// Secondary SyncStore instance, reached through its admin API.
+pub struct HttpReplicationTarget {
+    client: reqwest::Client,
+    // admin base url of the secondary
+    target: String,
+    runtime: tokio::runtime::Handle,
+}
+
+impl HttpReplicationTarget {
+    /// Must be called inside a tokio runtime, pushes block on it.
+    pub fn new(config: &Replication) -> StoreResult<Self> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|e| StoreError::Backend(format!("replication needs a tokio runtime: {}", e)))?;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout.unwrap_or(Duration::from_secs(30)))
+            .build()
+            .map_err(|e| StoreError::Backend(format!("failed to build replication client: {}", e)))?;
+        Ok(Self {
+            client,
+            target: config.target.trim_end_matches('/').to_string(),
+            runtime,
+        })
+    }
+}
+
+impl ReplicationTarget for HttpReplicationTarget {
+    fn name(&self) -> &str {
+        &self.target
+    }
+
+    fn push(&self, namespace: &str, batch: &ReplicationBatch) -> StoreResult<ReplicationAck> {
+        let url = format!("{}/replication/{}/apply", self.target, namespace);
+        let push_error = |e: reqwest::Error| StoreError::Backend(format!("replication push to {} failed: {}", url, e));
+        self.runtime.block_on(async {
+            let resp = self.client.post(&url).json(batch).send().await.map_err(push_error)?;
+            let status = resp.status();
+            if !status.is_success() {
+                let text = resp.text().await.unwrap_or_default();
+                return Err(StoreError::Backend(format!(
+                    "replication push to {} rejected with {}: {}",
+                    url, status, text
+                )));
+            }
+            resp.json::<ReplicationAck>().await.map_err(push_error)
+        })
+    }
+}
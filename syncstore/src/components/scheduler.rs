@@ -0,0 +1,280 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+
+use crate::error::{StoreError, StoreResult};
+
+type JobTask = Box<dyn Fn() -> StoreResult<()> + Send + Sync>;
+
+/// In-process scheduler running registered maintenance jobs periodically.
+///
+/// Each job sleeps `interval` plus a random delay up to `jitter` between runs, so that jobs
+/// registered together do not hit the database at the same moment.
+#[derive(Default)]
+pub struct Scheduler {
+    // dict<job name, job>
+    jobs: RwLock<BTreeMap<String, Arc<Job>>>,
+    started: AtomicBool,
+}
+
+struct Job {
+    name: String,
+    interval: Duration,
+    jitter: Duration,
+    task: JobTask,
+    stats: Mutex<JobStats>,
+}
+
+#[derive(Default)]
+struct JobStats {
+    running: bool,
+    runs: u64,
+    failures: u64,
+    last_run_at: Option<DateTime<Utc>>,
+    last_duration: Option<Duration>,
+    last_error: Option<String>,
+    next_run_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, salvo::oapi::ToSchema)]
+pub struct JobStatus {
+    pub name: String,
+    pub interval_secs: u64,
+    pub jitter_secs: u64,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    pub last_error: Option<String>,
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a job, jobs registered after `start` are started right away.
+    pub fn register<F>(&self, name: &str, interval: Duration, jitter: Duration, task: F) -> StoreResult<()>
+    where
+        F: Fn() -> StoreResult<()> + Send + Sync + 'static,
+    {
+        if interval.is_zero() {
+            return Err(StoreError::Validation(format!(
+                "job `{}`: interval must not be zero",
+                name
+            )));
+        }
+        let job = Arc::new(Job {
+            name: name.to_string(),
+            interval,
+            jitter,
+            task: Box::new(task),
+            stats: Mutex::new(JobStats::default()),
+        });
+        {
+            let mut jobs = self.jobs.write().map_err(|e| StoreError::Backend(e.to_string()))?;
+            if jobs.contains_key(name) {
+                return Err(StoreError::Validation(format!("job `{}` already registered", name)));
+            }
+            jobs.insert(name.to_string(), job.clone());
+        }
+        if self.started.load(Ordering::SeqCst) {
+            tokio::spawn(job_loop(job));
+        }
+        Ok(())
+    }
+
+    /// Spawn the loop of every registered job, must be called inside a tokio runtime.
+    pub fn start(&self) -> StoreResult<()> {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let jobs = self.jobs.read().map_err(|e| StoreError::Backend(e.to_string()))?;
+        for job in jobs.values() {
+            tokio::spawn(job_loop(job.clone()));
+        }
+        tracing::info!("scheduler started with {} jobs", jobs.len());
+        Ok(())
+    }
+
+    /// Run a job immediately, outside its schedule.
+    pub fn run_now(&self, name: &str) -> StoreResult<JobStatus> {
+        let job = self.job(name)?;
+        run_job(&job)?;
+        job.status()
+    }
+
+    pub fn status(&self, name: &str) -> StoreResult<JobStatus> {
+        self.job(name)?.status()
+    }
+
+    pub fn list_status(&self) -> StoreResult<Vec<JobStatus>> {
+        let jobs = self.jobs.read().map_err(|e| StoreError::Backend(e.to_string()))?;
+        jobs.values().map(|job| job.status()).collect()
+    }
+
+    fn job(&self, name: &str) -> StoreResult<Arc<Job>> {
+        self.jobs
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .get(name)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(format!("job `{}`", name)))
+    }
+}
+
+impl Job {
+    fn status(&self) -> StoreResult<JobStatus> {
+        let stats = self.stats.lock().map_err(|e| StoreError::Backend(e.to_string()))?;
+        Ok(JobStatus {
+            name: self.name.clone(),
+            interval_secs: self.interval.as_secs(),
+            jitter_secs: self.jitter.as_secs(),
+            running: stats.running,
+            runs: stats.runs,
+            failures: stats.failures,
+            last_run_at: stats.last_run_at,
+            last_duration_ms: stats.last_duration.map(|d| d.as_millis() as u64),
+            last_error: stats.last_error.clone(),
+            next_run_at: stats.next_run_at,
+        })
+    }
+
+    fn next_delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.interval;
+        }
+        let jitter_ms = rand::rng().random_range(0..=self.jitter.as_millis() as u64);
+        self.interval + Duration::from_millis(jitter_ms)
+    }
+}
+
+/// Run the job once and record its metrics, a job never runs concurrently with itself.
+fn run_job(job: &Job) -> StoreResult<()> {
+    {
+        let mut stats = job.stats.lock().map_err(|e| StoreError::Backend(e.to_string()))?;
+        if stats.running {
+            return Err(StoreError::Validation(format!("job `{}` is already running", job.name)));
+        }
+        stats.running = true;
+        stats.last_run_at = Some(Utc::now());
+    }
+    let start = Instant::now();
+    // a panicking job counts as failed, `running` must not stay set or it never runs again
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (job.task)())).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(StoreError::Backend(format!("job panicked: {}", message)))
+    });
+    let mut stats = job.stats.lock().map_err(|e| StoreError::Backend(e.to_string()))?;
+    stats.running = false;
+    stats.runs += 1;
+    stats.last_duration = Some(start.elapsed());
+    match &result {
+        Ok(()) => stats.last_error = None,
+        Err(e) => {
+            tracing::warn!("job `{}` failed: {}", job.name, e);
+            stats.failures += 1;
+            stats.last_error = Some(e.to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn job_loop(job: Arc<Job>) {
+    loop {
+        let delay = job.next_delay();
+        if let Ok(mut stats) = job.stats.lock() {
+            stats.next_run_at = chrono::Duration::from_std(delay).ok().map(|d| Utc::now() + d);
+        }
+        tokio::time::sleep(delay).await;
+        let job = job.clone();
+        match tokio::task::spawn_blocking(move || run_job(&job)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::info!("job skipped: {}", e),
+            Err(e) => tracing::error!("job panicked: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_now_records_metrics() {
+        let scheduler = Scheduler::new();
+        scheduler
+            .register("ok", Duration::from_secs(60), Duration::ZERO, || Ok(()))
+            .unwrap();
+        scheduler
+            .register("fail", Duration::from_secs(60), Duration::ZERO, || {
+                Err(StoreError::Backend("boom".to_string()))
+            })
+            .unwrap();
+        assert!(
+            scheduler
+                .register("ok", Duration::from_secs(60), Duration::ZERO, || Ok(()))
+                .is_err()
+        );
+
+        let status = scheduler.run_now("ok").unwrap();
+        assert_eq!(status.runs, 1);
+        assert_eq!(status.failures, 0);
+        assert!(status.last_run_at.is_some());
+
+        let status = scheduler.run_now("fail").unwrap();
+        assert_eq!(status.runs, 1);
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.last_error.as_deref(), Some("backend error: boom"));
+
+        assert!(scheduler.run_now("missing").is_err());
+        assert_eq!(scheduler.list_status().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_panicking_job_runs_again() {
+        let scheduler = Scheduler::new();
+        scheduler
+            .register("panic", Duration::from_secs(60), Duration::ZERO, || panic!("boom"))
+            .unwrap();
+
+        let status = scheduler.run_now("panic").unwrap();
+        assert!(!status.running);
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.last_error.as_deref(), Some("backend error: job panicked: boom"));
+        let status = scheduler.run_now("panic").unwrap();
+        assert_eq!(status.runs, 2);
+    }
+
+    #[tokio::test]
+    async fn test_started_job_runs_periodically() {
+        let scheduler = Scheduler::new();
+        let counter = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let c = counter.clone();
+        scheduler
+            .register("tick", Duration::from_millis(10), Duration::from_millis(5), move || {
+                c.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .unwrap();
+        scheduler.start().unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(counter.load(Ordering::SeqCst) >= 2);
+        assert!(scheduler.status("tick").unwrap().next_run_at.is_some());
+    }
+}
@@ -1,18 +1,32 @@
 use std::{path::Path, sync::Arc};
 
 use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     backend::{Backend, SqliteBackend, sqlite::SqliteBackendBuilder},
-    error::StoreResult,
-    types::{UserSchema, UserSchemaDocument},
-    utils::constant::{FRIENDS_TABLE, ROOT_OWNER, USER_TABLE},
+    error::{StoreError, StoreResult},
+    types::{Base64Standard, DataItem, DeactivatedUser, Passkey, UserSchema, UserSchemaDocument},
+    utils::constant::{FRIENDS_TABLE, PASSKEY_TABLE, ROOT_OWNER, USER_TABLE},
 };
 
 pub struct UserManager {
     backend: Arc<SqliteBackend>,
 }
 
+// stored passkey body, owned by its user
+#[derive(Serialize, Deserialize)]
+struct PasskeyDocument {
+    name: String,
+    credential_id: String,
+    #[serde(with = "Base64Standard")]
+    public_key: Vec<u8>,
+    sign_count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_used_at: Option<DateTime<Utc>>,
+}
+
 impl UserManager {
     pub fn new(base_dir: impl AsRef<Path>) -> StoreResult<Self> {
         let mut path = base_dir.as_ref().to_path_buf();
@@ -25,8 +39,10 @@ impl UserManager {
                 "username": { "type": "string" },
                 "password": { "type": "string" },
                 "avatar_url": { "type": "string" },
+                "email": { "type": "string" },
                 "public_key": { "type": "string", "contentEncoding": "base64" },
-                "secret_key": { "type": "string", "contentEncoding": "base64" }
+                "secret_key": { "type": "string", "contentEncoding": "base64" },
+                "deactivated_at": { "type": "string", "format": "date-time" }
             },
             "required": ["username", "password", "public_key", "secret_key"],
             "x-unique": "username"
@@ -41,10 +57,23 @@ impl UserManager {
             "x-parent-id": { "parent": USER_TABLE, "field": "friend_id" },
             "x-unique": "unique_key"
         });
+        let passkey_schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "credential_id": { "type": "string" },
+                "public_key": { "type": "string", "contentEncoding": "base64" },
+                "sign_count": { "type": "integer", "minimum": 0 },
+                "last_used_at": { "type": "string", "format": "date-time" }
+            },
+            "required": ["name", "credential_id", "public_key", "sign_count"],
+            "x-unique": "credential_id"
+        });
         let backend = Arc::new(
             SqliteBackendBuilder::file(path)
                 .with_collection_schema(USER_TABLE, user_schema)
                 .with_collection_schema(FRIENDS_TABLE, friend_schema)
+                .with_collection_schema(PASSKEY_TABLE, passkey_schema)
                 .build()?,
         );
 
@@ -63,9 +92,11 @@ impl UserManager {
         Ok(())
     }
 
+    /// The id of the user, none for a wrong password or a deactivated account.
     pub fn validate_user(&self, username: &str, password: &str) -> StoreResult<Option<String>> {
         if let Ok(item) = self.backend.get_by_unique(USER_TABLE, username)
             && item.body.get("password") == Some(&serde_json::json!(password))
+            && item.body.get("deactivated_at").is_none_or(|v| v.is_null())
         {
             Ok(Some(item.id))
         } else {
@@ -88,10 +119,64 @@ impl UserManager {
         Ok(())
     }
 
+    /// Mark the user deactivated from now, or active again with `false`.
+    pub fn set_deactivated(&self, user_id: &String, deactivated: bool) -> StoreResult<UserSchema> {
+        let mut user = self.get_user(user_id)?;
+        user.deactivated_at = deactivated.then(Utc::now);
+        self.update_user(user_id, &user)?;
+        Ok(user)
+    }
+
+    pub fn list_deactivated(&self) -> StoreResult<Vec<DeactivatedUser>> {
+        let mut users = Vec::new();
+        let mut marker = None;
+        loop {
+            let (items, next_marker) = self.backend.list_by_owner(USER_TABLE, ROOT_OWNER, marker, 100)?;
+            for item in items {
+                let doc = serde_json::from_value::<UserSchemaDocument>(item.body)?;
+                if let Some(deactivated_at) = doc.deactivated_at {
+                    users.push(DeactivatedUser {
+                        user_id: item.id,
+                        username: doc.username,
+                        deactivated_at,
+                    });
+                }
+            }
+            if next_marker.is_none() {
+                break;
+            }
+            marker = next_marker;
+        }
+        Ok(users)
+    }
+
+    /// Remove the user with the friendships in both directions.
+    pub fn remove_user(&self, user_id: &str) -> StoreResult<()> {
+        // the pages shrink as they are deleted, always read the first one
+        loop {
+            let (added, _) = self.backend.list_by_owner(FRIENDS_TABLE, user_id, None, 100)?;
+            let (added_by, _) = self.backend.list_children(FRIENDS_TABLE, user_id, None, 100)?;
+            if added.is_empty() && added_by.is_empty() {
+                break;
+            }
+            for item in added.into_iter().chain(added_by) {
+                self.backend.delete(FRIENDS_TABLE, &item.id)?;
+            }
+        }
+        for passkey in self.list_passkeys(user_id)? {
+            self.backend.delete(PASSKEY_TABLE, &passkey.id)?;
+        }
+        self.backend.delete(USER_TABLE, &user_id.to_string())
+    }
+
     pub fn get_inner_backend(&self) -> Arc<dyn Backend> {
         self.backend.clone()
     }
 
+    pub(crate) fn backend(&self) -> Arc<SqliteBackend> {
+        self.backend.clone()
+    }
+
     pub fn add_friend(&self, user_id: &String, friend_id: &String) -> StoreResult<()> {
         let body = serde_json::json!({
             "friend_id": friend_id,
@@ -101,11 +186,14 @@ impl UserManager {
         Ok(())
     }
 
-    pub fn list_friends(&self, user_id: &str) -> StoreResult<Vec<String>> {
-        // todo better with pagination
-        let items = self.backend.list_by_owner(FRIENDS_TABLE, user_id, None, 100)?;
+    pub fn list_friends(
+        &self,
+        user_id: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<String>, Option<String>)> {
+        let (items, next_marker) = self.backend.list_by_owner(FRIENDS_TABLE, user_id, marker, limit)?;
         let friend_ids = items
-            .0
             .into_iter()
             .filter_map(|item| {
                 item.body
@@ -114,6 +202,82 @@ impl UserManager {
                     .map(|s| s.to_string())
             })
             .collect();
-        Ok(friend_ids)
+        Ok((friend_ids, next_marker))
+    }
+}
+
+// passkeys, the credential id is unique across all users
+impl UserManager {
+    pub fn add_passkey(
+        &self,
+        user_id: &str,
+        name: &str,
+        credential_id: &str,
+        public_key: Vec<u8>,
+        sign_count: u32,
+    ) -> StoreResult<Passkey> {
+        let body = serde_json::to_value(PasskeyDocument {
+            name: name.to_string(),
+            credential_id: credential_id.to_string(),
+            public_key,
+            sign_count,
+            last_used_at: None,
+        })?;
+        let id = self.backend.insert(PASSKEY_TABLE, &body, user_id.to_string())?;
+        to_passkey(self.backend.get(PASSKEY_TABLE, &id)?)
+    }
+
+    pub fn list_passkeys(&self, user_id: &str) -> StoreResult<Vec<Passkey>> {
+        let mut passkeys = Vec::new();
+        let mut marker = None;
+        loop {
+            let (items, next_marker) = self.backend.list_by_owner(PASSKEY_TABLE, user_id, marker, 100)?;
+            for item in items {
+                passkeys.push(to_passkey(item)?);
+            }
+            if next_marker.is_none() {
+                break;
+            }
+            marker = next_marker;
+        }
+        Ok(passkeys)
+    }
+
+    /// The passkey of a credential id with the id of its user.
+    pub fn find_passkey(&self, credential_id: &str) -> StoreResult<(String, Passkey)> {
+        let item = self.backend.get_by_unique(PASSKEY_TABLE, credential_id)?;
+        Ok((item.owner.clone(), to_passkey(item)?))
     }
+
+    pub fn record_passkey_use(&self, id: &str, sign_count: u32) -> StoreResult<()> {
+        let item = self.backend.get(PASSKEY_TABLE, &id.to_string())?;
+        let mut doc = serde_json::from_value::<PasskeyDocument>(item.body)?;
+        doc.sign_count = sign_count;
+        doc.last_used_at = Some(Utc::now());
+        self.backend
+            .update(PASSKEY_TABLE, &id.to_string(), &serde_json::to_value(doc)?)?;
+        Ok(())
+    }
+
+    // passkeys of other users are reported as not found
+    pub fn delete_passkey(&self, user_id: &str, id: &str) -> StoreResult<()> {
+        let item = self.backend.get(PASSKEY_TABLE, &id.to_string())?;
+        if item.owner != user_id {
+            return Err(StoreError::NotFound(format!("Passkey {}", id)));
+        }
+        self.backend.delete(PASSKEY_TABLE, &item.id)
+    }
+}
+
+fn to_passkey(item: DataItem) -> StoreResult<Passkey> {
+    let doc: PasskeyDocument = serde_json::from_value(item.body)?;
+    Ok(Passkey {
+        id: item.id,
+        name: doc.name,
+        credential_id: doc.credential_id,
+        public_key: doc.public_key,
+        sign_count: doc.sign_count,
+        created_at: item.created_at,
+        last_used_at: doc.last_used_at,
+    })
 }
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    config::Webhooks,
+    error::{StoreError, StoreResult},
+};
+
+pub const SIGNATURE_HEADER: &str = "X-Syncstore-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Syncstore-Timestamp";
+pub const DELIVERY_HEADER: &str = "X-Syncstore-Delivery";
+
+/// A signed webhook request, ready to be sent.
+#[derive(Debug, Clone)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub delivery_id: i64,
+    /// unix seconds, part of the signed content to let receivers reject replays
+    pub timestamp: i64,
+    /// `sha256=<hex hmac>` of `{timestamp}.{body}`
+    pub signature: String,
+    pub body: String,
+}
+
+impl WebhookRequest {
+    pub fn new(url: &str, delivery_id: i64, secret: &str, body: String) -> Self {
+        let timestamp = chrono::Utc::now().timestamp();
+        Self {
+            url: url.to_string(),
+            delivery_id,
+            timestamp,
+            signature: sign_webhook(secret, timestamp, &body),
+            body,
+        }
+    }
+}
+
+/// Compute the signature header value of a webhook body, receivers recompute it with their secret.
+pub fn sign_webhook(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+/// Sends the webhook requests of `Store::deliver_webhooks`.
+///
+/// Deliveries run inside the blocking scheduler jobs, so the trait is synchronous.
+pub trait WebhookSender: Send + Sync {
+    /// Any error counts as a failed attempt and is retried with backoff.
+    fn send(&self, request: &WebhookRequest) -> StoreResult<()>;
+}
+
+pub struct HttpWebhookSender {
+    client: reqwest::Client,
+    runtime: tokio::runtime::Handle,
+}
+
+impl HttpWebhookSender {
+    /// Must be called inside a tokio runtime, sends block on it.
+    pub fn new(config: &Webhooks) -> StoreResult<Self> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|e| StoreError::Backend(format!("webhooks need a tokio runtime: {}", e)))?;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout.unwrap_or(Duration::from_secs(10)))
+            .build()
+            .map_err(|e| StoreError::Backend(format!("failed to build webhook client: {}", e)))?;
+        Ok(Self { client, runtime })
+    }
+}
+
+impl WebhookSender for HttpWebhookSender {
+    fn send(&self, request: &WebhookRequest) -> StoreResult<()> {
+        let send_error =
+            |e: reqwest::Error| StoreError::Backend(format!("webhook request to {} failed: {}", request.url, e));
+        self.runtime.block_on(async {
+            let resp = self
+                .client
+                .post(&request.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, &request.signature)
+                .header(TIMESTAMP_HEADER, request.timestamp.to_string())
+                .header(DELIVERY_HEADER, request.delivery_id.to_string())
+                .body(request.body.clone())
+                .send()
+                .await
+                .map_err(send_error)?;
+            let status = resp.status();
+            if !status.is_success() {
+                return Err(StoreError::Backend(format!(
+                    "webhook request to {} rejected with {}",
+                    request.url, status
+                )));
+            }
+            Ok(())
+        })
+    }
+}
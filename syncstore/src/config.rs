@@ -1,8 +1,13 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use serde::Deserialize;
 use serde::de::Error as _;
 
+use crate::store::StoreLayout;
+use crate::types::{NamespacePolicy, OrphanPolicy};
+
 #[derive(Debug, Deserialize)]
 pub struct ServiceConfig {
     pub admin_address: String,
@@ -10,6 +15,234 @@ pub struct ServiceConfig {
     pub jwt: Jwt,
     #[serde(default, deserialize_with = "deserialize_optional_duration")]
     pub latency_inject: Option<Duration>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub validation_limit: Option<ValidationLimit>,
+    #[serde(default)]
+    pub delivery: Option<Delivery>,
+    #[serde(default)]
+    pub replication: Option<Replication>,
+    #[serde(default)]
+    pub webhooks: Option<Webhooks>,
+    #[serde(default)]
+    pub page_size: PageSize,
+    #[serde(default)]
+    pub request_timeout: Option<RequestTimeout>,
+    #[serde(default)]
+    pub passkey: Option<Passkey>,
+}
+
+/// Asynchronous replication of every namespace to a secondary instance, see `Store::replicate_namespace`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Replication {
+    /// admin base url of the secondary, e.g. `http://10.0.0.2:8081/admin`
+    pub target: String,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+    /// max changes pushed in one request
+    #[serde(default = "default_replication_batch_size")]
+    pub batch_size: usize,
+    /// request timeout, defaults to 30s
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub timeout: Option<Duration>,
+}
+
+fn default_replication_batch_size() -> usize {
+    500
+}
+
+/// Delivery of the registered webhooks, see `Store::deliver_webhooks`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Webhooks {
+    /// how often the queue is polled
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+    /// failed attempts before a delivery is dead-lettered
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+    /// delay before the first retry, doubled on every further failure
+    #[serde(default = "default_webhook_backoff", deserialize_with = "deserialize_duration")]
+    pub backoff: Duration,
+    #[serde(default = "default_webhook_max_backoff", deserialize_with = "deserialize_duration")]
+    pub max_backoff: Duration,
+    /// request timeout, defaults to 10s
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub timeout: Option<Duration>,
+}
+
+impl Webhooks {
+    /// Delay before the next attempt after `attempts` failures, none once the delivery is dead.
+    pub fn retry_delay(&self, attempts: u32) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        Some(self.backoff.saturating_mul(factor).min(self.max_backoff))
+    }
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    8
+}
+
+fn default_webhook_backoff() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_webhook_max_backoff() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+/// Channel delivering notifications to users outside of the API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Delivery {
+    Smtp(Smtp),
+    /// only log the messages, for development
+    Log,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Smtp {
+    pub host: String,
+    /// defaults to the standard port of the tls mode
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// sender mailbox, e.g. `SyncStore <noreply@example.com>`
+    pub from: String,
+    #[serde(default)]
+    pub tls: SmtpTls,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    None,
+    #[default]
+    Starttls,
+    Tls,
+}
+
+/// Passkey (WebAuthn) login next to the password login, the endpoints refuse when not configured.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Passkey {
+    /// domain the passkeys are bound to, e.g. `example.com`
+    pub rp_id: String,
+    /// name the authenticator shows, defaults to `rp_id`
+    #[serde(default)]
+    pub rp_name: Option<String>,
+    /// origin of the web client, e.g. `https://app.example.com`
+    pub origin: String,
+    /// how long a registration or login ceremony may take
+    #[serde(default = "default_passkey_challenge_ttl", deserialize_with = "deserialize_duration")]
+    pub challenge_ttl: Duration,
+}
+
+fn default_passkey_challenge_ttl() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+/// Sliding window request limit applied to every authenticated token.
+///
+/// Users are limited per user, service keys each on their own so an integration never eats the
+/// budget of the interactive clients of its identity.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub window: Duration,
+    pub max_requests: u64,
+    /// limit of a service key, `max_requests` when omitted
+    #[serde(default)]
+    pub service_key_max_requests: Option<u64>,
+    /// dict<service key id, limit>, for the keys needing another limit
+    #[serde(default)]
+    pub service_keys: HashMap<String, u64>,
+}
+
+/// Writes of a user to a collection are refused for a while once too many of them failed
+/// validation within the window.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidationLimit {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub window: Duration,
+    pub max_failures: u64,
+}
+
+/// Page size of every listing endpoint: data, history, ACL, friends and notifications.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PageSize {
+    /// used when the request gives no limit
+    #[serde(default = "default_page_size")]
+    pub default: usize,
+    /// larger limits are lowered to it
+    #[serde(default = "default_max_page_size")]
+    pub max: usize,
+}
+
+impl Default for PageSize {
+    fn default() -> Self {
+        Self {
+            default: default_page_size(),
+            max: default_max_page_size(),
+        }
+    }
+}
+
+impl PageSize {
+    /// Limit of one page, a missing limit takes the default and a zero limit still returns one item.
+    pub fn resolve(&self, limit: Option<usize>) -> usize {
+        limit.unwrap_or(self.default).min(self.max).max(1)
+    }
+}
+
+/// Time a handler may run before it is cancelled with 504, the longest matching route prefix wins.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RequestTimeout {
+    /// for the routes not listed, no limit when omitted
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub default: Option<Duration>,
+    /// dict<path prefix, timeout>, e.g. `"/api/export" = "5m"`
+    #[serde(default, deserialize_with = "deserialize_duration_map")]
+    pub routes: HashMap<String, Duration>,
+}
+
+impl RequestTimeout {
+    pub fn timeout_for(&self, path: &str) -> Option<Duration> {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, timeout)| *timeout)
+            .or(self.default)
+    }
+}
+
+fn default_page_size() -> usize {
+    50
+}
+
+fn default_max_page_size() -> usize {
+    1000
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_optional_duration(deserializer)?.ok_or_else(|| D::Error::custom("missing duration"))
+}
+
+fn deserialize_duration_map<'de, D>(deserializer: D) -> Result<HashMap<String, Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Timeout(#[serde(deserialize_with = "deserialize_duration")] Duration);
+
+    let map = HashMap::<String, Timeout>::deserialize(deserializer)?;
+    Ok(map.into_iter().map(|(key, Timeout(value))| (key, value)).collect())
 }
 
 fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
@@ -40,4 +273,167 @@ pub struct Jwt {
 #[derive(Debug, Deserialize)]
 pub struct StoreConfig {
     pub directory: String,
+    /// dict<namespace, policy>, namespaces not listed here stay open for every user.
+    #[serde(default)]
+    pub namespace_policies: HashMap<String, NamespacePolicy>,
+    #[serde(default)]
+    pub orphan_cleanup: Option<OrphanCleanup>,
+    /// start in the read-only maintenance mode, switched off through the admin api
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub blob_offload: Option<BlobOffloadConfig>,
+    /// base64 of the 32 byte key encrypting the `x-encrypt-fields`, see `backend::encrypt`
+    #[serde(default)]
+    pub field_encryption_key: Option<String>,
+    /// dict<namespace, database file>, for namespaces not stored as `<directory>/<namespace>.db`
+    #[serde(default)]
+    pub database_paths: HashMap<String, String>,
+    /// directory of the users and notifications databases, defaults to `<directory>/inner`
+    #[serde(default)]
+    pub inner_directory: Option<String>,
+    /// namespaces whose existing database is served read-only, every mutation is rejected
+    #[serde(default)]
+    pub read_only_namespaces: Vec<String>,
+    /// dict<namespace, key>, namespaces whose database is encrypted at rest with SQLCipher, needs
+    /// syncstore built with the `sqlcipher` feature
+    #[serde(default)]
+    pub encryption_keys: HashMap<String, String>,
+    /// how often the buffered reads of the `x-access-stats` collections are written
+    #[serde(default = "default_access_stats_flush", deserialize_with = "deserialize_duration")]
+    pub access_stats_flush: Duration,
+    /// how long a deactivated account can still be restored before it is purged
+    #[serde(default = "default_deactivation_grace", deserialize_with = "deserialize_duration")]
+    pub deactivation_grace: Duration,
+    /// how often the expired documents of the `x-ttl` collections are deleted
+    #[serde(default = "default_expiry_sweep", deserialize_with = "deserialize_duration")]
+    pub expiry_sweep: Duration,
+    /// how long the tombstone of a deleted document stays in the change feed, a client offline for
+    /// longer syncs again from the start
+    #[serde(default = "default_tombstone_retention", deserialize_with = "deserialize_duration")]
+    pub tombstone_retention: Duration,
+    #[serde(default)]
+    pub write_queue: WriteQueueConfig,
+}
+
+fn default_access_stats_flush() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_deactivation_grace() -> Duration {
+    Duration::from_secs(30 * 24 * 60 * 60)
+}
+
+fn default_expiry_sweep() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_tombstone_retention() -> Duration {
+    Duration::from_secs(90 * 24 * 60 * 60)
+}
+
+pub const DEFAULT_INTERACTIVE_BURST: usize = 8;
+
+/// Limits of the write queue, see `backend::write_queue`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WriteQueueConfig {
+    /// writes running at a time, by default one per writer connection, i.e. per writable database
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    /// interactive writes let in a row before a waiting batch write
+    #[serde(default = "default_interactive_burst")]
+    pub interactive_burst: usize,
+}
+
+impl Default for WriteQueueConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: None,
+            interactive_burst: DEFAULT_INTERACTIVE_BURST,
+        }
+    }
+}
+
+fn default_interactive_burst() -> usize {
+    DEFAULT_INTERACTIVE_BURST
+}
+
+impl StoreConfig {
+    pub fn layout(&self) -> StoreLayout {
+        StoreLayout {
+            namespace_paths: self
+                .database_paths
+                .iter()
+                .map(|(namespace, path)| (namespace.clone(), PathBuf::from(path)))
+                .collect(),
+            inner_dir: self.inner_directory.as_ref().map(PathBuf::from),
+            read_only_namespaces: self.read_only_namespaces.iter().cloned().collect(),
+            encryption_keys: self.encryption_keys.clone(),
+        }
+    }
+}
+
+/// Body fields encoded larger than `threshold` bytes are stored as blobs, see `backend::blob`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlobOffloadConfig {
+    pub threshold: usize,
+    /// defaults to `blobs` under the store directory
+    #[serde(default)]
+    pub directory: Option<String>,
+}
+
+/// Periodic orphan cleanup of every namespace, see `Store::cleanup_orphans`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrphanCleanup {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub jitter: Option<Duration>,
+    #[serde(default = "default_orphan_policy")]
+    pub policy: OrphanPolicy,
+}
+
+fn default_orphan_policy() -> OrphanPolicy {
+    OrphanPolicy::Report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_size_resolve() {
+        let page_size: PageSize = toml::from_str("max = 200").unwrap();
+        assert_eq!(page_size.default, 50);
+        assert_eq!(page_size.resolve(None), 50);
+        assert_eq!(page_size.resolve(Some(0)), 1);
+        assert_eq!(page_size.resolve(Some(150)), 150);
+        assert_eq!(page_size.resolve(Some(5000)), 200);
+
+        // a default above the max is capped as well
+        let page_size = PageSize { default: 500, max: 100 };
+        assert_eq!(page_size.resolve(None), 100);
+    }
+
+    #[test]
+    fn test_request_timeout_for_route() {
+        let timeout: RequestTimeout = toml::from_str(
+            r#"
+            default = "30s"
+            routes = { "/api/data" = "10s", "/api/data/chat" = 2000, "/api/export" = "5m" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            timeout.timeout_for("/api/data/blog/post"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            timeout.timeout_for("/api/data/chat/message"),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(timeout.timeout_for("/api/export/blog"), Some(Duration::from_secs(300)));
+        assert_eq!(timeout.timeout_for("/api/user/friends"), Some(Duration::from_secs(30)));
+        assert_eq!(RequestTimeout::default().timeout_for("/api/data"), None);
+    }
 }
@@ -20,6 +20,23 @@ pub enum StoreError {
 
     #[error("permission denied")]
     PermissionDenied,
+
+    /// denied for a reason worth telling the caller, unlike `PermissionDenied`
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("conflict: {0}")]
+    Conflict(String),
+
+    /// the user holds as many of a resource as allowed, e.g. passkeys or saved views
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("rate limited: {0}")]
+    RateLimited(String),
 }
 
 pub type StoreResult<T> = std::result::Result<T, StoreError>;
@@ -46,51 +63,88 @@ pub enum ServiceError {
     #[error("Forbidden: {0}")]
     Forbidden(String),
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
     #[error("Internal server error: {0}")]
     InternalServerError(String),
+
+    /// the handler ran past the request timeout
+    #[error("Timeout: {0}")]
+    Timeout(String),
 }
 
 pub type ServiceResult<T> = std::result::Result<T, ServiceError>;
 
-impl Scribe for ServiceError {
-    fn render(self, res: &mut salvo::Response) {
-        res.render(format!("{self}"));
+impl StoreError {
+    pub fn status_code(&self) -> StatusCode {
         match self {
-            ServiceError::RequestError(_) => {
-                res.status_code(StatusCode::BAD_REQUEST);
-            }
-            ServiceError::Unauthorized(_) => {
-                res.status_code(StatusCode::UNAUTHORIZED);
-            }
-            ServiceError::Forbidden(_) => {
-                res.status_code(StatusCode::FORBIDDEN);
-            }
-            ServiceError::StoreError(store_error) => match &store_error {
-                StoreError::NotFound(_) => {
-                    res.status_code(StatusCode::NOT_FOUND);
-                }
-                StoreError::Validation(_) => {
-                    res.status_code(StatusCode::BAD_REQUEST);
-                }
-                StoreError::PermissionDenied => {
-                    res.status_code(StatusCode::FORBIDDEN);
-                }
-                _ => {
-                    res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            },
-            ServiceError::JwtError(_) | ServiceError::HpkeError(_) => {
-                res.status_code(StatusCode::UNAUTHORIZED);
-            }
-            ServiceError::InternalServerError(_) => {
-                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+            StoreError::NotFound(_) => StatusCode::NOT_FOUND,
+            StoreError::Validation(_) => StatusCode::BAD_REQUEST,
+            StoreError::PermissionDenied | StoreError::Forbidden(_) => StatusCode::FORBIDDEN,
+            StoreError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            // the request is fine but the resources held already leave no room for it
+            StoreError::Conflict(_) | StoreError::QuotaExceeded(_) => StatusCode::CONFLICT,
+            StoreError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            StoreError::Backend(_) | StoreError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl ServiceError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ServiceError::RequestError(_) => StatusCode::BAD_REQUEST,
+            ServiceError::StoreError(store_error) => store_error.status_code(),
+            ServiceError::JwtError(_) | ServiceError::HpkeError(_) | ServiceError::Unauthorized(_) => {
+                StatusCode::UNAUTHORIZED
             }
+            ServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ServiceError::Conflict(_) | ServiceError::QuotaExceeded(_) => StatusCode::CONFLICT,
+            ServiceError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ServiceError::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServiceError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
         }
     }
 }
 
+impl Scribe for ServiceError {
+    fn render(self, res: &mut salvo::Response) {
+        res.status_code(self.status_code());
+        res.render(format!("{self}"));
+    }
+}
+
+// every status an error may be answered with, the endpoints keep the ones of their `status_codes`
+const ERROR_RESPONSES: [(StatusCode, &str); 9] = [
+    (StatusCode::BAD_REQUEST, "Bad Request"),
+    (StatusCode::UNAUTHORIZED, "Unauthorized"),
+    (StatusCode::FORBIDDEN, "Forbidden"),
+    (StatusCode::NOT_FOUND, "Not Found"),
+    (StatusCode::CONFLICT, "Conflict or quota exceeded"),
+    (StatusCode::TOO_MANY_REQUESTS, "Rate limited"),
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+    (StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable"),
+    (StatusCode::GATEWAY_TIMEOUT, "Timeout"),
+];
+
 impl EndpointOutRegister for ServiceError {
-    fn register(_components: &mut salvo::oapi::Components, _operation: &mut salvo::oapi::Operation) {}
+    fn register(_components: &mut salvo::oapi::Components, operation: &mut salvo::oapi::Operation) {
+        for (status, description) in ERROR_RESPONSES {
+            // keep the descriptions the endpoints declare themselves
+            if !operation.responses.contains_key(status.as_str()) {
+                operation
+                    .responses
+                    .insert(status.as_str(), salvo::oapi::Response::new(description));
+            }
+        }
+    }
 }
 
 // for depot.get/obtain
@@ -135,3 +189,26 @@ impl From<chrono::ParseError> for StoreError {
         StoreError::Backend(error.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_error_status_codes() {
+        let cases = [
+            (StoreError::PermissionDenied, StatusCode::FORBIDDEN),
+            (StoreError::Forbidden("policy".into()), StatusCode::FORBIDDEN),
+            (StoreError::Conflict("locked".into()), StatusCode::CONFLICT),
+            (StoreError::QuotaExceeded("full".into()), StatusCode::CONFLICT),
+            (
+                StoreError::RateLimited("slow down".into()),
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            (StoreError::Backend("db".into()), StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+        for (error, status) in cases {
+            assert_eq!(ServiceError::from(error).status_code(), status);
+        }
+    }
+}
@@ -18,9 +18,29 @@ pub mod utils;
 
 pub async fn init_service(store: Arc<store::Store>, config: &config::ServiceConfig) -> anyhow::Result<()> {
     utils::jwt::set_jwt_config(&config.jwt);
+    if let Some(replication) = &config.replication {
+        let target = Arc::new(components::HttpReplicationTarget::new(replication)?);
+        store.schedule_replication(target, replication)?;
+    }
+    if let Some(webhooks) = &config.webhooks {
+        let sender = Arc::new(components::HttpWebhookSender::new(webhooks)?);
+        store.schedule_webhooks(sender, webhooks)?;
+    }
+    store.scheduler().start()?;
+    if let Some(delivery) = &config.delivery {
+        store.set_delivery_channel(components::build_channel(delivery)?)?;
+    }
 
-    let api_router = Router::new().push(Router::with_path("api").push(router::create_router(config, store.clone())));
-    let admin_router = Router::new().push(Router::with_path("admin").push(router::admin_router(store)));
+    let metrics = Arc::new(router::RequestMetrics::new());
+    let rate_limiter = Arc::new(router::RateLimiter::new(config.rate_limit.clone()));
+    let api_router = Router::new().push(Router::with_path("api").push(router::create_router(
+        config,
+        store.clone(),
+        metrics.clone(),
+        rate_limiter.clone(),
+    )));
+    let admin_router =
+        Router::new().push(Router::with_path("admin").push(router::admin_router(store, metrics, rate_limiter)));
 
     // make the openapi doc schema names more readable
     salvo::oapi::naming::set_namer(
@@ -2,26 +2,240 @@ use std::sync::Arc;
 
 use salvo::{
     Depot, Router, Scribe, Writer,
-    oapi::{RouterExt, ToResponse, ToSchema, endpoint, extract::PathParam},
+    oapi::{
+        ToResponse, ToSchema, endpoint,
+        extract::{PathParam, QueryParam},
+    },
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::ServiceResult,
-    router::hpke_wrapper::{HpkeRequest, HpkeResponse},
+    config::PageSize,
+    error::{ServiceError, ServiceResult},
+    router::{
+        data::PageInfo,
+        hpke_wrapper::{HpkeRequest, HpkeResponse},
+        metrics::TaggedRouter,
+    },
     store::Store,
-    types::{AccessControl, Permission, UserSchema},
+    types::{AccessControl, AclAction, AclDirection, AclEntry, Permission, PermissionExplanation, UserSchema},
+    utils::constant::MAX_BATCH_ITEMS,
 };
 
 pub fn create_router() -> Router {
-    Router::with_path("{namespace}/{collection}")
+    Router::new()
+        .push(Router::with_path("explain").get(explain_acl))
+        .push(Router::with_path("check").post(check_acl))
+        .push(Router::with_path("granted").get(list_granted_acls))
+        .push(Router::with_path("received").get(list_received_acls))
         .push(
-            Router::with_path("{id}")
-                .get(get_acl)
-                .post(update_acl)
-                .delete(delete_acl),
+            Router::with_path("{namespace}/{collection}")
+                .hoop(super::transaction::transaction)
+                .push(Router::with_path("batch").post(update_acls_bulk))
+                .push(
+                    Router::with_path("{id}")
+                        .get(get_acl)
+                        .post(update_acl)
+                        .delete(delete_acl),
+                ),
         )
-        .oapi_tag("acl")
+        .tagged("acl")
+}
+
+/// Check the current user's permissions on a batch of data
+///
+/// Missing data is reported as denied, at most 100 checks per request.
+#[endpoint(
+    status_codes(200, 400),
+    request_body(content = CheckAclRequest, description = "Permissions to check"),
+    responses(
+        (status_code = 200, description = "Check permissions successfully", body = CheckAclResponse),
+        (status_code = 400, description = "Bad Request"),
+    )
+)]
+async fn check_acl(
+    req: HpkeRequest<CheckAclRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<CheckAclResponse>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user = depot.get::<UserSchema>("user_schema")?;
+    if req.0.checks.len() > MAX_BATCH_ITEMS {
+        Err(ServiceError::RequestError(format!(
+            "Permission check limit exceeded: maximum {} checks per request",
+            MAX_BATCH_ITEMS
+        )))?;
+    }
+    let user_id = user.user_id.clone();
+    let req = req.0;
+    let (allowed, checks) = store
+        .run(move |store| {
+            let checks = req
+                .checks
+                .iter()
+                .map(|c| (c.collection.as_str(), c.data_id.as_str(), c.action))
+                .collect::<Vec<_>>();
+            let allowed = store.check_permissions(&req.namespace, &checks, &user_id)?;
+            Ok((allowed, req.checks))
+        })
+        .await?;
+    let results = checks
+        .into_iter()
+        .zip(allowed)
+        .map(|(check, allowed)| CheckAclResult { check, allowed })
+        .collect();
+    Ok(HpkeResponse(CheckAclResponse { results }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CheckAclRequest {
+    namespace: String,
+    checks: Vec<AclCheck>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AclCheck {
+    collection: String,
+    data_id: String,
+    action: AclAction,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CheckAclResult {
+    #[serde(flatten)]
+    check: AclCheck,
+    allowed: bool,
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+pub struct CheckAclResponse {
+    /// in the order of the request checks
+    results: Vec<CheckAclResult>,
+}
+
+impl Scribe for CheckAclResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Explain why a user can or can not access the data
+///
+/// Runs the same checks as the data operations and returns the decision path: owner match,
+/// ACL entry and the ancestors visited. `user` defaults to the current user, explaining
+/// another user needs full access on the data.
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "Explain permission successfully", body = PermissionExplanation),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Not Found")
+    )
+)]
+async fn explain_acl(
+    namespace: QueryParam<String, true>,
+    collection: QueryParam<String, true>,
+    data_id: QueryParam<String, true>,
+    user: QueryParam<String, false>,
+    action: QueryParam<AclAction, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<PermissionExplanation>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let current = depot.get::<UserSchema>("user_schema")?;
+    let current_id = current.user_id.clone();
+    let target = user.into_inner().unwrap_or_else(|| current_id.clone());
+    let action = action.into_inner().unwrap_or_default();
+    let explanation = store
+        .run(move |store| {
+            store.explain_permission(
+                (namespace.as_str(), collection.as_str()),
+                data_id.as_str(),
+                &target,
+                action,
+                &current_id,
+            )
+        })
+        .await?;
+    Ok(HpkeResponse(explanation))
+}
+
+/// List the ACL entries the current user granted on their data
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "List ACL entries successfully", body = ListAclResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Not Found")
+    )
+)]
+async fn list_granted_acls(
+    namespace: QueryParam<String, true>,
+    collection: QueryParam<String, false>,
+    marker: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListAclResponse>> {
+    list_acls(AclDirection::Granted, namespace, collection, marker, limit, depot).await
+}
+
+/// List the ACL entries granting the current user access to data of others
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "List ACL entries successfully", body = ListAclResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Not Found")
+    )
+)]
+async fn list_received_acls(
+    namespace: QueryParam<String, true>,
+    collection: QueryParam<String, false>,
+    marker: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListAclResponse>> {
+    list_acls(AclDirection::Received, namespace, collection, marker, limit, depot).await
+}
+
+async fn list_acls(
+    direction: AclDirection,
+    namespace: QueryParam<String, true>,
+    collection: QueryParam<String, false>,
+    marker: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListAclResponse>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let limit = depot.obtain::<PageSize>()?.resolve(limit.into_inner());
+    let user_id = user.user_id.clone();
+    let (items, next_marker) = store
+        .run(move |store| {
+            store.list_acls(
+                namespace.as_str(),
+                direction,
+                collection.as_deref(),
+                marker.into_inner(),
+                limit,
+                &user_id,
+            )
+        })
+        .await?;
+    Ok(HpkeResponse(ListAclResponse {
+        page_info: PageInfo::forward(items.len(), next_marker),
+        items,
+    }))
+}
+
+#[derive(Serialize, ToSchema, ToResponse)]
+pub struct ListAclResponse {
+    items: Vec<AclEntry>,
+    page_info: PageInfo,
+}
+
+impl Scribe for ListAclResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
 }
 
 /// Update ACL for specified resources
@@ -47,7 +261,10 @@ async fn update_acl(
         data_id: id.to_string(),
         permissions: req.0.permissions.clone(),
     };
-    store.update_acl((namespace.as_str(), collection.as_str()), acl, &user.user_id)?;
+    let user_id = user.user_id.clone();
+    store
+        .run(move |store| store.update_acl((namespace.as_str(), collection.as_str()), acl, &user_id))
+        .await?;
     tracing::info!("update_acl for data {}", id.as_str());
     Ok(HpkeResponse("success".to_string()))
 }
@@ -57,6 +274,62 @@ pub struct CreateAclRequest {
     permissions: Vec<Permission>,
 }
 
+/// Grant and revoke permissions on many data at once
+///
+/// Every data must be owned by the current user, nothing is applied otherwise. The entries of
+/// users neither granted nor revoked are kept, at most 100 data per request.
+#[endpoint(
+    status_codes(201, 400, 403, 404),
+    request_body(content = BulkAclRequest, description = "Permissions to apply"),
+    responses(
+        (status_code = 201, description = "ACL updated successfully"),
+        (status_code = 400, description = "Bad Request"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Not Found")
+    )
+)]
+async fn update_acls_bulk(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    req: HpkeRequest<BulkAclRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<String>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let req = req.0;
+    if req.data_ids.len() > MAX_BATCH_ITEMS {
+        Err(ServiceError::RequestError(format!(
+            "Batch ACL limit exceeded: maximum {} data per request",
+            MAX_BATCH_ITEMS
+        )))?;
+    }
+    let (user_id, count) = (user.user_id.clone(), req.data_ids.len());
+    store
+        .run(move |store| {
+            store.update_acls_bulk(
+                (namespace.as_str(), collection.as_str()),
+                &req.data_ids,
+                req.grant,
+                req.revoke,
+                &user_id,
+            )
+        })
+        .await?;
+    tracing::info!("update_acls_bulk for {} data", count);
+    Ok(HpkeResponse("success".to_string()))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BulkAclRequest {
+    data_ids: Vec<String>,
+    /// set the access level of these users, replacing their current one
+    #[serde(default)]
+    grant: Vec<Permission>,
+    /// users losing their access
+    #[serde(default)]
+    revoke: Vec<String>,
+}
+
 /// Get ACL for specified resources
 #[endpoint(
     status_codes(200, 403, 404),
@@ -74,7 +347,10 @@ async fn get_acl(
 ) -> ServiceResult<HpkeResponse<GetAclResponse>> {
     let store = depot.obtain::<Arc<Store>>()?;
     let user = depot.get::<UserSchema>("user_schema")?;
-    let acl = store.get_data_acl((namespace.as_str(), collection.as_str()), id.as_str(), &user.user_id)?;
+    let (user_id, data_id) = (user.user_id.clone(), id.to_string());
+    let acl = store
+        .run(move |store| store.get_data_acl((namespace.as_str(), collection.as_str()), &data_id, &user_id))
+        .await?;
     tracing::info!("get_acl for data {}", id.as_str());
     Ok(HpkeResponse(GetAclResponse {
         permissions: acl.permissions,
@@ -109,7 +385,10 @@ async fn delete_acl(
 ) -> ServiceResult<()> {
     let store = depot.obtain::<Arc<Store>>()?;
     let user = depot.get::<UserSchema>("user_schema")?;
-    store.delete_acl((namespace.as_str(), collection.as_str()), id.as_str(), &user.user_id)?;
+    let (user_id, data_id) = (user.user_id.clone(), id.to_string());
+    store
+        .run(move |store| store.delete_acl((namespace.as_str(), collection.as_str()), &data_id, &user_id))
+        .await?;
     tracing::info!("delete_acl for data {}", id.as_str());
     Ok(())
 }
@@ -1,12 +1,105 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
-use salvo::{Depot, Response, Router, Writer, handler, oapi::extract::JsonBody};
+use salvo::{
+    Depot, Response, Router, Writer, handler,
+    http::StatusCode,
+    oapi::extract::{JsonBody, PathParam},
+    writing::{Json, Text},
+};
 use serde::Deserialize;
 
-use crate::{error::ServiceResult, store::Store};
+use crate::{
+    backend::{
+        filter::FilterExpr,
+        write_queue::{WriteQueueStats, WriteTier},
+    },
+    components::JobStatus,
+    error::{ServiceResult, StoreError},
+    router::{
+        metrics::{self, RequestMetrics, RouteMetrics},
+        rate_limit::{RateKey, RateLimiter, UsageSnapshot},
+    },
+    store::Store,
+    types::{
+        BackupInfo, CollectionMode, CollectionSwitches, DeactivatedUser, GeneratedData, MaintenanceMode,
+        NamespaceHealth, NamespacePolicy, Orphan, OrphanPolicy, OrphanReport, ReplicationAck, ReplicationBatch,
+        ReplicationStatus, ServiceAccess, ServiceKey, VacuumReport, Webhook, WebhookDelivery, WebhookEventKind,
+    },
+};
 
 pub fn create_router() -> Router {
-    Router::new().push(Router::with_path("register").post(register))
+    Router::new()
+        .push(Router::with_path("register").post(register))
+        .push(Router::with_path("users/deactivated").get(list_deactivated_users))
+        .push(Router::with_path("users/{id}/restore").post(restore_user))
+        .push(
+            Router::with_path("namespace/{namespace}/policy")
+                .get(get_namespace_policy)
+                .post(set_namespace_policy),
+        )
+        .push(Router::with_path("namespace/{namespace}/snapshot").get(snapshot_namespace))
+        .push(
+            Router::with_path("namespace/{namespace}/collection/{collection}/mode")
+                .get(get_collection_mode)
+                .post(set_collection_mode),
+        )
+        .push(
+            Router::with_path("namespace/{namespace}/collection/{collection}/switches")
+                .get(get_collection_switches)
+                .post(set_collection_switches),
+        )
+        .push(Router::with_path("namespace/{namespace}/collection/{collection}/generate").post(generate_data))
+        .push(
+            Router::with_path("namespace/{namespace}/webhooks")
+                .get(list_webhooks)
+                .post(create_webhook)
+                .push(Router::with_path("{id}").delete(delete_webhook)),
+        )
+        .push(
+            Router::with_path("namespace/{namespace}/service-keys")
+                .get(list_service_keys)
+                .post(create_service_key)
+                .push(
+                    Router::with_path("{id}")
+                        .post(set_service_key_grants)
+                        .delete(revoke_service_key)
+                        .push(Router::with_path("usage").get(service_key_usage)),
+                ),
+        )
+        .push(
+            Router::with_path("namespace/{namespace}/dead_letters")
+                .get(list_dead_letters)
+                .push(Router::with_path("{id}/replay").post(replay_dead_letter)),
+        )
+        .push(
+            Router::with_path("maintenance/mode")
+                .get(get_maintenance_mode)
+                .post(set_maintenance_mode),
+        )
+        .push(Router::with_path("maintenance/{namespace}").post(vacuum_namespace))
+        .push(
+            Router::with_path("maintenance/orphans/{namespace}")
+                .get(list_orphans)
+                .post(cleanup_orphans),
+        )
+        .push(
+            Router::with_path("jobs")
+                .get(list_jobs)
+                .push(Router::with_path("{name}/run").post(run_job)),
+        )
+        .push(Router::with_path("backup/{namespace}").post(backup_namespace))
+        .push(Router::with_path("health").get(namespace_health))
+        .push(
+            Router::with_path("metrics")
+                .get(request_metrics)
+                .push(Router::with_path("prometheus").get(request_metrics_prometheus))
+                .push(Router::with_path("write-queue").get(write_queue_stats)),
+        )
+        .push(
+            Router::with_path("replication")
+                .get(replication_status)
+                .push(Router::with_path("{namespace}/apply").post(apply_replication)),
+        )
 }
 
 #[handler]
@@ -22,3 +115,421 @@ struct RegisterRequest {
     username: String,
     password: String,
 }
+
+#[handler]
+async fn get_namespace_policy(namespace: PathParam<String>, depot: &mut Depot) -> ServiceResult<Json<NamespacePolicy>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.get_namespace_policy(&namespace)?))
+}
+
+#[handler]
+async fn set_namespace_policy(
+    namespace: PathParam<String>,
+    body: JsonBody<NamespacePolicy>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<NamespacePolicy>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    store.set_namespace_policy(&namespace, body.0)?;
+    tracing::info!("namespace policy updated for {}", namespace.as_str());
+    Ok(Json(store.get_namespace_policy(&namespace)?))
+}
+
+#[handler]
+async fn get_collection_mode(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<CollectionMode>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.collection_mode(&namespace, &collection)?))
+}
+
+/// Make a collection read-only or writable again, e.g. when sunsetting it or freezing legacy data.
+#[handler]
+async fn set_collection_mode(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    body: JsonBody<CollectionModeRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<CollectionMode>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let body = body.into_inner();
+    Ok(Json(store.set_collection_mode(
+        &namespace,
+        &collection,
+        body.read_only,
+        body.reason,
+    )?))
+}
+
+#[derive(Deserialize)]
+struct CollectionModeRequest {
+    read_only: bool,
+    reason: Option<String>,
+}
+
+#[handler]
+async fn get_collection_switches(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<CollectionSwitches>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.collection_switches(&namespace, &collection)?))
+}
+
+/// Disable reads and/or writes of a single collection, requests hitting it are answered with 503.
+#[handler]
+async fn set_collection_switches(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    body: JsonBody<CollectionSwitches>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<CollectionSwitches>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.set_collection_switches(
+        &namespace,
+        &collection,
+        body.into_inner(),
+    )?))
+}
+
+/// Fill a collection with documents generated from its schema, for development databases.
+#[handler]
+async fn generate_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    body: JsonBody<GenerateDataRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<GeneratedData>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let body = body.into_inner();
+    let report = store
+        .run_write(WriteTier::Batch, move |store| {
+            store.generate_fake_data(&namespace, &collection, body.count, &body.owner)
+        })
+        .await?;
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+struct GenerateDataRequest {
+    count: usize,
+    /// user id owning the documents
+    owner: String,
+}
+
+#[handler]
+async fn list_webhooks(namespace: PathParam<String>, depot: &mut Depot) -> ServiceResult<Json<Vec<Webhook>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.list_webhooks(&namespace)?))
+}
+
+/// Register a webhook, the response is the only one carrying its signing secret.
+#[handler]
+async fn create_webhook(
+    namespace: PathParam<String>,
+    body: JsonBody<CreateWebhookRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<Webhook>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let body = body.into_inner();
+    Ok(Json(store.create_webhook(
+        &namespace,
+        &body.url,
+        body.collections,
+        body.events,
+        body.filter,
+        body.secret,
+    )?))
+}
+
+#[derive(Deserialize)]
+struct CreateWebhookRequest {
+    url: String,
+    #[serde(default)]
+    collections: Vec<String>,
+    /// every kind when empty
+    #[serde(default)]
+    events: Vec<WebhookEventKind>,
+    /// only upserts of matching documents are delivered
+    filter: Option<FilterExpr>,
+    /// generated when absent
+    secret: Option<String>,
+}
+
+#[handler]
+async fn delete_webhook(
+    namespace: PathParam<String>,
+    id: PathParam<String>,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> ServiceResult<()> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    store.delete_webhook(&namespace, &id)?;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+#[handler]
+async fn list_service_keys(namespace: PathParam<String>, depot: &mut Depot) -> ServiceResult<Json<Vec<ServiceKey>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.list_service_keys(&namespace)?))
+}
+
+/// Create a service key, the response is the only one carrying the key. Jobs send it as the
+/// `X-Api-Key` header of the data requests to the namespace.
+#[handler]
+async fn create_service_key(
+    namespace: PathParam<String>,
+    body: JsonBody<CreateServiceKeyRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<ServiceKey>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let body = body.into_inner();
+    Ok(Json(store.create_service_key(&namespace, &body.name, body.grants)?))
+}
+
+#[derive(Deserialize)]
+struct CreateServiceKeyRequest {
+    name: String,
+    #[serde(default)]
+    grants: BTreeMap<String, ServiceAccess>,
+}
+
+/// Replace the collections a service key is granted.
+#[handler]
+async fn set_service_key_grants(
+    namespace: PathParam<String>,
+    id: PathParam<String>,
+    body: JsonBody<SetServiceKeyGrantsRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<ServiceKey>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.set_service_key_grants(
+        &namespace,
+        &id,
+        body.into_inner().grants,
+    )?))
+}
+
+#[derive(Deserialize)]
+struct SetServiceKeyGrantsRequest {
+    grants: BTreeMap<String, ServiceAccess>,
+}
+
+#[handler]
+async fn revoke_service_key(
+    namespace: PathParam<String>,
+    id: PathParam<String>,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> ServiceResult<()> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    store.revoke_service_key(&namespace, &id)?;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// Request counters of a service key, accounted apart from the users like its rate limit.
+#[handler]
+async fn service_key_usage(
+    namespace: PathParam<String>,
+    id: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<UsageSnapshot>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    if !store.list_service_keys(&namespace)?.iter().any(|key| key.id == *id) {
+        return Err(StoreError::NotFound(format!("service key {}", *id)).into());
+    }
+    let limiter = depot.obtain::<Arc<RateLimiter>>()?;
+    Ok(Json(limiter.usage(&RateKey::ServiceKey(id.into_inner()))))
+}
+
+/// Webhook deliveries that failed every attempt.
+#[handler]
+async fn list_dead_letters(
+    namespace: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<Vec<WebhookDelivery>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.dead_webhook_deliveries(&namespace)?))
+}
+
+#[handler]
+async fn replay_dead_letter(
+    namespace: PathParam<String>,
+    id: PathParam<i64>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<WebhookDelivery>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.replay_webhook_delivery(&namespace, *id)?))
+}
+
+#[handler]
+async fn get_maintenance_mode(depot: &mut Depot) -> ServiceResult<Json<MaintenanceMode>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.maintenance_mode()?))
+}
+
+/// Switch the read-only maintenance mode, mutations are answered with 503 while enabled.
+#[handler]
+async fn set_maintenance_mode(
+    body: JsonBody<MaintenanceModeRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<MaintenanceMode>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let body = body.into_inner();
+    Ok(Json(store.set_maintenance_mode(body.enabled, body.reason)?))
+}
+
+#[derive(Deserialize)]
+struct MaintenanceModeRequest {
+    enabled: bool,
+    reason: Option<String>,
+}
+
+/// Copy the live namespace database to a file on the server, see `Store::backup`.
+#[handler]
+async fn backup_namespace(
+    namespace: PathParam<String>,
+    body: JsonBody<BackupRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<BackupInfo>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let path = body.into_inner().path;
+    let info = store.run(move |store| store.backup(&namespace, &path)).await?;
+    Ok(Json(info))
+}
+
+#[derive(Deserialize)]
+struct BackupRequest {
+    /// destination file on the server, must not exist yet
+    path: String,
+}
+
+/// VACUUM and ANALYZE the namespace database, with an integrity check reported along.
+#[handler]
+async fn vacuum_namespace(namespace: PathParam<String>, depot: &mut Depot) -> ServiceResult<Json<VacuumReport>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.run(move |store| store.vacuum_namespace(&namespace)).await?))
+}
+
+#[handler]
+async fn list_orphans(namespace: PathParam<String>, depot: &mut Depot) -> ServiceResult<Json<Vec<Orphan>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.find_orphans(&namespace)?))
+}
+
+#[handler]
+async fn cleanup_orphans(
+    namespace: PathParam<String>,
+    body: JsonBody<OrphanPolicy>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<OrphanReport>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.cleanup_orphans(&namespace, &body)?))
+}
+
+/// Accounts deactivated by their users, purged once the grace period is over.
+#[handler]
+async fn list_deactivated_users(depot: &mut Depot) -> ServiceResult<Json<Vec<DeactivatedUser>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.run(|store| store.list_deactivated_users()).await?))
+}
+
+/// Restore a deactivated account, its login and data come back as they were.
+#[handler]
+async fn restore_user(id: PathParam<String>, depot: &mut Depot) -> ServiceResult<()> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    tracing::info!("restore user {}", id.as_str());
+    store.run(move |store| store.restore_user(&id).map(|_| ())).await?;
+    Ok(())
+}
+
+/// Request metrics of the api router, grouped by route tag and collection.
+#[handler]
+async fn request_metrics(depot: &mut Depot) -> ServiceResult<Json<Vec<RouteMetrics>>> {
+    let metrics = depot.obtain::<Arc<RequestMetrics>>()?;
+    Ok(Json(metrics.snapshot()))
+}
+
+/// The same metrics in the Prometheus text format, for scraping, along with the write queue.
+#[handler]
+async fn request_metrics_prometheus(depot: &mut Depot) -> ServiceResult<Text<String>> {
+    let metrics = depot.obtain::<Arc<RequestMetrics>>()?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let mut text = metrics.render_prometheus();
+    text.push_str(&metrics::render_write_queue(&store.write_queue().stats()));
+    Ok(Text::Plain(text))
+}
+
+/// Writes running and waiting in the write queue, by tier.
+#[handler]
+async fn write_queue_stats(depot: &mut Depot) -> ServiceResult<Json<WriteQueueStats>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.write_queue().stats()))
+}
+
+#[handler]
+async fn list_jobs(depot: &mut Depot) -> ServiceResult<Json<Vec<JobStatus>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.scheduler().list_status()?))
+}
+
+#[handler]
+async fn run_job(name: PathParam<String>, depot: &mut Depot) -> ServiceResult<Json<JobStatus>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    tracing::info!("job {} triggered manually", name.as_str());
+    // jobs are blocking, some of them block on the runtime themselves
+    let store = store.clone();
+    let name = name.into_inner();
+    let status = tokio::task::spawn_blocking(move || store.scheduler().run_now(&name))
+        .await
+        .map_err(|e| crate::error::ServiceError::InternalServerError(e.to_string()))??;
+    Ok(Json(status))
+}
+
+/// Health of every namespace database.
+#[handler]
+async fn namespace_health(depot: &mut Depot) -> ServiceResult<Json<Vec<NamespaceHealth>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.health()))
+}
+
+/// Replication lag of every namespace on the primary.
+#[handler]
+async fn replication_status(depot: &mut Depot) -> ServiceResult<Json<Vec<ReplicationStatus>>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    Ok(Json(store.replication_status()?))
+}
+
+/// Apply the changes pushed by the primary, on the secondary.
+#[handler]
+async fn apply_replication(
+    namespace: PathParam<String>,
+    body: JsonBody<ReplicationBatch>,
+    depot: &mut Depot,
+) -> ServiceResult<Json<ReplicationAck>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let batch = body.into_inner();
+    let ack = store
+        .run_write(WriteTier::Batch, move |store| {
+            store.apply_replication(&namespace, &batch)
+        })
+        .await?;
+    Ok(Json(ack))
+}
+
+/// Download a consistent SQLite copy of the namespace, sha256 in the X-Checksum-Sha256 header.
+#[handler]
+async fn snapshot_namespace(namespace: PathParam<String>, depot: &mut Depot, res: &mut Response) -> ServiceResult<()> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let snapshot = store.snapshot_namespace(&namespace)?;
+    super::export::write_snapshot(
+        res,
+        snapshot,
+        &format!("{}.db", namespace.as_str()),
+        "application/vnd.sqlite3",
+    )
+}
@@ -2,13 +2,14 @@ use std::sync::Arc;
 
 use salvo::{
     Depot, Response, Router, Scribe, Writer,
-    oapi::{RouterExt, ToResponse, ToSchema, endpoint, extract::JsonBody},
+    oapi::{ToResponse, ToSchema, endpoint, extract::JsonBody},
     writing::Json,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{ServiceError, ServiceResult},
+    router::metrics::TaggedRouter,
     store::Store,
     utils::jwt::{generate_jwt_token, generate_refresh_token, verify_refresh_token},
 };
@@ -18,7 +19,7 @@ use crate::{
 pub fn create_router() -> Router {
     Router::new()
         .push(Router::with_path("edit").post(edit))
-        .oapi_tag("auth_info")
+        .tagged("auth_info")
 }
 
 #[endpoint]
@@ -31,7 +32,7 @@ pub fn create_non_auth_router() -> Router {
     Router::new()
         .push(Router::with_path("name-login").post(login))
         .push(Router::with_path("refresh").post(refresh))
-        .oapi_tag("auth")
+        .tagged("auth")
 }
 
 /// Login with username and password
@@ -52,7 +53,11 @@ async fn login(
 ) -> ServiceResult<LoginResponse> {
     tracing::info!("Login attempt for user: {}", req.username);
     let store = depot.obtain::<Arc<Store>>()?;
-    let Some(user_id) = store.validate_user(&req.username, &req.password)? else {
+    let req = req.into_inner();
+    let Some(user_id) = store
+        .run(move |store| store.validate_user(&req.username, &req.password))
+        .await?
+    else {
         return Err(ServiceError::Unauthorized("Invalid username or password".to_string()));
     };
     let access_token = generate_jwt_token(user_id.clone())?;
@@ -129,12 +134,23 @@ struct RefreshRequest {
 
 /// Response data for login
 #[derive(Serialize, ToResponse, ToSchema)]
-struct LoginResponse {
+pub(super) struct LoginResponse {
     access_token: String,
     refresh_token: String,
     user_id: String,
 }
 
+impl LoginResponse {
+    /// A fresh token pair for the user.
+    pub(super) fn issue(user_id: String) -> ServiceResult<Self> {
+        Ok(LoginResponse {
+            access_token: generate_jwt_token(user_id.clone())?,
+            refresh_token: generate_refresh_token(user_id.clone())?,
+            user_id,
+        })
+    }
+}
+
 impl Scribe for LoginResponse {
     fn render(self, res: &mut salvo::Response) {
         res.render(Json(self));
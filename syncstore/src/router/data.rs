@@ -2,10 +2,13 @@ use std::sync::Arc;
 
 use itertools::Itertools;
 use salvo::{
-    Depot, Response, Router, Scribe, Writer,
-    http::StatusCode,
+    Depot, Request, Response, Router, Scribe, Writer,
+    http::{
+        HeaderValue, StatusCode,
+        header::{ETAG, IF_MATCH},
+    },
     oapi::{
-        RouterExt, ToResponse, ToSchema, endpoint,
+        ToResponse, ToSchema, endpoint,
         extract::{PathParam, QueryParam},
     },
     writing::Json,
@@ -13,10 +16,20 @@ use salvo::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    backend::{filter::FilterExpr, patch::PatchOp, write_queue::WriteTier},
+    config::PageSize,
     error::{ServiceError, ServiceResult},
-    router::hpke_wrapper::{HpkeRequest, HpkeResponse},
-    store::Store,
-    types::{DataItem, DataItemSummary, UserSchema},
+    router::{
+        fs::{UploadGrants, UploadSlot},
+        hpke_wrapper::{HpkeRequest, HpkeResponse},
+        metrics::TaggedRouter,
+    },
+    store::{Listing, Store},
+    types::{
+        AccessStats, Attachment, AttachmentContent, BatchOutcome, Change, CollectionStats, DataItem, DataItemSummary,
+        ImportMode, Imported, ItemLock, ListOrder, NamespaceUsage, PageCursor, UserSchema, ValidationReport,
+    },
+    utils::constant::{MAX_BATCH_ITEMS, MAX_BULK_INSERT_ITEMS},
 };
 
 pub fn create_batch_data_router() -> Router {
@@ -25,7 +38,9 @@ pub fn create_batch_data_router() -> Router {
         .push(Router::new().post(batch_get_data)) // todo, deprecated. remove this router in future version.
         .push(Router::with_path("by_ids").post(batch_get_data))
         .push(Router::with_path("by_parent_ids").post(batch_list_data_by_parent))
-        .oapi_tag("data")
+        .push(Router::with_path("insert").post(batch_insert_data))
+        .push(Router::with_path("query").post(query_data))
+        .tagged("data")
 }
 
 /// Batch list data items by parent IDs
@@ -46,11 +61,12 @@ async fn batch_list_data_by_parent(
 ) -> ServiceResult<HpkeResponse<ListDataResponse>> {
     let store = depot.obtain::<Arc<Store>>()?;
     let user = depot.get::<UserSchema>("user_schema")?;
-    if req.0.ids.len() > 100 {
+    if req.0.ids.len() > MAX_BATCH_ITEMS {
         // limit batch get to 100 items to prevent abuse
-        Err(ServiceError::RequestError(
-            "Batch get limit exceeded: maximum 100 items per request".to_string(),
-        ))?;
+        Err(ServiceError::RequestError(format!(
+            "Batch get limit exceeded: maximum {} items per request",
+            MAX_BATCH_ITEMS
+        )))?;
     }
     let mut items = Vec::new();
     let mut start_parent_id = None;
@@ -83,8 +99,15 @@ async fn batch_list_data_by_parent(
             None
         };
         loop {
-            let (children, marker) =
-                store.list_children(&namespace, &collection, parent_id, loop_marker, 100, &user.user_id)?;
+            let (namespace, collection, parent, user_id) = (
+                namespace.to_string(),
+                collection.to_string(),
+                parent_id.clone(),
+                user.user_id.clone(),
+            );
+            let (children, marker) = store
+                .run(move |store| store.list_children(&namespace, &collection, &parent, loop_marker, 100, &user_id))
+                .await?;
             let summary = children.into_iter().map(Into::into).collect::<Vec<DataItemSummary>>();
             for item in &summary {
                 accumulated_size += serde_json::to_string(item)
@@ -110,12 +133,12 @@ async fn batch_list_data_by_parent(
         }
     }
     Ok(HpkeResponse(ListDataResponse {
-        page_info: PageInfo {
-            count: items.len(),
-            next_marker: next_p_marker
+        page_info: PageInfo::forward(
+            items.len(),
+            next_p_marker
                 .zip(next_c_marker)
                 .map(|(parent_id, id)| format!("{}.{}", parent_id, id)),
-        },
+        ),
         items,
     }))
 }
@@ -137,17 +160,27 @@ async fn batch_get_data(
 ) -> ServiceResult<HpkeResponse<BatchGetDataResponse>> {
     let store = depot.obtain::<Arc<Store>>()?;
     let user = depot.get::<UserSchema>("user_schema")?;
-    if req.0.ids.len() > 100 {
+    if req.0.ids.len() > MAX_BATCH_ITEMS {
         // limit batch get to 100 items to prevent abuse
-        Err(ServiceError::RequestError(
-            "Batch get limit exceeded: maximum 100 items per request".to_string(),
-        ))?;
+        Err(ServiceError::RequestError(format!(
+            "Batch get limit exceeded: maximum {} items per request",
+            MAX_BATCH_ITEMS
+        )))?;
     }
     let mut items = Vec::new();
     let mut truncated = None;
     let mut accumulated_size = 0;
     for id in req.0.ids.iter().unique() {
-        if let Ok(item) = store.get(&namespace, &collection, id, &user.user_id) {
+        let (namespace, collection, item_id, user_id) = (
+            namespace.to_string(),
+            collection.to_string(),
+            id.clone(),
+            user.user_id.clone(),
+        );
+        if let Ok(item) = store
+            .run(move |store| store.get(&namespace, &collection, &item_id, &user_id))
+            .await
+        {
             // simple size check, can be optimized by only counting the body size, or even support streaming response for large data items.
             accumulated_size += serde_json::to_string(&item)
                 .map_err(|e| ServiceError::RequestError(format!("Failed to serialize data item: {e}")))?
@@ -168,6 +201,148 @@ async fn batch_get_data(
     Ok(HpkeResponse(BatchGetDataResponse { items, truncated }))
 }
 
+/// Query data items with a filter expression
+///
+/// Lists the user's own items, or with `parent_id` the children of a readable parent, matching e.g.
+/// `{"and": [{"eq": ["category", "news"]}, {"gt": ["created_at", "2024-01-01T00:00:00Z"]}]}`.
+/// Operators are `and`, `or`, `not`, `eq`, `ne`, `gt`, `gte`, `lt`, `lte`, `in` and `exists`.
+#[endpoint(
+    status_codes(200, 400, 403),
+    request_body(content = QueryDataRequest, description = "Filter expression"),
+    responses(
+        (status_code = 200, description = "Query data successfully", body = ListDataResponse),
+        (status_code = 400, description = "Bad Request"),
+        (status_code = 403, description = "FORBIDDEN")
+    )
+)]
+async fn query_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    req: HpkeRequest<QueryDataRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListDataResponse>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let req = req.0;
+    let limit = depot.obtain::<PageSize>()?.resolve(req.limit);
+    let user_id = user.user_id.clone();
+    let (items, next_marker) = store
+        .run(move |store| {
+            store.query(
+                &namespace,
+                &collection,
+                req.parent_id.as_deref(),
+                &req.filter,
+                req.marker,
+                limit,
+                &user_id,
+            )
+        })
+        .await?;
+    Ok(HpkeResponse(ListDataResponse {
+        page_info: PageInfo::forward(items.len(), next_marker),
+        items: items.into_iter().map(Into::into).collect(),
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct QueryDataRequest {
+    #[salvo(schema(value_type = Object))]
+    filter: FilterExpr,
+    parent_id: Option<String>,
+    marker: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Batch insert data items, e.g. the initial upload of a large dataset
+///
+/// Accepts a gzip `Content-Encoding`. Items are inserted one by one, a failed item doesn't stop the others.
+/// With `mode` `skip_duplicates` an item whose content the user already stored returns the existing id
+/// flagged as `duplicate`.
+#[endpoint(
+    status_codes(200, 400),
+    request_body(content = BatchInsertRequest, description = "Data items to create"),
+    responses(
+        (status_code = 200, description = "Batch insert processed", body = BatchInsertResponse),
+        (status_code = 400, description = "Bad Request"),
+    )
+)]
+async fn batch_insert_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    req: HpkeRequest<BatchInsertRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<BatchInsertResponse>> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user = depot.get::<UserSchema>("user_schema")?;
+    if req.0.items.len() > MAX_BULK_INSERT_ITEMS {
+        Err(ServiceError::RequestError(format!(
+            "Batch insert limit exceeded: maximum {} items per request",
+            MAX_BULK_INSERT_ITEMS
+        )))?;
+    }
+    let (ns, coll, user_id) = (namespace.to_string(), collection.to_string(), user.user_id.clone());
+    let req = req.0;
+    // one blocking task for the whole batch, the items are still inserted one by one
+    let results = store
+        .run_write(WriteTier::Batch, move |store| {
+            Ok(req
+                .items
+                .iter()
+                .map(
+                    |body| match store.insert_with_mode(&ns, &coll, body, &user_id, req.mode) {
+                        Ok(Imported::Inserted(id)) => BatchInsertResult {
+                            id: Some(id),
+                            duplicate: false,
+                            error: None,
+                        },
+                        Ok(Imported::Duplicate(id)) => BatchInsertResult {
+                            id: Some(id),
+                            duplicate: true,
+                            error: None,
+                        },
+                        Err(e) => BatchInsertResult {
+                            id: None,
+                            duplicate: false,
+                            error: Some(e.to_string()),
+                        },
+                    },
+                )
+                .collect::<Vec<_>>())
+        })
+        .await?;
+    tracing::info!(
+        "Batch insert into {}/{}: {} of {} items inserted, {} duplicates",
+        namespace.as_str(),
+        collection.as_str(),
+        results.iter().filter(|r| r.id.is_some() && !r.duplicate).count(),
+        results.len(),
+        results.iter().filter(|r| r.duplicate).count()
+    );
+    Ok(HpkeResponse(BatchInsertResponse { results }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BatchInsertRequest {
+    items: Vec<serde_json::Value>,
+    #[serde(default)]
+    mode: ImportMode,
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+pub struct BatchInsertResponse {
+    /// one result per request item, in order
+    results: Vec<BatchInsertResult>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchInsertResult {
+    id: Option<String>,
+    /// the id is the item already holding the same content
+    duplicate: bool,
+    error: Option<String>,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct BatchIdRequest {
     ids: Vec<String>,
@@ -179,63 +354,356 @@ pub struct BatchGetDataResponse {
     truncated: Option<String>,
 }
 
+pub fn create_usage_router() -> Router {
+    Router::with_path("{namespace}/usage")
+        .get(namespace_usage)
+        .tagged("data")
+}
+
+/// Get the current user's item counts and storage bytes per collection of a namespace
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "Get usage successfully", body = NamespaceUsage),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Namespace not found")
+    )
+)]
+async fn namespace_usage(
+    namespace: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<NamespaceUsage>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let usage = store
+        .run(move |store| store.namespace_usage(&namespace, &user_id))
+        .await?;
+    Ok(HpkeResponse(usage))
+}
+
+/// Get the current user's item count, time range and per-parent counts in a collection
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "Get stats successfully", body = CollectionStats),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Namespace or collection not found")
+    )
+)]
+async fn collection_stats(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<CollectionStats>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let stats = store
+        .run(move |store| store.collection_stats(&namespace, &collection, &user_id))
+        .await?;
+    Ok(HpkeResponse(stats))
+}
+
+/// Full text search in the collection's `x-fulltext` fields, best match first
+///
+/// Every whitespace separated term of `q` has to match, only the items the user can read are returned.
+#[endpoint(
+    status_codes(200, 400, 403),
+    responses(
+        (status_code = 200, description = "Search data successfully", body = ListDataResponse),
+        (status_code = 400, description = "Empty query or collection without x-fulltext"),
+        (status_code = 403, description = "FORBIDDEN")
+    )
+)]
+async fn search_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    q: QueryParam<String, true>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListDataResponse>> {
+    let user_id = depot.get::<UserSchema>("user_schema")?.user_id.clone();
+    let store = depot.obtain::<Arc<Store>>()?;
+    let query = q.into_inner();
+    let items = store
+        .run(move |store| store.search(&namespace, &collection, &query, &user_id))
+        .await?;
+    Ok(HpkeResponse(ListDataResponse {
+        page_info: PageInfo::forward(items.len(), None),
+        items: items.into_iter().map(Into::into).collect(),
+    }))
+}
+
 pub fn create_data_router() -> Router {
     Router::with_path("{namespace}/{collection}")
         .hoop(super::chunk_data_wrapper::check_chunk)
         .push(Router::new().post(create_data).get(list_data))
+        // before `{id}`, which would take it as an id
+        .push(Router::with_path("stats").get(collection_stats))
+        .push(Router::with_path("search").get(search_data))
+        .push(Router::with_path("batch").post(insert_many_data))
+        .push(Router::with_path("batch-update").post(update_many_data))
+        .push(Router::with_path("validate").post(validate_data))
         .push(
             Router::with_path("{id}")
+                .hoop(super::transaction::transaction)
                 .get(get_data)
                 .post(update_data)
+                .patch(patch_data)
                 .delete(delete_data),
         )
-        .oapi_tag("data")
+        .push(Router::with_path("{id}/move").post(move_data))
+        .push(Router::with_path("{id}/restore").post(restore_data))
+        .push(Router::with_path("{id}/lock").post(lock_data).delete(unlock_data))
+        .push(Router::with_path("{id}/history").get(data_history))
+        .push(Router::with_path("{id}/access-stats").get(access_stats))
+        .push(
+            Router::with_path("{id}/attachments")
+                .get(list_attachments)
+                .post(upload_attachment),
+        )
+        .push(Router::with_path("{id}/attachments/upload-url").post(issue_upload_url))
+        .push(
+            Router::with_path("{id}/attachments/{attachment_id}")
+                .get(get_attachment)
+                .delete(delete_attachment),
+        )
+        .tagged("data")
 }
 
 /// List data items summary with pagination
+///
+/// With `snapshot=true` the owner and children listings return a `next_cursor` instead, pass it back
+/// as `cursor` to page over the items present when the first page was read.
+///
+/// With `view` the saved view of that name runs over the owner or children listing, the marker of
+/// a sorted view is the position of the next page.
+///
+/// With `filter` only the matching items of the owner or children listing are returned, either
+/// `field=value` pairs joined by `,` (`status=normal,category=tech`) or a JSON filter expression as
+/// the query endpoint takes it.
+///
+/// With `sort` the owner or children listing is ordered by `created_at`, `updated_at` or `unique`,
+/// ascending unless written `updated_at:desc`. The markers of a sorted listing only fit the same order.
+///
+/// With `since` (RFC 3339) only the owner or children items updated at or after it are listed, oldest
+/// change first, for sync clients catching up from their last visit. Deleted items are not listed.
+///
+/// With `include_deleted=true` the plain and `since` owner or children listings of an `x-soft-delete`
+/// collection also hold the deleted items, each with its `deleted_at`.
+///
+/// With `owner=true` and `parent_id` only the user's own children of that parent are listed, e.g. my
+/// posts in one repo, and the parent does not need to be readable.
 #[endpoint(
-    status_codes(200, 403),
+    status_codes(200, 400, 403),
     responses(
         (status_code = 200, description = "List data successfully", body = ListDataResponse),
+        (status_code = 400, description = "Bad request"),
         (status_code = 403, description = "FORBIDDEN")
     )
 )]
+#[allow(clippy::too_many_arguments)]
 async fn list_data(
     namespace: PathParam<String>,
     collection: PathParam<String>,
     parent_id: QueryParam<String, false>,
+    owner: QueryParam<bool, false>,
     permission: QueryParam<bool, false>,
     marker: QueryParam<String, false>,
-    limit: QueryParam<usize>,
+    snapshot: QueryParam<bool, false>,
+    cursor: QueryParam<String, false>,
+    view: QueryParam<String, false>,
+    filter: QueryParam<String, false>,
+    sort: QueryParam<String, false>,
+    since: QueryParam<String, false>,
+    include_deleted: QueryParam<bool, false>,
+    limit: QueryParam<usize, false>,
     depot: &mut Depot,
 ) -> ServiceResult<HpkeResponse<ListDataResponse>> {
-    let user = depot.get::<UserSchema>("user_schema")?;
-    let namespace = namespace.as_str();
-    let collection = collection.as_str();
-    let marker = marker.clone();
-    // limit must be positive
-    let limit = match *limit {
-        0 => 1,
-        n if n > 1000 => 1000,
-        n => n,
-    };
+    let user_id = depot.get::<UserSchema>("user_schema")?.user_id.clone();
+    let (namespace, collection) = (namespace.into_inner(), collection.into_inner());
+    let (parent_id, marker) = (parent_id.into_inner(), marker.into_inner());
+    let limit = depot.obtain::<PageSize>()?.resolve(limit.into_inner());
     let store = depot.obtain::<Arc<Store>>()?;
-    let (items, next_marker) = if let Some(parent_id) = parent_id.as_deref() {
-        tracing::info!("Listing data [children] namespace: {namespace}, collection: {collection}");
-        store.list_children(namespace, collection, parent_id, marker, limit, &user.user_id)?
-    } else if let Some(true) = *permission {
-        tracing::info!("Listing data [with permission] namespace: {namespace}, collection: {collection}");
-        store.list_with_permission(namespace, collection, marker, limit, &user.user_id)?
-    } else {
-        tracing::info!("Listing data [by owner] namespace: {namespace}, collection: {collection}");
-        store.list_by_owner(namespace, collection, marker, limit, &user.user_id)?
-    };
+    let plain = cursor.is_none() && !snapshot.unwrap_or(false) && !permission.unwrap_or(false);
+    let include_deleted = include_deleted.unwrap_or(false);
+    if include_deleted && (!plain || view.is_some() || filter.is_some() || sort.is_some()) {
+        return Err(ServiceError::RequestError(
+            "`include_deleted` does not combine with `view`, `filter`, `sort`, `snapshot`, `cursor` or `permission`"
+                .to_string(),
+        ));
+    }
+    if owner.unwrap_or(false)
+        && let Some(parent_id) = parent_id.clone()
+    {
+        if !plain || include_deleted || view.is_some() || filter.is_some() || sort.is_some() || since.is_some() {
+            return Err(ServiceError::RequestError(
+                "`owner` with `parent_id` only combines with `marker` and `limit`".to_string(),
+            ));
+        }
+        let (items, next_marker) = store
+            .run(move |store| store.list_by_owner_under(&namespace, &collection, &parent_id, marker, limit, &user_id))
+            .await?;
+        return Ok(HpkeResponse(ListDataResponse {
+            page_info: PageInfo::forward(items.len(), next_marker),
+            items: items.into_iter().map(Into::into).collect(),
+        }));
+    }
+    if let Some(since) = since.into_inner() {
+        if !plain || view.is_some() || filter.is_some() || sort.is_some() {
+            return Err(ServiceError::RequestError(
+                "`since` does not combine with `view`, `filter`, `sort`, `snapshot`, `cursor` or `permission`"
+                    .to_string(),
+            ));
+        }
+        let since = chrono::DateTime::parse_from_rfc3339(&since)
+            .map_err(|e| ServiceError::RequestError(format!("invalid `since`: {}", e)))?
+            .with_timezone(&chrono::Utc);
+        let (items, next_marker) = store
+            .run(move |store| {
+                let listing = match parent_id.as_deref() {
+                    Some(parent_id) => Listing::Children(parent_id),
+                    None => Listing::Owner,
+                };
+                store.list_updated_since(
+                    &namespace,
+                    &collection,
+                    listing,
+                    since,
+                    include_deleted,
+                    marker,
+                    limit,
+                    &user_id,
+                )
+            })
+            .await?;
+        return Ok(HpkeResponse(ListDataResponse {
+            page_info: PageInfo::forward(items.len(), next_marker),
+            items: items.into_iter().map(Into::into).collect(),
+        }));
+    }
+    if let Some(filter) = filter.into_inner() {
+        if !plain || view.is_some() || sort.is_some() {
+            return Err(ServiceError::RequestError(
+                "`filter` does not combine with `view`, `sort`, `snapshot`, `cursor` or `permission`".to_string(),
+            ));
+        }
+        let filter = FilterExpr::from_query(&filter)?;
+        let (items, next_marker) = store
+            .run(move |store| {
+                store.query(
+                    &namespace,
+                    &collection,
+                    parent_id.as_deref(),
+                    &filter,
+                    marker,
+                    limit,
+                    &user_id,
+                )
+            })
+            .await?;
+        return Ok(HpkeResponse(ListDataResponse {
+            page_info: PageInfo::forward(items.len(), next_marker),
+            items: items.into_iter().map(Into::into).collect(),
+        }));
+    }
+    if let Some(view) = view.into_inner() {
+        if !plain || sort.is_some() {
+            return Err(ServiceError::RequestError(
+                "`view` does not combine with `sort`, `snapshot`, `cursor` or `permission`".to_string(),
+            ));
+        }
+        let (items, next_marker) = store
+            .run(move |store| {
+                store.run_view(
+                    &namespace,
+                    &collection,
+                    &view,
+                    parent_id.as_deref(),
+                    marker,
+                    limit,
+                    &user_id,
+                )
+            })
+            .await?;
+        return Ok(HpkeResponse(ListDataResponse {
+            page_info: PageInfo::forward(items.len(), next_marker),
+            items: items.into_iter().map(Into::into).collect(),
+        }));
+    }
+    if let Some(sort) = sort.into_inner() {
+        if !plain {
+            return Err(ServiceError::RequestError(
+                "`sort` does not combine with `snapshot`, `cursor` or `permission`".to_string(),
+            ));
+        }
+        let order = sort.parse::<ListOrder>()?;
+        let (items, next_marker) = store
+            .run(move |store| {
+                let listing = match parent_id.as_deref() {
+                    Some(parent_id) => Listing::Children(parent_id),
+                    None => Listing::Owner,
+                };
+                store.list_sorted(&namespace, &collection, listing, order, marker, limit, &user_id)
+            })
+            .await?;
+        return Ok(HpkeResponse(ListDataResponse {
+            page_info: PageInfo::forward(items.len(), next_marker),
+            items: items.into_iter().map(Into::into).collect(),
+        }));
+    }
+    if cursor.is_some() || snapshot.unwrap_or(false) {
+        let cursor = cursor.as_deref().map(PageCursor::decode).transpose()?;
+        let (items, next_cursor) = store
+            .run(move |store| match parent_id.as_deref() {
+                Some(parent_id) => {
+                    store.list_children_snapshot(&namespace, &collection, parent_id, cursor, limit, &user_id)
+                }
+                None => store.list_by_owner_snapshot(&namespace, &collection, cursor, limit, &user_id),
+            })
+            .await?;
+        return Ok(HpkeResponse(ListDataResponse {
+            page_info: PageInfo {
+                next_cursor: next_cursor.as_ref().map(|c| c.encode()),
+                ..PageInfo::forward(items.len(), next_cursor.and_then(|c| c.marker))
+            },
+            items: items.into_iter().map(Into::into).collect(),
+        }));
+    }
+    let permission = permission.into_inner();
+    let page = store
+        .run(move |store| {
+            let listing = if let Some(parent_id) = parent_id.as_deref() {
+                tracing::info!("Listing data [children] namespace: {namespace}, collection: {collection}");
+                Listing::Children(parent_id)
+            } else if let Some(true) = permission {
+                tracing::info!("Listing data [with permission] namespace: {namespace}, collection: {collection}");
+                Listing::Permission
+            } else {
+                tracing::info!("Listing data [by owner] namespace: {namespace}, collection: {collection}");
+                Listing::Owner
+            };
+            store.list_page(
+                &namespace,
+                &collection,
+                listing,
+                marker,
+                include_deleted,
+                limit,
+                &user_id,
+            )
+        })
+        .await?;
     Ok(HpkeResponse(ListDataResponse {
         page_info: PageInfo {
-            count: items.len(),
-            next_marker,
+            count: page.items.len(),
+            has_next: page.has_next(),
+            next_marker: page.next_marker,
+            has_prev: page.has_prev,
+            prev_marker: page.prev_marker,
+            next_cursor: None,
         },
-        items: items.into_iter().map(Into::into).collect(),
+        items: page.items.into_iter().map(Into::into).collect(),
     }))
 }
 
@@ -246,9 +714,34 @@ struct ListDataResponse {
 }
 
 #[derive(Deserialize, Serialize, ToResponse, ToSchema)]
-struct PageInfo {
+pub(crate) struct PageInfo {
     count: usize,
+    #[serde(default)]
+    has_next: bool,
     next_marker: Option<String>,
+    /// only the plain listings look back, always false for batch, query and snapshot listings
+    #[serde(default)]
+    has_prev: bool,
+    /// marker of the page before, none with `has_prev` when the page before is the first page
+    #[serde(default)]
+    prev_marker: Option<String>,
+    /// only set by snapshot listings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+impl PageInfo {
+    // a page only linking to the next one
+    pub(crate) fn forward(count: usize, next_marker: Option<String>) -> Self {
+        Self {
+            count,
+            has_next: next_marker.is_some(),
+            next_marker,
+            has_prev: false,
+            prev_marker: None,
+            next_cursor: None,
+        }
+    }
 }
 
 impl Scribe for ListDataResponse {
@@ -258,6 +751,8 @@ impl Scribe for ListDataResponse {
 }
 
 /// Get a single data item by ID
+///
+/// With `include_deleted=true` a soft deleted item is returned too, with its `deleted_at`.
 #[endpoint(
     status_codes(200, 403, 404),
     responses(
@@ -270,44 +765,205 @@ async fn get_data(
     namespace: PathParam<String>,
     collection: PathParam<String>,
     id: PathParam<String>,
+    inline_blobs: QueryParam<bool, false>,
+    include_deleted: QueryParam<bool, false>,
     depot: &mut Depot,
 ) -> ServiceResult<HpkeResponse<DataItem>> {
     let store = depot.obtain::<Arc<Store>>()?;
     let user = depot.get::<UserSchema>("user_schema")?;
-    Ok(HpkeResponse(store.get(&namespace, &collection, &id, &user.user_id)?))
+    let user_id = user.user_id.clone();
+    let inline_blobs = inline_blobs.into_inner().unwrap_or(false);
+    let include_deleted = include_deleted.into_inner().unwrap_or(false);
+    let item = store
+        .run(move |store| {
+            let mut item = store.get_with_deleted(&namespace, &collection, &id, include_deleted, &user_id)?;
+            if inline_blobs {
+                store.get_data_backend(&namespace)?.inline_blobs(&mut item)?;
+            }
+            Ok(item)
+        })
+        .await?;
+    Ok(HpkeResponse(item))
 }
 
 /// Create a new data item
+///
+/// The item takes the `id` generated by the client when given, e.g. an offline client already
+/// referring to it. An id already taken in the collection is answered with 409.
 #[endpoint(
-    status_codes(201, 400, 403),
+    status_codes(201, 400, 403, 409),
     request_body(content = serde_json::Value, description = "Data item to create"),
     responses(
         (status_code = 201, description = "Data created successfully", body = String),
         (status_code = 400, description = "Bad request"),
-        (status_code = 403, description = "FORBIDDEN")
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 409, description = "Id already taken")
     )
 )]
 async fn create_data(
     namespace: PathParam<String>,
     collection: PathParam<String>,
+    id: QueryParam<String, false>,
     req: HpkeRequest<serde_json::Value>,
     depot: &mut Depot,
 ) -> ServiceResult<HpkeResponse<String>> {
     let user = depot.get::<UserSchema>("user_schema")?;
     let store = depot.obtain::<Arc<Store>>()?;
-    let id = store.insert(&namespace, &collection, &req.0, &user.user_id)?;
+    let user_id = user.user_id.clone();
+    let id = id.into_inner();
+    let id = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.insert_with_id(&namespace, &collection, id.as_deref(), &req.0, &user_id)
+        })
+        .await?;
     Ok(HpkeResponse(id))
 }
 
+/// Validate a data item without storing it
+///
+/// Runs the checks of a create, or of an update of `id`, against the body: the schema with its
+/// `x-parent-id` reference and the `x-unique` value. Every failing check is listed, a refused body
+/// is still answered with 200.
+#[endpoint(
+    status_codes(200, 400, 403),
+    request_body(content = serde_json::Value, description = "Data item to validate"),
+    responses(
+        (status_code = 200, description = "Validation report", body = ValidationReport),
+        (status_code = 400, description = "Bad request"),
+        (status_code = 403, description = "FORBIDDEN")
+    )
+)]
+async fn validate_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: QueryParam<String, false>,
+    req: HpkeRequest<serde_json::Value>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ValidationReport>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let id = id.into_inner();
+    let report = store
+        .run(move |store| store.validate_data(&namespace, &collection, &req.0, id.as_deref(), &user_id))
+        .await?;
+    Ok(HpkeResponse(report))
+}
+
+/// Create data items all at once
+///
+/// The items are validated and inserted in one transaction. When any of them fails nothing is
+/// stored and every failed item is listed with its position, answered with 400.
+#[endpoint(
+    status_codes(201, 400, 403),
+    request_body(content = InsertManyRequest, description = "Data items to create"),
+    responses(
+        (status_code = 201, description = "Data items created", body = BatchOutcome),
+        (status_code = 400, description = "Items rejected, or too many items", body = BatchOutcome),
+        (status_code = 403, description = "FORBIDDEN")
+    )
+)]
+async fn insert_many_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    req: HpkeRequest<InsertManyRequest>,
+    depot: &mut Depot,
+    resp: &mut Response,
+) -> ServiceResult<HpkeResponse<BatchOutcome>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    if req.0.items.len() > MAX_BULK_INSERT_ITEMS {
+        Err(ServiceError::RequestError(format!(
+            "Batch insert limit exceeded: maximum {} items per request",
+            MAX_BULK_INSERT_ITEMS
+        )))?;
+    }
+    let user_id = user.user_id.clone();
+    let items = req.0.items;
+    let outcome = store
+        .run_write(WriteTier::Batch, move |store| {
+            store.insert_many(&namespace, &collection, &items, &user_id)
+        })
+        .await?;
+    resp.status_code(match outcome {
+        BatchOutcome::Committed { .. } => StatusCode::CREATED,
+        BatchOutcome::Rejected { .. } => StatusCode::BAD_REQUEST,
+    });
+    Ok(HpkeResponse(outcome))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct InsertManyRequest {
+    items: Vec<serde_json::Value>,
+}
+
+/// Update data items all at once
+///
+/// The permissions on every item are checked before anything is written, then the items are
+/// updated in one transaction. When any of them fails nothing is changed and every failed item is
+/// listed with its position, answered with 400.
+#[endpoint(
+    status_codes(200, 400, 403),
+    request_body(content = UpdateManyRequest, description = "Data items to update"),
+    responses(
+        (status_code = 200, description = "Data items updated", body = BatchOutcome),
+        (status_code = 400, description = "Items rejected, or too many items", body = BatchOutcome),
+        (status_code = 403, description = "FORBIDDEN")
+    )
+)]
+async fn update_many_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    req: HpkeRequest<UpdateManyRequest>,
+    depot: &mut Depot,
+    resp: &mut Response,
+) -> ServiceResult<HpkeResponse<BatchOutcome>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    if req.0.items.len() > MAX_BULK_INSERT_ITEMS {
+        Err(ServiceError::RequestError(format!(
+            "Batch update limit exceeded: maximum {} items per request",
+            MAX_BULK_INSERT_ITEMS
+        )))?;
+    }
+    let user_id = user.user_id.clone();
+    let items = req.0.items.into_iter().map(|item| (item.id, item.body)).collect();
+    let outcome = store
+        .run_write(WriteTier::Batch, move |store| {
+            store.update_many(&namespace, &collection, items, &user_id)
+        })
+        .await?;
+    if let BatchOutcome::Rejected { .. } = outcome {
+        resp.status_code(StatusCode::BAD_REQUEST);
+    }
+    Ok(HpkeResponse(outcome))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateManyRequest {
+    items: Vec<UpdateItem>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateItem {
+    id: String,
+    /// the new body, replacing the current one
+    body: serde_json::Value,
+}
+
 /// Update an existing data item
+///
+/// With an `If-Match` header holding the `rev` of the item as the client read it, the update is
+/// refused with 409 once another change got there first. The new revision is returned as `ETag`.
 #[endpoint(
-    status_codes(200, 400, 403, 404),
+    status_codes(200, 400, 403, 404, 409),
     request_body(content = serde_json::Value, description = "Data item to update"),
     responses(
         (status_code = 200, description = "Data updated successfully", body = String),
         (status_code = 400, description = "Bad request"),
         (status_code = 403, description = "FORBIDDEN"),
-        (status_code = 404, description = "Data not found")
+        (status_code = 404, description = "Data not found"),
+        (status_code = 409, description = "Locked by another user, or changed since the `If-Match` revision")
     )
 )]
 async fn update_data(
@@ -315,33 +971,534 @@ async fn update_data(
     collection: PathParam<String>,
     id: PathParam<String>,
     req: HpkeRequest<serde_json::Value>,
+    request: &mut Request,
     depot: &mut Depot,
+    resp: &mut Response,
 ) -> ServiceResult<HpkeResponse<String>> {
     let user = depot.get::<UserSchema>("user_schema")?;
     let store = depot.obtain::<Arc<Store>>()?;
-    let item = store.update(&namespace, &collection, &id, &req.0, &user.user_id)?;
+    let (user_id, reason, expected_rev) = (user.user_id.clone(), change_reason(request), expected_rev(request)?);
+    let item = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.update_with_reason(
+                &namespace,
+                &collection,
+                &id,
+                &req.0,
+                expected_rev,
+                &user_id,
+                reason.as_deref(),
+            )
+        })
+        .await?;
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{}\"", item.rev)) {
+        resp.headers_mut().insert(ETAG, etag);
+    }
+    Ok(HpkeResponse(item.id))
+}
+
+/// Patch an existing data item
+///
+/// The body is a JSON Patch (RFC 6902) array, sent as `application/json-patch+json`. The operations
+/// are applied to the item as stored, the result is validated and written as an update, or nothing
+/// is written when one of them fails. `If-Match` and `ETag` work as for the update.
+#[endpoint(
+    status_codes(200, 400, 403, 404, 409),
+    request_body(content = Vec<PatchOp>, content_type = "application/json-patch+json", description = "Operations to apply"),
+    responses(
+        (status_code = 200, description = "Data patched successfully", body = String),
+        (status_code = 400, description = "Failed operation, or the patched item is invalid"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found"),
+        (status_code = 409, description = "Locked by another user, or changed since the `If-Match` revision")
+    )
+)]
+async fn patch_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    req: HpkeRequest<Vec<PatchOp>>,
+    request: &mut Request,
+    depot: &mut Depot,
+    resp: &mut Response,
+) -> ServiceResult<HpkeResponse<String>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let (user_id, expected_rev) = (user.user_id.clone(), expected_rev(request)?);
+    let item = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.patch(&namespace, &collection, &id, &req.0, expected_rev, &user_id)
+        })
+        .await?;
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{}\"", item.rev)) {
+        resp.headers_mut().insert(ETAG, etag);
+    }
     Ok(HpkeResponse(item.id))
 }
 
 /// Delete a data item
+///
+/// In an `x-soft-delete` collection the item is only marked deleted, it is left out of the reads
+/// until restored. Its children are deleted along or keep it from being deleted as the
+/// `x-on-parent-delete` of their collection says. An `If-Match` header works as for the update.
 #[endpoint(
-    status_codes(204, 403, 404),
+    status_codes(204, 400, 403, 404, 409),
     responses(
         (status_code = 204, description = "Data deleted successfully"),
+        (status_code = 400, description = "Invalid `If-Match`"),
         (status_code = 403, description = "FORBIDDEN"),
-        (status_code = 404, description = "Data not found")
+        (status_code = 404, description = "Data not found"),
+        (status_code = 409, description = "Locked, changed since the `If-Match` revision, or children restrict the delete")
     )
 )]
 async fn delete_data(
     namespace: PathParam<String>,
     collection: PathParam<String>,
     id: PathParam<String>,
+    req: &mut Request,
     depot: &mut Depot,
     resp: &mut Response,
 ) -> ServiceResult<()> {
     let user = depot.get::<UserSchema>("user_schema")?;
     let store = depot.obtain::<Arc<Store>>()?;
-    store.delete(&namespace, &collection, &id, &user.user_id)?;
+    let (user_id, reason, expected_rev) = (user.user_id.clone(), change_reason(req), expected_rev(req)?);
+    store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.delete_with_reason(&namespace, &collection, &id, expected_rev, &user_id, reason.as_deref())
+        })
+        .await?;
     resp.status_code(StatusCode::NO_CONTENT);
     Ok(())
 }
+
+/// Restore a deleted data item of an `x-soft-delete` collection
+#[endpoint(
+    status_codes(200, 400, 403, 404, 409),
+    responses(
+        (status_code = 200, description = "Data restored successfully", body = DataItem),
+        (status_code = 400, description = "The collection does not keep deleted items"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found"),
+        (status_code = 409, description = "Data is not deleted")
+    )
+)]
+async fn restore_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<DataItem>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let item = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.restore(&namespace, &collection, &id, &user_id)
+        })
+        .await?;
+    Ok(HpkeResponse(item))
+}
+
+/// Move a data item under another parent
+#[endpoint(
+    status_codes(200, 400, 403, 404),
+    request_body(content = MoveDataRequest, description = "New parent of the data item"),
+    responses(
+        (status_code = 200, description = "Data moved successfully", body = DataItem),
+        (status_code = 400, description = "Bad request"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found")
+    )
+)]
+async fn move_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    req: HpkeRequest<MoveDataRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<DataItem>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let item = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.move_item(&namespace, &collection, &id, &req.0.parent_id, &user_id)
+        })
+        .await?;
+    Ok(HpkeResponse(item))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MoveDataRequest {
+    parent_id: String,
+}
+
+/// Take or refresh an advisory lock on a data item
+///
+/// Other users can still read the item, but updates, moves and deletes are rejected with 409
+/// until the lock is released or expires.
+#[endpoint(
+    status_codes(200, 400, 403, 404, 409),
+    request_body(content = LockDataRequest, description = "Lock duration"),
+    responses(
+        (status_code = 200, description = "Data locked successfully", body = ItemLock),
+        (status_code = 400, description = "Bad request"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found"),
+        (status_code = 409, description = "Locked by another user")
+    )
+)]
+async fn lock_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    req: HpkeRequest<LockDataRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ItemLock>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let ttl = std::time::Duration::from_secs(req.0.ttl_secs.unwrap_or(300));
+    let user_id = user.user_id.clone();
+    let lock = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.lock(&namespace, &collection, &id, &user_id, ttl)
+        })
+        .await?;
+    Ok(HpkeResponse(lock))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LockDataRequest {
+    /// defaults to 300 seconds
+    ttl_secs: Option<u64>,
+}
+
+/// Release the advisory lock of a data item
+#[endpoint(
+    status_codes(204, 403, 404),
+    responses(
+        (status_code = 204, description = "Data unlocked successfully"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found")
+    )
+)]
+async fn unlock_data(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    depot: &mut Depot,
+    resp: &mut Response,
+) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.unlock(&namespace, &collection, &id, &user_id)
+        })
+        .await?;
+    resp.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// List the changes of a data item, newest first
+///
+/// Every change carries the user who made it and the reason given in `X-Change-Reason`, if any.
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "List history successfully", body = DataHistoryResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found")
+    )
+)]
+async fn data_history(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    marker: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<DataHistoryResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let limit = depot.obtain::<PageSize>()?.resolve(limit.into_inner());
+    let user_id = user.user_id.clone();
+    let (items, next_marker) = store
+        .run(move |store| store.history(&namespace, &collection, &id, &user_id, marker.into_inner(), limit))
+        .await?;
+    Ok(HpkeResponse(DataHistoryResponse { items, next_marker }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+pub struct DataHistoryResponse {
+    items: Vec<Change>,
+    next_marker: Option<String>,
+}
+
+impl Scribe for DataHistoryResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Read count and last read time of a data item
+///
+/// Only the owner sees them, and only in collections whose schema sets `x-access-stats: true`.
+#[endpoint(
+    status_codes(200, 400, 403, 404),
+    responses(
+        (status_code = 200, description = "Get access statistics successfully", body = AccessStats),
+        (status_code = 400, description = "Collection without access statistics"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found")
+    )
+)]
+async fn access_stats(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<AccessStats>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let stats = store
+        .run(move |store| store.access_stats(&namespace, &collection, &id, &user_id))
+        .await?;
+    Ok(HpkeResponse(stats))
+}
+
+/// List the attachments of a data item
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "List attachments successfully", body = ListAttachmentsResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found")
+    )
+)]
+async fn list_attachments(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListAttachmentsResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let items = store
+        .run(move |store| store.list_attachments((&namespace, &collection), &id, &user_id))
+        .await?;
+    Ok(HpkeResponse(ListAttachmentsResponse { items }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+pub struct ListAttachmentsResponse {
+    items: Vec<Attachment>,
+}
+
+impl Scribe for ListAttachmentsResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Upload a file bound to a data item
+///
+/// The attachment is readable by everyone who can read the item and is deleted with the item.
+#[endpoint(
+    status_codes(201, 400, 403, 404, 409),
+    request_body(content = UploadAttachmentRequest, description = "File name, type and base64 content"),
+    responses(
+        (status_code = 201, description = "Attachment uploaded successfully", body = Attachment),
+        (status_code = 400, description = "Bad request"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found"),
+        (status_code = 409, description = "Locked by another user")
+    )
+)]
+async fn upload_attachment(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    req: HpkeRequest<UploadAttachmentRequest>,
+    depot: &mut Depot,
+    resp: &mut Response,
+) -> ServiceResult<HpkeResponse<Attachment>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let req = req.0;
+    let content_type = req
+        .content_type
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let user_id = user.user_id.clone();
+    let attachment = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.add_attachment(
+                (&namespace, &collection),
+                &id,
+                &req.name,
+                &content_type,
+                &req.content,
+                &user_id,
+            )
+        })
+        .await?;
+    resp.status_code(StatusCode::CREATED);
+    Ok(HpkeResponse(attachment))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UploadAttachmentRequest {
+    name: String,
+    /// defaults to application/octet-stream
+    content_type: Option<String>,
+    #[serde(with = "crate::types::Base64Standard")]
+    #[salvo(schema(value_type = String))]
+    content: Vec<u8>,
+}
+
+/// Issue a one-time URL uploading a file bound to a data item
+///
+/// `PUT` the raw file to the returned URL, without the JWT and the HPKE envelope, to store it as
+/// the attachment `name` of the item on your behalf. The URL is accepted once and expires after a
+/// few minutes, the permissions checked here are checked again when the file arrives.
+#[endpoint(
+    status_codes(200, 400, 403, 404, 409),
+    request_body(content = UploadUrlRequest, description = "File name and type"),
+    responses(
+        (status_code = 200, description = "Upload URL issued", body = UploadUrlResponse),
+        (status_code = 400, description = "Bad request"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Data not found"),
+        (status_code = 409, description = "Locked by another user")
+    )
+)]
+async fn issue_upload_url(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    req: HpkeRequest<UploadUrlRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<UploadUrlResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let grants = depot.obtain::<Arc<UploadGrants>>()?;
+    let req = req.0;
+    let slot = UploadSlot {
+        namespace: namespace.into_inner(),
+        collection: collection.into_inner(),
+        id: id.into_inner(),
+        name: req.name,
+        content_type: req.content_type,
+        user_id: user.user_id.clone(),
+    };
+    let slot = store
+        .run(move |store| {
+            store.check_attachable((&slot.namespace, &slot.collection), &slot.id, &slot.name, &slot.user_id)?;
+            Ok(slot)
+        })
+        .await?;
+    let (token, expires_at) = grants.issue(slot);
+    Ok(HpkeResponse(UploadUrlResponse {
+        url: format!("/api/fs/upload/{}", token),
+        expires_at,
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UploadUrlRequest {
+    name: String,
+    /// defaults to the `Content-Type` of the upload, then application/octet-stream
+    content_type: Option<String>,
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct UploadUrlResponse {
+    /// `PUT` the file here
+    url: String,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Scribe for UploadUrlResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Download an attachment of a data item
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "Get attachment successfully", body = AttachmentContent),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Attachment not found")
+    )
+)]
+async fn get_attachment(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    attachment_id: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<AttachmentContent>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let attachment = store
+        .run(move |store| store.get_attachment((&namespace, &collection), &id, &attachment_id, &user_id))
+        .await?;
+    Ok(HpkeResponse(attachment))
+}
+
+/// Delete an attachment of a data item
+#[endpoint(
+    status_codes(204, 403, 404, 409),
+    responses(
+        (status_code = 204, description = "Attachment deleted successfully"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Attachment not found"),
+        (status_code = 409, description = "Locked by another user")
+    )
+)]
+async fn delete_attachment(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    id: PathParam<String>,
+    attachment_id: PathParam<String>,
+    depot: &mut Depot,
+    resp: &mut Response,
+) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.delete_attachment((&namespace, &collection), &id, &attachment_id, &user_id)
+        })
+        .await?;
+    resp.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+// optional `X-Change-Reason` header of update and delete requests
+fn change_reason(req: &Request) -> Option<String> {
+    req.headers()
+        .get("X-Change-Reason")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+// optional `If-Match` header of update and delete requests, the revision as `"3"` or `3`
+fn expected_rev(req: &Request) -> ServiceResult<Option<i64>> {
+    let Some(value) = req.headers().get(IF_MATCH) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().trim_matches('"').parse::<i64>().ok())
+        .map(Some)
+        .ok_or_else(|| ServiceError::RequestError("`If-Match` must hold the revision of the item".to_string()))
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use salvo::{
+    Depot, Response, Router, Writer,
+    http::{HeaderValue, header},
+    oapi::{endpoint, extract::PathParam},
+};
+
+use crate::{
+    error::{ServiceError, ServiceResult},
+    router::metrics::TaggedRouter,
+    store::Store,
+    types::{Snapshot, UserSchema},
+};
+
+pub fn create_router() -> Router {
+    Router::with_path("{namespace}").get(export_namespace).tagged("export")
+}
+
+/// Download every item owned by current user in the namespace as JSONL
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "JSONL export, sha256 in the X-Checksum-Sha256 header"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Namespace not found")
+    )
+)]
+async fn export_namespace(namespace: PathParam<String>, depot: &mut Depot, res: &mut Response) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let (ns, user_id) = (namespace.to_string(), user.user_id.clone());
+    let snapshot = store.run(move |store| store.export_owned_data(&ns, &user_id)).await?;
+    tracing::info!(
+        "exported {} bytes of namespace {} for user {}",
+        snapshot.data.len(),
+        namespace.as_str(),
+        user.user_id
+    );
+    write_snapshot(
+        res,
+        snapshot,
+        &format!("{}.jsonl", namespace.as_str()),
+        "application/x-ndjson",
+    )
+}
+
+/// Render a snapshot as attachment download with its checksum header.
+pub(crate) fn write_snapshot(
+    res: &mut Response,
+    snapshot: Snapshot,
+    filename: &str,
+    content_type: &'static str,
+) -> ServiceResult<()> {
+    let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename))
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    let checksum =
+        HeaderValue::from_str(&snapshot.sha256).map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    res.headers_mut().insert(header::CONTENT_DISPOSITION, disposition);
+    res.headers_mut().insert("X-Checksum-Sha256", checksum);
+    res.write_body(snapshot.data)
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    Ok(())
+}
@@ -1,19 +1,151 @@
-use salvo::{Request, Response, Router, handler, http::HeaderValue, prelude::StaticDir};
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use rand::Rng;
+use salvo::{
+    Depot, FlowCtrl, Request, Response, Router, handler,
+    http::{
+        HeaderValue, StatusCode,
+        header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH, IF_RANGE, RANGE},
+    },
+    prelude::StaticDir,
+    writing::Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use crate::{
+    error::{ServiceError, ServiceResult},
+    router::metrics::TaggedRouter,
+    store::Store,
+    types::Attachment,
+    utils::constant::{MAX_ATTACHMENT_SIZE, UPLOAD_URL_TTL_SECS},
+};
+
+const PUBLIC_ROOT: &str = "./fs/public";
+const PRIVATE_ROOT: &str = "./fs/private";
 
 pub fn create_non_auth_router() -> Router {
-    Router::with_path("/public/{*path}").hoop(cache_policies).get(
-        StaticDir::new(vec!["./fs/public"])
-            .auto_list(true)
-            .chunk_size(2 * 1024 * 1024),
-    )
+    Router::new()
+        .push(
+            Router::with_path("/public/{*path}")
+                .hoop(cache_policies)
+                .hoop(ContentEtag::new(PUBLIC_ROOT))
+                .get(
+                    StaticDir::new(vec![PUBLIC_ROOT])
+                        .auto_list(true)
+                        .chunk_size(2 * 1024 * 1024),
+                ),
+        )
+        // the token of the url stands in for the jwt
+        .push(Router::with_path("/upload/{token}").put(upload_with_url))
+        .tagged("fs")
 }
 
 pub fn create_router() -> Router {
-    Router::with_path("/private/{*path}").hoop(cache_policies).get(
-        StaticDir::new(vec!["./fs/private"])
-            .auto_list(true)
-            .chunk_size(2 * 1024 * 1024),
-    )
+    Router::with_path("/private/{*path}")
+        .hoop(cache_policies)
+        .hoop(ContentEtag::new(PRIVATE_ROOT))
+        .get(
+            StaticDir::new(vec![PRIVATE_ROOT])
+                .auto_list(true)
+                .chunk_size(2 * 1024 * 1024),
+        )
+        .tagged("fs")
+}
+
+/// The attachment slot an upload url writes to, on behalf of the user who asked for it.
+pub struct UploadSlot {
+    pub namespace: String,
+    pub collection: String,
+    pub id: String,
+    pub name: String,
+    pub content_type: Option<String>,
+    pub user_id: String,
+}
+
+/// The pending upload urls, each one accepted once until it expires.
+#[derive(Default)]
+pub struct UploadGrants {
+    // dict<token, (slot, expires_at)>
+    pending: DashMap<String, (UploadSlot, DateTime<Utc>)>,
+}
+
+impl UploadGrants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The token of a new upload url to the slot, and when it expires.
+    pub fn issue(&self, slot: UploadSlot) -> (String, DateTime<Utc>) {
+        let now = Utc::now();
+        self.pending.retain(|_, (_, expires_at)| *expires_at > now);
+        let bytes: [u8; 32] = rand::rng().random();
+        let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        let expires_at = now + chrono::Duration::seconds(UPLOAD_URL_TTL_SECS as i64);
+        self.pending.insert(token.clone(), (slot, expires_at));
+        (token, expires_at)
+    }
+
+    // the slot of the token, once, while it is valid
+    fn take(&self, token: &str) -> Option<UploadSlot> {
+        let (_, (slot, expires_at)) = self.pending.remove(token)?;
+        (expires_at > Utc::now()).then_some(slot)
+    }
+}
+
+/// Store the raw body of the request as the attachment the upload url was issued for.
+///
+/// The url is spent even if the upload fails, the permissions of its user are checked again here.
+#[handler]
+async fn upload_with_url(req: &mut Request, depot: &mut Depot, res: &mut Response) -> ServiceResult<Json<Attachment>> {
+    let token = req.param::<String>("token").unwrap_or_default();
+    let slot = depot
+        .obtain::<Arc<UploadGrants>>()?
+        .take(&token)
+        .ok_or_else(|| ServiceError::RequestError("unknown or expired upload url".to_string()))?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user = store.get_user(&slot.user_id)?;
+    if user.deactivated_at.is_some() {
+        return Err(ServiceError::Unauthorized("Account deactivated".to_string()));
+    }
+    let content_type = slot
+        .content_type
+        .or_else(|| {
+            req.headers()
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let content = req
+        .payload_with_max_size(MAX_ATTACHMENT_SIZE)
+        .await
+        .map_err(|e| ServiceError::RequestError(e.to_string()))?
+        .to_vec();
+    let attachment = store
+        .run(move |store| {
+            store.add_attachment(
+                (&slot.namespace, &slot.collection),
+                &slot.id,
+                &slot.name,
+                &content_type,
+                &content,
+                &slot.user_id,
+            )
+        })
+        .await?;
+    res.status_code(StatusCode::CREATED);
+    Ok(Json(attachment))
 }
 
 #[handler]
@@ -39,3 +171,222 @@ fn cache_policies(req: &mut Request, res: &mut Response) {
         }
     }
 }
+
+/// Strong ETag of the served files, the sha256 of their content.
+///
+/// `If-None-Match` is answered here with a 304, and an `If-Range` naming the current hash keeps the
+/// `Range` of the request, any other one gets the whole file. The static files handler only knows
+/// its own weak validators, so the conditional headers are settled before it runs.
+struct ContentEtag {
+    root: PathBuf,
+    hashes: Arc<HashIndex>,
+}
+
+impl ContentEtag {
+    fn new(root: &str) -> Self {
+        let root = PathBuf::from(root);
+        // beside the root, the index itself is not served
+        let index = root.with_extension("etags.json");
+        Self {
+            hashes: Arc::new(HashIndex::open(index)),
+            root,
+        }
+    }
+
+    // the requested file, `None` for directories and paths leaving the root
+    fn file(&self, req: &Request) -> Option<(String, PathBuf)> {
+        let rel = req.param::<String>("path")?;
+        let rel = rel.trim_start_matches('/');
+        if Path::new(rel).components().any(|c| !matches!(c, Component::Normal(_))) {
+            return None;
+        }
+        let path = self.root.join(rel);
+        path.is_file().then(|| (rel.to_string(), path))
+    }
+}
+
+#[handler]
+impl ContentEtag {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        let Some((rel, path)) = self.file(req) else {
+            return;
+        };
+        let hashes = self.hashes.clone();
+        let etag = match tokio::task::spawn_blocking(move || hashes.hash(&rel, &path)).await {
+            Ok(Ok(sha256)) => format!("\"{}\"", sha256),
+            Ok(Err(e)) => {
+                tracing::warn!("failed to hash file {}: {}", req.uri().path(), e);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("file hash task failed: {}", e);
+                return;
+            }
+        };
+        let Ok(etag_value) = HeaderValue::from_str(&etag) else {
+            return;
+        };
+
+        let headers = req.headers_mut();
+        if let Some(if_none_match) = headers.remove(IF_NONE_MATCH)
+            && if_none_match.to_str().is_ok_and(|tags| none_match_hits(tags, &etag))
+        {
+            res.status_code(StatusCode::NOT_MODIFIED);
+            res.headers_mut().insert(ETAG, etag_value);
+            ctrl.skip_rest();
+            return;
+        }
+        // an `If-Range` date is left to the static files handler
+        if let Some(if_range) = headers.get(IF_RANGE).and_then(|v| v.to_str().ok())
+            && (if_range.starts_with('"') || if_range.starts_with("W/"))
+        {
+            // weak tags never match for ranges
+            if if_range != etag {
+                headers.remove(RANGE);
+            }
+            headers.remove(IF_RANGE);
+        }
+
+        ctrl.call_next(req, depot, res).await;
+        if res
+            .status_code
+            .is_none_or(|status| status == StatusCode::OK || status == StatusCode::PARTIAL_CONTENT)
+        {
+            res.headers_mut().insert(ETAG, etag_value);
+        }
+    }
+}
+
+// weak comparison, as If-None-Match asks for
+fn none_match_hits(tags: &str, etag: &str) -> bool {
+    tags.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Sidecar index of the file hashes, recomputed when the size or modification time of a file changed.
+struct HashIndex {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, HashEntry>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct HashEntry {
+    size: u64,
+    modified: u64, // nanoseconds since the epoch
+    sha256: String,
+}
+
+impl HashIndex {
+    fn open(path: PathBuf) -> Self {
+        let entries = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                tracing::warn!("ignoring unreadable hash index {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    fn hash(&self, rel: &str, file: &Path) -> std::io::Result<String> {
+        let meta = std::fs::metadata(file)?;
+        let modified = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        if let Some(entry) = self.entries.lock().get(rel)
+            && entry.size == meta.len()
+            && entry.modified == modified
+        {
+            return Ok(entry.sha256.clone());
+        }
+
+        let mut hasher = sha2::Sha256::new();
+        let mut reader = std::fs::File::open(file)?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        let mut entries = self.entries.lock();
+        entries.insert(
+            rel.to_string(),
+            HashEntry {
+                size: meta.len(),
+                modified,
+                sha256: sha256.clone(),
+            },
+        );
+        // written aside and renamed, a crash never leaves a truncated index
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&*entries)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(sha256)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::blob::hex_sha256;
+
+    #[test]
+    fn test_hash_index_follows_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, b"first").unwrap();
+        let index = HashIndex::open(dir.path().join("index.json"));
+        let first = index.hash("a.txt", &file).unwrap();
+        assert_eq!(first, hex_sha256(b"first"));
+
+        // reopened from the sidecar file
+        let index = HashIndex::open(dir.path().join("index.json"));
+        assert_eq!(index.entries.lock()["a.txt"].sha256, first);
+        std::fs::write(&file, b"second!").unwrap();
+        assert_eq!(index.hash("a.txt", &file).unwrap(), hex_sha256(b"second!"));
+    }
+
+    #[test]
+    fn test_upload_grants_are_single_use() {
+        let slot = || UploadSlot {
+            namespace: "ns".to_string(),
+            collection: "coll".to_string(),
+            id: "id".to_string(),
+            name: "a.bin".to_string(),
+            content_type: None,
+            user_id: "user".to_string(),
+        };
+        let grants = UploadGrants::new();
+        let (token, expires_at) = grants.issue(slot());
+        assert!(expires_at > Utc::now());
+        assert_eq!(grants.take(&token).unwrap().name, "a.bin");
+        assert!(grants.take(&token).is_none());
+
+        let (token, _) = grants.issue(slot());
+        grants.pending.get_mut(&token).unwrap().1 = Utc::now() - chrono::Duration::seconds(1);
+        assert!(grants.take(&token).is_none());
+        assert!(grants.take("unknown").is_none());
+    }
+
+    #[test]
+    fn test_none_match_weak_comparison() {
+        assert!(none_match_hits("\"abc\"", "\"abc\""));
+        assert!(none_match_hits("\"x\", W/\"abc\"", "\"abc\""));
+        assert!(none_match_hits("*", "\"abc\""));
+        assert!(!none_match_hits("\"abd\"", "\"abc\""));
+    }
+}
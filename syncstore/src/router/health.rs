@@ -1,10 +1,21 @@
-use salvo::{Router, handler};
+use std::sync::Arc;
+
+use salvo::{Depot, Response, Router, handler, http::StatusCode};
+
+use crate::{error::ServiceResult, store::Store};
 
 pub fn create_router() -> Router {
     Router::with_path("health").get(get_health)
 }
 
+/// 503 as soon as one namespace database is unreachable, the details are on the admin router.
 #[handler]
-fn get_health() -> &'static str {
-    "OK"
+fn get_health(depot: &mut Depot, res: &mut Response) -> ServiceResult<&'static str> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    if store.health().iter().all(|h| h.reachable) {
+        Ok("OK")
+    } else {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        Ok("UNAVAILABLE")
+    }
 }
@@ -1,18 +1,25 @@
 use std::fmt;
+use std::io::Read;
 
 use base64::Engine;
 use http_body_util::BodyExt;
 use salvo::{
     Depot, Extractible, Request, Response, Scribe, Writer, async_trait,
     extract::Metadata,
-    http::{HeaderValue, StatusError, header::CONTENT_TYPE},
+    http::{
+        HeaderValue, StatusError,
+        header::{ACCEPT, CONTENT_ENCODING, CONTENT_TYPE},
+    },
     oapi::{
         Components, Content, EndpointArgRegister, EndpointOutRegister, Operation, RequestBody, ToRequestBody, ToSchema,
     },
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{types::UserSchema, utils::hpke};
+use crate::{
+    types::UserSchema,
+    utils::{constant::MAX_DECOMPRESSED_BODY_SIZE, hpke},
+};
 
 /// HPKE JSON body extractor
 #[derive(ToSchema)]
@@ -56,14 +63,17 @@ where
             hpke::decrypt_data(&bytes, &encapped_key, &user_schema.secret_key, &aad)
                 .map_err(|e| StatusError::bad_request().brief(e.to_string()))?
         } else {
-            tracing::info!("HPKE[extract req]: no X-Enc depot found, treat as plain JSON");
+            tracing::info!("HPKE[extract req]: no X-Enc depot found, treat as plain body");
             req.payload()
                 .await
                 .map_err(|e| StatusError::bad_request().brief(e.to_string()))?
                 .to_vec()
         };
-        let value = serde_json::from_slice(&final_bytes)
-            .map_err(|e| StatusError::bad_request().brief(format!("invalid json body: {}", e)))?;
+        // clients compress before encrypting, so the body is decompressed after the decryption
+        let final_bytes = decompress_body(req.headers(), final_bytes)?;
+        let value = BodyFormat::from_content_type(req.headers())
+            .decode(&final_bytes)
+            .map_err(|e| StatusError::bad_request().brief(e))?;
 
         Ok::<HpkeRequest<T>, StatusError>(HpkeRequest(value))
     }
@@ -73,9 +83,11 @@ where
     T: Deserialize<'de> + ToSchema,
 {
     fn to_request_body(components: &mut Components) -> RequestBody {
+        let schema = T::to_schema(components);
         RequestBody::new()
-            .description("Extract HPKE json format data from request.")
-            .add_content("application/json", Content::new(T::to_schema(components)))
+            .description("Extract HPKE json or cbor format data from request.")
+            .add_content("application/json", Content::new(schema.clone()))
+            .add_content("application/cbor", Content::new(schema))
     }
 }
 
@@ -107,7 +119,8 @@ where
     T: Serialize + Send,
 {
     fn render(self, res: &mut Response) {
-        let plaintext = match serde_json::to_vec(&self.0) {
+        let format = res.extensions.get::<BodyFormat>().copied().unwrap_or_default();
+        let plaintext = match format.encode(&self.0) {
             Ok(v) => v,
             Err(e) => {
                 tracing::error!(error = ?e, "HpkeJson serialize failed");
@@ -121,11 +134,9 @@ where
             res.headers().get_base64("X-Session-PubKey"),
             res.headers().get_bytes("X-Path"),
         ) else {
-            tracing::info!("HPKE[res]: no HPKE response key found, treat as plain JSON");
-            res.headers_mut().insert(
-                CONTENT_TYPE,
-                HeaderValue::from_static("application/json; charset=utf-8"),
-            );
+            tracing::info!("HPKE[res]: no HPKE response key found, treat as plain body");
+            res.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
             let _ = res.write_body(plaintext);
             return;
         };
@@ -150,6 +161,104 @@ where
     }
 }
 
+/// Wire format of the (decrypted) request and response bodies.
+///
+/// Requests pick it with `Content-Type`, responses with `Accept`, JSON when absent or unsupported.
+/// CBOR encodes the same serde model, it shrinks and speeds up large data listings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyFormat {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl BodyFormat {
+    const CBOR_MIME: &str = "application/cbor";
+
+    pub fn from_content_type(headers: &salvo::http::HeaderMap) -> Self {
+        match headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+            Some(v) if mime_essence(v) == Self::CBOR_MIME => BodyFormat::Cbor,
+            _ => BodyFormat::Json,
+        }
+    }
+
+    /// First supported format listed in `Accept`.
+    pub fn from_accept(headers: &salvo::http::HeaderMap) -> Self {
+        headers
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .into_iter()
+            .flat_map(|v| v.split(','))
+            .find_map(|v| match mime_essence(v).as_str() {
+                Self::CBOR_MIME => Some(BodyFormat::Cbor),
+                "application/json" => Some(BodyFormat::Json),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            BodyFormat::Json => "application/json; charset=utf-8",
+            BodyFormat::Cbor => Self::CBOR_MIME,
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            BodyFormat::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            BodyFormat::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf).map_err(|e| e.to_string())?;
+                Ok(buf)
+            }
+        }
+    }
+
+    pub fn decode<T: serde::de::DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            BodyFormat::Json => serde_json::from_slice(bytes).map_err(|e| format!("invalid json body: {}", e)),
+            BodyFormat::Cbor => ciborium::from_reader(bytes).map_err(|e| format!("invalid cbor body: {}", e)),
+        }
+    }
+}
+
+/// Undo the `Content-Encoding` of a request body, only `gzip` is supported.
+///
+/// The decompressed size is capped, a small gzip body can't blow up into an unbounded allocation.
+pub fn decompress_body(headers: &salvo::http::HeaderMap, bytes: Vec<u8>) -> Result<Vec<u8>, StatusError> {
+    let encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase());
+    match encoding.as_deref() {
+        None | Some("identity") => Ok(bytes),
+        Some("gzip") | Some("x-gzip") => {
+            let mut decoder =
+                flate2::read::GzDecoder::new(bytes.as_slice()).take(MAX_DECOMPRESSED_BODY_SIZE as u64 + 1);
+            let mut decompressed = Vec::new();
+            decoder
+                .read_to_end(&mut decompressed)
+                .map_err(|e| StatusError::bad_request().brief(format!("invalid gzip body: {}", e)))?;
+            if decompressed.len() > MAX_DECOMPRESSED_BODY_SIZE {
+                return Err(StatusError::payload_too_large().brief(format!(
+                    "decompressed body exceeds {} bytes",
+                    MAX_DECOMPRESSED_BODY_SIZE
+                )));
+            }
+            Ok(decompressed)
+        }
+        Some(other) => {
+            Err(StatusError::unsupported_media_type().brief(format!("unsupported content encoding `{}`", other)))
+        }
+    }
+}
+
+// `application/cbor; q=0.9` -> `application/cbor`
+fn mime_essence(value: &str) -> String {
+    value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
 // define a header helper trait
 trait HeaderExt {
     fn get_bytes(&self, name: impl AsRef<str>) -> Option<Vec<u8>>;
@@ -182,3 +291,52 @@ impl HeaderExt for salvo::http::HeaderMap {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::http::HeaderMap;
+
+    use super::*;
+
+    #[test]
+    fn body_format_negotiation() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(BodyFormat::from_accept(&headers), BodyFormat::Json);
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("text/html, application/cbor;q=0.9, */*"),
+        );
+        assert_eq!(BodyFormat::from_accept(&headers), BodyFormat::Cbor);
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json, application/cbor"));
+        assert_eq!(BodyFormat::from_accept(&headers), BodyFormat::Json);
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("Application/CBOR"));
+        assert_eq!(BodyFormat::from_content_type(&headers), BodyFormat::Cbor);
+    }
+
+    #[test]
+    fn gzip_body_decompressed() {
+        use std::io::Write;
+
+        let body = br#"{"items":[{"name":"repo"}]}"#.to_vec();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&body).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(decompress_body(&headers, body.clone()).unwrap(), body);
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        assert_eq!(decompress_body(&headers, gzipped).unwrap(), body);
+        assert!(decompress_body(&headers, body.clone()).is_err());
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("br"));
+        assert!(decompress_body(&headers, body).is_err());
+    }
+
+    #[test]
+    fn cbor_roundtrip() {
+        let value = serde_json::json!({ "name": "repo", "tags": ["a", "b"], "count": 3, "nested": { "ok": true } });
+        let cbor = BodyFormat::Cbor.encode(&value).unwrap();
+        assert!(cbor.len() < BodyFormat::Json.encode(&value).unwrap().len());
+        let back: serde_json::Value = BodyFormat::Cbor.decode(&cbor).unwrap();
+        assert_eq!(back, value);
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use salvo::{
+    Depot, Request, Router,
+    oapi::{ToResponse, ToSchema, endpoint},
+};
+use serde::Serialize;
+
+use crate::{
+    config::{PageSize, ServiceConfig},
+    error::ServiceResult,
+    router::metrics::TaggedRouter,
+    store::Store,
+    types::UserSchema,
+    utils::{
+        constant::{MAX_ATTACHMENT_SIZE, MAX_BATCH_ITEMS, MAX_BULK_INSERT_ITEMS, MAX_DECOMPRESSED_BODY_SIZE},
+        jwt::{ACCESS_TOKEN_EXPIRATION, REFRESH_TOKEN_EXPIRATION},
+    },
+};
+
+pub fn create_router() -> Router {
+    Router::new().get(get_meta).tagged("meta")
+}
+
+/// Optional features of this server, fixed at startup.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ServerFeatures {
+    /// end-to-end encrypted request and response bodies
+    pub hpke: bool,
+    pub webhooks: bool,
+    pub replication: bool,
+    pub rate_limit: bool,
+    /// writes are refused for a while after repeated validation failures
+    pub validation_limit: bool,
+    /// notifications are also delivered outside of the API, e.g. by email
+    pub notification_delivery: bool,
+}
+
+impl ServerFeatures {
+    pub fn new(config: &ServiceConfig) -> Self {
+        Self {
+            hpke: true,
+            webhooks: config.webhooks.is_some(),
+            replication: config.replication.is_some(),
+            rate_limit: config.rate_limit.is_some(),
+            validation_limit: config.validation_limit.is_some(),
+            notification_delivery: config.delivery.is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ServerLimits {
+    /// max request body size in bytes
+    pub max_body_size: usize,
+    pub default_page_size: usize,
+    pub max_page_size: usize,
+    /// max items of the batch endpoints
+    pub max_batch_items: usize,
+    pub max_bulk_insert_items: usize,
+    /// max size of a gzip request body once decompressed
+    pub max_decompressed_body_size: usize,
+    pub max_attachment_size: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenTtls {
+    pub access_token_secs: i64,
+    pub refresh_token_secs: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema, ToResponse)]
+pub struct ServerMeta {
+    pub version: String,
+    pub features: ServerFeatures,
+    /// namespaces the caller can access
+    pub namespaces: Vec<String>,
+    pub limits: ServerLimits,
+    pub token_ttls: TokenTtls,
+}
+
+impl salvo::Scribe for ServerMeta {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Describe the server capabilities, so clients can adapt without hard-coding them
+#[endpoint(
+    status_codes(200),
+    responses(
+        (status_code = 200, description = "Get server meta successfully", body = ServerMeta),
+    )
+)]
+async fn get_meta(req: &mut Request, depot: &mut Depot) -> ServiceResult<ServerMeta> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let features = depot.obtain::<Arc<ServerFeatures>>()?;
+    let page_size = depot.obtain::<PageSize>()?;
+    Ok(ServerMeta {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        features: features.as_ref().clone(),
+        namespaces: store.accessible_namespaces(&user.user_id)?,
+        limits: ServerLimits {
+            max_body_size: req.secure_max_size(),
+            default_page_size: page_size.default,
+            max_page_size: page_size.max,
+            max_batch_items: MAX_BATCH_ITEMS,
+            max_bulk_insert_items: MAX_BULK_INSERT_ITEMS,
+            max_decompressed_body_size: MAX_DECOMPRESSED_BODY_SIZE,
+            max_attachment_size: MAX_ATTACHMENT_SIZE,
+        },
+        token_ttls: TokenTtls {
+            access_token_secs: ACCESS_TOKEN_EXPIRATION,
+            refresh_token_secs: REFRESH_TOKEN_EXPIRATION,
+        },
+    })
+}
@@ -0,0 +1,353 @@
+use std::{
+    fmt::Write as _,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use salvo::{
+    Depot, FlowCtrl, Request, Response, Router, handler,
+    http::StatusCode,
+    oapi::{RouterExt, ToSchema},
+};
+use serde::Serialize;
+
+use crate::backend::write_queue::WriteQueueStats;
+
+// past this many series new collections are counted without their collection label,
+// a client asking for made up collections must not grow the metrics without bound
+const MAX_SERIES: usize = 4096;
+
+const TAG_KEY: &str = "metrics_tag";
+
+// routes without an oapi tag, e.g. the health check
+const UNTAGGED: &str = "untagged";
+
+/// Request counters grouped by the oapi tag of the route and the collection it targets.
+///
+/// Paths carry data ids, grouping by them would give every item its own series.
+#[derive(Default)]
+pub struct RequestMetrics {
+    series: DashMap<SeriesKey, SeriesStats>,
+    // dict<collection, counters> of the writes refused as invalid, see `rate_limit::validation_guard`
+    validation: DashMap<String, ValidationStats>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct SeriesKey {
+    tag: &'static str,
+    collection: Option<String>,
+    method: String,
+    status: u16,
+}
+
+#[derive(Default, Clone)]
+struct SeriesStats {
+    requests: u64,
+    total_duration: Duration,
+    max_duration: Duration,
+}
+
+#[derive(Default, Clone)]
+struct ValidationStats {
+    failures: u64,
+    throttled: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RouteMetrics {
+    pub tag: String,
+    pub collection: Option<String>,
+    pub method: String,
+    pub status: u16,
+    pub requests: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+impl RequestMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, tag: &'static str, collection: Option<String>, method: &str, status: u16, elapsed: Duration) {
+        let mut key = SeriesKey {
+            tag,
+            collection,
+            method: method.to_string(),
+            status,
+        };
+        if key.collection.is_some() && self.series.len() >= MAX_SERIES && !self.series.contains_key(&key) {
+            key.collection = None;
+        }
+        let mut stats = self.series.entry(key).or_default();
+        stats.requests += 1;
+        stats.total_duration += elapsed;
+        stats.max_duration = stats.max_duration.max(elapsed);
+    }
+
+    /// Account a write to the collection refused as invalid.
+    pub fn record_validation_failure(&self, collection: &str) {
+        self.validation_stats(collection, |stats| stats.failures += 1);
+    }
+
+    /// Account a write to the collection refused because its user failed validation too often.
+    pub fn record_validation_throttled(&self, collection: &str) {
+        self.validation_stats(collection, |stats| stats.throttled += 1);
+    }
+
+    fn validation_stats(&self, collection: &str, update: impl FnOnce(&mut ValidationStats)) {
+        let collection = if self.validation.len() >= MAX_SERIES && !self.validation.contains_key(collection) {
+            ""
+        } else {
+            collection
+        };
+        update(self.validation.entry(collection.to_string()).or_default().value_mut());
+    }
+
+    /// All series, ordered by tag, collection, method and status.
+    pub fn snapshot(&self) -> Vec<RouteMetrics> {
+        self.sorted()
+            .iter()
+            .map(|(key, stats)| to_metrics(key, stats))
+            .collect()
+    }
+
+    /// The Prometheus text exposition of the series.
+    pub fn render_prometheus(&self) -> String {
+        type Value = fn(&SeriesStats) -> f64;
+        let families: [(&str, &str, &str, Value); 3] = [
+            ("syncstore_requests_total", "counter", "Requests handled.", |s| {
+                s.requests as f64
+            }),
+            (
+                "syncstore_request_duration_seconds_sum",
+                "counter",
+                "Total time spent handling requests.",
+                |s| s.total_duration.as_secs_f64(),
+            ),
+            (
+                "syncstore_request_duration_seconds_max",
+                "gauge",
+                "Longest request handled.",
+                |s| s.max_duration.as_secs_f64(),
+            ),
+        ];
+        let series = self.sorted();
+        let mut out = String::new();
+        for (name, ty, help, value) in families {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, ty);
+            for (key, stats) in &series {
+                let _ = writeln!(
+                    out,
+                    "{}{{tag=\"{}\",collection=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                    name,
+                    escape_label(key.tag),
+                    escape_label(key.collection.as_deref().unwrap_or("")),
+                    escape_label(&key.method),
+                    key.status,
+                    value(stats)
+                );
+            }
+        }
+        let mut validation: Vec<_> = self
+            .validation
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        validation.sort_by(|a, b| a.0.cmp(&b.0));
+        type Counter = fn(&ValidationStats) -> u64;
+        let counters: [(&str, &str, Counter); 2] = [
+            (
+                "syncstore_validation_failures_total",
+                "Writes refused as invalid.",
+                |s| s.failures,
+            ),
+            (
+                "syncstore_validation_throttled_total",
+                "Writes refused after repeated validation failures of their user.",
+                |s| s.throttled,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (collection, stats) in &validation {
+                let _ = writeln!(
+                    out,
+                    "{}{{collection=\"{}\"}} {}",
+                    name,
+                    escape_label(collection),
+                    value(stats)
+                );
+            }
+        }
+        out
+    }
+
+    fn sorted(&self) -> Vec<(SeriesKey, SeriesStats)> {
+        let mut series: Vec<_> = self
+            .series
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        series.sort_by(|a, b| a.0.cmp(&b.0));
+        series
+    }
+}
+
+fn to_metrics(key: &SeriesKey, stats: &SeriesStats) -> RouteMetrics {
+    RouteMetrics {
+        tag: key.tag.to_string(),
+        collection: key.collection.clone(),
+        method: key.method.clone(),
+        status: key.status,
+        requests: stats.requests,
+        total_ms: stats.total_duration.as_micros() as f64 / 1000.0,
+        max_ms: stats.max_duration.as_micros() as f64 / 1000.0,
+    }
+}
+
+/// The Prometheus text exposition of the write queue, see `backend::write_queue`.
+pub fn render_write_queue(stats: &WriteQueueStats) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# HELP syncstore_write_queue_running Writes holding a slot of the writer."
+    );
+    let _ = writeln!(out, "# TYPE syncstore_write_queue_running gauge");
+    let _ = writeln!(out, "syncstore_write_queue_running {}", stats.running);
+    let tiers = [
+        ("interactive", stats.interactive_waiting, stats.interactive_granted),
+        ("batch", stats.batch_waiting, stats.batch_granted),
+    ];
+    let _ = writeln!(
+        out,
+        "# HELP syncstore_write_queue_waiting Writes waiting for a slot of the writer."
+    );
+    let _ = writeln!(out, "# TYPE syncstore_write_queue_waiting gauge");
+    for (tier, waiting, _) in tiers {
+        let _ = writeln!(out, "syncstore_write_queue_waiting{{tier=\"{}\"}} {}", tier, waiting);
+    }
+    let _ = writeln!(
+        out,
+        "# HELP syncstore_write_queue_granted_total Writes let in by the write queue."
+    );
+    let _ = writeln!(out, "# TYPE syncstore_write_queue_granted_total counter");
+    for (tier, _, granted) in tiers {
+        let _ = writeln!(
+            out,
+            "syncstore_write_queue_granted_total{{tier=\"{}\"}} {}",
+            tier, granted
+        );
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Tag routes in the OpenAPI document and in the request metrics alike.
+pub trait TaggedRouter {
+    fn tagged(self, tag: &'static str) -> Self;
+}
+
+impl TaggedRouter for Router {
+    fn tagged(self, tag: &'static str) -> Self {
+        self.hoop(MetricsTag(tag)).oapi_tag(tag)
+    }
+}
+
+struct MetricsTag(&'static str);
+
+#[handler]
+impl MetricsTag {
+    // the innermost tagged router runs last and wins
+    async fn handle(&self, depot: &mut Depot) {
+        depot.insert(TAG_KEY, self.0);
+    }
+}
+
+/// Time the rest of the chain and account it to the route's tag and collection.
+#[handler]
+pub async fn record(req: &mut Request, res: &mut Response, depot: &mut Depot, ctrl: &mut FlowCtrl) {
+    let started = Instant::now();
+    ctrl.call_next(req, depot, res).await;
+    let Ok(metrics) = depot.obtain::<Arc<RequestMetrics>>() else {
+        return;
+    };
+    let tag = depot.get::<&'static str>(TAG_KEY).map(|t| *t).unwrap_or(UNTAGGED);
+    let collection = req.param::<String>("collection");
+    let status = res.status_code.unwrap_or(StatusCode::OK).as_u16();
+    metrics.record(tag, collection, req.method().as_str(), status, started.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_grouped_by_tag_and_collection() {
+        let metrics = RequestMetrics::new();
+        for _ in 0..3 {
+            metrics.record("data", Some("post".to_string()), "GET", 200, Duration::from_millis(10));
+        }
+        metrics.record("data", Some("post".to_string()), "GET", 404, Duration::from_millis(1));
+        metrics.record("acl", None, "POST", 200, Duration::from_millis(30));
+
+        let series = metrics.snapshot();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].tag, "acl");
+        assert_eq!((series[1].status, series[1].requests), (200, 3));
+        assert_eq!(series[1].max_ms, 10.0);
+
+        let text = metrics.render_prometheus();
+        assert!(
+            text.contains("syncstore_requests_total{tag=\"data\",collection=\"post\",method=\"GET\",status=\"200\"} 3")
+        );
+        assert!(text.contains(
+            "syncstore_request_duration_seconds_max{tag=\"acl\",collection=\"\",method=\"POST\",status=\"200\"} 0.03"
+        ));
+    }
+
+    #[test]
+    fn test_collection_label_dropped_past_series_cap() {
+        let metrics = RequestMetrics::new();
+        for i in 0..MAX_SERIES {
+            metrics.record("data", Some(format!("c{}", i)), "GET", 200, Duration::ZERO);
+        }
+        metrics.record("data", Some("c0".to_string()), "GET", 200, Duration::ZERO);
+        metrics.record("data", Some("unknown".to_string()), "GET", 200, Duration::ZERO);
+        let series = metrics.snapshot();
+        assert_eq!(series.len(), MAX_SERIES + 1);
+        assert!(series.iter().any(|s| s.collection.is_none()));
+    }
+
+    #[test]
+    fn test_validation_counters() {
+        let metrics = RequestMetrics::new();
+        metrics.record_validation_failure("post");
+        metrics.record_validation_failure("post");
+        metrics.record_validation_throttled("post");
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("syncstore_validation_failures_total{collection=\"post\"} 2"));
+        assert!(text.contains("syncstore_validation_throttled_total{collection=\"post\"} 1"));
+    }
+
+    #[test]
+    fn test_write_queue_gauges() {
+        let text = render_write_queue(&WriteQueueStats {
+            running: 1,
+            interactive_waiting: 2,
+            batch_waiting: 5,
+            interactive_granted: 10,
+            batch_granted: 3,
+        });
+        assert!(text.contains("syncstore_write_queue_running 1"));
+        assert!(text.contains("syncstore_write_queue_waiting{tier=\"batch\"} 5"));
+        assert!(text.contains("syncstore_write_queue_granted_total{tier=\"interactive\"} 10"));
+    }
+}
@@ -3,17 +3,31 @@ mod admin;
 mod auth;
 mod chunk_data_wrapper;
 mod data;
+mod export;
 mod fs;
 mod health;
 mod hpke_wrapper;
+mod meta;
+mod metrics;
+mod notification;
+mod passkey;
+mod rate_limit;
+mod snapshot;
+mod sync;
+mod timeout;
+mod transaction;
 mod user;
+mod view;
+
+pub use metrics::RequestMetrics;
+pub use rate_limit::RateLimiter;
 
 use std::sync::Arc;
 
 use dashmap::DashMap;
 use salvo::{
     Depot, FlowCtrl, Request, Response, Router, affix_state, handler,
-    http::HeaderValue,
+    http::{HeaderValue, Method},
     jwt_auth::{ConstDecoder, HeaderFinder, QueryFinder},
     oapi::{RouterExt, SecurityRequirement},
     prelude::{JwtAuth, JwtAuthDepotExt, JwtAuthState},
@@ -26,7 +40,14 @@ use crate::{
     utils::jwt::JwtClaims,
 };
 
-pub fn create_router(config: &ServiceConfig, store: Arc<Store>) -> Router {
+const API_KEY_HEADER: &str = "X-Api-Key";
+
+pub fn create_router(
+    config: &ServiceConfig,
+    store: Arc<Store>,
+    metrics: Arc<RequestMetrics>,
+    rate_limiter: Arc<RateLimiter>,
+) -> Router {
     let auth_handler: JwtAuth<JwtClaims, _> =
         JwtAuth::new(ConstDecoder::from_secret(config.jwt.access_secret.as_bytes()))
             .finders(vec![
@@ -36,29 +57,94 @@ pub fn create_router(config: &ServiceConfig, store: Arc<Store>) -> Router {
             .force_passed(true);
 
     let non_auth_router = Router::new()
-        .push(Router::with_path("auth").push(auth::create_non_auth_router()))
+        .push(
+            Router::with_path("auth")
+                .push(auth::create_non_auth_router())
+                .push(passkey::create_non_auth_router()),
+        )
         .push(Router::with_path("fs").push(fs::create_non_auth_router()))
         .push(health::create_router());
+    // requests carrying a service key only reach the data routes, the others fall through to the jwt auth
+    let service_router = Router::new()
+        .filter_fn(|req, _| req.headers().contains_key(API_KEY_HEADER))
+        .hoop(api_key_to_user)
+        .hoop(rate_limit::rate_limit)
+        .hoop(header_makeup)
+        .push(
+            Router::with_path("data")
+                .hoop(snapshot::read_snapshot)
+                .hoop(rate_limit::validation_guard)
+                .push(data::create_data_router()),
+        )
+        .push(
+            Router::with_path("batch-data")
+                .hoop(snapshot::read_snapshot)
+                .hoop(rate_limit::validation_guard)
+                .push(data::create_batch_data_router()),
+        );
     let auth_router = Router::new()
         .hoop(auth_handler)
         .hoop(jwt_to_user)
+        .hoop(rate_limit::rate_limit)
         .hoop(header_makeup)
         // .hoop(hpke)
         .push(Router::with_path("acl").push(acl::create_router()))
-        .push(Router::with_path("auth").push(auth::create_router()))
-        .push(Router::with_path("data").push(data::create_data_router()))
-        .push(Router::with_path("batch-data").push(data::create_batch_data_router()))
+        .push(
+            Router::with_path("auth")
+                .push(auth::create_router())
+                .push(passkey::create_router()),
+        )
+        .push(
+            Router::with_path("data")
+                .hoop(snapshot::read_snapshot)
+                .hoop(rate_limit::validation_guard)
+                // before the data routes, `{namespace}/{collection}` would match them as well
+                .push(data::create_usage_router())
+                .push(view::create_router())
+                .push(data::create_data_router()),
+        )
+        .push(
+            Router::with_path("batch-data")
+                .hoop(snapshot::read_snapshot)
+                .hoop(rate_limit::validation_guard)
+                .push(data::create_batch_data_router()),
+        )
+        .push(Router::with_path("export").push(export::create_router()))
         .push(Router::with_path("fs").push(fs::create_router()))
+        .push(Router::with_path("meta").push(meta::create_router()))
+        .push(Router::with_path("notifications").push(notification::create_router()))
+        .push(Router::with_path("snapshots").push(snapshot::create_router()))
+        .push(Router::with_path("sync").push(sync::create_router()))
         .push(Router::with_path("user").push(user::create_router()))
         .oapi_security(SecurityRequirement::new("bearer", vec!["bearer"]));
     let chunk_status: DashMap<String, chunk_data_wrapper::UploadStatus> = DashMap::new();
+    let validation_guard = rate_limit::ValidationGuard::new(config.validation_limit.clone());
     let router = Router::new()
         .hoop(affix_state::inject(store))
         .hoop(affix_state::inject(Arc::new(chunk_status)))
+        .hoop(affix_state::inject(Arc::new(user::AccountExports::new())))
+        .hoop(affix_state::inject(Arc::new(snapshot::ReadSnapshots::new())))
+        .hoop(affix_state::inject(Arc::new(fs::UploadGrants::new())))
+        .hoop(affix_state::inject(rate_limiter))
+        .hoop(affix_state::inject(Arc::new(validation_guard)))
         .hoop(affix_state::inject(config.latency_inject))
+        .hoop(affix_state::inject(config.page_size))
+        .hoop(affix_state::inject(config.request_timeout.clone().unwrap_or_default()))
+        .hoop(affix_state::inject(Arc::new(meta::ServerFeatures::new(config))))
+        .hoop(affix_state::inject(Arc::new(passkey::PasskeyState::new(
+            config.passkey.clone(),
+        ))))
+        .hoop(affix_state::inject(metrics))
+        .hoop(metrics::record)
+        .push(service_router)
         .push(auth_router)
         .push(non_auth_router);
 
+    let router = if config.request_timeout.is_some() {
+        router.hoop(timeout::request_timeout)
+    } else {
+        router
+    };
     if config.latency_inject.is_some() {
         router.hoop(latency_inject)
     } else {
@@ -76,9 +162,11 @@ pub async fn latency_inject(req: &mut Request, res: &mut Response, depot: &mut D
     ctrl.call_next(req, depot, res).await;
 }
 
-pub fn admin_router(store: Arc<Store>) -> Router {
+pub fn admin_router(store: Arc<Store>, metrics: Arc<RequestMetrics>, rate_limiter: Arc<RateLimiter>) -> Router {
     Router::new()
         .hoop(affix_state::inject(store))
+        .hoop(affix_state::inject(metrics))
+        .hoop(affix_state::inject(rate_limiter))
         .push(admin::create_router())
 }
 
@@ -111,6 +199,13 @@ async fn jwt_to_user(
                 ctrl.skip_rest();
                 return Ok(());
             };
+            // tokens issued before the deactivation are refused too
+            if user.deactivated_at.is_some() {
+                tracing::info!("Unauthorized: User {} deactivated", user_id);
+                res.render(ServiceError::Unauthorized("Account deactivated".to_string()));
+                ctrl.skip_rest();
+                return Ok(());
+            }
             tracing::info!("Authorized. user:{}({})", user.username, user_id);
             depot.insert("user_schema", user.clone());
             if let Some(x_enc) = req.headers().get("X-Enc") {
@@ -135,6 +230,76 @@ async fn jwt_to_user(
     Ok(())
 }
 
+// check the service key against the namespace and collection of the request, act as its service identity.
+#[handler]
+async fn api_key_to_user(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+    ctrl: &mut FlowCtrl,
+) -> ServiceResult<()> {
+    let key = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let (Some(namespace), Some(collection)) = (req.param::<String>("namespace"), req.param::<String>("collection"))
+    else {
+        res.render(ServiceError::Unauthorized("Invalid service key".to_string()));
+        ctrl.skip_rest();
+        return Ok(());
+    };
+    let store = depot.obtain::<Arc<Store>>()?;
+    let Ok(Some(service_key)) = store.validate_service_key(&namespace, &key) else {
+        tracing::info!("Unauthorized: Invalid service key for {}", namespace);
+        res.render(ServiceError::Unauthorized("Invalid service key".to_string()));
+        ctrl.skip_rest();
+        return Ok(());
+    };
+    let write = is_write(req, &namespace, &collection);
+    if !service_key.allows(&collection, write) {
+        tracing::info!(
+            "Forbidden: service key {} has no {} access to {}/{}",
+            service_key.id,
+            if write { "write" } else { "read" },
+            namespace,
+            collection
+        );
+        res.render(ServiceError::Forbidden(format!(
+            "service key has no {} access to collection {}",
+            if write { "write" } else { "read" },
+            collection
+        )));
+        ctrl.skip_rest();
+        return Ok(());
+    }
+    let user = store.get_user(&service_key.user_id)?;
+    tracing::info!("Authorized. service key:{}({})", service_key.name, user.user_id);
+    depot.insert("user_schema", user);
+    // rate limited apart from the interactive clients of the user
+    depot.insert("service_key_id", service_key.id.clone());
+    if let Some(x_enc) = req.headers().get("X-Enc") {
+        depot.insert("X-Enc", x_enc.clone());
+    }
+    depot.insert("X-Path", req.uri().path().to_string());
+
+    ctrl.call_next(req, depot, res).await;
+    Ok(())
+}
+
+// the batch-data routes are posted reads, except the insert
+fn is_write(req: &Request, namespace: &str, collection: &str) -> bool {
+    if *req.method() == Method::GET {
+        return false;
+    }
+    let batch = format!("/batch-data/{}/{}", namespace, collection);
+    match req.uri().path().split_once(&batch) {
+        Some((_, rest)) => rest.trim_matches('/') == "insert",
+        None => true,
+    }
+}
+
 #[handler]
 async fn header_makeup(
     req: &mut Request,
@@ -156,6 +321,10 @@ async fn header_makeup(
         );
     }
 
+    // body format of the hpke responses
+    res.extensions
+        .insert(hpke_wrapper::BodyFormat::from_accept(req.headers()));
+
     ctrl.call_next(req, depot, res).await;
     Ok(())
 }
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use salvo::{
+    Depot, Router, Scribe, Writer,
+    oapi::{
+        ToResponse, ToSchema, endpoint,
+        extract::{PathParam, QueryParam},
+    },
+    writing::Json,
+};
+use serde::Serialize;
+
+use crate::{
+    config::PageSize,
+    error::ServiceResult,
+    router::{hpke_wrapper::HpkeResponse, metrics::TaggedRouter},
+    store::Store,
+    types::{Notification, UserSchema},
+};
+
+pub fn create_router() -> Router {
+    Router::new()
+        .get(list_notifications)
+        .push(Router::with_path("unread-count").get(unread_count))
+        .push(Router::with_path("read-all").post(mark_all_read))
+        .push(Router::with_path("{id}").delete(delete_notification))
+        .push(Router::with_path("{id}/read").post(mark_read))
+        .tagged("notification")
+}
+
+/// List notifications of current user, newest first
+#[endpoint(
+    status_codes(200),
+    responses(
+        (status_code = 200, description = "List notifications successfully", body = ListNotificationResponse),
+    )
+)]
+async fn list_notifications(
+    unread: QueryParam<bool, false>,
+    marker: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListNotificationResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let limit = depot.obtain::<PageSize>()?.resolve(limit.into_inner());
+    let (user_id, unread, marker) = (user.user_id.clone(), unread.unwrap_or(false), marker.into_inner());
+    let (items, next_marker) = store
+        .run(move |store| store.list_notifications(&user_id, unread, marker, limit))
+        .await?;
+    Ok(HpkeResponse(ListNotificationResponse { items, next_marker }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct ListNotificationResponse {
+    items: Vec<Notification>,
+    next_marker: Option<String>,
+}
+
+impl Scribe for ListNotificationResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Count unread notifications of current user
+#[endpoint(
+    status_codes(200),
+    responses(
+        (status_code = 200, description = "Get unread count successfully", body = UnreadCountResponse),
+    )
+)]
+async fn unread_count(depot: &mut Depot) -> ServiceResult<HpkeResponse<UnreadCountResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let unread = store
+        .run(move |store| store.unread_notification_count(&user_id))
+        .await?;
+    Ok(HpkeResponse(UnreadCountResponse { unread }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct UnreadCountResponse {
+    unread: u64,
+}
+
+impl Scribe for UnreadCountResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Mark a notification as read
+#[endpoint(
+    status_codes(200, 404),
+    responses(
+        (status_code = 200, description = "Mark notification read successfully", body = Notification),
+        (status_code = 404, description = "Notification not found"),
+    )
+)]
+async fn mark_read(id: PathParam<String>, depot: &mut Depot) -> ServiceResult<HpkeResponse<Notification>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let notification = store
+        .run(move |store| store.mark_notification_read(&user_id, &id))
+        .await?;
+    Ok(HpkeResponse(notification))
+}
+
+/// Mark every notification of current user as read
+#[endpoint(
+    status_codes(200),
+    responses(
+        (status_code = 200, description = "Mark all notifications read successfully", body = MarkAllReadResponse),
+    )
+)]
+async fn mark_all_read(depot: &mut Depot) -> ServiceResult<HpkeResponse<MarkAllReadResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let updated = store
+        .run(move |store| store.mark_all_notifications_read(&user_id))
+        .await?;
+    Ok(HpkeResponse(MarkAllReadResponse { updated }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct MarkAllReadResponse {
+    updated: usize,
+}
+
+impl Scribe for MarkAllReadResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Delete a notification
+#[endpoint(
+    status_codes(200, 404),
+    responses(
+        (status_code = 200, description = "Delete notification successfully"),
+        (status_code = 404, description = "Notification not found"),
+    )
+)]
+async fn delete_notification(id: PathParam<String>, depot: &mut Depot) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    store.run(move |store| store.delete_notification(&user_id, &id)).await?;
+    Ok(())
+}
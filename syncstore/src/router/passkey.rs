@@ -0,0 +1,408 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use salvo::{
+    Depot, Router, Scribe, Writer,
+    oapi::{
+        ToResponse, ToSchema, endpoint,
+        extract::{JsonBody, PathParam},
+    },
+    writing::Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    error::{ServiceError, ServiceResult},
+    router::{
+        auth::LoginResponse,
+        hpke_wrapper::{HpkeRequest, HpkeResponse},
+        metrics::TaggedRouter,
+    },
+    store::Store,
+    types::{Passkey, UserSchema},
+    utils::passkey::{self, RelyingParty},
+};
+
+/// Manage the passkeys of the current user
+pub fn create_router() -> Router {
+    Router::with_path("passkeys")
+        .get(list_passkeys)
+        .push(
+            Router::with_path("register")
+                .post(start_registration)
+                .push(Router::with_path("finish").post(finish_registration)),
+        )
+        .push(Router::with_path("{id}").delete(delete_passkey))
+        .tagged("auth_info")
+}
+
+/// Log in with a passkey, an alternative to `name-login`
+pub fn create_non_auth_router() -> Router {
+    Router::with_path("passkey-login")
+        .post(start_login)
+        .push(Router::with_path("finish").post(finish_login))
+        .tagged("auth")
+}
+
+/// Configuration and the ceremonies in flight, a challenge answers exactly one of them.
+pub struct PasskeyState {
+    config: Option<config::Passkey>,
+    // dict<challenge, ceremony>
+    pending: DashMap<String, Ceremony>,
+}
+
+struct Ceremony {
+    // the registering user, none for a login
+    user_id: Option<String>,
+    expires_at: DateTime<Utc>,
+}
+
+impl PasskeyState {
+    pub fn new(config: Option<config::Passkey>) -> Self {
+        Self {
+            config,
+            pending: DashMap::new(),
+        }
+    }
+
+    fn config(&self) -> ServiceResult<&config::Passkey> {
+        self.config
+            .as_ref()
+            .ok_or_else(|| ServiceError::RequestError("passkey login is not configured".to_string()))
+    }
+
+    fn relying_party(&self) -> ServiceResult<RelyingParty> {
+        let config = self.config()?;
+        Ok(RelyingParty {
+            id: config.rp_id.clone(),
+            origin: config.origin.clone(),
+        })
+    }
+
+    fn issue(&self, user_id: Option<String>) -> ServiceResult<String> {
+        let now = Utc::now();
+        self.pending.retain(|_, ceremony| ceremony.expires_at > now);
+        let challenge = passkey::new_challenge();
+        let ttl = chrono::Duration::from_std(self.config()?.challenge_ttl)
+            .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+        self.pending.insert(
+            challenge.clone(),
+            Ceremony {
+                user_id,
+                expires_at: now + ttl,
+            },
+        );
+        Ok(challenge)
+    }
+
+    // the challenge the client data answers, once, while it is valid and for the same user
+    fn take(&self, client_data_json: &[u8], user_id: Option<&str>) -> Option<String> {
+        let challenge = passkey::client_challenge(client_data_json).ok()?;
+        let (challenge, ceremony) = self.pending.remove(&challenge)?;
+        (ceremony.expires_at > Utc::now() && ceremony.user_id.as_deref() == user_id).then_some(challenge)
+    }
+}
+
+/// List the passkeys of the current user
+#[endpoint(
+    status_codes(200),
+    responses(
+        (status_code = 200, description = "List passkeys successfully", body = ListPasskeysResponse),
+    )
+)]
+async fn list_passkeys(depot: &mut Depot) -> ServiceResult<HpkeResponse<ListPasskeysResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let passkeys = store.run(move |store| store.list_passkeys(&user_id)).await?;
+    Ok(HpkeResponse(ListPasskeysResponse { passkeys }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct ListPasskeysResponse {
+    passkeys: Vec<Passkey>,
+}
+
+impl Scribe for ListPasskeysResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Start registering a passkey
+///
+/// Returns the `publicKey` options of `navigator.credentials.create()`, binary fields in base64url.
+#[endpoint(
+    status_codes(200, 400),
+    responses(
+        (status_code = 200, description = "Registration started", body = CreationOptions),
+        (status_code = 400, description = "Passkeys not configured"),
+    )
+)]
+async fn start_registration(depot: &mut Depot) -> ServiceResult<HpkeResponse<CreationOptions>> {
+    let user = depot.get::<UserSchema>("user_schema")?.clone();
+    let store = depot.obtain::<Arc<Store>>()?;
+    let state = depot.obtain::<Arc<PasskeyState>>()?;
+    let config = state.config()?;
+    let user_id = user.user_id.clone();
+    let existing = store.run(move |store| store.list_passkeys(&user_id)).await?;
+    let challenge = state.issue(Some(user.user_id.clone()))?;
+    Ok(HpkeResponse(CreationOptions {
+        challenge,
+        rp: RpEntity {
+            id: config.rp_id.clone(),
+            name: config.rp_name.clone().unwrap_or_else(|| config.rp_id.clone()),
+        },
+        user: UserEntity {
+            id: passkey::encode(user.user_id.as_bytes()),
+            name: user.username.clone(),
+            display_name: user.username,
+        },
+        pub_key_cred_params: vec![CredentialParameter {
+            ty: "public-key".to_string(),
+            alg: -7,
+        }],
+        timeout: config.challenge_ttl.as_millis() as u64,
+        attestation: "none".to_string(),
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "required".to_string(),
+            user_verification: "required".to_string(),
+        },
+        exclude_credentials: existing
+            .into_iter()
+            .map(|passkey| CredentialDescriptor {
+                ty: "public-key".to_string(),
+                id: passkey.credential_id,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct CreationOptions {
+    challenge: String,
+    rp: RpEntity,
+    user: UserEntity,
+    pub_key_cred_params: Vec<CredentialParameter>,
+    /// milliseconds
+    timeout: u64,
+    attestation: String,
+    authenticator_selection: AuthenticatorSelection,
+    exclude_credentials: Vec<CredentialDescriptor>,
+}
+
+impl Scribe for CreationOptions {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct RpEntity {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct UserEntity {
+    id: String,
+    name: String,
+    display_name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CredentialParameter {
+    #[serde(rename = "type")]
+    ty: String,
+    alg: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct AuthenticatorSelection {
+    resident_key: String,
+    user_verification: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    ty: String,
+    id: String,
+}
+
+/// Finish registering a passkey with the credential the browser created
+#[endpoint(
+    status_codes(200, 400, 409),
+    request_body(content = FinishRegistrationRequest, description = "Created credential"),
+    responses(
+        (status_code = 200, description = "Passkey registered", body = Passkey),
+        (status_code = 400, description = "Bad Request"),
+        (status_code = 409, description = "Credential already registered, or too many passkeys"),
+    )
+)]
+async fn finish_registration(
+    req: HpkeRequest<FinishRegistrationRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<Passkey>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let state = depot.obtain::<Arc<PasskeyState>>()?;
+    let rp = state.relying_party()?;
+    let req = req.0;
+    let client_data_json = passkey::decode("clientDataJSON", &req.credential.response.client_data_json)?;
+    let attestation_object = passkey::decode("attestationObject", &req.credential.response.attestation_object)?;
+    let challenge = state
+        .take(&client_data_json, Some(&user.user_id))
+        .ok_or_else(|| ServiceError::RequestError("unknown or expired registration challenge".to_string()))?;
+    let credential = passkey::verify_registration(&rp, &challenge, &client_data_json, &attestation_object)?;
+    let user_id = user.user_id.clone();
+    let passkey = store
+        .run(move |store| store.add_passkey(&user_id, &req.name, credential))
+        .await?;
+    Ok(HpkeResponse(passkey))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FinishRegistrationRequest {
+    /// label of the passkey, e.g. the device name
+    name: String,
+    credential: RegistrationCredential,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RegistrationCredential {
+    response: AttestationResponse,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    attestation_object: String,
+}
+
+/// Remove a passkey of the current user
+#[endpoint(
+    status_codes(200, 404),
+    responses(
+        (status_code = 200, description = "Passkey removed"),
+        (status_code = 404, description = "Passkey not found"),
+    )
+)]
+async fn delete_passkey(id: PathParam<String>, depot: &mut Depot) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    store.run(move |store| store.delete_passkey(&user_id, &id)).await?;
+    Ok(())
+}
+
+/// Start a passkey login
+///
+/// Returns the `publicKey` options of `navigator.credentials.get()`, any passkey of the site is offered.
+#[endpoint(
+    status_codes(200, 400),
+    responses(
+        (status_code = 200, description = "Login started", body = RequestOptions),
+        (status_code = 400, description = "Passkeys not configured"),
+    )
+)]
+async fn start_login(depot: &mut Depot) -> ServiceResult<RequestOptions> {
+    let state = depot.obtain::<Arc<PasskeyState>>()?;
+    let config = state.config()?;
+    Ok(RequestOptions {
+        challenge: state.issue(None)?,
+        rp_id: config.rp_id.clone(),
+        timeout: config.challenge_ttl.as_millis() as u64,
+        user_verification: "required".to_string(),
+    })
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct RequestOptions {
+    challenge: String,
+    rp_id: String,
+    /// milliseconds
+    timeout: u64,
+    user_verification: String,
+}
+
+impl Scribe for RequestOptions {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Finish a passkey login with the assertion the browser signed
+///
+/// Returns the same tokens as the password login.
+#[endpoint(
+    status_codes(200, 401),
+    request_body(content = AssertionCredential, description = "Signed assertion"),
+    responses(
+        (status_code = 200, description = "Login successful", body = LoginResponse),
+        (status_code = 401, description = "Unauthorized"),
+    )
+)]
+async fn finish_login(req: JsonBody<AssertionCredential>, depot: &mut Depot) -> ServiceResult<LoginResponse> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let state = depot.obtain::<Arc<PasskeyState>>()?;
+    let rp = state.relying_party()?;
+    let req = req.into_inner();
+    let unauthorized = || ServiceError::Unauthorized("Invalid passkey".to_string());
+    let decode = |field: &str, value: &str| passkey::decode(field, value).map_err(|_| unauthorized());
+    let client_data_json = decode("clientDataJSON", &req.response.client_data_json)?;
+    let authenticator_data = decode("authenticatorData", &req.response.authenticator_data)?;
+    let signature = decode("signature", &req.response.signature)?;
+    let challenge = state.take(&client_data_json, None).ok_or_else(unauthorized)?;
+    let verified = store
+        .run(move |store| {
+            store.validate_passkey(&req.id, |key| {
+                passkey::verify_assertion(
+                    &rp,
+                    &challenge,
+                    &client_data_json,
+                    &authenticator_data,
+                    &signature,
+                    &key.public_key,
+                    key.sign_count,
+                )
+            })
+        })
+        .await;
+    match verified {
+        Ok(Some(user_id)) => {
+            tracing::info!("Passkey login for user: {}", user_id);
+            LoginResponse::issue(user_id)
+        }
+        Ok(None) => Err(unauthorized()),
+        Err(e) => {
+            tracing::info!("Passkey login rejected: {}", e);
+            Err(unauthorized())
+        }
+    }
+}
+
+/// Assertion of `navigator.credentials.get()`, binary fields in base64url
+#[derive(Deserialize, ToSchema)]
+struct AssertionCredential {
+    /// credential id
+    id: String,
+    response: AssertionResponse,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    authenticator_data: String,
+    signature: String,
+}
@@ -0,0 +1,393 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use salvo::{
+    Depot, FlowCtrl, Request, Response, handler,
+    http::{HeaderValue, Method, StatusCode},
+    oapi::ToSchema,
+};
+use serde::Serialize;
+
+use crate::{
+    config::{RateLimit, ValidationLimit},
+    error::{ServiceError, ServiceResult},
+    router::metrics::RequestMetrics,
+    types::UserSchema,
+};
+
+// window used for usage accounting when no limit is configured
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+// usage of a token without requests for that long is dropped, its totals start over
+const IDLE_EVICTION: Duration = Duration::from_secs(60 * 60);
+
+/// Identity a request is accounted to: the user of a jwt, or the service key it came with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateKey {
+    User(String),
+    ServiceKey(String),
+}
+
+/// Sliding window request counter per token identity.
+///
+/// Requests are always accounted, the limit is only enforced when `RateLimit` is configured.
+pub struct RateLimiter {
+    config: Option<RateLimit>,
+    // dict<token key, usage>
+    usage: DashMap<RateKey, TokenUsage>,
+    last_eviction: Mutex<Instant>,
+}
+
+struct TokenUsage {
+    window: VecDeque<Instant>,
+    total_requests: u64,
+    throttled_requests: u64,
+    last_request_at: DateTime<Utc>,
+    last_seen: Instant,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UsageSnapshot {
+    pub window_secs: u64,
+    pub window_requests: u64,
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    pub total_requests: u64,
+    pub throttled_requests: u64,
+    pub last_request_at: Option<DateTime<Utc>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: Option<RateLimit>) -> Self {
+        Self {
+            config,
+            usage: DashMap::new(),
+            last_eviction: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn window(&self) -> Duration {
+        self.config.as_ref().map(|c| c.window).unwrap_or(DEFAULT_WINDOW)
+    }
+
+    fn limit(&self, key: &RateKey) -> Option<u64> {
+        let config = self.config.as_ref()?;
+        Some(match key {
+            RateKey::User(_) => config.max_requests,
+            RateKey::ServiceKey(id) => config
+                .service_keys
+                .get(id)
+                .copied()
+                .or(config.service_key_max_requests)
+                .unwrap_or(config.max_requests),
+        })
+    }
+
+    /// Account one request for `key`, return the time to wait if the request exceeds the limit.
+    pub fn hit(&self, key: &RateKey) -> Option<Duration> {
+        let now = Instant::now();
+        self.evict_idle(now);
+        let window = self.window();
+        let limit = self.limit(key);
+        let mut usage = self.usage.entry(key.clone()).or_insert_with(|| TokenUsage {
+            window: VecDeque::new(),
+            total_requests: 0,
+            throttled_requests: 0,
+            last_request_at: Utc::now(),
+            last_seen: now,
+        });
+        while usage.window.front().is_some_and(|t| now.duration_since(*t) >= window) {
+            usage.window.pop_front();
+        }
+        usage.total_requests += 1;
+        usage.last_request_at = Utc::now();
+        usage.last_seen = now;
+        if let Some(limit) = limit
+            && usage.window.len() as u64 >= limit
+        {
+            usage.throttled_requests += 1;
+            let retry_after = usage
+                .window
+                .front()
+                .map(|t| window.saturating_sub(now.duration_since(*t)))
+                .unwrap_or(window);
+            return Some(retry_after);
+        }
+        usage.window.push_back(now);
+        None
+    }
+
+    /// Drop the usage of the tokens idle for longer than `IDLE_EVICTION`, at most once a window.
+    pub fn evict_idle(&self, now: Instant) {
+        let idle = IDLE_EVICTION.max(self.window());
+        {
+            let mut last_eviction = self.last_eviction.lock();
+            if now.saturating_duration_since(*last_eviction) < self.window() {
+                return;
+            }
+            *last_eviction = now;
+        }
+        self.usage
+            .retain(|_, usage| now.saturating_duration_since(usage.last_seen) < idle);
+    }
+
+    pub fn usage(&self, key: &RateKey) -> UsageSnapshot {
+        let now = Instant::now();
+        let window = self.window();
+        let limit = self.limit(key);
+        let (window_requests, total_requests, throttled_requests, last_request_at) = self
+            .usage
+            .get(key)
+            .map(|u| {
+                let in_window = u.window.iter().filter(|t| now.duration_since(**t) < window).count() as u64;
+                (
+                    in_window,
+                    u.total_requests,
+                    u.throttled_requests,
+                    Some(u.last_request_at),
+                )
+            })
+            .unwrap_or_default();
+        UsageSnapshot {
+            window_secs: window.as_secs(),
+            window_requests,
+            limit,
+            remaining: limit.map(|l| l.saturating_sub(window_requests)),
+            total_requests,
+            throttled_requests,
+            last_request_at,
+        }
+    }
+}
+
+/// Validation failures of the writes per user and collection.
+///
+/// A client hammering a collection with invalid payloads has its writes to it refused for the rest
+/// of the window once it reaches the limit, its reads and other collections are not affected.
+/// Failures are only counted in the metrics when no `ValidationLimit` is configured.
+pub struct ValidationGuard {
+    config: Option<ValidationLimit>,
+    // dict<(user, namespace, collection), failures in the window>
+    failures: DashMap<(String, String, String), VecDeque<Instant>>,
+}
+
+impl ValidationGuard {
+    pub fn new(config: Option<ValidationLimit>) -> Self {
+        Self {
+            config,
+            failures: DashMap::new(),
+        }
+    }
+
+    /// The time to wait before the user may write to the collection again, none when it may now.
+    pub fn throttled(&self, key: &(String, String, String)) -> Option<Duration> {
+        let config = self.config.as_ref()?;
+        let now = Instant::now();
+        let mut failures = self.failures.get_mut(key)?;
+        while failures
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= config.window)
+        {
+            failures.pop_front();
+        }
+        if failures.is_empty() {
+            drop(failures);
+            self.failures.remove_if(key, |_, failures| failures.is_empty());
+            return None;
+        }
+        if (failures.len() as u64) < config.max_failures {
+            return None;
+        }
+        failures
+            .front()
+            .map(|t| config.window.saturating_sub(now.duration_since(*t)))
+    }
+
+    /// Account one failure, return true when it makes the user an offender of the collection.
+    pub fn fail(&self, key: (String, String, String)) -> bool {
+        let Some(config) = &self.config else {
+            return false;
+        };
+        let mut failures = self.failures.entry(key).or_default();
+        failures.push_back(Instant::now());
+        failures.len() as u64 == config.max_failures
+    }
+}
+
+/// Refuse the writes of users who failed validation too often on the collection and account the
+/// new failures, must be placed after `jwt_to_user` on routes with a `{collection}`.
+///
+/// Every write answered with 400 counts as a failure.
+#[handler]
+pub async fn validation_guard(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+    ctrl: &mut FlowCtrl,
+) -> ServiceResult<()> {
+    let (Some(namespace), Some(collection)) = (req.param::<String>("namespace"), req.param::<String>("collection"))
+    else {
+        return Ok(());
+    };
+    if req.method() == Method::GET {
+        return Ok(());
+    }
+    let guard = depot.obtain::<Arc<ValidationGuard>>()?.clone();
+    let metrics = depot.obtain::<Arc<RequestMetrics>>()?.clone();
+    let user_id = depot.get::<UserSchema>("user_schema")?.user_id.clone();
+    let key = (user_id, namespace, collection);
+    if let Some(retry_after) = guard.throttled(&key) {
+        metrics.record_validation_throttled(&key.2);
+        if let Ok(value) = HeaderValue::from_str(&retry_after.as_secs().max(1).to_string()) {
+            res.headers_mut().insert("Retry-After", value);
+        }
+        res.render(ServiceError::RateLimited(format!(
+            "too many invalid writes to {}, retry after {}s",
+            key.2,
+            retry_after.as_secs().max(1)
+        )));
+        ctrl.skip_rest();
+        return Ok(());
+    }
+    ctrl.call_next(req, depot, res).await;
+    if res.status_code == Some(StatusCode::BAD_REQUEST) {
+        metrics.record_validation_failure(&key.2);
+        let (user_id, namespace, collection) = &key;
+        if guard.fail(key.clone()) {
+            tracing::warn!(
+                "User {} flagged for repeated validation failures on {}/{}",
+                user_id,
+                namespace,
+                collection
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Enforce the per-token limit, must be placed after `jwt_to_user`.
+#[handler]
+pub async fn rate_limit(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+    ctrl: &mut FlowCtrl,
+) -> ServiceResult<()> {
+    let limiter = depot.obtain::<Arc<RateLimiter>>()?.clone();
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let key = match depot.get::<String>("service_key_id") {
+        Ok(id) => RateKey::ServiceKey(id.clone()),
+        Err(_) => RateKey::User(user.user_id.clone()),
+    };
+    if let Some(retry_after) = limiter.hit(&key) {
+        tracing::info!("Rate limited: {:?} exceeded request limit", key);
+        if let Ok(value) = HeaderValue::from_str(&retry_after.as_secs().max(1).to_string()) {
+            res.headers_mut().insert("Retry-After", value);
+        }
+        res.render(ServiceError::RateLimited(format!(
+            "too many requests, retry after {}s",
+            retry_after.as_secs().max(1)
+        )));
+        ctrl.skip_rest();
+        return Ok(());
+    }
+    ctrl.call_next(req, depot, res).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: &str) -> RateKey {
+        RateKey::User(id.to_string())
+    }
+
+    #[test]
+    fn test_limit_enforced_per_key() {
+        let limiter = RateLimiter::new(Some(RateLimit {
+            window: Duration::from_secs(60),
+            max_requests: 2,
+            service_key_max_requests: None,
+            service_keys: Default::default(),
+        }));
+        assert!(limiter.hit(&user("a")).is_none());
+        assert!(limiter.hit(&user("a")).is_none());
+        assert!(limiter.hit(&user("a")).is_some());
+        // other tokens are not affected
+        assert!(limiter.hit(&user("b")).is_none());
+
+        let usage = limiter.usage(&user("a"));
+        assert_eq!(usage.window_requests, 2);
+        assert_eq!(usage.total_requests, 3);
+        assert_eq!(usage.throttled_requests, 1);
+        assert_eq!(usage.remaining, Some(0));
+    }
+
+    #[test]
+    fn test_validation_failures_throttle_per_collection() {
+        let guard = ValidationGuard::new(Some(ValidationLimit {
+            window: Duration::from_secs(60),
+            max_failures: 2,
+        }));
+        let key = |collection: &str| ("u".to_string(), "ns".to_string(), collection.to_string());
+        assert!(guard.throttled(&key("post")).is_none());
+        assert!(!guard.fail(key("post")));
+        assert!(guard.throttled(&key("post")).is_none());
+        // flagged once, on the failure reaching the limit
+        assert!(guard.fail(key("post")));
+        assert!(!guard.fail(key("post")));
+        assert!(guard.throttled(&key("post")).is_some());
+        // other collections are not affected
+        assert!(guard.throttled(&key("comment")).is_none());
+
+        let unlimited = ValidationGuard::new(None);
+        assert!(!unlimited.fail(key("post")));
+        assert!(unlimited.throttled(&key("post")).is_none());
+    }
+
+    #[test]
+    fn test_usage_without_limit() {
+        let limiter = RateLimiter::new(None);
+        for _ in 0..10 {
+            assert!(limiter.hit(&user("a")).is_none());
+        }
+        let usage = limiter.usage(&user("a"));
+        assert_eq!(usage.window_requests, 10);
+        assert_eq!(usage.limit, None);
+    }
+
+    #[test]
+    fn test_service_keys_limited_on_their_own() {
+        let limiter = RateLimiter::new(Some(RateLimit {
+            window: Duration::from_secs(60),
+            max_requests: 1,
+            service_key_max_requests: Some(2),
+            service_keys: [("bulk".to_string(), 3)].into(),
+        }));
+        let key = |id: &str| RateKey::ServiceKey(id.to_string());
+        // a key of the user does not take from the user's budget
+        assert!(limiter.hit(&key("sync")).is_none());
+        assert!(limiter.hit(&user("a")).is_none());
+        assert!(limiter.hit(&user("a")).is_some());
+        assert!(limiter.hit(&key("sync")).is_none());
+        assert!(limiter.hit(&key("sync")).is_some());
+        assert_eq!(limiter.usage(&key("bulk")).limit, Some(3));
+    }
+
+    #[test]
+    fn test_idle_usage_evicted() {
+        let limiter = RateLimiter::new(None);
+        assert!(limiter.hit(&user("a")).is_none());
+        limiter.evict_idle(Instant::now() + IDLE_EVICTION / 2);
+        assert_eq!(limiter.usage(&user("a")).total_requests, 1);
+        limiter.evict_idle(Instant::now() + IDLE_EVICTION * 2);
+        assert_eq!(limiter.usage(&user("a")).total_requests, 0);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use salvo::{
+    Depot, FlowCtrl, Request, Response, Router, Scribe, handler,
+    oapi::{ToResponse, ToSchema, endpoint, extract::PathParam},
+    writing::Json,
+};
+use serde::Serialize;
+
+use crate::{
+    backend::tx::TxScope,
+    error::{ServiceError, ServiceResult},
+    router::{hpke_wrapper::HpkeResponse, metrics::TaggedRouter},
+    store::Store,
+    types::UserSchema,
+    utils::constant::{MAX_READ_SNAPSHOTS, READ_SNAPSHOT_TTL_SECS},
+};
+
+const SNAPSHOT_HEADER: &str = "X-Snapshot";
+
+/// Open and release read snapshots of a namespace
+pub fn create_router() -> Router {
+    Router::with_path("{namespace}")
+        .post(open_snapshot)
+        .push(Router::with_path("{token}").delete(release_snapshot))
+        .tagged("data")
+}
+
+/// The read snapshots held open, each one keeps a connection of its namespace in a read transaction.
+#[derive(Default)]
+pub struct ReadSnapshots {
+    // dict<token, snapshot>
+    held: DashMap<String, Arc<HeldSnapshot>>,
+}
+
+struct HeldSnapshot {
+    namespace: String,
+    user_id: String,
+    scope: Arc<TxScope>,
+    expires_at: DateTime<Utc>,
+    // one request at a time, a concurrent one would find the connection taken and read outside the scope
+    busy: tokio::sync::Mutex<()>,
+}
+
+impl ReadSnapshots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the snapshot of the token, while it is valid and only for the user who opened it
+    fn get(&self, token: &str, user_id: &str) -> ServiceResult<Arc<HeldSnapshot>> {
+        self.held
+            .get(token)
+            .map(|held| held.clone())
+            .filter(|held| held.expires_at > Utc::now() && held.user_id == user_id)
+            .ok_or_else(|| ServiceError::RequestError("unknown or expired snapshot".to_string()))
+    }
+
+    fn release(&self, token: &str) {
+        // the scope rolls back once the last request reading in it is done
+        self.held.remove(token);
+    }
+}
+
+/// Open a read snapshot of a namespace
+///
+/// Pass the returned token as the `X-Snapshot` header of the data reads, they all see the namespace
+/// as it was when the snapshot was opened while other clients keep writing. Writes carrying the
+/// header are refused. Snapshots are short lived, release them once the reads are done.
+#[endpoint(
+    status_codes(200, 403, 409),
+    responses(
+        (status_code = 200, description = "Snapshot opened", body = OpenSnapshotResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 409, description = "Too many snapshots of the namespace are open"),
+    )
+)]
+async fn open_snapshot(
+    namespace: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<OpenSnapshotResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let snapshots = depot.obtain::<Arc<ReadSnapshots>>()?.clone();
+    let namespace = namespace.into_inner();
+
+    let now = Utc::now();
+    snapshots.held.retain(|_, held| held.expires_at > now);
+    let open = snapshots.held.iter().filter(|held| held.namespace == namespace).count();
+    if open >= MAX_READ_SNAPSHOTS {
+        Err(ServiceError::QuotaExceeded(format!(
+            "at most {} snapshots of a namespace can be open",
+            MAX_READ_SNAPSHOTS
+        )))?;
+    }
+
+    let scope = TxScope::read_only();
+    let user_id = user.user_id.clone();
+    let seq = {
+        let namespace = namespace.clone();
+        scope
+            .enter(store.run(move |store| store.pin_read_snapshot(&namespace, &user_id)))
+            .await?
+    };
+    let ttl = Duration::from_secs(READ_SNAPSHOT_TTL_SECS);
+    let token = uuid::Uuid::new_v4().to_string();
+    let expires_at = now + chrono::Duration::seconds(READ_SNAPSHOT_TTL_SECS as i64);
+    snapshots.held.insert(
+        token.clone(),
+        Arc::new(HeldSnapshot {
+            namespace: namespace.clone(),
+            user_id: user.user_id.clone(),
+            scope,
+            expires_at,
+            busy: tokio::sync::Mutex::new(()),
+        }),
+    );
+    // nobody may come back for it, release the connection on time anyway
+    let expired = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(ttl).await;
+        snapshots.release(&expired);
+    });
+    Ok(HpkeResponse(OpenSnapshotResponse {
+        token,
+        namespace,
+        seq,
+        expires_at,
+    }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct OpenSnapshotResponse {
+    token: String,
+    namespace: String,
+    /// seq of the latest change the snapshot sees
+    seq: i64,
+    expires_at: DateTime<Utc>,
+}
+
+impl Scribe for OpenSnapshotResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Release a read snapshot before it expires
+#[endpoint(
+    status_codes(200, 400),
+    responses(
+        (status_code = 200, description = "Snapshot released"),
+        (status_code = 400, description = "Unknown or expired snapshot"),
+    )
+)]
+async fn release_snapshot(
+    namespace: PathParam<String>,
+    token: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let snapshots = depot.obtain::<Arc<ReadSnapshots>>()?;
+    let held = snapshots.get(&token, &user.user_id)?;
+    if held.namespace != *namespace {
+        Err(ServiceError::RequestError("unknown or expired snapshot".to_string()))?;
+    }
+    snapshots.release(&token);
+    Ok(())
+}
+
+/// Run the rest of the chain in the read snapshot named by the `X-Snapshot` header, if any.
+#[handler]
+pub async fn read_snapshot(req: &mut Request, res: &mut Response, depot: &mut Depot, ctrl: &mut FlowCtrl) {
+    let Some(token) = req
+        .headers()
+        .get(SNAPSHOT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    else {
+        return;
+    };
+    let held = match snapshot_of(&token, req, depot) {
+        Ok(held) => held,
+        Err(e) => {
+            res.render(e);
+            ctrl.skip_rest();
+            return;
+        }
+    };
+    let _busy = held.busy.lock().await;
+    held.scope.enter(ctrl.call_next(req, depot, res)).await;
+}
+
+fn snapshot_of(token: &str, req: &Request, depot: &Depot) -> ServiceResult<Arc<HeldSnapshot>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let held = depot.obtain::<Arc<ReadSnapshots>>()?.get(token, &user.user_id)?;
+    // the snapshot only pinned its own namespace
+    if req.param::<String>("namespace").as_deref() != Some(held.namespace.as_str()) {
+        return Err(ServiceError::RequestError(format!(
+            "the snapshot only reads namespace {}",
+            held.namespace
+        )));
+    }
+    Ok(held)
+}
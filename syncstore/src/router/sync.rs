@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use salvo::{
+    Depot, Router,
+    oapi::{
+        ToResponse, ToSchema, endpoint,
+        extract::{PathParam, QueryParam},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::write_queue::WriteTier,
+    config::PageSize,
+    error::ServiceResult,
+    router::{
+        hpke_wrapper::{HpkeRequest, HpkeResponse},
+        metrics::TaggedRouter,
+    },
+    store::Store,
+    types::{ChangeFeed, Mutation, PulledChanges, PushResult, UserSchema},
+};
+
+/// Sync of a namespace: the change feed, delta pulls, and pushes of the writes made offline
+pub fn create_router() -> Router {
+    Router::new()
+        .push(Router::with_path("{namespace}/changes").get(list_changes))
+        .push(Router::with_path("{namespace}/pull").get(pull_changes))
+        .push(Router::with_path("{namespace}/push").post(push_changes))
+        .tagged("sync")
+}
+
+/// List the changes of the namespace after the cursor, oldest first
+///
+/// Only the changes of data the user can read are listed. Pass the returned cursor back to get the
+/// next page, a page can be empty while `has_more` is set. With `parent_id` only the changes of the
+/// documents directly under that parent are listed.
+#[endpoint(
+    status_codes(200, 400, 403, 404),
+    responses(
+        (status_code = 200, description = "List changes successfully", body = ChangeFeed),
+        (status_code = 400, description = "Invalid cursor"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Namespace not found"),
+    )
+)]
+async fn list_changes(
+    namespace: PathParam<String>,
+    cursor: QueryParam<String, false>,
+    parent_id: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ChangeFeed>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let limit = depot.obtain::<PageSize>()?.resolve(limit.into_inner());
+    let user_id = user.user_id.clone();
+    let feed = store
+        .run(move |store| match parent_id.into_inner() {
+            Some(parent_id) => store.changes_under(&namespace, &parent_id, cursor.into_inner(), limit, &user_id),
+            None => store.changes(&namespace, cursor.into_inner(), limit, &user_id),
+        })
+        .await?;
+    Ok(HpkeResponse(feed))
+}
+
+/// Pull the documents changed after the cursor, as they are now
+///
+/// A document changed several times is returned once, deleted ones are listed by id. Only the
+/// documents the user can read are pulled. The cursor is opaque, pass the returned one back to get
+/// the next page; a page can be empty while `has_more` is set.
+#[endpoint(
+    status_codes(200, 400, 403, 404),
+    responses(
+        (status_code = 200, description = "Pull changes successfully", body = PulledChanges),
+        (status_code = 400, description = "Invalid cursor"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Namespace not found"),
+    )
+)]
+async fn pull_changes(
+    namespace: PathParam<String>,
+    cursor: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<PulledChanges>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let limit = depot.obtain::<PageSize>()?.resolve(limit.into_inner());
+    let user_id = user.user_id.clone();
+    let pulled = store
+        .run(move |store| store.pull_changes(&namespace, &user_id, cursor.as_deref(), limit))
+        .await?;
+    Ok(HpkeResponse(pulled))
+}
+
+/// Push the writes a client made offline
+///
+/// The creates, updates and deletes are applied in one transaction, each with the permission checks
+/// of the single write. Every mutation is answered in order: accepted with its new revision, a
+/// conflict when the document moved past its `base_rev` or the id is taken, or rejected. A conflict
+/// returns the server version along the pushed body, the client merges them and pushes again.
+#[endpoint(
+    status_codes(200, 400, 403, 404),
+    request_body(content = PushRequest, description = "Mutations to apply"),
+    responses(
+        (status_code = 200, description = "Mutations applied", body = PushResponse),
+        (status_code = 400, description = "Too many mutations"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Namespace not found"),
+    )
+)]
+async fn push_changes(
+    namespace: PathParam<String>,
+    req: HpkeRequest<PushRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<PushResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let mutations = req.0.mutations;
+    let results = store
+        .run_write(WriteTier::Batch, move |store| {
+            store.push_changes(&namespace, &mutations, &user_id)
+        })
+        .await?;
+    Ok(HpkeResponse(PushResponse { results }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PushRequest {
+    mutations: Vec<Mutation>,
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct PushResponse {
+    /// one per mutation, in order
+    results: Vec<PushResult>,
+}
@@ -0,0 +1,35 @@
+use std::time::Instant;
+
+use salvo::{Depot, FlowCtrl, Request, Response, handler};
+
+use crate::{backend::deadline, config::RequestTimeout, error::ServiceError};
+
+/// Cut off the rest of the chain once the route's timeout passes, answering 504.
+///
+/// The handler future is dropped at its next await, and the database statement it runs is interrupted,
+/// see [`deadline`]. A handler returning after the deadline gets its answer replaced as well, the
+/// request transaction of the route is rolled back then.
+#[handler]
+pub async fn request_timeout(req: &mut Request, res: &mut Response, depot: &mut Depot, ctrl: &mut FlowCtrl) {
+    let timeout = match depot.obtain::<RequestTimeout>() {
+        Ok(config) => config.timeout_for(req.uri().path()),
+        Err(_) => None,
+    };
+    let Some(timeout) = timeout else {
+        ctrl.call_next(req, depot, res).await;
+        return;
+    };
+    let deadline = Instant::now() + timeout;
+    let finished = tokio::time::timeout_at(
+        deadline.into(),
+        deadline::enter(deadline, ctrl.call_next(req, depot, res)),
+    )
+    .await
+    .is_ok();
+    if !finished || Instant::now() >= deadline {
+        tracing::warn!("request {} timed out after {:?}", req.uri().path(), timeout);
+        ctrl.skip_rest();
+        res.take_body();
+        res.render(ServiceError::Timeout(format!("request exceeded {:?}", timeout)));
+    }
+}
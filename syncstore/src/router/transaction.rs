@@ -0,0 +1,38 @@
+use salvo::{Depot, FlowCtrl, Request, Response, handler};
+
+use crate::{
+    backend::{deadline, tx::TxScope},
+    error::ServiceError,
+};
+
+/// Run the rest of the chain in one request transaction.
+///
+/// The `Store` calls of the handler join the `TxScope` injected in the depot, it is committed when
+/// the handler answered with a success status and rolled back otherwise.
+///
+/// Inside a scope entered further out, e.g. a read snapshot, the handler runs in that one.
+#[handler]
+pub async fn transaction(req: &mut Request, res: &mut Response, depot: &mut Depot, ctrl: &mut FlowCtrl) {
+    if TxScope::current().is_some() {
+        ctrl.call_next(req, depot, res).await;
+        return;
+    }
+    let scope = TxScope::new();
+    depot.inject(scope.clone());
+    scope.enter(ctrl.call_next(req, depot, res)).await;
+
+    let succeeded = res
+        .status_code
+        .is_none_or(|status| status.is_success() || status.is_redirection());
+    // past the request timeout the answer becomes a 504, keep nothing of it
+    if !succeeded || deadline::passed() {
+        scope.rollback();
+        return;
+    }
+    if let Err(e) = scope.commit() {
+        tracing::warn!("failed to commit request transaction: {}", e);
+        // the handler's answer no longer holds
+        res.take_body();
+        res.render(ServiceError::from(e));
+    }
+}
@@ -1,27 +1,51 @@
 use std::sync::Arc;
 
 use base64::Engine;
+use chrono::{DateTime, Utc};
+use dashmap::{DashMap, mapref::entry::Entry};
 use salvo::{
-    Depot, Router, Writer,
+    Depot, Response, Router, Writer,
+    http::StatusCode,
     oapi::{
-        RouterExt, ToResponse, ToSchema, endpoint,
-        extract::{JsonBody, PathParam},
+        ToResponse, ToSchema, endpoint,
+        extract::{JsonBody, PathParam, QueryParam},
     },
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::{ServiceError, ServiceResult},
-    router::hpke_wrapper::{HpkeRequest, HpkeResponse},
+    config::PageSize,
+    error::{ServiceError, ServiceResult, StoreError},
+    router::{
+        data::PageInfo,
+        export::write_snapshot,
+        hpke_wrapper::{HpkeRequest, HpkeResponse},
+        metrics::TaggedRouter,
+        rate_limit::{RateKey, RateLimiter, UsageSnapshot},
+    },
     store::Store,
-    types::UserSchema,
+    types::{Snapshot, UserSchema},
 };
 
 pub fn create_router() -> Router {
     Router::new()
-        .push(Router::with_path("profile").push(Router::with_path("{id}").get(get_user).post(update_user)))
+        .push(
+            Router::with_path("profile").push(
+                Router::with_path("{id}")
+                    .get(get_user)
+                    .post(update_user)
+                    .delete(deactivate_user),
+            ),
+        )
         .push(Router::with_path("friends").get(list_friends).post(add_friend))
-        .oapi_tag("user")
+        .push(Router::with_path("{id}/usage").get(get_usage))
+        .push(
+            Router::with_path("{id}/export")
+                .get(account_export_status)
+                .post(start_account_export)
+                .push(Router::with_path("download").get(download_account_export)),
+        )
+        .tagged("user")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema, ToResponse)]
@@ -59,8 +83,11 @@ impl UserProfile {
 )]
 async fn get_user(id: PathParam<String>, depot: &mut Depot) -> ServiceResult<UserProfile> {
     let store = depot.obtain::<Arc<Store>>()?;
-    let user_schema = store.get_user(&id)?;
-    let user = UserProfile::from_user_schema(id.to_string(), &user_schema);
+    let user_id = id.into_inner();
+    let (user_id, user_schema) = store
+        .run(move |store| store.get_user(&user_id).map(|schema| (user_id, schema)))
+        .await?;
+    let user = UserProfile::from_user_schema(user_id, &user_schema);
     Ok(user)
 }
 
@@ -85,19 +112,26 @@ async fn update_user(
             "Cannot update other user's profile".to_string(),
         ));
     }
-    let user_schema = store.get_user(&user.user_id)?;
-    let mut updated_schema = user_schema.clone();
-    if let Some(name) = &req.0.name {
-        updated_schema.username = name.clone();
-    }
-    if let Some(password) = &req.0.password {
-        updated_schema.password = password.clone();
-    }
-    if let Some(avatar_url) = &req.0.avatar_url {
-        updated_schema.avatar_url = Some(avatar_url.clone());
-    }
-    store.update_user(&user.user_id, &updated_schema)?;
-    let updated_user = store.get_user(&user.user_id)?;
+    let user_id = user.user_id.clone();
+    let updated_user = store
+        .run(move |store| {
+            let mut updated_schema = store.get_user(&user_id)?;
+            if let Some(name) = &req.0.name {
+                updated_schema.username = name.clone();
+            }
+            if let Some(password) = &req.0.password {
+                updated_schema.password = password.clone();
+            }
+            if let Some(avatar_url) = &req.0.avatar_url {
+                updated_schema.avatar_url = Some(avatar_url.clone());
+            }
+            if let Some(email) = &req.0.email {
+                updated_schema.email = Some(email.clone());
+            }
+            store.update_user(&user_id, &updated_schema)?;
+            store.get_user(&user_id)
+        })
+        .await?;
     let updated_user = UserProfile::from_user_schema(user.user_id.clone(), &updated_user);
     Ok(HpkeResponse(updated_user))
 }
@@ -107,6 +141,32 @@ pub struct UpdateUserProfile {
     pub name: Option<String>,
     pub password: Option<String>,
     pub avatar_url: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Deactivate the own account
+///
+/// Login is blocked and the data hidden at once, everything is purged after the grace period unless
+/// an admin restores the account before.
+#[endpoint(
+    status_codes(200, 403, 409),
+    responses(
+        (status_code = 200, description = "Deactivate account successfully"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 409, description = "Account already deactivated"),
+    )
+)]
+async fn deactivate_user(id: PathParam<String>, depot: &mut Depot) -> ServiceResult<()> {
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user = depot.get::<UserSchema>("user_schema")?;
+    if user.user_id != *id {
+        return Err(ServiceError::Forbidden(
+            "Cannot deactivate other user's account".to_string(),
+        ));
+    }
+    let user_id = user.user_id.clone();
+    store.run(move |store| store.deactivate_user(&user_id)).await?;
+    Ok(())
 }
 
 /// List friends of the user
@@ -117,20 +177,32 @@ pub struct UpdateUserProfile {
         (status_code = 403, description = "FORBIDDEN"),
     )
 )]
-async fn list_friends(depot: &mut Depot) -> ServiceResult<HpkeResponse<ListFriendsResponse>> {
+async fn list_friends(
+    marker: QueryParam<String, false>,
+    limit: QueryParam<usize, false>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListFriendsResponse>> {
     let store = depot.obtain::<Arc<Store>>()?;
     let user = depot.get::<UserSchema>("user_schema")?;
-    let friend_schemas = store.list_friends(&user.user_id)?;
-    let friends = friend_schemas
+    let limit = depot.obtain::<PageSize>()?.resolve(limit.into_inner());
+    let user_id = user.user_id.clone();
+    let (friend_schemas, next_marker) = store
+        .run(move |store| store.list_friends(&user_id, marker.into_inner(), limit))
+        .await?;
+    let friends: Vec<UserProfile> = friend_schemas
         .into_iter()
         .map(|(user_id, friend_schema)| UserProfile::from_user_schema(user_id, &friend_schema))
         .collect();
-    Ok(HpkeResponse(ListFriendsResponse { friends }))
+    Ok(HpkeResponse(ListFriendsResponse {
+        page_info: PageInfo::forward(friends.len(), next_marker),
+        friends,
+    }))
 }
 
 #[derive(Serialize, ToSchema, ToResponse)]
 struct ListFriendsResponse {
     friends: Vec<UserProfile>,
+    page_info: PageInfo,
 }
 
 impl salvo::Scribe for ListFriendsResponse {
@@ -151,7 +223,10 @@ impl salvo::Scribe for ListFriendsResponse {
 async fn add_friend(req: JsonBody<AddFriendRequest>, depot: &mut Depot) -> ServiceResult<()> {
     let store = depot.obtain::<Arc<Store>>()?;
     let user = depot.get::<UserSchema>("user_schema")?;
-    store.add_friend(&user.user_id, &req.0.friend_id)?;
+    let user_id = user.user_id.clone();
+    store
+        .run(move |store| store.add_friend(&user_id, &req.0.friend_id))
+        .await?;
     Ok(())
 }
 
@@ -159,3 +234,213 @@ async fn add_friend(req: JsonBody<AddFriendRequest>, depot: &mut Depot) -> Servi
 struct AddFriendRequest {
     friend_id: String,
 }
+
+/// Get request usage counters of the user
+#[endpoint(
+    status_codes(200, 403),
+    responses(
+        (status_code = 200, description = "Get usage successfully", body = UsageResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+    )
+)]
+async fn get_usage(id: PathParam<String>, depot: &mut Depot) -> ServiceResult<HpkeResponse<UsageResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    if user.user_id != *id {
+        return Err(ServiceError::Forbidden("Cannot view other user's usage".to_string()));
+    }
+    let limiter = depot.obtain::<Arc<RateLimiter>>()?;
+    Ok(HpkeResponse(UsageResponse {
+        user_id: user.user_id.clone(),
+        usage: limiter.usage(&RateKey::User(user.user_id.clone())),
+    }))
+}
+
+#[derive(Serialize, ToSchema, ToResponse)]
+struct UsageResponse {
+    user_id: String,
+    usage: UsageSnapshot,
+}
+
+impl salvo::Scribe for UsageResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Account export of each user, the latest one is kept until the user starts another.
+pub type AccountExports = DashMap<String, AccountExport>;
+
+pub struct AccountExport {
+    started_at: DateTime<Utc>,
+    state: ExportState,
+}
+
+enum ExportState {
+    Running,
+    Ready(Snapshot),
+    Failed(String),
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum ExportStatus {
+    Running,
+    Ready,
+    Failed,
+}
+
+#[derive(Serialize, ToSchema, ToResponse)]
+struct AccountExportResponse {
+    user_id: String,
+    status: ExportStatus,
+    started_at: DateTime<Utc>,
+    /// archive size in bytes once ready
+    size: Option<usize>,
+    /// hex sha256 of the archive once ready
+    sha256: Option<String>,
+    error: Option<String>,
+}
+
+impl AccountExportResponse {
+    fn new(user_id: &str, export: &AccountExport) -> Self {
+        let (status, size, sha256, error) = match &export.state {
+            ExportState::Running => (ExportStatus::Running, None, None, None),
+            ExportState::Ready(snapshot) => (
+                ExportStatus::Ready,
+                Some(snapshot.data.len()),
+                Some(snapshot.sha256.clone()),
+                None,
+            ),
+            ExportState::Failed(e) => (ExportStatus::Failed, None, None, Some(e.clone())),
+        };
+        AccountExportResponse {
+            user_id: user_id.to_string(),
+            status,
+            started_at: export.started_at,
+            size,
+            sha256,
+            error,
+        }
+    }
+}
+
+impl salvo::Scribe for AccountExportResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Start exporting everything the user owns into a downloadable archive
+///
+/// The archive is assembled in the background, poll the export status until it is `ready` and then
+/// download it. Starting while an export is running returns that export.
+#[endpoint(
+    status_codes(202, 403),
+    responses(
+        (status_code = 202, description = "Export started", body = AccountExportResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+    )
+)]
+async fn start_account_export(
+    id: PathParam<String>,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> ServiceResult<HpkeResponse<AccountExportResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    if user.user_id != *id {
+        return Err(ServiceError::Forbidden(
+            "Cannot export other user's account".to_string(),
+        ));
+    }
+    let store = depot.obtain::<Arc<Store>>()?.clone();
+    let exports = depot.obtain::<Arc<AccountExports>>()?.clone();
+    let user_id = user.user_id.clone();
+    res.status_code(StatusCode::ACCEPTED);
+    let response = match exports.entry(user_id.clone()) {
+        Entry::Occupied(export) if matches!(export.get().state, ExportState::Running) => {
+            return Ok(HpkeResponse(AccountExportResponse::new(&user_id, export.get())));
+        }
+        entry => {
+            let export = entry.insert(AccountExport {
+                started_at: Utc::now(),
+                state: ExportState::Running,
+            });
+            AccountExportResponse::new(&user_id, &export)
+        }
+    };
+    let (task_exports, task_user) = (exports.clone(), user_id.clone());
+    tokio::task::spawn_blocking(move || {
+        let state = match store.export_account(&task_user) {
+            Ok(snapshot) => {
+                tracing::info!("exported account {}, {} bytes", task_user, snapshot.data.len());
+                ExportState::Ready(snapshot)
+            }
+            Err(e) => {
+                tracing::warn!("failed to export account {}: {}", task_user, e);
+                ExportState::Failed(e.to_string())
+            }
+        };
+        if let Some(mut export) = task_exports.get_mut(&task_user) {
+            export.state = state;
+        }
+    });
+    Ok(HpkeResponse(response))
+}
+
+/// Get the status of the user's latest account export
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "Get export status successfully", body = AccountExportResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "No export started"),
+    )
+)]
+async fn account_export_status(
+    id: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<AccountExportResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    if user.user_id != *id {
+        return Err(ServiceError::Forbidden("Cannot view other user's export".to_string()));
+    }
+    let exports = depot.obtain::<Arc<AccountExports>>()?;
+    let export = exports
+        .get(&user.user_id)
+        .ok_or_else(|| StoreError::NotFound("account export".to_string()))?;
+    Ok(HpkeResponse(AccountExportResponse::new(&user.user_id, &export)))
+}
+
+/// Download the archive of the user's latest account export, gzip JSONL
+#[endpoint(
+    status_codes(200, 403, 404, 409),
+    responses(
+        (status_code = 200, description = "Gzip JSONL archive, sha256 in the X-Checksum-Sha256 header"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "No export started"),
+        (status_code = 409, description = "Export still running or failed"),
+    )
+)]
+async fn download_account_export(id: PathParam<String>, depot: &mut Depot, res: &mut Response) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    if user.user_id != *id {
+        return Err(ServiceError::Forbidden(
+            "Cannot download other user's export".to_string(),
+        ));
+    }
+    let exports = depot.obtain::<Arc<AccountExports>>()?;
+    let snapshot = match exports.get(&user.user_id).map(|export| match &export.state {
+        ExportState::Ready(snapshot) => Ok(snapshot.clone()),
+        ExportState::Running => Err(ServiceError::Conflict("account export still running".to_string())),
+        ExportState::Failed(e) => Err(ServiceError::Conflict(format!("account export failed: {}", e))),
+    }) {
+        Some(snapshot) => snapshot?,
+        None => Err(StoreError::NotFound("account export".to_string()))?,
+    };
+    write_snapshot(
+        res,
+        snapshot,
+        &format!("account-{}.jsonl.gz", user.user_id),
+        "application/gzip",
+    )
+}
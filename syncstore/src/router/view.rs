@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use salvo::{
+    Depot, Router, Scribe, Writer,
+    oapi::{ToResponse, ToSchema, endpoint, extract::PathParam},
+    writing::Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{
+        filter::{FilterExpr, SortSpec},
+        write_queue::WriteTier,
+    },
+    error::ServiceResult,
+    router::{
+        hpke_wrapper::{HpkeRequest, HpkeResponse},
+        metrics::TaggedRouter,
+    },
+    store::Store,
+    types::{SavedView, UserSchema},
+};
+
+/// Saved views, run them with `GET /data/{namespace}/{collection}?view={name}`
+pub fn create_router() -> Router {
+    Router::with_path("{namespace}/{collection}/views")
+        .get(list_views)
+        .post(save_view)
+        .push(Router::with_path("{name}").get(get_view).delete(delete_view))
+        .tagged("data")
+}
+
+/// List the current user's saved views of the collection
+#[endpoint(
+    status_codes(200, 403),
+    responses(
+        (status_code = 200, description = "List views successfully", body = ListViewsResponse),
+        (status_code = 403, description = "FORBIDDEN"),
+    )
+)]
+async fn list_views(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<ListViewsResponse>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let views = store
+        .run(move |store| store.list_views(&namespace, &collection, &user_id))
+        .await?;
+    Ok(HpkeResponse(ListViewsResponse { views }))
+}
+
+#[derive(Serialize, ToResponse, ToSchema)]
+struct ListViewsResponse {
+    views: Vec<SavedView>,
+}
+
+impl Scribe for ListViewsResponse {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(Json(self));
+    }
+}
+
+/// Save a named filter and sort, replacing the view of the same name
+///
+/// The filter takes the expressions of the query endpoint, the sort a meta column or a (dotted) body field.
+#[endpoint(
+    status_codes(200, 400, 403, 404, 409),
+    request_body(content = SaveViewRequest, description = "View to save"),
+    responses(
+        (status_code = 200, description = "Save view successfully", body = SavedView),
+        (status_code = 400, description = "Bad Request"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "Collection not found"),
+        (status_code = 409, description = "Too many views of the collection"),
+    )
+)]
+async fn save_view(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    req: HpkeRequest<SaveViewRequest>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<SavedView>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let req = req.0;
+    let view = store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.save_view(&namespace, &collection, &req.name, req.filter, req.sort, &user_id)
+        })
+        .await?;
+    Ok(HpkeResponse(view))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SaveViewRequest {
+    name: String,
+    #[salvo(schema(value_type = Option<Object>))]
+    filter: Option<FilterExpr>,
+    sort: Option<SortSpec>,
+}
+
+/// Get a saved view by name
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "Get view successfully", body = SavedView),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "View not found"),
+    )
+)]
+async fn get_view(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    name: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<HpkeResponse<SavedView>> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    let view = store
+        .run(move |store| store.get_view(&namespace, &collection, &name, &user_id))
+        .await?;
+    Ok(HpkeResponse(view))
+}
+
+/// Delete a saved view
+#[endpoint(
+    status_codes(200, 403, 404),
+    responses(
+        (status_code = 200, description = "Delete view successfully"),
+        (status_code = 403, description = "FORBIDDEN"),
+        (status_code = 404, description = "View not found"),
+    )
+)]
+async fn delete_view(
+    namespace: PathParam<String>,
+    collection: PathParam<String>,
+    name: PathParam<String>,
+    depot: &mut Depot,
+) -> ServiceResult<()> {
+    let user = depot.get::<UserSchema>("user_schema")?;
+    let store = depot.obtain::<Arc<Store>>()?;
+    let user_id = user.user_id.clone();
+    store
+        .run_write(WriteTier::Interactive, move |store| {
+            store.delete_view(&namespace, &collection, &name, &user_id)
+        })
+        .await?;
+    Ok(())
+}
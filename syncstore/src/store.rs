@@ -1,32 +1,131 @@
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
-    sync::Arc,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
+use rand::Rng;
 use serde_json::Value;
 
-use crate::backend::{Backend, SqliteBackend};
-use crate::components::{DataManager, DataManagerBuilder, DataSchemas, UserManager};
+use crate::backend::blob::{BlobOffload, hex_sha256};
+use crate::backend::encrypt::FieldCipher;
+use crate::backend::filter::{FilterExpr, SortSpec};
+use crate::backend::patch::{self, PatchOp};
+use crate::backend::sqlite::{QueryScope, check_document_id};
+use crate::backend::tx::TxScope;
+use crate::backend::write_queue::{WriteQueue, WriteTier};
+use crate::backend::{Backend, SqliteBackend, blocking, body_field};
+use crate::components::{
+    DataManager, DataManagerBuilder, DataSchemas, DeliveryChannel, DeliveryMessage, NotificationManager,
+    OutboxConsumer, ReplicationConsumer, ReplicationTarget, Scheduler, UserManager, WebhookRequest, WebhookSender,
+};
+use crate::config::{DEFAULT_INTERACTIVE_BURST, OrphanCleanup, Replication, Webhooks, WriteQueueConfig};
 use crate::error::{StoreError, StoreResult};
-use crate::types::{ACLMask, AccessControl, DataItem, Id, Permission, PermissionSchema, UserSchema};
+use crate::types::{
+    ACLMask, AccessControl, AccessStats, AclAction, AclDirection, AclEntry, Attachment, AttachmentContent, BackupInfo,
+    BatchFailure, BatchOutcome, Change, ChangeFeed, ChangeMeta, ChangeOp, CollectionMode, CollectionStats,
+    CollectionSwitches, DataItem, DeactivatedUser, DeletedItem, GeneratedData, Id, ImportMode, Imported, ItemLock,
+    ListOrder, MaintenanceMode, Mutation, NamespaceHealth, NamespacePolicy, NamespaceStartup, NamespaceUsage,
+    Notification, NotificationMessage, Orphan, OrphanPolicy, OrphanReport, Page, PageCursor, Passkey, Permission,
+    PermissionExplanation, PermissionSchema, PermissionStep, PulledChanges, PushResult, ReplicationAck,
+    ReplicationBatch, ReplicationStatus, SavedView, ServiceAccess, ServiceKey, Snapshot, SortKey, SortMarker,
+    StartupReport, SyncCursor, UserSchema, VacuumReport, ValidationReport, Webhook, WebhookDelivery, WebhookEvent,
+    WebhookEventKind,
+};
+use crate::utils::constant::{
+    MAX_ATTACHMENT_SIZE, MAX_BULK_INSERT_ITEMS, NOTIFICATIONS_NAMESPACE, ROOT_OWNER, USERS_NAMESPACE,
+};
+use crate::utils::fake::FakeGenerator;
+use crate::utils::passkey::{self, NewCredential};
+
+const MAX_CHANGE_REASON_CHARS: usize = 500;
+
+/// Most documents a full text search answers with.
+const MAX_SEARCH_RESULTS: usize = 50;
+
+/// Attempts at generating a fake document the schema accepts.
+const MAX_FAKE_ATTEMPTS: usize = 5;
+
+/// Views a user keeps per collection.
+const MAX_VIEWS_PER_COLLECTION: usize = 100;
+const MAX_VIEW_NAME_CHARS: usize = 64;
+
+/// Passkeys a user keeps registered.
+const MAX_PASSKEYS_PER_USER: usize = 20;
+const MAX_PASSKEY_NAME_CHARS: usize = 64;
+
+const MAX_SERVICE_KEY_NAME_CHARS: usize = 64;
+
+/// Upper bound of an advisory lock, a forgotten lock never blocks a document longer than this.
+const MAX_LOCK_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The users and notifications databases, written beside the namespaces.
+const INNER_DATABASES: usize = 2;
 
 pub struct Store {
     data_manager: Arc<DataManager>,
     user_manager: Arc<UserManager>,
+    notification_manager: Arc<NotificationManager>,
+    // dict<namespace, policy>, namespaces without an entry are open
+    namespace_policies: RwLock<HashMap<String, NamespacePolicy>>,
+    scheduler: Scheduler,
+    // outbound channel for notifications, none when not configured
+    delivery: RwLock<Option<Arc<dyn DeliveryChannel>>>,
+    // secondary receiving our change log, none when not configured
+    replication_target: RwLock<Option<Arc<dyn ReplicationTarget>>>,
+    maintenance: RwLock<MaintenanceMode>,
+    // dict<(namespace, collection), switches>, runtime only
+    collection_switches: RwLock<HashMap<(String, String), CollectionSwitches>>,
+    // accounts waiting for their purge, mirrors `deactivated_at` of the users database
+    deactivated_users: RwLock<HashSet<String>>,
+    write_queue: WriteQueue,
+}
+
+/// Where the databases live when they are not all under the base directory.
+#[derive(Debug, Clone, Default)]
+pub struct StoreLayout {
+    /// dict<namespace, database file>, other namespaces use `<base_dir>/<namespace>.db`
+    pub namespace_paths: HashMap<String, PathBuf>,
+    /// directory of the users and notifications databases, defaults to `<base_dir>/inner`
+    pub inner_dir: Option<PathBuf>,
+    /// namespaces whose existing database is served read-only
+    pub read_only_namespaces: HashSet<String>,
+    /// dict<namespace, SQLCipher key> of the namespaces encrypted at rest, needs the `sqlcipher` feature
+    pub encryption_keys: HashMap<String, String>,
 }
 
 impl Store {
     pub fn build(base_dir: impl AsRef<std::path::Path>, dbs: Vec<(&str, DataSchemas)>) -> StoreResult<Arc<Self>> {
+        Self::build_with_layout(base_dir, dbs, &StoreLayout::default())
+    }
+
+    pub fn build_with_layout(
+        base_dir: impl AsRef<std::path::Path>,
+        dbs: Vec<(&str, DataSchemas)>,
+        layout: &StoreLayout,
+    ) -> StoreResult<Arc<Self>> {
         let path = base_dir.as_ref().to_path_buf();
-        let inner_path = path.join("inner");
+        let inner_path = layout.inner_dir.clone().unwrap_or_else(|| path.join("inner"));
         std::fs::create_dir_all(&inner_path)?;
 
         let mut data_manager = DataManagerBuilder::new(&path);
+        for (namespace, key) in &layout.encryption_keys {
+            data_manager = data_manager.with_encryption_key(namespace, key);
+        }
         for (db_name, schemas) in dbs {
-            match db_name {
-                "memory" => {
+            let read_only = layout.read_only_namespaces.contains(db_name);
+            match (db_name, layout.namespace_paths.get(db_name)) {
+                ("memory", _) => {
                     data_manager = data_manager.add_memory_db(schemas)?;
                 }
+                (_, db_path) if read_only => {
+                    let db_path = db_path.cloned().unwrap_or_else(|| path.join(format!("{}.db", db_name)));
+                    data_manager = data_manager.add_readonly_db(db_name, db_path, schemas)?;
+                }
+                (_, Some(db_path)) => {
+                    data_manager = data_manager.add_db_at(db_name, db_path, schemas)?;
+                }
                 _ => {
                     data_manager = data_manager.add_db(db_name, schemas)?;
                 }
@@ -34,12 +133,424 @@ impl Store {
         }
         let data_manager = Arc::new(data_manager.build());
         let user_manager = Arc::new(UserManager::new(&inner_path)?);
+        let notification_manager = Arc::new(NotificationManager::new(&inner_path)?);
+        let deactivated_users = user_manager
+            .list_deactivated()?
+            .into_iter()
+            .map(|user| user.user_id)
+            .collect();
 
         Ok(Arc::new(Self {
             data_manager,
             user_manager,
+            notification_manager,
+            namespace_policies: RwLock::new(HashMap::new()),
+            scheduler: Scheduler::new(),
+            delivery: RwLock::new(None),
+            replication_target: RwLock::new(None),
+            maintenance: RwLock::new(MaintenanceMode::default()),
+            collection_switches: RwLock::new(HashMap::new()),
+            deactivated_users: RwLock::new(deactivated_users),
+            write_queue: WriteQueue::new(data_manager.writers() + INNER_DATABASES, DEFAULT_INTERACTIVE_BURST)?,
         }))
     }
+
+    /// Run store operations from async code, on the blocking pool so they don't stall the runtime workers.
+    ///
+    /// See [`blocking::spawn`] for the request transaction and deadline carried over.
+    pub async fn run<R, F>(self: &Arc<Self>, f: F) -> StoreResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Store) -> StoreResult<R> + Send + 'static,
+    {
+        let store = self.clone();
+        blocking::spawn(move || f(&store)).await
+    }
+
+    /// Like `run` for writes, admitted through the write queue in `tier` first so the bulk traffic
+    /// does not hold up the interactive writes, see [`WriteQueue`].
+    pub async fn run_write<R, F>(self: &Arc<Self>, tier: WriteTier, f: F) -> StoreResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Store) -> StoreResult<R> + Send + 'static,
+    {
+        let _permit = self.write_queue.acquire(tier).await?;
+        self.run(f).await
+    }
+}
+
+/// Startup phase, run once before the listeners bind
+impl Store {
+    /// Check every database and bring the derived columns of older documents up to date, failing
+    /// with a report of everything found rather than letting the first requests run into it.
+    ///
+    /// Validators are compiled and writable databases migrated while the store is built, a writable
+    /// database still missing tables or schemas afterwards is reported. Read-only databases can't
+    /// be migrated, their pending migrations are warnings.
+    pub fn startup(&self) -> StoreResult<StartupReport> {
+        let mut report = StartupReport::default();
+        for namespace in self.replication_namespaces() {
+            let backend = self.change_log_backend(&namespace)?;
+            let health = backend.health(&namespace);
+            let mut startup = NamespaceStartup {
+                namespace: namespace.clone(),
+                collections: backend.collections().len(),
+                backfilled: 0,
+                warnings: backend.schema_changes().to_vec(),
+                problems: backend.check_schemas(),
+            };
+            // parents in another namespace, the database itself can not check those
+            for collection in backend.collections() {
+                if let Some(parent_namespace) = backend.parent_namespace(collection)
+                    && let Some((parent, _)) = backend.parent_collection(collection)
+                    && !self
+                        .data_manager
+                        .backend_for(parent_namespace)
+                        .is_ok_and(|b| b.collections().contains(&parent))
+                {
+                    startup.problems.push(format!(
+                        "collection `{}`: x-parent-id names unknown collection `{}/{}`",
+                        collection, parent_namespace, parent
+                    ));
+                }
+            }
+            if let Some(error) = health.error {
+                startup.problems.push(format!("database unreachable: {}", error));
+            } else if backend.is_read_only() {
+                startup.warnings.extend(
+                    health
+                        .pending_migrations
+                        .iter()
+                        .map(|m| format!("read-only, migration of `{}` pending", m)),
+                );
+            } else {
+                startup.problems.extend(
+                    health
+                        .pending_migrations
+                        .iter()
+                        .map(|m| format!("migration of `{}` did not apply", m)),
+                );
+                let (backfilled, conflicts) = backend.backfill_derived_columns()?;
+                startup.backfilled = backfilled;
+                startup.warnings.extend(conflicts);
+            }
+            report.namespaces.push(startup);
+        }
+        if !report.is_ok() {
+            return Err(StoreError::Unavailable(format!("startup checks failed\n{}", report)));
+        }
+        tracing::info!("startup checks passed\n{}", report);
+        Ok(report)
+    }
+}
+
+/// Maintenance mode operations
+impl Store {
+    /// Enable or disable the read-only maintenance mode, e.g. around backups and migrations.
+    pub fn set_maintenance_mode(&self, enabled: bool, reason: Option<String>) -> StoreResult<MaintenanceMode> {
+        let mut mode = self
+            .maintenance
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let since = match (enabled, mode.enabled) {
+            // already enabled, only the reason changes
+            (true, true) => mode.since,
+            (true, false) => Some(chrono::Utc::now()),
+            (false, _) => None,
+        };
+        *mode = MaintenanceMode {
+            enabled,
+            reason: reason.filter(|_| enabled),
+            since,
+        };
+        tracing::info!("maintenance mode {}", if enabled { "enabled" } else { "disabled" });
+        Ok(mode.clone())
+    }
+
+    pub fn maintenance_mode(&self) -> StoreResult<MaintenanceMode> {
+        Ok(self
+            .maintenance
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .clone())
+    }
+
+    // reject mutations while in maintenance mode
+    fn check_writable(&self) -> StoreResult<()> {
+        let mode = self
+            .maintenance
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        if mode.enabled {
+            return Err(StoreError::Unavailable(
+                mode.reason
+                    .clone()
+                    .unwrap_or_else(|| "read-only maintenance mode".to_string()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Which items a paged listing walks, see `Store::list_page`.
+#[derive(Debug, Clone, Copy)]
+pub enum Listing<'a> {
+    /// the items owned by the user
+    Owner,
+    /// the children of a parent the user can read
+    Children(&'a str),
+    /// every item the user can read
+    Permission,
+}
+
+/// Blob offload operations
+impl Store {
+    /// Offload the oversized body fields of every namespace to the blob store, `None` keeps new writes inline.
+    pub fn set_blob_offload(&self, offload: Option<BlobOffload>) -> StoreResult<()> {
+        for namespace in self.data_manager.namespaces() {
+            self.data_manager
+                .backend_for(&namespace)?
+                .set_blob_offload(offload.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Like `get`, with the offloaded body fields re-inlined.
+    pub fn get_inlined(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<DataItem> {
+        let mut data = self.get(namespace, collection, id, user)?;
+        self.data_manager.backend_for(namespace)?.inline_blobs(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Field encryption operations
+impl Store {
+    /// Encrypt the `x-encrypt-fields` of every namespace with the key, see `backend::encrypt`.
+    ///
+    /// Without a key the collections listing encrypted fields can not be written, and documents
+    /// holding encrypted fields can not be read.
+    pub fn set_field_cipher(&self, cipher: Option<FieldCipher>) -> StoreResult<()> {
+        for namespace in self.data_manager.namespaces() {
+            self.data_manager
+                .backend_for(&namespace)?
+                .set_field_cipher(cipher.clone())?;
+        }
+        Ok(())
+    }
+}
+
+/// Read snapshot operations
+impl Store {
+    /// Pin the read view of the namespace for the `TxScope::read_only` scope the caller entered,
+    /// the reads run in that scope later on see the namespace as it is now.
+    ///
+    /// Returns the seq of the latest change the snapshot sees.
+    pub fn pin_read_snapshot(&self, namespace: &str, user: &str) -> StoreResult<i64> {
+        if !TxScope::current().is_some_and(|scope| scope.is_read_only()) {
+            return Err(StoreError::Backend(
+                "read snapshots are pinned inside a read-only scope".to_string(),
+            ));
+        }
+        self.check_namespace_access(namespace, user)?;
+        // the first statement of the scope on the database starts its read transaction
+        self.data_manager.backend_for(namespace)?.head_seq()
+    }
+}
+
+/// Namespace policy operations
+impl Store {
+    pub fn set_namespace_policy(&self, namespace: &str, policy: NamespacePolicy) -> StoreResult<()> {
+        // make sure the namespace exists before accepting a policy for it
+        self.data_manager.backend_for(namespace)?;
+        self.namespace_policies
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .insert(namespace.to_string(), policy);
+        Ok(())
+    }
+
+    pub fn get_namespace_policy(&self, namespace: &str) -> StoreResult<NamespacePolicy> {
+        self.data_manager.backend_for(namespace)?;
+        Ok(self
+            .namespace_policies
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .get(namespace)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    /// Namespaces whose policy lets the user in.
+    pub fn accessible_namespaces(&self, user: &str) -> StoreResult<Vec<String>> {
+        let policies = self
+            .namespace_policies
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let mut namespaces = self.data_manager.namespaces();
+        namespaces.retain(|ns| policies.get(ns).is_none_or(|policy| policy.allows(user)));
+        Ok(namespaces)
+    }
+
+    /// Reject the user early if the namespace is closed to them.
+    fn check_namespace_access(&self, namespace: &str, user: &str) -> StoreResult<()> {
+        let policies = self
+            .namespace_policies
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        match policies.get(namespace) {
+            Some(policy) if !policy.allows(user) => Err(StoreError::PermissionDenied),
+            _ => Ok(()),
+        }
+    }
+
+    // namespace and database holding the parents of `collection`, another namespace when its
+    // `x-parent-id` names one, the user then needs access to that namespace as well
+    fn parent_backend<'a>(
+        &self,
+        backend: &'a SqliteBackend,
+        namespace: &'a str,
+        collection: &str,
+        user: &str,
+    ) -> StoreResult<(&'a str, Arc<SqliteBackend>)> {
+        let Some(parent_namespace) = backend.parent_namespace(collection) else {
+            return Ok((namespace, self.data_manager.backend_for(namespace)?));
+        };
+        self.check_namespace_access(parent_namespace, user)?;
+        Ok((parent_namespace, self.data_manager.backend_for(parent_namespace)?))
+    }
+
+    // the database of the item can not look up a parent of another namespace, a body moving the
+    // item under such a parent must name one that exists
+    fn check_foreign_parent(
+        &self,
+        backend: &SqliteBackend,
+        collection: &str,
+        data: &DataItem,
+        body: &Value,
+    ) -> StoreResult<()> {
+        if let Some(parent_namespace) = backend.parent_namespace(collection)
+            && let Some((parent_collection, field)) = backend.parent_collection(collection)
+            && let Some(parent_id) = body_field(body, field).and_then(|v| v.as_str())
+            && data.parent_id.as_deref() != Some(parent_id)
+        {
+            self.data_manager
+                .backend_for(parent_namespace)?
+                .get(parent_collection, &parent_id.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Collection mode operations
+impl Store {
+    /// Switch a collection to read-only or back, the mode is persisted in the namespace database.
+    ///
+    /// Reads and acl changes keep working on a read-only collection.
+    pub fn set_collection_mode(
+        &self,
+        namespace: &str,
+        collection: &str,
+        read_only: bool,
+        reason: Option<String>,
+    ) -> StoreResult<CollectionMode> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        let current = self.collection_mode(namespace, collection)?;
+        let mode = CollectionMode {
+            read_only,
+            reason: reason.filter(|_| read_only),
+            since: match (read_only, current.read_only) {
+                (true, true) => current.since,
+                (true, false) => Some(chrono::Utc::now()),
+                (false, _) => None,
+            },
+        };
+        backend.set_collection_mode(collection, &mode)?;
+        tracing::info!(
+            "collection {}/{} is now {}",
+            namespace,
+            collection,
+            if read_only { "read-only" } else { "writable" }
+        );
+        Ok(mode)
+    }
+
+    pub fn collection_mode(&self, namespace: &str, collection: &str) -> StoreResult<CollectionMode> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        if !backend.collections().contains(&collection) {
+            return Err(StoreError::NotFound(format!("Collection {}/{}", namespace, collection)));
+        }
+        backend.collection_mode(collection)
+    }
+}
+
+/// Collection kill switch operations
+impl Store {
+    /// Disable reads and/or writes of a collection at runtime, rejected requests are answered with 503.
+    ///
+    /// Meant to isolate a misbehaving collection without taking the whole service down, the switches are
+    /// not persisted.
+    pub fn set_collection_switches(
+        &self,
+        namespace: &str,
+        collection: &str,
+        switches: CollectionSwitches,
+    ) -> StoreResult<CollectionSwitches> {
+        self.collection_mode(namespace, collection)?;
+        let mut all = self
+            .collection_switches
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let key = (namespace.to_string(), collection.to_string());
+        if switches.reads_disabled || switches.writes_disabled {
+            all.insert(key, switches.clone());
+        } else {
+            all.remove(&key);
+        }
+        tracing::warn!(
+            "collection {}/{} switches set, reads disabled: {}, writes disabled: {}",
+            namespace,
+            collection,
+            switches.reads_disabled,
+            switches.writes_disabled
+        );
+        Ok(switches)
+    }
+
+    pub fn collection_switches(&self, namespace: &str, collection: &str) -> StoreResult<CollectionSwitches> {
+        self.collection_mode(namespace, collection)?;
+        Ok(self
+            .collection_switches
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .get(&(namespace.to_string(), collection.to_string()))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn check_collection_switch(&self, namespace: &str, collection: &str, write: bool) -> StoreResult<()> {
+        let all = self
+            .collection_switches
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let Some(switches) = all.get(&(namespace.to_string(), collection.to_string())) else {
+            return Ok(());
+        };
+        let disabled = if write {
+            switches.writes_disabled
+        } else {
+            switches.reads_disabled
+        };
+        if disabled {
+            return Err(StoreError::Unavailable(format!(
+                "{} of collection `{}` are disabled{}",
+                if write { "writes" } else { "reads" },
+                collection,
+                switches.reason.as_ref().map(|r| format!(": {}", r)).unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
 }
 
 /// User management operations
@@ -52,10 +563,12 @@ impl Store {
     }
 
     pub fn update_user(&self, user_id: &String, user_schema: &UserSchema) -> StoreResult<()> {
+        self.check_writable()?;
         self.user_manager.update_user(user_id, user_schema)
     }
 
     pub fn create_user(&self, username: &str, password: &str) -> StoreResult<()> {
+        self.check_writable()?;
         self.user_manager.create_user(username, password)
     }
 
@@ -63,21 +576,272 @@ impl Store {
         self.user_manager.get_inner_backend()
     }
 
-    pub fn list_friends(&self, user_id: &str) -> StoreResult<Vec<(String, UserSchema)>> {
-        let friend_ids = self.user_manager.list_friends(user_id)?;
+    /// Deactivate the account instead of removing it, login is blocked and its data hidden from other
+    /// users until an admin restores it or the purge job removes it after the grace period.
+    pub fn deactivate_user(&self, user_id: &String) -> StoreResult<()> {
+        self.check_writable()?;
+        if self.get_user(user_id)?.deactivated_at.is_some() {
+            return Err(StoreError::Conflict("account is already deactivated".to_string()));
+        }
+        self.user_manager.set_deactivated(user_id, true)?;
+        self.deactivated_users
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .insert(user_id.clone());
+        Ok(())
+    }
+
+    /// Bring back a deactivated account before it is purged.
+    pub fn restore_user(&self, user_id: &String) -> StoreResult<UserSchema> {
+        self.check_writable()?;
+        if self.get_user(user_id)?.deactivated_at.is_none() {
+            return Err(StoreError::Conflict("account is not deactivated".to_string()));
+        }
+        let user = self.user_manager.set_deactivated(user_id, false)?;
+        self.deactivated_users
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .remove(user_id);
+        Ok(user)
+    }
+
+    /// Register a passkey whose registration ceremony the caller verified.
+    pub fn add_passkey(&self, user_id: &str, name: &str, credential: NewCredential) -> StoreResult<Passkey> {
+        self.check_writable()?;
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_PASSKEY_NAME_CHARS {
+            return Err(StoreError::Validation(format!(
+                "passkey name must be 1 to {} characters",
+                MAX_PASSKEY_NAME_CHARS
+            )));
+        }
+        if self.user_manager.list_passkeys(user_id)?.len() >= MAX_PASSKEYS_PER_USER {
+            return Err(StoreError::QuotaExceeded(format!(
+                "at most {} passkeys per user",
+                MAX_PASSKEYS_PER_USER
+            )));
+        }
+        self.user_manager.add_passkey(
+            user_id,
+            name,
+            &passkey::encode(&credential.credential_id),
+            credential.public_key,
+            credential.sign_count,
+        )
+    }
+
+    pub fn list_passkeys(&self, user_id: &str) -> StoreResult<Vec<Passkey>> {
+        self.user_manager.list_passkeys(user_id)
+    }
+
+    pub fn delete_passkey(&self, user_id: &str, id: &str) -> StoreResult<()> {
+        self.check_writable()?;
+        self.user_manager.delete_passkey(user_id, id)
+    }
+
+    /// Log in with the passkey of `credential_id`, none like a wrong password when it is unknown or
+    /// its account deactivated.
+    ///
+    /// `verify` checks the assertion against the stored passkey and returns the new signature counter.
+    pub fn validate_passkey(
+        &self,
+        credential_id: &str,
+        verify: impl FnOnce(&Passkey) -> StoreResult<u32>,
+    ) -> StoreResult<Option<String>> {
+        let (user_id, passkey) = match self.user_manager.find_passkey(credential_id) {
+            Ok(found) => found,
+            Err(StoreError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if self.get_user(&user_id)?.deactivated_at.is_some() {
+            return Ok(None);
+        }
+        let sign_count = verify(&passkey)?;
+        // logins go on in maintenance mode, the counter is only kept while writable
+        if self.check_writable().is_ok() {
+            self.user_manager.record_passkey_use(&passkey.id, sign_count)?;
+        }
+        Ok(Some(user_id))
+    }
+
+    pub fn list_deactivated_users(&self) -> StoreResult<Vec<DeactivatedUser>> {
+        self.user_manager.list_deactivated()
+    }
+
+    fn is_deactivated(&self, user_id: &str) -> StoreResult<bool> {
+        Ok(self
+            .deactivated_users
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .contains(user_id))
+    }
+
+    /// Purge the accounts deactivated for longer than `grace`, returns how many were purged.
+    pub fn purge_deactivated_users(&self, grace: Duration) -> StoreResult<usize> {
+        self.check_writable()?;
+        let grace = chrono::Duration::from_std(grace).map_err(|e| StoreError::Validation(e.to_string()))?;
+        let cutoff = chrono::Utc::now() - grace;
+        let mut purged = 0;
+        for user in self.user_manager.list_deactivated()? {
+            if user.deactivated_at > cutoff {
+                continue;
+            }
+            tracing::info!(
+                "purge user {}({}) deactivated at {}",
+                user.username,
+                user.user_id,
+                user.deactivated_at
+            );
+            self.purge_user(&user.user_id)?;
+            purged += 1;
+        }
+        Ok(purged)
+    }
+
+    // the owned documents with their ACLs, the ACL entries received, the notifications, friendships and
+    // profile. Children other users added under the purged documents are left to the orphan cleanup.
+    fn purge_user(&self, user_id: &str) -> StoreResult<()> {
+        let meta = change_meta(ROOT_OWNER, Some("account purged"))?;
+        for namespace in self.data_manager.namespaces() {
+            let backend = self.data_manager.backend_for(&namespace)?;
+            if backend.is_read_only() {
+                continue;
+            }
+            for collection in backend.collections() {
+                // the pages shrink as they are deleted, always read the first one
+                loop {
+                    let (items, _) = backend.list_by_owner(collection, user_id, None, 100)?;
+                    if items.is_empty() {
+                        break;
+                    }
+                    for item in items {
+                        backend.delete_acls_by_data_id(collection, &item.id)?;
+                        backend.delete_with_meta(collection, &item.id, None, &meta)?;
+                        backend.release_lock(collection, &item.id)?;
+                    }
+                }
+                for permission in backend.get_user_permissions(collection, user_id)? {
+                    let owner = match backend.get(collection, &permission.data_id) {
+                        Ok(data) => data.owner,
+                        Err(StoreError::NotFound(_)) => ROOT_OWNER.to_string(),
+                        Err(e) => return Err(e),
+                    };
+                    backend.update_acls_bulk(collection, &[permission.data_id], &[], &[user_id.to_string()], &owner)?;
+                }
+            }
+        }
+        self.notification_manager.delete_all(user_id)?;
+        self.user_manager.remove_user(user_id)?;
+        self.deactivated_users
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .remove(user_id);
+        Ok(())
+    }
+
+    /// Friends of the user with their profile, friends whose user was removed or deactivated are skipped.
+    pub fn list_friends(
+        &self,
+        user_id: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<(String, UserSchema)>, Option<String>)> {
+        let (friend_ids, next_marker) = self.user_manager.list_friends(user_id, marker, limit)?;
         let mut friends = Vec::new();
         for friend_id in friend_ids {
-            if let Ok(user_schema) = self.get_user(&friend_id) {
+            if let Ok(user_schema) = self.get_user(&friend_id)
+                && user_schema.deactivated_at.is_none()
+            {
                 friends.push((friend_id, user_schema));
             }
         }
-        Ok(friends)
+        Ok((friends, next_marker))
     }
     pub fn add_friend(&self, user_id: &String, friend_id: &String) -> StoreResult<()> {
+        self.check_writable()?;
         self.user_manager.add_friend(user_id, friend_id)?;
         self.user_manager.add_friend(friend_id, user_id)?;
+        let username = self.get_user(user_id)?.username;
+        self.notify_best_effort(
+            friend_id,
+            NotificationMessage {
+                kind: "friend_added".to_string(),
+                title: format!("{} added you as a friend", username),
+                content: None,
+                payload: Some(serde_json::json!({ "user_id": user_id })),
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Notification inbox operations, users only see their own notifications
+impl Store {
+    pub fn set_delivery_channel(&self, channel: Arc<dyn DeliveryChannel>) -> StoreResult<()> {
+        tracing::info!("notification delivery channel: {}", channel.name());
+        *self.delivery.write().map_err(|e| StoreError::Backend(e.to_string()))? = Some(channel);
         Ok(())
     }
+
+    /// Publish a notification into the inbox of `recipient`.
+    ///
+    /// It is also sent through the delivery channel in background if the recipient has an email.
+    pub fn notify(&self, recipient: &str, message: NotificationMessage) -> StoreResult<String> {
+        let delivery_message: DeliveryMessage = (&message).into();
+        let id = self.notification_manager.publish(recipient, message)?;
+        let delivery = self
+            .delivery
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .clone();
+        if let Some(channel) = delivery
+            && let Some(email) = self.get_user(&recipient.to_string())?.email
+            && let Ok(handle) = tokio::runtime::Handle::try_current()
+        {
+            handle.spawn(async move {
+                if let Err(e) = channel.deliver(&email, &delivery_message).await {
+                    tracing::warn!("failed to deliver notification to {}: {}", email, e);
+                }
+            });
+        }
+        Ok(id)
+    }
+
+    // notifications are a side effect, never fail the operation raising them
+    fn notify_best_effort(&self, recipient: &str, message: NotificationMessage) {
+        if let Err(e) = self.notify(recipient, message) {
+            tracing::warn!("failed to notify {}: {}", recipient, e);
+        }
+    }
+
+    pub fn list_notifications(
+        &self,
+        user: &str,
+        unread_only: bool,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<Notification>, Option<String>)> {
+        self.notification_manager.list(user, unread_only, marker, limit)
+    }
+
+    pub fn unread_notification_count(&self, user: &str) -> StoreResult<u64> {
+        self.notification_manager.unread_count(user)
+    }
+
+    pub fn mark_notification_read(&self, user: &str, id: &str) -> StoreResult<Notification> {
+        self.check_writable()?;
+        self.notification_manager.mark_read(user, id)
+    }
+
+    pub fn mark_all_notifications_read(&self, user: &str) -> StoreResult<usize> {
+        self.check_writable()?;
+        self.notification_manager.mark_all_read(user)
+    }
+
+    pub fn delete_notification(&self, user: &str, id: &str) -> StoreResult<()> {
+        self.check_writable()?;
+        self.notification_manager.delete(user, id)
+    }
 }
 
 /// Data operations, CRUD using data manager, re-expose here for convenience
@@ -85,20 +849,97 @@ impl Store {
     // -- CRUD operations below --
     /// Insert a document body. Returns meta including generated id.
     pub fn insert(&self, namespace: &str, collection: &str, body: &Value, user: &str) -> StoreResult<String> {
+        self.insert_with_id(namespace, collection, None, body, user)
+    }
+
+    /// Insert a document body under the id a client generated, offline clients refer to it before
+    /// it is stored. A server generated one when `None`.
+    ///
+    /// The id is 1 to 64 ASCII letters, digits, `-` or `_`; one already taken in the collection is
+    /// a conflict.
+    pub fn insert_with_id(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: Option<&str>,
+        body: &Value,
+        user: &str,
+    ) -> StoreResult<String> {
+        match self.insert_item(namespace, collection, id, body, user, ImportMode::Always)? {
+            Imported::Inserted(id) | Imported::Duplicate(id) => Ok(id),
+        }
+    }
+
+    /// Check a body as inserting it, or replacing the document `id` with it, would, without writing
+    /// anything. Every reason it would be refused for is reported.
+    pub fn validate_data(
+        &self,
+        namespace: &str,
+        collection: &str,
+        body: &Value,
+        id: Option<&str>,
+        user: &str,
+    ) -> StoreResult<ValidationReport> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let errors = backend.check_body(collection, body, id)?;
+        Ok(ValidationReport {
+            valid: errors.is_empty(),
+            errors,
+        })
+    }
+
+    /// Insert a document body, with `ImportMode::SkipDuplicates` the user's item already holding the same
+    /// content is returned as `Imported::Duplicate` instead.
+    pub fn insert_with_mode(
+        &self,
+        namespace: &str,
+        collection: &str,
+        body: &Value,
+        user: &str,
+        mode: ImportMode,
+    ) -> StoreResult<Imported> {
+        self.insert_item(namespace, collection, None, body, user, mode)
+    }
+
+    fn insert_item(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: Option<&str>,
+        body: &Value,
+        user: &str,
+        mode: ImportMode,
+    ) -> StoreResult<Imported> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
         let backend = self.data_manager.backend_for(namespace)?;
+        check_collection_writable(&backend, collection)?;
+        if let Some(id) = id {
+            check_document_id(id)?;
+            if backend.id_taken(collection, id)? {
+                return Err(StoreError::Conflict(format!(
+                    "document id '{}' already taken in collection '{}'",
+                    id, collection
+                )));
+            }
+        }
         // check permission on parent collection if exist.
         // else the collection is root level, allow insert for anyone.
         if let Some((parent_collection, field)) = backend.parent_collection(collection) {
             // get the parent field value from body
-            let Some(parent_id) = body.get(field).and_then(|v| v.as_str()) else {
+            let Some(parent_id) = body_field(body, field).and_then(|v| v.as_str()) else {
                 return Err(StoreError::Validation(format!(
                     "missing parent id field `{}` for collection `{}`",
                     field, collection
                 )));
             };
-            let parent_data = backend.get(parent_collection, &parent_id.to_string())?;
+            let (parent_namespace, parent_backend) = self.parent_backend(&backend, namespace, collection, user)?;
+            let parent_data = parent_backend.get(parent_collection, &parent_id.to_string())?;
             if !self.check_permission(
-                (namespace, parent_collection),
+                (parent_namespace, parent_collection),
                 &parent_data,
                 user,
                 ACLMask::APPEND_1_BELOW,
@@ -106,45 +947,373 @@ impl Store {
                 return Err(StoreError::PermissionDenied);
             }
         }
-        backend.insert(collection, body, user.to_string())
+        let now = chrono::Utc::now();
+        backend.import_with_mode(
+            collection,
+            body,
+            user.to_string(),
+            id.map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string),
+            now,
+            now,
+            mode,
+        )
     }
 
-    pub fn list_by_owner(
+    /// Insert every body in one transaction, or none of them when one item fails.
+    ///
+    /// An item may name a parent inserted earlier in the same batch. The failures of all the items
+    /// are reported, not only the first one.
+    pub fn insert_many(
         &self,
         namespace: &str,
         collection: &str,
-        marker: Option<String>,
-        limit: usize,
+        bodies: &[Value],
         user: &str,
-    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
-        // seems no need to check permission for listing by owner
+    ) -> StoreResult<BatchOutcome> {
+        if bodies.len() > MAX_BULK_INSERT_ITEMS {
+            return Err(StoreError::Validation(format!(
+                "at most {} items per batch",
+                MAX_BULK_INSERT_ITEMS
+            )));
+        }
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
         let backend = self.data_manager.backend_for(namespace)?;
-        backend.list_by_owner(collection, user, marker, limit)
+        check_collection_writable(&backend, collection)?;
+        run_batch(|| {
+            bodies
+                .iter()
+                .map(|body| self.insert(namespace, collection, body, user))
+                .collect()
+        })
     }
 
-    pub fn list_children(
+    /// Run several writes of the namespace in one transaction, e.g. a repo with its first post.
+    ///
+    /// Every write of the closure is committed together when it returns `Ok`, and rolled back when it
+    /// returns an error. The later writes see the earlier ones, a child can name a parent inserted
+    /// before it. Inside a request transaction the writes join it instead and are left to the
+    /// request to commit or roll back.
+    pub fn transaction<R>(
         &self,
         namespace: &str,
-        collection: &str,
-        parent_id: &str,
-        marker: Option<String>,
-        limit: usize,
-        user: &str,
-    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
-        // list children operation should have access for the parent collection.
+        f: impl FnOnce(&Transaction<'_>) -> StoreResult<R>,
+    ) -> StoreResult<R> {
+        self.check_writable()?;
+        check_backend_writable(&self.data_manager.backend_for(namespace)?)?;
+        let txn = Transaction { store: self, namespace };
+        if TxScope::current().is_some() {
+            return f(&txn);
+        }
+        let scope = TxScope::new();
+        match scope.enter_sync(|| f(&txn)) {
+            Ok(result) => {
+                scope.commit()?;
+                Ok(result)
+            }
+            Err(e) => {
+                scope.rollback();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn list_by_owner(
+        &self,
+        namespace: &str,
+        collection: &str,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        // seems no need to check permission for listing by owner
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        backend.list_by_owner(collection, user, marker, limit)
+    }
+
+    /// The user's documents under one parent, e.g. my posts in one repo.
+    ///
+    /// Like `list_by_owner` only the owner is checked, the parent does not need to be readable.
+    pub fn list_by_owner_under(
+        &self,
+        namespace: &str,
+        collection: &str,
+        parent_id: &str,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        if backend.parent_collection(collection).is_none() {
+            return Err(StoreError::NotFound(format!(
+                "no parent collection for current `{}`",
+                collection
+            )));
+        }
+        backend.list_by_owner_at(collection, user, Some(parent_id), marker, None, false, limit)
+    }
+
+    pub fn list_children(
+        &self,
+        namespace: &str,
+        collection: &str,
+        parent_id: &str,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        // list children operation should have access for the parent collection.
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        self.check_parent_readable(&backend, (namespace, collection), parent_id, user)?;
+        backend.list_children(collection, parent_id, marker, limit)
+    }
+
+    /// Like `list_by_owner`, with every page pinned to the items present when the first page was read.
+    pub fn list_by_owner_snapshot(
+        &self,
+        namespace: &str,
+        collection: &str,
+        cursor: Option<PageCursor>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<PageCursor>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let (marker, snapshot) = match cursor {
+            Some(cursor) => (cursor.marker, cursor.snapshot),
+            None => (None, backend.snapshot_boundary(collection)?),
+        };
+        let (items, next_marker) =
+            backend.list_by_owner_at(collection, user, None, marker, Some(snapshot), false, limit)?;
+        Ok((items, next_marker.map(|marker| PageCursor::new(marker, snapshot))))
+    }
+
+    /// Like `list_children`, with every page pinned to the items present when the first page was read.
+    pub fn list_children_snapshot(
+        &self,
+        namespace: &str,
+        collection: &str,
+        parent_id: &str,
+        cursor: Option<PageCursor>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<PageCursor>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
         let backend = self.data_manager.backend_for(namespace)?;
+        self.check_parent_readable(&backend, (namespace, collection), parent_id, user)?;
+        let (marker, snapshot) = match cursor {
+            Some(cursor) => (cursor.marker, cursor.snapshot),
+            None => (None, backend.snapshot_boundary(collection)?),
+        };
+        let (items, next_marker) =
+            backend.list_children_at(collection, parent_id, marker, Some(snapshot), false, limit)?;
+        Ok((items, next_marker.map(|marker| PageCursor::new(marker, snapshot))))
+    }
+
+    // listing children needs the read permission on their parent
+    fn check_parent_readable(
+        &self,
+        backend: &SqliteBackend,
+        (namespace, collection): (&str, &str),
+        parent_id: &str,
+        user: &str,
+    ) -> StoreResult<()> {
         let Some((parent_collection, _field)) = backend.parent_collection(collection) else {
             return Err(StoreError::NotFound(format!(
                 "no parent collection for current `{}`",
                 collection
             )));
         };
-        let parent_data = backend.get(parent_collection, &parent_id.to_string())?;
+        let (parent_namespace, parent_backend) = self.parent_backend(backend, namespace, collection, user)?;
+        let parent_data = parent_backend.get(parent_collection, &parent_id.to_string())?;
         // check permission on parent data
-        if !self.check_permission((namespace, parent_collection), &parent_data, user, ACLMask::READ_ONLY)? {
+        if !self.check_permission(
+            (parent_namespace, parent_collection),
+            &parent_data,
+            user,
+            ACLMask::READ_ONLY,
+        )? {
             return Err(StoreError::PermissionDenied);
         }
-        backend.list_children(collection, parent_id, marker, limit)
+        Ok(())
+    }
+
+    /// List the user's documents matching the filter, or with `parent_id` the matching children of a
+    /// parent the user can read.
+    #[allow(clippy::too_many_arguments)]
+    pub fn query(
+        &self,
+        namespace: &str,
+        collection: &str,
+        parent_id: Option<&str>,
+        filter: &FilterExpr,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let Some(parent_id) = parent_id else {
+            return backend.query(collection, QueryScope::Owner(user), filter, marker, limit);
+        };
+        self.check_parent_readable(&backend, (namespace, collection), parent_id, user)?;
+        backend.query(collection, QueryScope::Parent(parent_id), filter, marker, limit)
+    }
+
+    /// Full text search in the `x-fulltext` fields of a collection, best match first.
+    ///
+    /// Only the documents the user can read are kept, the matches are walked in batches until
+    /// `MAX_SEARCH_RESULTS` of them are found.
+    pub fn search(&self, namespace: &str, collection: &str, query: &str, user: &str) -> StoreResult<Vec<DataItem>> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let mut found = Vec::new();
+        let mut offset = 0;
+        loop {
+            let batch = backend.search(collection, query, offset, MAX_SEARCH_RESULTS)?;
+            let exhausted = batch.len() < MAX_SEARCH_RESULTS;
+            offset += batch.len();
+            for item in batch {
+                if self.check_permission((namespace, collection), &item, user, ACLMask::READ_ONLY)? {
+                    found.push(item);
+                    if found.len() == MAX_SEARCH_RESULTS {
+                        return Ok(found);
+                    }
+                }
+            }
+            if exhausted {
+                return Ok(found);
+            }
+        }
+    }
+
+    /// A page of the listing with the markers of the pages before and after.
+    ///
+    /// With `include_deleted` the owner and children listings also hold the soft deleted items.
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_page(
+        &self,
+        namespace: &str,
+        collection: &str,
+        listing: Listing,
+        marker: Option<String>,
+        include_deleted: bool,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<Page> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        match listing {
+            Listing::Owner => backend.list_page(collection, QueryScope::Owner(user), marker, include_deleted, limit),
+            Listing::Children(parent_id) => {
+                self.check_parent_readable(&backend, (namespace, collection), parent_id, user)?;
+                backend.list_page(
+                    collection,
+                    QueryScope::Parent(parent_id),
+                    marker,
+                    include_deleted,
+                    limit,
+                )
+            }
+            Listing::Permission if include_deleted => Err(StoreError::Validation(
+                "the permission listing does not list deleted items".to_string(),
+            )),
+            Listing::Permission => self.permission_page(namespace, collection, marker, limit, user),
+        }
+    }
+
+    /// A page of the owner or children listing in `order`, with the marker of the next page.
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_sorted(
+        &self,
+        namespace: &str,
+        collection: &str,
+        listing: Listing,
+        order: ListOrder,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let marker = marker.as_deref().map(SortMarker::decode).transpose()?;
+        let scope = self.keyset_scope(&backend, (namespace, collection), listing, user)?;
+        let (items, next_marker) =
+            backend.list_sorted(collection, scope, order, None, false, marker.as_ref(), limit)?;
+        Ok((items, next_marker.map(|m| m.encode())))
+    }
+
+    /// The owner or children listing restricted to the items updated at or after `since`, oldest
+    /// change first and paged by `(updated_at, id)`.
+    ///
+    /// A client keeps the `updated_at` of the last item it saw for its next visit. Deleted items are
+    /// not listed, their deletion shows in the document history. In an `x-soft-delete` collection
+    /// `include_deleted` lists them too, with their `deleted_at`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_updated_since(
+        &self,
+        namespace: &str,
+        collection: &str,
+        listing: Listing,
+        since: chrono::DateTime<chrono::Utc>,
+        include_deleted: bool,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let marker = marker.as_deref().map(SortMarker::decode).transpose()?;
+        let scope = self.keyset_scope(&backend, (namespace, collection), listing, user)?;
+        let order = ListOrder {
+            key: SortKey::UpdatedAt,
+            descending: false,
+        };
+        let (items, next_marker) = backend.list_sorted(
+            collection,
+            scope,
+            order,
+            Some(since),
+            include_deleted,
+            marker.as_ref(),
+            limit,
+        )?;
+        Ok((items, next_marker.map(|m| m.encode())))
+    }
+
+    // the keyset listings run over the owner's items or a readable parent's children, the permission
+    // listing walks the ACL entries and keeps its id order
+    fn keyset_scope<'a>(
+        &self,
+        backend: &SqliteBackend,
+        (namespace, collection): (&str, &str),
+        listing: Listing<'a>,
+        user: &'a str,
+    ) -> StoreResult<QueryScope<'a>> {
+        match listing {
+            Listing::Owner => Ok(QueryScope::Owner(user)),
+            Listing::Children(parent_id) => {
+                self.check_parent_readable(backend, (namespace, collection), parent_id, user)?;
+                Ok(QueryScope::Parent(parent_id))
+            }
+            Listing::Permission => Err(StoreError::Validation(
+                "the permission listing is only paged by id".to_string(),
+            )),
+        }
     }
 
     pub fn list_with_permission(
@@ -155,8 +1324,28 @@ impl Store {
         limit: usize,
         user: &str,
     ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let page = self.permission_page(namespace, collection, marker, limit, user)?;
+        Ok((page.items, page.next_marker))
+    }
+
+    fn permission_page(
+        &self,
+        namespace: &str,
+        collection: &str,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<Page> {
+        let empty = Page {
+            items: Vec::new(),
+            next_marker: None,
+            prev_marker: None,
+            has_prev: false,
+        };
         if limit == 0 {
-            return Ok((Vec::new(), None));
+            return Ok(empty);
         }
         let backend = self.data_manager.backend_for(namespace)?;
         let mut cache: HashMap<(String, String), DataItem> = HashMap::new();
@@ -186,7 +1375,7 @@ impl Store {
             duration
         );
         if accessible_ids.is_empty() {
-            return Ok((Vec::new(), None));
+            return Ok(empty);
         }
         let ids: Vec<String> = accessible_ids.into_iter().collect();
         let start_index = marker
@@ -209,7 +1398,17 @@ impl Store {
             };
             items.push(data);
         }
-        Ok((items, next_marker))
+        // the page before starts `limit` ids back, at the first id when fewer are left
+        let prev_marker = start_index
+            .checked_sub(limit)
+            .filter(|i| *i > 0)
+            .map(|i| ids[i].clone());
+        Ok(Page {
+            items,
+            next_marker,
+            prev_marker,
+            has_prev: start_index > 0,
+        })
     }
 
     const PERMISSION_PAGE_SIZE: usize = 128;
@@ -234,144 +1433,2039 @@ impl Store {
         Ok(items)
     }
 
-    fn collect_all_children_items(
-        &self,
-        backend: &Arc<SqliteBackend>,
-        collection: &str,
-        parent_id: &str,
-    ) -> StoreResult<Vec<DataItem>> {
-        let mut items = Vec::new();
-        let mut marker = None;
-        loop {
-            let (page, next_marker) =
-                backend.list_children(collection, parent_id, marker.clone(), Self::PERMISSION_PAGE_SIZE)?;
-            items.extend(page);
-            if next_marker.is_none() {
-                break;
-            }
-            marker = next_marker;
-        }
-        Ok(items)
+    fn collect_all_children_items(
+        &self,
+        backend: &Arc<SqliteBackend>,
+        collection: &str,
+        parent_id: &str,
+    ) -> StoreResult<Vec<DataItem>> {
+        let mut items = Vec::new();
+        let mut marker = None;
+        loop {
+            let (page, next_marker) =
+                backend.list_children(collection, parent_id, marker.clone(), Self::PERMISSION_PAGE_SIZE)?;
+            items.extend(page);
+            if next_marker.is_none() {
+                break;
+            }
+            marker = next_marker;
+        }
+        Ok(items)
+    }
+
+    fn collect_all_accessible_ids(
+        &self,
+        namespace: &str,
+        collection: &str,
+        user: &str,
+        visited: &mut HashSet<(String, String)>,
+        cache: &mut HashMap<(String, String), DataItem>,
+    ) -> StoreResult<BTreeSet<String>> {
+        let key = (namespace.to_string(), collection.to_string());
+        if !visited.insert(key.clone()) {
+            return Ok(BTreeSet::new());
+        }
+        let result = (|| -> StoreResult<BTreeSet<String>> {
+            let backend = self.data_manager.backend_for(namespace)?;
+            let mut ids = BTreeSet::new();
+            let collection_key = collection.to_string();
+            let owner_items = self.collect_all_owner_items(&backend, collection, user)?;
+            for item in owner_items {
+                let item_id = item.id.clone();
+                ids.insert(item_id.clone());
+                cache.insert((collection_key.clone(), item_id), item);
+            }
+            for perm in backend.get_user_permissions(collection, user)? {
+                ids.insert(perm.data_id);
+            }
+            if let Some((parent_collection, _)) = backend.parent_collection(collection) {
+                // no parents are reachable in a namespace the user has no access to
+                let parent_namespace = match self.parent_backend(&backend, namespace, collection, user) {
+                    Err(StoreError::PermissionDenied) => return Ok(ids),
+                    parent => parent?.0,
+                };
+                let parent_ids =
+                    self.collect_all_accessible_ids(parent_namespace, parent_collection, user, visited, cache)?;
+                for parent_id in parent_ids {
+                    let children = self.collect_all_children_items(&backend, collection, &parent_id)?;
+                    for child in children {
+                        let child_id = child.id.clone();
+                        ids.insert(child_id.clone());
+                        cache.insert((collection_key.clone(), child_id), child);
+                    }
+                }
+            }
+            Ok(ids)
+        })();
+        visited.remove(&key);
+        result
+    }
+
+    pub fn get(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<DataItem> {
+        self.get_with_deleted(namespace, collection, id, false, user)
+    }
+
+    /// Like `get`, with `include_deleted` a soft deleted item is read as well.
+    pub fn get_with_deleted(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: &Id,
+        include_deleted: bool,
+        user: &str,
+    ) -> StoreResult<DataItem> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let mut data = match include_deleted {
+            true => backend.get_with_deleted(collection, id)?,
+            false => backend.get(collection, id)?,
+        };
+        // check permission
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::READ_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        data.lock = backend.get_lock(collection, id)?;
+        data.attachments = backend.list_attachments(collection, id)?;
+        // the statistics never fail the read itself
+        if let Err(e) = backend.record_read(collection, id) {
+            tracing::warn!("failed to record read of {}/{}: {}", collection, id, e);
+        }
+        Ok(data)
+    }
+
+    pub fn update(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: &Id,
+        body: &Value,
+        user: &str,
+    ) -> StoreResult<DataItem> {
+        self.update_with_reason(namespace, collection, id, body, None, user, None)
+    }
+
+    /// Update and record why in the change log, the reason shows up in the document history.
+    ///
+    /// With `expected_rev` the update is a conflict unless the item is still at that revision, a
+    /// client writing shared data passes the `rev` it read so it never overwrites a change it has
+    /// not seen.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_with_reason(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: &Id,
+        body: &Value,
+        expected_rev: Option<i64>,
+        user: &str,
+        reason: Option<&str>,
+    ) -> StoreResult<DataItem> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        let meta = change_meta(user, reason)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_collection_writable(&backend, collection)?;
+        let data = backend.get(collection, id)?;
+        // check permission
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        check_unlocked(&backend, collection, id, user)?;
+        self.check_foreign_parent(&backend, collection, &data, body)?;
+        backend.update_with_meta(collection, id, body, expected_rev, &meta)
+    }
+
+    /// Apply JSON Patch operations to the item body and store the result as an update would.
+    ///
+    /// The body is patched as read, a change landing before the patched body is written is a
+    /// conflict. `expected_rev` works as for `update_with_reason`.
+    pub fn patch(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: &Id,
+        ops: &[PatchOp],
+        expected_rev: Option<i64>,
+        user: &str,
+    ) -> StoreResult<DataItem> {
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let data = backend.get(collection, id)?;
+        // a failing `test` op must not tell the value to a user who can not write it
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        if let Some(expected) = expected_rev
+            && expected != data.rev
+        {
+            return Err(StoreError::Conflict(format!(
+                "{} / {} is at revision {}, not {}",
+                collection, id, data.rev, expected
+            )));
+        }
+        let mut body = data.body;
+        patch::apply(&mut body, ops)?;
+        self.update_with_reason(namespace, collection, id, &body, Some(data.rev), user, None)
+    }
+
+    /// Update every item in one transaction, or none of them when one item fails.
+    ///
+    /// Permissions and locks of all the items are checked before the first write, a batch the user
+    /// may not fully write is rejected without touching a document.
+    pub fn update_many(
+        &self,
+        namespace: &str,
+        collection: &str,
+        items: Vec<(Id, Value)>,
+        user: &str,
+    ) -> StoreResult<BatchOutcome> {
+        if items.len() > MAX_BULK_INSERT_ITEMS {
+            return Err(StoreError::Validation(format!(
+                "at most {} items per batch",
+                MAX_BULK_INSERT_ITEMS
+            )));
+        }
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_collection_writable(&backend, collection)?;
+        let check = |id: &Id, body: &Value| -> StoreResult<()> {
+            let data = backend.get(collection, id)?;
+            if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
+                return Err(StoreError::PermissionDenied);
+            }
+            check_unlocked(&backend, collection, id, user)?;
+            self.check_foreign_parent(&backend, collection, &data, body)
+        };
+        let failures = items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (id, body))| {
+                check(id, body).err().map(|e| BatchFailure {
+                    index,
+                    error: e.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if !failures.is_empty() {
+            return Ok(BatchOutcome::Rejected { failures });
+        }
+        let meta = ChangeMeta::actor(user);
+        run_batch(|| {
+            items
+                .iter()
+                .map(|(id, body)| {
+                    backend
+                        .update_with_meta(collection, id, body, None, &meta)
+                        .map(|item| item.id)
+                })
+                .collect()
+        })
+    }
+
+    /// Export every item the user owns in the namespace as JSONL, one `{"collection": .., ..item}` per line.
+    pub fn export_owned_data(&self, namespace: &str, user: &str) -> StoreResult<Snapshot> {
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let mut data = Vec::new();
+        for collection in backend.collections() {
+            let mut marker = None;
+            loop {
+                let (items, next_marker) = backend.list_by_owner(collection, user, marker, 100)?;
+                for item in items {
+                    let mut line = serde_json::to_value(item)?;
+                    if let Some(map) = line.as_object_mut() {
+                        map.insert("collection".to_string(), Value::String(collection.to_string()));
+                    }
+                    serde_json::to_writer(&mut data, &line)?;
+                    data.push(b'\n');
+                }
+                if next_marker.is_none() {
+                    break;
+                }
+                marker = next_marker;
+            }
+        }
+        Ok(Snapshot::new(data))
+    }
+
+    /// Export everything the user owns as a gzip JSONL archive, one `{"kind": .., ..}` record per line.
+    ///
+    /// Records are the `profile` with the friend ids, then per accessible namespace the owned `item`s
+    /// with their `attachment`s, content in base64, and the `acl` entries the user granted or received.
+    /// Password and secret key are left out.
+    pub fn export_account(&self, user: &str) -> StoreResult<Snapshot> {
+        use base64::Engine;
+        use std::io::Write;
+
+        let mut archive = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut write_record = |kind: &str, namespace: Option<&str>, record: Value| -> StoreResult<()> {
+            let mut line = serde_json::json!({ "kind": kind });
+            if let Some(namespace) = namespace {
+                line["namespace"] = Value::String(namespace.to_string());
+            }
+            if let (Some(line), Value::Object(record)) = (line.as_object_mut(), record) {
+                line.extend(record);
+            }
+            serde_json::to_writer(&mut archive, &line)?;
+            archive.write_all(b"\n")?;
+            Ok(())
+        };
+
+        let profile = self.get_user(&user.to_string())?;
+        let mut friends = Vec::new();
+        let mut marker = None;
+        loop {
+            let (ids, next_marker) = self.user_manager.list_friends(user, marker, 100)?;
+            friends.extend(ids);
+            if next_marker.is_none() {
+                break;
+            }
+            marker = next_marker;
+        }
+        write_record(
+            "profile",
+            None,
+            serde_json::json!({
+                "user_id": profile.user_id,
+                "username": profile.username,
+                "avatar_url": profile.avatar_url,
+                "email": profile.email,
+                "public_key": base64::engine::general_purpose::STANDARD.encode(&profile.public_key),
+                "friends": friends,
+            }),
+        )?;
+
+        for namespace in self.accessible_namespaces(user)? {
+            let backend = self.data_manager.backend_for(&namespace)?;
+            for collection in backend.collections() {
+                let mut marker = None;
+                loop {
+                    let (items, next_marker) = backend.list_by_owner(collection, user, marker, 100)?;
+                    for item in items {
+                        let id = item.id.clone();
+                        let mut record = serde_json::to_value(item)?;
+                        record["collection"] = Value::String(collection.to_string());
+                        write_record("item", Some(&namespace), record)?;
+                        for attachment in backend.list_attachments(collection, &id)? {
+                            let content = backend.get_attachment(collection, &id, &attachment.id)?;
+                            let mut record = serde_json::to_value(content)?;
+                            record["collection"] = Value::String(collection.to_string());
+                            record["data_id"] = Value::String(id.clone());
+                            write_record("attachment", Some(&namespace), record)?;
+                        }
+                    }
+                    if next_marker.is_none() {
+                        break;
+                    }
+                    marker = next_marker;
+                }
+            }
+            for direction in [AclDirection::Granted, AclDirection::Received] {
+                let mut marker = None;
+                loop {
+                    let (entries, next_marker) = backend.list_acls(direction, user, None, marker, 100)?;
+                    for entry in entries {
+                        write_record("acl", Some(&namespace), serde_json::to_value(entry)?)?;
+                    }
+                    if next_marker.is_none() {
+                        break;
+                    }
+                    marker = next_marker;
+                }
+            }
+        }
+        Ok(Snapshot::new(archive.finish()?))
+    }
+
+    /// Move a document under another parent of its parent collection.
+    ///
+    /// Needs update permission on the document and append permission on the new parent.
+    pub fn move_item(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: &Id,
+        parent_id: &str,
+        user: &str,
+    ) -> StoreResult<DataItem> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let Some((parent_collection, _field)) = backend.parent_collection(collection) else {
+            return Err(StoreError::Validation(format!(
+                "collection `{}` has no parent collection",
+                collection
+            )));
+        };
+        check_collection_writable(&backend, collection)?;
+        let data = backend.get(collection, id)?;
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        check_unlocked(&backend, collection, id, user)?;
+        let (parent_namespace, parent_backend) = self.parent_backend(&backend, namespace, collection, user)?;
+        let new_parent = parent_backend.get(parent_collection, &parent_id.to_string())?;
+        if !self.check_permission(
+            (parent_namespace, parent_collection),
+            &new_parent,
+            user,
+            ACLMask::APPEND_1_BELOW,
+        )? {
+            return Err(StoreError::PermissionDenied);
+        }
+        backend.move_to_parent_with_meta(collection, id, parent_id, &ChangeMeta::actor(user))
+    }
+
+    /// Delete the document, needs the delete permission on it.
+    ///
+    /// The children in collections with `x-on-parent-delete: "cascade"` are deleted along whoever owns
+    /// them, `"restrict"` ones make it a conflict, and the others are left orphaned.
+    pub fn delete(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<()> {
+        self.delete_with_reason(namespace, collection, id, None, user, None)
+    }
+
+    /// Delete and record why in the change log, the reason shows up in the document history.
+    ///
+    /// With `expected_rev` the delete is a conflict unless the item is still at that revision.
+    pub fn delete_with_reason(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: &Id,
+        expected_rev: Option<i64>,
+        user: &str,
+        reason: Option<&str>,
+    ) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        let meta = change_meta(user, reason)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_collection_writable(&backend, collection)?;
+        let data = backend.get(collection, id)?;
+        // check permission
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::DELETE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        check_unlocked(&backend, collection, id, user)?;
+        backend.delete_with_meta(collection, id, expected_rev, &meta)?;
+        backend.release_lock(collection, id)
+    }
+
+    /// Bring back a soft deleted item, restoring needs the delete permission on it.
+    pub fn restore(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<DataItem> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_collection_writable(&backend, collection)?;
+        if !backend.soft_deletes(collection) {
+            return Err(StoreError::Validation(format!(
+                "collection '{}' does not keep deleted items",
+                collection
+            )));
+        }
+        let data = backend.get_with_deleted(collection, id)?;
+        if data.deleted_at.is_none() {
+            return Err(StoreError::Conflict(format!(
+                "Data {} / {} is not deleted",
+                collection, id
+            )));
+        }
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::DELETE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        backend.restore_with_meta(collection, id, &ChangeMeta::actor(user))
+    }
+
+    /// Changes of the document, newest first, including the ones made before it was deleted.
+    ///
+    /// Needs read permission while the document exists, only its last owner can read the history of a
+    /// deleted document.
+    pub fn history(
+        &self,
+        namespace: &str,
+        collection: &str,
+        id: &Id,
+        user: &str,
+        marker: Option<String>,
+        limit: usize,
+    ) -> StoreResult<(Vec<Change>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        match backend.get(collection, id) {
+            Ok(data) => {
+                if !self.check_permission((namespace, collection), &data, user, ACLMask::READ_ONLY)? {
+                    return Err(StoreError::PermissionDenied);
+                }
+            }
+            Err(StoreError::NotFound(_)) => {
+                let (last, _) = backend.history(collection, id, None, 2)?;
+                let last_owner = last.iter().find_map(|c| match &c.op {
+                    ChangeOp::Upsert { item } => Some(item.owner.as_str()),
+                    _ => None,
+                });
+                if last_owner != Some(user) {
+                    return Err(StoreError::NotFound(format!("History {} / {}", collection, id)));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+        backend.history(collection, id, marker, limit)
+    }
+
+    /// Changes of the namespace committed after `cursor`, oldest first, the ones of data the user can
+    /// read.
+    ///
+    /// A page looks at the next `limit` changes and keeps the visible ones, it can come back empty
+    /// with `has_more` set. The user must be able to read a document as of the change, or as of its
+    /// last state for a delete; acl changes go to the owner and the users they list.
+    ///
+    /// The cursor is `<seq>.<generation>`, the seq of the last change looked at and the tombstone
+    /// prunes done then. Deletes stay in the feed for the `tombstone_retention`, a cursor that has not
+    /// seen tombstones pruned since is a conflict and the client reads the feed again from the start.
+    pub fn changes(
+        &self,
+        namespace: &str,
+        cursor: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<ChangeFeed> {
+        self.change_page(namespace, None, cursor, limit, user)
+    }
+
+    /// Like `changes`, only the changes of the documents directly under `parent_id`, e.g. the posts
+    /// of one repo, so a client following one parent is not handed the whole namespace.
+    ///
+    /// A document moved to another parent is listed with the upsert moving it, or as a delete to a
+    /// user who can not read it under the new parent.
+    ///
+    /// A page looks at the next `limit` changes under the parent, the cursor is shared with `changes`.
+    pub fn changes_under(
+        &self,
+        namespace: &str,
+        parent_id: &str,
+        cursor: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<ChangeFeed> {
+        self.change_page(namespace, Some(parent_id), cursor, limit, user)
+    }
+
+    fn change_page(
+        &self,
+        namespace: &str,
+        parent_id: Option<&str>,
+        cursor: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<ChangeFeed> {
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let generation = backend.tombstone_generation()?;
+        let after = match cursor {
+            Some(cursor) => {
+                let invalid = || StoreError::Validation(format!("invalid change cursor: {}", cursor));
+                // a bare seq is a cursor handed out before the first prune
+                let (seq, cursor_generation) = cursor.split_once('.').unwrap_or((cursor.as_str(), "0"));
+                let seq = seq.parse::<i64>().map_err(|_| invalid())?;
+                let cursor_generation = cursor_generation.parse::<i64>().map_err(|_| invalid())?;
+                check_tombstones_kept(&backend, namespace, cursor_generation, seq)?;
+                seq
+            }
+            None => 0,
+        };
+        let limit = limit.max(1);
+        let (scanned, cursor) = match parent_id {
+            Some(parent_id) => {
+                // read before the scan, so no change committed after it is passed over
+                let head = backend.head_seq()?;
+                let scanned = backend.changes_under(parent_id, after, limit)?;
+                let cursor = match scanned.last() {
+                    Some(last) if scanned.len() == limit => last.seq,
+                    // the changes of the other parents up to the head were passed over as well
+                    last => last.map_or(after, |c| c.seq).max(head),
+                };
+                (scanned, cursor)
+            }
+            None => {
+                let scanned = backend.changes_since(after, limit)?;
+                let cursor = scanned.last().map_or(after, |c| c.seq);
+                (scanned, cursor)
+            }
+        };
+        let mut changes = Vec::new();
+        for mut change in scanned {
+            if self.change_visible(&backend, namespace, &change, user)? {
+                backend.decode_change(&mut change)?;
+                changes.push(change);
+            } else if let (Some(parent_id), ChangeOp::Upsert { item }) = (parent_id, &change.op)
+                && item.parent_id.as_deref() != Some(parent_id)
+                && self
+                    .check_collection_switch(namespace, &change.collection, false)
+                    .is_ok()
+                && self.readable_before(&backend, namespace, &change, user)?
+            {
+                // moved under a parent the user can not read, it only leaves the followed one
+                change.op = ChangeOp::Delete;
+                changes.push(change);
+            }
+        }
+        Ok(ChangeFeed {
+            changes,
+            cursor: format!("{}.{}", cursor, generation),
+            has_more: backend.head_seq()? > cursor,
+        })
+    }
+
+    /// The documents changed after `cursor` as they are now, for a client catching up after being
+    /// offline.
+    ///
+    /// A page looks at the next `limit` changes, a document changed several times among them is
+    /// returned once. Documents deleted since are listed by id; documents the user can not read, or
+    /// no longer reads, are left out. The cursor is opaque and only valid for this namespace; one
+    /// older than tombstones pruned since is a conflict, the client pulls again from the start.
+    pub fn pull_changes(
+        &self,
+        namespace: &str,
+        user: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> StoreResult<PulledChanges> {
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let generation = backend.tombstone_generation()?;
+        let after = match cursor {
+            Some(cursor) => {
+                let cursor = SyncCursor::decode(cursor, namespace)?;
+                check_tombstones_kept(&backend, namespace, cursor.generation, cursor.seq)?;
+                cursor.seq
+            }
+            None => 0,
+        };
+        let scanned = backend.changes_since(after, limit.max(1))?;
+        let cursor = SyncCursor {
+            seq: scanned.last().map_or(after, |c| c.seq),
+            generation,
+        };
+        // the last change of every document, in the order of those
+        let mut latest = HashMap::new();
+        for change in scanned {
+            latest.insert((change.collection.clone(), change.data_id.clone()), change);
+        }
+        let mut latest = latest.into_values().collect::<Vec<_>>();
+        latest.sort_by_key(|c| c.seq);
+        let mut pulled = PulledChanges {
+            items: Vec::new(),
+            deleted: Vec::new(),
+            cursor: cursor.encode(namespace),
+            has_more: backend.head_seq()? > cursor.seq,
+        };
+        for change in latest {
+            if self
+                .check_collection_switch(namespace, &change.collection, false)
+                .is_err()
+            {
+                continue;
+            }
+            if let ChangeOp::Delete = change.op {
+                if self.change_visible(&backend, namespace, &change, user)? {
+                    pulled.deleted.push(DeletedItem {
+                        collection: change.collection,
+                        id: change.data_id,
+                        deleted_at: change.changed_at,
+                        deleted_by: change.meta.actor,
+                    });
+                }
+                continue;
+            }
+            // deleted after this page, the next one lists it
+            let mut item = match backend.get(&change.collection, &change.data_id) {
+                Err(StoreError::NotFound(_)) => continue,
+                item => item?,
+            };
+            if self.readable(namespace, &change.collection, &item, user)? {
+                backend.inline_blobs(&mut item)?;
+                pulled.items.push(item);
+            }
+        }
+        Ok(pulled)
+    }
+
+    /// Apply the writes a client made offline, in one transaction, and tell for each whether it was
+    /// accepted.
+    ///
+    /// Every mutation goes through the same checks as the single write. One the user may not make
+    /// or whose `base_rev` is behind is answered as such and left out, the accepted ones are
+    /// committed together; a storage error rolls back the whole batch.
+    ///
+    /// A conflict never overwrites, it carries the stored document next to the pushed body for the
+    /// client to merge. An update at a `base_rev` of a document deleted since is a conflict as well.
+    pub fn push_changes(&self, namespace: &str, mutations: &[Mutation], user: &str) -> StoreResult<Vec<PushResult>> {
+        if mutations.len() > MAX_BULK_INSERT_ITEMS {
+            return Err(StoreError::Validation(format!(
+                "at most {} items per batch",
+                MAX_BULK_INSERT_ITEMS
+            )));
+        }
+        self.check_namespace_access(namespace, user)?;
+        self.transaction(namespace, |_| {
+            let mut results = Vec::with_capacity(mutations.len());
+            for mutation in mutations {
+                let (collection, id, client, applied) = match mutation {
+                    Mutation::Create { collection, id, body } => (
+                        collection,
+                        id.clone(),
+                        Some(body),
+                        self.insert_with_id(namespace, collection, id.as_deref(), body, user)
+                            .map(|id| (id, Some(1))),
+                    ),
+                    Mutation::Update {
+                        collection,
+                        id,
+                        body,
+                        base_rev,
+                    } => {
+                        let updated = self.update_with_reason(namespace, collection, id, body, *base_rev, user, None);
+                        let applied = match (updated, base_rev) {
+                            // deleted since the client read it
+                            (Err(StoreError::NotFound(_)), Some(base_rev)) => Err(StoreError::Conflict(format!(
+                                "{} / {} was deleted after revision {}",
+                                collection, id, base_rev
+                            ))),
+                            (updated, _) => updated.map(|item| (item.id, Some(item.rev))),
+                        };
+                        (collection, Some(id.clone()), Some(body), applied)
+                    }
+                    Mutation::Delete {
+                        collection,
+                        id,
+                        base_rev,
+                    } => (
+                        collection,
+                        Some(id.clone()),
+                        None,
+                        self.delete_with_reason(namespace, collection, id, *base_rev, user, None)
+                            .map(|_| (id.clone(), None)),
+                    ),
+                };
+                results.push(match applied {
+                    Ok((id, rev)) => PushResult::Accepted { id, rev },
+                    Err(StoreError::Conflict(error)) => {
+                        let server = match &id {
+                            Some(id) => self.current_version(namespace, collection, id, user)?,
+                            None => None,
+                        };
+                        PushResult::Conflict {
+                            id,
+                            error,
+                            server,
+                            client: client.cloned(),
+                        }
+                    }
+                    Err(e @ (StoreError::NotFound(_) | StoreError::Validation(_) | StoreError::PermissionDenied)) => {
+                        PushResult::Rejected {
+                            id,
+                            error: e.to_string(),
+                        }
+                    }
+                    Err(e) => return Err(e),
+                });
+            }
+            Ok(results)
+        })
+    }
+
+    // the document as stored, for a conflicting push to merge with; none when it is gone or the user
+    // can not read it
+    fn current_version(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<Option<DataItem>> {
+        let item = match self.data_manager.backend_for(namespace)?.get(collection, id) {
+            Err(StoreError::NotFound(_)) => return Ok(None),
+            item => item?,
+        };
+        Ok(self.readable(namespace, collection, &item, user)?.then_some(item))
+    }
+
+    // whether the change of the change log is for the user: the document readable as of the change,
+    // or as of its last state for a delete; acl changes go to the owner and the users they list
+    fn change_visible(
+        &self,
+        backend: &SqliteBackend,
+        namespace: &str,
+        change: &Change,
+        user: &str,
+    ) -> StoreResult<bool> {
+        if self
+            .check_collection_switch(namespace, &change.collection, false)
+            .is_err()
+        {
+            return Ok(false);
+        }
+        match &change.op {
+            ChangeOp::Upsert { item } => self.readable(namespace, &change.collection, item, user),
+            ChangeOp::Delete => self.readable_before(backend, namespace, change, user),
+            ChangeOp::Acl { owner, permissions } => Ok(owner == user || permissions.iter().any(|p| p.user_id == user)),
+        }
+    }
+
+    // read permission on the document as it was before the change
+    fn readable_before(
+        &self,
+        backend: &SqliteBackend,
+        namespace: &str,
+        change: &Change,
+        user: &str,
+    ) -> StoreResult<bool> {
+        let (before, _) = backend.history(&change.collection, &change.data_id, Some(change.seq.to_string()), 1)?;
+        match before.first().map(|c| &c.op) {
+            Some(ChangeOp::Upsert { item }) => self.readable(namespace, &change.collection, item, user),
+            _ => Ok(false),
+        }
+    }
+
+    // read permission on a document of the change log, a parent deleted since hides it as it hides
+    // the document
+    fn readable(&self, namespace: &str, collection: &str, item: &DataItem, user: &str) -> StoreResult<bool> {
+        match self.check_permission((namespace, collection), item, user, ACLMask::READ_ONLY) {
+            Err(StoreError::NotFound(_)) => Ok(false),
+            readable => readable,
+        }
+    }
+
+    /// Take an advisory lock on the document for `ttl`, refreshing it when the user already holds it.
+    ///
+    /// While locked, other users can still read the document but not update, move or delete it.
+    pub fn lock(&self, namespace: &str, collection: &str, id: &Id, user: &str, ttl: Duration) -> StoreResult<ItemLock> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        if ttl.is_zero() || ttl > MAX_LOCK_TTL {
+            return Err(StoreError::Validation(format!(
+                "lock ttl must be between 1s and {}s",
+                MAX_LOCK_TTL.as_secs()
+            )));
+        }
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        let data = backend.get(collection, id)?;
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        let ttl = chrono::Duration::from_std(ttl).map_err(|e| StoreError::Validation(e.to_string()))?;
+        backend.acquire_lock(collection, id, user, ttl)
+    }
+
+    /// Release the lock, only its holder or the owner of the document can release it.
+    pub fn unlock(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        let data = backend.get(collection, id)?;
+        match backend.get_lock(collection, id)? {
+            Some(lock) if lock.holder != user && data.owner != user => Err(StoreError::PermissionDenied),
+            Some(_) => backend.release_lock(collection, id),
+            None => Ok(()),
+        }
+    }
+
+    /// 1. if the data owner is the user, allow
+    /// 2. else check directly acl
+    /// 3. else check parent data recursively
+    fn check_permission(
+        &self,
+        (namespace, collection): (&str, &str),
+        data: &DataItem,
+        user: &str,
+        needed_mask: ACLMask,
+    ) -> StoreResult<bool> {
+        self.walk_permission((namespace, collection), data, user, needed_mask, None)
+    }
+
+    // owner, then direct ACL, then the parent chain with the append depth upgraded,
+    // every visited document is recorded into `trace` when given
+    fn walk_permission(
+        &self,
+        (namespace, collection): (&str, &str),
+        data: &DataItem,
+        user: &str,
+        needed_mask: ACLMask,
+        mut trace: Option<&mut Vec<PermissionStep>>,
+    ) -> StoreResult<bool> {
+        let mut step = trace.as_ref().map(|_| PermissionStep {
+            collection: collection.to_string(),
+            data_id: data.id.clone(),
+            owner: data.owner.clone(),
+            required: needed_mask.names().into_iter().map(String::from).collect(),
+            owner_match: data.owner == user,
+            access_level: None,
+            acl_match: false,
+        });
+        let mut record = |step: Option<PermissionStep>| {
+            if let (Some(trace), Some(step)) = (trace.as_deref_mut(), step) {
+                trace.push(step);
+            }
+        };
+        // check owner
+        if data.owner == user {
+            record(step);
+            return Ok(true);
+        }
+        // the data of a deactivated account is hidden, whatever it granted
+        if self.is_deactivated(&data.owner)? {
+            record(step);
+            return Ok(false);
+        }
+        // check ACL
+        if let Ok(acl) = self.root_get_data_acl(namespace, collection, &data.id) {
+            for perm in acl.permissions {
+                if perm.user != user {
+                    continue;
+                }
+                let acl_mask: ACLMask = perm.access_level.clone().into();
+                let acl_match = acl_mask.contains(needed_mask);
+                if let Some(step) = step.as_mut() {
+                    step.access_level = Some(perm.access_level);
+                    step.acl_match = acl_match;
+                }
+                if acl_match {
+                    record(step);
+                    return Ok(true);
+                }
+            }
+        }
+        record(step);
+        // check parent data recursively
+        let backend = self.data_manager.backend_for(namespace)?;
+        if let Some(parent_id) = data.parent_id.as_ref()
+            && let Some((parent_collection, _field)) = backend.parent_collection(collection)
+            && let Some(parent_needed_mask) = needed_mask.upgrade_for_parent()
+        {
+            let (parent_namespace, parent_backend) = match self.parent_backend(&backend, namespace, collection, user) {
+                Err(StoreError::PermissionDenied) => return Ok(false),
+                parent => parent?,
+            };
+            let parent_data = parent_backend.get(parent_collection, parent_id)?;
+            return self.walk_permission(
+                (parent_namespace, parent_collection),
+                &parent_data,
+                user,
+                parent_needed_mask,
+                trace,
+            );
+        }
+        Ok(false)
+    }
+
+    pub fn get_data_backend(&self, namespace: &str) -> StoreResult<Arc<crate::backend::SqliteBackend>> {
+        self.data_manager.backend_for(namespace)
+    }
+}
+
+/// Saved view operations, views are private to the user who saved them
+impl Store {
+    /// Save a named filter and sort of the collection, replacing the view of the same name.
+    pub fn save_view(
+        &self,
+        namespace: &str,
+        collection: &str,
+        name: &str,
+        filter: Option<FilterExpr>,
+        sort: Option<SortSpec>,
+        user: &str,
+    ) -> StoreResult<SavedView> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_VIEW_NAME_CHARS {
+            return Err(StoreError::Validation(format!(
+                "view name must have between 1 and {} characters",
+                MAX_VIEW_NAME_CHARS
+            )));
+        }
+        // rejected now rather than each time the view runs
+        if let Some(filter) = &filter {
+            filter.compile()?;
+        }
+        if let Some(sort) = &sort {
+            sort.compile()?;
+        }
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        if !backend.collections().contains(&collection) {
+            return Err(StoreError::NotFound(format!("Collection {}/{}", namespace, collection)));
+        }
+        let replaces = match backend.get_view(collection, user, name) {
+            Ok(_) => true,
+            Err(StoreError::NotFound(_)) => false,
+            Err(e) => return Err(e),
+        };
+        if !replaces && backend.count_views(collection, user)? >= MAX_VIEWS_PER_COLLECTION {
+            return Err(StoreError::QuotaExceeded(format!(
+                "at most {} views per collection",
+                MAX_VIEWS_PER_COLLECTION
+            )));
+        }
+        let view = SavedView {
+            name: name.to_string(),
+            filter,
+            sort,
+            updated_at: chrono::Utc::now(),
+        };
+        backend.save_view(collection, user, &view)?;
+        Ok(view)
+    }
+
+    pub fn get_view(&self, namespace: &str, collection: &str, name: &str, user: &str) -> StoreResult<SavedView> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        self.data_manager
+            .backend_for(namespace)?
+            .get_view(collection, user, name)
+    }
+
+    pub fn list_views(&self, namespace: &str, collection: &str, user: &str) -> StoreResult<Vec<SavedView>> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        self.data_manager.backend_for(namespace)?.list_views(collection, user)
+    }
+
+    pub fn delete_view(&self, namespace: &str, collection: &str, name: &str, user: &str) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        backend.delete_view(collection, user, name)
+    }
+
+    /// Run a saved view over the user's documents, or with `parent_id` over the children of a parent
+    /// the user can read, like `query` does with a filter.
+    ///
+    /// Sorted views page by position, the marker is then the offset of the next page.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run_view(
+        &self,
+        namespace: &str,
+        collection: &str,
+        name: &str,
+        parent_id: Option<&str>,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<DataItem>, Option<String>)> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let view = backend.get_view(collection, user, name)?;
+        let scope = match parent_id {
+            Some(parent_id) => {
+                self.check_parent_readable(&backend, (namespace, collection), parent_id, user)?;
+                QueryScope::Parent(parent_id)
+            }
+            None => QueryScope::Owner(user),
+        };
+        backend.query_sorted(
+            collection,
+            scope,
+            view.filter.as_ref(),
+            view.sort.as_ref(),
+            marker,
+            limit,
+        )
+    }
+}
+
+/// Attachment operations, attachments share the ACL of their data item
+impl Store {
+    /// Bind a file to the data item, attaching needs the update permission on the item.
+    pub fn add_attachment(
+        &self,
+        (namespace, collection): (&str, &str),
+        id: &Id,
+        name: &str,
+        content_type: &str,
+        content: &[u8],
+        user: &str,
+    ) -> StoreResult<Attachment> {
+        if content.len() > MAX_ATTACHMENT_SIZE {
+            return Err(StoreError::Validation(format!(
+                "attachment exceeds {} bytes",
+                MAX_ATTACHMENT_SIZE
+            )));
+        }
+        self.check_attachable((namespace, collection), id, name, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let attachment = Attachment {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            content_type: content_type.to_string(),
+            size: content.len() as u64,
+            sha256: hex_sha256(content),
+            owner: user.to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        backend.add_attachment(collection, id, &attachment, content)?;
+        Ok(attachment)
+    }
+
+    /// Check the user could attach a file named `name` to the data item now, without storing anything.
+    pub fn check_attachable(
+        &self,
+        (namespace, collection): (&str, &str),
+        id: &Id,
+        name: &str,
+        user: &str,
+    ) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        if name.trim().is_empty() {
+            return Err(StoreError::Validation("attachment name is empty".to_string()));
+        }
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_collection_writable(&backend, collection)?;
+        let data = backend.get(collection, id)?;
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        check_unlocked(&backend, collection, id, user)
+    }
+
+    pub fn list_attachments(
+        &self,
+        (namespace, collection): (&str, &str),
+        id: &Id,
+        user: &str,
+    ) -> StoreResult<Vec<Attachment>> {
+        Ok(self.get(namespace, collection, id, user)?.attachments)
+    }
+
+    pub fn get_attachment(
+        &self,
+        (namespace, collection): (&str, &str),
+        id: &Id,
+        attachment_id: &str,
+        user: &str,
+    ) -> StoreResult<AttachmentContent> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let data = backend.get(collection, id)?;
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::READ_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        backend.get_attachment(collection, id, attachment_id)
+    }
+
+    /// Remove a file from the data item, needs the update permission on the item.
+    pub fn delete_attachment(
+        &self,
+        (namespace, collection): (&str, &str),
+        id: &Id,
+        attachment_id: &str,
+        user: &str,
+    ) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_collection_writable(&backend, collection)?;
+        let data = backend.get(collection, id)?;
+        if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
+            return Err(StoreError::PermissionDenied);
+        }
+        check_unlocked(&backend, collection, id, user)?;
+        backend.delete_attachment(collection, id, attachment_id)
+    }
+}
+
+/// Development tooling, no user permission check, only exposed to admin
+impl Store {
+    /// Insert `count` documents generated from the collection schema, owned by `owner`.
+    ///
+    /// Children are spread over random parents the owner already has. A document the store rejects,
+    /// e.g. for a `pattern` or a unique clash, is generated again a few times before counting as failed.
+    pub fn generate_fake_data(
+        &self,
+        namespace: &str,
+        collection: &str,
+        count: usize,
+        owner: &str,
+    ) -> StoreResult<GeneratedData> {
+        if count > MAX_BULK_INSERT_ITEMS {
+            return Err(StoreError::Validation(format!(
+                "at most {} documents per call",
+                MAX_BULK_INSERT_ITEMS
+            )));
+        }
+        let backend = self.data_manager.backend_for(namespace)?;
+        let Some(schema) = backend
+            .schema_resources()
+            .get(collection)
+            .filter(|_| backend.collections().contains(&collection))
+        else {
+            return Err(StoreError::NotFound(format!("Collection {}/{}", namespace, collection)));
+        };
+        let parents = match backend.parent_collection(collection) {
+            Some((parent_collection, field)) => {
+                let (_, parent_backend) = self.parent_backend(&backend, namespace, collection, owner)?;
+                let (parents, _) =
+                    parent_backend.list_by_owner(parent_collection, owner, None, MAX_BULK_INSERT_ITEMS)?;
+                if parents.is_empty() {
+                    return Err(StoreError::Validation(format!(
+                        "no `{}` of the owner to attach the `{}` documents to",
+                        parent_collection, collection
+                    )));
+                }
+                Some((field.to_string(), parents.into_iter().map(|p| p.id).collect::<Vec<_>>()))
+            }
+            None => None,
+        };
+
+        let generator = FakeGenerator::new(backend.schema_resources());
+        let mut rng = rand::rng();
+        let mut report = GeneratedData {
+            inserted: Vec::with_capacity(count),
+            failed: 0,
+            first_error: None,
+        };
+        for _ in 0..count {
+            let mut last_error = None;
+            for _ in 0..MAX_FAKE_ATTEMPTS {
+                let mut body = generator.generate(schema, &mut rng);
+                if let Some((field, parent_ids)) = &parents
+                    && let Some(map) = body.as_object_mut()
+                {
+                    let parent_id = &parent_ids[rng.random_range(0..parent_ids.len())];
+                    map.insert(field.clone(), Value::String(parent_id.clone()));
+                }
+                match self.insert(namespace, collection, &body, owner) {
+                    Ok(id) => {
+                        report.inserted.push(id);
+                        last_error = None;
+                        break;
+                    }
+                    Err(e @ StoreError::Validation(_)) => last_error = Some(e),
+                    Err(e) => return Err(e),
+                }
+            }
+            if let Some(e) = last_error {
+                report.failed += 1;
+                report.first_error.get_or_insert_with(|| e.to_string());
+            }
+        }
+        tracing::info!(
+            "generated {} fake documents in {}/{}, {} failed",
+            report.inserted.len(),
+            namespace,
+            collection,
+            report.failed
+        );
+        Ok(report)
+    }
+}
+
+/// Usage operations, users only see their own items
+impl Store {
+    /// Items and storage bytes of the user in every collection of the namespace.
+    pub fn namespace_usage(&self, namespace: &str, user: &str) -> StoreResult<NamespaceUsage> {
+        self.check_namespace_access(namespace, user)?;
+        let collections = self.data_manager.backend_for(namespace)?.usage_by_owner(user)?;
+        Ok(NamespaceUsage::new(namespace, collections))
+    }
+
+    /// Count, time range and per-parent counts of the user's items in a collection.
+    pub fn collection_stats(&self, namespace: &str, collection: &str, user: &str) -> StoreResult<CollectionStats> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        backend.stats_by_owner(collection, user)
+    }
+
+    /// Read count and last read time of an item, only for its owner and in `x-access-stats` collections.
+    pub fn access_stats(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<AccessStats> {
+        self.check_collection_switch(namespace, collection, false)?;
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let item = backend.get(collection, id)?;
+        if item.owner != user {
+            return Err(StoreError::PermissionDenied);
+        }
+        if !backend.records_access_stats(collection) {
+            return Err(StoreError::Validation(format!(
+                "collection `{}` does not record access statistics",
+                collection
+            )));
+        }
+        backend.access_stats(collection, id)
+    }
+}
+
+/// Maintenance operations, no user permission check, only exposed to admin
+impl Store {
+    /// Find the children whose parent has been deleted, in every child collection of the namespace.
+    pub fn find_orphans(&self, namespace: &str) -> StoreResult<Vec<Orphan>> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        let mut orphans = Vec::new();
+        for collection in backend.child_collections() {
+            orphans.extend(backend.list_orphans(collection)?);
+        }
+        Ok(orphans)
+    }
+
+    /// Consistent SQLite copy of the whole namespace database.
+    pub fn snapshot_namespace(&self, namespace: &str) -> StoreResult<Snapshot> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        let path = std::env::temp_dir().join(format!("syncstore-{}-{}.db", namespace, uuid::Uuid::new_v4()));
+        let result = backend.snapshot_to(&path).and_then(|_| Ok(std::fs::read(&path)?));
+        // remove the temp file whether the snapshot succeeded or not
+        let _ = std::fs::remove_file(&path);
+        let snapshot = Snapshot::new(result?);
+        tracing::info!(
+            "snapshot of namespace {}: {} bytes, sha256 {}",
+            namespace,
+            snapshot.data.len(),
+            snapshot.sha256
+        );
+        Ok(snapshot)
+    }
+
+    /// Consistent copy of the namespace database at `dest`, taken with the SQLite online backup API
+    /// while the service keeps running. `dest` must not exist yet.
+    pub fn backup(&self, namespace: &str, dest: impl AsRef<Path>) -> StoreResult<BackupInfo> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        let dest = dest.as_ref();
+        backend.backup_to(dest)?;
+        let info = BackupInfo {
+            namespace: namespace.to_string(),
+            path: dest.to_string_lossy().into_owned(),
+            bytes: std::fs::metadata(dest)?.len(),
+            completed_at: chrono::Utc::now(),
+        };
+        tracing::info!(
+            "backup of namespace {}: {} bytes to {}",
+            namespace,
+            info.bytes,
+            info.path
+        );
+        Ok(info)
+    }
+
+    /// Check the integrity of the namespace database, VACUUM it to give back the space of deleted
+    /// rows and ANALYZE it for the query planner. The writes wait while the database is rebuilt.
+    pub fn vacuum_namespace(&self, namespace: &str) -> StoreResult<VacuumReport> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        let (size_before, size_after, integrity_errors) = backend.vacuum()?;
+        if !integrity_errors.is_empty() {
+            tracing::error!(
+                "integrity check of namespace {} failed: {:?}",
+                namespace,
+                integrity_errors
+            );
+        }
+        Ok(VacuumReport {
+            namespace: namespace.to_string(),
+            size_before,
+            size_after,
+            reclaimed: size_before.saturating_sub(size_after),
+            integrity_errors,
+        })
+    }
+
+    /// Find the orphans of the namespace and resolve them according to `policy`.
+    pub fn cleanup_orphans(&self, namespace: &str, policy: &OrphanPolicy) -> StoreResult<OrphanReport> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        if *policy != OrphanPolicy::Report {
+            self.check_writable()?;
+            check_backend_writable(&backend)?;
+        }
+        match policy {
+            OrphanPolicy::Report => Ok(OrphanReport {
+                orphans: self.find_orphans(namespace)?,
+                resolved: 0,
+            }),
+            OrphanPolicy::Delete => {
+                let mut report = OrphanReport {
+                    orphans: Vec::new(),
+                    resolved: 0,
+                };
+                // deleting orphans might orphan their own children, repeat until nothing is left
+                loop {
+                    let orphans = self.find_orphans(namespace)?;
+                    if orphans.is_empty() {
+                        break;
+                    }
+                    for orphan in &orphans {
+                        backend.delete(&orphan.collection, &orphan.id)?;
+                        backend.delete_acls_by_data_id(&orphan.collection, &orphan.id)?;
+                    }
+                    report.resolved += orphans.len();
+                    report.orphans.extend(orphans);
+                }
+                tracing::info!("deleted {} orphans in namespace {}", report.resolved, namespace);
+                Ok(report)
+            }
+            OrphanPolicy::Reparent { collection, parent_id } => {
+                let Some((parent_collection, _field)) = backend.parent_collection(collection) else {
+                    return Err(StoreError::Validation(format!(
+                        "collection `{}` has no parent collection",
+                        collection
+                    )));
+                };
+                let parent_namespace = backend.parent_namespace(collection).unwrap_or(namespace);
+                self.data_manager
+                    .backend_for(parent_namespace)?
+                    .get(parent_collection, parent_id)?;
+                let orphans = self.find_orphans(namespace)?;
+                let mut resolved = 0;
+                for orphan in orphans.iter().filter(|o| &o.collection == collection) {
+                    backend.move_to_parent(collection, &orphan.id, parent_id)?;
+                    resolved += 1;
+                }
+                tracing::info!(
+                    "re-parented {} orphans of {}/{} under {}",
+                    resolved,
+                    namespace,
+                    collection,
+                    parent_id
+                );
+                Ok(OrphanReport { orphans, resolved })
+            }
+        }
+    }
+}
+
+/// Replication, no user permission check, only exposed to admin
+///
+/// Besides the data namespaces, the inner user and notification databases are replicated under
+/// the `__users` and `__notifications` pseudo namespaces.
+impl Store {
+    pub fn replication_namespaces(&self) -> Vec<String> {
+        let mut namespaces = self.data_manager.namespaces();
+        namespaces.push(USERS_NAMESPACE.to_string());
+        namespaces.push(NOTIFICATIONS_NAMESPACE.to_string());
+        namespaces
+    }
+
+    /// Health of every namespace database, the inner ones included.
+    pub fn health(&self) -> Vec<NamespaceHealth> {
+        self.replication_namespaces()
+            .into_iter()
+            .filter_map(|namespace| Some(self.change_log_backend(&namespace).ok()?.health(&namespace)))
+            .collect()
+    }
+
+    fn change_log_backend(&self, namespace: &str) -> StoreResult<Arc<SqliteBackend>> {
+        match namespace {
+            USERS_NAMESPACE => Ok(self.user_manager.backend()),
+            NOTIFICATIONS_NAMESPACE => Ok(self.notification_manager.backend()),
+            _ => self.data_manager.backend_for(namespace),
+        }
+    }
+
+    /// Apply the changes pushed by a primary, in order.
+    pub fn apply_replication(&self, namespace: &str, batch: &ReplicationBatch) -> StoreResult<ReplicationAck> {
+        self.check_writable()?;
+        let backend = self.change_log_backend(namespace)?;
+        check_backend_writable(&backend)?;
+        for change in &batch.changes {
+            backend.apply_change(change)?;
+        }
+        Ok(ReplicationAck {
+            applied: batch.changes.len(),
+            last_seq: batch.changes.last().map(|c| c.seq),
+        })
+    }
+
+    /// Push the changes of the namespace not yet acknowledged by `target`, until caught up.
+    ///
+    /// The cursor only moves forward once a batch is acknowledged, a failed push is retried from the
+    /// same changes on the next run. Returns how many changes were pushed.
+    pub fn replicate_namespace(
+        &self,
+        namespace: &str,
+        target: &dyn ReplicationTarget,
+        batch_size: usize,
+    ) -> StoreResult<usize> {
+        let pushed = self.dispatch_outbox(namespace, &ReplicationConsumer(target), batch_size)?;
+        if pushed > 0 {
+            tracing::info!("replicated {} changes of {} to {}", pushed, namespace, target.name());
+        }
+        Ok(pushed)
+    }
+
+    /// Hand the changes of the namespace not yet accepted by `consumer` over in batches, until caught up.
+    ///
+    /// Returns how many changes were handed over, see `OutboxConsumer` for the delivery guarantees.
+    pub fn dispatch_outbox(
+        &self,
+        namespace: &str,
+        consumer: &dyn OutboxConsumer,
+        batch_size: usize,
+    ) -> StoreResult<usize> {
+        let backend = self.change_log_backend(namespace)?;
+        let mut cursor = backend.replication_cursor(consumer.name())?;
+        let mut dispatched = 0;
+        loop {
+            let changes = backend.changes_since(cursor, batch_size.max(1))?;
+            let Some(last_seq) = changes.last().map(|c| c.seq) else {
+                break;
+            };
+            consumer.consume(namespace, &changes)?;
+            dispatched += changes.len();
+            cursor = last_seq;
+            backend.set_replication_cursor(consumer.name(), cursor)?;
+        }
+        Ok(dispatched)
+    }
+
+    /// Replication lag of every namespace, empty when replication is not configured.
+    pub fn replication_status(&self) -> StoreResult<Vec<ReplicationStatus>> {
+        let target = self
+            .replication_target
+            .read()
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .clone();
+        let Some(target) = target else {
+            return Ok(Vec::new());
+        };
+        self.replication_namespaces()
+            .into_iter()
+            .map(|namespace| {
+                let backend = self.change_log_backend(&namespace)?;
+                Ok(ReplicationStatus {
+                    head_seq: backend.head_seq()?,
+                    replicated_seq: backend.replication_cursor(target.name())?,
+                    namespace,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Service keys, no user permission check, only exposed to admin
+///
+/// A key lets a backend job reach the granted collections of one namespace without a user account.
+/// Each key acts as its own service identity, a user without a password login that owns what the
+/// job inserts and goes through the namespace policy and the ACLs like any user.
+impl Store {
+    /// Create a key, the returned one is the only time the key itself is shown.
+    pub fn create_service_key(
+        &self,
+        namespace: &str,
+        name: &str,
+        grants: BTreeMap<String, ServiceAccess>,
+    ) -> StoreResult<ServiceKey> {
+        use base64::Engine;
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        if name.is_empty() || name.chars().count() > MAX_SERVICE_KEY_NAME_CHARS {
+            return Err(StoreError::Validation(format!(
+                "service key name must be 1 to {} characters",
+                MAX_SERVICE_KEY_NAME_CHARS
+            )));
+        }
+        if backend.list_service_keys()?.iter().any(|key| key.name == name) {
+            return Err(StoreError::Conflict(format!("service key `{}` already exists", name)));
+        }
+        check_service_grants(&backend, namespace, &grants)?;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        // the identity never logs in with a password, nobody knows this one
+        let username = format!("svc:{}:{}", namespace, id);
+        let password: [u8; 32] = rand::random();
+        let password = base64::engine::general_purpose::STANDARD.encode(password);
+        self.user_manager.create_user(&username, &password)?;
+        let user_id = self
+            .user_manager
+            .validate_user(&username, &password)?
+            .ok_or_else(|| StoreError::Backend(format!("service identity {} not created", username)))?;
+
+        let secret: [u8; 32] = rand::random();
+        let secret = format!(
+            "ssk_{}",
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret)
+        );
+        let key = ServiceKey {
+            id,
+            name: name.to_string(),
+            user_id,
+            grants,
+            created_at: chrono::Utc::now(),
+            key: None,
+        };
+        backend.add_service_key(&key, &hex_sha256(secret.as_bytes()))?;
+        tracing::info!("service key {} of {} created for {}", key.id, namespace, key.user_id);
+        Ok(ServiceKey {
+            key: Some(secret),
+            ..key
+        })
+    }
+
+    pub fn list_service_keys(&self, namespace: &str) -> StoreResult<Vec<ServiceKey>> {
+        self.data_manager.backend_for(namespace)?.list_service_keys()
+    }
+
+    /// Replace the grants of a key, the requests made with it from now on go by the new ones.
+    pub fn set_service_key_grants(
+        &self,
+        namespace: &str,
+        id: &str,
+        grants: BTreeMap<String, ServiceAccess>,
+    ) -> StoreResult<ServiceKey> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        check_service_grants(&backend, namespace, &grants)?;
+        backend.set_service_key_grants(id, &grants)?;
+        backend.get_service_key(id)
+    }
+
+    /// Revoke a key, the documents of its service identity stay.
+    pub fn revoke_service_key(&self, namespace: &str, id: &str) -> StoreResult<()> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        backend.delete_service_key(id)?;
+        tracing::info!("service key {} of {} revoked", id, namespace);
+        Ok(())
+    }
+
+    /// The key presented on a request to the namespace, none when it is unknown or revoked.
+    pub fn validate_service_key(&self, namespace: &str, key: &str) -> StoreResult<Option<ServiceKey>> {
+        self.data_manager
+            .backend_for(namespace)?
+            .find_service_key(&hex_sha256(key.as_bytes()))
+    }
+}
+
+fn check_service_grants(
+    backend: &SqliteBackend,
+    namespace: &str,
+    grants: &BTreeMap<String, ServiceAccess>,
+) -> StoreResult<()> {
+    if let Some(unknown) = grants.keys().find(|c| !backend.collections().contains(&c.as_str())) {
+        return Err(StoreError::NotFound(format!("Collection {}/{}", namespace, unknown)));
+    }
+    Ok(())
+}
+
+/// Webhooks, no user permission check, only exposed to admin
+///
+/// Every webhook belongs to a namespace and receives the changes of its change log made after it was
+/// registered. Each change is queued as its own delivery and retried with exponential backoff, a
+/// delivery failing `max_attempts` times is moved to the dead letters until it is replayed.
+impl Store {
+    pub fn create_webhook(
+        &self,
+        namespace: &str,
+        url: &str,
+        collections: Vec<String>,
+        events: Vec<WebhookEventKind>,
+        filter: Option<FilterExpr>,
+        secret: Option<String>,
+    ) -> StoreResult<Webhook> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(StoreError::Validation(format!("webhook url `{}` must be http(s)", url)));
+        }
+        if let Some(unknown) = collections
+            .iter()
+            .find(|c| !backend.collections().contains(&c.as_str()))
+        {
+            return Err(StoreError::NotFound(format!("Collection {}/{}", namespace, unknown)));
+        }
+        if let Some(filter) = &filter {
+            filter.compile()?;
+        }
+        let secret = match secret {
+            Some(secret) if secret.len() < 16 => {
+                return Err(StoreError::Validation(
+                    "webhook secret must be at least 16 characters".to_string(),
+                ));
+            }
+            Some(secret) => secret,
+            None => {
+                let bytes: [u8; 32] = rand::random();
+                bytes.iter().map(|b| format!("{:02x}", b)).collect()
+            }
+        };
+        let webhook = Webhook {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            collections,
+            events,
+            filter,
+            created_at: chrono::Utc::now(),
+            secret: Some(secret),
+        };
+        backend.add_webhook(&webhook)?;
+        tracing::info!("webhook {} of {} registered for {}", webhook.id, namespace, webhook.url);
+        Ok(webhook)
+    }
+
+    /// Registered webhooks of the namespace, without their secrets.
+    pub fn list_webhooks(&self, namespace: &str) -> StoreResult<Vec<Webhook>> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        Ok(backend
+            .list_webhooks()?
+            .into_iter()
+            .map(|webhook| Webhook {
+                secret: None,
+                ..webhook
+            })
+            .collect())
     }
 
-    fn collect_all_accessible_ids(
+    pub fn delete_webhook(&self, namespace: &str, id: &str) -> StoreResult<()> {
+        self.data_manager.backend_for(namespace)?.delete_webhook(id)
+    }
+
+    pub fn dead_webhook_deliveries(&self, namespace: &str) -> StoreResult<Vec<WebhookDelivery>> {
+        self.data_manager.backend_for(namespace)?.dead_webhook_deliveries()
+    }
+
+    /// Queue a dead delivery again, it is sent on the next run.
+    pub fn replay_webhook_delivery(&self, namespace: &str, id: i64) -> StoreResult<WebhookDelivery> {
+        self.data_manager.backend_for(namespace)?.replay_webhook_delivery(id)
+    }
+
+    /// Queue the new changes for every webhook of the namespace, then send the due deliveries.
+    ///
+    /// Failed attempts are recorded on the delivery and never fail the run. Returns how many
+    /// deliveries were sent successfully.
+    pub fn deliver_webhooks(
         &self,
         namespace: &str,
-        collection: &str,
-        user: &str,
-        visited: &mut HashSet<(String, String)>,
-        cache: &mut HashMap<(String, String), DataItem>,
-    ) -> StoreResult<BTreeSet<String>> {
-        let key = (namespace.to_string(), collection.to_string());
-        if !visited.insert(key.clone()) {
-            return Ok(BTreeSet::new());
+        sender: &dyn WebhookSender,
+        config: &Webhooks,
+    ) -> StoreResult<usize> {
+        let backend = self.data_manager.backend_for(namespace)?;
+        let webhooks = backend.list_webhooks()?;
+        if webhooks.is_empty() {
+            return Ok(0);
         }
-        let result = (|| -> StoreResult<BTreeSet<String>> {
-            let backend = self.data_manager.backend_for(namespace)?;
-            let mut ids = BTreeSet::new();
-            let collection_key = collection.to_string();
-            let owner_items = self.collect_all_owner_items(&backend, collection, user)?;
-            for item in owner_items {
-                let item_id = item.id.clone();
-                ids.insert(item_id.clone());
-                cache.insert((collection_key.clone(), item_id), item);
+        for webhook in &webhooks {
+            let mut cursor = backend.webhook_cursor(&webhook.id)?;
+            loop {
+                let changes = backend.changes_since(cursor, 500)?;
+                let Some(last_seq) = changes.last().map(|c| c.seq) else {
+                    break;
+                };
+                let mut deliveries = Vec::new();
+                for change in changes {
+                    if !webhook.wants(&change) {
+                        continue;
+                    }
+                    if let (Some(filter), ChangeOp::Upsert { item }) = (&webhook.filter, &change.op)
+                        && !backend.item_matches(item, filter)?
+                    {
+                        continue;
+                    }
+                    let seq = change.seq;
+                    let event = WebhookEvent {
+                        namespace: namespace.to_string(),
+                        change,
+                    };
+                    deliveries.push((seq, serde_json::to_string(&event)?));
+                }
+                backend.enqueue_webhook_deliveries(&webhook.id, &deliveries, last_seq)?;
+                cursor = last_seq;
             }
-            for perm in backend.get_user_permissions(collection, user)? {
-                ids.insert(perm.data_id);
+        }
+
+        let mut delivered = 0;
+        loop {
+            let due = backend.due_webhook_deliveries(chrono::Utc::now(), 100)?;
+            if due.is_empty() {
+                break;
             }
-            if let Some((parent_collection, _)) = backend.parent_collection(collection) {
-                let parent_ids = self.collect_all_accessible_ids(namespace, parent_collection, user, visited, cache)?;
-                for parent_id in parent_ids {
-                    let children = self.collect_all_children_items(&backend, collection, &parent_id)?;
-                    for child in children {
-                        let child_id = child.id.clone();
-                        ids.insert(child_id.clone());
-                        cache.insert((collection_key.clone(), child_id), child);
+            for delivery in due {
+                let Some(webhook) = webhooks.iter().find(|w| w.id == delivery.webhook_id) else {
+                    // removed while we were sending
+                    continue;
+                };
+                let secret = webhook.secret.as_deref().unwrap_or_default();
+                let request = WebhookRequest::new(&webhook.url, delivery.id, secret, delivery.payload);
+                match sender.send(&request) {
+                    Ok(()) => {
+                        backend.complete_webhook_delivery(delivery.id)?;
+                        delivered += 1;
+                    }
+                    Err(e) => {
+                        let attempts = delivery.attempts + 1;
+                        let next_attempt_at = config
+                            .retry_delay(attempts)
+                            .and_then(|delay| chrono::Duration::from_std(delay).ok())
+                            .map(|delay| chrono::Utc::now() + delay);
+                        if next_attempt_at.is_none() {
+                            tracing::warn!(
+                                "webhook delivery {} to {} dead after {} attempts: {}",
+                                delivery.id,
+                                webhook.url,
+                                attempts,
+                                e
+                            );
+                        }
+                        backend.fail_webhook_delivery(delivery.id, &e.to_string(), next_attempt_at)?;
                     }
                 }
             }
-            Ok(ids)
-        })();
-        visited.remove(&key);
-        result
+        }
+        Ok(delivered)
     }
+}
 
-    pub fn get(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<DataItem> {
-        let backend = self.data_manager.backend_for(namespace)?;
-        let data = backend.get(collection, id)?;
-        // check permission
-        if !self.check_permission((namespace, collection), &data, user, ACLMask::READ_ONLY)? {
-            return Err(StoreError::PermissionDenied);
+/// Write queue operations
+impl Store {
+    /// The queue `run_write` admits the writes through, see `backend::write_queue`.
+    pub fn write_queue(&self) -> &WriteQueue {
+        &self.write_queue
+    }
+
+    pub fn set_write_queue(&self, config: &WriteQueueConfig) -> StoreResult<()> {
+        let concurrency = config.concurrency.unwrap_or_else(|| self.writers());
+        self.write_queue.set_limits(concurrency, config.interactive_burst)
+    }
+
+    // one writer connection per writable database
+    fn writers(&self) -> usize {
+        self.data_manager.writers() + INNER_DATABASES
+    }
+}
+
+/// Background jobs
+impl Store {
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Write the buffered reads of every namespace, returns the number of items they were buffered for.
+    pub fn flush_access_stats(&self) -> StoreResult<usize> {
+        let mut flushed = 0;
+        for namespace in self.data_manager.namespaces() {
+            flushed += self.data_manager.backend_for(&namespace)?.flush_access_stats()?;
         }
-        Ok(data)
+        Ok(flushed)
     }
 
-    pub fn update(
-        &self,
-        namespace: &str,
-        collection: &str,
-        id: &Id,
-        body: &Value,
-        user: &str,
-    ) -> StoreResult<DataItem> {
+    /// Delete the expired documents of the `x-ttl` collections of the namespace, returns how many.
+    ///
+    /// They go as a delete of their owner would, with their children as `x-on-parent-delete` says. A
+    /// document its restricted children keep is left for a later run.
+    pub fn expire_documents(&self, namespace: &str) -> StoreResult<usize> {
+        self.check_writable()?;
         let backend = self.data_manager.backend_for(namespace)?;
-        let data = backend.get(collection, id)?;
-        // check permission
-        if !self.check_permission((namespace, collection), &data, user, ACLMask::UPDATE_ONLY)? {
-            return Err(StoreError::PermissionDenied);
+        if backend.is_read_only() {
+            return Ok(0);
         }
-        backend.update(collection, id, body)
+        let meta = ChangeMeta {
+            actor: None,
+            reason: Some("expired".to_string()),
+        };
+        let mut expired = 0;
+        for collection in backend.ttl_collections() {
+            for id in backend.list_expired(collection, chrono::Utc::now())? {
+                match backend.delete_with_meta(collection, &id, None, &meta) {
+                    Ok(()) => expired += 1,
+                    // gone with an expired parent already
+                    Err(StoreError::NotFound(_)) => continue,
+                    Err(StoreError::Conflict(e)) => {
+                        tracing::warn!("expired {}/{} / {} kept: {}", namespace, collection, id, e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+                backend.release_lock(collection, &id)?;
+            }
+        }
+        if expired > 0 {
+            tracing::info!("deleted {} expired documents in namespace {}", expired, namespace);
+        }
+        Ok(expired)
     }
 
-    // todo delete might leave child data orphaned, need to consider how to handle it
-    // add a re-mapping relation?
-    pub fn delete(&self, namespace: &str, collection: &str, id: &Id, user: &str) -> StoreResult<()> {
+    /// Register the periodic sweep of the expired documents of every namespace.
+    pub fn schedule_expiry_sweep(self: &Arc<Self>, interval: Duration) -> StoreResult<()> {
+        let store = Arc::downgrade(self);
+        self.scheduler
+            .register("expiry_sweep", interval, Duration::ZERO, move || {
+                match store.upgrade() {
+                    Some(store) => {
+                        for namespace in store.data_manager.namespaces() {
+                            store.expire_documents(&namespace)?;
+                        }
+                        Ok(())
+                    }
+                    None => Ok(()),
+                }
+            })
+    }
+
+    /// Drop the tombstones of the namespace older than `retention` with the change log of their
+    /// documents, returns how many were dropped.
+    ///
+    /// A client whose cursor had not reached a dropped tombstone gets a conflict from the change feed
+    /// and the delta pull, and syncs again from the start.
+    pub fn prune_tombstones(&self, namespace: &str, retention: Duration) -> StoreResult<usize> {
+        self.check_writable()?;
         let backend = self.data_manager.backend_for(namespace)?;
-        let data = backend.get(collection, id)?;
-        // check permission
-        if !self.check_permission((namespace, collection), &data, user, ACLMask::DELETE_ONLY)? {
-            return Err(StoreError::PermissionDenied);
+        if backend.is_read_only() {
+            return Ok(0);
         }
-        backend.delete(collection, id)
+        let retention = chrono::Duration::from_std(retention).map_err(|e| StoreError::Validation(e.to_string()))?;
+        let pruned = backend.prune_tombstones(chrono::Utc::now() - retention)?;
+        if pruned > 0 {
+            tracing::info!("pruned {} tombstones of namespace {}", pruned, namespace);
+        }
+        Ok(pruned)
     }
 
-    /// 1. if the data owner is the user, allow
-    /// 2. else check directly acl
-    /// 3. else check parent data recursively
-    fn check_permission(
-        &self,
-        (namespace, collection): (&str, &str),
-        data: &DataItem,
-        user: &str,
-        needed_mask: ACLMask,
-    ) -> StoreResult<bool> {
-        // check owner
-        if data.owner == user {
-            return Ok(true);
-        }
-        // check ACL
-        if let Ok(acl) = self.root_get_data_acl(namespace, collection, &data.id) {
-            for perm in acl.permissions {
-                let acl_mask: ACLMask = perm.access_level.clone().into();
-                if perm.user == user && acl_mask.contains(needed_mask) {
-                    return Ok(true);
+    /// Register the hourly prune of the tombstones older than `retention` in every namespace.
+    pub fn schedule_tombstone_gc(self: &Arc<Self>, retention: Duration) -> StoreResult<()> {
+        let store = Arc::downgrade(self);
+        self.scheduler.register(
+            "tombstone_gc",
+            Duration::from_secs(60 * 60),
+            Duration::ZERO,
+            move || match store.upgrade() {
+                Some(store) => {
+                    for namespace in store.data_manager.namespaces() {
+                        store.prune_tombstones(&namespace, retention)?;
+                    }
+                    Ok(())
                 }
-            }
+                None => Ok(()),
+            },
+        )
+    }
+
+    /// Register the periodic write of the buffered reads, see `SqliteBackend::record_read`.
+    pub fn schedule_access_stats_flush(self: &Arc<Self>, interval: Duration) -> StoreResult<()> {
+        let store = Arc::downgrade(self);
+        self.scheduler
+            .register("access_stats_flush", interval, Duration::ZERO, move || {
+                match store.upgrade() {
+                    Some(store) => store.flush_access_stats().map(|_| ()),
+                    None => Ok(()),
+                }
+            })
+    }
+
+    /// Register the hourly purge of the accounts deactivated for longer than `grace`.
+    pub fn schedule_user_purge(self: &Arc<Self>, grace: Duration) -> StoreResult<()> {
+        let store = Arc::downgrade(self);
+        self.scheduler.register(
+            "user_purge",
+            Duration::from_secs(60 * 60),
+            Duration::ZERO,
+            move || match store.upgrade() {
+                Some(store) => store.purge_deactivated_users(grace).map(|_| ()),
+                None => Ok(()),
+            },
+        )
+    }
+
+    /// Register a periodic orphan cleanup job for every namespace.
+    pub fn schedule_orphan_cleanup(self: &Arc<Self>, config: &OrphanCleanup) -> StoreResult<()> {
+        for namespace in self.data_manager.namespaces() {
+            // the job is owned by the store itself, hold a weak ref to avoid the cycle
+            let store = Arc::downgrade(self);
+            let policy = config.policy.clone();
+            let ns = namespace.clone();
+            self.scheduler.register(
+                &format!("orphan_cleanup:{}", namespace),
+                config.interval,
+                config.jitter.unwrap_or_default(),
+                move || match store.upgrade() {
+                    Some(store) => store.cleanup_orphans(&ns, &policy).map(|_| ()),
+                    None => Ok(()),
+                },
+            )?;
         }
-        // check parent data recursively
-        let backend = self.data_manager.backend_for(namespace)?;
-        if let Some(parent_id) = data.parent_id.as_ref()
-            && let Some((parent_collection, _field)) = backend.parent_collection(collection)
-            && let Some(parent_needed_mask) = needed_mask.upgrade_for_parent()
-        {
-            let parent_data = backend.get(parent_collection, parent_id)?;
-            return self.check_permission((namespace, parent_collection), &parent_data, user, parent_needed_mask);
+        Ok(())
+    }
+
+    /// Register a periodic replication job for every namespace, pushing to `target`.
+    pub fn schedule_replication(
+        self: &Arc<Self>,
+        target: Arc<dyn ReplicationTarget>,
+        config: &Replication,
+    ) -> StoreResult<()> {
+        *self
+            .replication_target
+            .write()
+            .map_err(|e| StoreError::Backend(e.to_string()))? = Some(target.clone());
+        for namespace in self.replication_namespaces() {
+            let store = Arc::downgrade(self);
+            let target = target.clone();
+            let batch_size = config.batch_size;
+            let ns = namespace.clone();
+            self.scheduler.register(
+                &format!("replication:{}", namespace),
+                config.interval,
+                Duration::ZERO,
+                move || match store.upgrade() {
+                    Some(store) => store.replicate_namespace(&ns, target.as_ref(), batch_size).map(|_| ()),
+                    None => Ok(()),
+                },
+            )?;
         }
-        Ok(false)
+        tracing::info!("replicating to {}", target.name());
+        Ok(())
     }
 
-    pub fn get_data_backend(&self, namespace: &str) -> StoreResult<Arc<crate::backend::SqliteBackend>> {
-        self.data_manager.backend_for(namespace)
+    /// Register a periodic job draining the outbox of every namespace, including the inner ones, to `consumer`.
+    pub fn schedule_outbox_dispatch(
+        self: &Arc<Self>,
+        consumer: Arc<dyn OutboxConsumer>,
+        interval: Duration,
+        batch_size: usize,
+    ) -> StoreResult<()> {
+        for namespace in self.replication_namespaces() {
+            let store = Arc::downgrade(self);
+            let consumer = consumer.clone();
+            let ns = namespace.clone();
+            self.scheduler.register(
+                &format!("outbox:{}:{}", consumer.name(), namespace),
+                interval,
+                Duration::ZERO,
+                move || match store.upgrade() {
+                    Some(store) => store.dispatch_outbox(&ns, consumer.as_ref(), batch_size).map(|_| ()),
+                    None => Ok(()),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Register a periodic webhook delivery job for every namespace.
+    pub fn schedule_webhooks(self: &Arc<Self>, sender: Arc<dyn WebhookSender>, config: &Webhooks) -> StoreResult<()> {
+        for namespace in self.data_manager.namespaces() {
+            let store = Arc::downgrade(self);
+            let sender = sender.clone();
+            let config = config.clone();
+            let ns = namespace.clone();
+            self.scheduler.register(
+                &format!("webhooks:{}", namespace),
+                config.interval,
+                Duration::ZERO,
+                move || match store.upgrade() {
+                    Some(store) => store.deliver_webhooks(&ns, sender.as_ref(), &config).map(|_| ()),
+                    None => Ok(()),
+                },
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -416,8 +3510,79 @@ impl Store {
         })
     }
 
+    /// Whether the user may do each `(collection, data_id, action)`, so clients can adapt their UI
+    /// without attempting the operation. Missing data is reported as denied.
+    pub fn check_permissions(
+        &self,
+        namespace: &str,
+        checks: &[(&str, &str, AclAction)],
+        user: &str,
+    ) -> StoreResult<Vec<bool>> {
+        if self.check_namespace_access(namespace, user).is_err() {
+            return Ok(vec![false; checks.len()]);
+        }
+        let backend = self.data_manager.backend_for(namespace)?;
+        checks
+            .iter()
+            .map(
+                |&(collection, data_id, action)| match backend.get(collection, &data_id.to_string()) {
+                    Ok(data) => self.check_permission((namespace, collection), &data, user, action.into()),
+                    Err(StoreError::NotFound(_)) => Ok(false),
+                    Err(e) => Err(e),
+                },
+            )
+            .collect()
+    }
+
+    /// Explain whether `target_user` may do `action` on the data, running the same checks as the data
+    /// operations. Explaining the access of another user needs full access on the data.
+    pub fn explain_permission(
+        &self,
+        (namespace, collection): (&str, &str),
+        data_id: &str,
+        target_user: &str,
+        action: AclAction,
+        user: &str,
+    ) -> StoreResult<PermissionExplanation> {
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        let data = backend.get(collection, &data_id.to_string())?;
+        if target_user != user && !self.check_permission((namespace, collection), &data, user, ACLMask::FULL_ACCESS)? {
+            return Err(StoreError::PermissionDenied);
+        }
+
+        let namespace_access = self.check_namespace_access(namespace, target_user).is_ok();
+        let mut path = Vec::new();
+        let granted = self.walk_permission(
+            (namespace, collection),
+            &data,
+            target_user,
+            action.into(),
+            Some(&mut path),
+        )?;
+        let reason = match path.last() {
+            _ if !namespace_access => format!("namespace `{}` policy does not allow the user", namespace),
+            Some(step) if step.owner_match => format!("owner of {}/{}", step.collection, step.data_id),
+            Some(PermissionStep {
+                collection,
+                data_id,
+                access_level: Some(level),
+                acl_match: true,
+                ..
+            }) => format!("acl `{}` on {}/{}", level.to_string(), collection, data_id),
+            _ => "no owner or acl match on the data or its ancestors".to_string(),
+        };
+        Ok(PermissionExplanation {
+            allowed: namespace_access && granted,
+            reason,
+            namespace_access,
+            path,
+        })
+    }
+
     /// query acls the user has access to
     pub fn get_user_acls(&self, (namespace, collection): (&str, &str), user: &str) -> StoreResult<Vec<AccessControl>> {
+        self.check_namespace_access(namespace, user)?;
         let backend = self.data_manager.backend_for(namespace)?;
         let permissions = backend.get_user_permissions(collection, user)?;
         Ok(permissions
@@ -438,13 +3603,99 @@ impl Store {
             .collect())
     }
 
+    /// Grant and revoke permissions on many documents at once, all of them owned by the user.
+    /// Nothing is applied unless every document passes the checks.
+    pub fn update_acls_bulk(
+        &self,
+        (namespace, collection): (&str, &str),
+        data_ids: &[Id],
+        grant: Vec<Permission>,
+        revoke: Vec<String>,
+        user: &str,
+    ) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
+        if grant.iter().any(|p| revoke.contains(&p.user)) {
+            return Err(StoreError::Validation(
+                "a user can not be both granted and revoked".to_string(),
+            ));
+        }
+        let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
+        let mut old_permissions = HashMap::new();
+        for data_id in data_ids {
+            let data = self.get(namespace, collection, data_id, user)?;
+            // only owner can update ACL for the data
+            if data.owner != user {
+                return Err(StoreError::PermissionDenied);
+            }
+            old_permissions.insert(data_id.clone(), backend.get_data_permissions(collection, data_id)?);
+        }
+        backend.update_acls_bulk(collection, data_ids, &grant, &revoke, user)?;
+        // one notification per user, listing the data whose access actually changed
+        for perm in &grant {
+            let changed = data_ids
+                .iter()
+                .filter(|id| {
+                    !old_permissions[*id]
+                        .iter()
+                        .any(|o| o.user_id == perm.user && o.access_level == perm.access_level)
+                })
+                .collect::<Vec<_>>();
+            if changed.is_empty() {
+                continue;
+            }
+            self.notify_best_effort(
+                &perm.user,
+                NotificationMessage {
+                    kind: "acl_granted".to_string(),
+                    title: format!(
+                        "You were granted {} access to {} items",
+                        perm.access_level.to_string(),
+                        changed.len()
+                    ),
+                    content: None,
+                    payload: Some(serde_json::json!({
+                        "namespace": namespace,
+                        "collection": collection,
+                        "data_ids": changed,
+                        "access_level": perm.access_level,
+                        "granted_by": user,
+                    })),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// ACL entries the user granted or received in the namespace, paged by marker.
+    pub fn list_acls(
+        &self,
+        namespace: &str,
+        direction: AclDirection,
+        collection: Option<&str>,
+        marker: Option<String>,
+        limit: usize,
+        user: &str,
+    ) -> StoreResult<(Vec<AclEntry>, Option<String>)> {
+        if let Some(collection) = collection {
+            self.check_collection_switch(namespace, collection, false)?;
+        }
+        self.check_namespace_access(namespace, user)?;
+        let backend = self.data_manager.backend_for(namespace)?;
+        backend.list_acls(direction, user, collection, marker, limit)
+    }
+
     pub fn update_acl(&self, (namespace, collection): (&str, &str), acl: AccessControl, user: &str) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
         let data = self.get(namespace, collection, &acl.data_id, user)?;
         // only owner can update ACL for the data
         if data.owner != user {
             return Err(StoreError::PermissionDenied);
         }
         let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
         let new_permissions = acl
             .permissions
             .into_iter()
@@ -454,11 +3705,36 @@ impl Store {
                 access_level: perm.access_level,
             })
             .collect::<Vec<_>>();
+        let old_permissions = backend.get_data_permissions(collection, &data.id)?;
         backend.update_acls(collection, &data.id, &new_permissions, user)?;
+        // only notify users whose access actually changed
+        for perm in new_permissions.iter().filter(|p| {
+            !old_permissions
+                .iter()
+                .any(|o| o.user_id == p.user_id && o.access_level == p.access_level)
+        }) {
+            self.notify_best_effort(
+                &perm.user_id,
+                NotificationMessage {
+                    kind: "acl_granted".to_string(),
+                    title: format!("You were granted {} access", perm.access_level.to_string()),
+                    content: None,
+                    payload: Some(serde_json::json!({
+                        "namespace": namespace,
+                        "collection": collection,
+                        "data_id": data.id,
+                        "access_level": perm.access_level,
+                        "granted_by": user,
+                    })),
+                },
+            );
+        }
         Ok(())
     }
 
     pub fn delete_acl(&self, (namespace, collection): (&str, &str), data_id: &str, user: &str) -> StoreResult<()> {
+        self.check_collection_switch(namespace, collection, true)?;
+        self.check_writable()?;
         let id = data_id.to_string();
         let data = self.get(namespace, collection, &id, user)?;
         // only owner can delete ACL for the data
@@ -466,7 +3742,132 @@ impl Store {
             return Err(StoreError::PermissionDenied);
         }
         let backend = self.data_manager.backend_for(namespace)?;
+        check_backend_writable(&backend)?;
         backend.delete_acls_by_data_id(collection, data_id)?;
         Ok(())
     }
 }
+
+/// The operations of a [`Store::transaction`] on its namespace.
+pub struct Transaction<'a> {
+    store: &'a Store,
+    namespace: &'a str,
+}
+
+impl Transaction<'_> {
+    pub fn insert(&self, collection: &str, body: &Value, user: &str) -> StoreResult<String> {
+        self.store.insert(self.namespace, collection, body, user)
+    }
+
+    pub fn get(&self, collection: &str, id: &Id, user: &str) -> StoreResult<DataItem> {
+        self.store.get(self.namespace, collection, id, user)
+    }
+
+    pub fn update(&self, collection: &str, id: &Id, body: &Value, user: &str) -> StoreResult<DataItem> {
+        self.store.update(self.namespace, collection, id, body, user)
+    }
+
+    pub fn delete(&self, collection: &str, id: &Id, user: &str) -> StoreResult<()> {
+        self.store.delete(self.namespace, collection, id, user)
+    }
+}
+
+// run the writes of a batch in one transaction, committed only when every item succeeded; inside a
+// request transaction the batch joins it and a rejected batch is left for the request to roll back
+fn run_batch(items: impl FnOnce() -> Vec<StoreResult<Id>>) -> StoreResult<BatchOutcome> {
+    let scope = TxScope::current().is_none().then(TxScope::new);
+    let results = match &scope {
+        Some(scope) => scope.enter_sync(items),
+        None => items(),
+    };
+    let mut ids = Vec::with_capacity(results.len());
+    let mut failures = Vec::new();
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(id) => ids.push(id),
+            Err(e) => failures.push(BatchFailure {
+                index,
+                error: e.to_string(),
+            }),
+        }
+    }
+    if !failures.is_empty() {
+        if let Some(scope) = scope {
+            scope.rollback();
+        }
+        return Ok(BatchOutcome::Rejected { failures });
+    }
+    if let Some(scope) = scope {
+        scope.commit()?;
+    }
+    Ok(BatchOutcome::Committed { ids })
+}
+
+// a cursor handed out before tombstones it had not seen yet were pruned may have missed deletes
+fn check_tombstones_kept(backend: &SqliteBackend, namespace: &str, generation: i64, seq: i64) -> StoreResult<()> {
+    if backend.tombstones_pruned_after(generation, seq)? {
+        return Err(StoreError::Conflict(format!(
+            "cursor is older than the kept tombstones of namespace {}, sync again from the start",
+            namespace
+        )));
+    }
+    Ok(())
+}
+
+// reject writes into a database attached read-only, or made from a read snapshot
+fn check_backend_writable(backend: &SqliteBackend) -> StoreResult<()> {
+    if backend.is_read_only() {
+        return Err(StoreError::Conflict("namespace is served read-only".to_string()));
+    }
+    if TxScope::current().is_some_and(|scope| scope.is_read_only()) {
+        return Err(StoreError::Conflict(
+            "writes are not allowed in a read snapshot".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// reject writes into a collection switched to read-only
+fn check_collection_writable(backend: &SqliteBackend, collection: &str) -> StoreResult<()> {
+    check_backend_writable(backend)?;
+    let mode = backend.collection_mode(collection)?;
+    if mode.read_only {
+        return Err(StoreError::Conflict(format!(
+            "collection `{}` is read-only{}",
+            collection,
+            mode.reason.map(|r| format!(": {}", r)).unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+// reject modifications of a document locked by another user
+fn check_unlocked(backend: &SqliteBackend, collection: &str, id: &str, user: &str) -> StoreResult<()> {
+    match backend.get_lock(collection, id)? {
+        Some(lock) if lock.holder != user => Err(StoreError::Conflict(format!(
+            "{}/{} is locked by {} until {}",
+            collection,
+            id,
+            lock.holder,
+            lock.expires_at.to_rfc3339()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// who made a change and why, the reason is a short free text
+fn change_meta(user: &str, reason: Option<&str>) -> StoreResult<ChangeMeta> {
+    let reason = reason.map(str::trim).filter(|r| !r.is_empty());
+    if let Some(reason) = reason
+        && reason.chars().count() > MAX_CHANGE_REASON_CHARS
+    {
+        return Err(StoreError::Validation(format!(
+            "change reason is limited to {} characters",
+            MAX_CHANGE_REASON_CHARS
+        )));
+    }
+    Ok(ChangeMeta {
+        actor: Some(user.to_string()),
+        reason: reason.map(str::to_string),
+    })
+}
@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::backend::filter::{FilterExpr, SortSpec};
 use crate::error::StoreError;
 
 /// Identifier type used across the store.
@@ -9,7 +10,7 @@ pub type Uid = String;
 
 use base64_serde::base64_serde_type;
 
-base64_serde_type!(Base64Standard, base64::engine::general_purpose::STANDARD);
+base64_serde_type!(pub Base64Standard, base64::engine::general_purpose::STANDARD);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct UserSchemaDocument {
@@ -17,10 +18,16 @@ pub struct UserSchemaDocument {
     pub password: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub avatar_url: Option<String>,
+    /// used to deliver notifications by email, never exposed in the public profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
     #[serde(with = "Base64Standard")]
     pub public_key: Vec<u8>,
     #[serde(with = "Base64Standard")]
     pub secret_key: Vec<u8>,
+    /// set while the account waits to be purged, login is blocked and its data hidden
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deactivated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone)]
@@ -29,8 +36,10 @@ pub struct UserSchema {
     pub username: String,
     pub password: String,
     pub avatar_url: Option<String>,
+    pub email: Option<String>,
     pub public_key: Vec<u8>,
     pub secret_key: Vec<u8>,
+    pub deactivated_at: Option<DateTime<Utc>>,
 }
 
 impl UserSchema {
@@ -40,8 +49,10 @@ impl UserSchema {
             username: doc.username,
             password: doc.password,
             avatar_url: doc.avatar_url,
+            email: doc.email,
             public_key: doc.public_key,
             secret_key: doc.secret_key,
+            deactivated_at: doc.deactivated_at,
         }
     }
 }
@@ -52,12 +63,38 @@ impl From<UserSchema> for UserSchemaDocument {
             username: value.username,
             password: value.password,
             avatar_url: value.avatar_url,
+            email: value.email,
             public_key: value.public_key,
             secret_key: value.secret_key,
+            deactivated_at: value.deactivated_at,
         }
     }
 }
 
+/// A passkey registered by a user, an alternative to the password login.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct Passkey {
+    pub id: Id,
+    /// label chosen by the user, e.g. the device name
+    pub name: String,
+    /// base64url credential id the browser presents on login
+    pub credential_id: String,
+    #[serde(skip)]
+    pub public_key: Vec<u8>,
+    #[serde(skip)]
+    pub sign_count: u32,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// An account waiting for its purge, listed for the admins who may restore it.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct DeactivatedUser {
+    pub user_id: String,
+    pub username: String,
+    pub deactivated_at: DateTime<Utc>,
+}
+
 /// DataItemDocument
 /// diff with DataItem: the body is String
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -69,6 +106,8 @@ pub struct DataItemDocument {
     pub unique: Option<String>,
     pub parent_id: Option<String>,
     pub body: String,
+    pub rev: i64,
+    pub schema_version: i64,
 }
 
 impl TryFrom<DataItemDocument> for DataItem {
@@ -84,6 +123,11 @@ impl TryFrom<DataItemDocument> for DataItem {
             unique: value.unique,
             parent_id: value.parent_id,
             body,
+            rev: value.rev,
+            schema_version: value.schema_version,
+            lock: None,
+            attachments: Vec::new(),
+            deleted_at: None,
         })
     }
 }
@@ -97,6 +141,21 @@ pub struct DataItem {
     pub unique: Option<String>,
     pub parent_id: Option<String>,
     pub body: serde_json::Value,
+    /// revision of the item, starting at 1 and raised by every change, see `Store::update_with_reason`
+    #[serde(default)]
+    pub rev: i64,
+    /// version of the collection schema the item was last written under, see `x-version`
+    #[serde(default)]
+    pub schema_version: i64,
+    /// advisory lock held on the item, only filled by `Store::get`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<ItemLock>,
+    /// files bound to the item, only filled by `Store::get`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    /// when the item of an `x-soft-delete` collection was deleted, only read with `include_deleted`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl salvo::Scribe for DataItem {
@@ -105,6 +164,178 @@ impl salvo::Scribe for DataItem {
     }
 }
 
+/// A page of a listing with the markers to move both ways.
+///
+/// `prev_marker` is `None` with `has_prev` set when the page before is the first page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub items: Vec<DataItem>,
+    pub next_marker: Option<String>,
+    pub prev_marker: Option<String>,
+    pub has_prev: bool,
+}
+
+impl Page {
+    pub fn has_next(&self) -> bool {
+        self.next_marker.is_some()
+    }
+}
+
+/// Opaque page token of a listing pinned to the rows present when its first page was read.
+///
+/// Items inserted while paging never show up, items updated show their latest version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageCursor {
+    /// id of the first item of the next page
+    pub marker: Option<String>,
+    /// highest rowid visible to the listing
+    pub snapshot: i64,
+}
+
+impl PageCursor {
+    pub fn new(marker: String, snapshot: i64) -> Self {
+        Self {
+            marker: Some(marker),
+            snapshot,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        use base64::Engine;
+        let raw = format!("{}.{}", self.snapshot, self.marker.as_deref().unwrap_or_default());
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(token: &str) -> Result<Self, StoreError> {
+        use base64::Engine;
+        let invalid = || StoreError::Validation(format!("invalid page cursor `{}`", token));
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (snapshot, marker) = raw.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            marker: (!marker.is_empty()).then(|| marker.to_string()),
+            snapshot: snapshot.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Meta column a listing can be ordered by, ties are broken by id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    CreatedAt,
+    UpdatedAt,
+    Unique,
+}
+
+/// Order of a listing, written `created_at`, `updated_at:desc` or `unique:asc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListOrder {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl std::str::FromStr for ListOrder {
+    type Err = StoreError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (key, direction) = s.split_once(':').unwrap_or((s, "asc"));
+        let key = match key {
+            "created_at" => SortKey::CreatedAt,
+            "updated_at" => SortKey::UpdatedAt,
+            "unique" => SortKey::Unique,
+            _ => return Err(StoreError::Validation(format!("Invalid sort key: {}", key))),
+        };
+        let descending = match direction {
+            "asc" => false,
+            "desc" => true,
+            _ => return Err(StoreError::Validation(format!("Invalid sort direction: {}", direction))),
+        };
+        Ok(ListOrder { key, descending })
+    }
+}
+
+/// Opaque marker of a sorted listing, the sort value and id of the first item of the next page.
+///
+/// The value is carried along instead of looked up, a page stays in place when that item changes or
+/// disappears meanwhile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortMarker {
+    pub value: String,
+    pub id: Id,
+}
+
+impl SortMarker {
+    pub fn encode(&self) -> String {
+        use base64::Engine;
+        // json keeps it unambiguous, imported ids and unique values may hold any character
+        let raw = serde_json::json!([self.value, self.id]).to_string();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(token: &str) -> Result<Self, StoreError> {
+        use base64::Engine;
+        let invalid = || StoreError::Validation(format!("invalid sort marker `{}`", token));
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|_| invalid())?;
+        let (value, id) = serde_json::from_slice::<(String, Id)>(&raw).map_err(|_| invalid())?;
+        Ok(Self { value, id })
+    }
+}
+
+/// File bound to a data item, readable with the item's ACL and deleted along with it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct Attachment {
+    pub id: Id,
+    pub name: String,
+    pub content_type: String,
+    pub size: u64,
+    /// hex sha256 of the content
+    pub sha256: String,
+    /// the uploader, not necessarily the owner of the item
+    pub owner: Uid,
+    pub created_at: DateTime<Utc>,
+}
+
+impl salvo::Scribe for Attachment {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// An attachment with its content, base64 encoded in JSON.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct AttachmentContent {
+    #[serde(flatten)]
+    pub attachment: Attachment,
+    #[serde(with = "Base64Standard")]
+    #[salvo(schema(value_type = String))]
+    pub content: Vec<u8>,
+}
+
+impl salvo::Scribe for AttachmentContent {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Advisory lock on a document, other users can not modify it until released or expired.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct ItemLock {
+    pub holder: Uid,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl salvo::Scribe for ItemLock {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
 /// DataItemSummary
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
 pub struct DataItemSummary {
@@ -114,6 +345,10 @@ pub struct DataItemSummary {
     pub owner: Uid,
     pub unique: Option<String>,
     pub parent_id: Option<String>,
+    #[serde(default)]
+    pub rev: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl salvo::Scribe for DataItemSummary {
@@ -131,6 +366,8 @@ impl From<DataItem> for DataItemSummary {
             owner: value.owner,
             unique: value.unique,
             parent_id: value.parent_id,
+            rev: value.rev,
+            deleted_at: value.deleted_at,
         }
     }
 }
@@ -147,6 +384,28 @@ pub struct Permission {
     pub access_level: AccessLevel,
 }
 
+/// One ACL entry, as listed by the owner granting it or by the user receiving it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct AclEntry {
+    pub collection: String,
+    pub data_id: String,
+    /// user receiving the access
+    pub user: String,
+    pub access_level: AccessLevel,
+    /// owner of the data granting the access
+    pub granted_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Side of the ACL entries listed for a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclDirection {
+    /// entries the user granted on their data
+    Granted,
+    /// entries granting the user access to data of others
+    Received,
+}
+
 /// This enum string will be stored in the database, so be sure to make compatible changes when modifying it.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
 #[serde(rename_all = "snake_case")]
@@ -196,7 +455,563 @@ impl std::str::FromStr for AccessLevel {
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// Namespace level access policy, checked before any data or ACL operation in the namespace.
+///
+/// An open namespace accepts every authenticated user, a closed one only the users in `allowed_users`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct NamespacePolicy {
+    #[serde(default = "default_true")]
+    pub open: bool,
+    #[serde(default)]
+    pub allowed_users: std::collections::BTreeSet<Uid>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for NamespacePolicy {
+    fn default() -> Self {
+        Self {
+            open: true,
+            allowed_users: Default::default(),
+        }
+    }
+}
+
+impl NamespacePolicy {
+    pub fn allows(&self, user: &str) -> bool {
+        self.open || self.allowed_users.contains(user)
+    }
+}
+
+/// Runtime read-only switch, while enabled every mutation is rejected and reads are still served.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct MaintenanceMode {
+    pub enabled: bool,
+    /// shown to the clients in the rejection message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// when the mode was last enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Persistent per-collection write mode, a read-only collection keeps serving reads
+/// but rejects new writes, e.g. while sunsetting it or freezing legacy data for a migration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct CollectionMode {
+    pub read_only: bool,
+    /// shown to the clients in the rejection message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// when the collection was made read-only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Runtime kill switches of a collection, disabled operations are rejected with 503.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct CollectionSwitches {
+    #[serde(default)]
+    pub reads_disabled: bool,
+    #[serde(default)]
+    pub writes_disabled: bool,
+    /// shown to the clients in the rejection message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A child document whose `parent_id` no longer resolves in its parent collection.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct Orphan {
+    pub collection: String,
+    pub id: Id,
+    pub owner: Uid,
+    pub parent_id: String,
+}
+
+/// What the orphan cleanup does with the orphans it finds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum OrphanPolicy {
+    /// Only report the orphans.
+    Report,
+    /// Delete the orphans, and the descendants left orphaned by that.
+    Delete,
+    /// Move the orphans of `collection` under `parent_id`, orphans of other collections are only reported.
+    Reparent { collection: String, parent_id: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct OrphanReport {
+    pub orphans: Vec<Orphan>,
+    /// number of orphans deleted or re-parented
+    pub resolved: usize,
+}
+
+/// Outcome of `Store::vacuum_namespace`, the database checked, rebuilt and its statistics refreshed.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct VacuumReport {
+    pub namespace: String,
+    /// database size in bytes before and after the VACUUM
+    pub size_before: u64,
+    pub size_after: u64,
+    pub reclaimed: u64,
+    /// what `PRAGMA integrity_check` found, empty for a sound database
+    pub integrity_errors: Vec<String>,
+}
+
+/// Outcome of filling a collection with fake documents, see `Store::generate_fake_data`.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct GeneratedData {
+    pub inserted: Vec<Id>,
+    /// documents still rejected after their retries
+    pub failed: usize,
+    /// the first rejection, usually naming the schema rule the generator does not follow
+    pub first_error: Option<String>,
+}
+
+/// A message published into a user's notification inbox.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct Notification {
+    pub id: Id,
+    pub created_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub message: NotificationMessage,
+    pub read: bool,
+}
+
+impl salvo::Scribe for Notification {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Content of a notification, as published by the feature raising it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, salvo::oapi::ToSchema)]
+pub struct NotificationMessage {
+    /// machine readable kind, e.g. `acl_granted`, `friend_added`
+    pub kind: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// extra data for the client to act on, e.g. the id of the shared data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+}
+
+/// A downloadable copy of namespace data.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub data: Vec<u8>,
+    /// hex encoded sha256 of `data`
+    pub sha256: String,
+}
+
+impl Snapshot {
+    pub fn new(data: Vec<u8>) -> Self {
+        use sha2::Digest;
+        let sha256 = sha2::Sha256::digest(&data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Self { data, sha256 }
+    }
+}
+
+/// A copy of a namespace database written by `Store::backup`.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct BackupInfo {
+    pub namespace: String,
+    pub path: String,
+    /// size of the copy in bytes
+    pub bytes: u64,
+    pub completed_at: DateTime<Utc>,
+}
+
+/// One entry of a namespace change log, written in the same transaction as the change itself.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct Change {
+    pub seq: i64,
+    pub collection: String,
+    pub data_id: Id,
+    pub changed_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub op: ChangeOp,
+    #[serde(flatten)]
+    pub meta: ChangeMeta,
+}
+
+/// A page of the change feed of a namespace, see `Store::changes`.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct ChangeFeed {
+    /// the changes of data the user can read, oldest first
+    pub changes: Vec<Change>,
+    /// `<seq>.<generation>` of the last change looked at, the feed resumes after it
+    pub cursor: String,
+    /// whether changes after the cursor were already committed
+    pub has_more: bool,
+}
+
+/// A page of the delta pull of a namespace, see `Store::pull_changes`.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct PulledChanges {
+    /// current state of the documents changed since the cursor the user can read
+    pub items: Vec<DataItem>,
+    /// documents deleted since the cursor the user could read
+    pub deleted: Vec<DeletedItem>,
+    /// opaque, the pull resumes after it
+    pub cursor: String,
+    /// whether changes after the cursor were already committed
+    pub has_more: bool,
+}
+
+/// Tombstone of a document deleted since a pull cursor, kept for the `tombstone_retention`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct DeletedItem {
+    pub collection: String,
+    pub id: String,
+    pub deleted_at: DateTime<Utc>,
+    /// user who deleted it, none for a delete of the store itself, e.g. an expiry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<Uid>,
+}
+
+/// Position in the change log of a namespace a delta pull resumes after, handed out opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncCursor {
+    pub seq: i64,
+    /// tombstone prunes done when the cursor was handed out, see `SqliteBackend::tombstone_generation`
+    pub generation: i64,
+}
+
+impl SyncCursor {
+    pub fn encode(&self, namespace: &str) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(format!("{}.{}.{}", self.seq, self.generation, namespace))
+    }
+
+    /// Reads a cursor, one handed out for another namespace is refused.
+    pub fn decode(token: &str, namespace: &str) -> Result<Self, StoreError> {
+        use base64::Engine;
+        let invalid = || StoreError::Validation(format!("invalid sync cursor `{}`", token));
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (seq, rest) = raw.split_once('.').ok_or_else(invalid)?;
+        let (generation, cursor_namespace) = rest.split_once('.').ok_or_else(invalid)?;
+        if cursor_namespace != namespace {
+            return Err(invalid());
+        }
+        Ok(Self {
+            seq: seq.parse().map_err(|_| invalid())?,
+            generation: generation.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A write a client made offline, pushed with the others in one batch, see `Store::push_changes`.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Mutation {
+    /// insert under the id the client generated, a server generated one when none
+    Create {
+        collection: String,
+        #[serde(default)]
+        id: Option<Id>,
+        body: serde_json::Value,
+    },
+    /// replace the body, while the document is still at `base_rev` when given
+    Update {
+        collection: String,
+        id: Id,
+        body: serde_json::Value,
+        #[serde(default)]
+        base_rev: Option<i64>,
+    },
+    /// delete, while the document is still at `base_rev` when given
+    Delete {
+        collection: String,
+        id: Id,
+        #[serde(default)]
+        base_rev: Option<i64>,
+    },
+}
+
+/// What became of one pushed mutation, in the order of the batch.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PushResult {
+    /// written, `rev` is the new revision, none for a delete
+    Accepted { id: Id, rev: Option<i64> },
+    /// the document changed or was deleted since `base_rev`, or the id is taken; nothing was
+    /// written, the client merges both versions and pushes the result at the server's `rev`
+    Conflict {
+        id: Option<Id>,
+        error: String,
+        /// the document as stored now, none when it was deleted or the user can not read it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        server: Option<DataItem>,
+        /// the body pushed, none for a delete
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client: Option<serde_json::Value>,
+    },
+    /// not allowed or not valid, retrying it will not help
+    Rejected { id: Option<Id>, error: String },
+}
+
+/// Who made a change and why, recorded with the change.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct ChangeMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<Uid>,
+    /// free text given by the client, e.g. through the `X-Change-Reason` header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl ChangeMeta {
+    pub fn actor(actor: &str) -> Self {
+        Self {
+            actor: Some(actor.to_string()),
+            reason: None,
+        }
+    }
+}
+
+/// The state after a change, replaying the ops in order rebuilds the namespace.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ChangeOp {
+    /// Document created or updated, with the full row after the change.
+    Upsert {
+        item: DataItem,
+    },
+    Delete,
+    /// ACL entries of the document replaced, `permissions` is the complete list after the change.
+    Acl {
+        owner: Uid,
+        permissions: Vec<PermissionSchema>,
+    },
+}
+
+impl ChangeOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeOp::Upsert { .. } => "upsert",
+            ChangeOp::Delete => "delete",
+            ChangeOp::Acl { .. } => "acl",
+        }
+    }
+}
+
+/// Changes pushed by a primary to its secondary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationBatch {
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReplicationAck {
+    pub applied: usize,
+    /// seq of the last applied change, none for an empty batch
+    pub last_seq: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReplicationStatus {
+    pub namespace: String,
+    /// seq of the latest local change
+    pub head_seq: i64,
+    /// seq of the latest change acknowledged by the secondary
+    pub replicated_seq: i64,
+}
+
+/// Health of one namespace database.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NamespaceHealth {
+    pub namespace: String,
+    /// the database file exists and answers queries
+    pub reachable: bool,
+    pub read_only: bool,
+    /// size of the database file in bytes, none for memory databases
+    pub file_size: Option<u64>,
+    /// time of the latest change in the change log
+    pub last_write: Option<DateTime<Utc>>,
+    /// tables missing from the file or collections whose stored schema differs from the configured one,
+    /// only a read-only database can fall behind as writable ones are brought up to date at startup
+    pub pending_migrations: Vec<String>,
+    /// why the database is unreachable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What the startup checks found in every namespace database, see `Store::startup`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StartupReport {
+    pub namespaces: Vec<NamespaceStartup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NamespaceStartup {
+    pub namespace: String,
+    pub collections: usize,
+    /// documents whose `uniq` or `parent_id` column was filled from their body
+    pub backfilled: usize,
+    /// served nevertheless, e.g. migrations a read-only database cannot run
+    pub warnings: Vec<String>,
+    /// what keeps the namespace from being served
+    pub problems: Vec<String>,
+}
+
+impl StartupReport {
+    pub fn is_ok(&self) -> bool {
+        self.namespaces.iter().all(|n| n.problems.is_empty())
+    }
+}
+
+impl std::fmt::Display for StartupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for namespace in &self.namespaces {
+            writeln!(
+                f,
+                "{}: {} collections, {} documents backfilled",
+                namespace.namespace, namespace.collections, namespace.backfilled
+            )?;
+            for warning in &namespace.warnings {
+                writeln!(f, "  warning: {}", warning)?;
+            }
+            for problem in &namespace.problems {
+                writeln!(f, "  error: {}", problem)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Endpoint receiving the changes of a namespace as HMAC signed POST requests.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    /// collections to deliver the changes of, every collection when empty
+    #[serde(default)]
+    pub collections: Vec<String>,
+    /// kinds of change to deliver, every kind when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+    /// condition on the document of an upsert, as the query endpoint takes it, deletes and ACL
+    /// changes carry no document and only go by `events`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[salvo(schema(value_type = Option<Object>))]
+    pub filter: Option<FilterExpr>,
+    pub created_at: DateTime<Utc>,
+    /// signing secret, only returned when the webhook is created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    /// Whether the change is of a collection and kind the webhook receives, the `filter` is left
+    /// to the backend.
+    pub fn wants(&self, change: &Change) -> bool {
+        let kind = match change.op {
+            ChangeOp::Upsert { .. } => WebhookEventKind::Upsert,
+            ChangeOp::Delete => WebhookEventKind::Delete,
+            ChangeOp::Acl { .. } => WebhookEventKind::Acl,
+        };
+        (self.collections.is_empty() || self.collections.contains(&change.collection))
+            && (self.events.is_empty() || self.events.contains(&kind))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    Upsert,
+    Delete,
+    Acl,
+}
+
+/// Body of a webhook request, deliveries are retried independently so receivers should order by `seq`.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct WebhookEvent {
+    pub namespace: String,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+/// A queued webhook request, dead once it failed `max_attempts` times.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: String,
+    /// seq of the delivered change
+    pub seq: i64,
+    /// the serialized `WebhookEvent`, signed as is
+    pub payload: String,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub dead: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Credential of a backend job on one namespace, it acts as a service identity instead of a user
+/// account and only reaches the collections it is granted.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema)]
+pub struct ServiceKey {
+    pub id: String,
+    pub name: String,
+    /// the service identity the key acts as, owner of the documents it inserts
+    pub user_id: String,
+    /// access by collection, the other collections are refused
+    pub grants: std::collections::BTreeMap<String, ServiceAccess>,
+    pub created_at: DateTime<Utc>,
+    /// the key itself, only returned when the key is created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl ServiceKey {
+    /// Whether the key reaches the collection, for a write or a read.
+    pub fn allows(&self, collection: &str, write: bool) -> bool {
+        match self.grants.get(collection) {
+            Some(ServiceAccess::Write) => true,
+            Some(ServiceAccess::Read) => !write,
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceAccess {
+    Read,
+    /// reads and writes
+    Write,
+}
+
+/// Outcome of checking a body against a collection without storing it.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub errors: Vec<ValidationIssue>,
+}
+
+/// One reason a body is refused.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct ValidationIssue {
+    /// JSON pointer of the offending value in the body, empty for the body itself
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, salvo::oapi::ToSchema)]
 pub struct PermissionSchema {
     pub data_id: String,
     pub user_id: String,
@@ -234,6 +1049,222 @@ impl ACLMask {
     }
 }
 
+impl ACLMask {
+    /// Readable names of the granted operations, e.g. `["read", "append_2_below"]`.
+    pub fn names(self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.contains(ACLMask::READ_ONLY) {
+            names.push("read");
+        }
+        if self.contains(ACLMask::UPDATE_ONLY) {
+            names.push("update");
+        }
+        if self.contains(ACLMask::DELETE_ONLY) {
+            names.push("delete");
+        }
+        match self & ACLMask::APPEND_1_BELOW {
+            ACLMask::APPEND_1_BELOW => names.push("append_1_below"),
+            ACLMask::APPEND_2_BELOW => names.push("append_2_below"),
+            ACLMask::APPEND_3_BELOW => names.push("append_3_below"),
+            _ => {}
+        }
+        names
+    }
+}
+
+/// Operation on a document, as checked against its owner and ACLs.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AclAction {
+    #[default]
+    Read,
+    Update,
+    Delete,
+    /// insert a child document below it
+    Append,
+}
+
+impl From<AclAction> for ACLMask {
+    fn from(action: AclAction) -> Self {
+        match action {
+            AclAction::Read => ACLMask::READ_ONLY,
+            AclAction::Update => ACLMask::UPDATE_ONLY,
+            AclAction::Delete => ACLMask::DELETE_ONLY,
+            AclAction::Append => ACLMask::APPEND_1_BELOW,
+        }
+    }
+}
+
+/// Decision path of a permission check, see `Store::explain_permission`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct PermissionExplanation {
+    pub allowed: bool,
+    /// short summary of what decided
+    pub reason: String,
+    /// whether the namespace policy lets the user in at all
+    pub namespace_access: bool,
+    /// every checked document, from the requested one up to the ancestor that decided
+    pub path: Vec<PermissionStep>,
+}
+
+impl salvo::Scribe for PermissionExplanation {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// How an insert treats a body the owner already stored in the collection.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Always insert a new item.
+    #[default]
+    Always,
+    /// Return the existing item instead when the content, without timestamps and computed fields, is the same.
+    SkipDuplicates,
+}
+
+/// Outcome of an insert with an `ImportMode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Imported {
+    Inserted(Id),
+    /// id of the item already holding the same content
+    Duplicate(Id),
+}
+
+/// Outcome of an all-or-nothing batch: every item written, or nothing and the items that failed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    /// ids in the order of the items
+    Committed {
+        ids: Vec<Id>,
+    },
+    Rejected {
+        failures: Vec<BatchFailure>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct BatchFailure {
+    /// position of the item in the batch
+    pub index: usize,
+    pub error: String,
+}
+
+/// Storage used by one user in a namespace, see `Store::namespace_usage`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct NamespaceUsage {
+    pub namespace: String,
+    pub collections: Vec<CollectionUsage>,
+    /// items over every collection
+    pub items: u64,
+    /// body and attachment bytes over every collection
+    pub bytes: u64,
+}
+
+impl NamespaceUsage {
+    pub fn new(namespace: &str, collections: Vec<CollectionUsage>) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            items: collections.iter().map(|c| c.items).sum(),
+            bytes: collections.iter().map(|c| c.body_bytes + c.attachment_bytes).sum(),
+            collections,
+        }
+    }
+}
+
+impl salvo::Scribe for NamespaceUsage {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Items of one collection owned by the user.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct CollectionUsage {
+    pub collection: String,
+    pub items: u64,
+    /// stored body bytes, an offloaded field only counts its blob reference
+    pub body_bytes: u64,
+    /// bytes of the files attached to the items
+    pub attachment_bytes: u64,
+}
+
+/// Numbers of the user's items in one collection, see `Store::collection_stats`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct CollectionStats {
+    pub collection: String,
+    pub count: u64,
+    /// creation time of the oldest and newest item, none without items
+    pub oldest: Option<DateTime<Utc>>,
+    pub newest: Option<DateTime<Utc>>,
+    /// latest update over the items
+    pub last_updated: Option<DateTime<Utc>>,
+    /// the items grouped by parent, empty for a root collection
+    pub parents: Vec<ParentCount>,
+}
+
+impl salvo::Scribe for CollectionStats {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct ParentCount {
+    pub parent_id: Id,
+    pub count: u64,
+}
+
+/// Reads of one item of an `x-access-stats` collection, see `Store::access_stats`.
+#[derive(
+    Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema, salvo::oapi::ToResponse,
+)]
+pub struct AccessStats {
+    pub reads: u64,
+    /// none before the first read
+    pub last_accessed: Option<DateTime<Utc>>,
+}
+
+impl salvo::Scribe for AccessStats {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// Named filter and sort of a collection saved by a user, run by name in the listings.
+#[derive(Debug, Clone, Serialize, Deserialize, salvo::oapi::ToSchema, salvo::oapi::ToResponse)]
+pub struct SavedView {
+    pub name: String,
+    /// filter expression as the query endpoint takes it, none lists everything
+    #[salvo(schema(value_type = Option<Object>))]
+    pub filter: Option<FilterExpr>,
+    /// none keeps the id order of the listings
+    pub sort: Option<SortSpec>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl salvo::Scribe for SavedView {
+    fn render(self, res: &mut salvo::Response) {
+        res.render(salvo::writing::Json(self));
+    }
+}
+
+/// One document visited by the permission check.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, salvo::oapi::ToSchema)]
+pub struct PermissionStep {
+    pub collection: String,
+    pub data_id: Id,
+    pub owner: Uid,
+    /// operations needed on this document, the append depth grows by one per ancestor
+    pub required: Vec<String>,
+    pub owner_match: bool,
+    /// ACL entry of the user on this document, if any
+    pub access_level: Option<AccessLevel>,
+    pub acl_match: bool,
+}
+
 impl From<AccessLevel> for ACLMask {
     fn from(level: AccessLevel) -> Self {
         match level {
@@ -1,4 +1,27 @@
 // user manager related constants
 pub const USER_TABLE: &str = "users";
 pub const FRIENDS_TABLE: &str = "friends";
+pub const PASSKEY_TABLE: &str = "passkeys";
 pub const ROOT_OWNER: &str = "root";
+
+// notification manager related constants
+pub const NOTIFICATION_TABLE: &str = "notifications";
+
+// replication related constants
+// pseudo namespaces replicating the inner databases
+pub const USERS_NAMESPACE: &str = "__users";
+pub const NOTIFICATIONS_NAMESPACE: &str = "__notifications";
+
+// request limits, advertised by the meta endpoint
+pub const MAX_BATCH_ITEMS: usize = 100;
+pub const MAX_BULK_INSERT_ITEMS: usize = 1000;
+// cap of a gzip request body once decompressed
+pub const MAX_DECOMPRESSED_BODY_SIZE: usize = 32 * 1024 * 1024;
+pub const MAX_ATTACHMENT_SIZE: usize = 16 * 1024 * 1024;
+
+// read snapshots, each one holds a connection of its namespace until released or expired
+pub const MAX_READ_SNAPSHOTS: usize = 4;
+pub const READ_SNAPSHOT_TTL_SECS: u64 = 60;
+
+// one-time upload urls of attachments
+pub const UPLOAD_URL_TTL_SECS: u64 = 300;
@@ -0,0 +1,187 @@
+//! Fake documents generated from a collection's JSON schema, to fill a development database.
+//!
+//! Covers `type`, `enum`, `const`, `anyOf`/`oneOf`/`allOf`, `$ref` to the registered schemas, the
+//! length and range bounds and the common string formats. `pattern`, custom keywords and references
+//! to other documents are not followed, the store's validation still has the last word.
+
+use std::collections::HashMap;
+
+use rand::{Rng, seq::IndexedRandom};
+use serde_json::{Map, Number, Value, json};
+
+use crate::backend::schema::SCHEMA_REF_PREFIX;
+
+const MAX_DEPTH: usize = 8;
+const MAX_ARRAY_ITEMS: u64 = 3;
+
+const WORDS: [&str; 24] = [
+    "alpha", "amber", "breeze", "cedar", "cloud", "coral", "delta", "ember", "fable", "garden", "harbor", "island",
+    "jade", "lantern", "maple", "meadow", "nova", "orbit", "pebble", "quartz", "river", "summit", "timber", "willow",
+];
+
+pub struct FakeGenerator<'a> {
+    // dict<schema name, schema>, what `urn:syncstore:<name>` references resolve to
+    resources: &'a HashMap<String, Value>,
+}
+
+impl<'a> FakeGenerator<'a> {
+    pub fn new(resources: &'a HashMap<String, Value>) -> Self {
+        Self { resources }
+    }
+
+    /// A value for the schema, objects get every required property and about half of the others.
+    pub fn generate(&self, schema: &Value, rng: &mut impl Rng) -> Value {
+        self.value(schema, rng, 0)
+    }
+
+    fn value(&self, schema: &Value, rng: &mut impl Rng, depth: usize) -> Value {
+        let Some(schema) = schema.as_object() else {
+            return Value::Null;
+        };
+        if depth > MAX_DEPTH {
+            return Value::Null;
+        }
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            return match reference
+                .strip_prefix(SCHEMA_REF_PREFIX)
+                .and_then(|name| self.resources.get(name))
+            {
+                Some(target) => self.value(target, rng, depth + 1),
+                None => Value::Null,
+            };
+        }
+        if let Some(value) = schema.get("const") {
+            return value.clone();
+        }
+        if let Some(value) = schema
+            .get("enum")
+            .and_then(|e| e.as_array())
+            .and_then(|e| e.choose(rng))
+        {
+            return value.clone();
+        }
+        if let Some(branches) = schema
+            .get("anyOf")
+            .or_else(|| schema.get("oneOf"))
+            .and_then(|b| b.as_array())
+            .filter(|b| !b.is_empty())
+        {
+            let branch = &branches[rng.random_range(0..branches.len())];
+            return self.value(branch, rng, depth + 1);
+        }
+        if let Some(parts) = schema.get("allOf").and_then(|p| p.as_array()) {
+            // object parts merged, the last scalar part wins otherwise
+            let mut merged = self.typed(schema, rng, depth);
+            for part in parts {
+                match (&mut merged, self.value(part, rng, depth + 1)) {
+                    (Value::Object(merged), Value::Object(part)) => merged.extend(part),
+                    (merged, part) => *merged = part,
+                }
+            }
+            return merged;
+        }
+        self.typed(schema, rng, depth)
+    }
+
+    fn typed(&self, schema: &Map<String, Value>, rng: &mut impl Rng, depth: usize) -> Value {
+        // a nullable type is generated as its other type
+        let ty = match schema.get("type") {
+            Some(Value::String(ty)) => ty.as_str(),
+            Some(Value::Array(types)) => types
+                .iter()
+                .filter_map(|t| t.as_str())
+                .find(|t| *t != "null")
+                .unwrap_or("null"),
+            _ if schema.contains_key("properties") => "object",
+            _ => "string",
+        };
+        match ty {
+            "object" => self.object(schema, rng, depth),
+            "array" => {
+                let min = schema.get("minItems").and_then(|v| v.as_u64()).unwrap_or(0);
+                let max = schema
+                    .get("maxItems")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(u64::MAX)
+                    .min(min.max(MAX_ARRAY_ITEMS))
+                    .max(min);
+                let len = rng.random_range(min..=max);
+                let items = schema.get("items").cloned().unwrap_or(json!({}));
+                Value::Array((0..len).map(|_| self.value(&items, rng, depth + 1)).collect())
+            }
+            "integer" => {
+                let (min, max) = bounds(schema, 0.0, 1000.0);
+                let (min, max) = (min.ceil() as i64, max.floor() as i64);
+                Value::from(if min <= max { rng.random_range(min..=max) } else { min })
+            }
+            "number" => {
+                let (min, max) = bounds(schema, 0.0, 1000.0);
+                let n = if min < max { rng.random_range(min..max) } else { min };
+                Number::from_f64((n * 100.0).round() / 100.0).map_or(Value::Null, Value::Number)
+            }
+            "boolean" => Value::Bool(rng.random_bool(0.5)),
+            "null" => Value::Null,
+            _ => Value::String(string(schema, rng)),
+        }
+    }
+
+    fn object(&self, schema: &Map<String, Value>, rng: &mut impl Rng, depth: usize) -> Value {
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|f| f.as_str()).collect())
+            .unwrap_or_default();
+        let mut object = Map::new();
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, property) in properties {
+                if required.contains(&name.as_str()) || rng.random_bool(0.5) {
+                    object.insert(name.clone(), self.value(property, rng, depth + 1));
+                }
+            }
+        }
+        Value::Object(object)
+    }
+}
+
+// inclusive range of a number, the exclusive bounds are moved inward by one
+fn bounds(schema: &Map<String, Value>, default_min: f64, default_max: f64) -> (f64, f64) {
+    let get = |key: &str| schema.get(key).and_then(|v| v.as_f64());
+    let min = get("minimum").or_else(|| get("exclusiveMinimum").map(|m| m + 1.0));
+    let max = get("maximum").or_else(|| get("exclusiveMaximum").map(|m| m - 1.0));
+    match (min, max) {
+        (Some(min), Some(max)) => (min, max),
+        (Some(min), None) => (min, min + (default_max - default_min)),
+        (None, Some(max)) => (max - (default_max - default_min), max),
+        (None, None) => (default_min, default_max),
+    }
+}
+
+fn string(schema: &Map<String, Value>, rng: &mut impl Rng) -> String {
+    let now = chrono::Utc::now();
+    // within the last year
+    let ago = chrono::Duration::minutes(rng.random_range(0..60 * 24 * 365));
+    match schema.get("format").and_then(|f| f.as_str()) {
+        Some("date-time") => return (now - ago).to_rfc3339(),
+        Some("date") => return (now - ago).format("%Y-%m-%d").to_string(),
+        Some("email") => return format!("{}{}@example.com", word(rng), rng.random_range(1..1000)),
+        Some("uri") | Some("url") => return format!("https://example.com/{}", word(rng)),
+        Some("uuid") => return uuid::Uuid::new_v4().to_string(),
+        _ => {}
+    }
+    let min = schema.get("minLength").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
+    let max = schema.get("maxLength").and_then(|v| v.as_u64()).map(|m| m as usize);
+    let target = rng.random_range(1..=4);
+    let mut text = (0..target).map(|_| word(rng)).collect::<Vec<_>>().join(" ");
+    while text.chars().count() < min {
+        text.push(' ');
+        text.push_str(word(rng));
+    }
+    match max {
+        Some(max) => text.chars().take(max).collect(),
+        None => text,
+    }
+}
+
+fn word(rng: &mut impl Rng) -> &'static str {
+    WORDS[rng.random_range(0..WORDS.len())]
+}
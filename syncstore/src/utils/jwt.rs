@@ -10,8 +10,8 @@ use crate::{
 static ACCESS_TOKEN_SECRET: OnceLock<String> = OnceLock::new();
 static REFRESH_TOKEN_SECRET: OnceLock<String> = OnceLock::new();
 
-const ACCESS_TOKEN_EXPIRATION: i64 = 3600; // 1 hour
-const REFRESH_TOKEN_EXPIRATION: i64 = 604800; // 7 days
+pub(crate) const ACCESS_TOKEN_EXPIRATION: i64 = 3600; // 1 hour
+pub(crate) const REFRESH_TOKEN_EXPIRATION: i64 = 604800; // 7 days
 
 pub fn set_jwt_config(jwt: &Jwt) {
     ACCESS_TOKEN_SECRET.set(jwt.access_secret.clone()).ok();
@@ -1,3 +1,5 @@
 pub mod constant;
+pub mod fake;
 pub mod hpke;
 pub mod jwt;
+pub mod passkey;
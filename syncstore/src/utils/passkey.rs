@@ -0,0 +1,364 @@
+//! WebAuthn ceremonies of the passkey login, ES256 credentials only.
+//!
+//! Attestation statements are not checked, the server asks for `"attestation": "none"` and trusts
+//! the authenticator the user registered from their own session. Both ceremonies require user
+//! presence and user verification, a passkey replaces the password rather than adding a factor.
+
+use base64::Engine;
+use ciborium::Value as Cbor;
+use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use rand::Rng;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{StoreError, StoreResult};
+
+// authenticator data flags
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+// COSE key parameters of an ES256 P-256 public key
+const COSE_KTY: i64 = 1;
+const COSE_ALG: i64 = 3;
+const COSE_CRV: i64 = -1;
+const COSE_X: i64 = -2;
+const COSE_Y: i64 = -3;
+const COSE_KTY_EC2: i64 = 2;
+const COSE_ALG_ES256: i64 = -7;
+const COSE_CRV_P256: i64 = 1;
+
+/// The site the passkeys are bound to.
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    /// domain the credentials are scoped to, e.g. `example.com`
+    pub id: String,
+    /// origin the browser reports, e.g. `https://app.example.com`
+    pub origin: String,
+}
+
+/// Credential created by a registration ceremony.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewCredential {
+    pub credential_id: Vec<u8>,
+    /// uncompressed SEC1 point of the P-256 public key
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ty: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    // attested credential data and extensions
+    rest: &'a [u8],
+}
+
+/// A fresh random challenge, base64url as the browser echoes it in the client data.
+pub fn new_challenge() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    encode(&bytes)
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decode a base64url field of a browser credential, with or without padding.
+pub fn decode(field: &str, value: &str) -> StoreResult<Vec<u8>> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| StoreError::Validation(format!("`{}` is not base64url", field)))
+}
+
+/// The challenge the client data answers, to find the pending ceremony before verifying it.
+pub fn client_challenge(client_data_json: &[u8]) -> StoreResult<String> {
+    Ok(parse_client_data(client_data_json)?.challenge)
+}
+
+/// Verify the answer to a `navigator.credentials.create()` call and extract the new credential.
+pub fn verify_registration(
+    rp: &RelyingParty,
+    challenge: &str,
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> StoreResult<NewCredential> {
+    check_client_data(rp, challenge, "webauthn.create", client_data_json)?;
+    let attestation: Cbor = ciborium::from_reader(attestation_object)
+        .map_err(|e| StoreError::Validation(format!("invalid attestation object: {}", e)))?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|map| map.iter().find(|(k, _)| k.as_text() == Some("authData")))
+        .and_then(|(_, v)| v.as_bytes())
+        .ok_or_else(|| StoreError::Validation("attestation object without authData".to_string()))?;
+    let auth_data = parse_authenticator_data(auth_data)?;
+    check_authenticator_data(rp, &auth_data)?;
+    if auth_data.flags & FLAG_ATTESTED_CREDENTIAL == 0 {
+        return Err(StoreError::Validation(
+            "authenticator data holds no credential".to_string(),
+        ));
+    }
+
+    // aaguid, credential id length, credential id, then the COSE public key
+    let rest = auth_data.rest;
+    let truncated = || StoreError::Validation("truncated attested credential data".to_string());
+    let id_len = rest.get(16..18).ok_or_else(truncated)?;
+    let id_len = u16::from_be_bytes([id_len[0], id_len[1]]) as usize;
+    let credential_id = rest.get(18..18 + id_len).ok_or_else(truncated)?;
+    let cose_key: Cbor = ciborium::from_reader(&rest[18 + id_len..])
+        .map_err(|e| StoreError::Validation(format!("invalid credential public key: {}", e)))?;
+    Ok(NewCredential {
+        credential_id: credential_id.to_vec(),
+        public_key: es256_public_key(&cose_key)?,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+/// Verify the answer to a `navigator.credentials.get()` call against the stored credential.
+///
+/// Returns the authenticator's new signature counter. A counter that did not move forward means the
+/// credential was cloned and is refused, authenticators without a counter always report zero.
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    challenge: &str,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+    stored_sign_count: u32,
+) -> StoreResult<u32> {
+    check_client_data(rp, challenge, "webauthn.get", client_data_json)?;
+    let auth_data = parse_authenticator_data(authenticator_data)?;
+    check_authenticator_data(rp, &auth_data)?;
+
+    let key = VerifyingKey::from_sec1_bytes(public_key)
+        .map_err(|_| StoreError::Validation("invalid stored public key".to_string()))?;
+    let signature =
+        Signature::from_der(signature).map_err(|_| StoreError::Validation("invalid signature encoding".to_string()))?;
+    let mut signed = authenticator_data.to_vec();
+    signed.extend_from_slice(&Sha256::digest(client_data_json));
+    key.verify(&signed, &signature)
+        .map_err(|_| StoreError::Validation("signature does not match".to_string()))?;
+
+    if (auth_data.sign_count != 0 || stored_sign_count != 0) && auth_data.sign_count <= stored_sign_count {
+        return Err(StoreError::Validation(
+            "signature counter did not increase, the credential may be cloned".to_string(),
+        ));
+    }
+    Ok(auth_data.sign_count)
+}
+
+fn parse_client_data(client_data_json: &[u8]) -> StoreResult<ClientData> {
+    serde_json::from_slice(client_data_json).map_err(|e| StoreError::Validation(format!("invalid client data: {}", e)))
+}
+
+fn check_client_data(rp: &RelyingParty, challenge: &str, ty: &str, client_data_json: &[u8]) -> StoreResult<()> {
+    let client_data = parse_client_data(client_data_json)?;
+    if client_data.ty != ty {
+        return Err(StoreError::Validation(format!(
+            "client data of a `{}` ceremony, expected `{}`",
+            client_data.ty, ty
+        )));
+    }
+    if client_data.challenge != challenge {
+        return Err(StoreError::Validation("challenge does not match".to_string()));
+    }
+    if client_data.origin != rp.origin {
+        return Err(StoreError::Validation(format!(
+            "origin `{}` is not allowed",
+            client_data.origin
+        )));
+    }
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> StoreResult<AuthenticatorData<'_>> {
+    if data.len() < 37 {
+        return Err(StoreError::Validation("truncated authenticator data".to_string()));
+    }
+    Ok(AuthenticatorData {
+        rp_id_hash: &data[..32],
+        flags: data[32],
+        sign_count: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+        rest: &data[37..],
+    })
+}
+
+fn check_authenticator_data(rp: &RelyingParty, auth_data: &AuthenticatorData) -> StoreResult<()> {
+    if auth_data.rp_id_hash != Sha256::digest(rp.id.as_bytes()).as_slice() {
+        return Err(StoreError::Validation(
+            "credential of another relying party".to_string(),
+        ));
+    }
+    if auth_data.flags & FLAG_USER_PRESENT == 0 || auth_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(StoreError::Validation(
+            "user was not verified by the authenticator".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// uncompressed SEC1 point of an ES256 COSE key
+fn es256_public_key(cose_key: &Cbor) -> StoreResult<Vec<u8>> {
+    let map = cose_key
+        .as_map()
+        .ok_or_else(|| StoreError::Validation("credential public key is not a COSE key".to_string()))?;
+    let get = |label: i64| {
+        map.iter()
+            .find(|(k, _)| k.as_integer().map(i128::from) == Some(label as i128))
+            .map(|(_, v)| v)
+    };
+    let int = |label: i64| get(label).and_then(|v| v.as_integer()).map(i128::from);
+    if int(COSE_KTY) != Some(COSE_KTY_EC2 as i128)
+        || int(COSE_ALG) != Some(COSE_ALG_ES256 as i128)
+        || int(COSE_CRV) != Some(COSE_CRV_P256 as i128)
+    {
+        return Err(StoreError::Validation(
+            "only ES256 (P-256) passkeys are supported".to_string(),
+        ));
+    }
+    let coordinate = |label: i64| {
+        get(label)
+            .and_then(|v| v.as_bytes())
+            .filter(|c| c.len() == 32)
+            .ok_or_else(|| StoreError::Validation("invalid EC2 coordinate".to_string()))
+    };
+    let mut point = vec![0x04];
+    point.extend_from_slice(coordinate(COSE_X)?);
+    point.extend_from_slice(coordinate(COSE_Y)?);
+    Ok(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::{SigningKey, signature::Signer};
+
+    use super::*;
+
+    fn rp() -> RelyingParty {
+        RelyingParty {
+            id: "example.com".to_string(),
+            origin: "https://example.com".to_string(),
+        }
+    }
+
+    fn client_data(ty: &str, challenge: &str) -> Vec<u8> {
+        serde_json::json!({ "type": ty, "challenge": challenge, "origin": "https://example.com" })
+            .to_string()
+            .into_bytes()
+    }
+
+    fn auth_data(flags: u8, sign_count: u32, attested: &[u8]) -> Vec<u8> {
+        let mut data = Sha256::digest(b"example.com").to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data.extend_from_slice(attested);
+        data
+    }
+
+    fn attestation_object(key: &SigningKey, credential_id: &[u8]) -> Vec<u8> {
+        let point = key.verifying_key().to_encoded_point(false);
+        let cose_key = Cbor::Map(vec![
+            (Cbor::from(COSE_KTY), Cbor::from(COSE_KTY_EC2)),
+            (Cbor::from(COSE_ALG), Cbor::from(COSE_ALG_ES256)),
+            (Cbor::from(COSE_CRV), Cbor::from(COSE_CRV_P256)),
+            (Cbor::from(COSE_X), Cbor::Bytes(point.x().unwrap().to_vec())),
+            (Cbor::from(COSE_Y), Cbor::Bytes(point.y().unwrap().to_vec())),
+        ]);
+        let mut attested = vec![0u8; 16];
+        attested.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+        attested.extend_from_slice(credential_id);
+        ciborium::into_writer(&cose_key, &mut attested).unwrap();
+        let object = Cbor::Map(vec![
+            (Cbor::from("fmt"), Cbor::from("none")),
+            (Cbor::from("attStmt"), Cbor::Map(vec![])),
+            (Cbor::from("authData"), Cbor::Bytes(auth_data(0x45, 0, &attested))),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&object, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn assertion(key: &SigningKey, challenge: &str, sign_count: u32) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let client_data = client_data("webauthn.get", challenge);
+        let auth_data = auth_data(0x05, sign_count, &[]);
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = key.sign(&signed);
+        (client_data, auth_data, signature.to_der().as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_register_then_assert() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let challenge = new_challenge();
+        let credential = verify_registration(
+            &rp(),
+            &challenge,
+            &client_data("webauthn.create", &challenge),
+            &attestation_object(&key, b"credential-1"),
+        )
+        .unwrap();
+        assert_eq!(credential.credential_id, b"credential-1");
+        assert_eq!(credential.sign_count, 0);
+
+        let challenge = new_challenge();
+        let (client_data, auth_data, signature) = assertion(&key, &challenge, 1);
+        let count = verify_assertion(
+            &rp(),
+            &challenge,
+            &client_data,
+            &auth_data,
+            &signature,
+            &credential.public_key,
+            0,
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+
+        // a replayed counter is refused
+        assert!(
+            verify_assertion(
+                &rp(),
+                &challenge,
+                &client_data,
+                &auth_data,
+                &signature,
+                &credential.public_key,
+                1
+            )
+            .is_err()
+        );
+        // so is an answer to another challenge
+        assert!(
+            verify_assertion(
+                &rp(),
+                "other",
+                &client_data,
+                &auth_data,
+                &signature,
+                &credential.public_key,
+                0
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_signature_of_another_key() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let other = SigningKey::from_slice(&[9u8; 32]).unwrap();
+        let public_key = key.verifying_key().to_encoded_point(false).as_bytes().to_vec();
+        let challenge = new_challenge();
+        let (client_data, auth_data, signature) = assertion(&other, &challenge, 0);
+        assert!(verify_assertion(&rp(), &challenge, &client_data, &auth_data, &signature, &public_key, 0).is_err());
+    }
+}
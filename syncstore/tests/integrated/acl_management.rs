@@ -1,5 +1,5 @@
 use serde_json::json;
-use syncstore::types::{AccessControl, AccessLevel, Permission};
+use syncstore::types::{AccessControl, AccessLevel, AclAction, AclDirection, NamespacePolicy, Permission};
 
 use crate::mock::*;
 
@@ -319,3 +319,260 @@ fn grant_write_can_read_update_insert() -> Result<(), Box<dyn std::error::Error>
 
     Ok(())
 }
+
+#[test]
+fn closed_namespace_rejects_other_users() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo_doc = json!({ "name": "Policy Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo_doc, user1)?;
+    store.update_acl((namespace, "repo"), gen_acl(&repo_id, user2, AccessLevel::Read), user1)?;
+
+    // close the namespace to everyone but user1
+    let policy = NamespacePolicy {
+        open: false,
+        allowed_users: [user1.clone()].into_iter().collect(),
+    };
+    store.set_namespace_policy(namespace, policy)?;
+    assert!(store.accessible_namespaces(user1)?.contains(namespace));
+    assert!(!store.accessible_namespaces(user2)?.contains(namespace));
+
+    store.get(namespace, "repo", &repo_id, user1)?;
+    // the ACL grant no longer helps user2 inside a closed namespace
+    assert_permission_denied(store.get(namespace, "repo", &repo_id, user2));
+    assert_permission_denied(store.insert(namespace, "repo", &repo_doc, user2));
+    assert_permission_denied(store.list_by_owner(namespace, "repo", None, 10, user2));
+
+    // reopen
+    store.set_namespace_policy(namespace, NamespacePolicy::default())?;
+    store.get(namespace, "repo", &repo_id, user2)?;
+
+    // unknown namespaces can't carry a policy
+    assert_not_found(store.set_namespace_policy("no_such_ns", NamespacePolicy::default()));
+
+    Ok(())
+}
+
+#[test]
+fn explain_permission_decision_path() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user1)?;
+    let comment_id = store.insert(
+        namespace,
+        "comment",
+        &json!({ "content": "hi", "post_id": post_id }),
+        user1,
+    )?;
+    store.update_acl(
+        (namespace, "repo"),
+        gen_acl(&repo_id, user2, AccessLevel::ReadAppend2),
+        user1,
+    )?;
+
+    // owner decides at the first step
+    let explained = store.explain_permission((namespace, "comment"), &comment_id, user1, AclAction::Update, user1)?;
+    assert!(explained.allowed);
+    assert_eq!(explained.path.len(), 1);
+    assert!(explained.path[0].owner_match);
+
+    // read is inherited from the repo acl, two levels up
+    let explained = store.explain_permission((namespace, "comment"), &comment_id, user2, AclAction::Read, user2)?;
+    assert!(explained.allowed);
+    assert_eq!(explained.path.len(), 3);
+    assert_eq!(explained.path[2].collection, "repo");
+    assert_eq!(explained.path[2].access_level, Some(AccessLevel::ReadAppend2));
+    assert!(explained.path[2].acl_match);
+    assert_eq!(explained.reason, format!("acl `read_append2` on repo/{}", repo_id));
+
+    // appending below the post needs append 2 levels below the repo
+    let explained = store.explain_permission((namespace, "post"), &post_id, user2, AclAction::Append, user2)?;
+    assert!(explained.allowed);
+    assert_eq!(explained.path[0].required, vec!["append_1_below"]);
+    assert_eq!(explained.path[1].required, vec!["append_2_below"]);
+
+    // update is not granted anywhere on the chain
+    let explained = store.explain_permission((namespace, "comment"), &comment_id, user2, AclAction::Update, user1)?;
+    assert!(!explained.allowed);
+    assert_eq!(explained.path.len(), 3);
+    assert!(!explained.path[2].acl_match);
+
+    // explaining another user's access needs full access
+    assert_permission_denied(store.explain_permission((namespace, "repo"), &repo_id, user1, AclAction::Read, user2));
+    assert_not_found(store.explain_permission((namespace, "repo"), "missing", user1, AclAction::Read, user1));
+
+    Ok(())
+}
+
+#[test]
+fn check_permissions_batch() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user1)?;
+    store.update_acl(
+        (namespace, "repo"),
+        gen_acl(&repo_id, user2, AccessLevel::Update),
+        user1,
+    )?;
+
+    let checks = [
+        ("repo", repo_id.as_str(), AclAction::Read),
+        ("repo", repo_id.as_str(), AclAction::Update),
+        ("repo", repo_id.as_str(), AclAction::Delete),
+        ("post", post_id.as_str(), AclAction::Update),
+        ("post", post_id.as_str(), AclAction::Append),
+        ("post", "missing", AclAction::Read),
+    ];
+    let allowed = store.check_permissions(namespace, &checks, user2)?;
+    assert_eq!(allowed, vec![true, true, false, true, false, false]);
+    let allowed = store.check_permissions(namespace, &checks, user1)?;
+    assert_eq!(allowed, vec![true, true, true, true, true, false]);
+
+    // users outside a closed namespace are denied everything
+    let policy = NamespacePolicy {
+        open: false,
+        allowed_users: [user1.clone()].into_iter().collect(),
+    };
+    store.set_namespace_policy(namespace, policy)?;
+    let allowed = store.check_permissions(namespace, &checks, user2)?;
+    assert!(allowed.iter().all(|a| !a));
+
+    Ok(())
+}
+
+#[test]
+fn acls_listed_by_granter_and_receiver() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let mut repo_ids = Vec::new();
+    for i in 0..3 {
+        let repo = json!({ "name": format!("Repo {}", i), "status": "normal" });
+        let repo_id = store.insert(namespace, "repo", &repo, user1)?;
+        store.update_acl((namespace, "repo"), gen_acl(&repo_id, user2, AccessLevel::Read), user1)?;
+        repo_ids.push(repo_id);
+    }
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_ids[0] });
+    let post_id = store.insert(namespace, "post", &post, user1)?;
+    store.update_acl((namespace, "post"), gen_acl(&post_id, user2, AccessLevel::Write), user1)?;
+
+    // granted entries are paged
+    let (page, marker) = store.list_acls(namespace, AclDirection::Granted, None, None, 3, user1)?;
+    assert_eq!(page.len(), 3);
+    let (rest, next) = store.list_acls(namespace, AclDirection::Granted, None, marker, 3, user1)?;
+    assert_eq!(rest.len(), 1);
+    assert!(next.is_none());
+    assert!(
+        page.iter()
+            .chain(&rest)
+            .all(|e| e.granted_by == *user1 && e.user == *user2)
+    );
+
+    // received entries filtered by collection
+    let (received, _) = store.list_acls(namespace, AclDirection::Received, Some("post"), None, 10, user2)?;
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].data_id, post_id);
+    assert_eq!(received[0].access_level, AccessLevel::Write);
+    let (received, _) = store.list_acls(namespace, AclDirection::Received, Some("repo"), None, 10, user2)?;
+    assert_eq!(received.len(), 3);
+
+    // nothing the other way around
+    assert!(
+        store
+            .list_acls(namespace, AclDirection::Granted, None, None, 10, user2)?
+            .0
+            .is_empty()
+    );
+    assert!(
+        store
+            .list_acls(namespace, AclDirection::Received, None, None, 10, user1)?
+            .0
+            .is_empty()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn acls_applied_in_bulk() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let mut repo_ids = Vec::new();
+    for i in 0..3 {
+        let repo = json!({ "name": format!("Repo {}", i), "status": "normal" });
+        repo_ids.push(store.insert(namespace, "repo", &repo, user1)?);
+    }
+    let read = || {
+        vec![Permission {
+            user: user2.to_string(),
+            access_level: AccessLevel::Read,
+        }]
+    };
+
+    // nothing applied when one of the data is not owned by the user
+    let foreign = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "Foreign", "status": "normal" }),
+        user2,
+    )?;
+    let mut with_foreign = repo_ids.clone();
+    with_foreign.push(foreign);
+    assert_permission_denied(store.update_acls_bulk((namespace, "repo"), &with_foreign, read(), vec![], user1));
+    assert_permission_denied(store.get(namespace, "repo", &repo_ids[0], user2));
+
+    store.update_acls_bulk((namespace, "repo"), &repo_ids, read(), vec![], user1)?;
+    for repo_id in &repo_ids {
+        assert_eq!(store.get(namespace, "repo", repo_id, user2)?.owner, *user1);
+    }
+
+    store.update_acls_bulk(
+        (namespace, "repo"),
+        &repo_ids[..2],
+        vec![],
+        vec![user2.to_string()],
+        user1,
+    )?;
+    assert_permission_denied(store.get(namespace, "repo", &repo_ids[0], user2));
+    assert_permission_denied(store.get(namespace, "repo", &repo_ids[1], user2));
+    store.get(namespace, "repo", &repo_ids[2], user2)?;
+
+    // granting and revoking the same user is ambiguous
+    assert_validation_error(store.update_acls_bulk(
+        (namespace, "repo"),
+        &repo_ids,
+        read(),
+        vec![user2.to_string()],
+        user1,
+    ));
+
+    Ok(())
+}
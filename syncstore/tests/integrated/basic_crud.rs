@@ -1,6 +1,20 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
 use crate::mock::*;
 use itertools::Itertools;
 use serde_json::json;
+use syncstore::{
+    backend::{
+        blob::{BlobOffload, FsBlobStore},
+        filter::FilterExpr,
+        patch::PatchOp,
+    },
+    error::StoreError,
+    store::Listing,
+    types::{AccessControl, AccessLevel, BatchOutcome, ImportMode, Imported, ListOrder, PageCursor, Permission},
+};
 
 #[test]
 fn owner_basic_crud() -> Result<(), Box<dyn std::error::Error>> {
@@ -136,6 +150,44 @@ fn insert_and_list_owner_data() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn list_owner_data_under_parent() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Repo A", "status": "normal" });
+    let repo_a = store.insert(namespace, "repo", &repo, user1)?;
+    let repo = json!({ "name": "Repo B", "status": "normal" });
+    let repo_b = store.insert(namespace, "repo", &repo, user1)?;
+    let mut in_a = Vec::new();
+    for i in 0..3 {
+        let post = json!({ "title": format!("A{}", i), "category": "c", "content": "content", "repo_id": repo_a });
+        in_a.push(store.insert(namespace, "post", &post, user1)?);
+    }
+    let post = json!({ "title": "B", "category": "c", "content": "content", "repo_id": repo_b });
+    store.insert(namespace, "post", &post, user1)?;
+
+    let (page1, marker) = store.list_by_owner_under(namespace, "post", &repo_a, None, 2, user1)?;
+    assert_eq!(page1.len(), 2);
+    let (page2, marker) = store.list_by_owner_under(namespace, "post", &repo_a, marker, 2, user1)?;
+    assert!(marker.is_none());
+    let mut listed: Vec<String> = page1.into_iter().chain(page2).map(|p| p.id).collect();
+    listed.sort();
+    in_a.sort();
+    assert_eq!(listed, in_a);
+
+    // only the user's own items, the parent is not checked
+    let (items, _) = store.list_by_owner_under(namespace, "post", &repo_a, None, 10, user2)?;
+    assert!(items.is_empty());
+    assert_not_found(store.list_by_owner_under(namespace, "repo", &repo_a, None, 10, user1));
+
+    Ok(())
+}
+
 #[test]
 fn validate_child_parent_data() -> Result<(), Box<dyn std::error::Error>> {
     let s = BasicTestSuite::new()?;
@@ -197,3 +249,939 @@ fn list_with_permission_includes_children_of_owned_parent() -> Result<(), Box<dy
 
     Ok(())
 }
+
+#[test]
+fn move_item_to_other_parent() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Repo A", "status": "normal" });
+    let repo_a = store.insert(namespace, "repo", &repo, user1)?;
+    let repo = json!({ "name": "Repo B", "status": "normal" });
+    let repo_b = store.insert(namespace, "repo", &repo, user1)?;
+    let repo = json!({ "name": "Repo C", "status": "normal" });
+    let repo_c = store.insert(namespace, "repo", &repo, user2)?;
+
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_a });
+    let post_id = store.insert(namespace, "post", &post, user1)?;
+
+    // move updates both the body field and the parent_id column
+    let item = store.move_item(namespace, "post", &post_id, &repo_b, user1)?;
+    assert_eq!(item.parent_id.as_deref(), Some(repo_b.as_str()));
+    assert_eq!(item.body["repo_id"], repo_b);
+    let (children, _) = store.list_children(namespace, "post", &repo_b, None, 10, user1)?;
+    assert_eq!(children.len(), 1);
+    let (children, _) = store.list_children(namespace, "post", &repo_a, None, 10, user1)?;
+    assert!(children.is_empty());
+
+    // no permission to append below other user's repo
+    assert_permission_denied(store.move_item(namespace, "post", &post_id, &repo_c, user1));
+    // no permission on the item itself
+    assert_permission_denied(store.move_item(namespace, "post", &post_id, &repo_c, user2));
+    // new parent must exist
+    assert_not_found(store.move_item(namespace, "post", &post_id, "missing", user1));
+    // root collections can not be moved
+    assert_validation_error(store.move_item(namespace, "repo", &repo_a, &repo_b, user1));
+
+    Ok(())
+}
+
+#[test]
+fn locked_item_rejects_other_writers() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user1)?;
+    let acl = AccessControl {
+        data_id: repo_id.clone(),
+        permissions: vec![Permission {
+            user: user2.clone(),
+            access_level: AccessLevel::Write,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user1)?;
+
+    let lock = store.lock(namespace, "repo", &repo_id, user2, Duration::from_secs(60))?;
+    assert_eq!(&lock.holder, user2);
+    assert_eq!(store.get(namespace, "repo", &repo_id, user1)?.lock, Some(lock.clone()));
+
+    // the holder keeps writing, everyone else is rejected
+    store.update(namespace, "repo", &repo_id, &repo, user2)?;
+    match store.update(namespace, "repo", &repo_id, &repo, user1) {
+        Err(StoreError::Conflict(_)) => {}
+        rest => panic!("Expected Conflict error, got: {:?}", rest),
+    }
+    assert!(
+        store
+            .lock(namespace, "repo", &repo_id, user1, Duration::from_secs(60))
+            .is_err()
+    );
+    // refreshing keeps the acquire time
+    let refreshed = store.lock(namespace, "repo", &repo_id, user2, Duration::from_secs(120))?;
+    assert_eq!(refreshed.acquired_at, lock.acquired_at);
+    assert_validation_error(store.lock(namespace, "repo", &repo_id, user2, Duration::ZERO));
+
+    // the owner can break the lock
+    store.unlock(namespace, "repo", &repo_id, user1)?;
+    assert_eq!(store.get(namespace, "repo", &repo_id, user1)?.lock, None);
+    store.update(namespace, "repo", &repo_id, &repo, user1)?;
+
+    // other users can not release a lock they do not hold
+    store.lock(namespace, "repo", &repo_id, user1, Duration::from_secs(60))?;
+    assert_permission_denied(store.unlock(namespace, "repo", &repo_id, user2));
+    store.delete(namespace, "repo", &repo_id, user1)?;
+
+    Ok(())
+}
+
+#[test]
+fn writes_with_stale_revision_conflict() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo_a = store.insert(namespace, "repo", &json!({ "name": "A", "status": "normal" }), user)?;
+    let repo_b = store.insert(namespace, "repo", &json!({ "name": "B", "status": "normal" }), user)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_a });
+    let post_id = store.insert(namespace, "post", &post, user)?;
+    assert_eq!(store.get(namespace, "post", &post_id, user)?.rev, 1);
+
+    let edited = json!({ "title": "Edited", "category": "c", "content": "content", "repo_id": repo_a });
+    let item = store.update_with_reason(namespace, "post", &post_id, &edited, Some(1), user, None)?;
+    assert_eq!(item.rev, 2);
+    // a writer still holding revision 1 does not overwrite the edit
+    assert!(matches!(
+        store.update_with_reason(namespace, "post", &post_id, &post, Some(1), user, None),
+        Err(StoreError::Conflict(_))
+    ));
+    assert_eq!(store.get(namespace, "post", &post_id, user)?.body["title"], "Edited");
+
+    // every change raises it, also the ones not asking for a revision
+    assert_eq!(store.update(namespace, "post", &post_id, &edited, user)?.rev, 3);
+    assert_eq!(store.move_item(namespace, "post", &post_id, &repo_b, user)?.rev, 4);
+    let (items, _) = store.list_by_owner(namespace, "post", None, 10, user)?;
+    assert_eq!(items[0].rev, 4);
+
+    assert!(matches!(
+        store.delete_with_reason(namespace, "post", &post_id, Some(3), user, None),
+        Err(StoreError::Conflict(_))
+    ));
+    assert_not_found(store.delete_with_reason(namespace, "post", "missing", Some(1), user, None));
+    store.delete_with_reason(namespace, "post", &post_id, Some(4), user, None)?;
+    assert_not_found(store.get(namespace, "post", &post_id, user));
+
+    Ok(())
+}
+
+#[test]
+fn insert_with_client_generated_id() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "A", "status": "normal" });
+    let id = store.insert_with_id(namespace, "repo", Some("local-repo_1"), &repo, user)?;
+    assert_eq!(id, "local-repo_1");
+    // a child refers to the id the client chose
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": id });
+    let post_id = store.insert_with_id(namespace, "post", Some("local-post"), &post, user)?;
+    assert_eq!(
+        store.get(namespace, "post", &post_id, user)?.parent_id.as_deref(),
+        Some("local-repo_1")
+    );
+
+    let other = json!({ "name": "B", "status": "normal" });
+    assert!(matches!(
+        store.insert_with_id(namespace, "repo", Some("local-repo_1"), &other, user),
+        Err(StoreError::Conflict(_))
+    ));
+    for invalid in ["", "has space", "slash/id", &"x".repeat(65)] {
+        assert_validation_error(store.insert_with_id(namespace, "repo", Some(invalid), &other, user));
+    }
+    // generated by the server without one
+    let generated = store.insert_with_id(namespace, "repo", None, &other, user)?;
+    assert!(uuid::Uuid::parse_str(&generated).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn history_records_actor_and_reason() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    let renamed = json!({ "name": "Renamed", "status": "normal" });
+    store.update_with_reason(namespace, "repo", &repo_id, &renamed, None, user1, Some("typo in name"))?;
+    assert_validation_error(store.update_with_reason(
+        namespace,
+        "repo",
+        &repo_id,
+        &renamed,
+        None,
+        user1,
+        Some(&"x".repeat(501)),
+    ));
+
+    let (items, next_marker) = store.history(namespace, "repo", &repo_id, user1, None, 1)?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].meta.reason.as_deref(), Some("typo in name"));
+    assert_eq!(items[0].meta.actor.as_deref(), Some(user1.as_str()));
+    let (items, next_marker) = store.history(namespace, "repo", &repo_id, user1, next_marker, 10)?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].meta.reason, None);
+    assert!(next_marker.is_none());
+    assert_permission_denied(store.history(namespace, "repo", &repo_id, user2, None, 10));
+
+    store.delete_with_reason(namespace, "repo", &repo_id, None, user1, Some("cleanup"))?;
+    let (items, _) = store.history(namespace, "repo", &repo_id, user1, None, 10)?;
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].op.name(), "delete");
+    assert_eq!(items[0].meta.reason.as_deref(), Some("cleanup"));
+    // only the last owner sees the history of deleted data
+    assert_not_found(store.history(namespace, "repo", &repo_id, user2, None, 10));
+
+    Ok(())
+}
+
+#[test]
+fn oversized_fields_offloaded_to_blobs() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    store.set_blob_offload(Some(BlobOffload {
+        store: Arc::new(FsBlobStore::new(s.path.join("blobs"))?),
+        threshold: 64,
+    }))?;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user)?;
+    let other_repo_id = store.insert(namespace, "repo", &json!({ "name": "Other", "status": "normal" }), user)?;
+    let content = "x".repeat(1024);
+    let post = json!({ "title": "Post", "category": "c", "content": content, "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user)?;
+
+    // the row keeps a reference, small fields stay inline
+    let item = store.get(namespace, "post", &post_id, user)?;
+    assert_eq!(item.body["title"], "Post");
+    assert!(item.body["content"]["$blob"]["sha256"].is_string());
+    assert_eq!(item.body["content"]["$blob"]["size"], content.len() + 2);
+    let item = store.get_inlined(namespace, "post", &post_id, user)?;
+    assert_eq!(item.body, post);
+
+    // references sent back are resolved before validation
+    let mut body = store.get(namespace, "post", &post_id, user)?.body;
+    body["title"] = json!("Renamed");
+    store.update(namespace, "post", &post_id, &body, user)?;
+    let item = store.get_inlined(namespace, "post", &post_id, user)?;
+    assert_eq!(item.body["title"], "Renamed");
+    assert_eq!(item.body["content"], content.as_str());
+
+    store.move_item(namespace, "post", &post_id, &other_repo_id, user)?;
+    let item = store.get_inlined(namespace, "post", &post_id, user)?;
+    assert_eq!(item.body["repo_id"], other_repo_id.as_str());
+    assert_eq!(item.body["content"], content.as_str());
+
+    // a reference to an unknown blob is rejected
+    body["content"]["$blob"]["sha256"] = json!("0".repeat(64));
+    assert!(store.update(namespace, "post", &post_id, &body, user).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn attachments_follow_item_acl_and_lifecycle() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user1)?;
+
+    let attachment = store.add_attachment((namespace, "post"), &post_id, "cover.png", "image/png", b"png", user1)?;
+    assert_eq!(attachment.size, 3);
+    assert_validation_error(store.add_attachment((namespace, "post"), &post_id, " ", "image/png", b"png", user1));
+    assert_permission_denied(store.add_attachment((namespace, "post"), &post_id, "x.png", "image/png", b"png", user2));
+    assert_permission_denied(store.get_attachment((namespace, "post"), &post_id, &attachment.id, user2));
+    // the checks of an upload url, nothing is stored
+    store.check_attachable((namespace, "post"), &post_id, "later.bin", user1)?;
+    assert_permission_denied(store.check_attachable((namespace, "post"), &post_id, "later.bin", user2));
+    assert_validation_error(store.check_attachable((namespace, "post"), &post_id, "", user1));
+    assert_eq!(store.list_attachments((namespace, "post"), &post_id, user1)?.len(), 1);
+
+    // listed with the item
+    let item = store.get(namespace, "post", &post_id, user1)?;
+    assert_eq!(item.attachments, vec![attachment.clone()]);
+
+    // readable through the ACL inherited from the parent repo
+    let acl = AccessControl {
+        data_id: repo_id.clone(),
+        permissions: vec![Permission {
+            user: user2.clone(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user1)?;
+    let content = store.get_attachment((namespace, "post"), &post_id, &attachment.id, user2)?;
+    assert_eq!(content.content, b"png");
+    assert_eq!(content.attachment, attachment);
+    assert_permission_denied(store.delete_attachment((namespace, "post"), &post_id, &attachment.id, user2));
+
+    let other = store.add_attachment(
+        (namespace, "post"),
+        &post_id,
+        "notes.txt",
+        "text/plain",
+        b"notes",
+        user1,
+    )?;
+    store.delete_attachment((namespace, "post"), &post_id, &other.id, user1)?;
+    assert_eq!(store.list_attachments((namespace, "post"), &post_id, user1)?.len(), 1);
+
+    // deleted along with the item
+    store.delete(namespace, "post", &post_id, user1)?;
+    let backend = store.get_data_backend(namespace)?;
+    assert!(backend.list_attachments("post", &post_id)?.is_empty());
+    assert_not_found(store.get_attachment((namespace, "post"), &post_id, &attachment.id, user1));
+
+    Ok(())
+}
+
+#[test]
+fn query_with_filter_expression() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    let mut ids = Vec::new();
+    for (title, category) in [("a", "news"), ("b", "news"), ("c", "blog"), ("d", "misc")] {
+        let post = json!({ "title": title, "category": category, "content": "content", "repo_id": repo_id });
+        ids.push(store.insert(namespace, "post", &post, user1)?);
+    }
+    let titles = |items: Vec<syncstore::types::DataItem>| -> Vec<String> {
+        items
+            .into_iter()
+            .map(|i| i.body["title"].as_str().unwrap().to_string())
+            .sorted()
+            .collect()
+    };
+
+    let filter = serde_json::from_value(json!({
+        "or": [
+            { "and": [{ "eq": ["category", "news"] }, { "ne": ["title", "a"] }] },
+            { "in": ["category", ["blog"]] }
+        ]
+    }))?;
+    let (items, _) = store.query(namespace, "post", None, &filter, None, 10, user1)?;
+    assert_eq!(titles(items), vec!["b", "c"]);
+
+    // meta columns, timestamps compared whatever their offset
+    let filter = serde_json::from_value(json!({
+        "and": [
+            { "eq": ["parent_id", repo_id] },
+            { "gt": ["created_at", "2000-01-01T08:00:00+08:00"] },
+            { "not": { "eq": ["category", "news"] } }
+        ]
+    }))?;
+    let (items, _) = store.query(namespace, "post", None, &filter, None, 10, user1)?;
+    assert_eq!(titles(items), vec!["c", "d"]);
+
+    // paged by id
+    let filter = serde_json::from_value(json!({ "exists": "title" }))?;
+    let (items, next_marker) = store.query(namespace, "post", None, &filter, None, 3, user1)?;
+    assert_eq!(items.len(), 3);
+    let (items, next_marker) = store.query(namespace, "post", None, &filter, next_marker, 3, user1)?;
+    assert_eq!(items.len(), 1);
+    assert!(next_marker.is_none());
+
+    // other users only see their own items, or children of parents they can read
+    let (items, _) = store.query(namespace, "post", None, &filter, None, 10, user2)?;
+    assert!(items.is_empty());
+    assert_permission_denied(store.query(namespace, "post", Some(&repo_id), &filter, None, 10, user2));
+    let (items, _) = store.query(namespace, "post", Some(&repo_id), &filter, None, 10, user1)?;
+    assert_eq!(items.len(), 4);
+
+    // fields never reach the SQL text
+    let filter = serde_json::from_value(json!({ "eq": ["title') OR 1=1 --", "a"] }))?;
+    assert_validation_error(store.query(namespace, "post", None, &filter, None, 10, user1));
+    let filter = serde_json::from_value(json!({ "eq": ["title", ["a"]] }))?;
+    assert_validation_error(store.query(namespace, "post", None, &filter, None, 10, user1));
+
+    Ok(())
+}
+
+#[test]
+fn compact_filter_from_query_string() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    for (title, category) in [("a", "tech"), ("1", "tech"), ("c", "news")] {
+        let post = json!({ "title": title, "category": category, "content": "content", "repo_id": repo_id });
+        store.insert(namespace, "post", &post, user1)?;
+    }
+    let count = |filter: &str| -> Result<usize, StoreError> {
+        let filter = FilterExpr::from_query(filter)?;
+        Ok(store.query(namespace, "post", None, &filter, None, 10, user1)?.0.len())
+    };
+
+    assert_eq!(count("category=tech")?, 2);
+    assert_eq!(count("category=tech, title=a")?, 1);
+    // a number only matches a number, quoting keeps it a string
+    assert_eq!(count("title=1")?, 0);
+    assert_eq!(count("title=\"1\"")?, 1);
+    assert_eq!(count(r#"{ "in": ["category", ["tech", "news"]] }"#)?, 3);
+
+    assert_validation_error(count("category"));
+    assert_validation_error(count(""));
+    assert_validation_error(count("{ \"eq\": 1 }"));
+    Ok(())
+}
+
+#[test]
+fn saved_views_run_by_name() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    for (title, category) in [("b", "news"), ("d", "news"), ("a", "blog"), ("c", "news")] {
+        let post = json!({ "title": title, "category": category, "content": "content", "repo_id": repo_id });
+        store.insert(namespace, "post", &post, user1)?;
+    }
+    let titles = |items: &[syncstore::types::DataItem]| -> Vec<String> {
+        items
+            .iter()
+            .map(|i| i.body["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let filter = serde_json::from_value(json!({ "eq": ["category", "news"] }))?;
+    let sort = serde_json::from_value(json!({ "field": "title", "descending": true }))?;
+    store.save_view(namespace, "post", "news", Some(filter), Some(sort), user1)?;
+
+    // sorted views page by position
+    let (items, next_marker) = store.run_view(namespace, "post", "news", None, None, 2, user1)?;
+    assert_eq!(titles(&items), vec!["d", "c"]);
+    assert_eq!(next_marker.as_deref(), Some("2"));
+    let (items, next_marker) = store.run_view(namespace, "post", "news", Some(&repo_id), next_marker, 2, user1)?;
+    assert_eq!(titles(&items), vec!["b"]);
+    assert!(next_marker.is_none());
+
+    // saving again replaces the view, without a sort it keeps the id order
+    store.save_view(namespace, "post", "news", None, None, user1)?;
+    let views = store.list_views(namespace, "post", user1)?;
+    assert_eq!(views.len(), 1);
+    assert!(views[0].filter.is_none());
+    let (items, _) = store.run_view(namespace, "post", "news", None, None, 10, user1)?;
+    assert_eq!(items.len(), 4);
+
+    // views are private to their user
+    assert!(store.list_views(namespace, "post", user2)?.is_empty());
+    assert_not_found(store.run_view(namespace, "post", "news", None, None, 10, user2));
+    store.save_view(namespace, "post", "news", None, None, user2)?;
+    let (items, _) = store.run_view(namespace, "post", "news", None, None, 10, user2)?;
+    assert!(items.is_empty());
+    assert_permission_denied(store.run_view(namespace, "post", "news", Some(&repo_id), None, 10, user2));
+
+    let sort = serde_json::from_value(json!({ "field": "title') --" }))?;
+    assert_validation_error(store.save_view(namespace, "post", "bad", None, Some(sort), user1));
+    assert_validation_error(store.save_view(namespace, "post", " ", None, None, user1));
+    assert_not_found(store.save_view(namespace, "missing", "all", None, None, user1));
+
+    store.delete_view(namespace, "post", "news", user1)?;
+    assert_not_found(store.delete_view(namespace, "post", "news", user1));
+    Ok(())
+}
+
+#[test]
+fn snapshot_cursor_pins_listing() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user)?;
+    for i in 0..5 {
+        let post = json!({ "title": format!("post {i}"), "category": "c", "content": "c", "repo_id": repo_id });
+        store.insert(namespace, "post", &post, user)?;
+    }
+
+    let (first, cursor) = store.list_children_snapshot(namespace, "post", &repo_id, None, 2, user)?;
+    assert_eq!(first.len(), 2);
+    // inserted mid-iteration, never listed whatever its id
+    for i in 5..10 {
+        let post = json!({ "title": format!("post {i}"), "category": "c", "content": "c", "repo_id": repo_id });
+        store.insert(namespace, "post", &post, user)?;
+    }
+    let mut seen: Vec<String> = first.into_iter().map(|i| i.id).collect();
+    let mut cursor = cursor.map(|c| PageCursor::decode(&c.encode())).transpose()?;
+    while let Some(c) = cursor {
+        let (items, next) = store.list_children_snapshot(namespace, "post", &repo_id, Some(c), 2, user)?;
+        seen.extend(items.into_iter().map(|i| i.id));
+        cursor = next;
+    }
+    assert_eq!(seen.len(), 5);
+    assert_eq!(seen.iter().unique().count(), 5);
+
+    // a new listing sees everything
+    let (items, _) = store.list_by_owner_snapshot(namespace, "post", None, 100, user)?;
+    assert_eq!(items.len(), 10);
+    assert_validation_error(PageCursor::decode("not a cursor"));
+
+    Ok(())
+}
+
+#[test]
+fn pages_link_back_to_previous_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user)?;
+    for i in 0..5 {
+        let post = json!({ "title": format!("post {i}"), "category": "c", "content": "c", "repo_id": repo_id });
+        store.insert(namespace, "post", &post, user)?;
+    }
+
+    for listing in [Listing::Owner, Listing::Children(&repo_id), Listing::Permission] {
+        // walk forward over pages of 2, 2 and 1 items
+        let first = store.list_page(namespace, "post", listing, None, false, 2, user)?;
+        assert!(first.has_next() && !first.has_prev);
+        let second = store.list_page(namespace, "post", listing, first.next_marker.clone(), false, 2, user)?;
+        assert!(second.has_next() && second.has_prev);
+        // the page before the second one is the first one
+        assert_eq!(second.prev_marker, None);
+        let third = store.list_page(namespace, "post", listing, second.next_marker.clone(), false, 2, user)?;
+        assert!(!third.has_next() && third.has_prev);
+        assert_eq!(third.items.len(), 1);
+
+        // and back
+        let back = store.list_page(namespace, "post", listing, third.prev_marker.clone(), false, 2, user)?;
+        assert_eq!(back, second);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn usage_counts_own_items_and_bytes() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Usage Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user1)?;
+    let other = json!({ "name": "Other", "status": "normal" });
+    store.insert(namespace, "repo", &other, user2)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user1)?;
+    store.add_attachment((namespace, "post"), &post_id, "cover.png", "image/png", b"png", user1)?;
+
+    let usage = store.namespace_usage(namespace, user1)?;
+    assert_eq!(usage.namespace, *namespace);
+    assert_eq!(usage.items, 2);
+    let by_name = |name: &str| usage.collections.iter().find(|c| c.collection == name).unwrap();
+    assert_eq!(by_name("repo").items, 1);
+    assert_eq!(by_name("post").items, 1);
+    assert_eq!(by_name("post").attachment_bytes, 3);
+    assert_eq!(by_name("comment").items, 0);
+    assert_eq!(by_name("comment").body_bytes, 0);
+    let body_bytes: u64 = usage.collections.iter().map(|c| c.body_bytes).sum();
+    assert!(body_bytes > 0);
+    assert_eq!(usage.bytes, body_bytes + 3);
+
+    // the other user only sees their own repo
+    let usage = store.namespace_usage(namespace, user2)?;
+    assert_eq!(usage.items, 1);
+    assert_eq!(usage.collections.iter().map(|c| c.attachment_bytes).sum::<u64>(), 0);
+    Ok(())
+}
+
+#[test]
+fn skip_duplicates_returns_existing_item() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Dup Repo", "status": "normal" });
+    let Imported::Inserted(repo_id) =
+        store.insert_with_mode(namespace, "repo", &repo, user1, ImportMode::SkipDuplicates)?
+    else {
+        panic!("first insert should not be a duplicate");
+    };
+    // same content with the keys in another order
+    let again = json!({ "status": "normal", "name": "Dup Repo" });
+    assert_eq!(
+        store.insert_with_mode(namespace, "repo", &again, user1, ImportMode::SkipDuplicates)?,
+        Imported::Duplicate(repo_id.clone())
+    );
+    // the other user's content is their own
+    assert!(matches!(
+        store.insert_with_mode(namespace, "repo", &repo, user2, ImportMode::SkipDuplicates)?,
+        Imported::Inserted(_)
+    ));
+    // always mode inserts anyway, and an update moves the hash along
+    assert!(matches!(
+        store.insert_with_mode(namespace, "repo", &repo, user1, ImportMode::Always)?,
+        Imported::Inserted(_)
+    ));
+    let changed = json!({ "name": "Dup Repo", "status": "deleted" });
+    store.update(namespace, "repo", &repo_id, &changed, user1)?;
+    let (items, _) = store.list_by_owner(namespace, "repo", None, 10, user1)?;
+    assert_eq!(items.len(), 2);
+    assert_eq!(
+        store.insert_with_mode(namespace, "repo", &changed, user1, ImportMode::SkipDuplicates)?,
+        Imported::Duplicate(repo_id)
+    );
+    Ok(())
+}
+
+#[test]
+fn collection_stats_of_own_items() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let stats = store.collection_stats(namespace, "repo", user1)?;
+    assert_eq!(stats.count, 0);
+    assert!(stats.oldest.is_none() && stats.newest.is_none());
+
+    let repo = json!({ "name": "Stats Repo", "status": "normal" });
+    let repo_a = store.insert(namespace, "repo", &repo, user1)?;
+    std::thread::sleep(Duration::from_millis(5));
+    let repo_b = store.insert(namespace, "repo", &repo, user1)?;
+    store.insert(namespace, "repo", &repo, user2)?;
+    for (repo_id, n) in [(&repo_a, 2), (&repo_b, 1)] {
+        for i in 0..n {
+            let post = json!({ "title": format!("Post {i}"), "category": "c", "content": "c", "repo_id": repo_id });
+            store.insert(namespace, "post", &post, user1)?;
+        }
+    }
+
+    let stats = store.collection_stats(namespace, "repo", user1)?;
+    assert_eq!(stats.count, 2);
+    assert_eq!(
+        stats.oldest,
+        Some(store.get(namespace, "repo", &repo_a, user1)?.created_at)
+    );
+    assert_eq!(
+        stats.newest,
+        Some(store.get(namespace, "repo", &repo_b, user1)?.created_at)
+    );
+    assert!(stats.parents.is_empty());
+
+    let stats = store.collection_stats(namespace, "post", user1)?;
+    assert_eq!(stats.count, 3);
+    let counts = stats
+        .parents
+        .iter()
+        .map(|p| (p.parent_id.clone(), p.count))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(counts, HashMap::from([(repo_a, 2), (repo_b, 1)]));
+
+    assert_eq!(store.collection_stats(namespace, "post", user2)?.count, 0);
+    Ok(())
+}
+
+#[test]
+fn fake_data_follows_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repos = store.generate_fake_data(namespace, "repo", 5, user1)?;
+    assert_eq!((repos.inserted.len(), repos.failed), (5, 0));
+    let posts = store.generate_fake_data(namespace, "post", 20, user1)?;
+    assert_eq!(posts.inserted.len(), 20);
+    for id in &posts.inserted {
+        let post = store.get(namespace, "post", id, user1)?;
+        assert!(repos.inserted.contains(&post.parent_id.unwrap()));
+        assert!(post.body["title"].is_string());
+    }
+    let repo = store.get(namespace, "repo", &repos.inserted[0], user1)?;
+    assert!(["normal", "deleted"].contains(&repo.body["status"].as_str().unwrap()));
+
+    // children need a parent of the owner
+    assert_validation_error(store.generate_fake_data(namespace, "post", 1, user2));
+    assert_not_found(store.generate_fake_data(namespace, "missing", 1, user1));
+    Ok(())
+}
+
+#[test]
+fn sorted_listing_markers_stay_in_place() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let (namespace, user1) = (&s.namespace, &s.user1_id);
+
+    let mut ids = Vec::new();
+    for i in 0..5 {
+        let doc = json!({ "name": format!("repo {}", i), "status": "normal" });
+        ids.push(store.insert(namespace, "repo", &doc, user1)?);
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    let walk = |order: &str, limit: usize| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let order = order.parse::<ListOrder>()?;
+        let (mut walked, mut marker) = (Vec::new(), None);
+        loop {
+            let (items, next_marker) =
+                store.list_sorted(namespace, "repo", Listing::Owner, order, marker, limit, user1)?;
+            walked.extend(items.into_iter().map(|item| item.id));
+            if next_marker.is_none() {
+                return Ok(walked);
+            }
+            marker = next_marker;
+        }
+    };
+    assert_eq!(walk("created_at", 2)?, ids);
+    assert_eq!(
+        walk("created_at:desc", 2)?,
+        ids.iter().rev().cloned().collect::<Vec<_>>()
+    );
+    assert_validation_error("name:desc".parse::<ListOrder>());
+    assert_validation_error(store.list_sorted(
+        namespace,
+        "repo",
+        Listing::Permission,
+        "updated_at".parse()?,
+        None,
+        2,
+        user1,
+    ));
+
+    // the item the marker points at moves to the end meanwhile, the next page starts where it was
+    let order = "updated_at".parse::<ListOrder>()?;
+    let (first, marker) = store.list_sorted(namespace, "repo", Listing::Owner, order, None, 2, user1)?;
+    assert_eq!(
+        first.iter().map(|item| &item.id).collect::<Vec<_>>(),
+        vec![&ids[0], &ids[1]]
+    );
+    let moved = store.get(namespace, "repo", &ids[2], user1)?;
+    store.update(namespace, "repo", &ids[2], &moved.body, user1)?;
+    let (rest, marker) = store.list_sorted(namespace, "repo", Listing::Owner, order, marker, 10, user1)?;
+    assert_eq!(
+        rest.iter().map(|item| &item.id).collect::<Vec<_>>(),
+        vec![&ids[3], &ids[4], &ids[2]]
+    );
+    assert!(marker.is_none());
+    assert_eq!(walk("updated_at:desc", 1)?[0], ids[2]);
+    Ok(())
+}
+
+#[test]
+fn updated_since_pages_by_update_time() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let (namespace, user1) = (&s.namespace, &s.user1_id);
+
+    let mut ids = Vec::new();
+    for i in 0..4 {
+        let doc = json!({ "name": format!("repo {}", i), "status": "normal" });
+        ids.push(store.insert(namespace, "repo", &doc, user1)?);
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    let last_visit = chrono::Utc::now();
+    std::thread::sleep(Duration::from_millis(2));
+    for id in [&ids[2], &ids[0]] {
+        let item = store.get(namespace, "repo", id, user1)?;
+        store.update(namespace, "repo", id, &item.body, user1)?;
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    let inserted = store.insert(namespace, "repo", &json!({ "name": "late", "status": "normal" }), user1)?;
+
+    // only the changes after the visit, oldest change first, one per page
+    let (mut changed, mut marker) = (Vec::new(), None);
+    loop {
+        let (items, next_marker) =
+            store.list_updated_since(namespace, "repo", Listing::Owner, last_visit, false, marker, 1, user1)?;
+        changed.extend(items.into_iter().map(|item| item.id));
+        if next_marker.is_none() {
+            break;
+        }
+        marker = next_marker;
+    }
+    assert_eq!(changed, vec![ids[2].clone(), ids[0].clone(), inserted]);
+    Ok(())
+}
+
+#[test]
+fn insert_many_is_all_or_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let (store, namespace, user) = (&s.store, &s.namespace, &s.user1_id);
+
+    let repos = [
+        json!({ "name": "one", "status": "normal" }),
+        json!({ "name": "two", "status": "normal" }),
+    ];
+    let BatchOutcome::Committed { ids } = store.insert_many(namespace, "repo", &repos, user)? else {
+        panic!("expected the batch to commit");
+    };
+    assert_eq!(ids.len(), 2);
+    assert_eq!(store.get(namespace, "repo", &ids[1], user)?.body["name"], "two");
+
+    let repos = [
+        json!({ "name": "three", "status": "normal" }),
+        json!({ "name": "four" }),
+        json!({ "name": "five", "status": "archived" }),
+    ];
+    let BatchOutcome::Rejected { failures } = store.insert_many(namespace, "repo", &repos, user)? else {
+        panic!("expected the batch to be rejected");
+    };
+    assert_eq!(failures.iter().map(|f| f.index).collect_vec(), vec![1, 2]);
+    assert_eq!(store.list_by_owner(namespace, "repo", None, 10, user)?.0.len(), 2);
+
+    // every item is checked against its parent's permissions
+    let post = json!({ "title": "t", "category": "c", "content": "c", "repo_id": ids[0] });
+    let outcome = store.insert_many(namespace, "post", &[post.clone()], &s.user2_id)?;
+    assert!(matches!(outcome, BatchOutcome::Rejected { failures } if failures.len() == 1));
+    assert!(matches!(
+        store.insert_many(namespace, "post", &[post.clone(), post], user)?,
+        BatchOutcome::Committed { ids } if ids.len() == 2
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn update_many_is_all_or_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let (store, namespace, user) = (&s.store, &s.namespace, &s.user1_id);
+
+    let first = store.insert(namespace, "repo", &json!({ "name": "one", "status": "normal" }), user)?;
+    let second = store.insert(namespace, "repo", &json!({ "name": "two", "status": "normal" }), user)?;
+    let renamed = |name: &str| json!({ "name": name, "status": "normal" });
+
+    let outcome = store.update_many(
+        namespace,
+        "repo",
+        vec![(first.clone(), renamed("uno")), (second.clone(), renamed("dos"))],
+        user,
+    )?;
+    assert!(matches!(outcome, BatchOutcome::Committed { ids } if ids == vec![first.clone(), second.clone()]));
+    assert_eq!(store.get(namespace, "repo", &second, user)?.body["name"], "dos");
+
+    // the second item fails validation, the first one is rolled back
+    let outcome = store.update_many(
+        namespace,
+        "repo",
+        vec![
+            (first.clone(), renamed("eins")),
+            (second.clone(), json!({ "name": "zwei" })),
+        ],
+        user,
+    )?;
+    let BatchOutcome::Rejected { failures } = outcome else {
+        panic!("expected the batch to be rejected");
+    };
+    assert_eq!(failures.iter().map(|f| f.index).collect_vec(), vec![1]);
+    assert_eq!(store.get(namespace, "repo", &first, user)?.body["name"], "uno");
+
+    // permissions are checked for every item before anything is written
+    let foreign = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "three", "status": "normal" }),
+        &s.user2_id,
+    )?;
+    let missing = "missing".to_string();
+    let outcome = store.update_many(
+        namespace,
+        "repo",
+        vec![
+            (first.clone(), renamed("eins")),
+            (foreign, renamed("drei")),
+            (missing, renamed("vier")),
+        ],
+        user,
+    )?;
+    let BatchOutcome::Rejected { failures } = outcome else {
+        panic!("expected the batch to be rejected");
+    };
+    assert_eq!(failures.iter().map(|f| f.index).collect_vec(), vec![1, 2]);
+    assert_eq!(store.get(namespace, "repo", &first, user)?.body["name"], "uno");
+
+    Ok(())
+}
+
+#[test]
+fn patch_item_with_json_patch() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let doc = json!({ "name": "Test Repo", "description": "draft", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &doc, user)?;
+    let rev = store.get(namespace, "repo", &repo_id, user)?.rev;
+
+    let ops = serde_json::from_value::<Vec<PatchOp>>(json!([
+        { "op": "test", "path": "/description", "value": "draft" },
+        { "op": "replace", "path": "/description", "value": "done" }
+    ]))?;
+    let item = store.patch(namespace, "repo", &repo_id, &ops, Some(rev), user)?;
+    assert_eq!(item.body["description"], "done");
+    assert_eq!(item.body["name"], "Test Repo");
+    assert!(item.rev > rev);
+
+    // the stale revision, a failed test and an invalid result all leave the item as it is
+    assert!(matches!(
+        store.patch(namespace, "repo", &repo_id, &ops, Some(rev), user),
+        Err(StoreError::Conflict(_))
+    ));
+    assert_validation_error(store.patch(namespace, "repo", &repo_id, &ops, None, user));
+    let drop_name = serde_json::from_value::<Vec<PatchOp>>(json!([{ "op": "remove", "path": "/name" }]))?;
+    assert_validation_error(store.patch(namespace, "repo", &repo_id, &drop_name, None, user));
+    assert_eq!(store.get(namespace, "repo", &repo_id, user)?.rev, item.rev);
+
+    // other users can not patch
+    assert_permission_denied(store.patch(namespace, "repo", &repo_id, &drop_name, None, &s.user2_id));
+
+    Ok(())
+}
@@ -2,4 +2,11 @@ pub mod mock;
 
 mod acl_management;
 mod basic_crud;
+mod maintenance;
+mod memory_backend;
+mod notifications;
+mod replication;
+mod schema_keywords;
+mod transactions;
 mod user_management;
+mod webhooks;
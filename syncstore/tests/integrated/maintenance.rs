@@ -0,0 +1,770 @@
+use std::io::Read;
+use std::time::Duration;
+
+use serde_json::json;
+use syncstore::{
+    collection,
+    components::DataSchemasBuilder,
+    config::OrphanCleanup,
+    error::StoreError,
+    store::{Store, StoreLayout},
+    types::{AccessControl, AccessLevel, CollectionSwitches, MaintenanceMode, OrphanPolicy, Permission},
+};
+
+use crate::mock::*;
+
+#[test]
+fn orphans_found_and_deleted() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user)?;
+    let comment = json!({ "content": "comment", "post_id": post_id });
+    let comment_id = store.insert(namespace, "comment", &comment, user)?;
+
+    assert!(store.find_orphans(namespace)?.is_empty());
+
+    // deleting the repo leaves the post orphaned
+    store.delete(namespace, "repo", &repo_id, user)?;
+    let orphans = store.find_orphans(namespace)?;
+    assert_eq!(orphans.len(), 1);
+    assert_eq!(orphans[0].collection, "post");
+    assert_eq!(orphans[0].id, post_id);
+    assert_eq!(orphans[0].parent_id, repo_id);
+
+    // report only
+    let report = store.cleanup_orphans(namespace, &OrphanPolicy::Report)?;
+    assert_eq!(report.orphans.len(), 1);
+    assert_eq!(report.resolved, 0);
+    store.get(namespace, "post", &post_id, user)?;
+
+    // delete also removes the comment orphaned by deleting the post
+    let report = store.cleanup_orphans(namespace, &OrphanPolicy::Delete)?;
+    assert_eq!(report.resolved, 2);
+    assert_not_found(store.get(namespace, "post", &post_id, user));
+    assert_not_found(store.get(namespace, "comment", &comment_id, user));
+    assert!(store.find_orphans(namespace)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn soft_deleted_orphans_resolved_once() -> Result<(), Box<dyn std::error::Error>> {
+    let folder = json!({ "type": "object", "properties": { "name": { "type": "string" } } });
+    let page = json!({
+        "type": "object",
+        "properties": { "name": { "type": "string" }, "folder_id": { "type": "string" } },
+        "required": ["name", "folder_id"],
+        "x-parent-id": { "parent": "folder", "field": "folder_id" },
+        "x-soft-delete": true
+    });
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("folder", folder)
+        .add_schema("page", page)
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let folder_id = store.insert(namespace, "folder", &json!({ "name": "f" }), user)?;
+    let page_id = store.insert(namespace, "page", &json!({ "name": "p", "folder_id": folder_id }), user)?;
+    store.delete(namespace, "folder", &folder_id, user)?;
+    assert_eq!(store.find_orphans(namespace)?.len(), 1);
+
+    // the orphan is only marked deleted, it is not found again
+    let report = store.cleanup_orphans(namespace, &OrphanPolicy::Delete)?;
+    assert_eq!(report.resolved, 1);
+    assert!(store.find_orphans(namespace)?.is_empty());
+    let item = store.get_with_deleted(namespace, "page", &page_id, true, user)?;
+    assert!(item.deleted_at.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn orphans_reparented() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Old Repo", "status": "normal" });
+    let old_repo = store.insert(namespace, "repo", &repo, user)?;
+    let repo = json!({ "name": "Lost and found", "status": "normal" });
+    let new_repo = store.insert(namespace, "repo", &repo, user)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": old_repo });
+    let post_id = store.insert(namespace, "post", &post, user)?;
+
+    store.delete(namespace, "repo", &old_repo, user)?;
+
+    // the new parent must exist
+    let policy = OrphanPolicy::Reparent {
+        collection: "post".to_string(),
+        parent_id: "missing".to_string(),
+    };
+    assert_not_found(store.cleanup_orphans(namespace, &policy));
+
+    let policy = OrphanPolicy::Reparent {
+        collection: "post".to_string(),
+        parent_id: new_repo.clone(),
+    };
+    let report = store.cleanup_orphans(namespace, &policy)?;
+    assert_eq!(report.resolved, 1);
+    let item = store.get(namespace, "post", &post_id, user)?;
+    assert_eq!(item.parent_id.as_deref(), Some(new_repo.as_str()));
+    assert_eq!(item.body["repo_id"], new_repo);
+    assert!(store.find_orphans(namespace)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn orphan_cleanup_job() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user)?;
+    store.delete(namespace, "repo", &repo_id, user)?;
+
+    store.schedule_orphan_cleanup(&OrphanCleanup {
+        interval: Duration::from_secs(3600),
+        jitter: None,
+        policy: OrphanPolicy::Delete,
+    })?;
+    let job = format!("orphan_cleanup:{}", namespace);
+    let jobs = store.scheduler().list_status()?;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].name, job);
+    assert_eq!(jobs[0].runs, 0);
+
+    let status = store.scheduler().run_now(&job)?;
+    assert_eq!(status.runs, 1);
+    assert_eq!(status.failures, 0);
+    assert_not_found(store.get(namespace, "post", &post_id, user));
+
+    Ok(())
+}
+
+#[test]
+fn online_backup_of_namespace() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    for name in ["A", "B"] {
+        store.insert(namespace, "repo", &json!({ "name": name, "status": "normal" }), user)?;
+    }
+    let path = s.path.join("backup.db");
+    let info = store.backup(namespace, &path)?;
+    assert_eq!(info.bytes, std::fs::metadata(&path)?.len());
+    let conn = rusqlite::Connection::open(&path)?;
+    let repos: i64 = conn.query_row("SELECT COUNT(*) FROM c_repo", [], |r| r.get(0))?;
+    assert_eq!(repos, 2);
+
+    // an existing file is never overwritten
+    store.insert(namespace, "repo", &json!({ "name": "C", "status": "normal" }), user)?;
+    assert_validation_error(store.backup(namespace, &path));
+    let repos: i64 = conn.query_row("SELECT COUNT(*) FROM c_repo", [], |r| r.get(0))?;
+    assert_eq!(repos, 2);
+    assert_not_found(store.backup("missing", s.path.join("missing.db")));
+
+    Ok(())
+}
+
+#[test]
+fn vacuum_reclaims_deleted_rows() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let content = "x".repeat(4096);
+    let mut ids = Vec::new();
+    for i in 0..50 {
+        let repo = json!({ "name": format!("Repo {i}"), "status": "normal", "content": content });
+        ids.push(store.insert(namespace, "repo", &repo, user)?);
+    }
+    for id in &ids[1..] {
+        store.delete(namespace, "repo", id, user)?;
+    }
+
+    let report = store.vacuum_namespace(namespace)?;
+    assert!(report.integrity_errors.is_empty());
+    assert!(report.reclaimed > 0 && report.size_after < report.size_before);
+    store.get(namespace, "repo", &ids[0], user)?;
+    assert_not_found(store.vacuum_namespace("missing"));
+
+    Ok(())
+}
+
+#[test]
+fn namespace_snapshot_and_owner_export() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user1)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    store.insert(namespace, "post", &post, user1)?;
+    let repo = json!({ "name": "Other", "status": "normal" });
+    store.insert(namespace, "repo", &repo, user2)?;
+
+    // the snapshot is a valid sqlite database holding every item
+    let snapshot = store.snapshot_namespace(namespace)?;
+    assert_eq!(snapshot.sha256.len(), 64);
+    let path = s.path.join("snapshot.db");
+    std::fs::write(&path, &snapshot.data)?;
+    let conn = rusqlite::Connection::open(&path)?;
+    let repos: i64 = conn.query_row("SELECT COUNT(*) FROM c_repo", [], |r| r.get(0))?;
+    assert_eq!(repos, 2);
+
+    // the export only holds the user's own items
+    let export = store.export_owned_data(namespace, user1)?;
+    let lines = std::str::from_utf8(&export.data)?
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|l| l["owner"] == *user1));
+    assert!(
+        lines
+            .iter()
+            .any(|l| l["collection"] == "post" && l["body"]["title"] == "Post")
+    );
+    assert_ne!(export.sha256, store.export_owned_data(namespace, user2)?.sha256);
+
+    Ok(())
+}
+
+#[test]
+fn account_export_holds_profile_items_attachments_and_acls() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user1)?;
+    store.add_attachment((namespace, "repo"), &repo_id, "a.txt", "text/plain", b"hello", user1)?;
+    let acl = AccessControl {
+        data_id: repo_id.clone(),
+        permissions: vec![Permission {
+            user: user2.clone(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user1)?;
+    let other = json!({ "name": "Other", "status": "normal" });
+    store.insert(namespace, "repo", &other, user2)?;
+
+    let archive = store.export_account(user1)?;
+    let mut text = String::new();
+    flate2::read::GzDecoder::new(archive.data.as_slice()).read_to_string(&mut text)?;
+    let records = text
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    let of_kind = |kind: &str| records.iter().filter(|r| r["kind"] == kind).collect::<Vec<_>>();
+
+    let profile = of_kind("profile");
+    assert_eq!(profile.len(), 1);
+    assert_eq!(profile[0]["user_id"], *user1);
+    assert!(profile[0].get("password").is_none() && profile[0].get("secret_key").is_none());
+    let items = of_kind("item");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["id"], repo_id);
+    assert_eq!(items[0]["namespace"], *namespace);
+    let attachments = of_kind("attachment");
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0]["data_id"], repo_id);
+    assert_eq!(attachments[0]["content"], "aGVsbG8=");
+    let acls = of_kind("acl");
+    assert_eq!(acls.len(), 1);
+    assert_eq!(acls[0]["user"], *user2);
+
+    Ok(())
+}
+
+#[test]
+fn maintenance_mode_rejects_mutations() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user)?;
+
+    let mode = store.set_maintenance_mode(true, Some("backup in progress".to_string()))?;
+    assert!(mode.enabled);
+    assert!(mode.since.is_some());
+
+    // reads are still served
+    store.get(namespace, "repo", &repo_id, user)?;
+    assert_eq!(store.list_by_owner(namespace, "repo", None, 10, user)?.0.len(), 1);
+
+    let assert_unavailable = |result: Result<(), StoreError>| match result {
+        Err(StoreError::Unavailable(reason)) => assert_eq!(reason, "backup in progress"),
+        rest => panic!("Expected Unavailable error, got: {:?}", rest),
+    };
+    assert_unavailable(store.insert(namespace, "repo", &repo, user).map(|_| ()));
+    assert_unavailable(store.update(namespace, "repo", &repo_id, &repo, user).map(|_| ()));
+    assert_unavailable(store.delete(namespace, "repo", &repo_id, user));
+    assert_unavailable(store.create_user("user3", "p3"));
+    assert_unavailable(store.cleanup_orphans(namespace, &OrphanPolicy::Delete).map(|_| ()));
+    store.cleanup_orphans(namespace, &OrphanPolicy::Report)?;
+
+    let mode = store.set_maintenance_mode(false, None)?;
+    assert_eq!(mode, MaintenanceMode::default());
+    store.delete(namespace, "repo", &repo_id, user)?;
+
+    Ok(())
+}
+
+#[test]
+fn read_only_collection_rejects_writes() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user)?;
+
+    assert!(!store.collection_mode(namespace, "post")?.read_only);
+    let mode = store.set_collection_mode(namespace, "post", true, Some("legacy".to_string()))?;
+    assert!(mode.read_only);
+    assert!(mode.since.is_some());
+    assert_eq!(store.collection_mode(namespace, "post")?, mode);
+
+    // reads are still served, writes into the collection are rejected
+    store.get(namespace, "post", &post_id, user)?;
+    let res = store.insert(namespace, "post", &post, user);
+    assert!(matches!(res, Err(StoreError::Conflict(ref m)) if m.contains("legacy")));
+    let res = store.update(namespace, "post", &post_id, &post, user);
+    assert!(matches!(res, Err(StoreError::Conflict(_))));
+    let res = store.delete(namespace, "post", &post_id, user);
+    assert!(matches!(res, Err(StoreError::Conflict(_))));
+
+    // other collections are untouched
+    store.update(namespace, "repo", &repo_id, &repo, user)?;
+
+    store.set_collection_mode(namespace, "post", false, None)?;
+    assert_eq!(store.collection_mode(namespace, "post")?, Default::default());
+    store.delete(namespace, "post", &post_id, user)?;
+
+    assert!(matches!(
+        store.collection_mode(namespace, "nope"),
+        Err(StoreError::NotFound(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn collection_switches_disable_reads_and_writes() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user)?;
+
+    let switches = CollectionSwitches {
+        writes_disabled: true,
+        reason: Some("investigating".to_string()),
+        ..Default::default()
+    };
+    store.set_collection_switches(namespace, "repo", switches.clone())?;
+    assert_eq!(store.collection_switches(namespace, "repo")?, switches);
+
+    store.get(namespace, "repo", &repo_id, user)?;
+    let res = store.insert(namespace, "repo", &repo, user);
+    assert!(matches!(res, Err(StoreError::Unavailable(ref m)) if m.contains("investigating")));
+    let res = store.delete(namespace, "repo", &repo_id, user);
+    assert!(matches!(res, Err(StoreError::Unavailable(_))));
+
+    store.set_collection_switches(
+        namespace,
+        "repo",
+        CollectionSwitches {
+            reads_disabled: true,
+            ..Default::default()
+        },
+    )?;
+    let res = store.get(namespace, "repo", &repo_id, user);
+    assert!(matches!(res, Err(StoreError::Unavailable(_))));
+    let res = store.list_by_owner(namespace, "repo", None, 10, user);
+    assert!(matches!(res, Err(StoreError::Unavailable(_))));
+    store.update(namespace, "repo", &repo_id, &repo, user)?;
+
+    store.set_collection_switches(namespace, "repo", CollectionSwitches::default())?;
+    store.get(namespace, "repo", &repo_id, user)?;
+
+    Ok(())
+}
+
+#[test]
+fn databases_at_custom_paths() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let base_dir = tmp.path().join("base");
+    let layout = StoreLayout {
+        namespace_paths: [("moved".to_string(), tmp.path().join("volume").join("moved.db"))].into(),
+        inner_dir: Some(tmp.path().join("secure")),
+        ..Default::default()
+    };
+    let schemas = || collection! { "note" => json!({ "type": "object" }) };
+    let store = Store::build_with_layout(&base_dir, vec![("moved", schemas()), ("kept", schemas())], &layout)?;
+
+    store.create_user("user", "password")?;
+    let user = store.validate_user("user", "password")?.unwrap();
+    let id = store.insert("moved", "note", &json!({ "text": "hi" }), &user)?;
+    assert_eq!(store.get("moved", "note", &id, &user)?.body["text"], "hi");
+
+    assert!(tmp.path().join("volume").join("moved.db").exists());
+    assert!(base_dir.join("kept.db").exists());
+    assert!(!base_dir.join("moved.db").exists());
+    assert!(tmp.path().join("secure").join("users.db").exists());
+    assert!(!base_dir.join("inner").exists());
+
+    Ok(())
+}
+
+#[test]
+fn read_only_namespace_rejects_mutations() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let schemas = || collection! { "note" => json!({ "type": "object" }) };
+    let (id, user) = {
+        let store = Store::build(tmp.path(), vec![("archive", schemas())])?;
+        store.create_user("user", "password")?;
+        let user = store.validate_user("user", "password")?.unwrap();
+        let id = store.insert("archive", "note", &json!({ "text": "kept" }), &user)?;
+        (id, user)
+    };
+    let db_path = tmp.path().join("archive.db");
+    let before = std::fs::read(&db_path)?;
+
+    let layout = StoreLayout {
+        read_only_namespaces: ["archive".to_string()].into(),
+        ..Default::default()
+    };
+    let store = Store::build_with_layout(tmp.path(), vec![("archive", schemas())], &layout)?;
+    assert_eq!(store.get("archive", "note", &id, &user)?.body["text"], "kept");
+    assert_eq!(store.list_by_owner("archive", "note", None, 10, &user)?.0.len(), 1);
+
+    let conflict = |r: Result<(), StoreError>| matches!(r, Err(StoreError::Conflict(_)));
+    assert!(conflict(
+        store
+            .insert("archive", "note", &json!({ "text": "new" }), &user)
+            .map(|_| ())
+    ));
+    assert!(conflict(
+        store
+            .update("archive", "note", &id, &json!({ "text": "changed" }), &user)
+            .map(|_| ())
+    ));
+    assert!(conflict(store.delete("archive", "note", &id, &user)));
+    assert!(conflict(
+        store
+            .lock("archive", "note", &id, &user, Duration::from_secs(30))
+            .map(|_| ())
+    ));
+
+    drop(store);
+    assert_eq!(std::fs::read(&db_path)?, before);
+    Ok(())
+}
+
+#[test]
+fn encrypted_namespace_needs_its_key() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let schemas = || collection! { "note" => json!({ "type": "object" }) };
+    let layout = |key: &str| StoreLayout {
+        encryption_keys: [("vault".to_string(), key.to_string())].into(),
+        ..Default::default()
+    };
+    let built = Store::build_with_layout(tmp.path(), vec![("vault", schemas())], &layout("first key"));
+    if !cfg!(feature = "sqlcipher") {
+        // never silently stored in plain text
+        assert!(matches!(built, Err(StoreError::Validation(_))));
+        return Ok(());
+    }
+    let (id, user) = {
+        let store = built?;
+        store.create_user("user", "password")?;
+        let user = store.validate_user("user", "password")?.unwrap();
+        let id = store.insert("vault", "note", &json!({ "text": "plain secret" }), &user)?;
+        (id, user)
+    };
+    let raw = std::fs::read(tmp.path().join("vault.db"))?;
+    assert!(!raw.windows(12).any(|w| w == b"plain secret"));
+
+    assert!(Store::build_with_layout(tmp.path(), vec![("vault", schemas())], &layout("other key")).is_err());
+    let store = Store::build_with_layout(tmp.path(), vec![("vault", schemas())], &layout("first key"))?;
+    assert_eq!(store.get("vault", "note", &id, &user)?.body["text"], "plain secret");
+    Ok(())
+}
+
+#[test]
+fn health_reported_per_namespace() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let note = || collection! { "note" => json!({ "type": "object" }) };
+    let store = Store::build(tmp.path(), vec![("notes", note()), ("chat", note())])?;
+    store.create_user("user", "password")?;
+    let user = store.validate_user("user", "password")?.unwrap();
+    store.insert("notes", "note", &json!({ "text": "hi" }), &user)?;
+
+    let health = store.health();
+    assert_eq!(health.len(), 4);
+    assert!(health.iter().all(|h| h.reachable && h.pending_migrations.is_empty()));
+    let notes = health.iter().find(|h| h.namespace == "notes").unwrap();
+    assert!(notes.last_write.is_some());
+    assert!(notes.file_size.unwrap() > 0);
+    assert!(
+        health
+            .iter()
+            .find(|h| h.namespace == "chat")
+            .unwrap()
+            .last_write
+            .is_none()
+    );
+
+    // a missing file is not healthy even though the open connections still read it
+    std::fs::remove_file(tmp.path().join("chat.db"))?;
+    let chat = store.health().into_iter().find(|h| h.namespace == "chat").unwrap();
+    assert!(!chat.reachable);
+    assert!(chat.error.is_some());
+    drop(store);
+
+    // a read-only database written with an older schema has migrations pending
+    let layout = StoreLayout {
+        read_only_namespaces: ["notes".to_string()].into(),
+        ..Default::default()
+    };
+    let schemas = collection! {
+        "note" => json!({ "type": "object", "required": ["text"] }),
+        "tag" => json!({ "type": "object" }),
+    };
+    let store = Store::build_with_layout(tmp.path(), vec![("notes", schemas)], &layout)?;
+    let notes = store.health().into_iter().find(|h| h.namespace == "notes").unwrap();
+    assert!(notes.reachable && notes.read_only);
+    assert_eq!(notes.pending_migrations, vec!["note", "tag"]);
+
+    Ok(())
+}
+
+#[test]
+fn startup_backfills_derived_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let (user, tag_id, note_id) = {
+        let schemas = collection! {
+            "tag" => json!({ "type": "object" }),
+            "note" => json!({ "type": "object" }),
+        };
+        let store = Store::build(tmp.path(), vec![("notes", schemas)])?;
+        store.create_user("user", "password")?;
+        let user = store.validate_user("user", "password")?.unwrap();
+        let tag_id = store.insert("notes", "tag", &json!({ "name": "work" }), &user)?;
+        store.insert("notes", "tag", &json!({ "name": "work" }), &user)?;
+        let note_id = store.insert("notes", "note", &json!({ "tag_id": tag_id }), &user)?;
+        (user, tag_id, note_id)
+    };
+
+    // the unique field and the parent are declared after the documents were written
+    let schemas = collection! {
+        "tag" => json!({ "type": "object", "x-unique": "name" }),
+        "note" => json!({ "type": "object", "x-parent-id": { "parent": "tag", "field": "tag_id" } }),
+    };
+    let store = Store::build(tmp.path(), vec![("notes", schemas)])?;
+    let report = store.startup()?;
+    let notes = report.namespaces.iter().find(|n| n.namespace == "notes").unwrap();
+    assert_eq!((notes.collections, notes.backfilled), (2, 2));
+    // the second tag keeps its value but can't hold it
+    assert_eq!(notes.warnings.len(), 1);
+    let (children, _) = store.list_children("notes", "note", &tag_id, None, 10, &user)?;
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].id, note_id);
+    assert_validation_error(store.insert("notes", "tag", &json!({ "name": "work" }), &user));
+    drop(store);
+
+    let schemas = collection! {
+        "note" => json!({ "type": "object", "x-parent-id": { "parent": "folder", "field": "folder_id" } }),
+    };
+    let store = Store::build(tmp.path(), vec![("notes", schemas)])?;
+    let res = store.startup();
+    assert!(matches!(res, Err(StoreError::Unavailable(report)) if report.contains("`folder`")));
+
+    Ok(())
+}
+
+#[test]
+fn changed_schema_migrates_documents() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let (user, a, b) = {
+        let schemas = collection! {
+            "task" => json!({ "type": "object", "properties": { "title": { "type": "string" } } }),
+        };
+        let store = Store::build(tmp.path(), vec![("tasks", schemas)])?;
+        store.create_user("user", "password")?;
+        let user = store.validate_user("user", "password")?.unwrap();
+        let a = store.insert("tasks", "task", &json!({ "title": "a" }), &user)?;
+        let b = store.insert("tasks", "task", &json!({ "title": "b", "done": "yes" }), &user)?;
+        (user, a, b)
+    };
+    let task = |check: &str| {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "status": { "type": "string" },
+                "done": { "type": "boolean" }
+            },
+            "required": ["name", "status"],
+            "x-migrate": { "rename": { "title": "name" }, "default": { "status": "open" }, "check": check }
+        })
+    };
+
+    // refused with the document not matching, and nothing of the transforms kept
+    let res = Store::build(tmp.path(), vec![("tasks", collection! { "task" => task("reject") })]);
+    assert!(matches!(res, Err(StoreError::Validation(ref e)) if e.contains(&b) && !e.contains(&a)));
+    let old = collection! { "task" => json!({ "type": "object" }) };
+    let store = Store::build(tmp.path(), vec![("tasks", old)])?;
+    assert_eq!(store.get("tasks", "task", &a, &user)?.body, json!({ "title": "a" }));
+    drop(store);
+
+    let store = Store::build(tmp.path(), vec![("tasks", collection! { "task" => task("warn") })])?;
+    let report = store.startup()?;
+    let tasks = report.namespaces.iter().find(|n| n.namespace == "tasks").unwrap();
+    assert_eq!(tasks.warnings.len(), 2);
+    assert!(tasks.warnings[1].contains(&b));
+    let item = store.get("tasks", "task", &a, &user)?;
+    assert_eq!(item.body, json!({ "name": "a", "status": "open" }));
+    assert_eq!(item.rev, 2);
+    drop(store);
+
+    // the migration ran once, the stored schema is the new one
+    let store = Store::build(tmp.path(), vec![("tasks", collection! { "task" => task("warn") })])?;
+    assert!(store.startup()?.namespaces.iter().all(|n| n.warnings.is_empty()));
+    assert_eq!(store.get("tasks", "task", &b, &user)?.rev, 2);
+
+    Ok(())
+}
+
+#[test]
+fn documents_keep_their_schema_version() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let v1 = json!({
+        "type": "object",
+        "properties": { "title": { "type": "string" } },
+        "required": ["title"]
+    });
+    let (user, a) = {
+        let store = Store::build(tmp.path(), vec![("tasks", collection! { "task" => v1.clone() })])?;
+        store.create_user("user", "password")?;
+        let user = store.validate_user("user", "password")?.unwrap();
+        let a = store.insert("tasks", "task", &json!({ "title": "a" }), &user)?;
+        assert_eq!(store.get("tasks", "task", &a, &user)?.schema_version, 1);
+        (user, a)
+    };
+    let v2 = json!({
+        "type": "object",
+        "properties": { "title": { "type": "string" }, "priority": { "type": "integer" } },
+        "required": ["title", "priority"],
+        "x-version": 2,
+        "x-migrate": { "check": "reject" }
+    });
+
+    // without the first version the stored document is checked against the second one
+    let res = Store::build(tmp.path(), vec![("tasks", collection! { "task" => v2.clone() })]);
+    assert!(matches!(res, Err(StoreError::Validation(ref e)) if e.contains(&a)));
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("task", v2)
+        .add_schema_version("task", 1, v1)
+        .build();
+    let store = Store::build(tmp.path(), vec![("tasks", schemas)])?;
+    assert_eq!(store.get("tasks", "task", &a, &user)?.schema_version, 1);
+
+    // new writes must satisfy the latest version
+    assert_validation_error(store.insert("tasks", "task", &json!({ "title": "b" }), &user));
+    let b = store.insert("tasks", "task", &json!({ "title": "b", "priority": 1 }), &user)?;
+    assert_eq!(store.get("tasks", "task", &b, &user)?.schema_version, 2);
+    assert_validation_error(store.update("tasks", "task", &a, &json!({ "title": "a2" }), &user));
+    let item = store.update("tasks", "task", &a, &json!({ "title": "a2", "priority": 2 }), &user)?;
+    assert_eq!(item.schema_version, 2);
+    drop(store);
+
+    // an earlier version is below the current one
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("task", json!({ "type": "object", "x-version": 2 }))
+        .add_schema_version("task", 2, json!({ "type": "object" }))
+        .build();
+    assert!(Store::build(tmp.path(), vec![("tasks", schemas)]).is_err());
+    Ok(())
+}
+
+#[test]
+fn expired_documents_swept() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("session", json!({ "type": "object", "x-ttl": 1 }))
+        .add_schema("cache", json!({ "type": "object", "x-ttl": true }))
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let past = (chrono::Utc::now() - chrono::Duration::minutes(1)).to_rfc3339();
+    let future = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+    let stale = store.insert(namespace, "cache", &json!({ "expires_at": past }), user)?;
+    let kept = store.insert(namespace, "cache", &json!({ "expires_at": future }), user)?;
+    // only the field sets the expiry of a `true` collection
+    let forever = store.insert(namespace, "cache", &json!({}), user)?;
+    // the field takes over from the lifetime
+    let extended = store.insert(namespace, "session", &json!({ "expires_at": future }), user)?;
+    let session = store.insert(namespace, "session", &json!({}), user)?;
+
+    assert_eq!(store.expire_documents(namespace)?, 1);
+    assert_not_found(store.get(namespace, "cache", &stale, user));
+    store.get(namespace, "session", &session, user)?;
+
+    std::thread::sleep(Duration::from_millis(1100));
+    assert_eq!(store.expire_documents(namespace)?, 1);
+    assert_not_found(store.get(namespace, "session", &session, user));
+    for (collection, id) in [("cache", &kept), ("cache", &forever), ("session", &extended)] {
+        store.get(namespace, collection, id, user)?;
+    }
+
+    let invalid = DataSchemasBuilder::new()
+        .add_schema("session", json!({ "type": "object", "x-ttl": -5 }))
+        .build();
+    assert!(BasicTestSuite::with_schemas(invalid).is_err());
+    Ok(())
+}
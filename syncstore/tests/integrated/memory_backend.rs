@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use serde_json::json;
+use syncstore::{
+    backend::{Backend, blocking::AsyncBackend, memory::MemoryBackendBuilder},
+    error::StoreError,
+};
+
+use crate::mock::*;
+
+fn backend() -> Result<syncstore::backend::MemoryBackend, Box<dyn std::error::Error>> {
+    Ok(MemoryBackendBuilder::new()
+        .with_collection_schema(
+            "repo",
+            json!({
+                "type": "object",
+                "properties": { "name": { "type": "string" } },
+                "required": ["name"],
+                "x-unique": "name"
+            }),
+        )
+        .with_collection_schema(
+            "post",
+            json!({
+                "type": "object",
+                "properties": { "title": { "type": "string" }, "repo_id": { "type": "string" } },
+                "required": ["title", "repo_id"],
+                "x-unique": "title",
+                "x-unique-scope": "parent",
+                "x-parent-id": { "parent": "repo", "field": "repo_id" }
+            }),
+        )
+        .build()?)
+}
+
+#[test]
+fn memory_backend_crud_and_paging() -> Result<(), Box<dyn std::error::Error>> {
+    let backend = backend()?;
+
+    let ids = (0..5)
+        .map(|i| backend.insert("repo", &json!({ "name": format!("repo {i}") }), "u1".to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    backend.insert("repo", &json!({ "name": "other" }), "u2".to_string())?;
+    assert_validation_error(backend.insert("repo", &json!({ "title": "no name" }), "u1".to_string()));
+
+    let (page, next) = backend.list_by_owner("repo", "u1", None, 3)?;
+    assert_eq!(page.len(), 3);
+    let (rest, last) = backend.list_by_owner("repo", "u1", next, 3)?;
+    assert_eq!(rest.len(), 2);
+    assert!(last.is_none());
+
+    let id = &ids[0];
+    let updated = backend.update("repo", id, &json!({ "name": "renamed" }))?;
+    assert_eq!(updated.body["name"], "renamed");
+    assert_eq!(backend.get_by_unique("repo", "renamed")?.id, *id);
+    backend.delete("repo", id)?;
+    assert_not_found(backend.get("repo", id));
+    // a batch with a missing id deletes nothing
+    assert_not_found(backend.batch_delete("repo", &[ids[1].clone(), id.clone()]));
+    assert!(backend.get("repo", &ids[1]).is_ok());
+    Ok(())
+}
+
+#[test]
+fn memory_backend_enforces_unique_and_parent() -> Result<(), Box<dyn std::error::Error>> {
+    let backend = backend()?;
+
+    let repo_a = backend.insert("repo", &json!({ "name": "a" }), "u1".to_string())?;
+    let repo_b = backend.insert("repo", &json!({ "name": "b" }), "u1".to_string())?;
+    let err = backend.insert("repo", &json!({ "name": "a" }), "u1".to_string());
+    assert!(matches!(err, Err(StoreError::Validation(msg)) if msg.contains("unique constraint")));
+
+    // the parent must exist
+    assert_validation_error(backend.insert("post", &json!({ "title": "t", "repo_id": "missing" }), "u1".to_string()));
+    let post = backend.insert("post", &json!({ "title": "t", "repo_id": repo_a }), "u1".to_string())?;
+    // unique among siblings only
+    assert_validation_error(backend.insert("post", &json!({ "title": "t", "repo_id": repo_a }), "u1".to_string()));
+    backend.insert("post", &json!({ "title": "t", "repo_id": repo_b }), "u1".to_string())?;
+    assert_validation_error(backend.get_by_unique("post", "t"));
+
+    let (children, _) = backend.list_children("post", &repo_a, None, 10)?;
+    assert_eq!(children.len(), 1);
+    // moving next to a sibling with the same title is rejected, the post stays
+    assert_validation_error(backend.move_to_parent("post", &post, &repo_b));
+    assert_eq!(backend.get("post", &post)?.parent_id.as_deref(), Some(repo_a.as_str()));
+    backend.delete("post", &backend.list_children("post", &repo_b, None, 10)?.0[0].id)?;
+    let moved = backend.move_to_parent("post", &post, &repo_b)?;
+    assert_eq!(moved.body["repo_id"], repo_b);
+    assert_eq!(moved.parent_id, Some(repo_b));
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_backend_over_memory() -> Result<(), Box<dyn std::error::Error>> {
+    let backend = AsyncBackend::new(Arc::new(backend()?));
+
+    let repo = backend
+        .insert("repo", &json!({ "name": "async" }), "u1".to_string())
+        .await?;
+    let post = json!({ "title": "first", "repo_id": repo });
+    let post_id = backend.insert("post", &post, "u1".to_string()).await?;
+    let (children, _) = backend.list_children("post", &repo, None, 10).await?;
+    assert_eq!(children[0].id, post_id);
+    assert_eq!(backend.get_by_unique("repo", "async").await?.id, repo);
+    backend.delete("post", &post_id).await?;
+    assert_not_found(backend.get("post", &post_id).await);
+    Ok(())
+}
@@ -3,6 +3,7 @@ use std::{path::PathBuf, sync::Arc};
 use serde_json::json;
 use syncstore::{
     collection,
+    components::DataSchemas,
     error::{StoreError, StoreResult},
     store::Store,
 };
@@ -48,10 +49,6 @@ pub struct BasicTestSuite {
 
 impl BasicTestSuite {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let tmp = tempfile::tempdir()?;
-        let path = tmp.path().to_path_buf();
-        // println!("created temp dir: {}", tmp.path().display());
-
         let post_schemas = collection! {
             "repo" => json!({
                 "type": "object",
@@ -86,8 +83,17 @@ impl BasicTestSuite {
                 "x-parent-id": { "parent": "post", "field": "post_id" }
             }),
         };
+        Self::with_schemas(post_schemas)
+    }
+
+    /// same users and namespace as `new`, but with custom collection schemas
+    pub fn with_schemas(schemas: DataSchemas) -> Result<Self, Box<dyn std::error::Error>> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().to_path_buf();
+        // println!("created temp dir: {}", tmp.path().display());
+
         let namespace = "example_ns".to_string();
-        let store = Store::build(&tmp, vec![(&namespace, post_schemas)])?;
+        let store = Store::build(&tmp, vec![(&namespace, schemas)])?;
 
         store.create_user("user1", "p1")?;
         store.create_user("user2", "p2")?;
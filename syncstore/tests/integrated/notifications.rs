@@ -0,0 +1,140 @@
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+use syncstore::{
+    components::{DeliveryChannel, DeliveryMessage},
+    error::StoreResult,
+    types::{AccessControl, AccessLevel, NotificationMessage, Permission},
+};
+
+use crate::mock::*;
+
+fn message(title: &str) -> NotificationMessage {
+    NotificationMessage {
+        kind: "test".to_string(),
+        title: title.to_string(),
+        content: None,
+        payload: None,
+    }
+}
+
+#[test]
+fn notification_inbox() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let first = store.notify(user1, message("first"))?;
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let second = store.notify(user1, message("second"))?;
+    assert_eq!(store.unread_notification_count(user1)?, 2);
+    assert_eq!(store.unread_notification_count(user2)?, 0);
+
+    // newest first, paginated
+    let (items, marker) = store.list_notifications(user1, false, None, 1)?;
+    assert_eq!(items[0].id, second);
+    assert_eq!(items[0].message.title, "second");
+    let (items, marker) = store.list_notifications(user1, false, marker, 1)?;
+    assert_eq!(items[0].id, first);
+    assert!(marker.is_none());
+
+    // other users can not touch the notification
+    assert_not_found(store.mark_notification_read(user2, &first));
+    assert_not_found(store.delete_notification(user2, &first));
+
+    let item = store.mark_notification_read(user1, &first)?;
+    assert!(item.read);
+    assert_eq!(store.unread_notification_count(user1)?, 1);
+    let (items, _) = store.list_notifications(user1, true, None, 10)?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].id, second);
+
+    assert_eq!(store.mark_all_notifications_read(user1)?, 1);
+    assert_eq!(store.unread_notification_count(user1)?, 0);
+
+    store.delete_notification(user1, &first)?;
+    let (items, _) = store.list_notifications(user1, false, None, 10)?;
+    assert_eq!(items.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn acl_grant_publishes_notification() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Shared Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user1)?;
+    let acl = AccessControl {
+        data_id: repo_id.clone(),
+        permissions: vec![Permission {
+            user: user2.to_string(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl.clone(), user1)?;
+    // granting the same access again does not notify twice
+    store.update_acl((namespace, "repo"), acl, user1)?;
+
+    let (items, _) = store.list_notifications(user2, true, None, 10)?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].message.kind, "acl_granted");
+    let payload = items[0].message.payload.clone().unwrap();
+    assert_eq!(payload["data_id"], repo_id);
+    assert_eq!(payload["access_level"], "read");
+
+    Ok(())
+}
+
+struct RecordingChannel {
+    sent: Mutex<Vec<(String, DeliveryMessage)>>,
+}
+
+#[async_trait::async_trait]
+impl DeliveryChannel for RecordingChannel {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    async fn deliver(&self, address: &str, message: &DeliveryMessage) -> StoreResult<()> {
+        self.sent.lock().unwrap().push((address.to_string(), message.clone()));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn notification_delivered_to_email() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let channel = Arc::new(RecordingChannel {
+        sent: Mutex::new(Vec::new()),
+    });
+    store.set_delivery_channel(channel.clone())?;
+    let mut user = store.get_user(user1)?;
+    user.email = Some("user1@example.com".to_string());
+    store.update_user(user1, &user)?;
+
+    store.notify(user1, message("hello"))?;
+    // user2 has no email, only the inbox receives it
+    store.notify(user2, message("hello"))?;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let sent = channel.sent.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, "user1@example.com");
+    assert_eq!(sent[0].1.subject, "hello");
+    assert_eq!(store.unread_notification_count(user2)?, 1);
+
+    Ok(())
+}
@@ -0,0 +1,630 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use serde_json::json;
+use syncstore::{
+    backend::encrypt::FieldCipher,
+    components::{DataSchemasBuilder, OutboxConsumer, ReplicationTarget},
+    config::Replication,
+    error::{StoreError, StoreResult},
+    store::Store,
+    types::{
+        AccessControl, AccessLevel, Change, ChangeOp, Mutation, Permission, PushResult, ReplicationAck,
+        ReplicationBatch,
+    },
+};
+
+use crate::mock::*;
+
+// secondary store applying the pushes in process
+struct StandbyTarget {
+    store: Arc<Store>,
+    offline: AtomicBool,
+}
+
+impl ReplicationTarget for StandbyTarget {
+    fn name(&self) -> &str {
+        "standby"
+    }
+
+    fn push(&self, namespace: &str, batch: &ReplicationBatch) -> StoreResult<ReplicationAck> {
+        if self.offline.load(Ordering::SeqCst) {
+            return Err(StoreError::Backend("standby unreachable".to_string()));
+        }
+        self.store.apply_replication(namespace, batch)
+    }
+}
+
+#[test]
+fn changes_replicated_to_secondary() -> Result<(), Box<dyn std::error::Error>> {
+    let primary = BasicTestSuite::new()?;
+    let secondary = BasicTestSuite::new()?;
+    let target = StandbyTarget {
+        store: secondary.store.clone(),
+        offline: AtomicBool::new(false),
+    };
+
+    let store = primary.store.clone();
+    let namespace = &primary.namespace;
+    let user1 = &primary.user1_id;
+    let user2 = &primary.user2_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user1)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user1)?;
+    store.update(
+        namespace,
+        "repo",
+        &repo_id,
+        &json!({ "name": "Renamed", "status": "normal" }),
+        user1,
+    )?;
+    let acl = AccessControl {
+        data_id: repo_id.clone(),
+        permissions: vec![Permission {
+            user: user2.clone(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user1)?;
+    store.delete(namespace, "post", &post_id, user1)?;
+
+    assert_eq!(store.replicate_namespace(namespace, &target, 2)?, 5);
+    // caught up, nothing left to push
+    assert_eq!(store.replicate_namespace(namespace, &target, 2)?, 0);
+
+    let replica = secondary.store.clone();
+    assert_eq!(
+        replica.get(namespace, "repo", &repo_id, user1)?,
+        store.get(namespace, "repo", &repo_id, user1)?
+    );
+    assert_not_found(replica.get(namespace, "post", &post_id, user1));
+    // the acl came along
+    replica.get(namespace, "repo", &repo_id, user2)?;
+
+    Ok(())
+}
+
+#[test]
+fn replication_is_idempotent() -> Result<(), Box<dyn std::error::Error>> {
+    let primary = BasicTestSuite::new()?;
+    let secondary = BasicTestSuite::new()?;
+
+    let store = primary.store.clone();
+    let namespace = &primary.namespace;
+    let user = &primary.user1_id;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user)?;
+    store.update(
+        namespace,
+        "repo",
+        &repo_id,
+        &json!({ "name": "Renamed", "status": "normal" }),
+        user,
+    )?;
+
+    let changes = store.get_data_backend(namespace)?.changes_since(0, 100)?;
+    assert_eq!(changes.len(), 2);
+    let batch = ReplicationBatch { changes };
+    let ack = secondary.store.apply_replication(namespace, &batch)?;
+    assert_eq!(ack.applied, 2);
+    assert_eq!(ack.last_seq, Some(2));
+    // a retried push leaves the same state
+    secondary.store.apply_replication(namespace, &batch)?;
+    let item = secondary.store.get(namespace, "repo", &repo_id, user)?;
+    assert_eq!(item.body["name"], "Renamed");
+    assert_eq!(item, store.get(namespace, "repo", &repo_id, user)?);
+
+    Ok(())
+}
+
+#[test]
+fn replication_job_resumes_after_failure() -> Result<(), Box<dyn std::error::Error>> {
+    let primary = BasicTestSuite::new()?;
+    let secondary = BasicTestSuite::new()?;
+    let target = Arc::new(StandbyTarget {
+        store: secondary.store.clone(),
+        offline: AtomicBool::new(true),
+    });
+
+    let store = primary.store.clone();
+    let namespace = &primary.namespace;
+    let user = &primary.user1_id;
+    let config = Replication {
+        target: "standby".to_string(),
+        interval: Duration::from_secs(3600),
+        batch_size: 100,
+        timeout: None,
+    };
+    store.schedule_replication(target.clone(), &config)?;
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user)?;
+    let job = format!("replication:{}", namespace);
+
+    let status = store.scheduler().run_now(&job)?;
+    assert_eq!(status.failures, 1);
+    let lag = store.replication_status()?;
+    let ns_lag = lag.iter().find(|s| &s.namespace == namespace).unwrap();
+    assert_eq!(ns_lag.head_seq, 1);
+    assert_eq!(ns_lag.replicated_seq, 0);
+    assert_not_found(secondary.store.get(namespace, "repo", &repo_id, user));
+
+    target.offline.store(false, Ordering::SeqCst);
+    let status = store.scheduler().run_now(&job)?;
+    assert_eq!(status.last_error, None);
+    let lag = store.replication_status()?;
+    let ns_lag = lag.iter().find(|s| &s.namespace == namespace).unwrap();
+    assert_eq!(ns_lag.replicated_seq, ns_lag.head_seq);
+    secondary.store.get(namespace, "repo", &repo_id, user)?;
+
+    Ok(())
+}
+
+// consumer failing on the first change of a given collection
+struct FlakyConsumer {
+    fail_on: &'static str,
+    failed: AtomicBool,
+    seen: Mutex<Vec<i64>>,
+}
+
+impl OutboxConsumer for FlakyConsumer {
+    fn name(&self) -> &str {
+        "flaky"
+    }
+
+    fn consume(&self, _namespace: &str, changes: &[Change]) -> StoreResult<()> {
+        if changes.iter().any(|c| c.collection == self.fail_on) && !self.failed.swap(true, Ordering::SeqCst) {
+            return Err(StoreError::Backend("consumer crashed".to_string()));
+        }
+        self.seen.lock().unwrap().extend(changes.iter().map(|c| c.seq));
+        Ok(())
+    }
+}
+
+#[test]
+fn outbox_dispatch_loses_no_event() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    let consumer = FlakyConsumer {
+        fail_on: "post",
+        failed: AtomicBool::new(false),
+        seen: Mutex::new(Vec::new()),
+    };
+
+    let repo_id = store.insert(namespace, "repo", &json!({ "name": "Repo", "status": "normal" }), user)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    store.insert(namespace, "post", &post, user)?;
+    store.delete(namespace, "repo", &repo_id, user)?;
+
+    // the batch holding the post fails, the ones before it stay dispatched
+    assert!(store.dispatch_outbox(namespace, &consumer, 1).is_err());
+    assert_eq!(*consumer.seen.lock().unwrap(), vec![1]);
+
+    assert_eq!(store.dispatch_outbox(namespace, &consumer, 1)?, 2);
+    assert_eq!(*consumer.seen.lock().unwrap(), vec![1, 2, 3]);
+    assert_eq!(store.dispatch_outbox(namespace, &consumer, 1)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn change_feed_lists_readable_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let (user1, user2) = (&s.user1_id, &s.user2_id);
+
+    let shared = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "shared", "status": "normal" }),
+        user1,
+    )?;
+    let gone = store.insert(namespace, "repo", &json!({ "name": "gone", "status": "normal" }), user1)?;
+    store.delete(namespace, "repo", &gone, user1)?;
+    let own = store.insert(namespace, "repo", &json!({ "name": "own", "status": "normal" }), user2)?;
+
+    let feed = store.changes(namespace, None, 100, user2)?;
+    let ids: Vec<_> = feed.changes.iter().map(|c| c.data_id.as_str()).collect();
+    assert_eq!(ids, vec![own.as_str()]);
+    assert!(!feed.has_more);
+    // the delete is listed to whoever could read the document
+    let feed = store.changes(namespace, None, 100, user1)?;
+    let ops: Vec<_> = feed.changes.iter().map(|c| (c.data_id.as_str(), c.op.name())).collect();
+    assert_eq!(
+        ops,
+        vec![
+            (shared.as_str(), "upsert"),
+            (gone.as_str(), "upsert"),
+            (gone.as_str(), "delete")
+        ]
+    );
+
+    let first_seq = feed.changes[0].seq;
+
+    // resumed from the cursor, only the changes after it
+    let cursor = feed.cursor.clone();
+    let acl = AccessControl {
+        data_id: shared.clone(),
+        permissions: vec![Permission {
+            user: user2.to_string(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user1)?;
+    let feed = store.changes(namespace, Some(cursor), 100, user2)?;
+    let ops: Vec<_> = feed.changes.iter().map(|c| (c.data_id.as_str(), c.op.name())).collect();
+    assert_eq!(ops, vec![(shared.as_str(), "acl")]);
+
+    // a page looks at `limit` changes
+    let page = store.changes(namespace, None, 1, user2)?;
+    assert!(page.changes.is_empty());
+    assert_eq!(page.cursor, format!("{}.0", first_seq));
+    assert!(page.has_more);
+
+    assert_validation_error(store.changes(namespace, Some("next".to_string()), 10, user2));
+    Ok(())
+}
+
+#[test]
+fn change_feed_decrypts_encrypted_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = json!({
+        "type": "object",
+        "properties": { "name": { "type": "string" }, "email": { "type": "string" } },
+        "required": ["name", "email"],
+        "x-encrypt-fields": ["email"]
+    });
+    let s = BasicTestSuite::with_schemas(DataSchemasBuilder::new().add_schema("account", schema).build())?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    store.set_field_cipher(Some(FieldCipher::new(&[42; 32])?))?;
+
+    let id = store.insert(
+        namespace,
+        "account",
+        &json!({ "name": "alice", "email": "alice@example.com" }),
+        user,
+    )?;
+    let feed = store.changes(namespace, None, 100, user)?;
+    assert_eq!(feed.changes.len(), 1);
+    match &feed.changes[0].op {
+        ChangeOp::Upsert { item } => {
+            assert_eq!(item.id, id);
+            assert_eq!(item.body["email"], "alice@example.com");
+        }
+        other => panic!("expected an upsert, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn change_feed_under_parent() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    let post = |repo: &str, title: &str| json!({ "title": title, "repo_id": repo, "category": "c", "content": "" });
+
+    let repo_a = store.insert(namespace, "repo", &json!({ "name": "a", "status": "normal" }), user)?;
+    let repo_b = store.insert(namespace, "repo", &json!({ "name": "b", "status": "normal" }), user)?;
+    let kept = store.insert(namespace, "post", &post(&repo_a, "kept"), user)?;
+    store.insert(namespace, "post", &post(&repo_b, "other"), user)?;
+    let gone = store.insert(namespace, "post", &post(&repo_a, "gone"), user)?;
+    store.update(namespace, "post", &kept, &post(&repo_a, "kept v2"), user)?;
+    store.delete(namespace, "post", &gone, user)?;
+
+    let feed = store.changes_under(namespace, &repo_a, None, 100, user)?;
+    let ops: Vec<_> = feed.changes.iter().map(|c| (c.data_id.as_str(), c.op.name())).collect();
+    assert_eq!(
+        ops,
+        vec![
+            (kept.as_str(), "upsert"),
+            (gone.as_str(), "upsert"),
+            (kept.as_str(), "upsert"),
+            (gone.as_str(), "delete")
+        ]
+    );
+    assert!(!feed.has_more);
+
+    // a full page resumes after its last change
+    let page = store.changes_under(namespace, &repo_a, None, 1, user)?;
+    assert_eq!(page.changes.len(), 1);
+    assert!(page.has_more);
+    let page = store.changes_under(namespace, &repo_a, Some(page.cursor), 3, user)?;
+    assert_eq!(page.changes.len(), 3);
+
+    // the changes of the other parents move the cursor along without being listed
+    store.insert(namespace, "post", &post(&repo_b, "elsewhere"), user)?;
+    let feed = store.changes_under(namespace, &repo_a, Some(feed.cursor), 100, user)?;
+    assert!(feed.changes.is_empty());
+    assert!(!feed.has_more);
+    let late = store.insert(namespace, "post", &post(&repo_a, "late"), user)?;
+    let feed = store.changes_under(namespace, &repo_a, Some(feed.cursor), 100, user)?;
+    let ids: Vec<_> = feed.changes.iter().map(|c| c.data_id.as_str()).collect();
+    assert_eq!(ids, vec![late.as_str()]);
+    Ok(())
+}
+
+#[test]
+fn change_feed_under_parent_follows_moves() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let (user1, user2) = (&s.user1_id, &s.user2_id);
+    let post = |repo: &str| json!({ "title": "moving", "repo_id": repo, "category": "c", "content": "" });
+
+    let repo_a = store.insert(namespace, "repo", &json!({ "name": "a", "status": "normal" }), user1)?;
+    let repo_b = store.insert(namespace, "repo", &json!({ "name": "b", "status": "normal" }), user1)?;
+    // user2 reads the posts of repo a only
+    let acl = AccessControl {
+        data_id: repo_a.clone(),
+        permissions: vec![Permission {
+            user: user2.to_string(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user1)?;
+    let moved = store.insert(namespace, "post", &post(&repo_a), user1)?;
+    let start = store.changes_under(namespace, &repo_a, None, 100, user1)?.cursor;
+    store.move_item(namespace, "post", &moved, &repo_b, user1)?;
+
+    // the old parent lists the move as well, the new parent then follows the document
+    for repo in [&repo_a, &repo_b] {
+        let feed = store.changes_under(namespace, repo, Some(start.clone()), 100, user1)?;
+        assert_eq!(feed.changes.len(), 1);
+        let ChangeOp::Upsert { item } = &feed.changes[0].op else {
+            panic!("expected an upsert, got {:?}", feed.changes[0].op);
+        };
+        assert_eq!(item.parent_id.as_deref(), Some(repo_b.as_str()));
+    }
+
+    // a user who can not follow it to the new parent sees it leave
+    let feed = store.changes_under(namespace, &repo_a, Some(start), 100, user2)?;
+    let ops: Vec<_> = feed.changes.iter().map(|c| (c.data_id.as_str(), c.op.name())).collect();
+    assert_eq!(ops, vec![(moved.as_str(), "delete")]);
+    Ok(())
+}
+
+#[test]
+fn pull_changes_returns_current_documents() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let (user1, user2) = (&s.user1_id, &s.user2_id);
+
+    let edited = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "draft", "status": "normal" }),
+        user1,
+    )?;
+    store.update(
+        namespace,
+        "repo",
+        &edited,
+        &json!({ "name": "final", "status": "normal" }),
+        user1,
+    )?;
+    let gone = store.insert(namespace, "repo", &json!({ "name": "gone", "status": "normal" }), user1)?;
+    store.delete(namespace, "repo", &gone, user1)?;
+    store.insert(namespace, "repo", &json!({ "name": "own", "status": "normal" }), user2)?;
+
+    // documents changed twice come once, with their current body
+    let pulled = store.pull_changes(namespace, user1, None, 100)?;
+    assert_eq!(pulled.items.len(), 1);
+    assert_eq!(pulled.items[0].id, edited);
+    assert_eq!(pulled.items[0].body["name"], "final");
+    assert_eq!(pulled.deleted.len(), 1);
+    assert_eq!(pulled.deleted[0].id, gone);
+    assert!(!pulled.has_more);
+
+    // resumed from the cursor, only what changed after it
+    let cursor = pulled.cursor.clone();
+    let acl = AccessControl {
+        data_id: edited.clone(),
+        permissions: vec![Permission {
+            user: user2.to_string(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user1)?;
+    let pulled = store.pull_changes(namespace, user2, Some(&cursor), 100)?;
+    let ids: Vec<_> = pulled.items.iter().map(|i| i.id.as_str()).collect();
+    assert_eq!(ids, vec![edited.as_str()]);
+    assert!(pulled.deleted.is_empty());
+
+    let page = store.pull_changes(namespace, user2, None, 1)?;
+    assert!(page.items.is_empty());
+    assert!(page.has_more);
+
+    assert_validation_error(store.pull_changes(namespace, user2, Some("next"), 10));
+    Ok(())
+}
+
+#[test]
+fn push_changes_answers_every_mutation() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let (user1, user2) = (&s.user1_id, &s.user2_id);
+
+    let edited = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "draft", "status": "normal" }),
+        user1,
+    )?;
+    let others = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "others", "status": "normal" }),
+        user2,
+    )?;
+    let mutations = vec![
+        Mutation::Create {
+            collection: "repo".to_string(),
+            id: Some("offline-1".to_string()),
+            body: json!({ "name": "offline", "status": "normal" }),
+        },
+        Mutation::Update {
+            collection: "repo".to_string(),
+            id: edited.clone(),
+            body: json!({ "name": "final", "status": "normal" }),
+            base_rev: Some(1),
+        },
+        Mutation::Delete {
+            collection: "repo".to_string(),
+            id: others.clone(),
+            base_rev: None,
+        },
+    ];
+    let results = store.push_changes(namespace, &mutations, user1)?;
+    assert_eq!(
+        results[0],
+        PushResult::Accepted {
+            id: "offline-1".to_string(),
+            rev: Some(1)
+        }
+    );
+    assert_eq!(
+        results[1],
+        PushResult::Accepted {
+            id: edited.clone(),
+            rev: Some(2)
+        }
+    );
+    assert!(matches!(&results[2], PushResult::Rejected { id: Some(id), .. } if *id == others));
+    assert_eq!(store.get(namespace, "repo", &edited, user1)?.body["name"], "final");
+    assert!(store.get(namespace, "repo", &others, user2).is_ok());
+
+    // pushed again, the writes made offline meanwhile are conflicts
+    let results = store.push_changes(namespace, &mutations[..2], user1)?;
+    assert!(matches!(&results[0], PushResult::Conflict { .. }));
+    assert!(matches!(&results[1], PushResult::Conflict { id: Some(id), .. } if *id == edited));
+    Ok(())
+}
+
+#[test]
+fn tombstones_are_pruned_after_retention() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+
+    let kept = store.insert(namespace, "repo", &json!({ "name": "kept", "status": "normal" }), user1)?;
+    let cursor = store.pull_changes(namespace, user1, None, 100)?.cursor;
+    let feed_cursor = store.changes(namespace, None, 100, user1)?.cursor;
+    let gone = store.insert(namespace, "repo", &json!({ "name": "gone", "status": "normal" }), user1)?;
+    store.delete(namespace, "repo", &gone, user1)?;
+
+    let pulled = store.pull_changes(namespace, user1, Some(&cursor), 100)?;
+    assert_eq!(pulled.deleted.len(), 1);
+    assert_eq!(pulled.deleted[0].id, gone);
+    assert_eq!(pulled.deleted[0].deleted_by.as_deref(), Some(user1.as_str()));
+    let seen = pulled.cursor.clone();
+
+    // within the retention the tombstone stays
+    assert_eq!(store.prune_tombstones(namespace, Duration::from_secs(60 * 60))?, 0);
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(store.prune_tombstones(namespace, Duration::ZERO)?, 1);
+
+    // the deleted document left the feed, a cursor from before it must sync again
+    let feed = store.changes(namespace, None, 100, user1)?;
+    let ids: Vec<_> = feed.changes.iter().map(|c| c.data_id.as_str()).collect();
+    assert_eq!(ids, vec![kept.as_str()]);
+    assert!(matches!(
+        store.pull_changes(namespace, user1, Some(&cursor), 100),
+        Err(StoreError::Conflict(_))
+    ));
+    assert!(matches!(
+        store.changes(namespace, Some(feed_cursor), 100, user1),
+        Err(StoreError::Conflict(_))
+    ));
+    let restarted = store.changes(namespace, None, 100, user1)?;
+    assert!(store.changes(namespace, Some(restarted.cursor), 100, user1).is_ok());
+    // a cursor past the pruned tombstones missed nothing
+    assert!(store.pull_changes(namespace, user1, Some(&seen), 100).is_ok());
+    let pulled = store.pull_changes(namespace, user1, None, 100)?;
+    assert!(pulled.deleted.is_empty());
+    assert!(store.pull_changes(namespace, user1, Some(&pulled.cursor), 100).is_ok());
+    Ok(())
+}
+
+#[test]
+fn push_conflict_returns_both_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+
+    let edited = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "draft", "status": "normal" }),
+        user1,
+    )?;
+    let gone = store.insert(namespace, "repo", &json!({ "name": "gone", "status": "normal" }), user1)?;
+    // changed on the server while the client was offline at revision 1
+    store.update(
+        namespace,
+        "repo",
+        &edited,
+        &json!({ "name": "server", "status": "normal" }),
+        user1,
+    )?;
+    store.delete(namespace, "repo", &gone, user1)?;
+
+    let offline = json!({ "name": "client", "status": "normal" });
+    let mutations = vec![
+        Mutation::Update {
+            collection: "repo".to_string(),
+            id: edited.clone(),
+            body: offline.clone(),
+            base_rev: Some(1),
+        },
+        Mutation::Update {
+            collection: "repo".to_string(),
+            id: gone.clone(),
+            body: offline.clone(),
+            base_rev: Some(1),
+        },
+    ];
+    let results = store.push_changes(namespace, &mutations, user1)?;
+    match &results[0] {
+        PushResult::Conflict { server, client, .. } => {
+            let server = server.as_ref().expect("server version");
+            assert_eq!(server.rev, 2);
+            assert_eq!(server.body["name"], "server");
+            assert_eq!(client.as_ref(), Some(&offline));
+        }
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    assert!(matches!(&results[1], PushResult::Conflict { server: None, .. }));
+    // nothing was overwritten
+    assert_eq!(store.get(namespace, "repo", &edited, user1)?.body["name"], "server");
+
+    // merged at the server revision it goes through
+    let merged = vec![Mutation::Update {
+        collection: "repo".to_string(),
+        id: edited.clone(),
+        body: json!({ "name": "server and client", "status": "normal" }),
+        base_rev: Some(2),
+    }];
+    let results = store.push_changes(namespace, &merged, user1)?;
+    assert_eq!(
+        results[0],
+        PushResult::Accepted {
+            id: edited,
+            rev: Some(3)
+        }
+    );
+    Ok(())
+}
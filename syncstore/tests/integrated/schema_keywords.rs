@@ -0,0 +1,1168 @@
+use std::sync::Arc;
+
+use serde_json::json;
+use syncstore::{
+    backend::{
+        encrypt::FieldCipher,
+        keyword::{Keyword, KeywordContext, KeywordFactory, ValidationError},
+    },
+    components::DataSchemasBuilder,
+    error::StoreError,
+    store::Listing,
+    types::{AccessControl, AccessLevel, BatchOutcome, ChangeOp, Permission},
+};
+
+use crate::mock::*;
+
+#[test]
+fn ref_to_shared_definition() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_definition(
+            "address",
+            json!({
+                "type": "object",
+                "properties": {
+                    "city": { "type": "string" },
+                    "zip": { "type": "string", "pattern": "^[0-9]{5}$" }
+                },
+                "required": ["city"]
+            }),
+        )
+        .add_schema(
+            "contact",
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "home": { "$ref": "urn:syncstore:address" },
+                    "work": { "$ref": "urn:syncstore:address" }
+                },
+                "required": ["name", "home"]
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let doc = json!({ "name": "alice", "home": { "city": "Paris", "zip": "75001" } });
+    store.insert(namespace, "contact", &doc, user)?;
+
+    // the shared definition is enforced on every referencing field
+    let doc = json!({ "name": "bob", "home": { "city": "Paris" }, "work": { "zip": "75001" } });
+    assert_validation_error(store.insert(namespace, "contact", &doc, user));
+    let doc = json!({ "name": "bob", "home": { "city": "Paris", "zip": "abc" } });
+    assert_validation_error(store.insert(namespace, "contact", &doc, user));
+
+    Ok(())
+}
+
+#[test]
+fn ref_to_other_collection_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "tag",
+            json!({
+                "type": "object",
+                "properties": { "label": { "type": "string" } },
+                "required": ["label"]
+            }),
+        )
+        .add_schema(
+            "note",
+            json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string" },
+                    "tags": { "type": "array", "items": { "$ref": "urn:syncstore:tag" } }
+                },
+                "required": ["text"]
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    store.insert(
+        namespace,
+        "note",
+        &json!({ "text": "hi", "tags": [{ "label": "a" }] }),
+        user,
+    )?;
+    assert_validation_error(store.insert(namespace, "note", &json!({ "text": "hi", "tags": [{}] }), user));
+
+    Ok(())
+}
+
+#[test]
+fn schema_draft_selected_by_dollar_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        // `prefixItems` only exists since draft 2020-12
+        .add_schema(
+            "point",
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "coords": { "type": "array", "prefixItems": [{ "type": "number" }, { "type": "number" }] }
+                }
+            }),
+        )
+        .add_schema(
+            "legacy_point",
+            json!({
+                "type": "object",
+                "properties": {
+                    "coords": { "type": "array", "prefixItems": [{ "type": "number" }, { "type": "number" }] }
+                }
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let doc = json!({ "coords": [1, "two"] });
+    assert_validation_error(store.insert(namespace, "point", &doc, user));
+    // draft 7 ignores the unknown keyword
+    store.insert(namespace, "legacy_point", &doc, user)?;
+
+    Ok(())
+}
+
+#[test]
+fn unknown_dollar_schema_rejected() {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "bad",
+            json!({ "$schema": "https://example.com/my-meta", "type": "object" }),
+        )
+        .build();
+    assert!(BasicTestSuite::with_schemas(schemas).is_err());
+}
+
+#[test]
+fn computed_fields_on_insert_and_update() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "article",
+            json!({
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string" },
+                    "content": { "type": "string" },
+                    "slug": { "type": "string" },
+                    "words": { "type": "integer" }
+                },
+                "required": ["title", "slug"],
+                "x-unique": "slug",
+                "x-computed": {
+                    "slug": { "op": "slug", "from": "title" },
+                    "words": { "op": "word_count", "from": "content" }
+                }
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    // client supplied values of computed fields are overwritten
+    let doc = json!({ "title": "Hello, World!", "content": "one two three", "slug": "whatever" });
+    let id = store.insert(namespace, "article", &doc, user)?;
+    let item = store.get(namespace, "article", &id, user)?;
+    assert_eq!(item.body["slug"], "hello-world");
+    assert_eq!(item.body["words"], 3);
+    assert_eq!(item.unique.as_deref(), Some("hello-world"));
+
+    // computed slug takes part in uniqueness
+    assert_validation_error(store.insert(namespace, "article", &json!({ "title": "hello world" }), user));
+
+    let item = store.update(namespace, "article", &id, &json!({ "title": "Second Title" }), user)?;
+    assert_eq!(item.body["slug"], "second-title");
+    assert!(item.body.get("words").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn invalid_computed_expression_rejected() {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "bad",
+            json!({ "type": "object", "x-computed": { "a": { "op": "eval", "from": "b" } } }),
+        )
+        .build();
+    assert!(BasicTestSuite::with_schemas(schemas).is_err());
+}
+
+#[test]
+fn timestamps_injected_into_body() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "memo",
+            json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string" },
+                    "createdAt": { "type": "string", "format": "date-time" }
+                },
+                // required fields are filled before validation
+                "required": ["text", "createdAt", "updatedAt"],
+                "x-inject-timestamps": { "created_at": "createdAt", "updated_at": "updatedAt" }
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let id = store.insert(namespace, "memo", &json!({ "text": "a", "createdAt": "forged" }), user)?;
+    let item = store.get(namespace, "memo", &id, user)?;
+    assert_eq!(item.body["createdAt"], item.created_at.to_rfc3339());
+    assert_eq!(item.body["updatedAt"], item.updated_at.to_rfc3339());
+
+    let updated = store.update(namespace, "memo", &id, &json!({ "text": "b" }), user)?;
+    assert_eq!(updated.body["createdAt"], item.created_at.to_rfc3339());
+    assert_eq!(updated.body["updatedAt"], updated.updated_at.to_rfc3339());
+    assert_ne!(updated.body["updatedAt"], item.body["updatedAt"]);
+
+    Ok(())
+}
+
+fn scoped_unique_schemas() -> syncstore::components::DataSchemas {
+    DataSchemasBuilder::new()
+        .add_schema(
+            "folder",
+            json!({ "type": "object", "properties": { "name": { "type": "string" } }, "required": ["name"] }),
+        )
+        .add_schema(
+            "page",
+            json!({
+                "type": "object",
+                "properties": {
+                    "folder_id": { "type": "string" },
+                    "title": { "type": "string" }
+                },
+                "required": ["folder_id", "title"],
+                "x-parent-id": { "parent": "folder", "field": "folder_id" },
+                "x-unique": "title",
+                "x-unique-scope": "parent"
+            }),
+        )
+        .build()
+}
+
+#[test]
+fn unique_scoped_by_parent() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::with_schemas(scoped_unique_schemas())?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let f1 = store.insert(namespace, "folder", &json!({ "name": "f1" }), user)?;
+    let f2 = store.insert(namespace, "folder", &json!({ "name": "f2" }), user)?;
+
+    let p1 = store.insert(namespace, "page", &json!({ "folder_id": f1, "title": "intro" }), user)?;
+    // same title under another parent is fine
+    store.insert(namespace, "page", &json!({ "folder_id": f2, "title": "intro" }), user)?;
+    // but not twice under the same parent
+    assert_validation_error(store.insert(namespace, "page", &json!({ "folder_id": f1, "title": "intro" }), user));
+
+    let p2 = store.insert(namespace, "page", &json!({ "folder_id": f1, "title": "other" }), user)?;
+    assert_validation_error(store.update(
+        namespace,
+        "page",
+        &p2,
+        &json!({ "folder_id": f1, "title": "intro" }),
+        user,
+    ));
+
+    let backend = store.get_data_backend(namespace)?;
+    assert_eq!(backend.get_by_parent_unique("page", &f1, "intro")?.id, p1);
+
+    Ok(())
+}
+
+#[test]
+fn unique_scope_requires_parent() {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "bad",
+            json!({ "type": "object", "x-unique": "name", "x-unique-scope": "parent" }),
+        )
+        .build();
+    assert!(BasicTestSuite::with_schemas(schemas).is_err());
+}
+
+#[test]
+fn unique_per_parent_shorthand() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("folder", json!({ "type": "object" }))
+        .add_schema(
+            "page",
+            json!({
+                "type": "object",
+                "x-parent-id": { "parent": "folder", "field": "folder_id" },
+                "x-unique-per-parent": "slug"
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let f1 = store.insert(namespace, "folder", &json!({}), user)?;
+    let f2 = store.insert(namespace, "folder", &json!({}), user)?;
+    store.insert(namespace, "page", &json!({ "folder_id": f1, "slug": "intro" }), user)?;
+    store.insert(namespace, "page", &json!({ "folder_id": f2, "slug": "intro" }), user)?;
+    assert_validation_error(store.insert(namespace, "page", &json!({ "folder_id": f1, "slug": "intro" }), user));
+
+    // combined with x-unique or a scope it is ambiguous
+    for extra in [json!({ "x-unique": "slug" }), json!({ "x-unique-scope": "global" })] {
+        let mut page = json!({
+            "type": "object",
+            "x-parent-id": { "parent": "folder", "field": "folder_id" },
+            "x-unique-per-parent": "slug"
+        });
+        page.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        let schemas = DataSchemasBuilder::new()
+            .add_schema("folder", json!({ "type": "object" }))
+            .add_schema("page", page)
+            .build();
+        assert!(BasicTestSuite::with_schemas(schemas).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn unique_scope_migrates_existing_table() -> Result<(), Box<dyn std::error::Error>> {
+    use syncstore::backend::{Backend, sqlite::SqliteBackendBuilder};
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("scoped.db");
+    let folder = json!({ "type": "object" });
+    let mut page = json!({
+        "type": "object",
+        "x-parent-id": { "parent": "folder", "field": "folder_id" },
+        "x-unique": "title"
+    });
+    let backend = SqliteBackendBuilder::file(&path)
+        .with_collection_schema("folder", folder.clone())
+        .with_collection_schema("page", page.clone())
+        .build()?;
+    let f1 = backend.insert("folder", &json!({}), "u".to_string())?;
+    let f2 = backend.insert("folder", &json!({}), "u".to_string())?;
+    let p1 = backend.insert("page", &json!({ "folder_id": f1, "title": "a" }), "u".to_string())?;
+    assert!(
+        backend
+            .insert("page", &json!({ "folder_id": f2, "title": "a" }), "u".to_string())
+            .is_err()
+    );
+    drop(backend);
+
+    page["x-unique-scope"] = json!("parent");
+    let backend = SqliteBackendBuilder::file(&path)
+        .with_collection_schema("folder", folder)
+        .with_collection_schema("page", page)
+        .build()?;
+    // existing rows survive the rebuild
+    assert_eq!(backend.get("page", &p1)?.body["title"], "a");
+    backend.insert("page", &json!({ "folder_id": f2, "title": "a" }), "u".to_string())?;
+    assert!(
+        backend
+            .insert("page", &json!({ "folder_id": f1, "title": "a" }), "u".to_string())
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parent_in_other_namespace() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let repos = DataSchemasBuilder::new()
+        .add_schema("repo", json!({ "type": "object" }))
+        .build();
+    let issues = DataSchemasBuilder::new()
+        .add_schema(
+            "issue",
+            json!({
+                "type": "object",
+                "x-parent-id": { "namespace": "repos", "parent": "repo", "field": "repo_id" }
+            }),
+        )
+        .build();
+    let store = syncstore::store::Store::build(tmp.path(), vec![("repos", repos), ("issues", issues)])?;
+    store.create_user("user1", "p1")?;
+    store.create_user("user2", "p2")?;
+    let user1 = &store.validate_user("user1", "p1")?.unwrap();
+    let user2 = &store.validate_user("user2", "p2")?.unwrap();
+
+    let repo = store.insert("repos", "repo", &json!({}), user1)?;
+    let issue = store.insert("issues", "issue", &json!({ "repo_id": repo }), user1)?;
+    assert_eq!(
+        store.get("issues", "issue", &issue, user1)?.parent_id.as_deref(),
+        Some(repo.as_str())
+    );
+    assert_not_found(store.insert("issues", "issue", &json!({ "repo_id": "missing" }), user1));
+    assert_permission_denied(store.insert("issues", "issue", &json!({ "repo_id": repo }), user2));
+    // moving the issue checks the new parent in the other namespace, alone or in a batch
+    assert_not_found(store.update("issues", "issue", &issue, &json!({ "repo_id": "missing" }), user1));
+    let outcome = store.update_many(
+        "issues",
+        "issue",
+        vec![(issue.clone(), json!({ "repo_id": "missing" }))],
+        user1,
+    )?;
+    assert!(matches!(outcome, BatchOutcome::Rejected { failures } if failures.len() == 1));
+
+    // an acl on the repo reaches the issues of the other namespace
+    let acl = AccessControl {
+        data_id: repo.clone(),
+        permissions: vec![Permission {
+            user: user2.to_string(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl(("repos", "repo"), acl, user1)?;
+    assert_eq!(store.get("issues", "issue", &issue, user2)?.id, issue);
+    let (children, _) = store.list_children("issues", "issue", &repo, None, 10, user2)?;
+    assert_eq!(children.len(), 1);
+    Ok(())
+}
+
+// `"x-exists-in": "<collection>"`, the string must be the id of a document of that collection
+struct ExistsIn {
+    collection: String,
+    db: KeywordContext,
+}
+
+impl Keyword for ExistsIn {
+    fn validate<'i>(&self, instance: &'i serde_json::Value) -> Result<(), ValidationError<'i>> {
+        if self.is_valid(instance) {
+            Ok(())
+        } else {
+            Err(ValidationError::custom(format!(
+                "{} is not in {}",
+                instance, self.collection
+            )))
+        }
+    }
+
+    fn is_valid(&self, instance: &serde_json::Value) -> bool {
+        instance
+            .as_str()
+            .is_some_and(|id| self.db.exists(&self.collection, id).unwrap_or(false))
+    }
+}
+
+fn exists_in_schemas() -> syncstore::components::DataSchemas {
+    let factory: KeywordFactory = Arc::new(|value, db| {
+        let collection = value
+            .as_str()
+            .ok_or_else(|| StoreError::Validation("x-exists-in takes a collection".to_string()))?;
+        Ok(Box::new(ExistsIn {
+            collection: collection.to_string(),
+            db,
+        }))
+    });
+    DataSchemasBuilder::new()
+        .add_keyword("x-exists-in", factory)
+        .add_schema("tag", json!({ "type": "object" }))
+        .add_schema(
+            "post",
+            json!({
+                "type": "object",
+                "properties": { "tags": { "type": "array", "items": { "x-exists-in": "tag" } } }
+            }),
+        )
+        .build()
+}
+
+#[test]
+fn custom_keyword_reads_database() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::with_schemas(exists_in_schemas())?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let tag = store.insert(namespace, "tag", &json!({ "name": "rust" }), user)?;
+    store.insert(namespace, "post", &json!({ "tags": [tag] }), user)?;
+    assert_validation_error(store.insert(namespace, "post", &json!({ "tags": [tag, "missing"] }), user));
+
+    Ok(())
+}
+
+#[test]
+fn custom_keyword_names_checked() {
+    let factory: KeywordFactory = Arc::new(|_, _| Err(StoreError::Validation("unused".to_string())));
+    let builtin = DataSchemasBuilder::new()
+        .add_keyword("x-parent-id", factory.clone())
+        .build();
+    assert!(BasicTestSuite::with_schemas(builtin).is_err());
+    let twice = DataSchemasBuilder::new()
+        .add_keyword("x-check", factory.clone())
+        .add_keyword("x-check", factory)
+        .build();
+    assert!(BasicTestSuite::with_schemas(twice).is_err());
+}
+
+#[test]
+fn validate_without_storing() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "account",
+            json!({
+                "type": "object",
+                "properties": { "email": { "type": "string" }, "age": { "type": "integer" } },
+                "required": ["email"],
+                "x-unique": "email"
+            }),
+        )
+        .add_schema(
+            "device",
+            json!({
+                "type": "object",
+                "properties": { "account_id": { "type": "string" } },
+                "required": ["account_id"],
+                "x-parent-id": { "parent": "account", "field": "account_id" }
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let report = store.validate_data(namespace, "account", &json!({ "email": "a@b.c" }), None, user)?;
+    assert!(report.valid && report.errors.is_empty());
+    // every error is listed
+    let report = store.validate_data(namespace, "account", &json!({ "age": "old" }), None, user)?;
+    assert!(!report.valid);
+    assert_eq!(report.errors.len(), 2);
+    assert!(report.errors.iter().any(|e| e.path == "/age"));
+
+    // nothing was stored, the value is still free
+    let id = store.insert(namespace, "account", &json!({ "email": "a@b.c" }), user)?;
+    let report = store.validate_data(namespace, "account", &json!({ "email": "a@b.c" }), None, user)?;
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].path, "/email");
+    // the document itself holds the value
+    let report = store.validate_data(namespace, "account", &json!({ "email": "a@b.c" }), Some(&id), user)?;
+    assert!(report.valid);
+
+    let report = store.validate_data(namespace, "device", &json!({ "account_id": "missing" }), None, user)?;
+    assert!(!report.valid);
+    let report = store.validate_data(namespace, "device", &json!({ "account_id": id }), None, user)?;
+    assert!(report.valid);
+    Ok(())
+}
+
+#[test]
+fn collection_names_checked() {
+    for name in [
+        "a-b",
+        "__schemas",
+        "Post",
+        "1post",
+        "post__old",
+        "post_fts",
+        "post_xi_title",
+        "post_owner_hash",
+    ] {
+        let schemas = DataSchemasBuilder::new()
+            .add_schema(name, json!({ "type": "object" }))
+            .build();
+        match BasicTestSuite::with_schemas(schemas) {
+            Err(e) => assert!(e.to_string().contains(name), "{}", e),
+            Ok(_) => panic!("collection name '{}' accepted", name),
+        }
+    }
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("check_list2", json!({ "type": "object" }))
+        .build();
+    assert!(BasicTestSuite::with_schemas(schemas).is_ok());
+}
+
+#[test]
+fn access_stats_counted_for_owner() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "article",
+            json!({
+                "type": "object",
+                "properties": { "title": { "type": "string" } },
+                "required": ["title"],
+                "x-access-stats": true
+            }),
+        )
+        .add_schema("note", json!({ "type": "object" }))
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let id = store.insert(namespace, "article", &json!({ "title": "hello" }), user)?;
+    assert_eq!(store.access_stats(namespace, "article", &id, user)?.reads, 0);
+    for _ in 0..3 {
+        store.get(namespace, "article", &id, user)?;
+    }
+    // the buffered reads are counted before and after being written
+    assert_eq!(store.access_stats(namespace, "article", &id, user)?.reads, 3);
+    assert_eq!(store.flush_access_stats()?, 1);
+    store.get(namespace, "article", &id, user)?;
+    let stats = store.access_stats(namespace, "article", &id, user)?;
+    assert_eq!(stats.reads, 4);
+    assert!(stats.last_accessed.is_some());
+
+    assert_permission_denied(store.access_stats(namespace, "article", &id, &s.user2_id));
+    let note = store.insert(namespace, "note", &json!({}), user)?;
+    assert_validation_error(store.access_stats(namespace, "note", &note, user));
+
+    // reads of a deleted item are dropped with it
+    store.get(namespace, "article", &id, user)?;
+    store.delete(namespace, "article", &id, user)?;
+    assert_eq!(store.flush_access_stats()?, 1);
+    assert_not_found(store.access_stats(namespace, "article", &id, user));
+    Ok(())
+}
+
+#[test]
+fn x_index_lookup_by_field() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "place",
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "rank": { "type": "integer" },
+                    "address": { "type": "object", "properties": { "city": { "type": "string" } } }
+                },
+                "required": ["name"],
+                "x-index": ["rank", "address.city"]
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    for i in 0..5 {
+        let city = if i % 2 == 0 { "Paris" } else { "Lyon" };
+        let doc = json!({ "name": format!("place {i}"), "rank": i % 2, "address": { "city": city } });
+        store.insert(namespace, "place", &doc, user)?;
+    }
+    store.insert(namespace, "place", &json!({ "name": "nowhere" }), user)?;
+
+    let backend = store.get_data_backend(namespace)?;
+    let (page, marker) = backend.list_by_field("place", "address.city", &json!("Paris"), None, 2)?;
+    assert_eq!(page.len(), 2);
+    let (rest, last) = backend.list_by_field("place", "address.city", &json!("Paris"), marker, 2)?;
+    assert_eq!(rest.len(), 1);
+    assert!(last.is_none());
+    let (ones, _) = backend.list_by_field("place", "rank", &json!(1), None, 10)?;
+    assert_eq!(ones.len(), 2);
+    assert!(ones.iter().all(|item| item.body["address"]["city"] == "Lyon"));
+    let (missing, _) = backend.list_by_field("place", "rank", &json!(null), None, 10)?;
+    assert_eq!(missing[0].body["name"], "nowhere");
+
+    assert_validation_error(backend.list_by_field("place", "name", &json!("place 1"), None, 10));
+    assert_validation_error(backend.list_by_field("place", "rank", &json!([1]), None, 10));
+    Ok(())
+}
+
+#[test]
+fn x_index_stored_columns_migrate_existing_table() -> Result<(), Box<dyn std::error::Error>> {
+    use syncstore::backend::{
+        Backend,
+        filter::{FilterExpr, SortSpec},
+        sqlite::{QueryScope, SqliteBackendBuilder},
+    };
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("indexed.db");
+    let mut place = json!({ "type": "object" });
+    let backend = SqliteBackendBuilder::file(&path)
+        .with_collection_schema("place", place.clone())
+        .build()?;
+    for rank in [3, 1, 2] {
+        backend.insert("place", &json!({ "rank": rank }), "u".to_string())?;
+    }
+    drop(backend);
+
+    place["x-index"] = json!(["rank"]);
+    let backend = SqliteBackendBuilder::file(&path)
+        .with_collection_schema("place", place)
+        .build()?;
+    let conn = rusqlite::Connection::open(&path)?;
+    // 3: stored generated column
+    let hidden: i64 = conn.query_row(
+        "SELECT hidden FROM pragma_table_xinfo('c_place') WHERE name = 'xi_rank'",
+        [],
+        |r| r.get(0),
+    )?;
+    assert_eq!(hidden, 3);
+
+    // existing rows survive the rebuild, filters and sorts read the column
+    let filter = FilterExpr::Gt("rank".to_string(), json!(1));
+    let sort = SortSpec {
+        field: "rank".to_string(),
+        descending: true,
+    };
+    let (page, _) = backend.query_sorted("place", QueryScope::Owner("u"), Some(&filter), Some(&sort), None, 10)?;
+    let ranks: Vec<_> = page.iter().map(|item| item.body["rank"].clone()).collect();
+    assert_eq!(ranks, vec![json!(3), json!(2)]);
+    Ok(())
+}
+
+#[test]
+fn x_index_field_names_checked() {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "bad",
+            json!({ "type": "object", "x-index": ["name'); DROP TABLE x; --"] }),
+        )
+        .build();
+    assert!(BasicTestSuite::with_schemas(schemas).is_err());
+}
+
+#[test]
+fn x_fulltext_search_respects_access() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "post",
+            json!({
+                "type": "object",
+                "properties": { "title": { "type": "string" }, "content": { "type": "string" } },
+                "x-fulltext": ["title", "content"]
+            }),
+        )
+        .add_schema("note", json!({ "type": "object" }))
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let (user1, user2) = (&s.user1_id, &s.user2_id);
+
+    let shared = store.insert(
+        namespace,
+        "post",
+        &json!({ "title": "Rust ownership", "content": "borrowing explained" }),
+        user1,
+    )?;
+    let private = store.insert(
+        namespace,
+        "post",
+        &json!({ "title": "Cooking", "content": "rust colored pans" }),
+        user1,
+    )?;
+    store.insert(namespace, "post", &json!({ "title": "Gardening" }), user1)?;
+    store.insert(namespace, "post", &json!({ "content": "my rust notes" }), user2)?;
+
+    assert_eq!(store.search(namespace, "post", "rust", user1)?.len(), 2);
+    assert_eq!(store.search(namespace, "post", "rust", user2)?.len(), 1);
+    let acl = AccessControl {
+        data_id: shared.clone(),
+        permissions: vec![Permission {
+            user: user2.to_string(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "post"), acl, user1)?;
+    let found = store.search(namespace, "post", "RUST", user2)?;
+    assert_eq!(found.len(), 2);
+    assert!(found.iter().any(|item| item.id == shared));
+
+    // every term has to match, operators are plain text
+    assert_eq!(store.search(namespace, "post", "rust borrowing", user1)?.len(), 1);
+    assert!(store.search(namespace, "post", "rust OR gardening", user1)?.is_empty());
+    assert!(store.search(namespace, "post", "title:cooking", user1)?.is_empty());
+
+    store.update(namespace, "post", &private, &json!({ "title": "Baking" }), user1)?;
+    assert_eq!(store.search(namespace, "post", "rust", user1)?.len(), 1);
+    assert_eq!(store.search(namespace, "post", "baking", user1)?[0].id, private);
+    store.delete(namespace, "post", &private, user1)?;
+    assert!(store.search(namespace, "post", "baking", user1)?.is_empty());
+
+    assert_validation_error(store.search(namespace, "post", "  ", user1));
+    assert_validation_error(store.search(namespace, "note", "rust", user1));
+    Ok(())
+}
+
+#[test]
+fn encrypted_fields_unreadable_in_database_file() -> Result<(), Box<dyn std::error::Error>> {
+    let account = |extra: serde_json::Value| {
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "email": { "type": "string", "pattern": "@" }
+            },
+            "required": ["name", "email"],
+            "x-encrypt-fields": ["email"]
+        });
+        schema
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        DataSchemasBuilder::new().add_schema("account", schema).build()
+    };
+    let s = BasicTestSuite::with_schemas(account(json!({})))?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    let doc = json!({ "name": "alice", "email": "alice@example.com" });
+
+    // no key, no write
+    assert!(matches!(
+        store.insert(namespace, "account", &doc, user),
+        Err(StoreError::Unavailable(_))
+    ));
+    store.set_field_cipher(Some(FieldCipher::new(&[42; 32])?))?;
+    // validated against the plain value
+    assert_validation_error(store.insert(namespace, "account", &json!({ "name": "bob", "email": "bob" }), user));
+    let id = store.insert(namespace, "account", &doc, user)?;
+    store.update(
+        namespace,
+        "account",
+        &id,
+        &json!({ "name": "alice", "email": "alice@example.org" }),
+        user,
+    )?;
+
+    let item = store.get(namespace, "account", &id, user)?;
+    assert_eq!(item.body["email"], "alice@example.org");
+    let (items, _) = store.list_by_owner(namespace, "account", None, 10, user)?;
+    assert_eq!(items[0].body["email"], "alice@example.org");
+    let (history, _) = store.history(namespace, "account", &id, user, None, 10)?;
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|change| match &change.op {
+        ChangeOp::Upsert { item } => item.body["email"].as_str().unwrap().starts_with("alice@"),
+        _ => false,
+    }));
+
+    // neither the rows nor the change log hold the plain value
+    let conn = rusqlite::Connection::open(s.path.join(format!("{}.db", namespace)))?;
+    let rows: Vec<String> = conn
+        .prepare("SELECT body FROM c_account UNION ALL SELECT payload FROM __changes")?
+        .query_map([], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(rows.len(), 3);
+    assert!(rows.iter().all(|row| row.contains("$enc") && !row.contains("example")));
+
+    store.set_field_cipher(Some(FieldCipher::new(&[7; 32])?))?;
+    assert!(store.get(namespace, "account", &id, user).is_err());
+    store.set_field_cipher(None)?;
+    assert!(matches!(
+        store.get(namespace, "account", &id, user),
+        Err(StoreError::Unavailable(_))
+    ));
+
+    // the database could not look an encrypted field up
+    assert!(BasicTestSuite::with_schemas(account(json!({ "x-unique": "email" }))).is_err());
+    assert!(BasicTestSuite::with_schemas(account(json!({ "x-index": ["email"] }))).is_err());
+    Ok(())
+}
+
+#[test]
+fn soft_deleted_items_kept_until_restored() -> Result<(), Box<dyn std::error::Error>> {
+    let note = |extra: serde_json::Value| {
+        let mut schema = json!({
+            "type": "object",
+            "properties": { "title": { "type": "string" } },
+            "required": ["title"]
+        });
+        schema
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        schema
+    };
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("note", note(json!({ "x-soft-delete": true })))
+        .add_schema("draft", note(json!({})))
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let before = chrono::Utc::now();
+    let kept = store.insert(namespace, "note", &json!({ "title": "kept" }), user1)?;
+    let gone = store.insert(namespace, "note", &json!({ "title": "gone" }), user1)?;
+    store.delete(namespace, "note", &gone, user1)?;
+
+    // left out of the reads by default
+    assert_not_found(store.get(namespace, "note", &gone, user1));
+    assert_not_found(store.delete(namespace, "note", &gone, user1));
+    assert_not_found(store.update(namespace, "note", &gone, &json!({ "title": "again" }), user1));
+    let (items, _) = store.list_by_owner(namespace, "note", None, 10, user1)?;
+    assert_eq!(items.iter().map(|i| &i.id).collect::<Vec<_>>(), vec![&kept]);
+
+    // and there when asked for
+    let item = store.get_with_deleted(namespace, "note", &gone, true, user1)?;
+    assert!(item.deleted_at.is_some());
+    assert_eq!(item.body["title"], "gone");
+    let page = store.list_page(namespace, "note", Listing::Owner, None, true, 10, user1)?;
+    assert_eq!(page.items.len(), 2);
+    let (changed, _) = store.list_updated_since(namespace, "note", Listing::Owner, before, true, None, 10, user1)?;
+    assert_eq!(
+        changed.last().map(|i| (&i.id, i.deleted_at.is_some())),
+        Some((&gone, true))
+    );
+    let (changed, _) = store.list_updated_since(namespace, "note", Listing::Owner, before, false, None, 10, user1)?;
+    assert_eq!(changed.len(), 1);
+
+    // restored by whoever may delete it
+    assert_permission_denied(store.restore(namespace, "note", &gone, user2));
+    assert!(matches!(
+        store.restore(namespace, "note", &kept, user1),
+        Err(StoreError::Conflict(_))
+    ));
+    let restored = store.restore(namespace, "note", &gone, user1)?;
+    assert_eq!(restored.deleted_at, None);
+    assert_eq!(store.get(namespace, "note", &gone, user1)?.body["title"], "gone");
+    let (history, _) = store.history(namespace, "note", &gone, user1, None, 10)?;
+    let ops: Vec<_> = history
+        .iter()
+        .map(|change| matches!(change.op, ChangeOp::Delete))
+        .collect();
+    assert_eq!(ops, vec![false, true, false]);
+
+    // the other collections still remove their rows
+    let draft = store.insert(namespace, "draft", &json!({ "title": "draft" }), user1)?;
+    store.delete(namespace, "draft", &draft, user1)?;
+    assert_not_found(store.get_with_deleted(namespace, "draft", &draft, true, user1));
+    assert_validation_error(store.restore(namespace, "draft", &draft, user1));
+    Ok(())
+}
+
+#[test]
+fn x_on_parent_delete_cascades_or_restricts() -> Result<(), Box<dyn std::error::Error>> {
+    let child = |parent: &str, policy: Option<&str>| {
+        let field = format!("{}_id", parent);
+        let mut schema = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "required": ["name", field],
+            "x-parent-id": { "parent": parent, "field": field }
+        });
+        schema["properties"][&field] = json!({ "type": "string" });
+        if let Some(policy) = policy {
+            schema["x-on-parent-delete"] = json!(policy);
+        }
+        schema
+    };
+    let folder = json!({ "type": "object", "properties": { "name": { "type": "string" } } });
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("folder", folder.clone())
+        .add_schema("file", child("folder", Some("cascade")))
+        .add_schema("chunk", child("file", Some("cascade")))
+        .add_schema("share", child("folder", Some("restrict")))
+        .add_schema("note", child("folder", None))
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let folder_id = store.insert(namespace, "folder", &json!({ "name": "f" }), user)?;
+    let file = store.insert(namespace, "file", &json!({ "name": "a", "folder_id": folder_id }), user)?;
+    let chunk = store.insert(namespace, "chunk", &json!({ "name": "c", "file_id": file }), user)?;
+    let share = store.insert(
+        namespace,
+        "share",
+        &json!({ "name": "s", "folder_id": folder_id }),
+        user,
+    )?;
+    let note = store.insert(namespace, "note", &json!({ "name": "n", "folder_id": folder_id }), user)?;
+
+    // a restricting child keeps the whole tree in place
+    assert!(matches!(
+        store.delete(namespace, "folder", &folder_id, user),
+        Err(StoreError::Conflict(_))
+    ));
+    store.get(namespace, "folder", &folder_id, user)?;
+    store.get(namespace, "chunk", &chunk, user)?;
+
+    store.delete(namespace, "share", &share, user)?;
+    let before = store.get_data_backend(namespace)?.head_seq()?;
+    store.delete(namespace, "folder", &folder_id, user)?;
+    assert_not_found(store.get(namespace, "file", &file, user));
+    assert_not_found(store.get(namespace, "chunk", &chunk, user));
+    // every deleted document is in the change log
+    let deleted = store.get_data_backend(namespace)?.changes_since(before, 10)?;
+    assert_eq!(deleted.len(), 3);
+    assert!(deleted.iter().all(|change| matches!(change.op, ChangeOp::Delete)));
+    // the orphaning default
+    assert_eq!(
+        store.get(namespace, "note", &note, user)?.body["folder_id"],
+        folder_id.as_str()
+    );
+
+    let invalid = |schema: serde_json::Value| {
+        BasicTestSuite::with_schemas(
+            DataSchemasBuilder::new()
+                .add_schema("folder", folder.clone())
+                .add_schema("file", schema)
+                .build(),
+        )
+        .is_err()
+    };
+    assert!(invalid(child("folder", Some("remove"))));
+    let mut no_parent = folder.clone();
+    no_parent["x-on-parent-delete"] = json!("cascade");
+    assert!(invalid(no_parent));
+    Ok(())
+}
+
+#[test]
+fn nested_unique_and_parent_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema(
+            "account",
+            json!({
+                "type": "object",
+                "properties": {
+                    "profile": { "type": "object", "properties": { "email": { "type": "string" } } }
+                },
+                "required": ["profile"],
+                "x-unique": "profile.email"
+            }),
+        )
+        .add_schema(
+            "note",
+            json!({
+                "type": "object",
+                "properties": {
+                    "meta": { "type": "object", "properties": { "account_id": { "type": "string" } } }
+                },
+                "x-parent-id": { "parent": "account", "field": "meta.account_id" }
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let a1 = store.insert(namespace, "account", &json!({ "profile": { "email": "a@x.io" } }), user)?;
+    let a2 = store.insert(namespace, "account", &json!({ "profile": { "email": "b@x.io" } }), user)?;
+    assert_validation_error(store.insert(namespace, "account", &json!({ "profile": { "email": "a@x.io" } }), user));
+    let backend = store.get_data_backend(namespace)?;
+    assert_eq!(backend.get_by_unique("account", "a@x.io")?.id, a1);
+
+    let note = store.insert(namespace, "note", &json!({ "meta": { "account_id": a1 } }), user)?;
+    assert_not_found(store.insert(namespace, "note", &json!({ "meta": { "account_id": "missing" } }), user));
+    assert_eq!(
+        store.get(namespace, "note", &note, user)?.parent_id.as_deref(),
+        Some(a1.as_str())
+    );
+
+    let moved = store.move_item(namespace, "note", &note, &a2, user)?;
+    assert_eq!(moved.body["meta"]["account_id"], a2);
+    assert_eq!(moved.parent_id.as_deref(), Some(a2.as_str()));
+
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("account", json!({ "type": "object", "x-unique": "profile..email" }))
+        .build();
+    assert!(BasicTestSuite::with_schemas(schemas).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn immutable_fields_kept_on_update() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("repo", json!({ "type": "object" }))
+        .add_schema(
+            "post",
+            json!({
+                "type": "object",
+                "properties": {
+                    "repo_id": { "type": "string" },
+                    "kind": { "type": "string" },
+                    "title": { "type": "string" }
+                },
+                "required": ["repo_id"],
+                "x-parent-id": { "parent": "repo", "field": "repo_id" },
+                "x-immutable": ["repo_id", "kind"]
+            }),
+        )
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let r1 = store.insert(namespace, "repo", &json!({}), user)?;
+    let r2 = store.insert(namespace, "repo", &json!({}), user)?;
+    let post = store.insert(
+        namespace,
+        "post",
+        &json!({ "repo_id": r1, "kind": "note", "title": "a" }),
+        user,
+    )?;
+
+    store.update(
+        namespace,
+        "post",
+        &post,
+        &json!({ "repo_id": r1, "kind": "note", "title": "b" }),
+        user,
+    )?;
+    for body in [
+        json!({ "repo_id": r2, "kind": "note", "title": "b" }),
+        json!({ "repo_id": r1, "kind": "task", "title": "b" }),
+        json!({ "repo_id": r1, "title": "b" }),
+    ] {
+        assert_validation_error(store.update(namespace, "post", &post, &body, user));
+    }
+    assert_eq!(
+        store.get(namespace, "post", &post, user)?.parent_id.as_deref(),
+        Some(r1.as_str())
+    );
+
+    // moving is still the way to re-parent
+    let moved = store.move_item(namespace, "post", &post, &r2, user)?;
+    assert_eq!(moved.body["repo_id"], r2);
+
+    Ok(())
+}
+
+#[test]
+fn oversized_bodies_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let schemas = DataSchemasBuilder::new()
+        .add_schema("note", json!({ "type": "object", "x-max-bytes": 64 }))
+        .build();
+    let s = BasicTestSuite::with_schemas(schemas)?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let note = store.insert(namespace, "note", &json!({ "text": "short" }), user)?;
+    let large = json!({ "text": "x".repeat(100) });
+    assert_validation_error(store.insert(namespace, "note", &large, user));
+    assert_validation_error(store.update(namespace, "note", &note, &large, user));
+    assert_eq!(store.get(namespace, "note", &note, user)?.body["text"], "short");
+    let report = store.validate_data(namespace, "note", &large, None, user)?;
+    assert!(!report.valid && report.errors[0].message.contains("x-max-bytes"));
+
+    let invalid = DataSchemasBuilder::new()
+        .add_schema("note", json!({ "type": "object", "x-max-bytes": 0 }))
+        .build();
+    assert!(BasicTestSuite::with_schemas(invalid).is_err());
+    Ok(())
+}
@@ -0,0 +1,405 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use syncstore::{
+    backend::{deadline, sqlite::SqliteBackendBuilder, tx::TxScope, write_queue::WriteTier},
+    error::{StoreError, StoreResult},
+    types::{AccessControl, AccessLevel, Permission},
+};
+
+use crate::mock::*;
+
+#[test]
+fn scope_rolled_back_after_failed_step() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Tx Repo", "status": "normal" });
+    let scope = TxScope::new();
+    let mut repo_id = String::new();
+    let result = scope.enter_sync(|| -> StoreResult<()> {
+        repo_id = store.insert(namespace, "repo", &repo, user1)?;
+        // the later steps see the uncommitted ones, including the parent check
+        let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+        store.insert(namespace, "post", &post, user1)?;
+        // only the owner can grant access
+        let acl = AccessControl {
+            data_id: repo_id.clone(),
+            permissions: vec![Permission {
+                user: user1.to_string(),
+                access_level: AccessLevel::Write,
+            }],
+        };
+        store.update_acl((namespace, "repo"), acl, user2)
+    });
+    assert_permission_denied(result);
+    scope.rollback();
+
+    assert_not_found(store.get(namespace, "repo", &repo_id, user1));
+    let (items, _) = store.list_by_owner(namespace, "repo", None, 10, user1)?;
+    assert!(items.is_empty());
+    Ok(())
+}
+
+#[test]
+fn store_transaction_commits_or_rolls_back() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+
+    let (repo_id, post_id) = store.transaction(namespace, |txn| {
+        let repo_id = txn.insert("repo", &json!({ "name": "Tx Repo", "status": "normal" }), user1)?;
+        let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+        let post_id = txn.insert("post", &post, user1)?;
+        Ok((repo_id, post_id))
+    })?;
+    assert_eq!(
+        store.get(namespace, "post", &post_id, user1)?.parent_id,
+        Some(repo_id.clone())
+    );
+
+    let mut inserted = String::new();
+    let result = store.transaction(namespace, |txn| {
+        inserted = txn.insert("repo", &json!({ "name": "Other", "status": "normal" }), user1)?;
+        txn.delete("post", &post_id, user1)?;
+        // the repo schema requires a name
+        txn.update("repo", &repo_id, &json!({ "status": "normal" }), user1)
+    });
+    assert_validation_error(result);
+    assert_not_found(store.get(namespace, "repo", &inserted, user1));
+    store.get(namespace, "post", &post_id, user1)?;
+    Ok(())
+}
+
+#[test]
+fn scope_committed() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    let repo = json!({ "name": "Tx Repo", "status": "normal" });
+    let scope = TxScope::new();
+    let repo_id = scope.enter_sync(|| -> StoreResult<String> {
+        let repo_id = store.insert(namespace, "repo", &repo, user1)?;
+        let acl = AccessControl {
+            data_id: repo_id.clone(),
+            permissions: vec![Permission {
+                user: user2.to_string(),
+                access_level: AccessLevel::Read,
+            }],
+        };
+        store.update_acl((namespace, "repo"), acl, user1)?;
+        Ok(repo_id)
+    })?;
+    scope.commit()?;
+
+    assert_eq!(store.get(namespace, "repo", &repo_id, user2)?.body["name"], "Tx Repo");
+    Ok(())
+}
+
+#[test]
+fn dropped_scope_rolls_back() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Tx Repo", "status": "normal" });
+    let scope = TxScope::new();
+    let repo_id = scope.enter_sync(|| store.insert(namespace, "repo", &repo, user))?;
+    drop(scope);
+
+    assert_not_found(store.get(namespace, "repo", &repo_id, user));
+    Ok(())
+}
+
+#[test]
+fn reads_served_beside_open_write() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let repo = json!({ "name": "Tx Repo", "status": "normal" });
+    let scope = TxScope::new();
+    let repo_id = scope.enter_sync(|| store.insert(namespace, "repo", &repo, user))?;
+    // the scope holds the writer, the reads outside it go to the read-only connections
+    assert_not_found(store.get(namespace, "repo", &repo_id, user));
+    let (items, _) = store.list_by_owner(namespace, "repo", None, 10, user)?;
+    assert!(items.is_empty());
+    scope.commit()?;
+    store.get(namespace, "repo", &repo_id, user)?;
+
+    let tmp = tempfile::tempdir()?;
+    let builder = SqliteBackendBuilder::file(tmp.path().join("reads.db"));
+    assert!(builder.read_connections(0).build().is_err());
+    Ok(())
+}
+
+#[test]
+fn reads_run_while_long_write_holds_writer() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = s.namespace.clone();
+    let user = s.user1_id.clone();
+    let kept = store.insert(
+        &namespace,
+        "repo",
+        &json!({ "name": "Kept", "status": "normal" }),
+        &user,
+    )?;
+
+    let (held, is_held) = mpsc::channel();
+    let (finish, finished) = mpsc::channel::<()>();
+    let writer = thread::spawn({
+        let (store, namespace, user) = (store.clone(), namespace.clone(), user.clone());
+        move || -> StoreResult<()> {
+            let scope = TxScope::new();
+            scope.enter_sync(|| {
+                store.insert(
+                    &namespace,
+                    "repo",
+                    &json!({ "name": "Slow", "status": "normal" }),
+                    &user,
+                )
+            })?;
+            // the transaction stays open on the one writer connection until told to commit
+            held.send(()).ok();
+            finished.recv().ok();
+            scope.commit()
+        }
+    });
+    is_held.recv()?;
+
+    // a second write queues for the writer
+    let queued = thread::spawn({
+        let (store, namespace, user) = (store.clone(), namespace.clone(), user.clone());
+        move || {
+            store.insert(
+                &namespace,
+                "repo",
+                &json!({ "name": "Queued", "status": "normal" }),
+                &user,
+            )
+        }
+    });
+    // the reads take read-only connections, several of them at once
+    thread::scope(|threads| {
+        for _ in 0..4 {
+            threads.spawn(|| {
+                assert_eq!(
+                    store.get(&namespace, "repo", &kept, &user).unwrap().body["name"],
+                    "Kept"
+                );
+                let (items, _) = store.list_by_owner(&namespace, "repo", None, 10, &user).unwrap();
+                assert_eq!(items.len(), 1);
+            });
+        }
+    });
+    thread::sleep(Duration::from_millis(50));
+    assert!(!queued.is_finished());
+
+    finish.send(())?;
+    writer.join().unwrap()?;
+    queued.join().unwrap()?;
+    let (items, _) = store.list_by_owner(&namespace, "repo", None, 10, &user)?;
+    assert_eq!(items.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn reading_request_leaves_writer_free() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    let repo = store.insert(namespace, "repo", &json!({ "name": "Read", "status": "normal" }), user)?;
+
+    // a `GET /{id}` runs in the transaction of its request, open until the answer is sent
+    let request = TxScope::new();
+    let read = request.enter_sync(|| store.get(namespace, "repo", &repo, user))?;
+    assert_eq!(read.body["name"], "Read");
+
+    // it never wrote, the writer stays free for the others meanwhile
+    let started = Instant::now();
+    let written = thread::scope(|threads| {
+        threads
+            .spawn(|| {
+                store.insert(
+                    namespace,
+                    "repo",
+                    &json!({ "name": "Beside", "status": "normal" }),
+                    user,
+                )
+            })
+            .join()
+            .unwrap()
+    })?;
+    assert!(started.elapsed() < Duration::from_secs(1));
+    request.commit()?;
+    store.get(namespace, "repo", &written, user)?;
+
+    // once the request wrote its reads join its transaction
+    let request = TxScope::new();
+    let id =
+        request.enter_sync(|| store.insert(namespace, "repo", &json!({ "name": "Own", "status": "normal" }), user))?;
+    request.enter_sync(|| store.get(namespace, "repo", &id, user))?;
+    request.rollback();
+    assert_not_found(store.get(namespace, "repo", &id, user));
+    Ok(())
+}
+
+#[test]
+fn read_snapshot_ignores_later_writes() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+
+    let deleted = store.insert(namespace, "repo", &json!({ "name": "Gone", "status": "normal" }), user)?;
+    let renamed = store.insert(namespace, "repo", &json!({ "name": "Old", "status": "normal" }), user)?;
+    let snapshot = TxScope::read_only();
+    let seq = snapshot.enter_sync(|| store.pin_read_snapshot(namespace, user))?;
+    assert!(seq > 0);
+
+    // written outside the snapshot after it was pinned
+    store.insert(namespace, "repo", &json!({ "name": "New", "status": "normal" }), user)?;
+    store.update(
+        namespace,
+        "repo",
+        &renamed,
+        &json!({ "name": "Renamed", "status": "normal" }),
+        user,
+    )?;
+    store.delete(namespace, "repo", &deleted, user)?;
+
+    snapshot.enter_sync(|| -> StoreResult<()> {
+        let (items, _) = store.list_by_owner(namespace, "repo", None, 10, user)?;
+        assert_eq!(items.len(), 2);
+        assert_eq!(store.get(namespace, "repo", &renamed, user)?.body["name"], "Old");
+        assert!(store.get(namespace, "repo", &deleted, user).is_ok());
+        let write = store.insert(namespace, "repo", &json!({ "name": "In", "status": "normal" }), user);
+        assert!(matches!(write, Err(StoreError::Conflict(_))));
+        Ok(())
+    })?;
+    drop(snapshot);
+
+    let (items, _) = store.list_by_owner(namespace, "repo", None, 10, user)?;
+    assert_eq!(items.len(), 2);
+    assert_eq!(store.get(namespace, "repo", &renamed, user)?.body["name"], "Renamed");
+    // the released connection writes again
+    store.insert(namespace, "repo", &json!({ "name": "After", "status": "normal" }), user)?;
+    // pinning only makes sense in a read-only scope
+    assert!(store.pin_read_snapshot(namespace, user).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn statements_interrupted_past_deadline() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    for i in 0..300 {
+        let repo = json!({ "name": format!("Repo {i}"), "status": "normal" });
+        store.insert(namespace, "repo", &repo, user1)?;
+    }
+
+    // the deadline passed already, the listing is aborted on its way
+    let result = deadline::enter(Instant::now(), async {
+        store.list_by_owner(namespace, "repo", None, 500, user1)
+    })
+    .await;
+    assert!(
+        matches!(result, Err(StoreError::Backend(_))),
+        "got {:?}",
+        result.map(|r| r.0.len())
+    );
+
+    // the pooled connection no longer carries the deadline
+    let (items, _) = store.list_by_owner(namespace, "repo", None, 500, user1)?;
+    assert_eq!(items.len(), 300);
+    Ok(())
+}
+
+#[tokio::test]
+async fn writes_admitted_by_the_store() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let (namespace, user) = (s.namespace.clone(), s.user1_id.clone());
+    let id = store
+        .run_write(WriteTier::Batch, move |store| {
+            store.insert(
+                &namespace,
+                "repo",
+                &json!({ "name": "Queued", "status": "normal" }),
+                &user,
+            )
+        })
+        .await?;
+    store.get(&s.namespace, "repo", &id, &s.user1_id)?;
+    let stats = store.write_queue().stats();
+    assert_eq!((stats.running, stats.batch_granted), (0, 1));
+
+    // one write at a time per database: the namespace, the users and the notifications
+    let held = [
+        store.write_queue().acquire(WriteTier::Interactive).await?,
+        store.write_queue().acquire(WriteTier::Interactive).await?,
+        store.write_queue().acquire(WriteTier::Interactive).await?,
+    ];
+    let waiting = tokio::time::timeout(
+        Duration::from_millis(10),
+        store.write_queue().acquire(WriteTier::Interactive),
+    )
+    .await;
+    assert!(waiting.is_err());
+    drop(held);
+    Ok(())
+}
+
+#[tokio::test]
+async fn blocking_run_keeps_scope_and_deadline() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let user1 = s.user1_id.clone();
+    let namespace = s.namespace.clone();
+
+    // the insert runs on the blocking pool and is discarded with the caller's transaction
+    let scope = TxScope::new();
+    let (ns, user) = (namespace.clone(), user1.clone());
+    let id = scope
+        .enter(
+            store.run(move |store| store.insert(&ns, "repo", &json!({ "name": "Pooled", "status": "normal" }), &user)),
+        )
+        .await?;
+    scope.rollback();
+    assert_not_found(store.get(&namespace, "repo", &id, &user1));
+
+    let (ns, user) = (namespace.clone(), user1.clone());
+    let result = deadline::enter(
+        Instant::now(),
+        store.run(move |store| store.list_by_owner(&ns, "repo", None, 500, &user)),
+    )
+    .await;
+    assert!(matches!(result, Err(StoreError::Backend(_))), "deadline not carried");
+    Ok(())
+}
@@ -1,3 +1,11 @@
+use std::time::Duration;
+
+use serde_json::json;
+use syncstore::{
+    error::StoreError,
+    types::{AccessControl, AccessLevel, Permission, ServiceAccess},
+};
+
 use crate::mock::*;
 
 #[test]
@@ -24,3 +32,133 @@ fn user_create_validate() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn friends_listed_in_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let user1 = &s.user1_id;
+
+    store.add_friend(user1, &s.user2_id)?;
+    store.create_user("user3", "p3")?;
+    let user3 = store.validate_user("user3", "p3")?.unwrap();
+    store.add_friend(user1, &user3)?;
+
+    let (page, marker) = store.list_friends(user1, None, 1)?;
+    assert_eq!(page.len(), 1);
+    assert!(marker.is_some());
+    let (rest, marker) = store.list_friends(user1, marker, 1)?;
+    assert_eq!(rest.len(), 1);
+    assert!(marker.is_none());
+    assert_ne!(page[0].0, rest[0].0);
+
+    // friendship goes both ways
+    let (friends, _) = store.list_friends(&user3, None, 10)?;
+    assert_eq!(friends.len(), 1);
+    assert_eq!(friends[0].0, *user1);
+    Ok(())
+}
+
+#[test]
+fn deactivated_user_restored_then_purged() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user1 = &s.user1_id;
+    let user2 = &s.user2_id;
+
+    // user2 shares a repo with user1
+    let repo_doc = json!({ "name": "Shared Repo", "description": "", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo_doc, user2)?;
+    let acl = AccessControl {
+        data_id: repo_id.clone(),
+        permissions: vec![Permission {
+            user: user1.to_string(),
+            access_level: AccessLevel::Read,
+        }],
+    };
+    store.update_acl((namespace, "repo"), acl, user2)?;
+    store.add_friend(user1, user2)?;
+    store.get(namespace, "repo", &repo_id, user1)?;
+
+    // login blocked and data hidden once deactivated
+    let username = store.get_user(user2)?.username;
+    store.deactivate_user(user2)?;
+    assert!(store.deactivate_user(user2).is_err());
+    assert!(store.get_user(user2)?.deactivated_at.is_some());
+    assert!(store.validate_user(&username, "p2")?.is_none());
+    assert_permission_denied(store.get(namespace, "repo", &repo_id, user1));
+    assert!(store.list_friends(user1, None, 10)?.0.is_empty());
+    assert_eq!(store.list_deactivated_users()?.len(), 1);
+
+    // still in the grace period, restoring brings everything back
+    assert_eq!(store.purge_deactivated_users(Duration::from_secs(60 * 60))?, 0);
+    store.restore_user(user2)?;
+    assert!(store.restore_user(user2).is_err());
+    store.get(namespace, "repo", &repo_id, user1)?;
+    assert_eq!(store.validate_user(&username, "p2")?.as_ref(), Some(user2));
+    assert_eq!(store.list_friends(user1, None, 10)?.0.len(), 1);
+
+    // purged after the grace period
+    store.deactivate_user(user2)?;
+    assert_eq!(store.purge_deactivated_users(Duration::ZERO)?, 1);
+    assert_not_found(store.get_user(user2));
+    assert_not_found(store.get(namespace, "repo", &repo_id, user1));
+    assert!(store.list_friends(user1, None, 10)?.0.is_empty());
+    assert!(store.list_deactivated_users()?.is_empty());
+    // the username is free again
+    store.create_user(&username, "password")?;
+    Ok(())
+}
+
+#[test]
+fn service_key_acts_as_service_identity() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+
+    let grants = [("repo".to_string(), ServiceAccess::Write)].into();
+    let created = store.create_service_key(namespace, "telemetry", grants)?;
+    let key = created.key.clone().expect("the key is returned once");
+    assert!(store.list_service_keys(namespace)?.iter().all(|k| k.key.is_none()));
+    assert!(matches!(
+        store.create_service_key(namespace, "telemetry", Default::default()),
+        Err(StoreError::Conflict(_))
+    ));
+    assert_not_found(store.create_service_key(
+        namespace,
+        "unknown",
+        [("missing".to_string(), ServiceAccess::Read)].into(),
+    ));
+
+    let found = store.validate_service_key(namespace, &key)?.expect("valid key");
+    assert_eq!(found.user_id, created.user_id);
+    assert!(found.allows("repo", true));
+    assert!(!found.allows("post", false));
+    assert!(store.validate_service_key(namespace, "ssk_wrong")?.is_none());
+
+    // the identity owns what it writes, like any user
+    let id = store.insert(
+        namespace,
+        "repo",
+        &json!({ "name": "metrics", "status": "normal" }),
+        &found.user_id,
+    )?;
+    assert_eq!(store.get(namespace, "repo", &id, &found.user_id)?.owner, found.user_id);
+
+    let updated = store.set_service_key_grants(
+        namespace,
+        &created.id,
+        [("repo".to_string(), ServiceAccess::Read)].into(),
+    )?;
+    assert!(updated.allows("repo", false) && !updated.allows("repo", true));
+
+    store.revoke_service_key(namespace, &created.id)?;
+    assert!(store.validate_service_key(namespace, &key)?.is_none());
+    assert_not_found(store.revoke_service_key(namespace, &created.id));
+    // its documents stay
+    assert!(store.get(namespace, "repo", &id, &found.user_id).is_ok());
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use serde_json::json;
+use syncstore::{
+    backend::filter::FilterExpr,
+    components::{WebhookRequest, WebhookSender, sign_webhook},
+    config::Webhooks,
+    error::{StoreError, StoreResult},
+    types::{ChangeOp, WebhookEvent, WebhookEventKind},
+};
+
+use crate::mock::*;
+
+// records the requests instead of sending them
+#[derive(Default)]
+struct RecordingSender {
+    offline: AtomicBool,
+    received: Mutex<Vec<WebhookRequest>>,
+}
+
+impl WebhookSender for RecordingSender {
+    fn send(&self, request: &WebhookRequest) -> StoreResult<()> {
+        if self.offline.load(Ordering::SeqCst) {
+            return Err(StoreError::Backend("receiver unreachable".to_string()));
+        }
+        self.received.lock().unwrap().push(request.clone());
+        Ok(())
+    }
+}
+
+fn config() -> Webhooks {
+    Webhooks {
+        interval: Duration::from_secs(1),
+        max_attempts: 3,
+        backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+        timeout: None,
+    }
+}
+
+#[test]
+fn webhooks_signed_and_filtered() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    let sender = RecordingSender::default();
+
+    // changes made before the registration are not delivered
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    let repo_id = store.insert(namespace, "repo", &repo, user)?;
+
+    let webhook = store.create_webhook(
+        namespace,
+        "http://receiver/hook",
+        vec!["post".to_string()],
+        Vec::new(),
+        None,
+        None,
+    )?;
+    let secret = webhook.secret.clone().expect("secret returned on creation");
+    assert!(store.list_webhooks(namespace)?.iter().all(|w| w.secret.is_none()));
+
+    store.update(namespace, "repo", &repo_id, &repo, user)?;
+    let post = json!({ "title": "Post", "category": "c", "content": "content", "repo_id": repo_id });
+    let post_id = store.insert(namespace, "post", &post, user)?;
+
+    assert_eq!(store.deliver_webhooks(namespace, &sender, &config())?, 1);
+    let received = sender.received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1);
+    let request = &received[0];
+    assert_eq!(
+        request.signature,
+        sign_webhook(&secret, request.timestamp, &request.body)
+    );
+    assert_ne!(
+        request.signature,
+        sign_webhook("other secret", request.timestamp, &request.body)
+    );
+    let event: WebhookEvent = serde_json::from_str(&request.body)?;
+    assert_eq!(event.namespace, *namespace);
+    assert_eq!(event.change.collection, "post");
+    assert_eq!(event.change.data_id, post_id);
+
+    // nothing new, nothing sent
+    assert_eq!(store.deliver_webhooks(namespace, &sender, &config())?, 0);
+
+    store.delete_webhook(namespace, &webhook.id)?;
+    assert!(store.list_webhooks(namespace)?.is_empty());
+    assert!(
+        store
+            .create_webhook(namespace, "ftp://receiver", vec![], Vec::new(), None, None)
+            .is_err()
+    );
+    assert!(
+        store
+            .create_webhook(
+                namespace,
+                "http://receiver",
+                vec!["nope".to_string()],
+                Vec::new(),
+                None,
+                None
+            )
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn webhooks_filtered_by_event_kind_and_predicate() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    let sender = RecordingSender::default();
+
+    let filter = FilterExpr::Eq("status".to_string(), json!("normal"));
+    store.create_webhook(
+        namespace,
+        "http://receiver/hook",
+        vec!["repo".to_string()],
+        vec![WebhookEventKind::Upsert, WebhookEventKind::Delete],
+        Some(filter),
+        None,
+    )?;
+
+    let matching = store.insert(namespace, "repo", &json!({ "name": "A", "status": "normal" }), user)?;
+    store.insert(namespace, "repo", &json!({ "name": "B", "status": "archived" }), user)?;
+    // the predicate is not applied to deletes, the document is gone
+    let archived = store.insert(namespace, "repo", &json!({ "name": "C", "status": "archived" }), user)?;
+    store.delete(namespace, "repo", &archived, user)?;
+
+    assert_eq!(store.deliver_webhooks(namespace, &sender, &config())?, 2);
+    let events = sender
+        .received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_str::<WebhookEvent>(&request.body))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(events[0].change.data_id, matching);
+    assert!(matches!(events[0].change.op, ChangeOp::Upsert { .. }));
+    assert_eq!(events[1].change.data_id, archived);
+    assert!(matches!(events[1].change.op, ChangeOp::Delete));
+
+    // a filter that does not compile is refused up front
+    let invalid = FilterExpr::In("status".to_string(), Vec::new());
+    assert!(
+        store
+            .create_webhook(namespace, "http://receiver", vec![], Vec::new(), Some(invalid), None)
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn failing_webhooks_dead_lettered_and_replayed() -> Result<(), Box<dyn std::error::Error>> {
+    let s = BasicTestSuite::new()?;
+
+    let store = s.store.clone();
+    let namespace = &s.namespace;
+    let user = &s.user1_id;
+    let sender = RecordingSender::default();
+    store.create_webhook(namespace, "http://receiver/hook", vec![], Vec::new(), None, None)?;
+
+    sender.offline.store(true, Ordering::SeqCst);
+    let repo = json!({ "name": "Repo", "status": "normal" });
+    store.insert(namespace, "repo", &repo, user)?;
+
+    // without backoff every attempt is due right away, until the delivery is dead
+    assert_eq!(store.deliver_webhooks(namespace, &sender, &config())?, 0);
+    let dead = store.dead_webhook_deliveries(namespace)?;
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].attempts, 3);
+    assert!(dead[0].last_error.as_deref().unwrap().contains("unreachable"));
+
+    // dead letters are not retried on their own
+    sender.offline.store(false, Ordering::SeqCst);
+    assert_eq!(store.deliver_webhooks(namespace, &sender, &config())?, 0);
+
+    let replayed = store.replay_webhook_delivery(namespace, dead[0].id)?;
+    assert!(!replayed.dead);
+    assert_eq!(replayed.attempts, 0);
+    assert_eq!(store.deliver_webhooks(namespace, &sender, &config())?, 1);
+    assert!(store.dead_webhook_deliveries(namespace)?.is_empty());
+    assert!(matches!(
+        store.replay_webhook_delivery(namespace, dead[0].id),
+        Err(StoreError::NotFound(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn webhook_retry_backoff() {
+    let config = Webhooks {
+        backoff: Duration::from_secs(10),
+        max_backoff: Duration::from_secs(30),
+        ..config()
+    };
+    assert_eq!(config.retry_delay(1), Some(Duration::from_secs(10)));
+    assert_eq!(config.retry_delay(2), Some(Duration::from_secs(20)));
+    assert_eq!(config.retry_delay(3), None);
+    let config = Webhooks {
+        max_attempts: 10,
+        ..config
+    };
+    assert_eq!(config.retry_delay(5), Some(Duration::from_secs(30)));
+}
@@ -1,4 +1,8 @@
+use std::sync::Arc;
+
 use serde_json::json;
+use syncstore::backend::blob::{BlobOffload, FsBlobStore};
+use syncstore::backend::encrypt::FieldCipher;
 use syncstore::{collection, store::Store};
 
 mod config;
@@ -181,7 +185,7 @@ async fn main() -> anyhow::Result<()> {
         }),
     };
 
-    let store = Store::build(
+    let store = Store::build_with_layout(
         &config.store_config.directory,
         vec![
             ("xbb", xbb_schema),
@@ -191,7 +195,36 @@ async fn main() -> anyhow::Result<()> {
             ("chat", chat_schema),
             ("checkin", checkin_schema),
         ],
+        &config.store_config.layout(),
     )?;
+    store.startup()?;
+    for (namespace, policy) in &config.store_config.namespace_policies {
+        store.set_namespace_policy(namespace, policy.clone())?;
+    }
+    if let Some(orphan_cleanup) = &config.store_config.orphan_cleanup {
+        store.schedule_orphan_cleanup(orphan_cleanup)?;
+    }
+    store.schedule_access_stats_flush(config.store_config.access_stats_flush)?;
+    store.schedule_user_purge(config.store_config.deactivation_grace)?;
+    store.schedule_expiry_sweep(config.store_config.expiry_sweep)?;
+    store.schedule_tombstone_gc(config.store_config.tombstone_retention)?;
+    store.set_write_queue(&config.store_config.write_queue)?;
+    if let Some(blob_offload) = &config.store_config.blob_offload {
+        let directory = match &blob_offload.directory {
+            Some(directory) => std::path::PathBuf::from(directory),
+            None => std::path::Path::new(&config.store_config.directory).join("blobs"),
+        };
+        store.set_blob_offload(Some(BlobOffload {
+            store: Arc::new(FsBlobStore::new(directory)?),
+            threshold: blob_offload.threshold,
+        }))?;
+    }
+    if let Some(key) = &config.store_config.field_encryption_key {
+        store.set_field_cipher(Some(FieldCipher::from_base64(key)?))?;
+    }
+    if config.store_config.read_only {
+        store.set_maintenance_mode(true, Some("started read-only".to_string()))?;
+    }
     syncstore::init_service(store, &config.service_config).await?;
     Ok(())
 }